@@ -0,0 +1,58 @@
+//! End-to-end overhead: the same request sent straight to a mock upstream and
+//! through the adapter, for both the buffered and the streaming path.
+
+#[path = "../tests/common/mod.rs"]
+mod common;
+
+use common::{chunk, completion, spawn_adapter, MockUpstream, Reply, CHAT_PATH};
+use criterion::{criterion_group, criterion_main, Criterion};
+use serde_json::json;
+
+fn bench_overhead(c: &mut Criterion) {
+    let rt = tokio::runtime::Runtime::new().unwrap();
+    let client = reqwest::Client::new();
+
+    for (name, stream, reply) in [
+        ("non_streaming", false, Reply::json(200, completion("Bonjour le monde"))),
+        ("streaming", true, {
+            let mut events: Vec<String> = (0..50).map(|i| chunk(&format!("t{} ", i))).collect();
+            events.push("[DONE]".to_string());
+            Reply::sse(&events)
+        }),
+    ] {
+        let (direct_url, adapter_url) = rt.block_on(async {
+            let upstream = MockUpstream::start().await;
+            upstream.always(reply);
+            let adapter = spawn_adapter(&upstream, "").await;
+            (
+                format!("{}/v1/chat/completions", upstream.base_url),
+                format!("{}{}", adapter, CHAT_PATH),
+            )
+        });
+        let body = json!({ "model": "test-model", "messages": [], "stream": stream });
+
+        let mut group = c.benchmark_group(name);
+        for (target, url) in [("direct", &direct_url), ("adapter", &adapter_url)] {
+            let client = &client;
+            let body = &body;
+            let url = url.as_str();
+            group.bench_function(target, |b| {
+                b.to_async(&rt).iter(|| async move {
+                    client
+                        .post(url)
+                        .json(body)
+                        .send()
+                        .await
+                        .unwrap()
+                        .bytes()
+                        .await
+                        .unwrap()
+                })
+            });
+        }
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_overhead);
+criterion_main!(benches);
@@ -0,0 +1,74 @@
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use openai_api_proxy::completion::ChunkAccumulator;
+use openai_api_proxy::sse::{SseEvent, SseParser};
+use serde_json::json;
+
+fn stream_body(chunks: usize) -> Vec<u8> {
+    let mut body = String::new();
+    for i in 0..chunks {
+        let chunk = json!({
+            "id": "chatcmpl-bench",
+            "object": "chat.completion.chunk",
+            "created": 1700000000,
+            "model": "bench-model",
+            "choices": [{ "index": 0, "delta": { "content": format!("token{} ", i) }, "finish_reason": null }]
+        });
+        body.push_str(&format!("data: {}\n\n", chunk));
+    }
+    body.push_str("data: [DONE]\n\n");
+    body.into_bytes()
+}
+
+fn bench_parser(c: &mut Criterion) {
+    let body = stream_body(500);
+    let mut group = c.benchmark_group("sse");
+    group.throughput(Throughput::Bytes(body.len() as u64));
+
+    group.bench_function("parse_whole", |b| {
+        b.iter(|| {
+            let mut parser = SseParser::new();
+            black_box(parser.feed(black_box(&body)));
+        })
+    });
+
+    // Typical network framing: many small reads that split events.
+    group.bench_function("parse_64b_reads", |b| {
+        b.iter(|| {
+            let mut parser = SseParser::new();
+            let mut events = 0;
+            for chunk in body.chunks(64) {
+                events += parser.feed(chunk).len();
+            }
+            black_box(events);
+        })
+    });
+
+    let events = SseParser::new().feed(&body);
+    group.bench_function("serialize", |b| {
+        b.iter(|| {
+            for event in &events {
+                black_box(event.to_bytes());
+            }
+        })
+    });
+
+    group.bench_function("assemble", |b| {
+        b.iter(|| {
+            let mut accumulator = ChunkAccumulator::default();
+            for event in &events {
+                accumulator.push_event(event);
+            }
+            black_box(accumulator.into_completion());
+        })
+    });
+
+    group.bench_function("classify", |b| {
+        let event = SseEvent::data(events[0].data.clone());
+        b.iter(|| black_box(event.kind()))
+    });
+
+    group.finish();
+}
+
+criterion_group!(benches, bench_parser);
+criterion_main!(benches);
@@ -0,0 +1,18 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use openai_api_proxy::completion::ChunkAccumulator;
+use openai_api_proxy::sse::SseParser;
+
+fuzz_target!(|data: &[u8]| {
+    let mut parser = SseParser::new();
+    let mut accumulator = ChunkAccumulator::default();
+    for event in parser.feed(data).into_iter().chain(parser.finish()) {
+        accumulator.push_event(&event);
+    }
+    if data.len() % 2 == 0 {
+        accumulator.mark_budget_exhausted();
+    }
+    let completion = accumulator.into_completion();
+    assert_eq!(completion["object"], "chat.completion");
+});
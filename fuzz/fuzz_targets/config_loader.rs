@@ -0,0 +1,8 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use openai_api_proxy::AppConfig;
+
+fuzz_target!(|data: &str| {
+    let _ = AppConfig::from_toml(data);
+});
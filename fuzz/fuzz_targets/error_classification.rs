@@ -0,0 +1,12 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use openai_api_proxy::provider::{OpenAiCompatible, Provider};
+
+fuzz_target!(|data: &[u8]| {
+    if data.len() < 2 {
+        return;
+    }
+    let status = 400 + u16::from_le_bytes([data[0], data[1]]) % 200;
+    let _ = OpenAiCompatible.classify_response(status, &reqwest::header::HeaderMap::new(), &data[2..]);
+});
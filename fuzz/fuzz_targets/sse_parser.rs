@@ -0,0 +1,29 @@
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+use openai_api_proxy::sse::{SseEvent, SseParser};
+
+fn parse(chunks: &[&[u8]]) -> Vec<SseEvent> {
+    let mut parser = SseParser::new();
+    let mut events = Vec::new();
+    for chunk in chunks {
+        events.extend(parser.feed(chunk));
+    }
+    events.extend(parser.finish());
+    events
+}
+
+fuzz_target!(|data: &[u8]| {
+    let whole = parse(&[data]);
+
+    // Where the network happens to split the stream must not matter.
+    if let Some(&first) = data.first() {
+        let (a, b) = data.split_at(first as usize % data.len());
+        assert_eq!(parse(&[a, b]), whole);
+    }
+
+    for event in &whole {
+        let _ = event.kind();
+        let _ = event.to_bytes();
+    }
+});
@@ -0,0 +1,111 @@
+use serde::Deserialize;
+
+/// Automatic certificates from an ACME directory (Let's Encrypt by default)
+/// for deployments that terminate TLS in the adapter itself. Requires the
+/// `acme` cargo feature.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AcmeConfig {
+    /// Domains on the certificate; all must resolve to this host.
+    pub domains: Vec<String>,
+    /// Contact emails registered with the ACME account.
+    #[serde(default)]
+    pub contact: Vec<String>,
+    /// Where the account key and issued certificates are cached across restarts.
+    #[serde(default = "default_cache_dir")]
+    pub cache_dir: String,
+    /// Use the Let's Encrypt staging directory, which has generous rate limits
+    /// but issues untrusted certificates.
+    #[serde(default)]
+    pub staging: bool,
+    #[serde(default)]
+    pub challenge: Challenge,
+    /// Port answering HTTP-01 challenges; must be reachable as port 80.
+    #[serde(default = "default_http_port")]
+    pub http_port: u16,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Challenge {
+    /// Answered on the TLS listener itself, no extra port needed.
+    #[default]
+    #[serde(rename = "tls-alpn-01")]
+    TlsAlpn01,
+    #[serde(rename = "http-01")]
+    Http01,
+}
+
+fn default_cache_dir() -> String {
+    "acme-cache".to_string()
+}
+
+fn default_http_port() -> u16 {
+    80
+}
+
+#[cfg(feature = "acme")]
+pub async fn serve(
+    config: &AcmeConfig,
+    addr: std::net::SocketAddr,
+    app: axum::Router,
+    shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) -> Result<(), Box<dyn std::error::Error>> {
+    use futures::StreamExt;
+    use rustls_acme::{caches::DirCache, UseChallenge};
+    use std::net::SocketAddr;
+
+    let mut state = rustls_acme::AcmeConfig::new(config.domains.clone())
+        .contact(config.contact.iter().map(|email| format!("mailto:{}", email)))
+        .cache(DirCache::new(config.cache_dir.clone()))
+        .directory_lets_encrypt(!config.staging)
+        .challenge_type(match config.challenge {
+            Challenge::TlsAlpn01 => UseChallenge::TlsAlpn01,
+            Challenge::Http01 => UseChallenge::Http01,
+        })
+        .state();
+    let acceptor = state.axum_acceptor(state.default_rustls_config());
+
+    if config.challenge == Challenge::Http01 {
+        let challenges = axum::Router::new().route_service(
+            "/.well-known/acme-challenge/:token",
+            state.http01_challenge_tower_service(),
+        );
+        let listener = tokio::net::TcpListener::bind((addr.ip(), config.http_port)).await?;
+        println!("Answering ACME HTTP-01 challenges on port {}", config.http_port);
+        tokio::spawn(async move { axum::serve(listener, challenges).await });
+    }
+
+    // Drives ordering and renewal; certificates are swapped in without restarts.
+    tokio::spawn(async move {
+        while let Some(event) = state.next().await {
+            match event {
+                Ok(ok) => println!("ACME: {:?}", ok),
+                Err(e) => println!("ACME error: {}", e),
+            }
+        }
+    });
+
+    let handle = axum_server::Handle::new();
+    let on_shutdown = handle.clone();
+    tokio::spawn(async move {
+        shutdown.await;
+        on_shutdown.graceful_shutdown(None);
+    });
+
+    println!("Server running on https://{}", addr);
+    axum_server::bind(addr)
+        .handle(handle)
+        .acceptor(acceptor)
+        .serve(app.into_make_service_with_connect_info::<SocketAddr>())
+        .await?;
+    Ok(())
+}
+
+#[cfg(not(feature = "acme"))]
+pub async fn serve(
+    _config: &AcmeConfig,
+    _addr: std::net::SocketAddr,
+    _app: axum::Router,
+    _shutdown: impl std::future::Future<Output = ()> + Send + 'static,
+) -> Result<(), Box<dyn std::error::Error>> {
+    Err("[acme] is configured but the adapter was built without the `acme` feature".into())
+}
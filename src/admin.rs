@@ -0,0 +1,583 @@
+use axum::{
+    body::Body,
+    extract::{Path, Request, State},
+    http::{header, StatusCode},
+    middleware::{self, Next},
+    response::{IntoResponse, Response},
+    routing::{delete, get, post},
+    Json, Router,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use subtle::ConstantTimeEq;
+
+use crate::buildinfo;
+use crate::create_error_response;
+use crate::keys::KeyOverrides;
+use crate::maintenance::MaintenanceMode;
+use crate::profiling;
+use crate::runtime;
+use crate::snapshot::{self, Snapshot};
+use crate::verbose::VerboseSettings;
+use crate::AppState;
+
+/// Operator endpoints, only reachable with the configured admin token.
+pub fn routes(state: Arc<AppState>) -> Router<Arc<AppState>> {
+    Router::new()
+        .route("/admin/streams", get(list_streams))
+        .route("/admin/streams/:id", delete(terminate_stream))
+        .route("/admin/requests", get(list_requests))
+        .route(
+            "/admin/maintenance",
+            get(get_maintenance).put(put_maintenance).delete(delete_maintenance),
+        )
+        .route(
+            "/admin/logging",
+            get(get_logging).put(put_logging).delete(delete_logging),
+        )
+        .route("/admin/backends", get(list_backends))
+        .route("/admin/quotas", get(list_quotas))
+        .route("/admin/jobs", get(list_jobs))
+        .route("/admin/slo", get(list_slo))
+        .route("/admin/deprecations", get(list_deprecations))
+        .route(
+            "/admin/glossaries/:tenant",
+            get(get_glossary).put(put_glossary).delete(delete_glossary),
+        )
+        .route("/admin/heatmap", get(token_heatmap))
+        .route("/admin/webhooks", get(list_webhooks))
+        .route("/admin/webhooks/:id/retry", post(retry_webhook))
+        .route("/admin/runtime", get(runtime_stats))
+        .route("/admin/version", get(buildinfo::handle_admin_version))
+        .route("/admin/profile/cpu", get(profiling::cpu_profile))
+        .route("/admin/profile/heap", get(profiling::heap_profile))
+        .route("/admin/jobs/:name/run", post(run_job))
+        .route("/admin/state", get(export_state).put(import_state))
+        .route("/admin/templates", get(list_templates))
+        .route("/admin/feedback", get(list_feedback))
+        .route("/admin/templates/:name", get(template_history).post(publish_template))
+        .route("/admin/templates/:name/rollback", post(rollback_template))
+        .route(
+            "/admin/templates/:name/experiment",
+            get(template_evaluation).put(put_experiment).delete(delete_experiment),
+        )
+        .route(
+            "/admin/backends/:name/drain",
+            post(drain_backend).delete(undrain_backend),
+        )
+        .route(
+            "/admin/keys/:name/overrides",
+            get(get_key_overrides).put(put_key_overrides).delete(delete_key_overrides),
+        )
+        .route_layer(middleware::from_fn_with_state(state, require_admin))
+}
+
+async fn require_admin(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let Some(token) = state.config.admin.token.as_deref() else {
+        return create_error_response(
+            StatusCode::NOT_FOUND,
+            "not_found",
+            "The admin API is disabled",
+        );
+    };
+
+    let presented = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer "));
+    if !presented.is_some_and(|presented| bool::from(presented.as_bytes().ct_eq(token.as_bytes()))) {
+        return create_error_response(
+            StatusCode::UNAUTHORIZED,
+            "invalid_admin_token",
+            "A valid admin token is required",
+        );
+    }
+
+    next.run(request).await
+}
+
+async fn list_streams(State(state): State<Arc<AppState>>) -> Response<Body> {
+    Json(state.streams.stats()).into_response()
+}
+
+async fn list_requests(State(state): State<Arc<AppState>>) -> Response<Body> {
+    let max_lifetime = std::time::Duration::from_secs(state.config.watchdog.max_lifetime_secs);
+    Json(state.inflight.stats(max_lifetime)).into_response()
+}
+
+async fn terminate_stream(
+    State(state): State<Arc<AppState>>,
+    Path(id): Path<String>,
+) -> Response<Body> {
+    if state.streams.terminate(&id) {
+        Json(json!({ "request_id": id, "terminated": true })).into_response()
+    } else {
+        create_error_response(
+            StatusCode::NOT_FOUND,
+            "stream_not_found",
+            &format!("No active stream with request id {}", id),
+        )
+    }
+}
+
+async fn get_key_overrides(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Response<Body> {
+    Json(state.keys.overrides(&name).unwrap_or_default()).into_response()
+}
+
+async fn put_key_overrides(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(overrides): Json<KeyOverrides>,
+) -> Response<Body> {
+    state.keys.set_overrides(&name, overrides.clone());
+    Json(overrides).into_response()
+}
+
+async fn delete_key_overrides(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Response<Body> {
+    if state.keys.remove(&name) {
+        StatusCode::NO_CONTENT.into_response()
+    } else {
+        create_error_response(
+            StatusCode::NOT_FOUND,
+            "key_not_found",
+            "No overrides are configured for this key",
+        )
+    }
+}
+
+async fn get_maintenance(State(state): State<Arc<AppState>>) -> Response<Body> {
+    Json(json!({ "enabled": state.maintenance.mode().is_some(), "mode": state.maintenance.mode() }))
+        .into_response()
+}
+
+async fn put_maintenance(
+    State(state): State<Arc<AppState>>,
+    Json(mode): Json<MaintenanceMode>,
+) -> Response<Body> {
+    println!("Maintenance mode enabled: {}", mode.message);
+    state.maintenance.set_mode(Some(mode.clone()));
+    Json(json!({ "enabled": true, "mode": mode })).into_response()
+}
+
+async fn delete_maintenance(State(state): State<Arc<AppState>>) -> Response<Body> {
+    println!("Maintenance mode disabled");
+    state.maintenance.set_mode(None);
+    Json(json!({ "enabled": false })).into_response()
+}
+
+async fn get_logging(State(state): State<Arc<AppState>>) -> Response<Body> {
+    match state.verbose.settings() {
+        Some((settings, remaining)) => Json(json!({
+            "enabled": true,
+            "settings": settings,
+            "remaining_secs": remaining.map(|left| left.as_secs()),
+        }))
+        .into_response(),
+        None => Json(json!({ "enabled": false })).into_response(),
+    }
+}
+
+async fn put_logging(
+    State(state): State<Arc<AppState>>,
+    Json(settings): Json<VerboseSettings>,
+) -> Response<Body> {
+    if !(0.0..=100.0).contains(&settings.sample_percent) {
+        return create_error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            "sample_percent must be between 0 and 100",
+        );
+    }
+    println!(
+        "Detailed logging enabled for keys [{}] and {}% of other requests",
+        settings.keys.join(", "),
+        settings.sample_percent
+    );
+    state.verbose.set(Some(settings.clone()));
+    Json(json!({ "enabled": true, "settings": settings })).into_response()
+}
+
+async fn delete_logging(State(state): State<Arc<AppState>>) -> Response<Body> {
+    println!("Detailed logging disabled");
+    state.verbose.set(None);
+    Json(json!({ "enabled": false })).into_response()
+}
+
+async fn list_backends(State(state): State<Arc<AppState>>) -> Response<Body> {
+    let backends: Vec<Value> = state
+        .backends
+        .all()
+        .map(|backend| {
+            let name = backend.name.as_str();
+            json!({
+                "name": name,
+                "models": backend.models,
+                "region": backend.region,
+                "healthy": backend.probe.is_healthy(),
+                "rtt_ms": backend.probe.rtt().map(|rtt| rtt.as_millis() as u64),
+                "drained": state.maintenance.is_drained(name),
+                "active_streams": state.streams.active_for_backend(name),
+                "in_flight": backend.handshakes.active(),
+                "weight": backend.weight,
+                "cooldown_ms": backend.cooldown.remaining().map(|left| left.as_millis() as u64),
+                "circuit": backend.breaker.state(),
+                "queue": state.admission.stats(name),
+                "capabilities": state.discovery.get(name),
+            })
+        })
+        .collect();
+    Json(json!({ "backends": backends })).into_response()
+}
+
+async fn list_quotas(State(state): State<Arc<AppState>>) -> Response<Body> {
+    Json(state.quotas.snapshot()).into_response()
+}
+
+async fn list_slo(State(state): State<Arc<AppState>>) -> Response<Body> {
+    Json(state.slo.snapshot()).into_response()
+}
+
+/// Deprecated models with their sunset, and who still requests them.
+async fn list_deprecations(State(state): State<Arc<AppState>>) -> Response<Body> {
+    let models: Vec<Value> = state
+        .config
+        .deprecations
+        .iter()
+        .map(|d| {
+            json!({
+                "models": d.models,
+                "since": d.since,
+                "sunset": d.sunset,
+                "replacement": d.replacement,
+                "after_sunset": d.after_sunset,
+                "status": if d.is_past_sunset() { "retired" } else { "deprecated" },
+            })
+        })
+        .collect();
+    Json(json!({ "deprecations": models, "uses": state.deprecations.uses() })).into_response()
+}
+
+async fn token_heatmap(State(state): State<Arc<AppState>>) -> Response<Body> {
+    Json(state.heatmap.report()).into_response()
+}
+
+async fn list_webhooks(State(state): State<Arc<AppState>>) -> Response<Body> {
+    match state.webhooks.report() {
+        Ok(report) => Json(report).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Queues a dead-lettered webhook delivery again.
+async fn retry_webhook(State(state): State<Arc<AppState>>, Path(id): Path<i64>) -> Response<Body> {
+    match state.webhooks.requeue(id) {
+        Ok(true) => {
+            state.scheduler.trigger("webhook-delivery");
+            StatusCode::ACCEPTED.into_response()
+        }
+        Ok(false) => create_error_response(StatusCode::NOT_FOUND, "not_found", "No dead letter by that id"),
+        Err(e) => storage_error(e),
+    }
+}
+
+async fn runtime_stats(State(state): State<Arc<AppState>>) -> Response<Body> {
+    Json(runtime::snapshot(&state)).into_response()
+}
+
+async fn list_jobs(State(state): State<Arc<AppState>>) -> Response<Body> {
+    Json(state.scheduler.snapshot()).into_response()
+}
+
+async fn run_job(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Response<Body> {
+    if state.scheduler.trigger(&name) {
+        (StatusCode::ACCEPTED, Json(json!({ "job": name, "triggered": true }))).into_response()
+    } else {
+        create_error_response(
+            StatusCode::NOT_FOUND,
+            "job_not_found",
+            &format!("No job named {}", name),
+        )
+    }
+}
+
+fn storage_error(e: String) -> Response<Body> {
+    create_error_response(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", &e)
+}
+
+/// Feedback per model and backend, plus the latest reports.
+async fn list_feedback(State(state): State<Arc<AppState>>) -> Response<Body> {
+    let summary = match state.feedback.by_model() {
+        Ok(summary) => summary,
+        Err(e) => return storage_error(e),
+    };
+    match state.feedback.recent(100) {
+        Ok(recent) => Json(json!({ "models": summary, "recent": recent })).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+async fn list_templates(State(state): State<Arc<AppState>>) -> Response<Body> {
+    match state.templates.list() {
+        Ok(templates) => Json(json!({ "templates": templates })).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+async fn template_history(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Response<Body> {
+    let active = match state.templates.get(&name, None) {
+        Ok(Some(active)) => active.version,
+        Ok(None) => {
+            return create_error_response(
+                StatusCode::NOT_FOUND,
+                "template_not_found",
+                &format!("No template named {}", name),
+            )
+        }
+        Err(e) => return storage_error(e),
+    };
+    match state.templates.history(&name) {
+        Ok(versions) => Json(json!({ "name": name, "active_version": active, "versions": versions })).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[derive(Deserialize)]
+struct PublishTemplate {
+    content: String,
+}
+
+/// Publishes a new version, which becomes active immediately.
+async fn publish_template(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(body): Json<PublishTemplate>,
+) -> Response<Body> {
+    match state.templates.publish(&name, &body.content) {
+        Ok(version) => {
+            state.cache.invalidate();
+            println!("Published template {} version {}", name, version);
+            (StatusCode::CREATED, Json(json!({ "name": name, "version": version }))).into_response()
+        }
+        Err(e) => storage_error(e),
+    }
+}
+
+#[derive(Deserialize)]
+struct RollbackTemplate {
+    version: i64,
+}
+
+async fn rollback_template(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(body): Json<RollbackTemplate>,
+) -> Response<Body> {
+    match state.templates.rollback(&name, body.version) {
+        Ok(true) => {
+            state.cache.invalidate();
+            println!("Rolled template {} back to version {}", name, body.version);
+            Json(json!({ "name": name, "active_version": body.version })).into_response()
+        }
+        Ok(false) => create_error_response(
+            StatusCode::NOT_FOUND,
+            "template_not_found",
+            &format!("Template {} has no version {}", name, body.version),
+        ),
+        Err(e) => storage_error(e),
+    }
+}
+
+#[derive(Deserialize)]
+struct Experiment {
+    version: i64,
+    percent: f64,
+}
+
+/// Routes a share of unpinned requests to a candidate version.
+async fn put_experiment(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+    Json(body): Json<Experiment>,
+) -> Response<Body> {
+    match state.templates.set_experiment(&name, body.version, body.percent) {
+        Ok(true) => {
+            state.cache.invalidate();
+            println!("Sending {}% of {} traffic to version {}", body.percent, name, body.version);
+            template_evaluation(State(state), Path(name)).await
+        }
+        Ok(false) => create_error_response(
+            StatusCode::NOT_FOUND,
+            "template_not_found",
+            &format!("Template {} has no version {}", name, body.version),
+        ),
+        Err(e) => storage_error(e),
+    }
+}
+
+async fn delete_experiment(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Response<Body> {
+    match state.templates.clear_experiment(&name) {
+        Ok(true) => {
+            state.cache.invalidate();
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => create_error_response(
+            StatusCode::NOT_FOUND,
+            "experiment_not_found",
+            &format!("No experiment running on {}", name),
+        ),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// The running experiment and per-version request and feedback figures.
+async fn template_evaluation(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Response<Body> {
+    let experiment = match state.templates.experiment(&name) {
+        Ok(experiment) => experiment.map(|(version, percent)| json!({ "version": version, "percent": percent })),
+        Err(e) => return storage_error(e),
+    };
+    match state.templates.evaluation(&name) {
+        Ok(versions) => Json(json!({ "name": name, "experiment": experiment, "versions": versions })).into_response(),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// The tenant's stored terms and every term that applies to it, including
+/// configured and shared ones.
+async fn get_glossary(
+    State(state): State<Arc<AppState>>,
+    Path(tenant): Path<String>,
+) -> Response<Body> {
+    let stored = match state.glossaries.stored(&tenant) {
+        Ok(stored) => stored,
+        Err(e) => return storage_error(e),
+    };
+    let effective = match state.glossaries.terms(&tenant) {
+        Ok(effective) => effective,
+        Err(e) => return storage_error(e),
+    };
+    Json(json!({ "tenant": tenant, "terms": stored, "effective": effective })).into_response()
+}
+
+/// Replaces the tenant's stored terms.
+async fn put_glossary(
+    State(state): State<Arc<AppState>>,
+    Path(tenant): Path<String>,
+    Json(terms): Json<BTreeMap<String, String>>,
+) -> Response<Body> {
+    if terms.keys().any(|term| term.trim().is_empty()) {
+        return create_error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            "Glossary terms must not be empty",
+        );
+    }
+    match state.glossaries.replace(&tenant, &terms) {
+        Ok(()) => {
+            state.cache.invalidate();
+            println!("Stored {} glossary terms for {}", terms.len(), tenant);
+            Json(json!({ "tenant": tenant, "terms": terms })).into_response()
+        }
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Removes the tenant's stored terms; configured ones still apply.
+async fn delete_glossary(
+    State(state): State<Arc<AppState>>,
+    Path(tenant): Path<String>,
+) -> Response<Body> {
+    match state.glossaries.delete(&tenant) {
+        Ok(true) => {
+            state.cache.invalidate();
+            StatusCode::NO_CONTENT.into_response()
+        }
+        Ok(false) => create_error_response(
+            StatusCode::NOT_FOUND,
+            "glossary_not_found",
+            "No glossary terms are stored for this tenant",
+        ),
+        Err(e) => storage_error(e),
+    }
+}
+
+/// Snapshot of runtime state, for handing over to another instance.
+async fn export_state(State(state): State<Arc<AppState>>) -> Response<Body> {
+    Json(snapshot::export(&state)).into_response()
+}
+
+async fn import_state(
+    State(state): State<Arc<AppState>>,
+    Json(snapshot): Json<Snapshot>,
+) -> Response<Body> {
+    match snapshot::import(&state, snapshot) {
+        Ok(()) => StatusCode::NO_CONTENT.into_response(),
+        Err(e) => create_error_response(StatusCode::BAD_REQUEST, "invalid_snapshot", &e),
+    }
+}
+
+#[allow(clippy::result_large_err)]
+fn known_backend(state: &AppState, name: &str) -> Result<(), Response<Body>> {
+    if state.backends.get(name).is_some() {
+        Ok(())
+    } else {
+        Err(create_error_response(
+            StatusCode::NOT_FOUND,
+            "backend_not_found",
+            &format!("No backend named {}", name),
+        ))
+    }
+}
+
+async fn drain_backend(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Response<Body> {
+    if let Err(response) = known_backend(&state, &name) {
+        return response;
+    }
+    println!("Draining backend {}", name);
+    state.maintenance.set_drained(&name, true);
+    Json(json!({
+        "name": name,
+        "drained": true,
+        "active_streams": state.streams.active_for_backend(&name),
+    }))
+    .into_response()
+}
+
+async fn undrain_backend(
+    State(state): State<Arc<AppState>>,
+    Path(name): Path<String>,
+) -> Response<Body> {
+    if let Err(response) = known_backend(&state, &name) {
+        return response;
+    }
+    println!("Backend {} back in rotation", name);
+    state.maintenance.set_drained(&name, false);
+    Json(json!({ "name": name, "drained": false })).into_response()
+}
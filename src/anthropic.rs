@@ -0,0 +1,373 @@
+use axum::http::StatusCode;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::provider::{self, ErrorClass, Provider, ProviderError, StreamTranslator};
+use crate::sse::SseEvent;
+use crate::tools::{self, ParallelToolCalls, ToolDeltaNormalizer};
+use crate::translation::content_text;
+
+/// `[anthropic]`: settings for backends with `protocol = "anthropic"`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AnthropicConfig {
+    /// Sent as `anthropic-version`.
+    #[serde(default = "default_version")]
+    pub version: String,
+    /// `max_tokens` for requests that set no limit; Anthropic requires one.
+    #[serde(default = "default_max_tokens")]
+    pub default_max_tokens: u64,
+}
+
+fn default_version() -> String {
+    "2023-06-01".to_string()
+}
+
+fn default_max_tokens() -> u64 {
+    4096
+}
+
+impl Default for AnthropicConfig {
+    fn default() -> Self {
+        AnthropicConfig {
+            version: default_version(),
+            default_max_tokens: default_max_tokens(),
+        }
+    }
+}
+
+/// Anthropic's `/v1/messages`, spoken to on behalf of OpenAI clients.
+pub struct Anthropic {
+    config: AnthropicConfig,
+}
+
+impl Anthropic {
+    pub fn new(config: AnthropicConfig) -> Self {
+        Anthropic { config }
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// OpenAI content (a string or parts) as Anthropic content blocks.
+fn content_blocks(content: &Value) -> Vec<Value> {
+    match content {
+        Value::String(text) if text.is_empty() => Vec::new(),
+        Value::String(text) => vec![json!({ "type": "text", "text": text })],
+        Value::Array(parts) => parts.iter().filter_map(content_block).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn content_block(part: &Value) -> Option<Value> {
+    match part.get("type").and_then(Value::as_str)? {
+        "text" => {
+            let text = part.get("text").and_then(Value::as_str).filter(|t| !t.is_empty())?;
+            Some(json!({ "type": "text", "text": text }))
+        }
+        "image_url" => {
+            let url = part.get("image_url").and_then(|i| i.get("url")).and_then(Value::as_str)?;
+            let source = match url
+                .strip_prefix("data:")
+                .and_then(|rest| rest.split_once(";base64,"))
+            {
+                Some((media_type, data)) => json!({ "type": "base64", "media_type": media_type, "data": data }),
+                None => json!({ "type": "url", "url": url }),
+            };
+            Some(json!({ "type": "image", "source": source }))
+        }
+        _ => None,
+    }
+}
+
+/// Appends a turn, merging it into the previous one when the role repeats:
+/// Anthropic wants alternating turns and tool results inside user turns.
+fn push_turn(messages: &mut Vec<Value>, role: &str, mut blocks: Vec<Value>) {
+    if blocks.is_empty() {
+        return;
+    }
+    if let Some(last) = messages.last_mut().filter(|m| m["role"] == role) {
+        if let Some(content) = last["content"].as_array_mut() {
+            content.append(&mut blocks);
+            return;
+        }
+    }
+    messages.push(json!({ "role": role, "content": blocks }));
+}
+
+fn finish_reason(stop_reason: &str) -> &'static str {
+    match stop_reason {
+        "max_tokens" => "length",
+        "tool_use" => "tool_calls",
+        "refusal" => "content_filter",
+        _ => "stop",
+    }
+}
+
+fn openai_usage(usage: &Value, prompt_tokens: u64) -> Value {
+    let cached = ["cache_creation_input_tokens", "cache_read_input_tokens"]
+        .iter()
+        .filter_map(|k| usage.get(*k).and_then(Value::as_u64))
+        .sum::<u64>();
+    let prompt_tokens = usage
+        .get("input_tokens")
+        .and_then(Value::as_u64)
+        .map(|input| input + cached)
+        .unwrap_or(prompt_tokens);
+    let completion_tokens = usage.get("output_tokens").and_then(Value::as_u64).unwrap_or(0);
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    })
+}
+
+impl Provider for Anthropic {
+    fn name(&self) -> &'static str {
+        "anthropic"
+    }
+
+    fn classify_response(
+        &self,
+        status: u16,
+        headers: &reqwest::header::HeaderMap,
+        body: &[u8],
+    ) -> ProviderError {
+        let mut error = provider::classify(status, headers, body);
+        if status == 529 {
+            // `overloaded_error`: transient, and not a status clients know.
+            error.class = ErrorClass::Retryable;
+            error.status = StatusCode::SERVICE_UNAVAILABLE;
+        }
+        error
+    }
+
+    fn authorize(&self, headers: &mut reqwest::header::HeaderMap, key: &str) {
+        headers.remove(reqwest::header::AUTHORIZATION);
+        headers.insert("x-api-key", key.parse().unwrap());
+        headers.insert("anthropic-version", self.config.version.parse().unwrap());
+    }
+
+    fn translate_request(&self, payload: &mut Map<String, Value>) -> Result<bool, String> {
+        if payload.contains_key("parallel_tool_calls") {
+            tools::apply_parallel_mode(payload, ParallelToolCalls::DisableParallelToolUse);
+        }
+        let Some(Value::Array(input)) = payload.remove("messages") else {
+            return Err("messages must be an array".to_string());
+        };
+
+        let mut system = Vec::new();
+        let mut messages = Vec::new();
+        for message in &input {
+            let content = message.get("content").unwrap_or(&Value::Null);
+            match message.get("role").and_then(Value::as_str).unwrap_or("user") {
+                "system" | "developer" => system.push(content_text(content)),
+                "assistant" => {
+                    let mut blocks = content_blocks(content);
+                    for call in message.get("tool_calls").and_then(Value::as_array).into_iter().flatten() {
+                        let function = &call["function"];
+                        let input = function["arguments"]
+                            .as_str()
+                            .and_then(|a| serde_json::from_str::<Value>(a).ok())
+                            .filter(Value::is_object)
+                            .unwrap_or_else(|| json!({}));
+                        blocks.push(json!({
+                            "type": "tool_use",
+                            "id": call["id"],
+                            "name": function["name"],
+                            "input": input,
+                        }));
+                    }
+                    push_turn(&mut messages, "assistant", blocks);
+                }
+                "tool" => {
+                    let result = json!({
+                        "type": "tool_result",
+                        "tool_use_id": message["tool_call_id"],
+                        "content": content_text(content),
+                    });
+                    push_turn(&mut messages, "user", vec![result]);
+                }
+                _ => push_turn(&mut messages, "user", content_blocks(content)),
+            }
+        }
+
+        let max_tokens = payload
+            .get("max_completion_tokens")
+            .or_else(|| payload.get("max_tokens"))
+            .and_then(Value::as_u64)
+            .unwrap_or(self.config.default_max_tokens);
+        let mut request = Map::new();
+        request.insert("model".to_string(), payload.get("model").cloned().unwrap_or(Value::Null));
+        request.insert("messages".to_string(), Value::Array(messages));
+        request.insert("max_tokens".to_string(), json!(max_tokens));
+        system.retain(|s| !s.is_empty());
+        if !system.is_empty() {
+            request.insert("system".to_string(), Value::String(system.join("\n\n")));
+        }
+        for key in ["stream", "temperature", "top_p"] {
+            if let Some(value) = payload.get(key) {
+                request.insert(key.to_string(), value.clone());
+            }
+        }
+        match payload.get("stop") {
+            Some(Value::String(stop)) => {
+                request.insert("stop_sequences".to_string(), json!([stop]));
+            }
+            Some(Value::Array(stops)) => {
+                request.insert("stop_sequences".to_string(), Value::Array(stops.clone()));
+            }
+            _ => {}
+        }
+        if let Some(Value::Array(functions)) = payload.get("tools") {
+            let tools: Vec<Value> = functions
+                .iter()
+                .map(|tool| {
+                    let function = tool.get("function").unwrap_or(tool);
+                    json!({
+                        "name": function["name"],
+                        "description": function.get("description").cloned().unwrap_or(json!("")),
+                        "input_schema": function.get("parameters").cloned().unwrap_or(json!({ "type": "object" })),
+                    })
+                })
+                .collect();
+            if !tools.is_empty() {
+                request.insert("tools".to_string(), Value::Array(tools));
+                let choice = tools::anthropic_tool_choice(payload.get("tool_choice").cloned());
+                if choice["type"] != "auto" || choice.get("disable_parallel_tool_use").is_some() {
+                    request.insert("tool_choice".to_string(), choice);
+                }
+            }
+        }
+        if let Some(user) = payload.get("user").and_then(Value::as_str) {
+            request.insert("metadata".to_string(), json!({ "user_id": user }));
+        }
+
+        *payload = request;
+        Ok(true)
+    }
+
+    fn translate_completion(&self, completion: &mut Value, _model: &str) -> bool {
+        if completion.get("type").and_then(Value::as_str) != Some("message") {
+            return false;
+        }
+        let blocks = completion["content"].as_array().cloned().unwrap_or_default();
+        let text: String = blocks
+            .iter()
+            .filter(|b| b["type"] == "text")
+            .filter_map(|b| b["text"].as_str())
+            .collect();
+        let tool_calls: Vec<Value> = blocks
+            .iter()
+            .filter(|b| b["type"] == "tool_use")
+            .map(|b| {
+                json!({
+                    "id": b["id"],
+                    "type": "function",
+                    "function": { "name": b["name"], "arguments": b["input"].to_string() },
+                })
+            })
+            .collect();
+
+        let mut message = json!({
+            "role": "assistant",
+            "content": if text.is_empty() && !tool_calls.is_empty() { Value::Null } else { json!(text) },
+        });
+        if !tool_calls.is_empty() {
+            message["tool_calls"] = Value::Array(tool_calls);
+        }
+        *completion = json!({
+            "id": completion["id"],
+            "object": "chat.completion",
+            "created": now(),
+            "model": completion["model"],
+            "choices": [{
+                "index": 0,
+                "message": message,
+                "finish_reason": finish_reason(completion["stop_reason"].as_str().unwrap_or("")),
+            }],
+            "usage": openai_usage(&completion["usage"], 0),
+        });
+        true
+    }
+
+    fn stream_translator(&self, _model: &str) -> Option<Box<dyn StreamTranslator>> {
+        Some(Box::new(AnthropicStream {
+            id: String::new(),
+            model: String::new(),
+            created: now(),
+            prompt_tokens: 0,
+            tools: ToolDeltaNormalizer::default(),
+        }))
+    }
+}
+
+/// Turns Messages API stream events into `chat.completion.chunk` events.
+struct AnthropicStream {
+    id: String,
+    model: String,
+    created: u64,
+    prompt_tokens: u64,
+    tools: ToolDeltaNormalizer,
+}
+
+impl AnthropicStream {
+    fn chunk(&self, delta: Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+        })
+    }
+}
+
+impl StreamTranslator for AnthropicStream {
+    fn translate(&mut self, event: SseEvent) -> Vec<SseEvent> {
+        let Ok(data) = serde_json::from_str::<Value>(&event.data) else {
+            return vec![event];
+        };
+        match data["type"].as_str().unwrap_or("") {
+            "message_start" => {
+                let message = &data["message"];
+                self.id = message["id"].as_str().unwrap_or_default().to_string();
+                self.model = message["model"].as_str().unwrap_or_default().to_string();
+                self.prompt_tokens = openai_usage(&message["usage"], 0)["prompt_tokens"]
+                    .as_u64()
+                    .unwrap_or(0);
+                let chunk = self.chunk(json!({ "role": "assistant", "content": "" }), None);
+                vec![SseEvent::data(chunk.to_string())]
+            }
+            "content_block_start" | "content_block_delta" => {
+                if let Some(delta) = self.tools.anthropic_delta(&data) {
+                    return vec![SseEvent::data(self.chunk(delta, None).to_string())];
+                }
+                let text = data["content_block"]["text"]
+                    .as_str()
+                    .or_else(|| data["delta"]["text"].as_str())
+                    .unwrap_or("");
+                if text.is_empty() {
+                    Vec::new()
+                } else {
+                    vec![SseEvent::data(self.chunk(json!({ "content": text }), None).to_string())]
+                }
+            }
+            "message_delta" => {
+                let reason = finish_reason(data["delta"]["stop_reason"].as_str().unwrap_or(""));
+                let mut chunk = self.chunk(json!({}), Some(reason));
+                chunk["usage"] = openai_usage(&data["usage"], self.prompt_tokens);
+                vec![SseEvent::data(chunk.to_string())]
+            }
+            "message_stop" => vec![SseEvent::data("[DONE]")],
+            "error" => vec![SseEvent::data(json!({ "error": data["error"] }).to_string())],
+            // `ping` and `content_block_stop` carry nothing for OpenAI clients.
+            _ => Vec::new(),
+        }
+    }
+}
@@ -0,0 +1,460 @@
+use axum::{
+    body::Body,
+    extract::{ConnectInfo, Request, State},
+    http::{header, HeaderMap, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use crate::backends::Backend;
+use crate::create_error_response;
+use crate::policy;
+use crate::snapshot::AgedCount;
+use crate::AppState;
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AuthConfig {
+    /// Trust identity headers set by an authenticating reverse proxy.
+    #[serde(default)]
+    pub trusted_header: Option<TrustedHeaderConfig>,
+    /// Accept HMAC-signed requests from server-to-server clients.
+    #[serde(default)]
+    pub hmac: Option<HmacConfig>,
+    /// Client keys issued by the adapter. Once any are configured, API
+    /// routes reject bearer keys not among them.
+    #[serde(default)]
+    pub virtual_keys: Vec<VirtualKey>,
+}
+
+/// `[[auth.virtual_keys]]` entry.
+#[derive(Debug, Deserialize, Clone)]
+pub struct VirtualKey {
+    pub key: String,
+    /// Shown in logs and admin views instead of the key.
+    pub name: String,
+    /// Models the key may request; a trailing `*` matches by prefix. Empty
+    /// allows every model.
+    #[serde(default)]
+    pub models: Vec<String>,
+    #[serde(default)]
+    pub requests_per_minute: Option<u64>,
+    /// Chat tokens per minute, in place of `limits.tokens_per_minute`.
+    #[serde(default)]
+    pub tokens_per_minute: Option<u64>,
+    #[serde(default)]
+    pub tenant: Option<String>,
+    /// Sent upstream instead of the backend's own key.
+    #[serde(default)]
+    pub upstream_key: Option<String>,
+    /// Upstream keys by backend name, taking precedence over `upstream_key`.
+    #[serde(default)]
+    pub upstream_keys: HashMap<String, String>,
+    /// Labels that `[[rules]]` can match on.
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+/// Signed requests carry `x-llmta-client`, `x-llmta-timestamp` (unix seconds),
+/// `x-llmta-nonce` and `x-llmta-signature`: the hex HMAC-SHA256 of
+/// `{timestamp}.{nonce}.{body}` under the client's secret.
+#[derive(Debug, Deserialize, Clone)]
+pub struct HmacConfig {
+    pub clients: Vec<HmacClient>,
+    /// Accepted clock skew; nonces are remembered for the same window.
+    #[serde(default = "default_max_skew_secs")]
+    pub max_skew_secs: u64,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct HmacClient {
+    pub id: String,
+    pub secret: String,
+    #[serde(default)]
+    pub requests_per_minute: Option<u64>,
+}
+
+fn default_max_skew_secs() -> u64 {
+    300
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TrustedHeaderConfig {
+    #[serde(default = "default_identity_header")]
+    pub header: String,
+    /// Peer addresses or CIDR ranges allowed to assert identities.
+    pub trusted_proxies: Vec<String>,
+    /// Identity to tenant mapping, first match wins.
+    #[serde(default)]
+    pub tenants: Vec<TenantMapping>,
+    /// Tenant for identities matching no mapping; the identity itself if unset.
+    #[serde(default)]
+    pub default_tenant: Option<String>,
+    /// Reject requests that do not carry a trusted identity.
+    #[serde(default)]
+    pub required: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TenantMapping {
+    /// An exact identity (`alice@example.com`) or a domain suffix (`@example.com`).
+    pub identity: String,
+    pub tenant: String,
+    /// Overrides `limits.requests_per_minute` for this tenant.
+    #[serde(default)]
+    pub requests_per_minute: Option<u64>,
+}
+
+fn default_identity_header() -> String {
+    "x-auth-request-email".to_string()
+}
+
+/// Who a request is attributed to for limits, overrides and logging.
+#[derive(Debug, Clone)]
+pub struct Identity {
+    /// Stable key for limits and the key store; may be a secret.
+    pub id: String,
+    /// Safe to log and show in admin views.
+    pub label: String,
+    /// Name per-key overrides are stored under: the virtual key's name, the
+    /// signing client's id or the asserted identity. Unset for plain bearer
+    /// keys, which are named in `[[keys]]`.
+    pub name: Option<String>,
+    pub tenant: Option<String>,
+    pub requests_per_minute: Option<u64>,
+    /// The virtual key the caller presented, if any.
+    pub virtual_key: Option<Arc<VirtualKey>>,
+}
+
+impl Identity {
+    /// Who quotas and per-tenant stores are charged to: the tenant, or the caller itself.
+    pub fn tenant_key(&self) -> String {
+        self.tenant.clone().unwrap_or_else(|| self.label.clone())
+    }
+
+    /// Rejects models outside the caller's virtual key.
+    #[allow(clippy::result_large_err)]
+    pub fn check_model(&self, model: &str) -> Result<(), Response<Body>> {
+        match &self.virtual_key {
+            Some(key) if !policy::matches(&key.models, model) => Err(create_error_response(
+                StatusCode::FORBIDDEN,
+                "model_not_allowed",
+                &format!("Key {} may not use model {}", key.name, model),
+            )),
+            _ => Ok(()),
+        }
+    }
+
+    /// The key to send to `backend`: the virtual key's upstream credentials
+    /// if it has any for it, else the backend's own.
+    pub fn upstream_key<'a>(&'a self, backend: &'a Backend) -> &'a str {
+        let Some(key) = &self.virtual_key else {
+            return &backend.key;
+        };
+        key.upstream_keys
+            .get(&backend.name)
+            .or(key.upstream_key.as_ref())
+            .unwrap_or(&backend.key)
+    }
+}
+
+/// Resolves the caller of each request from the configured auth methods.
+pub struct Authenticator {
+    config: AuthConfig,
+    nonces: Mutex<HashMap<String, Instant>>,
+    virtual_keys: HashMap<String, Arc<VirtualKey>>,
+}
+
+impl Authenticator {
+    pub fn new(config: AuthConfig) -> Self {
+        let virtual_keys = config
+            .virtual_keys
+            .iter()
+            .map(|key| (key.key.clone(), Arc::new(key.clone())))
+            .collect();
+        Authenticator {
+            config,
+            nonces: Mutex::new(HashMap::new()),
+            virtual_keys,
+        }
+    }
+
+    /// With virtual keys configured, lets through only requests presenting
+    /// one or authenticating another configured way. Signatures are only
+    /// checked for presence here; `identify` verifies them.
+    #[allow(clippy::result_large_err)]
+    pub fn check_key(&self, headers: &HeaderMap, peer: Option<IpAddr>) -> Result<(), Response<Body>> {
+        let key = bearer_key(headers);
+        if self.virtual_keys.is_empty() || self.virtual_keys.contains_key(key) {
+            return Ok(());
+        }
+        if self.config.hmac.is_some() && headers.contains_key("x-llmta-signature") {
+            return Ok(());
+        }
+        let trusted = self.config.trusted_header.as_ref();
+        if trusted.is_some_and(|trusted| trusted_identity(trusted, headers, peer).is_some()) {
+            return Ok(());
+        }
+        let message = if key.is_empty() {
+            "Requests must carry an API key as `Authorization: Bearer <key>`"
+        } else {
+            "Incorrect API key provided"
+        };
+        Err(create_error_response(StatusCode::UNAUTHORIZED, "invalid_api_key", message))
+    }
+
+    pub fn export_nonces(&self) -> Vec<AgedCount> {
+        self.nonces
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(key, seen)| AgedCount {
+                key: key.clone(),
+                count: 1,
+                age_ms: seen.elapsed().as_millis() as u64,
+            })
+            .collect()
+    }
+
+    pub fn import_nonces(&self, nonces: &[AgedCount]) {
+        let now = Instant::now();
+        let mut seen = self.nonces.lock().unwrap();
+        for nonce in nonces {
+            if let Some(at) = now.checked_sub(Duration::from_millis(nonce.age_ms)) {
+                seen.insert(nonce.key.clone(), at);
+            }
+        }
+    }
+
+    /// Forgets nonces older than the replay window; returns how many.
+    pub fn purge_nonces(&self) -> usize {
+        let Some(hmac) = &self.config.hmac else {
+            return 0;
+        };
+        let window = Duration::from_secs(hmac.max_skew_secs * 2);
+        let mut nonces = self.nonces.lock().unwrap();
+        let before = nonces.len();
+        nonces.retain(|_, seen| seen.elapsed() < window);
+        before - nonces.len()
+    }
+
+    #[allow(clippy::result_large_err)]
+    pub fn identify(
+        &self,
+        headers: &HeaderMap,
+        peer: Option<IpAddr>,
+        body: &[u8],
+    ) -> Result<Identity, Response<Body>> {
+        if let Some(hmac) = &self.config.hmac {
+            if headers.contains_key("x-llmta-signature") {
+                return self.verify_signature(hmac, headers, body);
+            }
+        }
+        if let Some(key) = self.virtual_keys.get(bearer_key(headers)) {
+            return Ok(Identity {
+                id: format!("vkey:{}", key.name),
+                label: key.name.clone(),
+                name: Some(key.name.clone()),
+                tenant: key.tenant.clone(),
+                requests_per_minute: key.requests_per_minute,
+                virtual_key: Some(key.clone()),
+            });
+        }
+        identify(&self.config, headers, peer)
+    }
+
+    #[allow(clippy::result_large_err)]
+    fn verify_signature(
+        &self,
+        config: &HmacConfig,
+        headers: &HeaderMap,
+        body: &[u8],
+    ) -> Result<Identity, Response<Body>> {
+        let header = |name: &str| headers.get(name).and_then(|v| v.to_str().ok()).unwrap_or("");
+        let reject = |message: &str| {
+            create_error_response(StatusCode::UNAUTHORIZED, "invalid_signature", message)
+        };
+
+        let Some(client) = config.clients.iter().find(|c| c.id == header("x-llmta-client")) else {
+            return Err(reject("Unknown signing client"));
+        };
+        let (timestamp, nonce) = (header("x-llmta-timestamp"), header("x-llmta-nonce"));
+        if nonce.is_empty() {
+            return Err(reject("Signed requests must carry a nonce"));
+        }
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap_or_default()
+            .as_secs();
+        match timestamp.parse::<u64>() {
+            Ok(ts) if ts.abs_diff(now) <= config.max_skew_secs => {}
+            _ => return Err(reject("Signature timestamp is missing or outside the allowed skew")),
+        }
+
+        let Ok(signature) = hex::decode(header("x-llmta-signature")) else {
+            return Err(reject("Signature is not valid hex"));
+        };
+        let mut mac = Hmac::<Sha256>::new_from_slice(client.secret.as_bytes())
+            .expect("HMAC accepts keys of any length");
+        mac.update(timestamp.as_bytes());
+        mac.update(b".");
+        mac.update(nonce.as_bytes());
+        mac.update(b".");
+        mac.update(body);
+        if mac.verify_slice(&signature).is_err() {
+            return Err(reject("Signature does not match"));
+        }
+
+        // Only verified nonces are remembered, so forgeries cannot burn them.
+        let window = Duration::from_secs(config.max_skew_secs * 2);
+        let mut nonces = self.nonces.lock().unwrap();
+        nonces.retain(|_, seen| seen.elapsed() < window);
+        let nonce_key = format!("{}:{}", client.id, nonce);
+        if nonces.contains_key(&nonce_key) {
+            return Err(reject("Nonce has already been used"));
+        }
+        nonces.insert(nonce_key, Instant::now());
+
+        Ok(Identity {
+            id: format!("hmac:{}", client.id),
+            label: client.id.clone(),
+            name: Some(client.id.clone()),
+            tenant: None,
+            requests_per_minute: client.requests_per_minute,
+            virtual_key: None,
+        })
+    }
+}
+
+/// Middleware on the API routes enforcing `[[auth.virtual_keys]]`.
+pub async fn require_key(State(state): State<Arc<AppState>>, request: Request, next: Next) -> Response<Body> {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    if let Err(response) = state.auth.check_key(request.headers(), peer) {
+        return response;
+    }
+    next.run(request).await
+}
+
+#[allow(clippy::result_large_err)]
+pub fn identify(
+    config: &AuthConfig,
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+) -> Result<Identity, Response<Body>> {
+    if let Some(trusted) = &config.trusted_header {
+        if let Some(identity) = trusted_identity(trusted, headers, peer) {
+            return Ok(identity);
+        }
+        if trusted.required {
+            return Err(create_error_response(
+                StatusCode::UNAUTHORIZED,
+                "authentication_required",
+                "Requests must come through the authenticating proxy",
+            ));
+        }
+    }
+
+    Ok(Identity {
+        id: bearer_key(headers).to_string(),
+        label: key_label(bearer_key(headers)),
+        name: None,
+        tenant: None,
+        requests_per_minute: None,
+        virtual_key: None,
+    })
+}
+
+fn trusted_identity(
+    config: &TrustedHeaderConfig,
+    headers: &HeaderMap,
+    peer: Option<IpAddr>,
+) -> Option<Identity> {
+    let peer = peer?.to_canonical();
+    if !config.trusted_proxies.iter().any(|cidr| cidr_contains(cidr, peer)) {
+        return None;
+    }
+    let identity = headers
+        .get(config.header.as_str())
+        .and_then(|v| v.to_str().ok())
+        .map(str::trim)
+        .filter(|v| !v.is_empty())?;
+
+    let mapping = config.tenants.iter().find(|m| {
+        if m.identity.starts_with('@') {
+            identity.to_lowercase().ends_with(&m.identity.to_lowercase())
+        } else {
+            m.identity.eq_ignore_ascii_case(identity)
+        }
+    });
+    let tenant = mapping
+        .map(|m| m.tenant.clone())
+        .or_else(|| config.default_tenant.clone())
+        .unwrap_or_else(|| identity.to_string());
+
+    Some(Identity {
+        id: format!("tenant:{}", tenant),
+        label: identity.to_string(),
+        name: Some(identity.to_string()),
+        tenant: Some(tenant),
+        requests_per_minute: mapping.and_then(|m| m.requests_per_minute),
+        virtual_key: None,
+    })
+}
+
+/// The caller's bearer key, also accepted in DeepL's `DeepL-Auth-Key`
+/// scheme, or an empty string for anonymous requests.
+pub fn bearer_key(headers: &HeaderMap) -> &str {
+    headers
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.strip_prefix("Bearer ").or_else(|| v.strip_prefix("DeepL-Auth-Key ")))
+        .unwrap_or("")
+}
+
+/// A short, non-secret label for a bearer key (`sk-a…wxyz`).
+pub fn key_label(key: &str) -> String {
+    let chars: Vec<char> = key.chars().collect();
+    match chars.len() {
+        0 => "anonymous".to_string(),
+        n if n <= 8 => "*".repeat(n),
+        n => format!(
+            "{}…{}",
+            chars[..4].iter().collect::<String>(),
+            chars[n - 4..].iter().collect::<String>()
+        ),
+    }
+}
+
+/// Matches an address against `10.0.0.0/8`, `fd00::/8` or a bare address.
+pub fn cidr_contains(cidr: &str, ip: IpAddr) -> bool {
+    let (network, bits) = match cidr.split_once('/') {
+        Some((network, bits)) => (network, bits.parse::<u32>().ok()),
+        None => (cidr, None),
+    };
+    let Ok(network) = network.trim().parse::<IpAddr>() else {
+        return false;
+    };
+
+    match (network, ip) {
+        (IpAddr::V4(network), IpAddr::V4(ip)) => {
+            let bits = bits.unwrap_or(32).min(32);
+            let mask = u32::MAX.checked_shl(32 - bits).unwrap_or(0);
+            u32::from(network) & mask == u32::from(ip) & mask
+        }
+        (IpAddr::V6(network), IpAddr::V6(ip)) => {
+            let bits = bits.unwrap_or(128).min(128);
+            let mask = u128::MAX.checked_shl(128 - bits).unwrap_or(0);
+            u128::from(network) & mask == u128::from(ip) & mask
+        }
+        _ => false,
+    }
+}
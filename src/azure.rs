@@ -0,0 +1,75 @@
+use serde::Deserialize;
+use std::collections::HashMap;
+
+use crate::provider::Provider;
+
+/// `[azure]`: settings for backends with `protocol = "azure"`, whose URL is
+/// the resource endpoint such as `https://my-resource.openai.azure.com`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct AzureConfig {
+    #[serde(default = "default_api_version")]
+    pub api_version: String,
+    /// Deployment name by model; unlisted models are used as the deployment name.
+    #[serde(default)]
+    pub deployments: HashMap<String, String>,
+}
+
+fn default_api_version() -> String {
+    "2024-06-01".to_string()
+}
+
+impl Default for AzureConfig {
+    fn default() -> Self {
+        AzureConfig {
+            api_version: default_api_version(),
+            deployments: HashMap::new(),
+        }
+    }
+}
+
+impl AzureConfig {
+    pub fn deployment<'a>(&'a self, model: &'a str) -> &'a str {
+        self.deployments.get(model).map(String::as_str).unwrap_or(model)
+    }
+}
+
+/// Azure OpenAI: the OpenAI wire format behind per-deployment URLs and
+/// `api-key` authentication.
+pub struct Azure {
+    config: AzureConfig,
+}
+
+impl Azure {
+    pub fn new(config: AzureConfig) -> Self {
+        Azure { config }
+    }
+}
+
+impl Provider for Azure {
+    fn name(&self) -> &'static str {
+        "azure"
+    }
+
+    fn chat_url(&self, url: &str, model: &str, _stream: bool) -> String {
+        format!(
+            "{}/openai/deployments/{}/chat/completions?api-version={}",
+            url.trim_end_matches('/'),
+            self.config.deployment(model),
+            self.config.api_version
+        )
+    }
+
+    fn embeddings_url(&self, base_url: &str, model: &str) -> Option<String> {
+        Some(format!(
+            "{}/openai/deployments/{}/embeddings?api-version={}",
+            base_url,
+            self.config.deployment(model),
+            self.config.api_version
+        ))
+    }
+
+    fn authorize(&self, headers: &mut reqwest::header::HeaderMap, key: &str) {
+        headers.remove(reqwest::header::AUTHORIZATION);
+        headers.insert("api-key", key.parse().unwrap());
+    }
+}
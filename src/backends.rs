@@ -0,0 +1,232 @@
+use reqwest::Client;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::balance::{Balancer, Cooldown};
+use crate::breaker::Breaker;
+use crate::config::AppConfig;
+use crate::handshake::Handshakes;
+use crate::maintenance::{Maintenance, DEFAULT_BACKEND};
+use crate::provider::{Protocol, Provider, ProviderError};
+use crate::regions::{self, RegionProbe};
+use crate::tls::{self, TlsConfig};
+
+/// `[[backends]]` entry: an upstream serving the listed models.
+#[derive(Debug, Deserialize, Clone)]
+pub struct BackendConfig {
+    pub name: String,
+    /// Chat completions URL, like `model_url`.
+    pub url: String,
+    pub key: String,
+    #[serde(default)]
+    pub protocol: Protocol,
+    /// Model names routed here; a trailing `*` matches by prefix.
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Overrides the top-level `[tls]` for this backend. Pins are taken
+    /// from here only.
+    #[serde(default)]
+    pub tls: Option<TlsConfig>,
+    /// Most inputs per embeddings request, below the provider's own limit;
+    /// larger requests are split into batches.
+    #[serde(default)]
+    pub max_batch: Option<usize>,
+    /// Region of this deployment. Among regional backends serving the same
+    /// model, requests go to the fastest healthy one; see `[regions]`.
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Override `[pool]` `max_handshakes` and `prewarm` for this backend.
+    #[serde(default)]
+    pub max_handshakes: Option<usize>,
+    #[serde(default)]
+    pub prewarm: Option<usize>,
+    /// Share of traffic under the `weighted` balancing strategy.
+    #[serde(default = "default_weight")]
+    pub weight: u32,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+pub struct Backend {
+    pub name: String,
+    pub url: String,
+    pub key: String,
+    pub models: Vec<String>,
+    pub client: Client,
+    pub provider: Arc<dyn Provider>,
+    pub max_batch: Option<usize>,
+    pub region: Option<String>,
+    pub probe: RegionProbe,
+    pub handshakes: Arc<Handshakes>,
+    /// Connections to open at startup.
+    pub prewarm: usize,
+    pub weight: u32,
+    pub cooldown: Cooldown,
+    pub breaker: Breaker,
+}
+
+impl Backend {
+    pub fn serves(&self, model: &str) -> bool {
+        self.models.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => model.starts_with(prefix),
+            None => model == pattern,
+        })
+    }
+
+    /// Most inputs one embeddings request to this backend may carry.
+    pub fn max_batch(&self) -> usize {
+        let limit = self.provider.max_embedding_inputs();
+        self.max_batch.map_or(limit, |max| max.clamp(1, limit))
+    }
+
+    /// Feeds the outcome of a request, as the backend's provider classifies
+    /// it, to the cooldown and circuit breaker. A failed response's body goes
+    /// on to the client, so it is classified by status and headers.
+    pub fn observe(&self, sent: &Result<reqwest::Response, ProviderError>) {
+        let failure = match sent {
+            Ok(response) if response.status().is_success() => None,
+            Ok(response) => Some(self.provider.classify_response(
+                response.status().as_u16(),
+                response.headers(),
+                &[],
+            )),
+            Err(error) => Some(error.clone()),
+        };
+        self.cooldown.observe(&self.name, failure.as_ref());
+        self.breaker.observe(&self.name, failure.as_ref());
+    }
+
+    /// The API root: `url` without its `/chat/completions`.
+    pub fn base_url(&self) -> &str {
+        self.url
+            .strip_suffix("/chat/completions")
+            .unwrap_or(&self.url)
+            .trim_end_matches('/')
+    }
+}
+
+/// Where a request goes, and the model name to send.
+pub struct Route {
+    pub backend: Arc<Backend>,
+    pub model: String,
+}
+
+/// The `model_url` backend plus any `[[backends]]`, with routing by model.
+pub struct Backends {
+    default: Arc<Backend>,
+    configured: Vec<Arc<Backend>>,
+    default_model: String,
+    /// Region by tenant, from `[regions]`.
+    pins: HashMap<String, String>,
+    balancer: Balancer,
+}
+
+fn handshakes(config: &AppConfig, max: Option<usize>) -> Arc<Handshakes> {
+    let idle_timeout = Duration::from_secs(config.pool.idle_timeout_secs);
+    Arc::new(Handshakes::new(max, idle_timeout, config.pool.max_idle_per_host))
+}
+
+impl Backends {
+    pub fn new(config: &AppConfig) -> Result<Self, String> {
+        let balancer = Balancer::new(&config.balancing);
+        let default = Arc::new(Backend {
+            name: DEFAULT_BACKEND.to_string(),
+            url: config.model_url.clone(),
+            key: config.model_key.clone(),
+            models: vec![config.default_model.clone()],
+            client: tls::build_client(&config.tls, &config.pool)?,
+            provider: config.protocol.provider(config),
+            max_batch: None,
+            region: None,
+            probe: RegionProbe::default(),
+            handshakes: handshakes(config, config.pool.max_handshakes),
+            prewarm: config.pool.prewarm,
+            weight: 1,
+            cooldown: Cooldown::new(balancer.cooldown()),
+            breaker: Breaker::new(&config.breaker),
+        });
+        let mut configured: Vec<Arc<Backend>> = Vec::new();
+        for backend in &config.backends {
+            if backend.name == DEFAULT_BACKEND || configured.iter().any(|b| b.name == backend.name) {
+                return Err(format!("duplicate backend name {:?}", backend.name));
+            }
+            let tls = backend.tls.clone().unwrap_or_else(|| config.tls.without_pins());
+            configured.push(Arc::new(Backend {
+                name: backend.name.clone(),
+                url: backend.url.clone(),
+                key: backend.key.clone(),
+                models: backend.models.clone(),
+                client: tls::build_client(&tls, &config.pool).map_err(|e| format!("backend {}: {}", backend.name, e))?,
+                provider: backend.protocol.provider(config),
+                max_batch: backend.max_batch,
+                region: backend.region.clone(),
+                probe: RegionProbe::default(),
+                handshakes: handshakes(config, backend.max_handshakes.or(config.pool.max_handshakes)),
+                prewarm: backend.prewarm.unwrap_or(config.pool.prewarm),
+                weight: backend.weight,
+                cooldown: Cooldown::new(balancer.cooldown()),
+                breaker: Breaker::new(&config.breaker),
+            }));
+        }
+        Ok(Backends {
+            default,
+            configured,
+            default_model: config.default_model.clone(),
+            pins: config.regions.pins.clone(),
+            balancer,
+        })
+    }
+
+    /// Picks the backend for `model`, asked for by `tenant`, passing over
+    /// drained backends as `for_model` does. Without
+    /// `[[backends]]` everything goes to `model_url` unchanged. Otherwise a
+    /// backend listing the model wins, see `for_model`; a model no backend
+    /// knows is replaced by `default_model`.
+    pub fn route(&self, model: &str, tenant: Option<&str>, maintenance: &Maintenance) -> Route {
+        if self.configured.is_empty() {
+            return Route {
+                backend: self.default.clone(),
+                model: model.to_string(),
+            };
+        }
+        let model = if model == self.default_model || self.configured.iter().any(|b| b.serves(model)) {
+            model
+        } else {
+            self.default_model.as_str()
+        };
+        Route {
+            backend: self.for_model(model, tenant, maintenance).clone(),
+            model: model.to_string(),
+        }
+    }
+
+    /// The backend listing `model`, else the `model_url` backend. When
+    /// several regions serve it, the tenant's pinned region or the fastest
+    /// healthy one; otherwise one chosen by `[balancing]`. Drained backends
+    /// are passed over; when every one serving the model is drained, one of
+    /// them is returned, and the request is answered 503.
+    pub fn for_model(&self, model: &str, tenant: Option<&str>, maintenance: &Maintenance) -> &Arc<Backend> {
+        let serving: Vec<&Arc<Backend>> = self.configured.iter().filter(|b| b.serves(model)).collect();
+        let candidates: Vec<&Arc<Backend>> =
+            serving.iter().copied().filter(|b| !maintenance.is_drained(&b.name)).collect();
+        if candidates.is_empty() {
+            return serving.first().copied().unwrap_or(&self.default);
+        }
+        let pin = tenant.and_then(|tenant| self.pins.get(tenant)).map(String::as_str);
+        regions::select(&candidates, pin)
+            .or_else(|| self.balancer.pick(&candidates))
+            .unwrap_or(&self.default)
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Arc<Backend>> {
+        self.all().find(|b| b.name == name)
+    }
+
+    pub fn all(&self) -> impl Iterator<Item = &Arc<Backend>> {
+        std::iter::once(&self.default).chain(&self.configured)
+    }
+}
@@ -0,0 +1,152 @@
+use rand::Rng;
+use serde::Deserialize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::backends::Backend;
+use crate::provider::ProviderError;
+
+/// `[balancing]`: how requests spread over several backends serving the
+/// same model, such as one per API key. Regional backends are chosen by
+/// `[regions]` instead.
+#[derive(Debug, Deserialize, Clone)]
+pub struct BalancingConfig {
+    #[serde(default)]
+    pub strategy: Strategy,
+    /// Seconds a backend is passed over after a failure its provider
+    /// classifies as retryable, such as 429, 503 or no connection; a longer
+    /// `Retry-After` wins. 0 disables.
+    #[serde(default = "default_cooldown_secs")]
+    pub cooldown_secs: u64,
+}
+
+fn default_cooldown_secs() -> u64 {
+    30
+}
+
+impl Default for BalancingConfig {
+    fn default() -> Self {
+        BalancingConfig {
+            strategy: Strategy::default(),
+            cooldown_secs: default_cooldown_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum Strategy {
+    /// The first backend listed, the others only while it cools down.
+    #[default]
+    First,
+    RoundRobin,
+    /// The backend with the fewest chat requests and streams in flight.
+    LeastConnections,
+    /// Random, in proportion to each backend's `weight`.
+    Weighted,
+}
+
+/// Keeps a backend out of rotation for a while after it failed.
+#[derive(Debug)]
+pub struct Cooldown {
+    period: Duration,
+    until: Mutex<Option<Instant>>,
+}
+
+impl Cooldown {
+    pub fn new(period: Duration) -> Self {
+        Cooldown {
+            period,
+            until: Mutex::new(None),
+        }
+    }
+
+    /// Time left before the backend is used again, if it is cooling down.
+    pub fn remaining(&self) -> Option<Duration> {
+        let until = (*self.until.lock().unwrap())?;
+        until.checked_duration_since(Instant::now()).filter(|d| !d.is_zero())
+    }
+
+    pub fn is_cooling(&self) -> bool {
+        self.remaining().is_some()
+    }
+
+    fn start(&self, retry_after: Option<Duration>) {
+        if self.period.is_zero() {
+            return;
+        }
+        let period = retry_after.map_or(self.period, |after| after.max(self.period));
+        let until = Instant::now() + period;
+        let mut current = self.until.lock().unwrap();
+        if !current.is_some_and(|current| current >= until) {
+            *current = Some(until);
+        }
+    }
+
+    /// Starts the cooldown after a failure worth retrying elsewhere.
+    pub fn observe(&self, backend: &str, failure: Option<&ProviderError>) {
+        let Some(failure) = failure.filter(|f| f.is_retryable()) else {
+            return;
+        };
+        if !self.is_cooling() {
+            println!("Backend {} answered {}, cooling down", backend, failure.status.as_u16());
+        }
+        self.start(failure.retry_after);
+    }
+}
+
+/// Picks among the backends serving a model by the configured strategy.
+pub struct Balancer {
+    config: BalancingConfig,
+    next: AtomicUsize,
+}
+
+impl Balancer {
+    pub fn new(config: &BalancingConfig) -> Self {
+        Balancer {
+            config: config.clone(),
+            next: AtomicUsize::new(0),
+        }
+    }
+
+    pub fn cooldown(&self) -> Duration {
+        Duration::from_secs(self.config.cooldown_secs)
+    }
+
+    /// `None` without candidates. Backends cooling down or with an open
+    /// circuit are skipped unless all of them are.
+    pub fn pick<'a>(&self, candidates: &[&'a Arc<Backend>]) -> Option<&'a Arc<Backend>> {
+        let ready: Vec<&Arc<Backend>> = candidates
+            .iter()
+            .copied()
+            .filter(|b| !b.cooldown.is_cooling() && b.breaker.allows())
+            .collect();
+        let pool = if ready.is_empty() { candidates } else { &ready[..] };
+        match self.config.strategy {
+            Strategy::First => pool.first().copied(),
+            Strategy::RoundRobin if !pool.is_empty() => {
+                Some(pool[self.next.fetch_add(1, Ordering::Relaxed) % pool.len()])
+            }
+            Strategy::RoundRobin => None,
+            Strategy::LeastConnections => {
+                pool.iter().copied().min_by_key(|b| b.handshakes.active())
+            }
+            Strategy::Weighted => {
+                let total: u64 = pool.iter().map(|b| u64::from(b.weight)).sum();
+                if total == 0 {
+                    return pool.first().copied();
+                }
+                let mut roll = rand::thread_rng().gen_range(0..total);
+                pool.iter().copied().find(|b| {
+                    let weight = u64::from(b.weight);
+                    if roll < weight {
+                        return true;
+                    }
+                    roll -= weight;
+                    false
+                })
+            }
+        }
+    }
+}
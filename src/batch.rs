@@ -0,0 +1,241 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, OriginalUri, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::{channel::mpsc, SinkExt, StreamExt};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::create_error_response;
+use crate::translation::{self, Pipeline};
+use crate::AppState;
+
+/// `[batch]`: the `/v1/batch/translate` endpoint.
+#[derive(Debug, Deserialize, Clone)]
+#[serde(default)]
+pub struct BatchConfig {
+    /// Texts one request may submit.
+    pub max_items: usize,
+    /// Texts translated at once when the request does not say.
+    pub concurrency: usize,
+    /// Upper bound on the concurrency a request may ask for.
+    pub max_concurrency: usize,
+    /// Further attempts at a text after a failure classified as transient,
+    /// such as a 429 or 503 answer.
+    pub retries: u32,
+    /// Wait before the first retry, doubling after each; a longer
+    /// `Retry-After` from the pipeline wins.
+    pub backoff_ms: u64,
+}
+
+impl Default for BatchConfig {
+    fn default() -> Self {
+        BatchConfig {
+            max_items: 500,
+            concurrency: 4,
+            max_concurrency: 16,
+            retries: 2,
+            backoff_ms: 500,
+        }
+    }
+}
+
+/// Longest wait between attempts, whatever `Retry-After` asks for.
+const MAX_BACKOFF: Duration = Duration::from_secs(30);
+
+#[derive(Debug, Deserialize)]
+pub struct BatchRequest {
+    /// Chat model doing the translation; the default model when unset.
+    #[serde(default)]
+    pub model: Option<String>,
+    pub target_language: String,
+    #[serde(default)]
+    pub source_language: Option<String>,
+    pub texts: Vec<String>,
+    /// Terms to translate consistently, as for `x_glossary`.
+    #[serde(default)]
+    pub glossary: Option<Value>,
+    /// Texts translated at once; `[batch].concurrency` when unset.
+    #[serde(default)]
+    pub concurrency: Option<usize>,
+    /// Answer with NDJSON lines as texts complete rather than one body.
+    #[serde(default)]
+    pub stream: bool,
+}
+
+/// The outcome for one text.
+#[derive(Debug, Serialize, Clone)]
+pub struct BatchItem {
+    pub index: usize,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub translation: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub error: Option<String>,
+    /// HTTP status of the last attempt.
+    pub status: u16,
+    pub attempts: u32,
+    pub total_tokens: u64,
+}
+
+struct Batch {
+    pipeline: Pipeline,
+    request_id: Option<String>,
+    retries: u32,
+    backoff: Duration,
+}
+
+impl Batch {
+    /// Translates one text, retrying answers that may succeed later.
+    async fn translate(&self, index: usize, text: String) -> BatchItem {
+        let mut item = BatchItem {
+            index,
+            translation: None,
+            error: None,
+            status: 0,
+            attempts: 0,
+            total_tokens: 0,
+        };
+        let mut backoff = self.backoff;
+        loop {
+            item.attempts += 1;
+            let request_id = match &self.request_id {
+                Some(request_id) => format!("{}-{}-{}", request_id, index, item.attempts),
+                None => uuid::Uuid::new_v4().to_string(),
+            };
+            let failure = match self.pipeline.translate(&text, Some(&request_id)).await {
+                Ok((translated, usage)) => {
+                    item.status = StatusCode::OK.as_u16();
+                    item.total_tokens += usage["total_tokens"].as_u64().unwrap_or(0);
+                    item.translation = Some(translated);
+                    item.error = None;
+                    return item;
+                }
+                Err(failure) => failure,
+            };
+            item.status = failure.status.as_u16();
+            item.error = Some(failure.message.clone());
+            if !failure.retryable || item.attempts > self.retries {
+                println!("Batch item {} failed after {} attempts: {}", index, item.attempts, failure.message);
+                return item;
+            }
+            let wait = failure.retry_after.unwrap_or_default().max(backoff);
+            tokio::time::sleep(wait.min(MAX_BACKOFF)).await;
+            backoff = backoff.saturating_mul(2);
+        }
+    }
+}
+
+fn wants_ndjson(headers: &HeaderMap) -> bool {
+    headers
+        .get(header::ACCEPT)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|accept| accept.contains("application/x-ndjson"))
+}
+
+fn summary(items: usize, completed: usize, total_tokens: u64) -> Value {
+    json!({
+        "object": "batch.summary",
+        "items": items,
+        "completed": completed,
+        "failed": items - completed,
+        "total_tokens": total_tokens,
+    })
+}
+
+/// `POST /v1/batch/translate`: translates many texts, a bounded number at a
+/// time, retrying each on its own. Answers with the results in order, or,
+/// with `stream` or `Accept: application/x-ndjson`, with one NDJSON line per
+/// text as it completes and a final summary line.
+pub async fn handle_batch_translate(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let request = match serde_json::from_slice::<BatchRequest>(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            return create_error_response(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                &format!("Invalid batch request: {}", e),
+            );
+        }
+    };
+    let config = state.config.batch.clone();
+    if request.texts.is_empty() || request.texts.len() > config.max_items {
+        return create_error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            &format!("Submit between 1 and {} texts", config.max_items),
+        );
+    }
+
+    let stream = request.stream || wants_ndjson(&headers);
+    let concurrency = request
+        .concurrency
+        .unwrap_or(config.concurrency)
+        .clamp(1, config.max_concurrency.max(1));
+    let texts = request.texts;
+    let count = texts.len();
+    println!(
+        "Translating a batch of {} texts into {}, {} at a time",
+        count, request.target_language, concurrency
+    );
+    let instruction =
+        translation::instruction(&request.target_language, request.source_language.as_deref());
+    let mut pipeline = Pipeline::new(state, connect_info, uri, headers, instruction);
+    pipeline.model = request.model;
+    pipeline.glossary = request.glossary;
+    let batch = Arc::new(Batch {
+        request_id: pipeline.request_id().map(str::to_string),
+        pipeline,
+        retries: config.retries,
+        backoff: Duration::from_millis(config.backoff_ms),
+    });
+    let items = futures::stream::iter(texts.into_iter().enumerate()).map(move |(index, text)| {
+        let batch = batch.clone();
+        async move { batch.translate(index, text).await }
+    });
+
+    if !stream {
+        let items: Vec<BatchItem> = items.buffered(concurrency).collect().await;
+        let completed = items.iter().filter(|item| item.translation.is_some()).count();
+        let total_tokens = items.iter().map(|item| item.total_tokens).sum();
+        let mut answer = summary(count, completed, total_tokens);
+        answer["object"] = json!("batch.translation");
+        answer["results"] = json!(items);
+        return Json(answer).into_response();
+    }
+
+    let (mut tx, rx) = mpsc::channel::<Result<Bytes, std::io::Error>>(32);
+    tokio::spawn(async move {
+        let mut items = Box::pin(items.buffer_unordered(concurrency));
+        let (mut completed, mut total_tokens) = (0, 0);
+        while let Some(item) = items.next().await {
+            completed += usize::from(item.translation.is_some());
+            total_tokens += item.total_tokens;
+            let mut line = json!(item);
+            line["object"] = json!("batch.item");
+            // The client has gone; dropping `items` cancels the texts in flight.
+            if tx.is_closed() || tx.send(Ok(Bytes::from(format!("{}\n", line)))).await.is_err() {
+                return;
+            }
+        }
+        let done = summary(count, completed, total_tokens);
+        let _ = tx.send(Ok(Bytes::from(format!("{}\n", done)))).await;
+    });
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "application/x-ndjson")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::from_stream(rx))
+        .unwrap()
+}
@@ -0,0 +1,384 @@
+use base64::Engine;
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::anthropic::{Anthropic, AnthropicConfig};
+use crate::provider::{self, Provider, ProviderError, StreamTranslator};
+use crate::sigv4::{self, Credentials, Signer};
+use crate::sse::{SseEvent, StreamFormat};
+use crate::translation::content_text;
+
+/// `[bedrock]`: settings for backends with `protocol = "bedrock"`, whose URL
+/// is the runtime endpoint such as `https://bedrock-runtime.us-east-1.amazonaws.com`.
+/// The backend `key` is not used; requests are signed with SigV4 instead.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct BedrockConfig {
+    /// Defaults to the region in the endpoint host name, then `AWS_REGION`.
+    #[serde(default)]
+    pub region: Option<String>,
+    /// Credentials default to `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY`
+    /// and `AWS_SESSION_TOKEN`.
+    #[serde(default)]
+    pub access_key_id: Option<String>,
+    #[serde(default)]
+    pub secret_access_key: Option<String>,
+    #[serde(default)]
+    pub session_token: Option<String>,
+}
+
+/// Model families Bedrock serves with their own request bodies.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Family {
+    Claude,
+    Titan,
+    Llama,
+}
+
+/// Family of a model ID such as `anthropic.claude-3-haiku-20240307-v1:0`,
+/// with or without a cross-region prefix like `us.`.
+fn family(model: &str) -> Option<Family> {
+    if model.contains("anthropic.claude") {
+        Some(Family::Claude)
+    } else if model.contains("amazon.titan-text") {
+        Some(Family::Titan)
+    } else if model.contains("meta.llama") {
+        Some(Family::Llama)
+    } else {
+        None
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn region_from_host(url: &reqwest::Url) -> Option<String> {
+    let host = url.host_str()?;
+    let rest = host.strip_prefix("bedrock-runtime.")?;
+    rest.split('.').next().map(str::to_string)
+}
+
+/// Titan takes a transcript ending in the turn it should write.
+fn titan_prompt(messages: &[Value]) -> String {
+    let mut prompt = String::new();
+    for message in messages {
+        let text = content_text(message.get("content").unwrap_or(&Value::Null));
+        match message.get("role").and_then(Value::as_str).unwrap_or("user") {
+            "system" | "developer" => prompt.push_str(&format!("{}\n\n", text)),
+            "assistant" => prompt.push_str(&format!("Bot: {}\n", text)),
+            _ => prompt.push_str(&format!("User: {}\n", text)),
+        }
+    }
+    prompt.push_str("Bot:");
+    prompt
+}
+
+/// The Llama 3 chat template.
+fn llama_prompt(messages: &[Value]) -> String {
+    let mut prompt = String::from("<|begin_of_text|>");
+    for message in messages {
+        let role = match message.get("role").and_then(Value::as_str).unwrap_or("user") {
+            "developer" => "system",
+            "tool" => "ipython",
+            role => role,
+        };
+        let text = content_text(message.get("content").unwrap_or(&Value::Null));
+        prompt.push_str(&format!("<|start_header_id|>{}<|end_header_id|>\n\n{}<|eot_id|>", role, text));
+    }
+    prompt.push_str("<|start_header_id|>assistant<|end_header_id|>\n\n");
+    prompt
+}
+
+fn stop_sequences(payload: &Map<String, Value>) -> Option<Value> {
+    match payload.get("stop") {
+        Some(Value::String(stop)) => Some(json!([stop])),
+        Some(Value::Array(stops)) => Some(Value::Array(stops.clone())),
+        _ => None,
+    }
+}
+
+fn finish_reason(reason: &str) -> &'static str {
+    match reason {
+        "LENGTH" | "MAX_TOKENS" | "length" => "length",
+        "CONTENT_FILTERED" => "content_filter",
+        _ => "stop",
+    }
+}
+
+/// Token counts from a Titan or Llama body, or from the invocation metrics
+/// Bedrock adds to the last streamed chunk.
+fn openai_usage(body: &Value) -> Option<Value> {
+    let metrics = &body["amazon-bedrock-invocationMetrics"];
+    let prompt_tokens = metrics["inputTokenCount"]
+        .as_u64()
+        .or_else(|| body["inputTextTokenCount"].as_u64())
+        .or_else(|| body["prompt_token_count"].as_u64())?;
+    let completion_tokens = metrics["outputTokenCount"]
+        .as_u64()
+        .or_else(|| body["results"][0]["tokenCount"].as_u64())
+        .or_else(|| body["totalOutputTextTokenCount"].as_u64())
+        .or_else(|| body["generation_token_count"].as_u64())
+        .unwrap_or(0);
+    Some(json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    }))
+}
+
+/// AWS Bedrock `InvokeModel` and `InvokeModelWithResponseStream` for
+/// Claude, Titan and Llama models, signed with SigV4.
+pub struct Bedrock {
+    anthropic: Anthropic,
+    region: Option<String>,
+    credentials: Option<Credentials>,
+}
+
+impl Bedrock {
+    pub fn new(config: &BedrockConfig, anthropic: AnthropicConfig) -> Self {
+        let credentials = match (&config.access_key_id, &config.secret_access_key) {
+            (Some(access_key_id), Some(secret_access_key)) => Some(Credentials {
+                access_key_id: access_key_id.clone(),
+                secret_access_key: secret_access_key.clone(),
+                session_token: config.session_token.clone(),
+            }),
+            _ => Credentials::from_env(),
+        };
+        if credentials.is_none() {
+            println!("No AWS credentials for Bedrock; requests will be sent unsigned");
+        }
+        Bedrock {
+            anthropic: Anthropic::new(anthropic),
+            region: config.region.clone().or_else(|| std::env::var("AWS_REGION").ok()),
+            credentials,
+        }
+    }
+}
+
+impl Provider for Bedrock {
+    fn name(&self) -> &'static str {
+        "bedrock"
+    }
+
+    fn classify_response(
+        &self,
+        status: u16,
+        headers: &reqwest::header::HeaderMap,
+        body: &[u8],
+    ) -> ProviderError {
+        let mut error = provider::classify(status, headers, body);
+        // `x-amzn-errortype: ThrottlingException:http://internal.amazon.com/...`
+        if let Some(kind) = headers.get("x-amzn-errortype").and_then(|v| v.to_str().ok()) {
+            error.error_type = kind.split(':').next().unwrap_or(kind).to_string();
+        }
+        let message = serde_json::from_slice::<Value>(body)
+            .ok()
+            .and_then(|v| v["message"].as_str().map(str::to_string));
+        if let Some(message) = message {
+            error.message = message;
+        }
+        error
+    }
+
+    fn chat_url(&self, url: &str, model: &str, stream: bool) -> String {
+        let action = if stream { "invoke-with-response-stream" } else { "invoke" };
+        format!(
+            "{}/model/{}/{}",
+            url.trim_end_matches('/'),
+            sigv4::uri_encode(model, false),
+            action
+        )
+    }
+
+    fn stream_format(&self) -> StreamFormat {
+        StreamFormat::AwsEventStream
+    }
+
+    fn authorize(&self, headers: &mut reqwest::header::HeaderMap, _key: &str) {
+        headers.remove(reqwest::header::AUTHORIZATION);
+        headers.remove(reqwest::header::ACCEPT);
+        headers.insert(reqwest::header::CONTENT_TYPE, "application/json".parse().unwrap());
+    }
+
+    fn sign(&self, method: &str, url: &str, headers: &mut reqwest::header::HeaderMap, body: &[u8]) {
+        let (Some(credentials), Ok(url)) = (&self.credentials, reqwest::Url::parse(url)) else {
+            return;
+        };
+        let region = self
+            .region
+            .clone()
+            .or_else(|| region_from_host(&url))
+            .unwrap_or_else(|| "us-east-1".to_string());
+        let signer = Signer {
+            credentials: credentials.clone(),
+            region,
+            service: "bedrock".to_string(),
+        };
+        signer.sign(method, &url, headers, body, SystemTime::now());
+    }
+
+    fn translate_request(&self, payload: &mut Map<String, Value>) -> Result<bool, String> {
+        let model = payload.get("model").and_then(Value::as_str).unwrap_or("").to_string();
+        let Some(family) = family(&model) else {
+            return Err(format!(
+                "Bedrock model {:?} is not supported; use an Anthropic Claude, Amazon Titan Text or Meta Llama model",
+                model
+            ));
+        };
+        if family == Family::Claude {
+            self.anthropic.translate_request(payload)?;
+            payload.remove("model");
+            payload.remove("stream");
+            payload.insert("anthropic_version".to_string(), json!("bedrock-2023-05-31"));
+            return Ok(true);
+        }
+
+        let Some(Value::Array(messages)) = payload.get("messages") else {
+            return Err("messages must be an array".to_string());
+        };
+        let max_tokens = payload
+            .get("max_completion_tokens")
+            .or_else(|| payload.get("max_tokens"))
+            .cloned();
+        let mut request = Map::new();
+        if family == Family::Titan {
+            let mut config = Map::new();
+            if let Some(max_tokens) = max_tokens {
+                config.insert("maxTokenCount".to_string(), max_tokens);
+            }
+            for (from, to) in [("temperature", "temperature"), ("top_p", "topP")] {
+                if let Some(value) = payload.get(from) {
+                    config.insert(to.to_string(), value.clone());
+                }
+            }
+            if let Some(stops) = stop_sequences(payload) {
+                config.insert("stopSequences".to_string(), stops);
+            }
+            request.insert("inputText".to_string(), json!(titan_prompt(messages)));
+            if !config.is_empty() {
+                request.insert("textGenerationConfig".to_string(), Value::Object(config));
+            }
+        } else {
+            request.insert("prompt".to_string(), json!(llama_prompt(messages)));
+            if let Some(max_tokens) = max_tokens {
+                request.insert("max_gen_len".to_string(), max_tokens);
+            }
+            for key in ["temperature", "top_p"] {
+                if let Some(value) = payload.get(key) {
+                    request.insert(key.to_string(), value.clone());
+                }
+            }
+        }
+        *payload = request;
+        Ok(true)
+    }
+
+    fn translate_completion(&self, completion: &mut Value, model: &str) -> bool {
+        if completion.get("type").and_then(Value::as_str) == Some("message") {
+            return self.anthropic.translate_completion(completion, model);
+        }
+        let (text, reason) = if let Some(result) = completion["results"].get(0) {
+            (&result["outputText"], &result["completionReason"])
+        } else if completion.get("generation").is_some() {
+            (&completion["generation"], &completion["stop_reason"])
+        } else {
+            return false;
+        };
+        let mut translated = json!({
+            "id": format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
+            "object": "chat.completion",
+            "created": now(),
+            "model": model,
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": text.as_str().unwrap_or("").trim_start() },
+                "finish_reason": finish_reason(reason.as_str().unwrap_or("")),
+            }],
+        });
+        if let Some(usage) = openai_usage(completion) {
+            translated["usage"] = usage;
+        }
+        *completion = translated;
+        true
+    }
+
+    fn stream_translator(&self, model: &str) -> Option<Box<dyn StreamTranslator>> {
+        Some(Box::new(BedrockStream {
+            id: format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
+            model: model.to_string(),
+            created: now(),
+            started: false,
+            claude: self.anthropic.stream_translator(model),
+        }))
+    }
+}
+
+/// Unwraps `chunk` events, whose payload is `{"bytes": "<base64 JSON>"}`, and
+/// turns the model's own chunks into `chat.completion.chunk` events.
+struct BedrockStream {
+    id: String,
+    model: String,
+    created: u64,
+    started: bool,
+    /// Claude chunks are Messages API events.
+    claude: Option<Box<dyn StreamTranslator>>,
+}
+
+impl BedrockStream {
+    fn chunk(&self, delta: Value, finish_reason: Option<&str>) -> Value {
+        json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": self.model,
+            "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+        })
+    }
+}
+
+impl StreamTranslator for BedrockStream {
+    fn translate(&mut self, event: SseEvent) -> Vec<SseEvent> {
+        if event.event.as_deref() != Some("chunk") {
+            // Errors decoded from exception frames are already OpenAI-shaped.
+            return vec![SseEvent::data(event.data)];
+        }
+        let body = serde_json::from_str::<Value>(&event.data)
+            .ok()
+            .and_then(|v| v["bytes"].as_str().map(str::to_string))
+            .and_then(|b| base64::engine::general_purpose::STANDARD.decode(b).ok())
+            .and_then(|b| serde_json::from_slice::<Value>(&b).ok());
+        let Some(body) = body else {
+            return Vec::new();
+        };
+        if body.get("type").is_some() {
+            if let Some(claude) = self.claude.as_mut() {
+                return claude.translate(SseEvent::data(body.to_string()));
+            }
+        }
+
+        let mut events = Vec::new();
+        let text = body["outputText"].as_str().or_else(|| body["generation"].as_str()).unwrap_or("");
+        if !self.started || !text.is_empty() {
+            let mut delta = json!({ "content": text });
+            if !self.started {
+                self.started = true;
+                delta["role"] = json!("assistant");
+            }
+            events.push(SseEvent::data(self.chunk(delta, None).to_string()));
+        }
+        let reason = body["completionReason"].as_str().or_else(|| body["stop_reason"].as_str());
+        if let Some(reason) = reason {
+            let mut chunk = self.chunk(json!({}), Some(finish_reason(reason)));
+            if let Some(usage) = openai_usage(&body) {
+                chunk["usage"] = usage;
+            }
+            events.push(SseEvent::data(chunk.to_string()));
+            events.push(SseEvent::data("[DONE]"));
+        }
+        events
+    }
+}
@@ -0,0 +1,196 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderValue, StatusCode},
+    response::Response,
+};
+use serde::{Deserialize, Serialize};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::create_error_response;
+use crate::provider::{ErrorClass, ProviderError};
+
+/// `[breaker]`: stops sending to a backend after repeated failures, then
+/// lets a few trial requests through once it has had time to recover.
+#[derive(Debug, Deserialize, Clone)]
+pub struct BreakerConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Consecutive failures that open the circuit: those the backend's
+    /// provider classifies as retryable or ambiguous, such as 5xx answers
+    /// and transport failures.
+    #[serde(default = "default_failures")]
+    pub failures: u32,
+    /// Seconds the circuit stays open before trial requests are let through.
+    #[serde(default = "default_open_secs")]
+    pub open_secs: u64,
+    /// Trial requests in flight at once while half-open.
+    #[serde(default = "default_half_open_requests")]
+    pub half_open_requests: u32,
+}
+
+fn default_failures() -> u32 {
+    5
+}
+
+fn default_open_secs() -> u64 {
+    30
+}
+
+fn default_half_open_requests() -> u32 {
+    1
+}
+
+impl Default for BreakerConfig {
+    fn default() -> Self {
+        BreakerConfig {
+            enabled: false,
+            failures: default_failures(),
+            open_secs: default_open_secs(),
+            half_open_requests: default_half_open_requests(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CircuitState {
+    Closed,
+    Open,
+    HalfOpen,
+}
+
+impl CircuitState {
+    /// Value of the `llmta_circuit_state` gauge.
+    pub fn gauge(self) -> u8 {
+        match self {
+            CircuitState::Closed => 0,
+            CircuitState::HalfOpen => 1,
+            CircuitState::Open => 2,
+        }
+    }
+}
+
+#[derive(Debug)]
+struct Circuit {
+    state: CircuitState,
+    failures: u32,
+    /// When the circuit last opened, or went half-open.
+    since: Instant,
+    trials: u32,
+}
+
+/// A backend's circuit breaker.
+#[derive(Debug)]
+pub struct Breaker {
+    config: BreakerConfig,
+    circuit: Mutex<Circuit>,
+}
+
+impl Breaker {
+    pub fn new(config: &BreakerConfig) -> Self {
+        Breaker {
+            config: config.clone(),
+            circuit: Mutex::new(Circuit {
+                state: CircuitState::Closed,
+                failures: 0,
+                since: Instant::now(),
+                trials: 0,
+            }),
+        }
+    }
+
+    fn open_for(&self) -> Duration {
+        Duration::from_secs(self.config.open_secs)
+    }
+
+    pub fn state(&self) -> CircuitState {
+        let circuit = self.circuit.lock().unwrap();
+        match circuit.state {
+            CircuitState::Open if circuit.since.elapsed() >= self.open_for() => {
+                CircuitState::HalfOpen
+            }
+            state => state,
+        }
+    }
+
+    /// Whether a request would be let through now, without claiming a trial.
+    pub fn allows(&self) -> bool {
+        self.state() != CircuitState::Open
+    }
+
+    /// Lets a request through, or says how long until the next may try.
+    /// While half-open, every admitted request is a trial; a round of
+    /// trials that never reported back is given up after `open_secs`.
+    pub fn admit(&self) -> Result<(), Duration> {
+        if !self.config.enabled {
+            return Ok(());
+        }
+        let mut circuit = self.circuit.lock().unwrap();
+        let open_for = self.open_for();
+        if circuit.state == CircuitState::Closed {
+            return Ok(());
+        }
+        if circuit.since.elapsed() >= open_for {
+            circuit.state = CircuitState::HalfOpen;
+            circuit.since = Instant::now();
+            circuit.trials = 0;
+        }
+        if circuit.state == CircuitState::Open {
+            return Err(open_for.saturating_sub(circuit.since.elapsed()));
+        }
+        if circuit.trials >= self.config.half_open_requests.max(1) {
+            return Err(Duration::from_secs(1));
+        }
+        circuit.trials += 1;
+        Ok(())
+    }
+
+    /// Counts the outcome of a request that was admitted: its classified
+    /// failure, or `None` when it succeeded.
+    pub fn observe(&self, backend: &str, failure: Option<&ProviderError>) {
+        if !self.config.enabled {
+            return;
+        }
+        // Refusals of the request itself say nothing about the backend.
+        let failed = failure.is_some_and(|f| f.class != ErrorClass::NonRetryable);
+        let mut circuit = self.circuit.lock().unwrap();
+        if !failed {
+            if circuit.state != CircuitState::Closed {
+                println!("Circuit for backend {} closed", backend);
+            }
+            circuit.state = CircuitState::Closed;
+            circuit.failures = 0;
+            return;
+        }
+        circuit.failures += 1;
+        let trips = match circuit.state {
+            CircuitState::Closed => circuit.failures >= self.config.failures.max(1),
+            CircuitState::HalfOpen => true,
+            CircuitState::Open => false,
+        };
+        if trips {
+            println!(
+                "Circuit for backend {} opened after {} consecutive failures",
+                backend, circuit.failures
+            );
+            circuit.state = CircuitState::Open;
+            circuit.since = Instant::now();
+        }
+    }
+}
+
+/// The 503 sent instead of calling a backend whose circuit is open.
+pub fn rejection(backend: &str, retry_after: Duration) -> Response<Body> {
+    println!("Circuit for backend {} is open, failing fast", backend);
+    let mut response = create_error_response(
+        StatusCode::SERVICE_UNAVAILABLE,
+        "circuit_open",
+        &format!("Backend {} is failing and is not being called for now", backend),
+    );
+    let secs = retry_after.as_secs() + u64::from(retry_after.subsec_nanos() > 0);
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(secs.max(1)));
+    response
+}
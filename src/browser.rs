@@ -0,0 +1,226 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderMap, Method, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::buildinfo;
+use crate::degrade::{accepted_languages, locale_matches};
+use crate::AppState;
+
+/// `[browser]`: what a browser sees when it opens an API route.
+#[derive(Debug, Deserialize, Clone)]
+pub struct BrowserConfig {
+    /// Serve a status page instead of a bare 405. Turn off for pure-API
+    /// deployments.
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default = "default_service_name")]
+    pub service_name: String,
+    /// Defaults to the adapter's own `/openapi.json`.
+    #[serde(default)]
+    pub docs_url: Option<String>,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn default_service_name() -> String {
+    "LLM Translator Adapter".to_string()
+}
+
+impl Default for BrowserConfig {
+    fn default() -> Self {
+        BrowserConfig {
+            enabled: default_enabled(),
+            service_name: default_service_name(),
+            docs_url: None,
+        }
+    }
+}
+
+/// Fixed strings of the status page in one language.
+struct Strings {
+    locale: &'static str,
+    heading: &'static str,
+    explanation: &'static str,
+    version: &'static str,
+    docs: &'static str,
+    status: &'static str,
+    operational: &'static str,
+    maintenance: &'static str,
+    drained: &'static str,
+    streams: &'static str,
+}
+
+const STRINGS: &[Strings] = &[
+    Strings {
+        locale: "en",
+        heading: "This is an API endpoint",
+        explanation: "It expects POST requests with a JSON body from an API client, not a browser.",
+        version: "Version",
+        docs: "API documentation",
+        status: "Status",
+        operational: "Operational",
+        maintenance: "Under maintenance",
+        drained: "Drained backends",
+        streams: "Active streams",
+    },
+    Strings {
+        locale: "de",
+        heading: "Dies ist ein API-Endpunkt",
+        explanation: "Er erwartet POST-Anfragen mit JSON-Inhalt von einem API-Client, nicht von einem Browser.",
+        version: "Version",
+        docs: "API-Dokumentation",
+        status: "Status",
+        operational: "In Betrieb",
+        maintenance: "Wartungsarbeiten",
+        drained: "Deaktivierte Backends",
+        streams: "Aktive Streams",
+    },
+    Strings {
+        locale: "fr",
+        heading: "Ceci est un point d'accès d'API",
+        explanation: "Il attend des requêtes POST avec un corps JSON envoyées par un client d'API, pas par un navigateur.",
+        version: "Version",
+        docs: "Documentation de l'API",
+        status: "État",
+        operational: "Opérationnel",
+        maintenance: "En maintenance",
+        drained: "Backends désactivés",
+        streams: "Flux actifs",
+    },
+    Strings {
+        locale: "es",
+        heading: "Este es un endpoint de API",
+        explanation: "Espera peticiones POST con un cuerpo JSON desde un cliente de API, no desde un navegador.",
+        version: "Versión",
+        docs: "Documentación de la API",
+        status: "Estado",
+        operational: "Operativo",
+        maintenance: "En mantenimiento",
+        drained: "Backends desactivados",
+        streams: "Streams activos",
+    },
+    Strings {
+        locale: "zh",
+        heading: "这是一个 API 接口",
+        explanation: "它接收来自 API 客户端的 JSON POST 请求，无法在浏览器中直接使用。",
+        version: "版本",
+        docs: "API 文档",
+        status: "状态",
+        operational: "运行正常",
+        maintenance: "维护中",
+        drained: "已停用的后端",
+        streams: "活动流",
+    },
+    Strings {
+        locale: "ja",
+        heading: "これは API エンドポイントです",
+        explanation: "API クライアントからの JSON 本文付き POST リクエストを受け付けます。ブラウザからは利用できません。",
+        version: "バージョン",
+        docs: "API ドキュメント",
+        status: "ステータス",
+        operational: "正常稼働中",
+        maintenance: "メンテナンス中",
+        drained: "停止中のバックエンド",
+        streams: "アクティブなストリーム",
+    },
+];
+
+fn strings(headers: &HeaderMap) -> &'static Strings {
+    accepted_languages(headers)
+        .iter()
+        .find_map(|(tag, _)| STRINGS.iter().find(|s| locale_matches(s.locale, tag)))
+        .unwrap_or(&STRINGS[0])
+}
+
+fn wants_html(request: &Request) -> bool {
+    request.method() == Method::GET
+        && request
+            .headers()
+            .get(header::ACCEPT)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|accept| accept.contains("text/html"))
+}
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn page(state: &AppState, strings: &Strings) -> String {
+    let browser = &state.config.browser;
+    let docs_url = browser.docs_url.clone().unwrap_or_else(|| {
+        let prefix = state.config.routes.prefix.as_deref().unwrap_or("");
+        format!("{}/openapi.json", prefix.trim_end_matches('/'))
+    });
+    let mut health = match state.maintenance.mode() {
+        Some(mode) => format!("{}: {}", strings.maintenance, escape(&mode.message)),
+        None => strings.operational.to_string(),
+    };
+    let drained = state.maintenance.drained();
+    if !drained.is_empty() {
+        health.push_str(&format!("<br>{}: {}", strings.drained, escape(&drained.join(", "))));
+    }
+    let name = escape(&browser.service_name);
+
+    format!(
+        r#"<!DOCTYPE html>
+<html lang="{lang}">
+<head><meta charset="utf-8"><title>{name}</title></head>
+<body style="font-family: sans-serif; max-width: 40em; margin: 3em auto">
+<h1>{name}</h1>
+<h2>{heading}</h2>
+<p>{explanation}</p>
+<dl>
+<dt>{version_label}</dt><dd>{version}</dd>
+<dt>{status_label}</dt><dd>{health}</dd>
+<dt>{streams_label}</dt><dd>{streams}</dd>
+</dl>
+<p><a href="{docs_url}">{docs}</a></p>
+</body>
+</html>
+"#,
+        lang = strings.locale,
+        heading = strings.heading,
+        explanation = strings.explanation,
+        version_label = strings.version,
+        version = buildinfo::VERSION,
+        status_label = strings.status,
+        streams_label = strings.streams,
+        streams = state.streams.stats().active,
+        docs_url = escape(&docs_url),
+        docs = strings.docs,
+    )
+}
+
+/// Replaces the empty 405 a browser gets on an API route with a small,
+/// localized status page.
+pub async fn status_page(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    if !state.config.browser.enabled || !wants_html(&request) {
+        return next.run(request).await;
+    }
+    let strings = strings(request.headers());
+    let mut response = next.run(request).await;
+    if response.status() != StatusCode::METHOD_NOT_ALLOWED {
+        return response;
+    }
+
+    let headers = response.headers_mut();
+    headers.insert(header::CONTENT_TYPE, "text/html; charset=utf-8".parse().unwrap());
+    headers.remove(header::CONTENT_LENGTH);
+    *response.body_mut() = Body::from(page(&state, strings));
+    response
+}
@@ -0,0 +1,67 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, HeaderValue},
+    response::Response,
+    Json,
+};
+use serde_json::{json, Value};
+use std::sync::Arc;
+
+use crate::AppState;
+
+pub const VERSION: &str = env!("CARGO_PKG_VERSION");
+/// Short commit hash, or `unknown` when built outside a git checkout.
+pub const GIT_COMMIT: &str = env!("LLMTA_GIT_COMMIT");
+pub const BUILD_DATE: &str = env!("LLMTA_BUILD_DATE");
+
+/// Cargo features compiled in.
+pub fn features() -> Vec<&'static str> {
+    [
+        ("native-tls", cfg!(feature = "native-tls")),
+        ("native-tls-system", cfg!(feature = "native-tls-system")),
+        ("rustls-ring", cfg!(feature = "rustls-ring")),
+        ("acme", cfg!(feature = "acme")),
+        ("tiktoken", cfg!(feature = "tiktoken")),
+        ("hf-tokenizers", cfg!(feature = "hf-tokenizers")),
+        ("redis", cfg!(feature = "redis")),
+        ("console", cfg!(feature = "console")),
+        ("profiling", cfg!(feature = "profiling")),
+        ("heap-profiling", cfg!(feature = "heap-profiling")),
+    ]
+    .into_iter()
+    .filter_map(|(name, enabled)| enabled.then_some(name))
+    .collect()
+}
+
+fn build_info() -> Value {
+    json!({
+        "version": VERSION,
+        "git_commit": GIT_COMMIT,
+        "build_date": BUILD_DATE,
+        "features": features(),
+    })
+}
+
+/// `GET /version`. Needs no key, so it leaves out the config hash: the
+/// configuration it is taken over includes secrets.
+pub async fn handle_version() -> Json<Value> {
+    Json(build_info())
+}
+
+/// `GET /admin/version`: the build plus `config_hash`, to tell whether
+/// instances run the same configuration.
+pub async fn handle_admin_version(State(state): State<Arc<AppState>>) -> Json<Value> {
+    let mut info = build_info();
+    info["config_hash"] = json!(state.config.config_hash);
+    Json(info)
+}
+
+/// Identifies the adapter build in the `server` header of every response.
+pub async fn server_header(mut response: Response<Body>) -> Response<Body> {
+    response.headers_mut().insert(
+        header::SERVER,
+        HeaderValue::from_static(concat!("llm-translator-adapter/", env!("CARGO_PKG_VERSION"))),
+    );
+    response
+}
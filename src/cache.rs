@@ -0,0 +1,609 @@
+use axum::{
+    body::{Body, Bytes},
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::fmt::Write;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::completion;
+use crate::embeddings;
+use crate::keys::KeyOverrides;
+use crate::snapshot::CachedAnswer;
+use crate::sse::SseEvent;
+use crate::translation;
+use crate::AppState;
+
+/// Request fields that do not change the answer.
+const IGNORED_FIELDS: &[&str] = &["stream", "stream_options", "user", "metadata", "store"];
+
+/// `[cache]`: answers to repeated chat requests, served without calling the
+/// backend.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CacheConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    #[serde(default)]
+    pub backend: CacheBackend,
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+    /// Entries kept by the in-memory cache; the least recently used go first.
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+    /// Larger answers are not cached.
+    #[serde(default = "default_max_entry_bytes")]
+    pub max_entry_bytes: usize,
+    /// For the `redis` backend, which needs the `redis` feature.
+    #[serde(default)]
+    pub redis_url: Option<String>,
+    #[serde(default = "default_redis_prefix")]
+    pub redis_prefix: String,
+    #[serde(default)]
+    pub semantic: Option<SemanticCacheConfig>,
+}
+
+/// `[cache.semantic]`: also serves the answer to an earlier request whose
+/// last user message means nearly the same, judged by the cosine similarity
+/// of their embeddings. The rest of the two requests must be identical.
+/// Kept in memory whatever the `backend`, and filled by unstreamed answers.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SemanticCacheConfig {
+    /// Embedding model, sent to whichever backend serves it.
+    pub model: String,
+    /// Least cosine similarity, up to 1, for a cached answer to be served.
+    #[serde(default = "default_threshold")]
+    pub threshold: f32,
+    #[serde(default = "default_semantic_entries")]
+    pub max_entries: usize,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum CacheBackend {
+    #[default]
+    Memory,
+    Redis,
+}
+
+fn default_ttl_secs() -> u64 {
+    3600
+}
+
+fn default_max_entries() -> usize {
+    10_000
+}
+
+fn default_max_entry_bytes() -> usize {
+    1024 * 1024
+}
+
+fn default_redis_prefix() -> String {
+    "llmta:cache:".to_string()
+}
+
+fn default_threshold() -> f32 {
+    0.95
+}
+
+fn default_semantic_entries() -> usize {
+    1000
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        CacheConfig {
+            enabled: false,
+            backend: CacheBackend::default(),
+            ttl_secs: default_ttl_secs(),
+            max_entries: default_max_entries(),
+            max_entry_bytes: default_max_entry_bytes(),
+            redis_url: None,
+            redis_prefix: default_redis_prefix(),
+            semantic: None,
+        }
+    }
+}
+
+/// An LRU map with expiring entries.
+#[derive(Default)]
+struct Lru {
+    entries: HashMap<String, (Bytes, Instant, u64)>,
+    /// Keys by last use.
+    order: BTreeMap<u64, String>,
+    tick: u64,
+}
+
+impl Lru {
+    fn get(&mut self, key: &str) -> Option<Bytes> {
+        let (value, expires, used) = self.entries.get_mut(key)?;
+        if *expires <= Instant::now() {
+            let used = *used;
+            self.entries.remove(key);
+            self.order.remove(&used);
+            return None;
+        }
+        self.tick += 1;
+        self.order.remove(used);
+        self.order.insert(self.tick, key.to_string());
+        *used = self.tick;
+        Some(value.clone())
+    }
+
+    fn put(&mut self, key: String, value: Bytes, ttl: Duration, max_entries: usize) {
+        self.tick += 1;
+        let entry = (value, Instant::now() + ttl, self.tick);
+        if let Some((_, _, used)) = self.entries.insert(key.clone(), entry) {
+            self.order.remove(&used);
+        }
+        self.order.insert(self.tick, key);
+        while self.entries.len() > max_entries.max(1) {
+            let Some((_, oldest)) = self.order.pop_first() else {
+                break;
+            };
+            self.entries.remove(&oldest);
+        }
+    }
+
+    /// Live entries, least recently used first.
+    fn export(&self) -> Vec<CachedAnswer> {
+        let now = Instant::now();
+        self.order
+            .values()
+            .filter_map(|key| {
+                let (value, expires, _) = self.entries.get(key)?;
+                let ttl = expires.checked_duration_since(now)?;
+                Some(CachedAnswer {
+                    key: key.clone(),
+                    body: String::from_utf8(value.to_vec()).ok()?,
+                    ttl_ms: ttl.as_millis() as u64,
+                })
+            })
+            .collect()
+    }
+}
+
+/// An answer remembered by the embedding of the message it answered.
+struct SemanticEntry {
+    /// Hash of the request without that message.
+    context: String,
+    vector: Vec<f32>,
+    completion: Bytes,
+    expires: Instant,
+}
+
+/// A request's last user message, embedded, and the hash of the rest.
+pub struct SemanticQuery {
+    context: String,
+    vector: Vec<f32>,
+}
+
+/// Completed chat answers by a hash of the request that produced them.
+pub struct ResponseCache {
+    config: CacheConfig,
+    memory: Mutex<Lru>,
+    redis: redis_store::Store,
+    /// Oldest first.
+    semantic: Mutex<VecDeque<SemanticEntry>>,
+    /// Bumped whenever stored templates or glossaries change.
+    generation: AtomicU64,
+}
+
+fn cosine_similarity(a: &[f32], b: &[f32]) -> f32 {
+    if a.len() != b.len() {
+        return 0.0;
+    }
+    let dot: f32 = a.iter().zip(b).map(|(x, y)| x * y).sum();
+    let norm = |v: &[f32]| v.iter().map(|x| x * x).sum::<f32>().sqrt();
+    let norms = norm(a) * norm(b);
+    if norms == 0.0 {
+        0.0
+    } else {
+        dot / norms
+    }
+}
+
+/// Writes `value` with object keys sorted, so equal payloads hash equally
+/// whatever order the client sent their fields in.
+fn canonical(value: &Value, out: &mut String) {
+    match value {
+        Value::Object(map) => {
+            let mut keys: Vec<&String> = map.keys().collect();
+            keys.sort();
+            out.push('{');
+            for (i, key) in keys.into_iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                let _ = write!(out, "{}:", Value::String(key.clone()));
+                canonical(&map[key], out);
+            }
+            out.push('}');
+        }
+        Value::Array(items) => {
+            out.push('[');
+            for (i, item) in items.iter().enumerate() {
+                if i > 0 {
+                    out.push(',');
+                }
+                canonical(item, out);
+            }
+            out.push(']');
+        }
+        other => {
+            let _ = write!(out, "{}", other);
+        }
+    }
+}
+
+fn cache_control(headers: &HeaderMap, directive: &str) -> bool {
+    headers
+        .get_all(header::CACHE_CONTROL)
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .flat_map(|v| v.split(','))
+        .any(|d| d.trim().eq_ignore_ascii_case(directive))
+}
+
+/// Whether the client accepts a cached answer; `Cache-Control: no-cache`
+/// asks for a fresh one, which is still cached.
+pub fn reads(headers: &HeaderMap) -> bool {
+    !cache_control(headers, "no-cache")
+}
+
+impl ResponseCache {
+    pub fn new(config: CacheConfig) -> Result<Self, String> {
+        let redis = match (config.enabled, config.backend) {
+            (true, CacheBackend::Redis) => {
+                let url = config
+                    .redis_url
+                    .as_deref()
+                    .ok_or("cache.backend = \"redis\" needs cache.redis_url")?;
+                redis_store::Store::open(url)?
+            }
+            _ => Default::default(),
+        };
+        Ok(ResponseCache {
+            config,
+            memory: Mutex::new(Lru::default()),
+            redis,
+            semantic: Mutex::new(VecDeque::new()),
+            generation: AtomicU64::new(0),
+        })
+    }
+
+    /// What besides the payload shapes the request sent upstream: the
+    /// tenant, the caller's key overrides, and the stored templates and
+    /// glossaries as of their last change.
+    pub fn scope(&self, tenant: &str, overrides: Option<&KeyOverrides>) -> String {
+        let overrides = overrides.map(|o| serde_json::to_string(o).unwrap()).unwrap_or_default();
+        format!("{}\0{}\0{}", tenant, self.generation.load(Ordering::Relaxed), overrides)
+    }
+
+    /// Moves every later key to a new scope, so answers given before stored
+    /// templates or glossaries changed are no longer served.
+    pub fn invalidate(&self) {
+        self.generation.fetch_add(1, Ordering::Relaxed);
+    }
+
+    /// The in-memory answers and the generation their keys were made in.
+    /// A Redis cache is already shared and the semantic one is left to refill.
+    pub fn export(&self) -> (u64, Vec<CachedAnswer>) {
+        let entries = match self.config.backend {
+            CacheBackend::Memory => self.memory.lock().unwrap().export(),
+            CacheBackend::Redis => Vec::new(),
+        };
+        (self.generation.load(Ordering::Relaxed), entries)
+    }
+
+    /// Takes over answers exported by [`ResponseCache::export`]. Adopting
+    /// their generation keeps answers invalidated before the export from
+    /// matching again.
+    pub fn import(&self, generation: u64, entries: &[CachedAnswer]) {
+        self.generation.fetch_max(generation, Ordering::Relaxed);
+        if !self.config.enabled || self.config.backend != CacheBackend::Memory {
+            return;
+        }
+        let mut memory = self.memory.lock().unwrap();
+        for entry in entries {
+            let ttl = Duration::from_millis(entry.ttl_ms).min(Duration::from_secs(self.config.ttl_secs));
+            memory.put(entry.key.clone(), Bytes::from(entry.body.clone()), ttl, self.config.max_entries);
+        }
+    }
+
+    /// The cache key of a chat request within `scope`; `None` when caching
+    /// is off or the client sent `Cache-Control: no-store`.
+    pub fn key(
+        &self,
+        scope: &str,
+        payload: &Map<String, Value>,
+        headers: &HeaderMap,
+    ) -> Option<String> {
+        if !self.config.enabled || cache_control(headers, "no-store") {
+            return None;
+        }
+        Some(hash(scope, payload.clone()))
+    }
+
+    /// Served answers to earlier requests with the same context, if one was
+    /// close enough in meaning.
+    pub fn similar(&self, query: &SemanticQuery) -> Option<Bytes> {
+        let threshold = self.config.semantic.as_ref()?.threshold;
+        let mut entries = self.semantic.lock().unwrap();
+        let now = Instant::now();
+        entries.retain(|entry| entry.expires > now);
+        entries
+            .iter()
+            .filter(|entry| entry.context == query.context)
+            .map(|entry| (cosine_similarity(&entry.vector, &query.vector), entry))
+            .filter(|(similarity, _)| *similarity >= threshold)
+            .max_by(|(a, _), (b, _)| a.total_cmp(b))
+            .map(|(_, entry)| entry.completion.clone())
+    }
+
+    fn remember(&self, query: SemanticQuery, completion: Bytes) {
+        let Some(config) = &self.config.semantic else {
+            return;
+        };
+        let mut entries = self.semantic.lock().unwrap();
+        while entries.len() >= config.max_entries.max(1) {
+            entries.pop_front();
+        }
+        entries.push_back(SemanticEntry {
+            context: query.context,
+            vector: query.vector,
+            completion,
+            expires: Instant::now() + Duration::from_secs(self.config.ttl_secs),
+        });
+    }
+
+    pub async fn get(&self, key: &str) -> Option<Bytes> {
+        match self.config.backend {
+            CacheBackend::Memory => self.memory.lock().unwrap().get(key),
+            CacheBackend::Redis => {
+                let key = format!("{}{}", self.config.redis_prefix, key);
+                match self.redis.get(&key).await {
+                    Ok(value) => value.map(Bytes::from),
+                    Err(e) => {
+                        println!("Cache lookup failed: {}", e);
+                        None
+                    }
+                }
+            }
+        }
+    }
+
+    /// Stores a completion as served to the client.
+    pub async fn put(&self, key: String, completion: Bytes) {
+        if completion.len() > self.config.max_entry_bytes {
+            return;
+        }
+        let ttl = Duration::from_secs(self.config.ttl_secs);
+        match self.config.backend {
+            CacheBackend::Memory => {
+                self.memory
+                    .lock()
+                    .unwrap()
+                    .put(key, completion, ttl, self.config.max_entries);
+            }
+            CacheBackend::Redis => {
+                let key = format!("{}{}", self.config.redis_prefix, key);
+                let stored = self.redis.set(&key, completion.to_vec(), self.config.ttl_secs).await;
+                if let Err(e) = stored {
+                    println!("Cache store failed: {}", e);
+                }
+            }
+        }
+    }
+
+    /// Caches a successful JSON answer, also by meaning given the `query`
+    /// it was looked up with, and passes it on.
+    pub async fn store(
+        &self,
+        key: String,
+        query: Option<SemanticQuery>,
+        response: Response<Body>,
+    ) -> Response<Body> {
+        let json = response
+            .headers()
+            .get(header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("application/json"));
+        if response.status() != StatusCode::OK || !json {
+            return response;
+        }
+        let (mut parts, body) = response.into_parts();
+        let Ok(bytes) = axum::body::to_bytes(body, usize::MAX).await else {
+            return Response::from_parts(parts, Body::empty());
+        };
+        if bytes.len() <= self.config.max_entry_bytes {
+            if let Some(query) = query {
+                self.remember(query, bytes.clone());
+            }
+        }
+        self.put(key, bytes.clone()).await;
+        parts.headers.insert("x-llmta-cache", header::HeaderValue::from_static("miss"));
+        Response::from_parts(parts, Body::from(bytes))
+    }
+}
+
+/// The cache key of a request: a hash of its canonical form, less the
+/// fields that do not change the answer, scoped to `tenant`.
+fn hash(scope: &str, mut payload: Map<String, Value>) -> String {
+    for field in IGNORED_FIELDS {
+        payload.remove(*field);
+    }
+    let mut text = String::new();
+    canonical(&Value::Object(payload), &mut text);
+    let digest = Sha256::new()
+        .chain_update(scope)
+        .chain_update([0])
+        .chain_update(text)
+        .finalize();
+    hex::encode(digest)
+}
+
+/// Embeds the last user message of a request for the semantic cache;
+/// `None` when that is off, the request has no such message or the
+/// embedding failed.
+pub async fn semantic_query(
+    state: &AppState,
+    scope: &str,
+    payload: &Map<String, Value>,
+) -> Option<SemanticQuery> {
+    let config = state.config.cache.semantic.as_ref()?;
+    let mut rest = payload.clone();
+    let messages = rest.get_mut("messages")?.as_array_mut()?;
+    let last = messages
+        .iter()
+        .rposition(|m| m.get("role").and_then(Value::as_str) == Some("user"))?;
+    let text = translation::content_text(&messages.remove(last)["content"]);
+    if text.trim().is_empty() {
+        return None;
+    }
+    match embeddings::embed_text(state, &config.model, &text).await {
+        Ok(vector) => Some(SemanticQuery {
+            context: hash(scope, rest),
+            vector,
+        }),
+        Err(e) => {
+            println!("Semantic cache lookup failed: {}", e);
+            None
+        }
+    }
+}
+
+/// Serves a cached completion: as is, or replayed as the chunks of a stream
+/// when the client asked for one. `how` is reported in `x-llmta-cache`.
+pub fn replay(
+    state: &AppState,
+    cached: Bytes,
+    how: &'static str,
+    stream: bool,
+    include_usage: bool,
+) -> Response<Body> {
+    let builder = Response::builder()
+        .status(StatusCode::OK)
+        .header("x-llmta-cache", how);
+    if !stream {
+        let mut builder = builder.header(header::CONTENT_TYPE, "application/json");
+        if let Some(signer) = &state.signer {
+            builder = builder.header("x-llmta-signature", signer.sign_body(&cached));
+        }
+        return builder.body(Body::from(cached)).unwrap();
+    }
+
+    let completion: Value = serde_json::from_slice(&cached).unwrap_or(Value::Null);
+    let meta = completion::chunk_meta(&completion);
+    let mut chunks = Vec::new();
+    for choice in completion["choices"].as_array().into_iter().flatten() {
+        let mut delta = choice["message"].clone();
+        if let Some(calls) = delta.get_mut("tool_calls").and_then(Value::as_array_mut) {
+            for (index, call) in calls.iter_mut().enumerate() {
+                call["index"] = json!(index);
+            }
+        }
+        let mut chunk = completion::finish_chunk(&meta, "stop");
+        chunk["choices"] = json!([{
+            "index": choice["index"],
+            "delta": delta,
+            "finish_reason": null,
+        }]);
+        chunks.push(chunk);
+        let mut finish = completion::finish_chunk(&meta, "stop");
+        finish["choices"][0]["index"] = choice["index"].clone();
+        finish["choices"][0]["finish_reason"] = choice["finish_reason"].clone();
+        chunks.push(finish);
+    }
+    if let Some(usage) = completion.get("usage").filter(|_| include_usage) {
+        chunks.push(completion::usage_chunk(&meta, usage.clone()));
+    }
+
+    let mut body = Vec::new();
+    let events = chunks
+        .iter()
+        .map(|chunk| chunk.to_string())
+        .chain(std::iter::once("[DONE]".to_string()));
+    for (id, data) in events.enumerate() {
+        let mut event = SseEvent::data(data);
+        event.id = Some((id + 1).to_string());
+        body.extend_from_slice(&event.to_bytes());
+    }
+    builder
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+#[cfg(feature = "redis")]
+mod redis_store {
+    use redis::aio::MultiplexedConnection;
+    use redis::AsyncCommands;
+    use tokio::sync::OnceCell;
+
+    /// A connection opened on first use and shared from then on.
+    #[derive(Default)]
+    pub struct Store {
+        client: Option<redis::Client>,
+        connection: OnceCell<MultiplexedConnection>,
+    }
+
+    impl Store {
+        pub fn open(url: &str) -> Result<Self, String> {
+            Ok(Store {
+                client: Some(redis::Client::open(url).map_err(|e| e.to_string())?),
+                connection: OnceCell::new(),
+            })
+        }
+
+        async fn connection(&self) -> Result<MultiplexedConnection, String> {
+            let client = self.client.as_ref().ok_or("no redis_url")?;
+            self.connection
+                .get_or_try_init(|| client.get_multiplexed_async_connection())
+                .await
+                .cloned()
+                .map_err(|e| e.to_string())
+        }
+
+        pub async fn get(&self, key: &str) -> Result<Option<Vec<u8>>, String> {
+            self.connection().await?.get(key).await.map_err(|e| e.to_string())
+        }
+
+        pub async fn set(&self, key: &str, value: Vec<u8>, ttl_secs: u64) -> Result<(), String> {
+            self.connection()
+                .await?
+                .set_ex(key, value, ttl_secs)
+                .await
+                .map_err(|e| e.to_string())
+        }
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+mod redis_store {
+    const UNAVAILABLE: &str =
+        "cache.backend = \"redis\" needs the adapter built with the `redis` feature";
+
+    #[derive(Default)]
+    pub struct Store;
+
+    impl Store {
+        pub fn open(_url: &str) -> Result<Self, String> {
+            Err(UNAVAILABLE.to_string())
+        }
+
+        pub async fn get(&self, _key: &str) -> Result<Option<Vec<u8>>, String> {
+            Err(UNAVAILABLE.to_string())
+        }
+
+        pub async fn set(&self, _key: &str, _value: Vec<u8>, _ttl_secs: u64) -> Result<(), String> {
+            Err(UNAVAILABLE.to_string())
+        }
+    }
+}
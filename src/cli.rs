@@ -0,0 +1,145 @@
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+use std::sync::OnceLock;
+
+/// `openai-api-proxy [command] [options]`; serves the API when no command is
+/// given. Unknown flags and arguments are errors.
+#[derive(Debug, Parser)]
+#[command(name = "openai-api-proxy", version, about = "OpenAI-compatible gateway in front of LLM backends")]
+pub struct Cli {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+    #[command(flatten)]
+    pub config: ConfigArgs,
+    #[command(flatten)]
+    pub daemon: DaemonArgs,
+}
+
+#[derive(Debug, Subcommand, PartialEq, Eq)]
+pub enum Command {
+    /// Validate the configuration and check every backend is reachable.
+    Doctor {
+        /// Also send a one-token completion to each backend.
+        #[arg(long)]
+        completion: bool,
+    },
+    /// Run under the Windows service control manager.
+    #[cfg(windows)]
+    Service,
+}
+
+/// Configuration overrides, applied over the files and the environment.
+#[derive(Debug, Args)]
+pub struct ConfigArgs {
+    /// Address to listen on.
+    #[arg(long, global = true)]
+    pub host: Option<String>,
+    /// Port to listen on.
+    #[arg(long, global = true)]
+    pub port: Option<u16>,
+    /// Chat completions URL of the default backend.
+    #[arg(long, global = true)]
+    pub model_url: Option<String>,
+    /// Model used when a request names none.
+    #[arg(long, global = true)]
+    pub default_model: Option<String>,
+    /// Wire protocol of the default backend.
+    #[arg(long, global = true)]
+    pub protocol: Option<String>,
+    /// File holding the backend key.
+    #[arg(long, global = true)]
+    pub model_key_file: Option<String>,
+    /// Any other key, e.g. limits.max_concurrency=32. Secrets are refused;
+    /// give their *_file path instead.
+    #[arg(long = "set", global = true, value_name = "KEY=VALUE", value_parser = parse_set)]
+    pub set: Vec<(String, String)>,
+}
+
+/// Running detached from the terminal on Unix.
+#[derive(Debug, Args)]
+pub struct DaemonArgs {
+    /// Fork into the background.
+    #[arg(long)]
+    pub daemon: bool,
+    /// Where to write the process id once detached.
+    #[arg(long, requires = "daemon")]
+    pub pidfile: Option<PathBuf>,
+    /// Receives stdout and stderr once detached; discarded when unset.
+    #[arg(long, requires = "daemon")]
+    pub log_file: Option<PathBuf>,
+}
+
+/// Keys that must not appear on a command line, where any local user can
+/// read them; they come from the files, the environment or a `*_file` path
+/// next to them. `*` stands for any array index or table key.
+pub const SECRETS: &[&str] = &[
+    "model_key",
+    "admin.token",
+    "signing.private_key",
+    "bedrock.secret_access_key",
+    "bedrock.session_token",
+    "backends.*.key",
+    "keys.*.key",
+    "auth.virtual_keys.*.key",
+    "auth.virtual_keys.*.upstream_key",
+    "auth.virtual_keys.*.upstream_keys.*",
+    "auth.hmac.clients.*.secret",
+    "webhooks.endpoints.*.secret",
+];
+
+/// Whether a configuration key, such as `backends[0].key`, names a secret.
+pub fn is_secret(key: &str) -> bool {
+    let key = key.to_lowercase().replace('[', ".").replace(']', "");
+    let segments: Vec<&str> = key.split('.').collect();
+    SECRETS.iter().any(|pattern| {
+        let pattern: Vec<&str> = pattern.split('.').collect();
+        pattern.len() == segments.len()
+            && pattern.iter().zip(&segments).all(|(p, s)| *p == "*" || p == s)
+    })
+}
+
+fn parse_set(value: &str) -> Result<(String, String), String> {
+    let (key, value) = value
+        .split_once('=')
+        .ok_or_else(|| format!("expected key=value, not {}", value))?;
+    let key = key.trim();
+    if is_secret(key) {
+        return Err(format!(
+            "{} may not be given on the command line; set it in a file, \
+             the environment or {}_file",
+            key, key
+        ));
+    }
+    Ok((key.to_string(), value.to_string()))
+}
+
+impl ConfigArgs {
+    /// The overrides as configuration keys and values, flags first.
+    pub fn overrides(&self) -> Vec<(String, String)> {
+        let flags = [
+            ("host", self.host.clone()),
+            ("port", self.port.map(|port| port.to_string())),
+            ("model_url", self.model_url.clone()),
+            ("default_model", self.default_model.clone()),
+            ("protocol", self.protocol.clone()),
+            ("model_key_file", self.model_key_file.clone()),
+        ];
+        flags
+            .into_iter()
+            .filter_map(|(key, value)| Some((key.to_string(), value?)))
+            .chain(self.set.iter().cloned())
+            .collect()
+    }
+}
+
+static OVERRIDES: OnceLock<Vec<(String, String)>> = OnceLock::new();
+
+/// Remembers the command-line overrides so every later `AppConfig::load`,
+/// including reloads, applies them.
+pub fn set_overrides(overrides: Vec<(String, String)>) {
+    let _ = OVERRIDES.set(overrides);
+}
+
+pub fn overrides() -> &'static [(String, String)] {
+    OVERRIDES.get().map(Vec::as_slice).unwrap_or_default()
+}
@@ -0,0 +1,78 @@
+use serde_json::{json, Map, Value};
+
+use crate::provider::Provider;
+
+/// Cohere's `embed` API. Only embeddings are translated; chat requests are
+/// rejected rather than sent in a format Cohere does not understand.
+pub struct Cohere;
+
+/// The input texts of an OpenAI embeddings request. Token arrays have no
+/// Cohere equivalent.
+fn texts(input: Option<Value>) -> Result<Vec<Value>, String> {
+    let texts = match input {
+        Some(Value::String(text)) => vec![Value::String(text)],
+        Some(Value::Array(items)) => items,
+        _ => return Err("'input' must be a string or an array of strings".to_string()),
+    };
+    if !texts.iter().all(Value::is_string) {
+        return Err("cohere backends only embed text inputs, not token arrays".to_string());
+    }
+    Ok(texts)
+}
+
+impl Provider for Cohere {
+    fn name(&self) -> &'static str {
+        "cohere"
+    }
+
+    fn translate_request(&self, _payload: &mut Map<String, Value>) -> Result<bool, String> {
+        Err("cohere backends only serve embeddings".to_string())
+    }
+
+    fn embeddings_url(&self, base_url: &str, _model: &str) -> Option<String> {
+        Some(format!("{}/embed", base_url))
+    }
+
+    fn max_embedding_inputs(&self) -> usize {
+        96
+    }
+
+    /// `input` becomes `texts` and `dimensions` becomes `output_dimension`.
+    /// `input_type`, which Cohere requires, defaults to `search_document`.
+    fn translate_embeddings_request(&self, payload: &mut Map<String, Value>) -> Result<(), String> {
+        let texts = texts(payload.remove("input"))?;
+        payload.insert("texts".to_string(), Value::Array(texts));
+        if !payload.contains_key("input_type") {
+            payload.insert("input_type".to_string(), json!("search_document"));
+        }
+        if let Some(dimensions) = payload.remove("dimensions") {
+            payload.insert("output_dimension".to_string(), dimensions);
+        }
+        // Floats are requested always; base64 is encoded by the adapter.
+        payload.remove("encoding_format");
+        payload.remove("user");
+        payload.insert("embedding_types".to_string(), json!(["float"]));
+        Ok(())
+    }
+
+    /// Accepts both the v2 `{"embeddings": {"float": [...]}}` and the v1
+    /// `{"embeddings": [...]}` shapes.
+    fn translate_embeddings(&self, response: &mut Value, model: &str) {
+        let embeddings = match &response["embeddings"] {
+            Value::Array(embeddings) => embeddings.clone(),
+            embeddings => embeddings["float"].as_array().cloned().unwrap_or_default(),
+        };
+        let tokens = response["meta"]["billed_units"]["input_tokens"].as_u64().unwrap_or(0);
+        let data: Vec<Value> = embeddings
+            .into_iter()
+            .enumerate()
+            .map(|(index, embedding)| json!({ "object": "embedding", "index": index, "embedding": embedding }))
+            .collect();
+        *response = json!({
+            "object": "list",
+            "data": data,
+            "model": model,
+            "usage": { "prompt_tokens": tokens, "total_tokens": tokens },
+        });
+    }
+}
@@ -0,0 +1,271 @@
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::sync::Arc;
+
+use crate::sse::SseEvent;
+use crate::tokenizer::Tokenizer;
+
+/// Copies the fields that identify a completion (`id`, `created`, `model`,
+/// `system_fingerprint`) out of a chunk so synthetic chunks can reuse them.
+pub fn chunk_meta(chunk: &Value) -> Map<String, Value> {
+    let mut meta = Map::new();
+    for key in ["id", "created", "model", "system_fingerprint"] {
+        if let Some(value) = chunk.get(key) {
+            meta.insert(key.to_string(), value.clone());
+        }
+    }
+    meta
+}
+
+/// Builds a terminal chunk for a stream the adapter cuts short.
+pub fn finish_chunk(meta: &Map<String, Value>, finish_reason: &str) -> Value {
+    let mut chunk = meta.clone();
+    chunk.insert("object".to_string(), json!("chat.completion.chunk"));
+    chunk.insert(
+        "choices".to_string(),
+        json!([{ "index": 0, "delta": {}, "finish_reason": finish_reason }]),
+    );
+    Value::Object(chunk)
+}
+
+/// Builds the final `usage` chunk, with no choices, that OpenAI sends when
+/// asked for `stream_options.include_usage`.
+pub fn usage_chunk(meta: &Map<String, Value>, usage: Value) -> Value {
+    let mut chunk = meta.clone();
+    chunk.insert("object".to_string(), json!("chat.completion.chunk"));
+    chunk.insert("choices".to_string(), json!([]));
+    chunk.insert("usage".to_string(), usage);
+    Value::Object(chunk)
+}
+
+/// A `usage` object for a completion the backend reported none for.
+pub fn counted_usage(tokenizer: &dyn Tokenizer, prompt_tokens: u64, text: &str) -> Value {
+    let completion_tokens = tokenizer.count(text) as u64;
+    json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": prompt_tokens + completion_tokens,
+    })
+}
+
+/// Generated text of a completion: message contents and tool call arguments.
+pub fn completion_text(completion: &Value) -> String {
+    let mut text = String::new();
+    for choice in completion["choices"].as_array().into_iter().flatten() {
+        push_generated(&mut text, &choice["message"]);
+    }
+    text
+}
+
+fn push_generated(text: &mut String, message: &Value) {
+    if let Some(content) = message["content"].as_str() {
+        text.push_str(content);
+    }
+    for call in message["tool_calls"].as_array().into_iter().flatten() {
+        text.push_str(call["function"]["name"].as_str().unwrap_or(""));
+        text.push_str(call["function"]["arguments"].as_str().unwrap_or(""));
+    }
+}
+
+/// Token counts of a stream: the `usage` the backend sent, or else counted
+/// from the streamed deltas with the model's tokenizer.
+pub struct StreamUsage {
+    tokenizer: Arc<dyn Tokenizer>,
+    prompt_tokens: u64,
+    text: String,
+    reported: Option<Value>,
+}
+
+impl StreamUsage {
+    pub fn new(tokenizer: Arc<dyn Tokenizer>, prompt_tokens: u64) -> Self {
+        StreamUsage {
+            tokenizer,
+            prompt_tokens,
+            text: String::new(),
+            reported: None,
+        }
+    }
+
+    pub fn observe(&mut self, chunk: &Value) {
+        if let Some(usage) = chunk.get("usage").filter(|u| u.is_object()) {
+            self.reported = Some(usage.clone());
+        }
+        for choice in chunk["choices"].as_array().into_iter().flatten() {
+            push_generated(&mut self.text, &choice["delta"]);
+        }
+    }
+
+    /// Whether the backend sent its own counts.
+    pub fn reported(&self) -> bool {
+        self.reported.is_some()
+    }
+
+    pub fn usage(&self) -> Value {
+        match &self.reported {
+            Some(usage) => usage.clone(),
+            None => counted_usage(self.tokenizer.as_ref(), self.prompt_tokens, &self.text),
+        }
+    }
+}
+
+/// Rewrites choices streamed as whole `message`s or completion-style `text`
+/// into `delta`s; returns whether anything changed.
+pub fn normalize_chunk(chunk: &mut Value) -> bool {
+    let Some(choices) = chunk.get_mut("choices").and_then(Value::as_array_mut) else {
+        return false;
+    };
+    let mut changed = false;
+    for choice in choices.iter_mut().filter_map(Value::as_object_mut) {
+        if choice.contains_key("delta") {
+            continue;
+        }
+        let delta = match (choice.remove("message"), choice.remove("text")) {
+            (Some(message), _) => message,
+            (None, Some(text)) => json!({ "content": text }),
+            (None, None) => continue,
+        };
+        choice.insert("delta".to_string(), delta);
+        changed = true;
+    }
+    changed
+}
+
+#[derive(Debug, Default)]
+struct ChoiceState {
+    role: Option<String>,
+    content: String,
+    finish_reason: Option<Value>,
+    tool_calls: BTreeMap<u64, Value>,
+}
+
+/// Folds `chat.completion.chunk` deltas back into a single `chat.completion`.
+#[derive(Debug, Default)]
+pub struct ChunkAccumulator {
+    meta: Map<String, Value>,
+    choices: BTreeMap<u64, ChoiceState>,
+    usage: Option<Value>,
+    budget_exhausted: bool,
+    truncated: bool,
+}
+
+impl ChunkAccumulator {
+    pub fn push_event(&mut self, event: &SseEvent) {
+        if event.is_done() {
+            return;
+        }
+        if let Ok(mut chunk) = serde_json::from_str::<Value>(&event.data) {
+            normalize_chunk(&mut chunk);
+            self.push(&chunk);
+        }
+    }
+
+    pub fn push(&mut self, chunk: &Value) {
+        self.meta.extend(chunk_meta(chunk));
+        if let Some(usage) = chunk.get("usage").filter(|u| !u.is_null()) {
+            self.usage = Some(usage.clone());
+        }
+
+        let choices = chunk.get("choices").and_then(Value::as_array);
+        for choice in choices.into_iter().flatten() {
+            let index = choice.get("index").and_then(Value::as_u64).unwrap_or(0);
+            let state = self.choices.entry(index).or_default();
+
+            if let Some(reason) = choice.get("finish_reason").filter(|r| !r.is_null()) {
+                state.finish_reason = Some(reason.clone());
+            }
+            let Some(delta) = choice.get("delta") else {
+                continue;
+            };
+            if let Some(role) = delta.get("role").and_then(Value::as_str) {
+                state.role = Some(role.to_string());
+            }
+            if let Some(content) = delta.get("content").and_then(Value::as_str) {
+                state.content.push_str(content);
+            }
+            let tool_calls = delta.get("tool_calls").and_then(Value::as_array);
+            for call in tool_calls.into_iter().flatten() {
+                let call_index = call.get("index").and_then(Value::as_u64).unwrap_or(0);
+                merge_tool_call(state.tool_calls.entry(call_index).or_insert_with(|| json!({})), call);
+            }
+        }
+    }
+
+    /// Marks the completion as cut off by the response time budget.
+    pub fn mark_budget_exhausted(&mut self) {
+        self.budget_exhausted = true;
+        self.cut_short();
+    }
+
+    /// Marks the completion as cut off by the response size cap.
+    pub fn mark_truncated(&mut self) {
+        self.truncated = true;
+        self.cut_short();
+    }
+
+    fn cut_short(&mut self) {
+        self.choices.entry(0).or_default();
+        for state in self.choices.values_mut() {
+            if state.finish_reason.is_none() {
+                state.finish_reason = Some(json!("length"));
+            }
+        }
+    }
+
+    pub fn into_completion(self) -> Value {
+        let mut completion = self.meta;
+        completion.insert("object".to_string(), json!("chat.completion"));
+
+        let choices: Vec<Value> = self
+            .choices
+            .into_iter()
+            .map(|(index, state)| {
+                let mut message = json!({
+                    "role": state.role.unwrap_or_else(|| "assistant".to_string()),
+                    "content": state.content,
+                });
+                if !state.tool_calls.is_empty() {
+                    message["tool_calls"] = Value::Array(state.tool_calls.into_values().collect());
+                }
+                json!({
+                    "index": index,
+                    "message": message,
+                    "finish_reason": state.finish_reason.unwrap_or(Value::Null),
+                })
+            })
+            .collect();
+        completion.insert("choices".to_string(), Value::Array(choices));
+
+        if let Some(usage) = self.usage {
+            completion.insert("usage".to_string(), usage);
+        }
+        if self.budget_exhausted {
+            completion.insert("x_budget_exhausted".to_string(), json!(true));
+        }
+        if self.truncated {
+            completion.insert("x_truncated".to_string(), json!(true));
+        }
+        Value::Object(completion)
+    }
+}
+
+fn merge_tool_call(target: &mut Value, delta: &Value) {
+    for key in ["id", "type"] {
+        if let Some(value) = delta.get(key).filter(|v| !v.is_null()) {
+            target[key] = value.clone();
+        }
+    }
+    let Some(function) = delta.get("function") else {
+        return;
+    };
+    if target.get("function").is_none() {
+        target["function"] = json!({ "name": "", "arguments": "" });
+    }
+    if let Some(name) = function.get("name").and_then(Value::as_str) {
+        target["function"]["name"] = json!(name);
+    }
+    if let Some(arguments) = function.get("arguments").and_then(Value::as_str) {
+        let mut merged = target["function"]["arguments"].as_str().unwrap_or("").to_string();
+        merged.push_str(arguments);
+        target["function"]["arguments"] = json!(merged);
+    }
+}
@@ -0,0 +1,159 @@
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::{HashMap, HashSet};
+
+use crate::tokenizer::Tokenizer;
+
+/// Heuristic prompt compression in the spirit of LLMLingua: the least
+/// informative sentences of long prompts are dropped before forwarding.
+#[derive(Debug, Deserialize, Clone)]
+pub struct CompressionConfig {
+    /// Fraction of prompt tokens to keep, e.g. `0.5` halves long prompts.
+    pub ratio: f64,
+    /// Prompts shorter than this are forwarded unchanged.
+    #[serde(default = "default_min_tokens")]
+    pub min_tokens: usize,
+}
+
+fn default_min_tokens() -> usize {
+    2000
+}
+
+struct Sentence {
+    message: usize,
+    text: String,
+    tokens: usize,
+    score: f64,
+    /// First and last sentences of a message carry its framing and are kept.
+    pinned: bool,
+    keep: bool,
+}
+
+/// Compresses the string contents of user and assistant messages in place.
+/// System prompts are never touched. Returns the token counts before and
+/// after when anything was removed.
+pub fn compress(
+    payload: &mut Map<String, Value>,
+    config: &CompressionConfig,
+    tokenizer: &dyn Tokenizer,
+) -> Option<(usize, usize)> {
+    let messages = payload.get_mut("messages")?.as_array_mut()?;
+
+    let mut sentences = Vec::new();
+    for (index, message) in messages.iter().enumerate() {
+        let role = message.get("role").and_then(Value::as_str).unwrap_or("");
+        if !["user", "assistant"].contains(&role) {
+            continue;
+        }
+        let Some(content) = message.get("content").and_then(Value::as_str) else {
+            continue;
+        };
+        let parts = split_sentences(content);
+        let last = parts.len().saturating_sub(1);
+        for (i, text) in parts.into_iter().enumerate() {
+            sentences.push(Sentence {
+                message: index,
+                tokens: tokenizer.count(&text),
+                text,
+                score: 0.0,
+                pinned: i == 0 || i == last,
+                keep: true,
+            });
+        }
+    }
+
+    let before: usize = sentences.iter().map(|s| s.tokens).sum();
+    if before < config.min_tokens {
+        return None;
+    }
+    let target = (before as f64 * config.ratio.clamp(0.0, 1.0)) as usize;
+
+    score(&mut sentences);
+    let mut order: Vec<usize> = (0..sentences.len()).filter(|&i| !sentences[i].pinned).collect();
+    order.sort_by(|&a, &b| sentences[a].score.total_cmp(&sentences[b].score));
+
+    let mut after = before;
+    for i in order {
+        if after <= target {
+            break;
+        }
+        sentences[i].keep = false;
+        after -= sentences[i].tokens;
+    }
+    if after == before {
+        return None;
+    }
+
+    let mut rebuilt: HashMap<usize, String> = HashMap::new();
+    for sentence in sentences.iter().filter(|s| s.keep) {
+        rebuilt.entry(sentence.message).or_default().push_str(&sentence.text);
+    }
+    for (index, message) in messages.iter_mut().enumerate() {
+        if sentences.iter().any(|s| s.message == index) {
+            message["content"] = Value::String(rebuilt.remove(&index).unwrap_or_default());
+        }
+    }
+    Some((before, after))
+}
+
+/// Scores sentences by the mean self-information of their words across the
+/// whole prompt; repeated sentences score zero so they are dropped first.
+fn score(sentences: &mut [Sentence]) {
+    let mut frequency: HashMap<String, usize> = HashMap::new();
+    let mut total = 0usize;
+    for sentence in sentences.iter() {
+        for word in words(&sentence.text) {
+            *frequency.entry(word).or_default() += 1;
+            total += 1;
+        }
+    }
+
+    let mut seen = HashSet::new();
+    for sentence in sentences.iter_mut() {
+        if !seen.insert(sentence.text.trim().to_lowercase()) {
+            continue;
+        }
+        let words = words(&sentence.text);
+        if words.is_empty() {
+            continue;
+        }
+        let information: f64 = words
+            .iter()
+            .map(|w| -(frequency[w] as f64 / total as f64).ln())
+            .sum();
+        sentence.score = information / words.len() as f64;
+    }
+}
+
+fn words(text: &str) -> Vec<String> {
+    text.split(|c: char| !c.is_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .map(str::to_lowercase)
+        .collect()
+}
+
+/// Splits after sentence punctuation (including CJK) and newlines, keeping
+/// delimiters and whitespace so kept sentences concatenate back verbatim.
+fn split_sentences(text: &str) -> Vec<String> {
+    let mut sentences = Vec::new();
+    let mut current = String::new();
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        current.push(c);
+        let boundary = matches!(c, '.' | '!' | '?' | '。' | '！' | '？' | '\n');
+        if boundary {
+            while let Some(&next) = chars.peek() {
+                if !next.is_whitespace() {
+                    break;
+                }
+                current.push(next);
+                chars.next();
+            }
+            sentences.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        sentences.push(current);
+    }
+    sentences
+}
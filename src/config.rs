@@ -0,0 +1,354 @@
+use config::{Config, ConfigError};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+use crate::acme::AcmeConfig;
+use crate::anthropic::AnthropicConfig;
+use crate::auth::AuthConfig;
+use crate::azure::AzureConfig;
+use crate::backends::BackendConfig;
+use crate::balance::BalancingConfig;
+use crate::batch::BatchConfig;
+use crate::bedrock::BedrockConfig;
+use crate::breaker::BreakerConfig;
+use crate::browser::BrowserConfig;
+use crate::cli;
+use crate::cache::CacheConfig;
+use crate::version::ApiConfig;
+use crate::compression::CompressionConfig;
+use crate::db::DatabaseConfig;
+use crate::deepl::DeepLConfig;
+use crate::degrade::DegradedConfig;
+use crate::deidentify::DeidentifyConfig;
+use crate::deprecation::ModelDeprecation;
+use crate::discovery::DiscoveryConfig;
+use crate::estimate::PricingConfig;
+use crate::headers::HeaderConfig;
+use crate::health::HealthConfig;
+use crate::heatmap::HeatmapConfig;
+use crate::inflight::WatchdogConfig;
+use crate::keys::KeyConfig;
+use crate::limits::LimitsConfig;
+use crate::locale::ErrorsConfig;
+use crate::metrics::MetricsConfig;
+use crate::models::ModelsConfig;
+use crate::normalize::{Flavor, NormalizeConfig};
+use crate::policy::PolicyConfig;
+use crate::prompts::PromptConfig;
+use crate::provider::Protocol;
+use crate::quotas::QuotaConfig;
+use crate::regions::RegionsConfig;
+use crate::reload::ReloadConfig;
+use crate::rules::Rule;
+use crate::runtime::PoolConfig;
+use crate::scheduler::SchedulerConfig;
+use crate::schema::ValidationConfig;
+use crate::shutdown::ShutdownConfig;
+use crate::signing::SigningConfig;
+use crate::slo::SloConfig;
+use crate::snapshot::StateConfig;
+use crate::sysprompt::SystemPrompt;
+use crate::tls::TlsConfig;
+use crate::tokenizer::TokenizerConfig;
+use crate::tools::ToolsConfig;
+use crate::usage::UsageConfig;
+use crate::webhooks::WebhooksConfig;
+use crate::translation::TranslationConfig;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct AppConfig {
+    pub model_url: String,
+    pub model_key: String,
+    pub default_model: String,
+    pub port: u16,
+    pub host: String,
+    /// API spoken by `model_url`.
+    #[serde(default)]
+    pub protocol: Protocol,
+    #[serde(default)]
+    pub anthropic: AnthropicConfig,
+    #[serde(default)]
+    pub azure: AzureConfig,
+    #[serde(default)]
+    pub bedrock: BedrockConfig,
+    /// Further backends, chosen by the requested model.
+    #[serde(default)]
+    pub backends: Vec<BackendConfig>,
+    /// Contents of `GET /v1/models`.
+    #[serde(default)]
+    pub models: ModelsConfig,
+    #[serde(default)]
+    pub regions: RegionsConfig,
+    #[serde(default)]
+    pub balancing: BalancingConfig,
+    #[serde(default)]
+    pub breaker: BreakerConfig,
+    /// What `GET /readyz` checks before reporting ready.
+    #[serde(default)]
+    pub health: HealthConfig,
+    #[serde(default)]
+    pub deidentify: DeidentifyConfig,
+    /// TLS policy for calls to the backend.
+    #[serde(default)]
+    pub tls: TlsConfig,
+    /// Upstream connection pool and idle reaping.
+    #[serde(default)]
+    pub pool: PoolConfig,
+    /// Message shapes the backend accepts; drives `[normalize]` defaults.
+    #[serde(default)]
+    pub flavor: Flavor,
+    #[serde(default)]
+    pub normalize: NormalizeConfig,
+    #[serde(default)]
+    pub api: ApiConfig,
+    #[serde(default)]
+    pub routes: RoutesConfig,
+    /// Status page shown to browsers opening an API route.
+    #[serde(default)]
+    pub browser: BrowserConfig,
+    /// Which upstream response headers are relayed to clients.
+    #[serde(default)]
+    pub headers: HeaderConfig,
+    #[serde(default)]
+    pub streaming: StreamingConfig,
+    /// Schema checks on responses before they reach the client.
+    #[serde(default)]
+    pub validation: ValidationConfig,
+    #[serde(default)]
+    pub admin: AdminConfig,
+    #[serde(default)]
+    pub limits: LimitsConfig,
+    /// Error messages in the client's language.
+    #[serde(default)]
+    pub errors: ErrorsConfig,
+    #[serde(default)]
+    pub metrics: MetricsConfig,
+    /// Usage events and alerts sent to receivers through a retry queue.
+    #[serde(default)]
+    pub webhooks: WebhooksConfig,
+    /// Answers to repeated requests, served without calling the backend.
+    #[serde(default)]
+    pub cache: CacheConfig,
+    #[serde(default)]
+    pub auth: AuthConfig,
+    /// Per-tenant ceilings on connections, buffered bytes and cache entries.
+    #[serde(default)]
+    pub quotas: QuotaConfig,
+    /// Sign responses so consumers can verify they passed through the adapter.
+    #[serde(default)]
+    pub signing: Option<SigningConfig>,
+    /// Client keys with per-key overrides.
+    #[serde(default)]
+    pub keys: Vec<KeyConfig>,
+    #[serde(default)]
+    pub translation: TranslationConfig,
+    /// The DeepL-compatible `/v2/translate` endpoint.
+    #[serde(default)]
+    pub deepl: DeepLConfig,
+    /// The `/v1/batch/translate` endpoint's limits and retries.
+    #[serde(default)]
+    pub batch: BatchConfig,
+    /// Tokenizers by model alias; unlisted models use a character estimate.
+    #[serde(default)]
+    pub tokenizers: Vec<TokenizerConfig>,
+    /// Drop low-information sentences from long prompts before forwarding.
+    #[serde(default)]
+    pub compression: Option<CompressionConfig>,
+    /// Interval overrides for background maintenance jobs.
+    #[serde(default)]
+    pub scheduler: SchedulerConfig,
+    /// Serve HTTPS with certificates obtained automatically.
+    #[serde(default)]
+    pub acme: Option<AcmeConfig>,
+    /// Stored system prompts referenced with `x_prompt_id`.
+    #[serde(default)]
+    pub prompts: PromptConfig,
+    /// SQLite storage for templates.
+    #[serde(default)]
+    pub database: DatabaseConfig,
+    /// Canned assistant reply returned when the backend fails.
+    #[serde(default)]
+    pub degraded: Option<DegradedConfig>,
+    /// List prices by model alias, for `/v1/estimate`.
+    #[serde(default)]
+    pub pricing: Vec<PricingConfig>,
+    /// Latency objectives by model alias.
+    #[serde(default)]
+    pub slo: Vec<SloConfig>,
+    /// Upstream timeouts, retries and fallbacks by route and model.
+    #[serde(default)]
+    pub policy: PolicyConfig,
+    /// Routing, rejection, transformation and tagging of chat requests,
+    /// evaluated in order.
+    #[serde(default)]
+    pub rules: Vec<Rule>,
+    /// System prompts sent with chat requests by route and model, the first
+    /// match applying.
+    #[serde(default)]
+    pub system_prompts: Vec<SystemPrompt>,
+    /// Model aliases being retired, the first match applying.
+    #[serde(default)]
+    pub deprecations: Vec<ModelDeprecation>,
+    /// Tool calling quirks of the backend.
+    #[serde(default)]
+    pub tools: ToolsConfig,
+    /// Features of each backend, probed at startup.
+    #[serde(default)]
+    pub discovery: DiscoveryConfig,
+    /// Usage reporting for tenants.
+    #[serde(default)]
+    pub usage: UsageConfig,
+    /// Token consumption by prompt section and template.
+    #[serde(default)]
+    pub heatmap: HeatmapConfig,
+    /// Runtime state handed over between instances across a redeploy.
+    #[serde(default)]
+    pub state: StateConfig,
+    /// Draining of in-flight streams when the server stops.
+    #[serde(default)]
+    pub shutdown: ShutdownConfig,
+    /// Reloading the configuration files while running.
+    #[serde(default)]
+    pub reload: ReloadConfig,
+    /// Detection of requests that never complete.
+    #[serde(default)]
+    pub watchdog: WatchdogConfig,
+    /// SHA-256 of the merged configuration, to tell instances apart.
+    #[serde(skip)]
+    pub config_hash: String,
+}
+
+/// Fills secrets such as `model_key` or `backends[0].key` from the file
+/// their `*_file` sibling names, e.g. a mounted container secret.
+fn read_secret_files(config: Config) -> Result<Config, ConfigError> {
+    let tree: serde_json::Value = config.clone().try_deserialize()?;
+    let mut files = Vec::new();
+    for pattern in cli::SECRETS {
+        let pattern: Vec<&str> = pattern.split('.').collect();
+        secret_files(&tree, &pattern, String::new(), &mut files);
+    }
+    let mut builder = Config::builder().add_source(config);
+    for (key, path) in files {
+        let secret = std::fs::read_to_string(&path)
+            .map_err(|e| ConfigError::Message(format!("{}_file {}: {}", key, path, e)))?;
+        builder = builder.set_override(key, secret.trim())?;
+    }
+    builder.build()
+}
+
+/// Collects `(key, file)` for the secrets matching `pattern` under `tree`
+/// that have a `*_file` sibling.
+fn secret_files(tree: &serde_json::Value, pattern: &[&str], path: String, files: &mut Vec<(String, String)>) {
+    let join = |segment: &str| if path.is_empty() { segment.to_string() } else { format!("{}.{}", path, segment) };
+    match pattern {
+        [] => {}
+        [leaf] => {
+            if let Some(file) = tree.get(format!("{}_file", leaf)).and_then(|v| v.as_str()) {
+                files.push((join(leaf), file.to_string()));
+            }
+        }
+        ["*", rest @ ..] => match tree {
+            serde_json::Value::Array(items) => {
+                for (i, item) in items.iter().enumerate() {
+                    secret_files(item, rest, format!("{}[{}]", path, i), files);
+                }
+            }
+            serde_json::Value::Object(map) => {
+                for (key, value) in map {
+                    secret_files(value, rest, join(key), files);
+                }
+            }
+            _ => {}
+        },
+        [segment, rest @ ..] => {
+            if let Some(next) = tree.get(*segment) {
+                secret_files(next, rest, join(segment), files);
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RoutesConfig {
+    /// Mounts every route under this prefix, e.g. `/llm`.
+    #[serde(default)]
+    pub prefix: Option<String>,
+    /// Extra paths served by an existing route. Aliases are absolute and
+    /// not affected by `prefix`.
+    #[serde(default)]
+    pub aliases: Vec<RouteAlias>,
+    /// Proxy `/v1/*` paths without a dedicated route straight to the backend.
+    #[serde(default)]
+    pub passthrough: bool,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct RouteAlias {
+    /// e.g. `/openai/v1/chat/completions`
+    pub path: String,
+    /// The built-in route it stands for, e.g. `/v1beta/openai/chat/completions`.
+    pub target: String,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct AdminConfig {
+    /// Bearer token for `/admin/*`. The admin API is disabled when unset.
+    #[serde(default)]
+    pub token: Option<String>,
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct StreamingConfig {
+    /// Tag outgoing chunks with `event: delta|done|error`. Off by default since
+    /// some OpenAI SDKs treat any named event as a non-completion message.
+    #[serde(default)]
+    pub event_types: bool,
+    /// Total time budget for a response. When it runs out mid-generation the
+    /// stream is closed with `finish_reason: "length"` and
+    /// `x_budget_exhausted: true` instead of an error.
+    #[serde(default)]
+    pub budget_ms: Option<u64>,
+}
+
+impl AppConfig {
+    /// `config/default` and `config/local`, overridden by `LTA__`-prefixed
+    /// environment variables (`LTA__LIMITS__MAX_CONCURRENCY` for
+    /// `limits.max_concurrency`) and then by command-line flags.
+    pub fn load() -> Result<Self, ConfigError> {
+        let mut builder = Config::builder()
+            .add_source(config::File::with_name("config/default"))
+            .add_source(config::File::with_name("config/local").required(false))
+            .add_source(config::Environment::with_prefix("LTA").separator("__").try_parsing(true));
+        for (key, value) in cli::overrides() {
+            builder = builder.set_override(key, value.as_str())?;
+        }
+
+        Self::from_config(read_secret_files(builder.build()?)?)
+    }
+
+    /// The backend's API root: `model_url` without its `/chat/completions`.
+    pub fn base_url(&self) -> &str {
+        self.model_url
+            .strip_suffix("/chat/completions")
+            .unwrap_or(&self.model_url)
+            .trim_end_matches('/')
+    }
+
+    /// Builds a configuration from an inline TOML document, e.g. when the
+    /// adapter is embedded or started from tests.
+    pub fn from_toml(source: &str) -> Result<Self, ConfigError> {
+        Self::from_config(read_secret_files(
+            Config::builder()
+                .add_source(config::File::from_str(source, config::FileFormat::Toml))
+                .build()?,
+        )?)
+    }
+
+    fn from_config(config: Config) -> Result<Self, ConfigError> {
+        // serde_json sorts object keys, so the hash ignores source ordering.
+        let merged: serde_json::Value = config.clone().try_deserialize()?;
+        let mut app: AppConfig = config.try_deserialize()?;
+        app.config_hash = hex::encode(Sha256::digest(merged.to_string().as_bytes()));
+        Ok(app)
+    }
+}
@@ -0,0 +1,419 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, OriginalUri, Query, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Instant;
+
+use crate::create_error_response;
+use crate::proxy;
+use crate::sse::SseParser;
+use crate::AppState;
+
+#[derive(Debug, Deserialize)]
+pub struct ConformanceQuery {
+    /// Backend to test, with the first model it lists.
+    pub backend: Option<String>,
+    /// Model to test; the default model when neither is given.
+    pub model: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct CheckResult {
+    pub name: &'static str,
+    pub passed: bool,
+    pub detail: String,
+    pub latency_ms: u64,
+}
+
+/// Collects check results as the script runs.
+#[derive(Default)]
+struct Report {
+    checks: Vec<CheckResult>,
+}
+
+impl Report {
+    fn record(&mut self, name: &'static str, started: Instant, outcome: Result<String, String>) {
+        let passed = outcome.is_ok();
+        let detail = outcome.unwrap_or_else(|e| e);
+        println!("Conformance {:<16} {} {}", name, if passed { "pass" } else { "FAIL" }, detail);
+        self.checks.push(CheckResult {
+            name,
+            passed,
+            detail,
+            latency_ms: started.elapsed().as_millis() as u64,
+        });
+    }
+}
+
+/// Sends the scripted requests through the chat pipeline with the caller's
+/// credentials, as a client SDK would.
+struct Runner {
+    state: Arc<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    uri: Uri,
+    headers: HeaderMap,
+    model: String,
+}
+
+impl Runner {
+    async fn send(&self, check: &str, mut payload: Value) -> (StatusCode, Bytes) {
+        payload["model"] = json!(self.model);
+        let mut headers = self.headers.clone();
+        headers.remove(header::CONTENT_LENGTH);
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-cache"));
+        let request_id = format!("conformance-{}-{}", uuid::Uuid::new_v4(), check);
+        if let Ok(value) = HeaderValue::from_str(&request_id) {
+            headers.insert("x-request-id", value);
+        }
+        let response = proxy::handle_chat(
+            State(self.state.clone()),
+            self.connect_info,
+            OriginalUri(self.uri.clone()),
+            headers,
+            Bytes::from(payload.to_string()),
+        )
+        .await;
+        let status = response.status();
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap_or_default();
+        (status, body)
+    }
+
+    /// A non-streamed completion, or why there is none.
+    async fn complete(&self, check: &str, payload: Value) -> Result<Value, String> {
+        let (status, body) = self.send(check, payload).await;
+        if !status.is_success() {
+            return Err(format!("status {}: {}", status.as_u16(), String::from_utf8_lossy(&body)));
+        }
+        let completion: Value =
+            serde_json::from_slice(&body).map_err(|e| format!("response is not JSON: {}", e))?;
+        if completion["object"] != "chat.completion" {
+            return Err(format!("object is {}, not chat.completion", completion["object"]));
+        }
+        Ok(completion)
+    }
+
+    /// The chunks of a streamed completion, checking the stream's framing.
+    async fn stream(&self, check: &str, payload: Value) -> Result<Vec<Value>, String> {
+        let (status, body) = self.send(check, payload).await;
+        if !status.is_success() {
+            return Err(format!("status {}: {}", status.as_u16(), String::from_utf8_lossy(&body)));
+        }
+        let mut parser = SseParser::new();
+        let mut events = parser.feed(&body);
+        events.extend(parser.finish());
+        let Some(last) = events.last() else {
+            return Err("the stream carried no events".to_string());
+        };
+        if !last.is_done() {
+            return Err("the stream did not end with [DONE]".to_string());
+        }
+        let mut chunks = Vec::new();
+        for event in events.iter().filter(|e| !e.is_done()) {
+            let chunk: Value = serde_json::from_str(&event.data)
+                .map_err(|e| format!("chunk is not JSON ({}): {}", e, event.data))?;
+            if chunk["object"] != "chat.completion.chunk" {
+                return Err(format!("chunk object is {}, not chat.completion.chunk", chunk["object"]));
+            }
+            chunks.push(chunk);
+        }
+        Ok(chunks)
+    }
+}
+
+fn weather_tool() -> Value {
+    json!([{
+        "type": "function",
+        "function": {
+            "name": "get_weather",
+            "description": "Current weather in a city",
+            "parameters": {
+                "type": "object",
+                "properties": { "city": { "type": "string" } },
+                "required": ["city"],
+            },
+        },
+    }])
+}
+
+fn forced_tool() -> Value {
+    json!({ "type": "function", "function": { "name": "get_weather" } })
+}
+
+fn user(content: &str) -> Value {
+    json!([{ "role": "user", "content": content }])
+}
+
+fn finish_reason(choice: &Value) -> Result<&str, String> {
+    choice["finish_reason"]
+        .as_str()
+        .ok_or_else(|| "no finish_reason".to_string())
+}
+
+/// Arguments of a tool call, which must be a JSON object.
+fn arguments(text: &str) -> Result<Map<String, Value>, String> {
+    match serde_json::from_str(text) {
+        Ok(Value::Object(arguments)) => Ok(arguments),
+        _ => Err(format!("tool call arguments are not a JSON object: {}", text)),
+    }
+}
+
+async fn check_chat(runner: &Runner) -> Result<String, String> {
+    let completion = runner
+        .complete("chat", json!({ "messages": user("Reply with the word pong."), "max_tokens": 20 }))
+        .await?;
+    let choice = &completion["choices"][0];
+    if choice["message"]["role"] != "assistant" {
+        return Err(format!("message role is {}", choice["message"]["role"]));
+    }
+    if choice["message"]["content"].as_str().unwrap_or_default().is_empty() {
+        return Err("message has no text content".to_string());
+    }
+    let reason = finish_reason(choice)?;
+    let usage = &completion["usage"];
+    for field in ["prompt_tokens", "completion_tokens", "total_tokens"] {
+        if !usage[field].is_u64() {
+            return Err(format!("usage.{} is missing", field));
+        }
+    }
+    Ok(format!("finish_reason {}, {} tokens", reason, usage["total_tokens"]))
+}
+
+async fn check_streaming(runner: &Runner, report: &mut Report) {
+    let started = Instant::now();
+    let payload = json!({
+        "messages": user("Count from 1 to 5."),
+        "stream": true,
+        "stream_options": { "include_usage": true },
+    });
+    let chunks = match runner.stream("streaming", payload).await {
+        Ok(chunks) => chunks,
+        Err(e) => {
+            report.record("streaming", started, Err(e.clone()));
+            report.record("stream_usage", started, Err(e));
+            return;
+        }
+    };
+    let text: String = chunks
+        .iter()
+        .filter_map(|c| c["choices"][0]["delta"]["content"].as_str())
+        .collect();
+    let reason = chunks.iter().find_map(|c| c["choices"][0]["finish_reason"].as_str());
+    let streaming = match reason {
+        _ if text.is_empty() => Err("no content deltas".to_string()),
+        None => Err("no chunk carried a finish_reason".to_string()),
+        Some(reason) => Ok(format!("{} chunks, finish_reason {}", chunks.len(), reason)),
+    };
+    report.record("streaming", started, streaming);
+    let usage = match chunks.iter().find(|c| c["usage"].is_object()) {
+        Some(chunk) => Ok(format!("{} tokens", chunk["usage"]["total_tokens"])),
+        None => Err("no usage chunk despite stream_options.include_usage".to_string()),
+    };
+    report.record("stream_usage", started, usage);
+}
+
+async fn check_tools(runner: &Runner) -> Result<String, String> {
+    let payload = json!({
+        "messages": user("What is the weather in Paris?"),
+        "tools": weather_tool(),
+        "tool_choice": forced_tool(),
+    });
+    let completion = runner.complete("tools", payload).await?;
+    let choice = &completion["choices"][0];
+    let Some(call) = choice["message"]["tool_calls"].get(0) else {
+        return Err("no tool_calls in the message".to_string());
+    };
+    if call["id"].as_str().unwrap_or_default().is_empty() {
+        return Err("tool call has no id".to_string());
+    }
+    if call["type"] != "function" || call["function"]["name"] != "get_weather" {
+        return Err(format!("unexpected tool call {}", call));
+    }
+    let arguments = arguments(call["function"]["arguments"].as_str().unwrap_or_default())?;
+    Ok(format!(
+        "called get_weather with {}, finish_reason {}",
+        Value::Object(arguments),
+        finish_reason(choice)?
+    ))
+}
+
+async fn check_streaming_tools(runner: &Runner) -> Result<String, String> {
+    let payload = json!({
+        "messages": user("What is the weather in Paris?"),
+        "tools": weather_tool(),
+        "tool_choice": forced_tool(),
+        "stream": true,
+    });
+    let chunks = runner.stream("streaming_tools", payload).await?;
+    let mut name = String::new();
+    let mut text = String::new();
+    let deltas = chunks
+        .iter()
+        .filter_map(|c| c["choices"][0]["delta"]["tool_calls"].as_array())
+        .flatten()
+        .filter(|d| d["index"].as_u64().unwrap_or(0) == 0);
+    for delta in deltas {
+        name.push_str(delta["function"]["name"].as_str().unwrap_or_default());
+        text.push_str(delta["function"]["arguments"].as_str().unwrap_or_default());
+    }
+    if name != "get_weather" {
+        return Err(format!("streamed tool call is named {:?}", name));
+    }
+    let arguments = arguments(&text)?;
+    Ok(format!("streamed get_weather with {}", Value::Object(arguments)))
+}
+
+async fn check_json_mode(runner: &Runner) -> Result<String, String> {
+    let payload = json!({
+        "messages": [
+            { "role": "system", "content": "Reply in JSON." },
+            { "role": "user", "content": "Return a JSON object whose key answer is 42." },
+        ],
+        "response_format": { "type": "json_object" },
+    });
+    let completion = runner.complete("json_mode", payload).await?;
+    let content = completion["choices"][0]["message"]["content"].as_str().unwrap_or_default();
+    match serde_json::from_str(content) {
+        Ok(Value::Object(_)) => Ok("content is a JSON object".to_string()),
+        _ => Err(format!("content is not a JSON object: {}", content)),
+    }
+}
+
+async fn check_json_schema(runner: &Runner) -> Result<String, String> {
+    let payload = json!({
+        "messages": user("What is six times seven?"),
+        "response_format": {
+            "type": "json_schema",
+            "json_schema": {
+                "name": "answer",
+                "strict": true,
+                "schema": {
+                    "type": "object",
+                    "properties": { "answer": { "type": "integer" } },
+                    "required": ["answer"],
+                    "additionalProperties": false,
+                },
+            },
+        },
+    });
+    let completion = runner.complete("json_schema", payload).await?;
+    let content = completion["choices"][0]["message"]["content"].as_str().unwrap_or_default();
+    let answer: Value = serde_json::from_str(content).unwrap_or(Value::Null);
+    if answer["answer"].is_i64() {
+        Ok("content matches the schema".to_string())
+    } else {
+        Err(format!("content does not match the schema: {}", content))
+    }
+}
+
+async fn check_max_tokens(runner: &Runner) -> Result<String, String> {
+    let payload = json!({
+        "messages": user("Count from 1 to 100, separated by spaces."),
+        "max_tokens": 5,
+    });
+    let completion = runner.complete("max_tokens", payload).await?;
+    match finish_reason(&completion["choices"][0])? {
+        "length" => Ok("finish_reason length".to_string()),
+        other => Err(format!("finish_reason {} instead of length", other)),
+    }
+}
+
+async fn check_errors(runner: &Runner) -> Result<String, String> {
+    let (status, body) = runner.send("errors", json!({ "messages": [], "temperature": 7 })).await;
+    if status.is_success() {
+        return Err("an invalid request was accepted".to_string());
+    }
+    let error: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+    if !error["error"]["message"].is_string() {
+        return Err(format!("status {} without an OpenAI error body: {}", status.as_u16(), String::from_utf8_lossy(&body)));
+    }
+    if status.is_server_error() {
+        return Err(format!("status {} instead of a client error", status.as_u16()));
+    }
+    Ok(format!("status {}, type {}", status.as_u16(), error["error"]["type"]))
+}
+
+/// The backend and model under test, checking the model is routed to the
+/// requested backend.
+fn target(state: &AppState, query: &ConformanceQuery) -> Result<(String, String), String> {
+    let model = match (&query.model, &query.backend) {
+        (Some(model), _) => model.clone(),
+        (None, Some(name)) => {
+            let backend = state.backends.get(name).ok_or_else(|| format!("No backend {}", name))?;
+            backend
+                .models
+                .iter()
+                .find(|m| !m.contains('*'))
+                .cloned()
+                .unwrap_or_else(|| state.config.default_model.clone())
+        }
+        (None, None) => state.config.default_model.clone(),
+    };
+    let route = state.backends.route(&model, None, &state.maintenance);
+    if let Some(name) = query.backend.as_deref().filter(|name| *name != route.backend.name) {
+        return Err(format!(
+            "Model {} is served by backend {}, not {}; pass a model {} serves",
+            route.model, route.backend.name, name, name
+        ));
+    }
+    Ok((route.backend.name.clone(), route.model))
+}
+
+/// `POST /conformance`: runs a scripted set of chat requests against one
+/// backend and reports which OpenAI features come back intact, to check a
+/// new provider before sending it traffic.
+pub async fn handle_conformance(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    OriginalUri(uri): OriginalUri,
+    Query(query): Query<ConformanceQuery>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let (backend, model) = match target(&state, &query) {
+        Ok(target) => target,
+        Err(message) => {
+            return create_error_response(StatusCode::BAD_REQUEST, "invalid_request_error", &message);
+        }
+    };
+    println!("Running conformance checks against {} on {}", model, backend);
+    let runner = Runner {
+        state,
+        connect_info,
+        uri,
+        headers,
+        model: model.clone(),
+    };
+
+    let mut report = Report::default();
+    let started = Instant::now();
+    report.record("chat", started, check_chat(&runner).await);
+    check_streaming(&runner, &mut report).await;
+    let started = Instant::now();
+    report.record("tools", started, check_tools(&runner).await);
+    let started = Instant::now();
+    report.record("streaming_tools", started, check_streaming_tools(&runner).await);
+    let started = Instant::now();
+    report.record("json_mode", started, check_json_mode(&runner).await);
+    let started = Instant::now();
+    report.record("json_schema", started, check_json_schema(&runner).await);
+    let started = Instant::now();
+    report.record("max_tokens", started, check_max_tokens(&runner).await);
+    let started = Instant::now();
+    report.record("errors", started, check_errors(&runner).await);
+
+    let passed = report.checks.iter().filter(|c| c.passed).count();
+    Json(json!({
+        "backend": backend,
+        "model": model,
+        "passed": passed,
+        "failed": report.checks.len() - passed,
+        "checks": report.checks,
+    }))
+    .into_response()
+}
@@ -0,0 +1,146 @@
+use serde_json::Value;
+use std::sync::Arc;
+
+use crate::anthropic::{Anthropic, AnthropicConfig};
+use crate::gemini::Gemini;
+use crate::provider::{Provider, StreamTranslator};
+use crate::sse::{EventParser, SseEvent};
+
+/// The provider translations the server applies, for applications that
+/// want them in-process without running the server. Requests go from
+/// OpenAI's chat format to the provider's, responses and streams back.
+///
+/// ```
+/// use openai_api_proxy::convert::Translator;
+/// use serde_json::json;
+///
+/// let translator = Translator::anthropic(Default::default());
+/// let request = translator
+///     .request(&json!({
+///         "model": "claude-sonnet-4",
+///         "messages": [
+///             { "role": "system", "content": "Be brief." },
+///             { "role": "user", "content": "Hi" },
+///         ],
+///     }))
+///     .unwrap();
+/// assert_eq!(request["system"], "Be brief.");
+/// ```
+#[derive(Clone)]
+pub struct Translator {
+    provider: Arc<dyn Provider>,
+}
+
+impl Translator {
+    /// Any provider, such as one built with `Protocol::provider`.
+    pub fn new(provider: Arc<dyn Provider>) -> Self {
+        Translator { provider }
+    }
+
+    /// Anthropic Messages API.
+    pub fn anthropic(config: AnthropicConfig) -> Self {
+        Translator::new(Arc::new(Anthropic::new(config)))
+    }
+
+    /// Google Gemini `generateContent`.
+    pub fn gemini() -> Self {
+        Translator::new(Arc::new(Gemini))
+    }
+
+    /// Rewrites an OpenAI chat request into the provider's format. Fails
+    /// when the request is not a JSON object or cannot be expressed.
+    pub fn request(&self, request: &Value) -> Result<Value, String> {
+        let Value::Object(mut payload) = request.clone() else {
+            return Err("the request must be a JSON object".to_string());
+        };
+        self.provider.translate_request(&mut payload)?;
+        Ok(Value::Object(payload))
+    }
+
+    /// Rewrites a successful non-streamed provider response into an OpenAI
+    /// chat completion for `model`.
+    pub fn completion(&self, response: &Value, model: &str) -> Value {
+        let mut completion = response.clone();
+        self.provider.translate_completion(&mut completion, model);
+        completion
+    }
+
+    /// Starts translating one streamed response for `model`.
+    pub fn stream(&self, model: &str) -> StreamConverter {
+        StreamConverter {
+            parser: EventParser::new(self.provider.stream_format()),
+            translator: self.provider.stream_translator(model),
+        }
+    }
+}
+
+/// Turns the raw bytes of one provider stream into OpenAI
+/// `chat.completion.chunk` events, however the bytes are split.
+pub struct StreamConverter {
+    parser: EventParser,
+    translator: Option<Box<dyn StreamTranslator>>,
+}
+
+impl StreamConverter {
+    pub fn feed(&mut self, bytes: &[u8]) -> Vec<SseEvent> {
+        let events = self.parser.feed(bytes);
+        self.translate(events)
+    }
+
+    /// Events still due once the provider closed the stream.
+    pub fn finish(&mut self) -> Vec<SseEvent> {
+        let rest = self.parser.finish().into_iter().collect();
+        let mut events = self.translate(rest);
+        events.extend(self.translator.as_mut().map(|t| t.finish()).unwrap_or_default());
+        events
+    }
+
+    fn translate(&mut self, events: Vec<SseEvent>) -> Vec<SseEvent> {
+        match self.translator.as_mut() {
+            Some(translator) => events.into_iter().flat_map(|e| translator.translate(e)).collect(),
+            None => events,
+        }
+    }
+}
+
+/// The chunks of a whole stream, without the closing `[DONE]`.
+fn chunks(mut converter: StreamConverter, body: &[u8]) -> Vec<Value> {
+    let mut events = converter.feed(body);
+    events.extend(converter.finish());
+    events
+        .iter()
+        .filter(|event| !event.is_done())
+        .filter_map(|event| serde_json::from_str(&event.data).ok())
+        .collect()
+}
+
+/// An OpenAI chat request as an Anthropic Messages request, with the
+/// default `[anthropic]` settings.
+pub fn openai_to_anthropic(request: &Value) -> Result<Value, String> {
+    Translator::anthropic(AnthropicConfig::default()).request(request)
+}
+
+/// An Anthropic Messages response as an OpenAI chat completion.
+pub fn anthropic_to_openai(response: &Value, model: &str) -> Value {
+    Translator::anthropic(AnthropicConfig::default()).completion(response, model)
+}
+
+/// A whole Anthropic event stream as OpenAI chunks.
+pub fn anthropic_stream_to_openai_chunks(body: &[u8], model: &str) -> Vec<Value> {
+    chunks(Translator::anthropic(AnthropicConfig::default()).stream(model), body)
+}
+
+/// An OpenAI chat request as a Gemini `generateContent` request.
+pub fn openai_to_gemini(request: &Value) -> Result<Value, String> {
+    Translator::gemini().request(request)
+}
+
+/// A Gemini `generateContent` response as an OpenAI chat completion.
+pub fn gemini_to_openai(response: &Value, model: &str) -> Value {
+    Translator::gemini().completion(response, model)
+}
+
+/// A whole Gemini `streamGenerateContent` response as OpenAI chunks.
+pub fn gemini_stream_to_openai_chunks(body: &[u8], model: &str) -> Vec<Value> {
+    chunks(Translator::gemini().stream(model), body)
+}
@@ -0,0 +1,141 @@
+use rusqlite::Connection;
+use serde::Deserialize;
+use std::sync::{Mutex, MutexGuard};
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DatabaseConfig {
+    /// SQLite file for templates and other durable records. Without one an
+    /// in-memory database is used and everything is lost on restart.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Days request metadata is kept for attaching feedback.
+    #[serde(default = "default_retention_days")]
+    pub retention_days: u64,
+}
+
+fn default_retention_days() -> u64 {
+    30
+}
+
+impl Default for DatabaseConfig {
+    fn default() -> Self {
+        DatabaseConfig {
+            path: None,
+            retention_days: default_retention_days(),
+        }
+    }
+}
+
+/// Schema changes, applied in order; the index of the last one applied is
+/// kept in `user_version`. Append only.
+const MIGRATIONS: &[&str] = &[
+    "CREATE TABLE templates (
+        name TEXT PRIMARY KEY,
+        active_version INTEGER NOT NULL
+    );
+    CREATE TABLE template_versions (
+        name TEXT NOT NULL,
+        version INTEGER NOT NULL,
+        content TEXT NOT NULL,
+        created_at INTEGER NOT NULL,
+        PRIMARY KEY (name, version)
+    );",
+    "CREATE TABLE template_experiments (
+        name TEXT PRIMARY KEY,
+        candidate_version INTEGER NOT NULL,
+        percent REAL NOT NULL
+    );
+    CREATE TABLE requests (
+        request_id TEXT PRIMARY KEY,
+        tenant TEXT NOT NULL,
+        model TEXT NOT NULL,
+        template TEXT,
+        template_version INTEGER,
+        created_at INTEGER NOT NULL
+    );
+    CREATE TABLE feedback (
+        request_id TEXT NOT NULL,
+        score REAL NOT NULL,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX requests_template ON requests (template, template_version);
+    CREATE INDEX feedback_request ON feedback (request_id);",
+    "ALTER TABLE requests ADD COLUMN key_label TEXT;
+    ALTER TABLE requests ADD COLUMN backend TEXT;
+    ALTER TABLE feedback ADD COLUMN comment TEXT;
+    CREATE INDEX requests_created ON requests (created_at);",
+    "CREATE TABLE webhook_deliveries (
+        id INTEGER PRIMARY KEY AUTOINCREMENT,
+        endpoint TEXT NOT NULL,
+        event TEXT NOT NULL,
+        payload TEXT NOT NULL,
+        attempts INTEGER NOT NULL DEFAULT 0,
+        next_attempt_at INTEGER NOT NULL,
+        last_error TEXT,
+        dead INTEGER NOT NULL DEFAULT 0,
+        created_at INTEGER NOT NULL
+    );
+    CREATE INDEX webhook_deliveries_due ON webhook_deliveries (dead, next_attempt_at);",
+    "CREATE TABLE glossary_terms (
+        tenant TEXT NOT NULL,
+        source TEXT NOT NULL,
+        target TEXT NOT NULL,
+        updated_at INTEGER NOT NULL,
+        PRIMARY KEY (tenant, source)
+    );",
+    // Requests are keyed by an id of the adapter's own; `request_id` is
+    // whatever the client sent and need not be unique.
+    "ALTER TABLE requests RENAME TO requests_by_client_id;
+    CREATE TABLE requests (
+        id TEXT PRIMARY KEY,
+        request_id TEXT NOT NULL,
+        tenant TEXT NOT NULL,
+        model TEXT NOT NULL,
+        template TEXT,
+        template_version INTEGER,
+        created_at INTEGER NOT NULL,
+        key_label TEXT,
+        backend TEXT
+    );
+    INSERT INTO requests (id, request_id, tenant, model, template, template_version, created_at, key_label, backend)
+        SELECT request_id, request_id, tenant, model, template, template_version, created_at, key_label, backend
+        FROM requests_by_client_id;
+    DROP TABLE requests_by_client_id;
+    CREATE INDEX requests_template ON requests (template, template_version);
+    CREATE INDEX requests_created ON requests (created_at);
+    CREATE INDEX requests_client_id ON requests (request_id, tenant);
+    ALTER TABLE feedback RENAME COLUMN request_id TO request;",
+];
+
+/// The adapter's SQLite database. Queries are small and local, so they run
+/// on the calling task behind a mutex.
+pub struct Database {
+    conn: Mutex<Connection>,
+}
+
+impl Database {
+    pub fn open(config: &DatabaseConfig) -> Result<Self, String> {
+        let conn = match &config.path {
+            Some(path) => Connection::open(path).map_err(|e| format!("{}: {}", path, e))?,
+            None => Connection::open_in_memory().map_err(|e| e.to_string())?,
+        };
+        migrate(&conn).map_err(|e| format!("database migration failed: {}", e))?;
+        Ok(Database { conn: Mutex::new(conn) })
+    }
+
+    pub fn conn(&self) -> MutexGuard<'_, Connection> {
+        self.conn.lock().unwrap()
+    }
+}
+
+fn migrate(conn: &Connection) -> rusqlite::Result<()> {
+    let applied: usize = conn.query_row("PRAGMA user_version", [], |row| row.get(0))?;
+    for (index, migration) in MIGRATIONS.iter().enumerate().skip(applied) {
+        conn.execute_batch(&format!(
+            "BEGIN; {} PRAGMA user_version = {}; COMMIT;",
+            migration,
+            index + 1
+        ))?;
+    }
+    Ok(())
+}
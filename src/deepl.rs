@@ -0,0 +1,244 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, OriginalUri, State},
+    http::{header, HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::json;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::translation::{self, Pipeline};
+use crate::AppState;
+
+/// Texts one request may carry, as DeepL allows.
+const MAX_TEXTS: usize = 50;
+
+/// `[deepl]`: the DeepL-compatible `/v2/translate` endpoint.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct DeepLConfig {
+    /// Chat model translating the texts; `default_model` when unset.
+    #[serde(default)]
+    pub model: Option<String>,
+}
+
+/// DeepL language codes and the names the model is given.
+const LANGUAGES: &[(&str, &str)] = &[
+    ("AR", "Arabic"),
+    ("BG", "Bulgarian"),
+    ("CS", "Czech"),
+    ("DA", "Danish"),
+    ("DE", "German"),
+    ("EL", "Greek"),
+    ("EN", "English"),
+    ("EN-GB", "British English"),
+    ("EN-US", "American English"),
+    ("ES", "Spanish"),
+    ("ET", "Estonian"),
+    ("FI", "Finnish"),
+    ("FR", "French"),
+    ("HU", "Hungarian"),
+    ("ID", "Indonesian"),
+    ("IT", "Italian"),
+    ("JA", "Japanese"),
+    ("KO", "Korean"),
+    ("LT", "Lithuanian"),
+    ("LV", "Latvian"),
+    ("NB", "Norwegian Bokmål"),
+    ("NL", "Dutch"),
+    ("PL", "Polish"),
+    ("PT", "Portuguese"),
+    ("PT-BR", "Brazilian Portuguese"),
+    ("PT-PT", "European Portuguese"),
+    ("RO", "Romanian"),
+    ("RU", "Russian"),
+    ("SK", "Slovak"),
+    ("SL", "Slovenian"),
+    ("SV", "Swedish"),
+    ("TR", "Turkish"),
+    ("UK", "Ukrainian"),
+    ("ZH", "Chinese"),
+    ("ZH-HANS", "Simplified Chinese"),
+    ("ZH-HANT", "Traditional Chinese"),
+];
+
+fn language(code: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(known, _)| known.eq_ignore_ascii_case(code))
+        .map(|(_, name)| *name)
+}
+
+/// A `/v2/translate` request, from JSON or a form.
+#[derive(Debug, Default, Deserialize)]
+pub struct DeepLRequest {
+    pub text: Vec<String>,
+    #[serde(default)]
+    pub target_lang: String,
+    #[serde(default)]
+    pub source_lang: Option<String>,
+    #[serde(default)]
+    pub formality: Option<String>,
+    /// Text around the texts that helps translate them, itself not translated.
+    #[serde(default)]
+    pub context: Option<String>,
+    #[serde(default)]
+    pub show_billed_characters: bool,
+}
+
+/// Decodes `application/x-www-form-urlencoded` bytes, where `text` may repeat.
+fn parse_form(body: &[u8]) -> Result<DeepLRequest, String> {
+    let mut request = DeepLRequest::default();
+    for pair in body.split(|b| *b == b'&').filter(|p| !p.is_empty()) {
+        let (name, value) = match pair.iter().position(|b| *b == b'=') {
+            Some(at) => (&pair[..at], &pair[at + 1..]),
+            None => (pair, &b""[..]),
+        };
+        let value = percent_decode(value)?;
+        match percent_decode(name)?.as_str() {
+            "text" => request.text.push(value),
+            "target_lang" => request.target_lang = value,
+            "source_lang" => request.source_lang = Some(value),
+            "formality" => request.formality = Some(value),
+            "context" => request.context = Some(value),
+            "show_billed_characters" => request.show_billed_characters = value == "1" || value == "true",
+            _ => {}
+        }
+    }
+    Ok(request)
+}
+
+fn percent_decode(encoded: &[u8]) -> Result<String, String> {
+    let mut decoded = Vec::with_capacity(encoded.len());
+    let mut bytes = encoded.iter();
+    while let Some(&byte) = bytes.next() {
+        match byte {
+            b'+' => decoded.push(b' '),
+            b'%' => {
+                let hex = [*bytes.next().unwrap_or(&0), *bytes.next().unwrap_or(&0)];
+                let hex = std::str::from_utf8(&hex).map_err(|_| "Invalid percent-encoding")?;
+                decoded.push(u8::from_str_radix(hex, 16).map_err(|_| "Invalid percent-encoding")?);
+            }
+            other => decoded.push(other),
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| "Form values must be UTF-8".to_string())
+}
+
+/// An error in DeepL's shape, which its client libraries read.
+fn deepl_error(status: StatusCode, message: &str) -> Response<Body> {
+    (status, Json(json!({ "message": message }))).into_response()
+}
+
+/// The instruction the model translates under: the built-in one, with
+/// DeepL's formality and context.
+fn instruction(request: &DeepLRequest, target: &str, source: Option<&str>) -> Result<String, String> {
+    let mut instruction = translation::instruction(target, source);
+    match request.formality.as_deref().unwrap_or("default") {
+        "default" => {}
+        "more" | "prefer_more" => instruction.push_str(" Use a formal register."),
+        "less" | "prefer_less" => instruction.push_str(" Use an informal register."),
+        _ => return Err("Value for 'formality' not supported.".to_string()),
+    }
+    if let Some(context) = request.context.as_deref().filter(|c| !c.trim().is_empty()) {
+        instruction.push_str(&format!(
+            " For context only, not to be translated, the text appears alongside:\n{}",
+            context
+        ));
+    }
+    Ok(instruction)
+}
+
+/// Translates one text, answering failures as DeepL does.
+async fn translate(pipeline: &Pipeline, text: &str) -> Result<String, Response<Body>> {
+    let failure = match pipeline.translate(text, None).await {
+        Ok((translated, _)) => return Ok(translated.trim().to_string()),
+        Err(failure) => failure,
+    };
+    // DeepL answers an exhausted quota with 456, an unavailable service
+    // with 503 and a missing or invalid key with 403.
+    Err(match failure.status {
+        StatusCode::TOO_MANY_REQUESTS if failure.degraded => {
+            deepl_error(StatusCode::from_u16(456).unwrap(), "Quota exceeded.")
+        }
+        _ if failure.degraded => {
+            deepl_error(StatusCode::SERVICE_UNAVAILABLE, "Resource currently unavailable. Try again later.")
+        }
+        StatusCode::UNAUTHORIZED => deepl_error(StatusCode::FORBIDDEN, &failure.message),
+        status if status.is_success() => {
+            deepl_error(StatusCode::BAD_GATEWAY, "The model returned no translation")
+        }
+        status => deepl_error(status, &failure.message),
+    })
+}
+
+/// `POST /v2/translate`: DeepL's translate API, answered by the chat model,
+/// so DeepL client integrations can switch by changing the URL. Keys may
+/// be sent with DeepL's `DeepL-Auth-Key` scheme.
+pub async fn handle_translate(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let form = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|t| t.starts_with("application/x-www-form-urlencoded"));
+    let request = if form {
+        parse_form(&body)
+    } else {
+        serde_json::from_slice::<DeepLRequest>(&body).map_err(|e| e.to_string())
+    };
+    let request = match request {
+        Ok(request) => request,
+        Err(e) => return deepl_error(StatusCode::BAD_REQUEST, &format!("Invalid request: {}", e)),
+    };
+    if request.text.is_empty() || request.text.len() > MAX_TEXTS {
+        return deepl_error(
+            StatusCode::BAD_REQUEST,
+            &format!("Parameter 'text' must hold between 1 and {} texts.", MAX_TEXTS),
+        );
+    }
+    let Some(target) = language(&request.target_lang) else {
+        return deepl_error(StatusCode::BAD_REQUEST, "Value for 'target_lang' not supported.");
+    };
+    let source = match request.source_lang.as_deref().filter(|s| !s.is_empty()) {
+        Some(code) => match language(code) {
+            Some(name) => Some((code.to_uppercase(), name)),
+            None => return deepl_error(StatusCode::BAD_REQUEST, "Value for 'source_lang' not supported."),
+        },
+        None => None,
+    };
+    let instruction = match instruction(&request, target, source.as_ref().map(|(_, name)| *name)) {
+        Ok(instruction) => instruction,
+        Err(message) => return deepl_error(StatusCode::BAD_REQUEST, &message),
+    };
+
+    let model = state.config.deepl.model.clone().unwrap_or_else(|| state.config.default_model.clone());
+    let mut pipeline = Pipeline::new(state, connect_info, uri, headers, instruction);
+    pipeline.model = Some(model);
+    let translations = request.text.iter().map(|text| translate(&pipeline, text));
+    let translations = futures::future::join_all(translations).await;
+
+    let mut results = Vec::with_capacity(translations.len());
+    for (text, translated) in request.text.iter().zip(translations) {
+        let translated = match translated {
+            Ok(translated) => translated,
+            Err(response) => return response,
+        };
+        let detected = match &source {
+            Some((code, _)) => code.split('-').next().unwrap_or(code).to_string(),
+            None => translation::detect_language(text).unwrap_or_default(),
+        };
+        let mut result = json!({ "detected_source_language": detected, "text": translated });
+        if request.show_billed_characters {
+            result["billed_characters"] = json!(text.chars().count());
+        }
+        results.push(result);
+    }
+    Json(json!({ "translations": results })).into_response()
+}
@@ -0,0 +1,152 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::Response,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::sse::SseEvent;
+
+/// `[degraded]`: a canned assistant reply sent instead of an upstream failure
+/// or an exhausted budget, so chat UIs show a friendly message rather than a
+/// raw 502 or 429.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DegradedConfig {
+    #[serde(default = "default_message")]
+    pub message: String,
+    /// Failure statuses replaced with the canned reply, whether from the
+    /// backend or the adapter's own limits.
+    #[serde(default = "default_statuses")]
+    pub statuses: Vec<u16>,
+    /// Messages for specific routes and/or `Accept-Language` locales.
+    #[serde(default)]
+    pub messages: Vec<DegradedMessage>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct DegradedMessage {
+    /// Request path as the client sent it, prefix and aliases included.
+    #[serde(default)]
+    pub route: Option<String>,
+    /// Language tag such as `de` or `pt-BR`; `de` also matches `de-AT`.
+    #[serde(default)]
+    pub locale: Option<String>,
+    pub message: String,
+}
+
+fn default_message() -> String {
+    "The service is busy right now. Please try again in a moment.".to_string()
+}
+
+fn default_statuses() -> Vec<u16> {
+    vec![429, 502, 503, 504]
+}
+
+/// Languages from `Accept-Language`, most preferred first.
+pub(crate) fn accepted_languages(headers: &HeaderMap) -> Vec<(String, f32)> {
+    let Some(value) = headers.get(header::ACCEPT_LANGUAGE).and_then(|v| v.to_str().ok()) else {
+        return Vec::new();
+    };
+    let mut languages: Vec<(String, f32)> = value
+        .split(',')
+        .filter_map(|item| {
+            let mut parts = item.trim().split(';');
+            let tag = parts.next()?.trim().to_lowercase();
+            let quality = parts
+                .find_map(|p| p.trim().strip_prefix("q="))
+                .and_then(|q| q.parse().ok())
+                .unwrap_or(1.0);
+            (!tag.is_empty() && tag != "*").then_some((tag, quality))
+        })
+        .collect();
+    languages.sort_by(|a, b| b.1.total_cmp(&a.1));
+    languages
+}
+
+pub(crate) fn locale_matches(locale: &str, tag: &str) -> bool {
+    let locale = locale.to_lowercase();
+    tag == locale || tag.starts_with(&format!("{}-", locale))
+}
+
+impl DegradedConfig {
+    /// The most specific message for this route and the caller's languages.
+    pub fn message(&self, route: &str, headers: &HeaderMap) -> &str {
+        let languages = accepted_languages(headers);
+        let route_matches = |m: &&DegradedMessage| m.route.is_none() || m.route.as_deref() == Some(route);
+        for (tag, _) in &languages {
+            let localized = self
+                .messages
+                .iter()
+                .filter(route_matches)
+                .filter(|m| m.locale.as_deref().is_some_and(|l| locale_matches(l, tag)))
+                .max_by_key(|m| m.route.is_some());
+            if let Some(found) = localized {
+                return &found.message;
+            }
+        }
+        self.messages
+            .iter()
+            .find(|m| m.locale.is_none() && m.route.as_deref() == Some(route))
+            .map(|m| m.message.as_str())
+            .unwrap_or(&self.message)
+    }
+
+    pub fn applies(&self, status: StatusCode) -> bool {
+        self.statuses.contains(&status.as_u16())
+    }
+}
+
+/// A complete, well-formed completion carrying `message`, as JSON or as an
+/// SSE stream depending on what the client asked for.
+pub fn response(message: &str, model: &str, stream: bool, failed: StatusCode) -> Response<Body> {
+    let id = format!("chatcmpl-degraded-{}", uuid::Uuid::new_v4().simple());
+    let created = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0);
+
+    let (content_type, body) = if stream {
+        let chunk = |delta: Value, finish_reason: Value| {
+            json!({
+                "id": id,
+                "object": "chat.completion.chunk",
+                "created": created,
+                "model": model,
+                "choices": [{ "index": 0, "delta": delta, "finish_reason": finish_reason }],
+            })
+        };
+        let events = [
+            chunk(json!({ "role": "assistant", "content": message }), Value::Null).to_string(),
+            chunk(json!({}), json!("stop")).to_string(),
+            "[DONE]".to_string(),
+        ];
+        let body: Vec<u8> = events
+            .into_iter()
+            .flat_map(|data| SseEvent::data(data).to_bytes())
+            .collect();
+        ("text/event-stream", Body::from(body))
+    } else {
+        let completion = json!({
+            "id": id,
+            "object": "chat.completion",
+            "created": created,
+            "model": model,
+            "choices": [{
+                "index": 0,
+                "message": { "role": "assistant", "content": message },
+                "finish_reason": "stop",
+            }],
+            "usage": { "prompt_tokens": 0, "completion_tokens": 0, "total_tokens": 0 },
+        });
+        ("application/json", Body::from(completion.to_string()))
+    };
+
+    Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, content_type)
+        .header("x-llmta-degraded", HeaderValue::from(failed.as_u16()))
+        .body(body)
+        .unwrap()
+}
@@ -0,0 +1,239 @@
+use regex::Regex;
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::collections::HashMap;
+use std::sync::Arc;
+
+const EMAIL: &str = r"[A-Za-z0-9._%+-]+@[A-Za-z0-9.-]+\.[A-Za-z]{2,}";
+
+/// Longest placeholder the streaming restorer waits for; longer `[[` runs
+/// are passed on as they are.
+const MAX_PLACEHOLDER: usize = 48;
+
+/// `[[deidentify.patterns]]` entry: text replaced by `[[LABEL_n]]`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MaskPattern {
+    pub label: String,
+    pub regex: String,
+}
+
+/// `[deidentify]`: replaces personal data in chat requests bound for
+/// backends outside the home regions with placeholders, and puts the
+/// originals back into the answers.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DeidentifyConfig {
+    /// Regions requests may reach unmasked. Backends without a `region`
+    /// count as home; an empty list turns masking off.
+    #[serde(default)]
+    pub home_regions: Vec<String>,
+    #[serde(default = "default_true")]
+    pub emails: bool,
+    #[serde(default)]
+    pub patterns: Vec<MaskPattern>,
+    /// Names and other terms matched as whole words, masked as `NAME`.
+    #[serde(default)]
+    pub names: Vec<String>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for DeidentifyConfig {
+    fn default() -> Self {
+        DeidentifyConfig {
+            home_regions: Vec::new(),
+            emails: default_true(),
+            patterns: Vec::new(),
+            names: Vec::new(),
+        }
+    }
+}
+
+/// The compiled `[deidentify]` rules.
+pub struct Deidentifier {
+    home_regions: Vec<String>,
+    rules: Vec<(String, Regex)>,
+}
+
+impl Deidentifier {
+    pub fn new(config: &DeidentifyConfig) -> Result<Self, String> {
+        let mut rules = Vec::new();
+        if config.emails {
+            rules.push(("EMAIL".to_string(), Regex::new(EMAIL).unwrap()));
+        }
+        for pattern in &config.patterns {
+            let regex = Regex::new(&pattern.regex)
+                .map_err(|e| format!("deidentify pattern {}: {}", pattern.label, e))?;
+            rules.push((pattern.label.to_uppercase(), regex));
+        }
+        if !config.names.is_empty() {
+            let names: Vec<String> = config.names.iter().map(|n| regex::escape(n)).collect();
+            let regex = Regex::new(&format!(r"\b(?:{})\b", names.join("|"))).map_err(|e| e.to_string())?;
+            rules.push(("NAME".to_string(), regex));
+        }
+        Ok(Deidentifier {
+            home_regions: config.home_regions.clone(),
+            rules,
+        })
+    }
+
+    /// Whether requests to a backend in `region` are masked.
+    pub fn applies(&self, region: Option<&str>) -> bool {
+        !self.home_regions.is_empty() && region.is_some_and(|r| !self.home_regions.iter().any(|h| h == r))
+    }
+
+    /// Masks the text of every message; `None` when nothing matched.
+    pub fn mask(&self, payload: &mut Map<String, Value>) -> Option<Placeholders> {
+        let mut placeholders = Placeholders::default();
+        let messages = payload.get_mut("messages").and_then(Value::as_array_mut)?;
+        for message in messages {
+            match message.get_mut("content") {
+                Some(Value::String(text)) => *text = self.mask_text(text, &mut placeholders),
+                Some(Value::Array(parts)) => {
+                    for part in parts {
+                        if let Some(Value::String(text)) = part.get_mut("text") {
+                            *text = self.mask_text(text, &mut placeholders);
+                        }
+                    }
+                }
+                _ => {}
+            }
+        }
+        (!placeholders.entries.is_empty()).then_some(placeholders)
+    }
+
+    fn mask_text(&self, text: &str, placeholders: &mut Placeholders) -> String {
+        let mut text = text.to_string();
+        for (label, regex) in &self.rules {
+            text = regex
+                .replace_all(&text, |found: &regex::Captures| placeholders.insert(label, &found[0]))
+                .into_owned();
+        }
+        text
+    }
+}
+
+/// The originals behind one request's placeholders.
+#[derive(Debug, Default)]
+pub struct Placeholders {
+    entries: Vec<(String, String)>,
+    counts: HashMap<String, usize>,
+}
+
+impl Placeholders {
+    /// The placeholder for `original`, the same one each time it recurs.
+    fn insert(&mut self, label: &str, original: &str) -> String {
+        if let Some((placeholder, _)) = self.entries.iter().find(|(_, o)| o == original) {
+            return placeholder.clone();
+        }
+        let count = self.counts.entry(label.to_string()).or_default();
+        *count += 1;
+        let placeholder = format!("[[{}_{}]]", label, count);
+        self.entries.push((placeholder.clone(), original.to_string()));
+        placeholder
+    }
+
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    pub fn restore(&self, text: &str) -> String {
+        let mut text = text.to_string();
+        for (placeholder, original) in &self.entries {
+            if text.contains(placeholder.as_str()) {
+                text = text.replace(placeholder.as_str(), original);
+            }
+        }
+        text
+    }
+
+    /// Restores message contents and tool call arguments; returns whether
+    /// anything changed.
+    pub fn restore_completion(&self, completion: &mut Value) -> bool {
+        let mut changed = false;
+        let choices = completion.get_mut("choices").and_then(Value::as_array_mut);
+        for message in choices.into_iter().flatten().filter_map(|c| c.get_mut("message")) {
+            changed |= self.restore_field(message, "content");
+            let calls = message.get_mut("tool_calls").and_then(Value::as_array_mut);
+            for function in calls.into_iter().flatten().filter_map(|c| c.get_mut("function")) {
+                changed |= self.restore_field(function, "arguments");
+            }
+        }
+        changed
+    }
+
+    fn restore_field(&self, value: &mut Value, key: &str) -> bool {
+        let Some(Value::String(text)) = value.get_mut(key) else {
+            return false;
+        };
+        let restored = self.restore(text);
+        let changed = restored != *text;
+        *text = restored;
+        changed
+    }
+}
+
+/// Restores placeholders in streamed content, holding back the tail of a
+/// choice's text while it may be the start of a placeholder split across
+/// chunks.
+pub struct StreamRestorer {
+    placeholders: Arc<Placeholders>,
+    pending: HashMap<u64, String>,
+}
+
+/// Where the text that may still grow into a placeholder starts.
+fn held_from(text: &str) -> usize {
+    if let Some(start) = text.rfind("[[") {
+        if !text[start..].contains("]]") && text.len() - start <= MAX_PLACEHOLDER {
+            return start;
+        }
+    }
+    if text.ends_with('[') {
+        return text.len() - 1;
+    }
+    text.len()
+}
+
+impl StreamRestorer {
+    pub fn new(placeholders: Arc<Placeholders>) -> Self {
+        StreamRestorer {
+            placeholders,
+            pending: HashMap::new(),
+        }
+    }
+
+    /// Returns whether the chunk changed. A choice's held text is released
+    /// with its `finish_reason`.
+    pub fn restore_chunk(&mut self, chunk: &mut Value) -> bool {
+        let Some(choices) = chunk.get_mut("choices").and_then(Value::as_array_mut) else {
+            return false;
+        };
+        let mut changed = false;
+        for choice in choices {
+            let index = choice["index"].as_u64().unwrap_or(0);
+            let finished = choice["finish_reason"].is_string();
+            let content = choice["delta"]["content"].as_str();
+            if content.is_none() && !(finished && self.pending.contains_key(&index)) {
+                continue;
+            }
+            let mut text = self.pending.remove(&index).unwrap_or_default();
+            text.push_str(content.unwrap_or_default());
+            let split = if finished { text.len() } else { held_from(&text) };
+            let held = text.split_off(split);
+            if !held.is_empty() {
+                self.pending.insert(index, held);
+            }
+            let restored = self.placeholders.restore(&text);
+            if Some(restored.as_str()) != content {
+                choice["delta"]["content"] = Value::String(restored);
+                changed = true;
+            }
+        }
+        changed
+    }
+}
@@ -0,0 +1,200 @@
+use axum::http::{HeaderMap, HeaderValue};
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::policy;
+
+/// What happens to requests for a model once its sunset date has passed.
+#[derive(Debug, Deserialize, Serialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum AfterSunset {
+    /// Keep serving, with the headers and a log line per request.
+    #[default]
+    Warn,
+    /// Answer 410 Gone, naming the replacement.
+    Reject,
+}
+
+/// `[[deprecations]]` entry: model aliases being retired.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ModelDeprecation {
+    /// Requested models; a trailing `*` matches by prefix.
+    pub models: Vec<String>,
+    /// Date the models were deprecated, `YYYY-MM-DD`, for the `Deprecation` header.
+    #[serde(default)]
+    pub since: Option<String>,
+    /// Date the models stop being served as requested, `YYYY-MM-DD` (UTC).
+    pub sunset: String,
+    /// Model clients should move to.
+    #[serde(default)]
+    pub replacement: Option<String>,
+    #[serde(default)]
+    pub after_sunset: AfterSunset,
+    /// Migration notes, sent as a `Link` header.
+    #[serde(default)]
+    pub link: Option<String>,
+}
+
+/// Year, month and day of a `YYYY-MM-DD` date.
+fn parse_date(date: &str) -> Option<(i64, u32, u32)> {
+    let mut parts = date.trim().splitn(3, '-');
+    let year = parts.next()?.parse().ok()?;
+    let month = parts.next()?.parse().ok()?;
+    let day = parts.next()?.parse().ok()?;
+    ((1..=12).contains(&month) && (1..=31).contains(&day)).then_some((year, month, day))
+}
+
+/// Days since 1970-01-01 of a civil date (Howard Hinnant's algorithm).
+fn days_from_civil(year: i64, month: u32, day: u32) -> i64 {
+    let year = if month <= 2 { year - 1 } else { year };
+    let era = year.div_euclid(400);
+    let yoe = year - era * 400;
+    let mp = (i64::from(month) + 9) % 12;
+    let doy = (153 * mp + 2) / 5 + i64::from(day) - 1;
+    let doe = yoe * 365 + yoe / 4 - yoe / 100 + doy;
+    era * 146_097 + doe - 719_468
+}
+
+/// Unix seconds at the start of a `YYYY-MM-DD` date.
+fn timestamp(date: &str) -> Option<i64> {
+    let (year, month, day) = parse_date(date)?;
+    Some(days_from_civil(year, month, day) * 86_400)
+}
+
+/// `Wed, 31 Dec 2025 00:00:00 GMT`, as the `Sunset` header wants.
+fn http_date(date: &str) -> Option<String> {
+    const WEEKDAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let (year, month, day) = parse_date(date)?;
+    let weekday = WEEKDAYS[days_from_civil(year, month, day).rem_euclid(7) as usize];
+    Some(format!(
+        "{}, {:02} {} {:04} 00:00:00 GMT",
+        weekday,
+        day,
+        MONTHS[month as usize - 1],
+        year
+    ))
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+impl ModelDeprecation {
+    pub fn find<'a>(deprecations: &'a [ModelDeprecation], model: &str) -> Option<&'a ModelDeprecation> {
+        deprecations
+            .iter()
+            .find(|d| !d.models.is_empty() && policy::matches(&d.models, model))
+    }
+
+    /// Rejects dates that are not `YYYY-MM-DD`, so a typo fails at startup.
+    pub fn validate(deprecations: &[ModelDeprecation]) -> Result<(), String> {
+        for deprecation in deprecations {
+            for date in std::iter::once(&deprecation.sunset).chain(&deprecation.since) {
+                if parse_date(date).is_none() {
+                    return Err(format!(
+                        "Deprecation of {:?}: {} is not a YYYY-MM-DD date",
+                        deprecation.models, date
+                    ));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    pub fn is_past_sunset(&self) -> bool {
+        timestamp(&self.sunset).is_some_and(|sunset| now() >= sunset)
+    }
+
+    /// `Deprecation`, `Sunset` and `Link` headers (RFC 9745 and RFC 8594).
+    pub fn headers(&self) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        let deprecation = match self.since.as_deref().and_then(timestamp) {
+            Some(since) => format!("@{}", since),
+            None => "true".to_string(),
+        };
+        if let Ok(value) = HeaderValue::from_str(&deprecation) {
+            headers.insert("deprecation", value);
+        }
+        if let Some(value) = http_date(&self.sunset).and_then(|d| HeaderValue::from_str(&d).ok()) {
+            headers.insert("sunset", value);
+        }
+        if let Some(link) = &self.link {
+            let value = format!("<{}>; rel=\"deprecation\"", link);
+            if let Ok(value) = HeaderValue::from_str(&value) {
+                headers.insert("link", value);
+            }
+        }
+        if let Some(value) = self.replacement.as_deref().and_then(|r| HeaderValue::from_str(r).ok()) {
+            headers.insert("x-llmta-replacement-model", value);
+        }
+        headers
+    }
+
+    pub fn message(&self, model: &str) -> String {
+        let mut message = format!("Model {} was retired on {}", model, self.sunset);
+        if let Some(replacement) = &self.replacement {
+            message.push_str(&format!("; use {} instead", replacement));
+        }
+        message
+    }
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct DeprecatedUse {
+    pub model: String,
+    pub key: String,
+    pub requests: u64,
+    pub rejected: u64,
+    /// Unix seconds.
+    pub last_used: i64,
+}
+
+/// Who still requests deprecated models, so they can be chased before the
+/// sunset.
+#[derive(Default)]
+pub struct DeprecationLog {
+    uses: Mutex<HashMap<(String, String), DeprecatedUse>>,
+}
+
+impl DeprecationLog {
+    /// Counts a request, logging a key's first use of each deprecated model.
+    pub fn record(&self, model: &str, key: &str, deprecation: &ModelDeprecation, rejected: bool) {
+        let mut uses = self.uses.lock().unwrap();
+        let entry = uses
+            .entry((model.to_string(), key.to_string()))
+            .or_insert_with(|| {
+                println!(
+                    "{} uses deprecated model {} (sunset {}, replacement {})",
+                    key,
+                    model,
+                    deprecation.sunset,
+                    deprecation.replacement.as_deref().unwrap_or("none")
+                );
+                DeprecatedUse {
+                    model: model.to_string(),
+                    key: key.to_string(),
+                    requests: 0,
+                    rejected: 0,
+                    last_used: 0,
+                }
+            });
+        entry.requests += 1;
+        entry.rejected += u64::from(rejected);
+        entry.last_used = now();
+    }
+
+    /// Uses by model and key, most requests first.
+    pub fn uses(&self) -> Vec<DeprecatedUse> {
+        let mut uses: Vec<DeprecatedUse> = self.uses.lock().unwrap().values().cloned().collect();
+        uses.sort_by(|a, b| b.requests.cmp(&a.requests).then_with(|| a.model.cmp(&b.model)));
+        uses
+    }
+}
@@ -0,0 +1,278 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
+
+use crate::backends::Backend;
+use crate::AppState;
+
+/// `[discovery]`: probes each OpenAI-compatible backend at startup for the
+/// request features it accepts, and adapts requests to the ones it rejects.
+#[derive(Debug, Deserialize, Clone)]
+pub struct DiscoveryConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Model the test requests ask for; defaults to the first model the
+    /// backend lists in its config without a `*`, else `default_model`.
+    #[serde(default)]
+    pub model: Option<String>,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+impl Default for DiscoveryConfig {
+    fn default() -> Self {
+        DiscoveryConfig {
+            enabled: false,
+            model: None,
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Serialize, Clone, Copy, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum Support {
+    Yes,
+    No,
+    /// The probe failed for another reason than the feature.
+    Unknown,
+}
+
+/// What a backend was found to accept.
+#[derive(Debug, Serialize, Clone)]
+pub struct Capabilities {
+    /// Models from its `/models`, if it has that endpoint.
+    pub models: Option<Vec<String>>,
+    pub tools: Support,
+    pub parallel_tool_calls: Support,
+    pub json_schema: Support,
+    pub stream_options: Support,
+    pub developer_role: Support,
+    /// Why the backend could not be probed, which usually means its URL
+    /// or key is wrong.
+    pub error: Option<String>,
+}
+
+/// Discovered capabilities by backend name.
+#[derive(Default)]
+pub struct Discovery {
+    backends: RwLock<HashMap<String, Capabilities>>,
+}
+
+impl Discovery {
+    pub fn get(&self, backend: &str) -> Option<Capabilities> {
+        self.backends.read().unwrap().get(backend).cloned()
+    }
+}
+
+impl Capabilities {
+    /// Rewrites what the backend rejects into what it accepts; returns a
+    /// description of every change so it can be logged.
+    pub fn adapt(&self, payload: &mut Map<String, Value>) -> Vec<String> {
+        let mut changes = Vec::new();
+        if self.developer_role == Support::No {
+            let messages = payload.get_mut("messages").and_then(Value::as_array_mut);
+            for message in messages.into_iter().flatten() {
+                if message["role"] == "developer" {
+                    message["role"] = json!("system");
+                    changes.push("developer role sent as system".to_string());
+                }
+            }
+        }
+        if self.stream_options == Support::No && payload.remove("stream_options").is_some() {
+            changes.push("removed stream_options".to_string());
+        }
+        if self.parallel_tool_calls == Support::No
+            && payload.remove("parallel_tool_calls").is_some()
+        {
+            changes.push("removed parallel_tool_calls".to_string());
+        }
+        if self.json_schema == Support::No {
+            let format = payload.get_mut("response_format");
+            if let Some(format) = format.filter(|f| f["type"] == "json_schema") {
+                *format = json!({ "type": "json_object" });
+                changes.push("json_schema response format sent as json_object".to_string());
+            }
+        }
+        changes
+    }
+}
+
+fn probe_model(state: &AppState, backend: &Backend) -> String {
+    state
+        .config
+        .discovery
+        .model
+        .clone()
+        .or_else(|| backend.models.iter().find(|m| !m.contains('*')).cloned())
+        .unwrap_or_else(|| state.config.default_model.clone())
+}
+
+/// The smallest chat request, plus `extra`.
+fn test_request(model: &str, extra: Value) -> Value {
+    let mut request = json!({
+        "model": model,
+        "messages": [{ "role": "user", "content": "Hi" }],
+        "max_tokens": 1,
+    });
+    if let (Some(request), Value::Object(extra)) = (request.as_object_mut(), extra) {
+        request.extend(extra);
+    }
+    request
+}
+
+fn test_tool() -> Value {
+    json!([{
+        "type": "function",
+        "function": {
+            "name": "noop",
+            "description": "Does nothing",
+            "parameters": { "type": "object", "properties": {} },
+        },
+    }])
+}
+
+/// Sends a test request: `Ok` with its support if the backend answered,
+/// `Err` if it could not be reached.
+async fn try_request(
+    backend: &Backend,
+    timeout: Duration,
+    request: &Value,
+) -> Result<Support, String> {
+    let mut headers = reqwest::header::HeaderMap::new();
+    backend.provider.authorize(&mut headers, &backend.key);
+    let response = backend
+        .client
+        .post(&backend.url)
+        .headers(headers)
+        .timeout(timeout)
+        .json(request)
+        .send()
+        .await
+        .map_err(|e| e.to_string())?;
+    let status = response.status();
+    // Read the body so the connection can be reused.
+    let _ = response.bytes().await;
+    Ok(match status.as_u16() {
+        200..=299 => Support::Yes,
+        400 | 404 | 422 => Support::No,
+        _ => Support::Unknown,
+    })
+}
+
+async fn list_models(backend: &Backend, timeout: Duration) -> Option<Vec<String>> {
+    let mut headers = reqwest::header::HeaderMap::new();
+    backend.provider.authorize(&mut headers, &backend.key);
+    let url = format!("{}/models", backend.base_url());
+    let response = backend.client.get(url).headers(headers).timeout(timeout).send().await.ok()?;
+    let list: Value = response.error_for_status().ok()?.json().await.ok()?;
+    let models = list["data"].as_array()?;
+    Some(models.iter().filter_map(|m| m["id"].as_str().map(str::to_string)).collect())
+}
+
+async fn probe(state: &AppState, backend: &Backend) -> Capabilities {
+    let timeout = Duration::from_secs(state.config.discovery.timeout_secs);
+    let model = probe_model(state, backend);
+    let mut capabilities = Capabilities {
+        models: list_models(backend, timeout).await,
+        tools: Support::Unknown,
+        parallel_tool_calls: Support::Unknown,
+        json_schema: Support::Unknown,
+        stream_options: Support::Unknown,
+        developer_role: Support::Unknown,
+        error: None,
+    };
+    if let Some(models) = capabilities.models.as_ref().filter(|m| !m.contains(&model)) {
+        println!(
+            "Backend {} does not list {} among its {} models",
+            backend.name,
+            model,
+            models.len()
+        );
+    }
+    match try_request(backend, timeout, &test_request(&model, json!({}))).await {
+        Ok(Support::Yes) => {}
+        Ok(_) => {
+            capabilities.error = Some(format!("a minimal request for {} was refused", model));
+            return capabilities;
+        }
+        Err(e) => {
+            capabilities.error = Some(e);
+            return capabilities;
+        }
+    }
+
+    let features = [
+        json!({ "tools": test_tool() }),
+        json!({ "tools": test_tool(), "parallel_tool_calls": false }),
+        json!({
+            "response_format": {
+                "type": "json_schema",
+                "json_schema": {
+                    "name": "probe",
+                    "schema": { "type": "object", "properties": {} },
+                },
+            },
+        }),
+        json!({ "stream": true, "stream_options": { "include_usage": true } }),
+        json!({
+            "messages": [
+                { "role": "developer", "content": "Be brief." },
+                { "role": "user", "content": "Hi" },
+            ],
+        }),
+    ];
+    let mut support = Vec::with_capacity(features.len());
+    for extra in features {
+        let request = test_request(&model, extra);
+        support.push(try_request(backend, timeout, &request).await.unwrap_or(Support::Unknown));
+    }
+    capabilities.tools = support[0];
+    capabilities.parallel_tool_calls = support[1];
+    capabilities.json_schema = support[2];
+    capabilities.stream_options = support[3];
+    capabilities.developer_role = support[4];
+    capabilities
+}
+
+/// Probes every OpenAI-compatible backend in the background.
+pub fn discover(state: Arc<AppState>) {
+    if !state.config.discovery.enabled {
+        return;
+    }
+    for backend in state.backends.all() {
+        if backend.provider.name() != "openai" {
+            continue;
+        }
+        let backend = backend.clone();
+        let state = state.clone();
+        tokio::spawn(async move {
+            let capabilities = probe(&state, &backend).await;
+            match &capabilities.error {
+                Some(error) => println!("Could not probe backend {}: {}", backend.name, error),
+                None => println!(
+                    "Backend {} supports tools: {:?}, parallel_tool_calls: {:?}, \
+                     json_schema: {:?}, stream_options: {:?}, developer role: {:?}",
+                    backend.name,
+                    capabilities.tools,
+                    capabilities.parallel_tool_calls,
+                    capabilities.json_schema,
+                    capabilities.stream_options,
+                    capabilities.developer_role
+                ),
+            }
+            state
+                .discovery
+                .backends
+                .write()
+                .unwrap()
+                .insert(backend.name.clone(), capabilities);
+        });
+    }
+}
@@ -0,0 +1,156 @@
+use serde_json::json;
+use std::time::{Duration, Instant};
+
+use crate::maintenance::DEFAULT_BACKEND;
+use crate::{AppConfig, AppState};
+
+/// Collects check results and prints them as they come in.
+#[derive(Default)]
+struct Report {
+    failures: usize,
+}
+
+impl Report {
+    fn ok(&mut self, check: &str, detail: impl AsRef<str>) {
+        println!("[ ok ] {:<12} {}", check, detail.as_ref());
+    }
+
+    fn fail(&mut self, check: &str, detail: impl AsRef<str>) {
+        self.failures += 1;
+        println!("[FAIL] {:<12} {}", check, detail.as_ref());
+    }
+
+    fn skip(&mut self, check: &str, detail: impl AsRef<str>) {
+        println!("[skip] {:<12} {}", check, detail.as_ref());
+    }
+}
+
+/// `openai-api-proxy doctor [--completion]`: validates the configuration and
+/// checks every backend is reachable with the configured credentials.
+/// Returns whether all checks passed.
+pub async fn run(test_completion: bool) -> bool {
+    match AppConfig::load() {
+        Ok(config) => {
+            Report::default().ok("config", "loaded config/default (+ config/local)");
+            check(config, test_completion).await
+        }
+        Err(e) => {
+            Report::default().fail("config", e.to_string());
+            false
+        }
+    }
+}
+
+/// Runs the checks after loading against an already built configuration.
+pub async fn check(config: AppConfig, test_completion: bool) -> bool {
+    let mut report = Report::default();
+    let state = match AppState::new(config.clone()) {
+        Ok(state) => {
+            report.ok("state", "auth, limits and signing settings are valid");
+            state
+        }
+        Err(e) => {
+            report.fail("state", e.to_string());
+            return false;
+        }
+    };
+
+    check_backend(&mut report, &state, &config, test_completion).await;
+    let tokenizers = state.tokenizers.check();
+    if tokenizers.is_empty() {
+        report.skip("tokenizer", "none configured, approximating for all models");
+    }
+    for (models, result) in tokenizers {
+        match result {
+            Ok(name) => report.ok("tokenizer", format!("{} -> {}", models, name)),
+            Err(e) => report.fail("tokenizer", format!("{}: {}", models, e)),
+        }
+    }
+
+    println!();
+    if report.failures == 0 {
+        println!("Ready to serve on {}:{}", config.host, config.port);
+    } else {
+        println!("{} check(s) failed", report.failures);
+    }
+    report.failures == 0
+}
+
+async fn check_backend(report: &mut Report, state: &AppState, config: &AppConfig, test_completion: bool) {
+    let url = match reqwest::Url::parse(&config.model_url) {
+        Ok(url) => url,
+        Err(e) => {
+            report.fail("backend", format!("invalid model_url {}: {}", config.model_url, e));
+            return;
+        }
+    };
+    report.ok("backend", format!("{} -> {}", DEFAULT_BACKEND, url));
+
+    let host = url.host_str().unwrap_or_default().to_string();
+    let port = url.port_or_known_default().unwrap_or(443);
+    match tokio::net::lookup_host((host.as_str(), port)).await {
+        Ok(addrs) => {
+            let addrs: Vec<String> = addrs.map(|a| a.ip().to_string()).collect();
+            report.ok("dns", format!("{} -> {}", host, addrs.join(", ")));
+        }
+        Err(e) => {
+            report.fail("dns", format!("{}: {}", host, e));
+            return;
+        }
+    }
+
+    // An authenticated but free call: list models next to the completions URL.
+    let models_url = format!("{}/models", config.base_url());
+    let started = Instant::now();
+    match state
+        .client
+        .get(&models_url)
+        .bearer_auth(&config.model_key)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await
+    {
+        Ok(response) if response.status().is_success() => report.ok(
+            "auth",
+            format!("{} answered {} in {:?}", models_url, response.status(), started.elapsed()),
+        ),
+        Ok(response) => report.fail(
+            "auth",
+            format!("{} answered {}", models_url, response.status()),
+        ),
+        Err(e) => report.fail("auth", format!("{}: {}", models_url, e)),
+    }
+
+    if !test_completion {
+        report.skip("completion", "pass --completion to send a 1-token request");
+        return;
+    }
+    let started = Instant::now();
+    let result = state
+        .client
+        .post(&config.model_url)
+        .bearer_auth(&config.model_key)
+        .timeout(Duration::from_secs(30))
+        .json(&json!({
+            "model": config.default_model,
+            "messages": [{ "role": "user", "content": "ping" }],
+            "max_tokens": 1,
+        }))
+        .send()
+        .await;
+    match result {
+        Ok(response) if response.status().is_success() => report.ok(
+            "completion",
+            format!("{} answered in {:?}", config.default_model, started.elapsed()),
+        ),
+        Ok(response) => {
+            let status = response.status();
+            let body = response.text().await.unwrap_or_default();
+            report.fail(
+                "completion",
+                format!("{}: {}", status, body.chars().take(200).collect::<String>()),
+            );
+        }
+        Err(e) => report.fail("completion", e.to_string()),
+    }
+}
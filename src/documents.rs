@@ -0,0 +1,251 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, OriginalUri, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+};
+use futures::{channel::mpsc, SinkExt, StreamExt};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::create_error_response;
+use crate::sse::SseEvent;
+use crate::translation::{self, Pipeline};
+use crate::AppState;
+
+/// Documents one request may submit.
+const MAX_DOCUMENTS: usize = 100;
+
+#[derive(Debug, Deserialize)]
+pub struct DocumentsRequest {
+    /// Chat model doing the translation; the default model when unset.
+    #[serde(default)]
+    pub model: Option<String>,
+    pub target_language: String,
+    #[serde(default)]
+    pub source_language: Option<String>,
+    pub documents: Vec<Document>,
+    /// Terms to translate consistently, as for `x_glossary`.
+    #[serde(default)]
+    pub glossary: Option<Value>,
+    /// Documents are translated in segments of about this many characters,
+    /// split at paragraph breaks where possible.
+    #[serde(default = "default_max_segment_chars")]
+    pub max_segment_chars: usize,
+    /// Documents translated at once.
+    #[serde(default = "default_concurrency")]
+    pub concurrency: usize,
+}
+
+fn default_max_segment_chars() -> usize {
+    2000
+}
+
+fn default_concurrency() -> usize {
+    4
+}
+
+#[derive(Debug, Deserialize)]
+pub struct Document {
+    /// Echoed in progress events; the document's index when unset.
+    #[serde(default)]
+    pub id: Option<String>,
+    pub text: String,
+}
+
+/// Splits `text` into pieces of at most `max_chars` characters, breaking
+/// at paragraphs, then at whitespace, then anywhere. Concatenated, the
+/// pieces give back `text`.
+pub fn segments(text: &str, max_chars: usize) -> Vec<String> {
+    let max_chars = max_chars.max(1);
+    let mut segments = Vec::new();
+    let mut current = String::new();
+    for paragraph in text.split_inclusive("\n\n") {
+        if !current.is_empty() && current.chars().count() + paragraph.chars().count() > max_chars {
+            segments.push(std::mem::take(&mut current));
+        }
+        current.push_str(paragraph);
+        while current.chars().count() > max_chars {
+            let rest = current.split_off(split_point(&current, max_chars));
+            segments.push(std::mem::replace(&mut current, rest));
+        }
+    }
+    if !current.is_empty() {
+        segments.push(current);
+    }
+    segments
+}
+
+/// Byte offset to cut an overlong piece at: after the last whitespace within
+/// `max_chars`, or at `max_chars` when there is none.
+fn split_point(text: &str, max_chars: usize) -> usize {
+    let limit = text.char_indices().nth(max_chars).map_or(text.len(), |(i, _)| i);
+    match text[..limit].char_indices().rev().find(|(_, c)| c.is_whitespace()) {
+        Some((i, c)) if i > 0 => i + c.len_utf8(),
+        _ => limit,
+    }
+}
+
+struct Job {
+    pipeline: Pipeline,
+    max_segment_chars: usize,
+}
+
+type Events = mpsc::Sender<Result<Bytes, std::io::Error>>;
+
+/// Sends an event; false once the client has gone.
+async fn emit(events: &mut Events, kind: &str, data: Value) -> bool {
+    let event = SseEvent {
+        event: Some(kind.to_string()),
+        ..SseEvent::data(data.to_string())
+    };
+    events.send(Ok(event.to_bytes())).await.is_ok()
+}
+
+impl Job {
+    /// Translates a document segment by segment, reporting progress; returns
+    /// whether it completed.
+    async fn document(&self, index: usize, document: Document, mut events: Events) -> bool {
+        let id = document.id.unwrap_or_else(|| index.to_string());
+        let segments = segments(&document.text, self.max_segment_chars);
+        let total = segments.len();
+        let started = json!({
+            "id": id,
+            "index": index,
+            "segments": total,
+            "characters": document.text.chars().count(),
+        });
+        if !emit(&mut events, "document_started", started).await {
+            return false;
+        }
+
+        let request_id = self.pipeline.request_id();
+        let mut translation = String::new();
+        let mut tokens = 0;
+        for (n, segment) in segments.iter().enumerate() {
+            // The model is asked for the text alone; whitespace around it is kept as sent.
+            let core = segment.trim();
+            let leading = &segment[..segment.len() - segment.trim_start().len()];
+            let trailing = &segment[segment.trim_end().len()..];
+            if events.is_closed() {
+                return false;
+            }
+            let translated = if core.is_empty() {
+                String::new()
+            } else {
+                let segment_id = match request_id {
+                    Some(request_id) => format!("{}-{}-{}", request_id, index, n),
+                    None => uuid::Uuid::new_v4().to_string(),
+                };
+                match self.pipeline.translate(core, Some(&segment_id)).await {
+                    Ok((text, usage)) => {
+                        tokens += usage["total_tokens"].as_u64().unwrap_or(0);
+                        text
+                    }
+                    Err(failure) => {
+                        let error = format!("{} ({})", failure.message, failure.status.as_u16());
+                        println!("Document {} failed at segment {}: {}", id, n, error);
+                        let failed = json!({
+                            "id": id,
+                            "index": index,
+                            "status": "failed",
+                            "segment": n,
+                            "error": error,
+                        });
+                        emit(&mut events, "document_done", failed).await;
+                        return false;
+                    }
+                }
+            };
+            translation.push_str(leading);
+            translation.push_str(&translated);
+            translation.push_str(trailing);
+            let done = json!({
+                "id": id,
+                "index": index,
+                "segment": n,
+                "segments": total,
+                "translation": translated,
+            });
+            if !emit(&mut events, "segment_done", done).await {
+                return false;
+            }
+        }
+        let done = json!({
+            "id": id,
+            "index": index,
+            "status": "completed",
+            "translation": translation,
+            "total_tokens": tokens,
+        });
+        emit(&mut events, "document_done", done).await;
+        true
+    }
+}
+
+/// `POST /v1/documents/translate`: translates many documents, several at a
+/// time, streaming `document_started`, `segment_done` and `document_done`
+/// events as they progress and a final `done` with the totals.
+pub async fn handle_translate_documents(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    OriginalUri(uri): OriginalUri,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let request = match serde_json::from_slice::<DocumentsRequest>(&body) {
+        Ok(request) => request,
+        Err(e) => {
+            return create_error_response(
+                StatusCode::BAD_REQUEST,
+                "invalid_request_error",
+                &format!("Invalid documents request: {}", e),
+            );
+        }
+    };
+    if request.documents.is_empty() || request.documents.len() > MAX_DOCUMENTS {
+        return create_error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            &format!("Submit between 1 and {} documents", MAX_DOCUMENTS),
+        );
+    }
+
+    let instruction =
+        translation::instruction(&request.target_language, request.source_language.as_deref());
+    let concurrency = request.concurrency.clamp(1, 16);
+    let documents = request.documents;
+    let count = documents.len();
+    println!("Translating {} documents into {}", count, request.target_language);
+    let mut pipeline = Pipeline::new(state, connect_info, uri, headers, instruction);
+    pipeline.model = request.model;
+    pipeline.glossary = request.glossary;
+    let job = Arc::new(Job {
+        pipeline,
+        max_segment_chars: request.max_segment_chars.max(20),
+    });
+
+    let (tx, rx) = mpsc::channel(32);
+    tokio::spawn(async move {
+        let completed = futures::stream::iter(documents.into_iter().enumerate())
+            .map(|(index, document)| {
+                let job = job.clone();
+                let events = tx.clone();
+                async move { job.document(index, document, events).await }
+            })
+            .buffer_unordered(concurrency)
+            .filter(|completed| std::future::ready(*completed))
+            .count()
+            .await;
+        let done = json!({ "documents": count, "completed": completed, "failed": count - completed });
+        emit(&mut tx.clone(), "done", done).await;
+    });
+
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/event-stream")
+        .header(header::CACHE_CONTROL, "no-cache")
+        .body(Body::from_stream(rx))
+        .unwrap()
+}
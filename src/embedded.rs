@@ -0,0 +1,63 @@
+use axum::Router;
+use std::sync::Arc;
+
+use crate::config::AppConfig;
+use crate::snapshot;
+use crate::AppState;
+
+/// The adapter as part of another Axum application, sharing its runtime and
+/// listener instead of running as a separate process.
+///
+/// ```no_run
+/// # async fn run(config: openai_api_proxy::AppConfig) -> Result<(), Box<dyn std::error::Error>> {
+/// use openai_api_proxy::embedded::Embedded;
+///
+/// let adapter = Embedded::new(config).await?;
+/// let app: axum::Router = axum::Router::new().nest("/llm", adapter.router());
+/// let listener = tokio::net::TcpListener::bind("127.0.0.1:8080").await?;
+/// axum::serve(listener, app).await?;
+/// adapter.shutdown().await;
+/// # Ok(())
+/// # }
+/// ```
+///
+/// Client addresses, used by IP allow lists and rate limits, are only known
+/// when the host serves with `into_make_service_with_connect_info`.
+pub struct Embedded {
+    state: Arc<AppState>,
+    router: Router,
+}
+
+impl Embedded {
+    /// Builds the adapter and starts its background jobs, restoring the
+    /// runtime state a previous instance saved.
+    pub async fn new(config: AppConfig) -> Result<Self, Box<dyn std::error::Error>> {
+        let state = Arc::new(AppState::new(config)?);
+        if let Err(e) = snapshot::restore(&state).await {
+            println!("Not restoring runtime state: {}", e);
+        }
+        let router = crate::router(state.clone());
+        Ok(Embedded { state, router })
+    }
+
+    /// The adapter's routes, to `nest` or `merge` into a router of any state
+    /// type; nested ones see their paths without the prefix.
+    pub fn router<S>(&self) -> Router<S>
+    where
+        S: Clone + Send + Sync + 'static,
+    {
+        self.router.clone().with_state(())
+    }
+
+    pub fn state(&self) -> &Arc<AppState> {
+        &self.state
+    }
+
+    /// Saves the runtime state for the next instance, as the standalone
+    /// server does when it stops.
+    pub async fn shutdown(self) {
+        if let Err(e) = snapshot::save(&self.state).await {
+            println!("Failed to save runtime state: {}", e);
+        }
+    }
+}
@@ -0,0 +1,226 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, State},
+    http::{self, header, StatusCode},
+    response::Response,
+};
+use base64::Engine;
+use serde_json::{Map, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::backends::Backend;
+use crate::breaker;
+use crate::create_error_response;
+use crate::limits;
+use crate::policy::{self, Policy};
+use crate::proxy::{self, Admitted};
+use crate::quotas;
+use crate::AppState;
+
+/// The `input` of a request, split into batches of at most `max` inputs.
+/// A single string or token array is never split.
+fn split_batches(input: &Value, max: usize) -> Vec<Value> {
+    match input {
+        Value::Array(items) if items.len() > max && !items.iter().all(Value::is_number) => {
+            items.chunks(max).map(|batch| Value::Array(batch.to_vec())).collect()
+        }
+        input => vec![input.clone()],
+    }
+}
+
+fn input_count(batch: &Value) -> usize {
+    match batch {
+        Value::Array(items) if !items.iter().all(Value::is_number) => items.len(),
+        _ => 1,
+    }
+}
+
+/// Sends one batch and returns its response in OpenAI's format, or the
+/// error response for the client.
+async fn embed(
+    backend: &Backend,
+    key: &str,
+    policy: &Policy,
+    url: &str,
+    model: &str,
+    mut payload: Map<String, Value>,
+) -> Result<Value, Response<Body>> {
+    let invalid = |message: &str| create_error_response(StatusCode::BAD_REQUEST, "invalid_request_error", message);
+    backend
+        .provider
+        .translate_embeddings_request(&mut payload)
+        .map_err(|message| invalid(&message))?;
+    let body = Bytes::from(serde_json::to_vec(&payload).unwrap());
+
+    let mut headers = reqwest::header::HeaderMap::new();
+    headers.insert(reqwest::header::CONTENT_TYPE, "application/json".parse().unwrap());
+    backend.provider.authorize(&mut headers, key);
+    if let Err(retry_after) = backend.breaker.admit() {
+        return Err(breaker::rejection(&backend.name, retry_after));
+    }
+    let sent = policy::send(policy, backend.provider.as_ref(), None, false, |_| {
+        let mut headers = headers.clone();
+        backend.provider.sign("POST", url, &mut headers, &body);
+        backend.client.post(url).headers(headers).body(body.clone())
+    })
+    .await;
+    backend.observe(&sent);
+    let response = sent.map_err(|error| error.into_response())?;
+    let status = response.status();
+    let upstream_headers = response.headers().clone();
+    let bytes = response
+        .bytes()
+        .await
+        .map_err(|e| backend.provider.classify_transport(&e).into_response())?;
+    if !status.is_success() {
+        let error = backend
+            .provider
+            .classify_response(status.as_u16(), &upstream_headers, &bytes);
+        return Err(error.into_response());
+    }
+    let mut embeddings = serde_json::from_slice::<Value>(&bytes).map_err(|e| {
+        create_error_response(StatusCode::BAD_GATEWAY, "Invalid upstream response", &e.to_string())
+    })?;
+    backend.provider.translate_embeddings(&mut embeddings, model);
+    Ok(embeddings)
+}
+
+/// Embeds one text with `model` on the adapter's own behalf, under the
+/// backend's key.
+pub async fn embed_text(state: &AppState, model: &str, text: &str) -> Result<Vec<f32>, String> {
+    let backend = state.backends.for_model(model, None, &state.maintenance).clone();
+    let url = backend
+        .provider
+        .embeddings_url(backend.base_url(), model)
+        .ok_or_else(|| format!("backend {} does not serve embeddings", backend.name))?;
+    let mut policy = state.config.policy.resolve("/v1/embeddings", model);
+    policy.fallback_model = None;
+    policy.screen_limit = state.config.limits.max_response_bytes;
+    let mut payload = Map::new();
+    payload.insert("model".to_string(), Value::String(model.to_string()));
+    payload.insert("input".to_string(), Value::String(text.to_string()));
+    let embeddings = embed(&backend, &backend.key, &policy, &url, model, payload)
+        .await
+        .map_err(|response| format!("embedding request failed with {}", response.status()))?;
+    let vector = embeddings["data"][0]["embedding"]
+        .as_array()
+        .ok_or("the backend returned no embedding")?;
+    Ok(vector.iter().map(|f| f.as_f64().unwrap_or(0.0) as f32).collect())
+}
+
+/// Appends a later batch to the first one's response, shifting its indexes
+/// past the inputs already embedded and adding up usage.
+fn merge(merged: &mut Value, mut batch: Value, offset: usize) {
+    if let (Some(data), Some(entries)) = (merged["data"].as_array_mut(), batch["data"].as_array_mut()) {
+        for mut entry in entries.drain(..) {
+            let index = entry["index"].as_u64().unwrap_or(0) as usize;
+            entry["index"] = Value::from(index + offset);
+            data.push(entry);
+        }
+    }
+    for field in ["prompt_tokens", "total_tokens"] {
+        if let Some(tokens) = batch["usage"][field].as_u64() {
+            let total = merged["usage"][field].as_u64().unwrap_or(0) + tokens;
+            merged["usage"][field] = Value::from(total);
+        }
+    }
+}
+
+/// Replaces float embeddings with base64 of their little-endian `f32`s, as
+/// OpenAI returns them for `encoding_format: "base64"`.
+fn encode_base64(response: &mut Value) {
+    let Some(data) = response["data"].as_array_mut() else {
+        return;
+    };
+    for entry in data {
+        let Some(floats) = entry["embedding"].as_array() else {
+            continue;
+        };
+        let bytes: Vec<u8> = floats
+            .iter()
+            .flat_map(|f| (f.as_f64().unwrap_or(0.0) as f32).to_le_bytes())
+            .collect();
+        entry["embedding"] = Value::String(base64::engine::general_purpose::STANDARD.encode(bytes));
+    }
+}
+
+/// `POST /v1/embeddings`: forwarded to the backend serving `model`, as is
+/// for OpenAI-compatible backends and translated for Cohere and Voyage.
+/// Requests with more inputs than the backend accepts at once are split
+/// into batches that are sent in turn and answered as one list.
+pub async fn handle_embeddings(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: http::HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let Admitted { identity, limit, lease } = match proxy::admit(&state, connect_info, &headers, &body) {
+        Ok(admitted) => admitted,
+        Err(response) => return response,
+    };
+    let invalid = |message: &str| create_error_response(StatusCode::BAD_REQUEST, "invalid_request_error", message);
+    let payload = match serde_json::from_slice::<Map<String, Value>>(&body) {
+        Ok(payload) => payload,
+        Err(e) => return invalid(&format!("Invalid JSON body: {}", e)),
+    };
+    let Some(model) = payload.get("model").and_then(Value::as_str).map(str::to_string) else {
+        return invalid("'model' is required");
+    };
+    let Some(input) = payload.get("input") else {
+        return invalid("'input' is required");
+    };
+
+    if let Err(response) = identity.check_model(&model) {
+        return response;
+    }
+    let backend = state.backends.for_model(&model, Some(&identity.tenant_key()), &state.maintenance).clone();
+    let Some(url) = backend.provider.embeddings_url(backend.base_url(), &model) else {
+        return invalid(&format!(
+            "Backend {} ({}) does not serve embeddings",
+            backend.name,
+            backend.provider.name()
+        ));
+    };
+    let batches = split_batches(input, backend.max_batch());
+    let base64 = payload.get("encoding_format").is_some_and(|f| f == "base64");
+    let mut policy = state.config.policy.resolve("/v1/embeddings", &model);
+    policy.fallback_model = None;
+    policy.screen_limit = state.config.limits.max_response_bytes;
+
+    let key = identity.upstream_key(&backend);
+    let mut merged: Option<Value> = None;
+    let mut offset = 0;
+    for batch in &batches {
+        let mut request = payload.clone();
+        request.insert("input".to_string(), batch.clone());
+        let embeddings = match embed(&backend, key, &policy, &url, &model, request).await {
+            Ok(embeddings) => embeddings,
+            Err(response) => return response,
+        };
+        match merged.as_mut() {
+            Some(merged) => merge(merged, embeddings, offset),
+            None => merged = Some(embeddings),
+        }
+        offset += input_count(batch);
+    }
+    let mut merged = merged.unwrap_or_default();
+    if base64 {
+        encode_base64(&mut merged);
+    }
+    println!(
+        "Embeddings for {}: {} inputs in {} batch(es) to {}",
+        identity.label,
+        offset,
+        batches.len(),
+        backend.name
+    );
+
+    let mut response = Response::builder()
+        .status(StatusCode::OK)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(merged.to_string()))
+        .unwrap();
+    limits::merge_upstream(response.headers_mut(), limit.as_ref(), None);
+    quotas::hold(response, lease)
+}
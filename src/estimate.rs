@@ -0,0 +1,165 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::create_error_response;
+use crate::translation::{self, content_text};
+use crate::AppState;
+
+/// `[[pricing]]` entry: list prices for a group of models.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PricingConfig {
+    /// Model names; a trailing `*` matches by prefix (`"gpt-4o*"`).
+    pub models: Vec<String>,
+    pub input_per_million: f64,
+    pub output_per_million: f64,
+    #[serde(default = "default_currency")]
+    pub currency: String,
+}
+
+fn default_currency() -> String {
+    "USD".to_string()
+}
+
+impl PricingConfig {
+    fn matches(&self, model: &str) -> bool {
+        self.models.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => model.starts_with(prefix),
+            None => model == pattern,
+        })
+    }
+
+    fn cost(&self, prompt_tokens: usize, completion_tokens: usize) -> f64 {
+        (prompt_tokens as f64 * self.input_per_million
+            + completion_tokens as f64 * self.output_per_million)
+            / 1_000_000.0
+    }
+}
+
+/// Tokens per unit of content relative to English, for BPE vocabularies
+/// trained mostly on English text. Rough, but far closer than a flat
+/// characters-per-token rule for non-Latin scripts.
+fn relative_token_cost(language: Option<&str>) -> f64 {
+    match language {
+        None | Some("EN") => 1.0,
+        Some("ES" | "FR" | "DE" | "IT" | "PT" | "NL" | "CA" | "DA" | "NB" | "SV") => 1.3,
+        Some("PL" | "CS" | "SK" | "RO" | "HR" | "SL" | "HU" | "FI" | "ET" | "LV" | "LT" | "TR" | "ID") => 1.6,
+        Some("RU" | "UK" | "BG" | "BE" | "MK" | "SR" | "EL") => 2.0,
+        Some("ZH") => 1.5,
+        Some("JA") => 1.8,
+        Some("KO") => 2.2,
+        Some("AR" | "HE" | "FA") => 2.3,
+        Some("HI" | "BN" | "TA" | "TE" | "TH" | "MR" | "GU" | "KN" | "ML") => 3.5,
+        Some(_) => 1.5,
+    }
+}
+
+/// Approximate characters per token for text in `language`.
+fn chars_per_token(language: Option<&str>) -> f64 {
+    match language {
+        Some("ZH" | "JA") => 1.0,
+        Some("KO") => 1.2,
+        _ => 4.0 / relative_token_cost(language),
+    }
+}
+
+/// `POST /v1/estimate`: token and cost preview for a chat request, without
+/// sending it anywhere. Accepts the chat body plus an optional
+/// `x_target_language` to size the expected translation.
+pub async fn handle_estimate(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let peer = connect_info.map(|ConnectInfo(addr)| addr.ip());
+    if let Err(response) = state.auth.identify(&headers, peer, &body) {
+        return response;
+    }
+    let Ok(payload) = serde_json::from_slice::<Value>(&body) else {
+        return create_error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            "The request body must be a JSON chat completion request",
+        );
+    };
+    Json(estimate(&state, &payload)).into_response()
+}
+
+pub fn estimate(state: &AppState, payload: &Value) -> Value {
+    let model = payload
+        .get("model")
+        .and_then(Value::as_str)
+        .unwrap_or(state.config.default_model.as_str());
+    let route = state.backends.route(model, None, &state.maintenance);
+    let model = route.model.as_str();
+    let messages = payload
+        .get("messages")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or(&[]);
+
+    let source: String = messages
+        .iter()
+        .filter(|m| m.get("role").and_then(Value::as_str) == Some("user"))
+        .filter_map(|m| m.get("content"))
+        .map(content_text)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let language = translation::detect_language(&source);
+    let target_language = payload
+        .get("x_target_language")
+        .and_then(Value::as_str)
+        .map(str::to_uppercase);
+
+    let tokenizer = state.tokenizers.for_model(model);
+    let mut prompt_tokens = state.tokenizers.count_messages(model, messages);
+    let source_tokens = if tokenizer.name() == "approximate" {
+        // The character estimate assumes English; rescale for the detected script.
+        let scaled = source.chars().count() as f64 / chars_per_token(language.as_deref());
+        let counted = tokenizer.count(&source) as f64;
+        prompt_tokens = (prompt_tokens as f64 + scaled - counted).max(0.0).round() as usize;
+        scaled
+    } else {
+        tokenizer.count(&source) as f64
+    };
+    let expected_completion_tokens = (source_tokens
+        * relative_token_cost(target_language.as_deref().or(language.as_deref()))
+        / relative_token_cost(language.as_deref()))
+    .ceil() as usize;
+    let max_completion_tokens = payload
+        .get("max_completion_tokens")
+        .or_else(|| payload.get("max_tokens"))
+        .and_then(Value::as_u64);
+
+    let pricing = state.config.pricing.iter().find(|p| p.matches(model));
+    let backends = vec![json!({
+        "backend": route.backend.name,
+        "model": model,
+        "cost": pricing.map(|p| json!({
+            "currency": p.currency,
+            "prompt": p.cost(prompt_tokens, 0),
+            "expected_total": p.cost(prompt_tokens, expected_completion_tokens),
+            "max_total": max_completion_tokens.map(|max| p.cost(prompt_tokens, max as usize)),
+        })),
+    })];
+
+    json!({
+        "model": model,
+        "tokenizer": tokenizer.name(),
+        "language": language,
+        "target_language": target_language,
+        "prompt_tokens": prompt_tokens,
+        "expected_completion_tokens": expected_completion_tokens,
+        "max_completion_tokens": max_completion_tokens,
+        "backends": backends,
+    })
+}
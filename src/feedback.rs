@@ -0,0 +1,228 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use rusqlite::params;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::task::JoinHandle;
+
+use crate::create_error_response;
+use crate::db::Database;
+use crate::AppState;
+
+const MAX_COMMENT_CHARS: usize = 2000;
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+/// What is stored about each chat request so feedback can be attributed.
+pub struct RequestRecord {
+    /// As sent by the client in `x-request-id`, or generated.
+    pub request_id: String,
+    pub tenant: String,
+    pub key: String,
+    pub model: String,
+    pub backend: String,
+    pub template: Option<(String, i64)>,
+}
+
+/// Request metadata and the quality feedback reported against it.
+pub struct FeedbackStore {
+    db: Arc<Database>,
+}
+
+impl FeedbackStore {
+    pub fn new(db: Arc<Database>) -> Self {
+        FeedbackStore { db }
+    }
+
+    /// Stores `record` under an id of its own, off the async runtime.
+    /// Awaiting the handle before answering lets feedback find the request.
+    pub fn record_request(&self, record: RequestRecord) -> JoinHandle<()> {
+        let db = self.db.clone();
+        tokio::task::spawn_blocking(move || {
+            let result = db.conn().execute(
+                "INSERT INTO requests
+                     (id, request_id, tenant, key_label, model, backend, template, template_version, created_at)
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9)",
+                params![
+                    uuid::Uuid::new_v4().to_string(),
+                    record.request_id,
+                    record.tenant,
+                    record.key,
+                    record.model,
+                    record.backend,
+                    record.template.as_ref().map(|t| &t.0),
+                    record.template.as_ref().map(|t| t.1),
+                    now()
+                ],
+            );
+            if let Err(e) = result {
+                println!("Failed to record request {}: {}", record.request_id, e);
+            }
+        })
+    }
+
+    /// Adds a score in `[0, 1]` for one of the tenant's requests, the latest
+    /// if the client reused its id; false if the request is unknown or
+    /// belongs to someone else.
+    pub fn add(&self, request_id: &str, tenant: &str, score: f64, comment: Option<&str>) -> Result<bool, String> {
+        self.db
+            .conn()
+            .execute(
+                "INSERT INTO feedback (request, score, comment, created_at)
+                 SELECT id, ?3, ?4, ?5 FROM requests WHERE request_id = ?1 AND tenant = ?2
+                 ORDER BY rowid DESC LIMIT 1",
+                params![request_id, tenant, score, comment, now()],
+            )
+            .map(|inserted| inserted > 0)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Mean score and counts per model and backend.
+    pub fn by_model(&self) -> Result<Vec<Value>, String> {
+        let conn = self.db.conn();
+        let mut statement = conn
+            .prepare(
+                "SELECT r.model, r.backend, COUNT(f.score), AVG(f.score)
+                 FROM feedback f JOIN requests r ON r.id = f.request
+                 GROUP BY r.model, r.backend ORDER BY r.model, r.backend",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok(json!({
+                    "model": row.get::<_, String>(0)?,
+                    "backend": row.get::<_, Option<String>>(1)?,
+                    "feedback": row.get::<_, i64>(2)?,
+                    "mean_score": row.get::<_, Option<f64>>(3)?,
+                }))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+
+    /// The most recent feedback with the metadata of the request it concerns.
+    pub fn recent(&self, limit: u32) -> Result<Vec<Value>, String> {
+        let conn = self.db.conn();
+        let mut statement = conn
+            .prepare(
+                "SELECT r.request_id, f.score, f.comment, f.created_at, r.tenant, r.key_label, r.model,
+                        r.backend, r.template, r.template_version, r.created_at
+                 FROM feedback f JOIN requests r ON r.id = f.request
+                 ORDER BY f.rowid DESC LIMIT ?1",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params![limit], |row| {
+                Ok(json!({
+                    "request_id": row.get::<_, String>(0)?,
+                    "score": row.get::<_, f64>(1)?,
+                    "comment": row.get::<_, Option<String>>(2)?,
+                    "created_at": row.get::<_, i64>(3)?,
+                    "request": {
+                        "tenant": row.get::<_, String>(4)?,
+                        "key": row.get::<_, Option<String>>(5)?,
+                        "model": row.get::<_, String>(6)?,
+                        "backend": row.get::<_, Option<String>>(7)?,
+                        "template": row.get::<_, Option<String>>(8)?,
+                        "template_version": row.get::<_, Option<i64>>(9)?,
+                        "created_at": row.get::<_, i64>(10)?,
+                    },
+                }))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+
+    /// Forgets requests older than `days` and their feedback; returns how many requests.
+    pub fn purge_older_than(&self, days: u64) -> Result<usize, String> {
+        let cutoff = now() - (days * 86_400) as i64;
+        let conn = self.db.conn();
+        conn.execute(
+            "DELETE FROM feedback WHERE request IN (SELECT id FROM requests WHERE created_at < ?1)",
+            params![cutoff],
+        )
+        .map_err(|e| e.to_string())?;
+        conn.execute("DELETE FROM requests WHERE created_at < ?1", params![cutoff])
+            .map_err(|e| e.to_string())
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum Rating {
+    /// 1 to 5 stars.
+    Stars(u8),
+    /// `"up"` or `"down"`.
+    Thumb(String),
+}
+
+#[derive(Deserialize)]
+struct Feedback {
+    request_id: String,
+    /// Quality estimate in `[0, 1]`, e.g. from a QE model.
+    #[serde(default)]
+    score: Option<f64>,
+    #[serde(default)]
+    rating: Option<Rating>,
+    #[serde(default)]
+    comment: Option<String>,
+}
+
+/// A single score in `[0, 1]` from whichever signal the client sent.
+fn normalized_score(feedback: &Feedback) -> Option<f64> {
+    match (feedback.score, &feedback.rating) {
+        (Some(score), None) => (0.0..=1.0).contains(&score).then_some(score),
+        (None, Some(Rating::Stars(stars @ 1..=5))) => Some(f64::from(stars - 1) / 4.0),
+        (None, Some(Rating::Thumb(thumb))) if thumb == "up" => Some(1.0),
+        (None, Some(Rating::Thumb(thumb))) if thumb == "down" => Some(0.0),
+        _ => None,
+    }
+}
+
+/// `POST /v1/feedback`: a quality signal for an earlier request.
+pub async fn handle_feedback(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let peer = connect_info.map(|ConnectInfo(addr)| addr.ip());
+    let identity = match state.auth.identify(&headers, peer, &body) {
+        Ok(identity) => identity,
+        Err(response) => return response,
+    };
+    let invalid = |message: &str| create_error_response(StatusCode::BAD_REQUEST, "invalid_request_error", message);
+    let Ok(feedback) = serde_json::from_slice::<Feedback>(&body) else {
+        return invalid("Expected a JSON object with `request_id` and `score` or `rating`");
+    };
+    let Some(score) = normalized_score(&feedback) else {
+        return invalid("Give either `score` between 0 and 1, or `rating` of 1 to 5 or \"up\" or \"down\"");
+    };
+    if feedback.comment.as_ref().is_some_and(|c| c.chars().count() > MAX_COMMENT_CHARS) {
+        return invalid(&format!("Comments are limited to {} characters", MAX_COMMENT_CHARS));
+    }
+
+    let tenant = identity.tenant_key();
+    match state.feedback.add(&feedback.request_id, &tenant, score, feedback.comment.as_deref()) {
+        Ok(true) => (StatusCode::CREATED, Json(json!({ "request_id": feedback.request_id, "score": score }))).into_response(),
+        Ok(false) => create_error_response(
+            StatusCode::NOT_FOUND,
+            "request_not_found",
+            &format!("No request {} to attach feedback to", feedback.request_id),
+        ),
+        Err(e) => create_error_response(StatusCode::INTERNAL_SERVER_ERROR, "storage_error", &e),
+    }
+}
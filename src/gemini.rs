@@ -0,0 +1,428 @@
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::provider::{self, Provider, ProviderError, StreamTranslator};
+use crate::sse::{SseEvent, StreamFormat};
+use crate::translation::content_text;
+
+/// Google Gemini's `generateContent` and `streamGenerateContent`.
+pub struct Gemini;
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+/// OpenAI content as Gemini `parts`.
+fn parts(content: &Value) -> Vec<Value> {
+    match content {
+        Value::String(text) if text.is_empty() => Vec::new(),
+        Value::String(text) => vec![json!({ "text": text })],
+        Value::Array(items) => items.iter().filter_map(part).collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn part(item: &Value) -> Option<Value> {
+    match item.get("type").and_then(Value::as_str)? {
+        "text" => Some(json!({ "text": item.get("text").and_then(Value::as_str)? })),
+        "image_url" => {
+            let url = item.get("image_url").and_then(|i| i.get("url")).and_then(Value::as_str)?;
+            match url.strip_prefix("data:").and_then(|rest| rest.split_once(";base64,")) {
+                Some((mime_type, data)) => Some(json!({ "inlineData": { "mimeType": mime_type, "data": data } })),
+                None => Some(json!({ "fileData": { "mimeType": image_mime_type(url), "fileUri": url } })),
+            }
+        }
+        _ => None,
+    }
+}
+
+fn image_mime_type(url: &str) -> &'static str {
+    let path = url.split(['?', '#']).next().unwrap_or(url).to_ascii_lowercase();
+    match path.rsplit('.').next() {
+        Some("png") => "image/png",
+        Some("gif") => "image/gif",
+        Some("webp") => "image/webp",
+        _ => "image/jpeg",
+    }
+}
+
+/// Appends a turn, merging consecutive turns of the same role.
+fn push_turn(contents: &mut Vec<Value>, role: &str, mut parts: Vec<Value>) {
+    if parts.is_empty() {
+        return;
+    }
+    if let Some(last) = contents.last_mut().filter(|c| c["role"] == role) {
+        if let Some(existing) = last["parts"].as_array_mut() {
+            existing.append(&mut parts);
+            return;
+        }
+    }
+    contents.push(json!({ "role": role, "parts": parts }));
+}
+
+fn finish_reason(reason: &str, called_tools: bool) -> &'static str {
+    match reason {
+        "MAX_TOKENS" => "length",
+        "SAFETY" | "RECITATION" | "BLOCKLIST" | "PROHIBITED_CONTENT" | "SPII" | "IMAGE_SAFETY" => "content_filter",
+        _ if called_tools => "tool_calls",
+        _ => "stop",
+    }
+}
+
+fn openai_usage(usage: &Value) -> Option<Value> {
+    let prompt_tokens = usage.get("promptTokenCount")?.as_u64()?;
+    let completion_tokens = usage.get("candidatesTokenCount").and_then(Value::as_u64).unwrap_or(0);
+    let total_tokens = usage
+        .get("totalTokenCount")
+        .and_then(Value::as_u64)
+        .unwrap_or(prompt_tokens + completion_tokens);
+    Some(json!({
+        "prompt_tokens": prompt_tokens,
+        "completion_tokens": completion_tokens,
+        "total_tokens": total_tokens,
+    }))
+}
+
+/// The visible text and function calls of a candidate; thoughts are skipped.
+fn candidate_output(candidate: &Value) -> (String, Vec<&Value>) {
+    let parts = candidate["content"]["parts"].as_array().map(Vec::as_slice).unwrap_or(&[]);
+    let text = parts
+        .iter()
+        .filter(|p| p.get("thought").and_then(Value::as_bool) != Some(true))
+        .filter_map(|p| p.get("text").and_then(Value::as_str))
+        .collect();
+    let calls = parts.iter().filter_map(|p| p.get("functionCall")).collect();
+    (text, calls)
+}
+
+fn tool_call(call: &Value, id: String) -> Value {
+    json!({
+        "id": call.get("id").and_then(Value::as_str).map(str::to_string).unwrap_or(id),
+        "type": "function",
+        "function": {
+            "name": call["name"],
+            "arguments": call.get("args").cloned().unwrap_or_else(|| json!({})).to_string(),
+        },
+    })
+}
+
+impl Provider for Gemini {
+    fn name(&self) -> &'static str {
+        "gemini"
+    }
+
+    fn classify_response(
+        &self,
+        status: u16,
+        headers: &reqwest::header::HeaderMap,
+        body: &[u8],
+    ) -> ProviderError {
+        let mut error = provider::classify(status, headers, body);
+        // Gemini's `code` is the HTTP status; `status` names the error.
+        let named = serde_json::from_slice::<Value>(body)
+            .ok()
+            .and_then(|v| v["error"]["status"].as_str().map(str::to_ascii_lowercase));
+        if let Some(named) = named {
+            error.error_type = named;
+        }
+        error
+    }
+
+    fn chat_url(&self, url: &str, model: &str, stream: bool) -> String {
+        let model = model.strip_prefix("models/").unwrap_or(model);
+        let method = if stream { "streamGenerateContent" } else { "generateContent" };
+        format!("{}/models/{}:{}", url.trim_end_matches('/'), model, method)
+    }
+
+    fn stream_format(&self) -> StreamFormat {
+        StreamFormat::JsonArray
+    }
+
+    fn authorize(&self, headers: &mut reqwest::header::HeaderMap, key: &str) {
+        headers.remove(reqwest::header::AUTHORIZATION);
+        headers.insert("x-goog-api-key", key.parse().unwrap());
+    }
+
+    fn translate_request(&self, payload: &mut Map<String, Value>) -> Result<bool, String> {
+        let Some(Value::Array(messages)) = payload.get("messages") else {
+            return Err("messages must be an array".to_string());
+        };
+
+        let mut system = Vec::new();
+        let mut contents = Vec::new();
+        let mut call_names = HashMap::new();
+        for message in messages {
+            let content = message.get("content").unwrap_or(&Value::Null);
+            match message.get("role").and_then(Value::as_str).unwrap_or("user") {
+                "system" | "developer" => system.push(content_text(content)),
+                "assistant" => {
+                    let mut turn = parts(content);
+                    for call in message.get("tool_calls").and_then(Value::as_array).into_iter().flatten() {
+                        let function = &call["function"];
+                        if let Some(id) = call["id"].as_str() {
+                            call_names.insert(id.to_string(), function["name"].clone());
+                        }
+                        let args = function["arguments"]
+                            .as_str()
+                            .and_then(|a| serde_json::from_str::<Value>(a).ok())
+                            .filter(Value::is_object)
+                            .unwrap_or_else(|| json!({}));
+                        turn.push(json!({ "functionCall": { "name": function["name"], "args": args } }));
+                    }
+                    push_turn(&mut contents, "model", turn);
+                }
+                "tool" => {
+                    let id = message["tool_call_id"].as_str().unwrap_or_default();
+                    let text = content_text(content);
+                    let response = serde_json::from_str::<Value>(&text)
+                        .ok()
+                        .filter(Value::is_object)
+                        .unwrap_or_else(|| json!({ "content": text }));
+                    let name = call_names.get(id).cloned().unwrap_or_else(|| json!(id));
+                    push_turn(
+                        &mut contents,
+                        "user",
+                        vec![json!({ "functionResponse": { "name": name, "response": response } })],
+                    );
+                }
+                _ => push_turn(&mut contents, "user", parts(content)),
+            }
+        }
+
+        let mut config = Map::new();
+        let renames = [
+            ("temperature", "temperature"),
+            ("top_p", "topP"),
+            ("n", "candidateCount"),
+            ("presence_penalty", "presencePenalty"),
+            ("frequency_penalty", "frequencyPenalty"),
+            ("seed", "seed"),
+        ];
+        for (from, to) in renames {
+            if let Some(value) = payload.get(from) {
+                config.insert(to.to_string(), value.clone());
+            }
+        }
+        if let Some(max) = payload.get("max_completion_tokens").or_else(|| payload.get("max_tokens")) {
+            config.insert("maxOutputTokens".to_string(), max.clone());
+        }
+        match payload.get("stop") {
+            Some(Value::String(stop)) => {
+                config.insert("stopSequences".to_string(), json!([stop]));
+            }
+            Some(Value::Array(stops)) => {
+                config.insert("stopSequences".to_string(), Value::Array(stops.clone()));
+            }
+            _ => {}
+        }
+        let format = payload.get("response_format");
+        match format.and_then(|f| f["type"].as_str()) {
+            Some("json_object") => {
+                config.insert("responseMimeType".to_string(), json!("application/json"));
+            }
+            Some("json_schema") => {
+                config.insert("responseMimeType".to_string(), json!("application/json"));
+                if let Some(schema) = format.and_then(|f| f["json_schema"].get("schema")) {
+                    config.insert("responseSchema".to_string(), schema.clone());
+                }
+            }
+            _ => {}
+        }
+
+        let mut request = Map::new();
+        request.insert("contents".to_string(), Value::Array(contents));
+        system.retain(|s| !s.is_empty());
+        if !system.is_empty() {
+            request.insert(
+                "systemInstruction".to_string(),
+                json!({ "parts": [{ "text": system.join("\n\n") }] }),
+            );
+        }
+        if !config.is_empty() {
+            request.insert("generationConfig".to_string(), Value::Object(config));
+        }
+        if let Some(Value::Array(tools)) = payload.get("tools") {
+            let declarations: Vec<Value> = tools
+                .iter()
+                .map(|tool| {
+                    let function = tool.get("function").unwrap_or(tool);
+                    let mut declaration = json!({ "name": function["name"] });
+                    for key in ["description", "parameters"] {
+                        if let Some(value) = function.get(key) {
+                            declaration[key] = value.clone();
+                        }
+                    }
+                    declaration
+                })
+                .collect();
+            if !declarations.is_empty() {
+                request.insert("tools".to_string(), json!([{ "functionDeclarations": declarations }]));
+            }
+        }
+        let calling = match payload.get("tool_choice") {
+            Some(Value::String(choice)) if choice == "none" => Some(json!({ "mode": "NONE" })),
+            Some(Value::String(choice)) if choice == "required" => Some(json!({ "mode": "ANY" })),
+            Some(Value::Object(choice)) => choice
+                .get("function")
+                .and_then(|f| f.get("name"))
+                .map(|name| json!({ "mode": "ANY", "allowedFunctionNames": [name] })),
+            _ => None,
+        };
+        if let Some(calling) = calling {
+            request.insert("toolConfig".to_string(), json!({ "functionCallingConfig": calling }));
+        }
+
+        *payload = request;
+        Ok(true)
+    }
+
+    fn translate_completion(&self, completion: &mut Value, _model: &str) -> bool {
+        if completion.get("candidates").is_none() && completion.get("promptFeedback").is_none() {
+            return false;
+        }
+        let choices: Vec<Value> = completion["candidates"]
+            .as_array()
+            .map(Vec::as_slice)
+            .unwrap_or(&[])
+            .iter()
+            .enumerate()
+            .map(|(position, candidate)| {
+                let (text, calls) = candidate_output(candidate);
+                let index = candidate.get("index").and_then(Value::as_u64).unwrap_or(position as u64);
+                let mut message = json!({
+                    "role": "assistant",
+                    "content": if text.is_empty() && !calls.is_empty() { Value::Null } else { json!(text) },
+                });
+                if !calls.is_empty() {
+                    let calls: Vec<Value> = calls
+                        .iter()
+                        .enumerate()
+                        .map(|(i, call)| tool_call(call, format!("call_{}_{}", index, i)))
+                        .collect();
+                    message["tool_calls"] = Value::Array(calls);
+                }
+                let reason = candidate["finishReason"].as_str().unwrap_or("STOP");
+                json!({
+                    "index": index,
+                    "message": message,
+                    "finish_reason": finish_reason(reason, message.get("tool_calls").is_some()),
+                })
+            })
+            .collect();
+        // A prompt blocked outright comes back without candidates.
+        let choices = if choices.is_empty() {
+            vec![json!({
+                "index": 0,
+                "message": { "role": "assistant", "content": "" },
+                "finish_reason": "content_filter",
+            })]
+        } else {
+            choices
+        };
+
+        let mut translated = json!({
+            "id": completion
+                .get("responseId")
+                .and_then(Value::as_str)
+                .map(|id| format!("chatcmpl-{}", id))
+                .unwrap_or_else(|| format!("chatcmpl-{}", uuid::Uuid::new_v4().simple())),
+            "object": "chat.completion",
+            "created": now(),
+            "model": completion.get("modelVersion").cloned().unwrap_or(Value::Null),
+            "choices": choices,
+        });
+        if let Some(usage) = openai_usage(&completion["usageMetadata"]) {
+            translated["usage"] = usage;
+        }
+        *completion = translated;
+        true
+    }
+
+    fn stream_translator(&self, _model: &str) -> Option<Box<dyn StreamTranslator>> {
+        Some(Box::new(GeminiStream {
+            id: format!("chatcmpl-{}", uuid::Uuid::new_v4().simple()),
+            created: now(),
+            started: HashMap::new(),
+        }))
+    }
+}
+
+/// Turns each streamed `GenerateContentResponse` into one chunk.
+struct GeminiStream {
+    id: String,
+    created: u64,
+    /// Tool calls sent so far, per candidate that has started.
+    started: HashMap<u64, usize>,
+}
+
+impl StreamTranslator for GeminiStream {
+    fn translate(&mut self, event: SseEvent) -> Vec<SseEvent> {
+        let Ok(response) = serde_json::from_str::<Value>(&event.data) else {
+            return vec![event];
+        };
+        if response.get("error").is_some() {
+            return vec![SseEvent::data(json!({ "error": response["error"] }).to_string())];
+        }
+
+        let mut choices = Vec::new();
+        for (position, candidate) in response["candidates"].as_array().into_iter().flatten().enumerate() {
+            let index = candidate.get("index").and_then(Value::as_u64).unwrap_or(position as u64);
+            let first = !self.started.contains_key(&index);
+            let sent_calls = self.started.entry(index).or_insert(0);
+            let (text, calls) = candidate_output(candidate);
+
+            let mut delta = Map::new();
+            if first {
+                delta.insert("role".to_string(), json!("assistant"));
+            }
+            if !text.is_empty() || first {
+                delta.insert("content".to_string(), json!(text));
+            }
+            if !calls.is_empty() {
+                let calls: Vec<Value> = calls
+                    .iter()
+                    .map(|call| {
+                        let mut call = tool_call(call, format!("call_{}_{}", index, sent_calls));
+                        call["index"] = json!(*sent_calls);
+                        *sent_calls += 1;
+                        call
+                    })
+                    .collect();
+                delta.insert("tool_calls".to_string(), Value::Array(calls));
+            }
+            let finish = candidate["finishReason"]
+                .as_str()
+                .map(|reason| finish_reason(reason, *sent_calls > 0));
+            choices.push(json!({ "index": index, "delta": delta, "finish_reason": finish }));
+        }
+
+        let mut chunk = json!({
+            "id": self.id,
+            "object": "chat.completion.chunk",
+            "created": self.created,
+            "model": response.get("modelVersion").cloned().unwrap_or(Value::Null),
+            "choices": choices,
+        });
+        if choices_finished(&chunk) {
+            if let Some(usage) = openai_usage(&response["usageMetadata"]) {
+                chunk["usage"] = usage;
+            }
+        }
+        vec![SseEvent::data(chunk.to_string())]
+    }
+
+    /// Gemini has no end-of-stream marker.
+    fn finish(&mut self) -> Vec<SseEvent> {
+        vec![SseEvent::data("[DONE]")]
+    }
+}
+
+fn choices_finished(chunk: &Value) -> bool {
+    chunk["choices"]
+        .as_array()
+        .is_some_and(|choices| choices.iter().any(|c| !c["finish_reason"].is_null()))
+}
@@ -0,0 +1,174 @@
+use axum::http::{HeaderMap, HeaderValue};
+use rusqlite::params;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::db::Database;
+use crate::translation::content_text;
+
+/// Tenant whose terms apply to every tenant.
+pub const ALL_TENANTS: &str = "*";
+
+/// `[translation.glossary]`: terminology every tenant's translations keep.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct GlossaryConfig {
+    /// Terms by tenant, source term to required translation; `"*"` applies
+    /// to every tenant. Terms stored through `/admin/glossaries` take
+    /// precedence.
+    #[serde(default)]
+    pub tenants: HashMap<String, BTreeMap<String, String>>,
+    /// Check non-streamed answers for the required translations and report
+    /// which were honored.
+    #[serde(default)]
+    pub validate: bool,
+}
+
+/// Which glossary terms an answer kept.
+#[derive(Debug, Serialize, Clone, Default, PartialEq, Eq)]
+pub struct GlossaryReport {
+    pub honored: Vec<String>,
+    pub missed: Vec<String>,
+}
+
+impl GlossaryReport {
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        let summary = format!("honored={}; missed={}", self.honored.len(), self.missed.len());
+        if let Ok(value) = HeaderValue::from_str(&summary) {
+            headers.insert("x-llmta-glossary", value);
+        }
+    }
+}
+
+/// Per-tenant glossaries from the configuration and the database.
+pub struct GlossaryStore {
+    config: GlossaryConfig,
+    db: Arc<Database>,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+impl GlossaryStore {
+    pub fn new(config: GlossaryConfig, db: Arc<Database>) -> Self {
+        GlossaryStore { config, db }
+    }
+
+    /// Terms stored for exactly `tenant`.
+    pub fn stored(&self, tenant: &str) -> Result<BTreeMap<String, String>, String> {
+        let conn = self.db.conn();
+        let mut statement = conn
+            .prepare("SELECT source, target FROM glossary_terms WHERE tenant = ?1")
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params![tenant], |row| Ok((row.get(0)?, row.get(1)?)))
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+
+    /// Replaces the terms stored for `tenant`.
+    pub fn replace(&self, tenant: &str, terms: &BTreeMap<String, String>) -> Result<(), String> {
+        let mut conn = self.db.conn();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        tx.execute("DELETE FROM glossary_terms WHERE tenant = ?1", params![tenant])
+            .map_err(|e| e.to_string())?;
+        for (source, target) in terms {
+            tx.execute(
+                "INSERT INTO glossary_terms (tenant, source, target, updated_at) VALUES (?1, ?2, ?3, ?4)",
+                params![tenant, source, target, now()],
+            )
+            .map_err(|e| e.to_string())?;
+        }
+        tx.commit().map_err(|e| e.to_string())
+    }
+
+    /// Forgets the terms stored for `tenant`; false if there were none.
+    pub fn delete(&self, tenant: &str) -> Result<bool, String> {
+        self.db
+            .conn()
+            .execute("DELETE FROM glossary_terms WHERE tenant = ?1", params![tenant])
+            .map(|deleted| deleted > 0)
+            .map_err(|e| e.to_string())
+    }
+
+    /// Every term that applies to `tenant`: configured, then stored, shared
+    /// terms first so the tenant's own override them.
+    pub fn terms(&self, tenant: &str) -> Result<BTreeMap<String, String>, String> {
+        let mut terms = BTreeMap::new();
+        for scope in [ALL_TENANTS, tenant] {
+            if let Some(configured) = self.config.tenants.get(scope) {
+                terms.extend(configured.clone());
+            }
+            terms.extend(self.stored(scope)?);
+        }
+        Ok(terms)
+    }
+
+    /// The tenant's terms occurring in the request's user messages, the only
+    /// ones worth sending and checking.
+    pub fn relevant(&self, tenant: &str, payload: &Map<String, Value>) -> Result<Vec<(String, String)>, String> {
+        let source = user_text(payload).to_lowercase();
+        Ok(self
+            .terms(tenant)?
+            .into_iter()
+            .filter(|(term, _)| !term.is_empty() && source.contains(&term.to_lowercase()))
+            .collect())
+    }
+
+    pub fn validates(&self) -> bool {
+        self.config.validate
+    }
+}
+
+fn user_text(payload: &Map<String, Value>) -> String {
+    payload
+        .get("messages")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|m| m["role"] == "user")
+        .map(|m| content_text(&m["content"]))
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Merges stored terms into an inline `x_glossary`, whose entries win.
+pub fn merge(terms: &[(String, String)], inline: Option<Value>) -> Result<Map<String, Value>, String> {
+    let mut merged: Map<String, Value> = terms
+        .iter()
+        .map(|(source, target)| (source.clone(), json!(target)))
+        .collect();
+    match inline {
+        None => {}
+        Some(Value::Object(inline)) => merged.extend(inline),
+        Some(_) => return Err("x_glossary must be an object mapping terms to translations".to_string()),
+    }
+    Ok(merged)
+}
+
+/// Checks that a completion uses the required translation of each term.
+pub fn check(completion: &Value, terms: &[(String, String)]) -> GlossaryReport {
+    let answer = completion["choices"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .map(|choice| content_text(&choice["message"]["content"]))
+        .collect::<Vec<_>>()
+        .join("\n")
+        .to_lowercase();
+    let mut report = GlossaryReport::default();
+    for (source, target) in terms {
+        if answer.contains(&target.to_lowercase()) {
+            report.honored.push(source.clone());
+        } else {
+            report.missed.push(source.clone());
+        }
+    }
+    report
+}
@@ -0,0 +1,133 @@
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+use crate::backends::Backend;
+
+/// Bounds how many requests to one backend may be setting up a connection,
+/// DNS lookup through TLS handshake, at once. reqwest hides its pool, so
+/// pooled connections are estimated: each request that got an answer is
+/// assumed to leave its connection idle for the pool's idle timeout, and a
+/// request finding such a connection skips the limit.
+pub struct Handshakes {
+    permits: Option<Arc<Semaphore>>,
+    /// When connections became idle, oldest first.
+    idle: Mutex<VecDeque<Instant>>,
+    idle_timeout: Duration,
+    max_idle: usize,
+    /// Connections claimed and not yet dropped.
+    active: AtomicUsize,
+}
+
+/// A request's claim on a connection. Holds a handshake slot until
+/// `established`, and counts as an idle connection once dropped after it.
+pub struct Connection {
+    handshakes: Arc<Handshakes>,
+    permit: Option<OwnedSemaphorePermit>,
+    established: bool,
+}
+
+impl Handshakes {
+    pub fn new(max: Option<usize>, idle_timeout: Duration, max_idle: Option<usize>) -> Self {
+        Handshakes {
+            permits: max.map(|max| Arc::new(Semaphore::new(max.max(1)))),
+            idle: Mutex::new(VecDeque::new()),
+            idle_timeout,
+            max_idle: max_idle.unwrap_or(usize::MAX),
+            active: AtomicUsize::new(0),
+        }
+    }
+
+    /// Takes an idle connection if one is likely pooled, else waits for a
+    /// handshake slot.
+    pub async fn connect(self: &Arc<Self>) -> Connection {
+        self.active.fetch_add(1, Ordering::Relaxed);
+        let mut connection = Connection {
+            handshakes: self.clone(),
+            permit: None,
+            established: false,
+        };
+        let Some(permits) = &self.permits else {
+            return connection;
+        };
+        if self.take_idle() {
+            return connection;
+        }
+        connection.permit = permits.clone().acquire_owned().await.ok();
+        connection
+    }
+
+    fn take_idle(&self) -> bool {
+        let mut idle = self.idle.lock().unwrap();
+        while idle.front().is_some_and(|since| since.elapsed() >= self.idle_timeout) {
+            idle.pop_front();
+        }
+        idle.pop_back().is_some()
+    }
+
+    fn release(&self) {
+        if self.permits.is_none() {
+            return;
+        }
+        let mut idle = self.idle.lock().unwrap();
+        if idle.len() >= self.max_idle {
+            idle.pop_front();
+        }
+        idle.push_back(Instant::now());
+    }
+
+    /// Requests holding a connection, streams included.
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Free handshake slots; `None` when unlimited.
+    pub fn available(&self) -> Option<usize> {
+        self.permits.as_ref().map(|permits| permits.available_permits())
+    }
+}
+
+impl Connection {
+    /// The backend answered, so the connection is up; frees the slot.
+    pub fn established(&mut self) {
+        self.permit = None;
+        self.established = true;
+    }
+}
+
+impl Drop for Connection {
+    fn drop(&mut self) {
+        self.handshakes.active.fetch_sub(1, Ordering::Relaxed);
+        if self.established {
+            self.handshakes.release();
+        }
+    }
+}
+
+/// Opens `count` connections to a backend ahead of traffic, through the
+/// handshake limit, by listing its models.
+pub fn prewarm(backend: Arc<Backend>, count: usize) {
+    if count == 0 {
+        return;
+    }
+    println!("Pre-warming {} connections to backend {}", count, backend.name);
+    for _ in 0..count {
+        let backend = backend.clone();
+        tokio::spawn(async move {
+            let mut connection = backend.handshakes.connect().await;
+            let mut headers = reqwest::header::HeaderMap::new();
+            backend.provider.authorize(&mut headers, &backend.key);
+            let url = format!("{}/models", backend.base_url());
+            match backend.client.get(url).headers(headers).send().await {
+                Ok(response) => {
+                    connection.established();
+                    // The connection returns to the pool once the body is read.
+                    let _ = response.bytes().await;
+                }
+                Err(e) => println!("Failed to pre-warm backend {}: {}", backend.name, e),
+            }
+        });
+    }
+}
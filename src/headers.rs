@@ -0,0 +1,97 @@
+use axum::http::{self, response::Builder};
+use serde::Deserialize;
+
+/// Hop-by-hop and framing headers; the adapter sets its own.
+const HOP_BY_HOP: &[&str] = &["transfer-encoding", "connection", "content-length", "keep-alive"];
+
+/// Passed through regardless of allow/deny lists so clients keep their
+/// content type and rate-limit feedback.
+const ALWAYS_KEPT: &[&str] = &[
+    "content-type",
+    "retry-after",
+    "x-ratelimit-*",
+    "ratelimit-*",
+    "anthropic-ratelimit-*",
+];
+
+/// Provider internals stripped unless `default_deny = false`.
+const DEFAULT_DENY: &[&str] = &[
+    "set-cookie",
+    "via",
+    "server",
+    "x-powered-by",
+    "x-amzn-*",
+    "x-amz-*",
+    "openai-organization",
+    "openai-project",
+    "cf-ray",
+    "x-envoy-*",
+];
+
+/// Which upstream response headers reach clients. Patterns are lower-case
+/// header names; a trailing `*` matches by prefix.
+#[derive(Debug, Deserialize, Clone)]
+pub struct HeaderConfig {
+    /// When non-empty, only these headers (plus content type and rate-limit
+    /// headers) are passed through.
+    #[serde(default)]
+    pub allow: Vec<String>,
+    /// Headers stripped in addition to the defaults.
+    #[serde(default)]
+    pub deny: Vec<String>,
+    #[serde(default = "default_true")]
+    pub default_deny: bool,
+}
+
+impl Default for HeaderConfig {
+    fn default() -> Self {
+        HeaderConfig {
+            allow: Vec::new(),
+            deny: Vec::new(),
+            default_deny: true,
+        }
+    }
+}
+
+fn default_true() -> bool {
+    true
+}
+
+fn matches(pattern: &str, name: &str) -> bool {
+    match pattern.strip_suffix('*') {
+        Some(prefix) => name.starts_with(&prefix.to_ascii_lowercase()),
+        None => name.eq_ignore_ascii_case(pattern),
+    }
+}
+
+impl HeaderConfig {
+    pub fn allows(&self, name: &str) -> bool {
+        if HOP_BY_HOP.contains(&name) {
+            return false;
+        }
+        if ALWAYS_KEPT.iter().any(|p| matches(p, name)) {
+            return true;
+        }
+        let defaults = if self.default_deny { DEFAULT_DENY } else { &[] };
+        if defaults.iter().any(|p| matches(p, name)) || self.deny.iter().any(|p| matches(p, name)) {
+            return false;
+        }
+        self.allow.is_empty() || self.allow.iter().any(|p| matches(p, name))
+    }
+
+    /// Copies the permitted upstream headers onto a client response.
+    pub fn copy_upstream(&self, mut builder: Builder, upstream: &reqwest::header::HeaderMap) -> Builder {
+        for (key, value) in upstream.iter() {
+            if !self.allows(key.as_str()) {
+                continue;
+            }
+            if let (Ok(name), Ok(val)) = (
+                http::HeaderName::from_bytes(key.as_ref()),
+                http::HeaderValue::from_bytes(value.as_bytes()),
+            ) {
+                builder = builder.header(name, val);
+            }
+        }
+        builder
+    }
+}
@@ -0,0 +1,197 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::backends::Backend;
+use crate::breaker::CircuitState;
+use crate::AppState;
+
+/// `[health]`: what `GET /readyz` checks.
+#[derive(Debug, Deserialize, Clone)]
+pub struct HealthConfig {
+    #[serde(default)]
+    pub probe: ProbeMode,
+    /// Time each backend has to answer its probe.
+    #[serde(default = "default_timeout_ms")]
+    pub timeout_ms: u64,
+    /// Probe results are reused for this long, so frequent readiness checks
+    /// do not turn into upstream traffic.
+    #[serde(default = "default_cache_secs")]
+    pub cache_secs: u64,
+}
+
+fn default_timeout_ms() -> u64 {
+    2000
+}
+
+fn default_cache_secs() -> u64 {
+    10
+}
+
+impl Default for HealthConfig {
+    fn default() -> Self {
+        HealthConfig {
+            probe: ProbeMode::default(),
+            timeout_ms: default_timeout_ms(),
+            cache_secs: default_cache_secs(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum ProbeMode {
+    /// Ready as soon as the server runs.
+    #[default]
+    None,
+    /// List each backend's models; any answer below 500 counts.
+    Models,
+    /// Ask each backend for a one-token completion, which must succeed.
+    /// Costs a request per backend every `cache_secs`.
+    Completion,
+}
+
+#[derive(Debug, Serialize, Clone)]
+pub struct BackendHealth {
+    pub name: String,
+    /// `up`, `down` or, for drained backends, `drained`.
+    pub status: &'static str,
+    pub latency_ms: Option<u64>,
+    pub error: Option<String>,
+    pub circuit: CircuitState,
+}
+
+/// The last readiness probe, reused for `cache_secs`.
+#[derive(Default)]
+pub struct Health {
+    last: Mutex<Option<(Instant, Vec<BackendHealth>)>>,
+    draining: AtomicBool,
+}
+
+impl Health {
+    /// Reports not ready from now on, so load balancers stop sending new
+    /// requests while in-flight ones finish.
+    pub fn set_draining(&self) {
+        self.draining.store(true, Ordering::Relaxed);
+    }
+}
+
+/// `GET /healthz`: the process is up and serving.
+pub async fn handle_healthz() -> Json<Value> {
+    Json(json!({ "status": "ok" }))
+}
+
+/// `GET /readyz`: 200 when every backend that is not drained passed its
+/// probe, else 503, with the status of each backend either way.
+pub async fn handle_readyz(State(state): State<Arc<AppState>>) -> Response<Body> {
+    if state.health.draining.load(Ordering::Relaxed) {
+        let body = Json(json!({ "status": "shutting_down" }));
+        return (StatusCode::SERVICE_UNAVAILABLE, body).into_response();
+    }
+    let config = &state.config.health;
+    if config.probe == ProbeMode::None {
+        return Json(json!({ "status": "ready" })).into_response();
+    }
+    let backends = probe_all(&state).await;
+    let ready = backends.iter().all(|b| b.status != "down");
+    let body = json!({
+        "status": if ready { "ready" } else { "not_ready" },
+        "backends": backends,
+    });
+    let status = if ready { StatusCode::OK } else { StatusCode::SERVICE_UNAVAILABLE };
+    (status, [(header::CACHE_CONTROL, "no-store")], Json(body)).into_response()
+}
+
+async fn probe_all(state: &AppState) -> Vec<BackendHealth> {
+    let max_age = Duration::from_secs(state.config.health.cache_secs);
+    if let Some((at, backends)) = &*state.health.last.lock().unwrap() {
+        if at.elapsed() < max_age {
+            return backends.clone();
+        }
+    }
+    let probes = state.backends.all().map(|backend| async move {
+        let name = backend.name.clone();
+        let circuit = backend.breaker.state();
+        if state.maintenance.is_drained(&name) {
+            return BackendHealth {
+                name,
+                status: "drained",
+                latency_ms: None,
+                error: None,
+                circuit,
+            };
+        }
+        let started = Instant::now();
+        let outcome = probe(state, backend).await;
+        BackendHealth {
+            name,
+            status: if outcome.is_ok() { "up" } else { "down" },
+            latency_ms: Some(started.elapsed().as_millis() as u64),
+            error: outcome.err(),
+            circuit,
+        }
+    });
+    let backends = futures::future::join_all(probes).await;
+    for backend in backends.iter().filter(|b| b.status == "down") {
+        println!(
+            "Readiness probe of backend {} failed: {}",
+            backend.name,
+            backend.error.as_deref().unwrap_or("")
+        );
+    }
+    *state.health.last.lock().unwrap() = Some((Instant::now(), backends.clone()));
+    backends
+}
+
+async fn probe(state: &AppState, backend: &Backend) -> Result<(), String> {
+    let timeout = Duration::from_millis(state.config.health.timeout_ms);
+    let mut headers = reqwest::header::HeaderMap::new();
+    backend.provider.authorize(&mut headers, &backend.key);
+    let request = match state.config.health.probe {
+        ProbeMode::None => return Ok(()),
+        ProbeMode::Models => {
+            let url = format!("{}/models", backend.base_url());
+            backend.client.get(url).headers(headers)
+        }
+        ProbeMode::Completion => {
+            let model = backend
+                .models
+                .iter()
+                .find(|m| !m.contains('*'))
+                .cloned()
+                .unwrap_or_else(|| state.config.default_model.clone());
+            let mut payload = Map::new();
+            payload.insert("model".to_string(), json!(model));
+            payload.insert("messages".to_string(), json!([{ "role": "user", "content": "ping" }]));
+            payload.insert("max_tokens".to_string(), json!(1));
+            backend.provider.translate_request(&mut payload)?;
+            let url = backend.provider.chat_url(&backend.url, &model, false);
+            let body = serde_json::to_vec(&payload).unwrap();
+            headers.insert(reqwest::header::CONTENT_TYPE, "application/json".parse().unwrap());
+            backend.provider.sign("POST", &url, &mut headers, &body);
+            backend.client.post(url).headers(headers).body(body)
+        }
+    };
+    let response = request.timeout(timeout).send().await.map_err(|e| e.to_string())?;
+    let status = response.status();
+    // Read the body so the connection can be reused.
+    let _ = response.bytes().await;
+    let passed = match state.config.health.probe {
+        ProbeMode::Completion => status.is_success(),
+        _ => !status.is_server_error(),
+    };
+    if passed {
+        Ok(())
+    } else {
+        Err(format!("answered {}", status))
+    }
+}
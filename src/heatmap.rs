@@ -0,0 +1,242 @@
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::Mutex;
+
+use crate::tokenizer::TokenizerRegistry;
+use crate::translation::content_text;
+
+/// `[heatmap]`: which system prompt sections and templates consume the
+/// most tokens, reported at `/admin/heatmap`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct HeatmapConfig {
+    #[serde(default)]
+    pub enabled: bool,
+    /// Requests waiting for the `token-heatmap` job; more are not sampled.
+    #[serde(default = "default_max_pending")]
+    pub max_pending: usize,
+    /// Distinct sections tracked; tokens of further ones are only totalled.
+    #[serde(default = "default_max_sections")]
+    pub max_sections: usize,
+    /// Sections listed in the report.
+    #[serde(default = "default_top")]
+    pub top: usize,
+}
+
+fn default_max_pending() -> usize {
+    1000
+}
+
+fn default_max_sections() -> usize {
+    5000
+}
+
+fn default_top() -> usize {
+    50
+}
+
+impl Default for HeatmapConfig {
+    fn default() -> Self {
+        HeatmapConfig {
+            enabled: false,
+            max_pending: default_max_pending(),
+            max_sections: default_max_sections(),
+            top: default_top(),
+        }
+    }
+}
+
+/// The prompt of one request, kept until the job tokenizes it.
+struct Sample {
+    model: String,
+    template: String,
+    /// Paragraphs of system and developer messages, with their role.
+    sections: Vec<(String, String)>,
+    user: String,
+}
+
+#[derive(Default)]
+struct SectionStats {
+    role: String,
+    preview: String,
+    templates: BTreeMap<String, u64>,
+    uses: u64,
+    tokens: u64,
+}
+
+#[derive(Default, Clone, Copy)]
+struct TemplateStats {
+    requests: u64,
+    section_tokens: u64,
+    user_tokens: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+}
+
+#[derive(Default)]
+struct Totals {
+    sections: HashMap<String, SectionStats>,
+    templates: BTreeMap<String, TemplateStats>,
+    untracked_tokens: u64,
+}
+
+/// Samples prompts on the request path and aggregates their token counts
+/// in the background.
+pub struct TokenHeatmap {
+    config: HeatmapConfig,
+    pending: Mutex<VecDeque<Sample>>,
+    totals: Mutex<Totals>,
+}
+
+fn template_label(template: Option<&str>) -> String {
+    template.unwrap_or("none").to_string()
+}
+
+fn section_key(role: &str, text: &str) -> String {
+    let digest = Sha256::new().chain_update(role).chain_update(text).finalize();
+    hex::encode(&digest[..8])
+}
+
+fn preview(text: &str) -> String {
+    let mut preview: String = text.chars().take(80).collect();
+    if preview.len() < text.len() {
+        preview.push('…');
+    }
+    preview
+}
+
+impl TokenHeatmap {
+    pub fn new(config: HeatmapConfig) -> Self {
+        TokenHeatmap {
+            config,
+            pending: Mutex::new(VecDeque::new()),
+            totals: Mutex::new(Totals::default()),
+        }
+    }
+
+    /// Queues the prompt of a request as it goes upstream. Sections are the
+    /// blank-line separated paragraphs of its system and developer messages.
+    pub fn sample(&self, model: &str, template: Option<&str>, payload: &Map<String, Value>) {
+        if !self.config.enabled {
+            return;
+        }
+        let mut pending = self.pending.lock().unwrap();
+        if pending.len() >= self.config.max_pending {
+            return;
+        }
+        let mut sample = Sample {
+            model: model.to_string(),
+            template: template_label(template),
+            sections: Vec::new(),
+            user: String::new(),
+        };
+        let messages = payload.get("messages").and_then(Value::as_array);
+        for message in messages.into_iter().flatten() {
+            let role = message["role"].as_str().unwrap_or("");
+            let text = message.get("content").map(content_text).unwrap_or_default();
+            match role {
+                "system" | "developer" => sample.sections.extend(
+                    text.split("\n\n")
+                        .map(str::trim)
+                        .filter(|p| !p.is_empty())
+                        .map(|p| (role.to_string(), p.to_string())),
+                ),
+                "user" => sample.user.push_str(&text),
+                _ => {}
+            }
+        }
+        pending.push_back(sample);
+    }
+
+    /// Adds the token counts the backend reported for a request.
+    pub fn record_usage(&self, template: Option<&str>, usage: Option<&Value>) {
+        if !self.config.enabled {
+            return;
+        }
+        let tokens = |name: &str| usage.and_then(|u| u[name].as_u64()).unwrap_or(0);
+        let mut totals = self.totals.lock().unwrap();
+        let stats = totals.templates.entry(template_label(template)).or_default();
+        stats.prompt_tokens += tokens("prompt_tokens");
+        stats.completion_tokens += tokens("completion_tokens");
+    }
+
+    /// Tokenizes the queued prompts into the totals; run by the
+    /// `token-heatmap` job.
+    pub fn aggregate(&self, tokenizers: &TokenizerRegistry) -> String {
+        let samples: Vec<Sample> = self.pending.lock().unwrap().drain(..).collect();
+        let mut totals = self.totals.lock().unwrap();
+        for sample in &samples {
+            let tokenizer = tokenizers.for_model(&sample.model);
+            let user_tokens = tokenizer.count(&sample.user) as u64;
+            let mut section_tokens = 0;
+            for (role, text) in &sample.sections {
+                let tokens = tokenizer.count(text) as u64;
+                section_tokens += tokens;
+                let key = section_key(role, text);
+                if !totals.sections.contains_key(&key) && totals.sections.len() >= self.config.max_sections {
+                    totals.untracked_tokens += tokens;
+                    continue;
+                }
+                let section = totals.sections.entry(key).or_insert_with(|| SectionStats {
+                    role: role.clone(),
+                    preview: preview(text),
+                    ..SectionStats::default()
+                });
+                section.uses += 1;
+                section.tokens += tokens;
+                *section.templates.entry(sample.template.clone()).or_default() += 1;
+            }
+            let stats = totals.templates.entry(sample.template.clone()).or_default();
+            stats.requests += 1;
+            stats.section_tokens += section_tokens;
+            stats.user_tokens += user_tokens;
+        }
+        format!("aggregated {} prompts", samples.len())
+    }
+
+    /// The most expensive sections and per-template totals.
+    pub fn report(&self) -> Value {
+        let totals = self.totals.lock().unwrap();
+        let all_tokens: u64 = totals.sections.values().map(|s| s.tokens).sum::<u64>() + totals.untracked_tokens;
+        let mut sections: Vec<(&String, &SectionStats)> = totals.sections.iter().collect();
+        sections.sort_by(|a, b| b.1.tokens.cmp(&a.1.tokens).then_with(|| a.0.cmp(b.0)));
+        let sections: Vec<Value> = sections
+            .into_iter()
+            .take(self.config.top)
+            .map(|(key, s)| {
+                json!({
+                    "id": key,
+                    "role": s.role,
+                    "preview": s.preview,
+                    "uses": s.uses,
+                    "tokens_per_use": s.tokens / s.uses.max(1),
+                    "total_tokens": s.tokens,
+                    "share": if all_tokens == 0 { 0.0 } else { s.tokens as f64 / all_tokens as f64 },
+                    "templates": s.templates,
+                })
+            })
+            .collect();
+        let templates: Map<String, Value> = totals
+            .templates
+            .iter()
+            .map(|(name, t)| {
+                let stats = json!({
+                    "requests": t.requests,
+                    "system_tokens": t.section_tokens,
+                    "user_tokens": t.user_tokens,
+                    "prompt_tokens": t.prompt_tokens,
+                    "completion_tokens": t.completion_tokens,
+                });
+                (name.clone(), stats)
+            })
+            .collect();
+        json!({
+            "pending": self.pending.lock().unwrap().len(),
+            "system_tokens": all_tokens,
+            "untracked_tokens": totals.untracked_tokens,
+            "sections": sections,
+            "templates": templates,
+        })
+    }
+}
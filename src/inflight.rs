@@ -0,0 +1,265 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::StatusCode,
+    middleware::Next,
+    response::Response,
+};
+use futures::task::AtomicWaker;
+use http_body::Frame;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+use crate::create_error_response;
+use crate::AppState;
+
+/// `[watchdog]`: catches requests and streams that never complete.
+#[derive(Debug, Deserialize, Clone)]
+pub struct WatchdogConfig {
+    /// Requests still running after this many seconds, counted until their
+    /// response body is finished, are reported by the `request-watchdog` job.
+    #[serde(default = "default_max_lifetime_secs")]
+    pub max_lifetime_secs: u64,
+    /// Also abort them, closing the client connection.
+    #[serde(default)]
+    pub abort: bool,
+    /// Requests tracked at once; further ones are served untracked so the
+    /// registry cannot grow without bound.
+    #[serde(default = "default_max_entries")]
+    pub max_entries: usize,
+}
+
+fn default_max_lifetime_secs() -> u64 {
+    3600
+}
+
+fn default_max_entries() -> usize {
+    10_000
+}
+
+impl Default for WatchdogConfig {
+    fn default() -> Self {
+        WatchdogConfig {
+            max_lifetime_secs: default_max_lifetime_secs(),
+            abort: false,
+            max_entries: default_max_entries(),
+        }
+    }
+}
+
+struct Entry {
+    method: String,
+    path: String,
+    request_id: Option<String>,
+    started: Instant,
+    /// Reported by the watchdog already, so it is logged once.
+    reported: AtomicBool,
+    aborted: AtomicBool,
+    abort: Notify,
+    waker: AtomicWaker,
+}
+
+impl Entry {
+    fn abort(&self) {
+        self.aborted.store(true, Ordering::SeqCst);
+        self.abort.notify_waiters();
+        self.waker.wake();
+    }
+
+    async fn aborted(&self) {
+        let notified = self.abort.notified();
+        if !self.aborted.load(Ordering::SeqCst) {
+            notified.await;
+        }
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct InFlightInfo {
+    pub method: String,
+    pub path: String,
+    pub request_id: Option<String>,
+    pub age_ms: u128,
+    pub overdue: bool,
+}
+
+#[derive(Debug, Serialize)]
+pub struct InFlightStats {
+    pub active: usize,
+    pub untracked: u64,
+    pub overdue: u64,
+    pub aborted: u64,
+    pub requests: Vec<InFlightInfo>,
+}
+
+/// Every request being served, from arrival until its response body is done.
+#[derive(Default)]
+pub struct InFlight {
+    entries: Mutex<HashMap<u64, Arc<Entry>>>,
+    next_id: AtomicU64,
+    untracked: AtomicU64,
+    overdue: AtomicU64,
+    aborted: AtomicU64,
+}
+
+impl InFlight {
+    fn register(
+        self: &Arc<Self>,
+        request: &Request,
+        max_entries: usize,
+    ) -> Option<InFlightGuard> {
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= max_entries {
+            self.untracked.fetch_add(1, Ordering::Relaxed);
+            return None;
+        }
+        let id = self.next_id.fetch_add(1, Ordering::Relaxed);
+        let entry = Arc::new(Entry {
+            method: request.method().to_string(),
+            path: request.uri().path().to_string(),
+            request_id: request
+                .headers()
+                .get("x-request-id")
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string),
+            started: Instant::now(),
+            reported: AtomicBool::new(false),
+            aborted: AtomicBool::new(false),
+            abort: Notify::new(),
+            waker: AtomicWaker::new(),
+        });
+        entries.insert(id, entry.clone());
+        Some(InFlightGuard {
+            registry: self.clone(),
+            id,
+            entry,
+        })
+    }
+
+    pub fn stats(&self, max_lifetime: Duration) -> InFlightStats {
+        let mut requests: Vec<InFlightInfo> = self
+            .entries
+            .lock()
+            .unwrap()
+            .values()
+            .map(|e| InFlightInfo {
+                method: e.method.clone(),
+                path: e.path.clone(),
+                request_id: e.request_id.clone(),
+                age_ms: e.started.elapsed().as_millis(),
+                overdue: e.started.elapsed() > max_lifetime,
+            })
+            .collect();
+        requests.sort_by_key(|r| std::cmp::Reverse(r.age_ms));
+        InFlightStats {
+            active: requests.len(),
+            untracked: self.untracked.load(Ordering::Relaxed),
+            overdue: self.overdue.load(Ordering::Relaxed),
+            aborted: self.aborted.load(Ordering::Relaxed),
+            requests,
+        }
+    }
+
+    /// Reports requests older than `max_lifetime` and, with `abort`, ends
+    /// them; returns how many were found.
+    pub fn check(&self, max_lifetime: Duration, abort: bool) -> usize {
+        let entries = self.entries.lock().unwrap();
+        let mut found = 0;
+        for entry in entries.values().filter(|e| e.started.elapsed() > max_lifetime) {
+            found += 1;
+            if !entry.reported.swap(true, Ordering::Relaxed) {
+                self.overdue.fetch_add(1, Ordering::Relaxed);
+                println!(
+                    "{} {} (request {}) still running after {:?}, possibly leaked",
+                    entry.method,
+                    entry.path,
+                    entry.request_id.as_deref().unwrap_or("without id"),
+                    entry.started.elapsed()
+                );
+            }
+            if abort && !entry.aborted.load(Ordering::SeqCst) {
+                println!("Aborting {} {}", entry.method, entry.path);
+                self.aborted.fetch_add(1, Ordering::Relaxed);
+                entry.abort();
+            }
+        }
+        found
+    }
+}
+
+/// Keeps a request registered until it is dropped with its response body.
+struct InFlightGuard {
+    registry: Arc<InFlight>,
+    id: u64,
+    entry: Arc<Entry>,
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.registry.entries.lock().unwrap().remove(&self.id);
+    }
+}
+
+/// A response body that unregisters the request once it is done, and fails
+/// when the watchdog aborts it.
+struct Tracked {
+    inner: Body,
+    guard: InFlightGuard,
+}
+
+impl http_body::Body for Tracked {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<Frame<Bytes>, axum::Error>>> {
+        let this = self.get_mut();
+        let entry = &this.guard.entry;
+        entry.waker.register(cx.waker());
+        if entry.aborted.load(Ordering::SeqCst) {
+            return Poll::Ready(Some(Err(axum::Error::new("aborted by the request watchdog"))));
+        }
+        http_body::Body::poll_frame(Pin::new(&mut this.inner), cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        http_body::Body::is_end_stream(&self.inner)
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        http_body::Body::size_hint(&self.inner)
+    }
+}
+
+/// Registers every request for the watchdog and `/admin/requests`.
+pub async fn track(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let max_entries = state.config.watchdog.max_entries;
+    let Some(guard) = state.inflight.register(&request, max_entries) else {
+        return next.run(request).await;
+    };
+    let entry = guard.entry.clone();
+    let response = tokio::select! {
+        response = next.run(request) => response,
+        _ = entry.aborted() => {
+            return create_error_response(
+                StatusCode::GATEWAY_TIMEOUT,
+                "request_aborted",
+                "The request ran past its maximum lifetime and was aborted",
+            );
+        }
+    };
+    let (parts, body) = response.into_parts();
+    Response::from_parts(parts, Body::new(Tracked { inner: body, guard }))
+}
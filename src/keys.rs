@@ -0,0 +1,115 @@
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+use std::sync::RwLock;
+
+use crate::auth::Identity;
+
+/// Per-key adjustments applied to every request made with that key.
+#[derive(Debug, Clone, Default, Deserialize, Serialize)]
+pub struct KeyOverrides {
+    /// System prompt inserted ahead of the client's messages.
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    /// Request parameters forced onto the payload, e.g. `temperature`.
+    #[serde(default)]
+    pub params: Map<String, Value>,
+}
+
+impl KeyOverrides {
+    pub fn is_empty(&self) -> bool {
+        self.system_prompt.is_none() && self.params.is_empty()
+    }
+
+    pub fn apply(&self, payload: &mut Map<String, Value>) {
+        for (name, value) in &self.params {
+            payload.insert(name.clone(), value.clone());
+        }
+        if let Some(prompt) = &self.system_prompt {
+            if let Some(Value::Array(messages)) = payload.get_mut("messages") {
+                messages.insert(0, json!({ "role": "system", "content": prompt }));
+            }
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct KeyConfig {
+    /// How the key is addressed in admin routes and state exports, so the
+    /// key itself never has to appear in a URL.
+    pub name: String,
+    pub key: String,
+    #[serde(default)]
+    pub system_prompt: Option<String>,
+    #[serde(default)]
+    pub params: Map<String, Value>,
+}
+
+/// Client keys known to the adapter, seeded from config and editable at
+/// runtime. Overrides are stored by key name.
+pub struct KeyStore {
+    names: HashMap<String, String>,
+    entries: RwLock<HashMap<String, KeyOverrides>>,
+}
+
+impl KeyStore {
+    pub fn new(keys: &[KeyConfig]) -> Self {
+        let entries = keys
+            .iter()
+            .map(|k| {
+                let overrides = KeyOverrides {
+                    system_prompt: k.system_prompt.clone(),
+                    params: k.params.clone(),
+                };
+                (k.name.clone(), overrides)
+            })
+            .collect();
+        KeyStore {
+            names: keys.iter().map(|k| (k.key.clone(), k.name.clone())).collect(),
+            entries: RwLock::new(entries),
+        }
+    }
+
+    /// The configured name of a client key.
+    pub fn name_of(&self, key: &str) -> Option<&str> {
+        self.names.get(key).map(String::as_str)
+    }
+
+    /// Overrides for a caller, by the name its credential carries or the
+    /// name configured for its bearer key.
+    pub fn overrides_for(&self, identity: &Identity) -> Option<KeyOverrides> {
+        let name = identity.name.as_deref().or_else(|| self.name_of(&identity.id))?;
+        self.overrides(name)
+    }
+
+    pub fn overrides(&self, name: &str) -> Option<KeyOverrides> {
+        self.entries
+            .read()
+            .unwrap()
+            .get(name)
+            .filter(|o| !o.is_empty())
+            .cloned()
+    }
+
+    pub fn set_overrides(&self, name: &str, overrides: KeyOverrides) {
+        self.entries
+            .write()
+            .unwrap()
+            .insert(name.to_string(), overrides);
+    }
+
+    /// Every key name with overrides, for state export.
+    pub fn all(&self) -> HashMap<String, KeyOverrides> {
+        self.entries
+            .read()
+            .unwrap()
+            .iter()
+            .filter(|(_, o)| !o.is_empty())
+            .map(|(k, o)| (k.clone(), o.clone()))
+            .collect()
+    }
+
+    pub fn remove(&self, name: &str) -> bool {
+        self.entries.write().unwrap().remove(name).is_some()
+    }
+}
@@ -0,0 +1,407 @@
+use axum::{
+    body::Body,
+    http::{header, StatusCode},
+    extract::State,
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{any, get, post, MethodRouter},
+    Json, Router,
+};
+use reqwest::Client;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use tokio::net::TcpListener;
+
+pub mod acme;
+pub mod admin;
+pub mod anthropic;
+pub mod auth;
+pub mod azure;
+pub mod backends;
+pub mod balance;
+pub mod batch;
+pub mod bedrock;
+pub mod breaker;
+pub mod browser;
+pub mod buildinfo;
+pub mod cache;
+pub mod cli;
+pub mod cohere;
+pub mod completion;
+pub mod compression;
+pub mod config;
+pub mod conformance;
+pub mod convert;
+pub mod db;
+pub mod deepl;
+pub mod degrade;
+pub mod deidentify;
+pub mod deprecation;
+pub mod discovery;
+pub mod documents;
+pub mod doctor;
+pub mod embedded;
+pub mod embeddings;
+pub mod estimate;
+pub mod feedback;
+pub mod gemini;
+pub mod glossary;
+pub mod headers;
+pub mod health;
+pub mod handshake;
+pub mod heatmap;
+pub mod inflight;
+pub mod keys;
+pub mod limits;
+pub mod locale;
+pub mod maintenance;
+pub mod metrics;
+pub mod models;
+pub mod normalize;
+pub mod openapi;
+pub mod panics;
+pub mod passthrough;
+pub mod policy;
+pub mod postedit;
+pub mod profiling;
+pub mod prompts;
+pub mod provider;
+pub mod proxy;
+pub mod queue;
+pub mod regions;
+pub mod reload;
+pub mod rules;
+pub mod quotas;
+pub mod runtime;
+pub mod scheduler;
+pub mod schema;
+pub mod service;
+pub mod shutdown;
+pub mod signing;
+pub mod sigv4;
+pub mod slo;
+pub mod snapshot;
+pub mod sse;
+pub mod streams;
+pub mod sysprompt;
+pub mod templates;
+pub mod tls;
+pub mod tokenizer;
+pub mod tools;
+pub mod translation;
+pub mod usage;
+pub mod verbose;
+pub mod version;
+pub mod voyage;
+pub mod webhooks;
+
+pub use crate::config::AppConfig;
+use auth::Authenticator;
+use backends::Backends;
+use cache::ResponseCache;
+use db::Database;
+use deidentify::Deidentifier;
+use deprecation::{DeprecationLog, ModelDeprecation};
+use discovery::Discovery;
+use feedback::FeedbackStore;
+use glossary::GlossaryStore;
+use health::Health;
+use heatmap::TokenHeatmap;
+use inflight::InFlight;
+use keys::KeyStore;
+use limits::{Budgets, RateLimiter, Smoother};
+use locale::ErrorCatalog;
+use maintenance::Maintenance;
+use metrics::Metrics;
+use prompts::PromptStore;
+use provider::Provider;
+use queue::Admission;
+use quotas::TenantQuotas;
+use scheduler::Scheduler;
+use signing::ResponseSigner;
+use slo::SloTracker;
+use streams::StreamRegistry;
+use templates::TemplateStore;
+use tokenizer::TokenizerRegistry;
+use usage::UsageLedger;
+use verbose::VerboseLogging;
+use webhooks::Webhooks;
+
+#[derive(Clone)]
+pub struct AppState {
+    pub client: Client,
+    pub backends: Arc<Backends>,
+    pub config: Arc<AppConfig>,
+    pub provider: Arc<dyn Provider>,
+    pub streams: Arc<StreamRegistry>,
+    /// Every request being served, for the watchdog.
+    pub inflight: Arc<InFlight>,
+    pub limiter: Arc<RateLimiter>,
+    /// Per-minute request and token budgets by key and model.
+    pub budgets: Arc<Budgets>,
+    pub smoother: Arc<Smoother>,
+    pub admission: Arc<Admission>,
+    pub quotas: Arc<TenantQuotas>,
+    pub keys: Arc<KeyStore>,
+    pub auth: Arc<Authenticator>,
+    pub signer: Option<Arc<ResponseSigner>>,
+    pub maintenance: Arc<Maintenance>,
+    pub tokenizers: Arc<TokenizerRegistry>,
+    pub scheduler: Arc<Scheduler>,
+    pub slo: Arc<SloTracker>,
+    pub prompts: Arc<PromptStore>,
+    pub usage: Arc<UsageLedger>,
+    pub heatmap: Arc<TokenHeatmap>,
+    pub errors: Arc<ErrorCatalog>,
+    pub metrics: Arc<Metrics>,
+    pub db: Arc<Database>,
+    pub templates: Arc<TemplateStore>,
+    pub feedback: Arc<FeedbackStore>,
+    /// Per-tenant terminology for translations.
+    pub glossaries: Arc<GlossaryStore>,
+    pub deidentifier: Arc<Deidentifier>,
+    pub webhooks: Arc<Webhooks>,
+    pub cache: Arc<ResponseCache>,
+    /// Request features each backend was found to accept.
+    pub discovery: Arc<Discovery>,
+    /// The last readiness probe of the backends.
+    pub health: Arc<Health>,
+    /// Keys and sample rate of requests logged in detail.
+    pub verbose: Arc<VerboseLogging>,
+    /// Requests for deprecated models by key.
+    pub deprecations: Arc<DeprecationLog>,
+}
+
+impl AppState {
+    pub fn new(config: AppConfig) -> Result<Self, ::config::ConfigError> {
+        let signer = match &config.signing {
+            Some(signing) => Some(Arc::new(
+                ResponseSigner::from_config(signing).map_err(::config::ConfigError::Message)?,
+            )),
+            None => None,
+        };
+
+        let db = Arc::new(Database::open(&config.database).map_err(::config::ConfigError::Message)?);
+
+        let scheduler = Scheduler::new(config.scheduler.clone());
+        scheduler::register_builtin(&scheduler);
+        let client = tls::build_client(&config.tls.without_pins(), &config.pool).map_err(::config::ConfigError::Message)?;
+
+        let backends = Arc::new(Backends::new(&config).map_err(::config::ConfigError::Message)?);
+        ModelDeprecation::validate(&config.deprecations).map_err(::config::ConfigError::Message)?;
+        let provider = config.protocol.provider(&config);
+
+        let webhooks = Arc::new(Webhooks::new(config.webhooks.clone(), db.clone(), client.clone()));
+
+        Ok(AppState {
+            client,
+            backends,
+            limiter: Arc::new(RateLimiter::new(config.limits.clone())),
+            budgets: Arc::new(Budgets::default()),
+            smoother: Arc::new(Smoother::new(&config.limits)),
+            admission: Arc::new(Admission::new(
+                config.limits.max_concurrency,
+                config.limits.max_queue_wait_ms.map(std::time::Duration::from_millis),
+            )),
+            keys: Arc::new(KeyStore::new(&config.keys)),
+            auth: Arc::new(Authenticator::new(config.auth.clone())),
+            quotas: Arc::new(TenantQuotas::new(config.quotas.clone())),
+            tokenizers: Arc::new(TokenizerRegistry::new(config.tokenizers.clone())),
+            slo: Arc::new(SloTracker::new(config.slo.clone())),
+            prompts: Arc::new(PromptStore::new(config.prompts.clone())),
+            usage: Arc::new(UsageLedger::new(config.usage.clone())),
+            heatmap: Arc::new(TokenHeatmap::new(config.heatmap.clone())),
+            errors: Arc::new(ErrorCatalog::new(&config.errors)),
+            metrics: Arc::new(Metrics::default()),
+            templates: Arc::new(TemplateStore::new(db.clone())),
+            feedback: Arc::new(FeedbackStore::new(db.clone())),
+            glossaries: Arc::new(GlossaryStore::new(config.translation.glossary.clone(), db.clone())),
+            deidentifier: Arc::new(Deidentifier::new(&config.deidentify).map_err(::config::ConfigError::Message)?),
+            webhooks,
+            cache: Arc::new(ResponseCache::new(config.cache.clone()).map_err(::config::ConfigError::Message)?),
+            discovery: Arc::new(Discovery::default()),
+            health: Arc::new(Health::default()),
+            verbose: Arc::new(VerboseLogging::default()),
+            deprecations: Arc::new(DeprecationLog::default()),
+            db,
+            config: Arc::new(config),
+            provider,
+            streams: Arc::new(StreamRegistry::default()),
+            inflight: Arc::new(InFlight::default()),
+            signer,
+            maintenance: Arc::new(Maintenance::default()),
+            scheduler: Arc::new(scheduler),
+        })
+    }
+}
+
+/// Routes served without an API key even when virtual keys are configured.
+const KEYLESS_ROUTES: &[&str] = &[
+    "/.well-known/llmta-signing-key",
+    "/openapi.json",
+    "/version",
+    "/metrics",
+    "/healthz",
+    "/readyz",
+];
+
+/// Built-in public routes; `[routes].aliases` may point at any of them.
+fn endpoints() -> Vec<(&'static str, MethodRouter<Arc<AppState>>)> {
+    vec![
+        ("/v1beta/openai/chat/completions", post(proxy::handle_chat)),
+        ("/v1/models", get(models::handle_models)),
+        ("/v1/embeddings", post(embeddings::handle_embeddings)),
+        ("/v1/estimate", post(estimate::handle_estimate)),
+        ("/v1/prompts", post(prompts::upload_prompt)),
+        ("/v1/prompts/:id", get(prompts::get_prompt)),
+        ("/v1/usage", get(usage::handle_usage)),
+        ("/v1/feedback", post(feedback::handle_feedback)),
+        ("/v1/documents/translate", post(documents::handle_translate_documents)),
+        ("/v1/batch/translate", post(batch::handle_batch_translate)),
+        ("/conformance", post(conformance::handle_conformance)),
+        ("/v2/translate", post(deepl::handle_translate)),
+        ("/.well-known/llmta-signing-key", get(signing_key)),
+        ("/openapi.json", get(openapi_spec)),
+        ("/version", get(buildinfo::handle_version)),
+        ("/metrics", get(metrics::handle_metrics)),
+        ("/healthz", get(health::handle_healthz)),
+        ("/readyz", get(health::handle_readyz)),
+    ]
+}
+
+pub fn router(state: Arc<AppState>) -> Router {
+    state.scheduler.start(Arc::downgrade(&state));
+    for backend in state.backends.all() {
+        handshake::prewarm(backend.clone(), backend.prewarm);
+    }
+    discovery::discover(state.clone());
+    let routes = &state.config.routes;
+    let mut api = Router::new();
+    let mut aliases = Router::new();
+    let require_key = middleware::from_fn_with_state(state.clone(), auth::require_key);
+    for (path, handler) in endpoints() {
+        let handler = if KEYLESS_ROUTES.contains(&path) {
+            handler
+        } else {
+            handler.route_layer(require_key.clone())
+        };
+        for alias in routes.aliases.iter().filter(|a| a.target == path) {
+            aliases = aliases.route(&alias.path, handler.clone());
+        }
+        api = api.route(path, handler);
+    }
+    if routes.passthrough {
+        // Dedicated routes take precedence over the wildcard.
+        api = api.route("/v1/*rest", any(passthrough::handle_passthrough).layer(require_key));
+    }
+    for alias in &routes.aliases {
+        if !endpoints().iter().any(|(path, _)| *path == alias.target) {
+            println!("Ignoring route alias {}: no route {}", alias.path, alias.target);
+        }
+    }
+    api = api.merge(admin::routes(state.clone()));
+
+    let prefix = routes.prefix.as_deref().unwrap_or("").trim_end_matches('/');
+    let app = if prefix.is_empty() {
+        api
+    } else {
+        Router::new().nest(prefix, api)
+    };
+    app.merge(aliases)
+        .layer(middleware::from_fn_with_state(state.clone(), version::negotiate))
+        .layer(middleware::from_fn_with_state(state.clone(), browser::status_page))
+        .layer(middleware::from_fn_with_state(state.clone(), locale::localize_errors))
+        .layer(middleware::map_response(buildinfo::server_header))
+        .layer(middleware::from_fn_with_state(state.clone(), inflight::track))
+        .layer(middleware::from_fn_with_state(state.clone(), panics::catch_panics))
+        .with_state(state)
+}
+
+/// Binds the configured address and serves until `shutdown` resolves, then
+/// stops accepting connections and lets active streams finish for up to
+/// `[shutdown].drain_timeout_secs`.
+pub async fn serve(
+    config: AppConfig,
+    shutdown: impl Future<Output = ()> + Send + 'static,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let addr = format!("{}:{}", config.host, config.port);
+    let acme = config.acme.clone();
+    let state = Arc::new(AppState::new(config)?);
+    if let Err(e) = snapshot::restore(&state).await {
+        println!("Not restoring runtime state: {}", e);
+    }
+    let live = reload::Reloadable::new(state.clone());
+    reload::spawn(live.clone());
+    let app = live.router();
+    let stopping = shutdown::Stopping::new(shutdown);
+
+    let served = async {
+        if let Some(acme) = acme {
+            let addr = tokio::net::lookup_host(&addr)
+                .await?
+                .next()
+                .ok_or_else(|| format!("{} did not resolve", addr))?;
+            acme::serve(&acme, addr, app, stopping.clone().wait()).await?;
+        } else {
+            let listener = TcpListener::bind(&addr).await?;
+            println!("Server running on http://{}", addr);
+
+            axum::serve(listener, app.into_make_service_with_connect_info::<SocketAddr>())
+                .with_graceful_shutdown(stopping.clone().wait())
+                .await?;
+        }
+        Ok::<_, Box<dyn std::error::Error>>(())
+    };
+    tokio::select! {
+        served = served => served?,
+        _ = shutdown::drain_deadline(&state, stopping.clone()) => {}
+    }
+
+    // Hand over to the next instance; losing the snapshot only costs warm state.
+    if let Err(e) = snapshot::save(&live.state()).await {
+        println!("Failed to save runtime state: {}", e);
+    }
+    Ok(())
+}
+
+async fn openapi_spec(State(state): State<Arc<AppState>>) -> Json<serde_json::Value> {
+    Json(openapi::spec(&state.config))
+}
+
+/// Public half of the response signing key, for consumers verifying signatures.
+async fn signing_key(State(state): State<Arc<AppState>>) -> Response<Body> {
+    match &state.signer {
+        Some(signer) => Json(serde_json::json!({
+            "key_id": signer.key_id(),
+            "algorithm": "ed25519",
+            "digest": "sha256",
+            "public_key": signer.public_key_hex(),
+        }))
+        .into_response(),
+        None => create_error_response(
+            StatusCode::NOT_FOUND,
+            "not_found",
+            "Response signing is not enabled",
+        ),
+    }
+}
+
+pub fn create_error_response(
+    status: StatusCode,
+    error_type: &str,
+    message: &str,
+) -> Response<Body> {
+    let error_response = serde_json::json!({
+        "error": {
+            "type": error_type,
+            "message": message,
+        }
+    });
+
+    Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(serde_json::to_string(&error_response).unwrap()))
+        .unwrap()
+}
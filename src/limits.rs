@@ -0,0 +1,617 @@
+use axum::{
+    body::Body,
+    http::{header, HeaderMap, HeaderValue, StatusCode},
+    response::Response,
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use crate::create_error_response;
+use crate::policy;
+use crate::snapshot::{AgedCount, BudgetLevel};
+
+const WINDOW: Duration = Duration::from_secs(60);
+
+/// Stands in for a client key in the limit tables, so neither they nor the
+/// state exported from them hold the key itself.
+fn fingerprint(key: &str) -> String {
+    hex::encode(Sha256::digest(key.as_bytes()))
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct LimitsConfig {
+    /// Requests per minute allowed for each client key; unlimited when unset.
+    #[serde(default)]
+    pub requests_per_minute: Option<u64>,
+    /// Chat tokens per minute allowed for each client key; unlimited when
+    /// unset. Virtual keys may set their own.
+    #[serde(default)]
+    pub tokens_per_minute: Option<u64>,
+    /// Budgets shared by all callers of a model, the first match applying.
+    #[serde(default)]
+    pub models: Vec<ModelBudget>,
+    /// Share of a limit after which responses carry `x-ratelimit-warning`.
+    #[serde(default = "default_soft_ratio")]
+    pub soft_ratio: f64,
+    /// Outbound requests per second per backend, regardless of client.
+    /// Bursts beyond `upstream_burst` wait for their slot instead of failing.
+    #[serde(default)]
+    pub upstream_rps: Option<f64>,
+    #[serde(default = "default_upstream_burst")]
+    pub upstream_burst: u32,
+    /// Concurrent upstream requests per backend; further requests queue.
+    #[serde(default)]
+    pub max_concurrency: Option<usize>,
+    /// Longest a request may queue, unless the client sends a tighter
+    /// `x-llmta-max-queue-wait-ms`. Requests expected to wait longer get 429.
+    #[serde(default)]
+    pub max_queue_wait_ms: Option<u64>,
+    /// Largest upstream response the adapter will relay.
+    #[serde(default)]
+    pub max_response_bytes: Option<usize>,
+    #[serde(default)]
+    pub oversize_policy: OversizePolicy,
+}
+
+/// What happens when a response passes `max_response_bytes`.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum OversizePolicy {
+    /// Fail the request (or end the stream with an error event).
+    #[default]
+    Error,
+    /// End the completion early with `finish_reason: "length"` and
+    /// `x_truncated: true`. Non-streaming requests are streamed upstream so
+    /// there is a partial completion to return.
+    TruncateWithMarker,
+}
+
+impl Default for LimitsConfig {
+    fn default() -> Self {
+        LimitsConfig {
+            requests_per_minute: None,
+            tokens_per_minute: None,
+            models: Vec::new(),
+            soft_ratio: default_soft_ratio(),
+            upstream_rps: None,
+            upstream_burst: default_upstream_burst(),
+            max_concurrency: None,
+            max_queue_wait_ms: None,
+            max_response_bytes: None,
+            oversize_policy: OversizePolicy::default(),
+        }
+    }
+}
+
+/// `[[limits.models]]` entry: per-minute budgets for each matching model.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ModelBudget {
+    /// Model names; a trailing `*` matches by prefix.
+    pub models: Vec<String>,
+    #[serde(default)]
+    pub requests_per_minute: Option<u64>,
+    #[serde(default)]
+    pub tokens_per_minute: Option<u64>,
+}
+
+impl LimitsConfig {
+    /// The budgets a chat request for `model` by `key` draws on. `tokens`
+    /// estimates what it will use and is only called when a token budget
+    /// applies.
+    pub fn charges(
+        &self,
+        key: &str,
+        key_tpm: Option<u64>,
+        model: &str,
+        tokens: impl FnOnce() -> u64,
+    ) -> Vec<Charge> {
+        let budget = self.models.iter().find(|b| policy::matches(&b.models, model));
+        let key_tpm = key_tpm.or(self.tokens_per_minute);
+        let model_tpm = budget.and_then(|b| b.tokens_per_minute);
+        let tokens = if key_tpm.is_some() || model_tpm.is_some() { tokens() } else { 0 };
+        let mut charges = Vec::new();
+        if let Some(per_minute) = key_tpm {
+            charges.push(Charge::tokens(format!("key:{}", fingerprint(key)), per_minute, tokens, "tokens per minute"));
+        }
+        if let Some(per_minute) = budget.and_then(|b| b.requests_per_minute) {
+            let what = format!("requests per minute for {}", model);
+            charges.push(Charge::new(format!("model-requests:{}", model), per_minute, 1, &what));
+        }
+        if let Some(per_minute) = model_tpm {
+            let what = format!("tokens per minute for {}", model);
+            charges.push(Charge::tokens(format!("model-tokens:{}", model), per_minute, tokens, &what));
+        }
+        charges
+    }
+}
+
+fn default_soft_ratio() -> f64 {
+    0.8
+}
+
+fn default_upstream_burst() -> u32 {
+    1
+}
+
+/// Where a key stands against its request limit after a check.
+#[derive(Debug, Clone)]
+pub struct LimitStatus {
+    pub limit: u64,
+    pub remaining: u64,
+    pub reset: Duration,
+    /// Past the soft threshold: still served, but the client should slow down.
+    pub soft: bool,
+}
+
+impl LimitStatus {
+    /// Adds OpenAI-compatible `x-ratelimit-*-requests` headers.
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        self.insert(headers, Unit::Requests);
+    }
+
+    fn insert(&self, headers: &mut HeaderMap, unit: Unit) {
+        let [limit, remaining, reset] = unit.headers();
+        headers.insert(limit, HeaderValue::from(self.limit));
+        headers.insert(remaining, HeaderValue::from(self.remaining));
+        if let Ok(value) = HeaderValue::from_str(&format_reset(self.reset)) {
+            headers.insert(reset, value);
+        }
+        if self.soft {
+            headers.insert("x-ratelimit-warning", HeaderValue::from_static(unit.warning()));
+        }
+    }
+}
+
+/// What a limit counts, as named in `x-ratelimit-*` headers.
+#[derive(Clone, Copy)]
+enum Unit {
+    Requests,
+    Tokens,
+}
+
+impl Unit {
+    /// The limit, remaining and reset headers.
+    fn headers(self) -> [&'static str; 3] {
+        match self {
+            Unit::Requests => [
+                "x-ratelimit-limit-requests",
+                "x-ratelimit-remaining-requests",
+                "x-ratelimit-reset-requests",
+            ],
+            Unit::Tokens => [
+                "x-ratelimit-limit-tokens",
+                "x-ratelimit-remaining-tokens",
+                "x-ratelimit-reset-tokens",
+            ],
+        }
+    }
+
+    fn warning(self) -> &'static str {
+        match self {
+            Unit::Requests => "approaching request rate limit",
+            Unit::Tokens => "approaching token rate limit",
+        }
+    }
+}
+
+struct Bucket {
+    tokens: f64,
+    updated: Instant,
+}
+
+/// Token bucket spacing requests out to each backend.
+pub struct Smoother {
+    rate: Option<f64>,
+    burst: f64,
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl Smoother {
+    pub fn new(config: &LimitsConfig) -> Self {
+        Smoother {
+            rate: config.upstream_rps.filter(|r| *r > 0.0),
+            burst: f64::from(config.upstream_burst.max(1)),
+            buckets: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Drops buckets that have refilled completely; returns how many.
+    pub fn purge_idle(&self) -> usize {
+        let Some(rate) = self.rate else {
+            return 0;
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let before = buckets.len();
+        buckets.retain(|_, b| b.tokens + now.duration_since(b.updated).as_secs_f64() * rate < self.burst);
+        before - buckets.len()
+    }
+
+    /// Takes a slot for one request to `backend` and returns how long to wait
+    /// before sending it. Slots are reserved up front, so concurrent callers
+    /// queue behind each other rather than racing for the same token.
+    pub fn reserve(&self, backend: &str) -> Duration {
+        let Some(rate) = self.rate else {
+            return Duration::ZERO;
+        };
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        let bucket = buckets.entry(backend.to_string()).or_insert(Bucket {
+            tokens: self.burst,
+            updated: now,
+        });
+        let refill = now.duration_since(bucket.updated).as_secs_f64() * rate;
+        bucket.tokens = (bucket.tokens + refill).min(self.burst) - 1.0;
+        bucket.updated = now;
+        if bucket.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            Duration::from_secs_f64(-bucket.tokens / rate)
+        }
+    }
+}
+
+/// An amount drawn from one per-minute budget.
+#[derive(Debug, Clone)]
+pub struct Charge {
+    pub bucket: String,
+    pub per_minute: u64,
+    pub amount: u64,
+    /// Names the budget in errors, e.g. `tokens per minute`.
+    pub what: String,
+    /// The amount is an estimate of tokens, settled once usage is known.
+    pub tokens: bool,
+}
+
+impl Charge {
+    fn new(bucket: String, per_minute: u64, amount: u64, what: &str) -> Self {
+        Charge {
+            bucket,
+            per_minute,
+            amount,
+            what: what.to_string(),
+            tokens: false,
+        }
+    }
+
+    fn tokens(bucket: String, per_minute: u64, amount: u64, what: &str) -> Self {
+        Charge {
+            tokens: true,
+            ..Charge::new(bucket, per_minute, amount, what)
+        }
+    }
+}
+
+/// A request's token charges, drawn up front from an estimate and settled
+/// against the usage its answers report. The first answer settles the
+/// estimate; any later one, such as a post-edit stage, adds its own usage.
+pub struct Settlement {
+    charges: Vec<Charge>,
+    settled: AtomicBool,
+}
+
+impl Settlement {
+    /// `None` when no token budget was charged.
+    pub fn new(charges: &[Charge]) -> Option<Self> {
+        let charges: Vec<Charge> = charges.iter().filter(|c| c.tokens).cloned().collect();
+        (!charges.is_empty()).then(|| Settlement {
+            charges,
+            settled: AtomicBool::new(false),
+        })
+    }
+
+    pub fn settle(&self, budgets: &Budgets, used: u64) {
+        let estimated = !self.settled.swap(true, Ordering::AcqRel);
+        budgets.settle(&self.charges, estimated, used);
+    }
+}
+
+/// A budget that was not enough for a request.
+#[derive(Debug)]
+pub struct BudgetExceeded {
+    pub charge: Charge,
+    pub retry_after: Duration,
+}
+
+impl BudgetExceeded {
+    pub fn into_response(self) -> Response<Body> {
+        let mut response = create_error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            "rate_limit_exceeded",
+            &format!(
+                "Rate limit of {} {} reached; retry in {}",
+                self.charge.per_minute,
+                self.charge.what,
+                format_reset(self.retry_after)
+            ),
+        );
+        let secs = self.retry_after.as_secs_f64().ceil().max(1.0) as u64;
+        response.headers_mut().insert(header::RETRY_AFTER, HeaderValue::from(secs));
+        response
+    }
+}
+
+/// Token buckets for per-minute request and token budgets, each holding
+/// up to a minute's worth and refilling continuously. Requests larger than
+/// a whole budget are let through once it is full, and empty it.
+#[derive(Default)]
+pub struct Budgets {
+    buckets: Mutex<HashMap<String, Bucket>>,
+}
+
+impl Budgets {
+    /// Draws every charge, or none when any budget falls short.
+    pub fn take(&self, charges: &[Charge]) -> Result<(), BudgetExceeded> {
+        if charges.is_empty() {
+            return Ok(());
+        }
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        for charge in charges {
+            let capacity = charge.per_minute as f64;
+            let bucket = buckets.entry(charge.bucket.clone()).or_insert(Bucket {
+                tokens: capacity,
+                updated: now,
+            });
+            let rate = capacity / WINDOW.as_secs_f64();
+            let refill = now.duration_since(bucket.updated).as_secs_f64() * rate;
+            bucket.tokens = (bucket.tokens + refill).min(capacity);
+            bucket.updated = now;
+            let missing = (charge.amount as f64).min(capacity) - bucket.tokens;
+            if missing > 0.0 {
+                return Err(BudgetExceeded {
+                    charge: charge.clone(),
+                    retry_after: Duration::from_secs_f64(missing / rate.max(f64::MIN_POSITIVE)),
+                });
+            }
+        }
+        for charge in charges {
+            if let Some(bucket) = buckets.get_mut(&charge.bucket) {
+                bucket.tokens -= (charge.amount as f64).min(charge.per_minute as f64);
+            }
+        }
+        Ok(())
+    }
+
+    /// Where the tightest token budget among `charges` stands: the tokens
+    /// left now and the time until it is full again.
+    pub fn status(&self, charges: &[Charge], soft_ratio: f64) -> Option<LimitStatus> {
+        let now = Instant::now();
+        let buckets = self.buckets.lock().unwrap();
+        charges
+            .iter()
+            .filter(|c| c.tokens)
+            .map(|charge| {
+                let capacity = charge.per_minute as f64;
+                let rate = capacity / WINDOW.as_secs_f64();
+                let tokens = buckets.get(&charge.bucket).map_or(capacity, |b| {
+                    (b.tokens + now.duration_since(b.updated).as_secs_f64() * rate).min(capacity)
+                });
+                let used = capacity - tokens;
+                LimitStatus {
+                    limit: charge.per_minute,
+                    remaining: tokens.max(0.0) as u64,
+                    reset: Duration::from_secs_f64(used / rate.max(f64::MIN_POSITIVE)),
+                    soft: used >= capacity * soft_ratio,
+                }
+            })
+            .min_by_key(|status| status.remaining)
+    }
+
+    /// Settles token charges against `used`: gives back what was drawn for
+    /// the estimate, if `estimated`, and draws what was used. A request that
+    /// used more than it was charged leaves the budget owing, by up to a
+    /// minute's worth.
+    pub fn settle(&self, charges: &[Charge], estimated: bool, used: u64) {
+        let mut buckets = self.buckets.lock().unwrap();
+        for charge in charges {
+            let Some(bucket) = buckets.get_mut(&charge.bucket) else {
+                continue;
+            };
+            let drawn = if estimated { charge.amount.min(charge.per_minute) } else { 0 };
+            let capacity = charge.per_minute as f64;
+            bucket.tokens = (bucket.tokens + drawn as f64 - used as f64).clamp(-capacity, capacity);
+        }
+    }
+
+    /// Buckets that have not refilled yet.
+    pub fn export(&self) -> Vec<BudgetLevel> {
+        let now = Instant::now();
+        self.buckets
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, b)| now.duration_since(b.updated) < WINDOW)
+            .map(|(bucket, b)| BudgetLevel {
+                bucket: bucket.clone(),
+                tokens: b.tokens,
+                age_ms: now.duration_since(b.updated).as_millis() as u64,
+            })
+            .collect()
+    }
+
+    pub fn import(&self, levels: &[BudgetLevel]) {
+        let now = Instant::now();
+        let mut buckets = self.buckets.lock().unwrap();
+        for level in levels {
+            let Some(updated) = now.checked_sub(Duration::from_millis(level.age_ms)) else {
+                continue;
+            };
+            buckets.insert(level.bucket.clone(), Bucket { tokens: level.tokens, updated });
+        }
+    }
+
+    /// Drops buckets idle for long enough to have refilled; returns how many.
+    pub fn purge_idle(&self) -> usize {
+        let mut buckets = self.buckets.lock().unwrap();
+        let before = buckets.len();
+        buckets.retain(|_, b| b.updated.elapsed() < WINDOW);
+        before - buckets.len()
+    }
+}
+
+struct Window {
+    started: Instant,
+    count: u64,
+}
+
+/// Fixed one-minute request windows per client key, held by fingerprint.
+pub struct RateLimiter {
+    config: LimitsConfig,
+    windows: Mutex<HashMap<String, Window>>,
+}
+
+impl RateLimiter {
+    pub fn new(config: LimitsConfig) -> Self {
+        RateLimiter {
+            config,
+            windows: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn export(&self) -> Vec<AgedCount> {
+        let now = Instant::now();
+        self.windows
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|(_, w)| now.duration_since(w.started) < WINDOW)
+            .map(|(key, w)| AgedCount {
+                key: key.clone(),
+                count: w.count,
+                age_ms: now.duration_since(w.started).as_millis() as u64,
+            })
+            .collect()
+    }
+
+    pub fn import(&self, counts: &[AgedCount]) {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        for entry in counts {
+            let Some(started) = now.checked_sub(Duration::from_millis(entry.age_ms)) else {
+                continue;
+            };
+            windows.insert(entry.key.clone(), Window { started, count: entry.count });
+        }
+    }
+
+    /// Drops windows that have already ended; returns how many.
+    pub fn purge_expired(&self) -> usize {
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+        let before = windows.len();
+        windows.retain(|_, w| now.duration_since(w.started) < WINDOW);
+        before - windows.len()
+    }
+
+    /// Counts a request for `key` against `limit`, or the configured default.
+    /// `None` when unlimited, `Err` when the hard limit is exceeded.
+    pub fn check(&self, key: &str, limit: Option<u64>) -> Option<Result<LimitStatus, LimitStatus>> {
+        let limit = limit.or(self.config.requests_per_minute)?;
+        let now = Instant::now();
+        let mut windows = self.windows.lock().unwrap();
+
+        if windows.len() > 4096 {
+            windows.retain(|_, w| now.duration_since(w.started) < WINDOW);
+        }
+        let window = windows.entry(fingerprint(key)).or_insert(Window {
+            started: now,
+            count: 0,
+        });
+        if now.duration_since(window.started) >= WINDOW {
+            window.started = now;
+            window.count = 0;
+        }
+
+        let reset = WINDOW.saturating_sub(now.duration_since(window.started));
+        if window.count >= limit {
+            return Some(Err(LimitStatus {
+                limit,
+                remaining: 0,
+                reset,
+                soft: true,
+            }));
+        }
+
+        window.count += 1;
+        Some(Ok(LimitStatus {
+            limit,
+            remaining: limit - window.count,
+            reset,
+            soft: window.count as f64 >= limit as f64 * self.config.soft_ratio,
+        }))
+    }
+}
+
+/// Provider-specific rate-limit headers and the OpenAI header they map to.
+const UPSTREAM_ALIASES: &[(&str, &[&str])] = &[
+    (
+        "x-ratelimit-limit-requests",
+        &["anthropic-ratelimit-requests-limit", "ratelimit-limit"],
+    ),
+    (
+        "x-ratelimit-remaining-requests",
+        &["anthropic-ratelimit-requests-remaining", "ratelimit-remaining"],
+    ),
+    ("x-ratelimit-limit-tokens", &["anthropic-ratelimit-tokens-limit"]),
+    ("x-ratelimit-remaining-tokens", &["anthropic-ratelimit-tokens-remaining"]),
+];
+
+/// Normalizes upstream rate-limit headers to OpenAI's names and merges them
+/// with the adapter's own request limit and token budgets, reporting
+/// whichever is tighter.
+pub fn merge_upstream(headers: &mut HeaderMap, adapter: Option<&LimitStatus>, tokens: Option<&LimitStatus>) {
+    for (openai, aliases) in UPSTREAM_ALIASES {
+        if headers.contains_key(*openai) {
+            continue;
+        }
+        let value = aliases.iter().find_map(|alias| header_u64(headers, alias));
+        if let Some(value) = value {
+            headers.insert(*openai, HeaderValue::from(value));
+        }
+    }
+    if !headers.contains_key("x-ratelimit-reset-requests") {
+        if let Some(seconds) = header_u64(headers, "ratelimit-reset") {
+            if let Ok(reset) = HeaderValue::from_str(&format_reset(Duration::from_secs(seconds))) {
+                headers.insert("x-ratelimit-reset-requests", reset);
+            }
+        }
+    }
+
+    for (status, unit) in [(adapter, Unit::Requests), (tokens, Unit::Tokens)] {
+        let Some(status) = status else {
+            continue;
+        };
+        match header_u64(headers, unit.headers()[1]) {
+            Some(upstream) if upstream < status.remaining => {
+                if status.soft {
+                    headers.insert("x-ratelimit-warning", HeaderValue::from_static(unit.warning()));
+                }
+            }
+            _ => status.insert(headers, unit),
+        }
+    }
+}
+
+/// Reads the leading integer of a header (`100` in `100, 100;w=60`).
+fn header_u64(headers: &HeaderMap, name: &str) -> Option<u64> {
+    let value = headers.get(name)?.to_str().ok()?.trim();
+    let digits: String = value.chars().take_while(|c| c.is_ascii_digit()).collect();
+    digits.parse().ok()
+}
+
+/// Formats a duration the way OpenAI does in `x-ratelimit-reset-*` (`20ms`, `6m0s`).
+pub fn format_reset(duration: Duration) -> String {
+    let millis = duration.as_millis();
+    if millis < 1000 {
+        format!("{}ms", millis)
+    } else if millis < 60_000 {
+        format!("{}s", duration.as_secs())
+    } else {
+        format!("{}m{}s", duration.as_secs() / 60, duration.as_secs() % 60)
+    }
+}
@@ -0,0 +1,275 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{header, HeaderMap, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use crate::AppState;
+
+/// `[errors]`: error messages in the client's `Accept-Language`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ErrorsConfig {
+    #[serde(default = "default_true")]
+    pub localize: bool,
+    /// Messages by locale and error type, added to or replacing the
+    /// built-in ones, e.g. `[errors.messages.nl] rate_limit_exceeded = "..."`.
+    #[serde(default)]
+    pub messages: HashMap<String, HashMap<String, String>>,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for ErrorsConfig {
+    fn default() -> Self {
+        ErrorsConfig {
+            localize: default_true(),
+            messages: HashMap::new(),
+        }
+    }
+}
+
+const BUILTIN: &[(&str, &[(&str, &str)])] = &[
+    (
+        "de",
+        &[
+            ("rate_limit_exceeded", "Ratenlimit erreicht. Bitte später erneut versuchen."),
+            ("invalid_api_key", "Ungültiger oder fehlender API-Schlüssel."),
+            ("authentication_required", "Authentifizierung erforderlich."),
+            ("model_not_allowed", "Dieser Schlüssel darf das angeforderte Modell nicht verwenden."),
+            ("invalid_request_error", "Die Anfrage ist ungültig."),
+            ("backend_unavailable", "Das Backend ist vorübergehend nicht verfügbar."),
+            ("maintenance", "Der Dienst wird gewartet. Bitte später erneut versuchen."),
+            ("overloaded", "Der Dienst ist überlastet. Bitte später erneut versuchen."),
+            ("tenant_quota_exceeded", "Ihr Kontingent ist erschöpft."),
+            ("response_too_large", "Die Antwort ist zu groß."),
+            ("Upstream timeout", "Der Upstream-Dienst hat nicht rechtzeitig geantwortet."),
+            (
+                "Failed to forward request",
+                "Die Anfrage konnte nicht an den Upstream-Dienst weitergeleitet werden.",
+            ),
+            ("not_found", "Nicht gefunden."),
+            ("unsupported_api_version", "Die angeforderte API-Version wird nicht unterstützt."),
+        ],
+    ),
+    (
+        "fr",
+        &[
+            ("rate_limit_exceeded", "Limite de débit atteinte. Veuillez réessayer plus tard."),
+            ("invalid_api_key", "Clé API invalide ou manquante."),
+            ("authentication_required", "Authentification requise."),
+            ("model_not_allowed", "Cette clé ne peut pas utiliser le modèle demandé."),
+            ("invalid_request_error", "La requête est invalide."),
+            ("backend_unavailable", "Le backend est temporairement indisponible."),
+            ("maintenance", "Le service est en maintenance. Veuillez réessayer plus tard."),
+            ("overloaded", "Le service est surchargé. Veuillez réessayer plus tard."),
+            ("tenant_quota_exceeded", "Votre quota est dépassé."),
+            ("response_too_large", "La réponse est trop volumineuse."),
+            ("Upstream timeout", "Le service en amont n'a pas répondu à temps."),
+            (
+                "Failed to forward request",
+                "La requête n'a pas pu être transmise au service en amont.",
+            ),
+            ("not_found", "Introuvable."),
+            ("unsupported_api_version", "La version d'API demandée n'est pas prise en charge."),
+        ],
+    ),
+    (
+        "es",
+        &[
+            (
+                "rate_limit_exceeded",
+                "Se alcanzó el límite de solicitudes. Vuelva a intentarlo más tarde.",
+            ),
+            ("invalid_api_key", "Clave de API no válida o ausente."),
+            ("authentication_required", "Se requiere autenticación."),
+            ("model_not_allowed", "Esta clave no puede usar el modelo solicitado."),
+            ("invalid_request_error", "La solicitud no es válida."),
+            ("backend_unavailable", "El backend no está disponible temporalmente."),
+            ("maintenance", "El servicio está en mantenimiento. Vuelva a intentarlo más tarde."),
+            ("overloaded", "El servicio está sobrecargado. Vuelva a intentarlo más tarde."),
+            ("tenant_quota_exceeded", "Se ha superado su cuota."),
+            ("response_too_large", "La respuesta es demasiado grande."),
+            ("Upstream timeout", "El servicio de origen no respondió a tiempo."),
+            (
+                "Failed to forward request",
+                "No se pudo reenviar la solicitud al servicio de origen.",
+            ),
+            ("not_found", "No encontrado."),
+            ("unsupported_api_version", "La versión de API solicitada no es compatible."),
+        ],
+    ),
+    (
+        "ja",
+        &[
+            ("rate_limit_exceeded", "レート制限に達しました。しばらくしてから再試行してください。"),
+            ("invalid_api_key", "APIキーが無効か、指定されていません。"),
+            ("authentication_required", "認証が必要です。"),
+            ("model_not_allowed", "このキーでは要求されたモデルを使用できません。"),
+            ("invalid_request_error", "リクエストが無効です。"),
+            ("backend_unavailable", "バックエンドは一時的に利用できません。"),
+            ("maintenance", "サービスはメンテナンス中です。しばらくしてから再試行してください。"),
+            ("overloaded", "サービスが過負荷状態です。しばらくしてから再試行してください。"),
+            ("tenant_quota_exceeded", "クォータを超過しました。"),
+            ("response_too_large", "レスポンスが大きすぎます。"),
+            ("Upstream timeout", "上流サービスがタイムアウトしました。"),
+            ("Failed to forward request", "リクエストを上流サービスに転送できませんでした。"),
+            ("not_found", "見つかりません。"),
+            ("unsupported_api_version", "要求されたAPIバージョンはサポートされていません。"),
+        ],
+    ),
+    (
+        "zh",
+        &[
+            ("rate_limit_exceeded", "已达到速率限制，请稍后重试。"),
+            ("invalid_api_key", "API 密钥无效或缺失。"),
+            ("authentication_required", "需要身份验证。"),
+            ("model_not_allowed", "此密钥无权使用所请求的模型。"),
+            ("invalid_request_error", "请求无效。"),
+            ("backend_unavailable", "后端暂时不可用。"),
+            ("maintenance", "服务正在维护，请稍后重试。"),
+            ("overloaded", "服务过载，请稍后重试。"),
+            ("tenant_quota_exceeded", "您的配额已用尽。"),
+            ("response_too_large", "响应过大。"),
+            ("Upstream timeout", "上游服务超时。"),
+            ("Failed to forward request", "无法将请求转发到上游服务。"),
+            ("not_found", "未找到。"),
+            ("unsupported_api_version", "不支持所请求的 API 版本。"),
+        ],
+    ),
+];
+
+/// Error messages by locale and error type.
+pub struct ErrorCatalog {
+    enabled: bool,
+    messages: HashMap<String, HashMap<String, String>>,
+}
+
+impl ErrorCatalog {
+    pub fn new(config: &ErrorsConfig) -> Self {
+        let mut messages: HashMap<String, HashMap<String, String>> = BUILTIN
+            .iter()
+            .map(|(locale, entries)| {
+                let entries = entries.iter().map(|(t, m)| (t.to_string(), m.to_string())).collect();
+                (locale.to_string(), entries)
+            })
+            .collect();
+        for (locale, entries) in &config.messages {
+            messages
+                .entry(locale.to_lowercase())
+                .or_default()
+                .extend(entries.iter().map(|(t, m)| (t.clone(), m.clone())));
+        }
+        ErrorCatalog {
+            enabled: config.localize,
+            messages,
+        }
+    }
+
+    /// The catalog locale best matching `Accept-Language`, or `None` when
+    /// the client prefers English or nothing the catalog has.
+    pub fn negotiate(&self, headers: &HeaderMap) -> Option<String> {
+        if !self.enabled {
+            return None;
+        }
+        let accepted = headers.get(header::ACCEPT_LANGUAGE)?.to_str().ok()?;
+        let mut ranges: Vec<(String, f64)> = accepted
+            .split(',')
+            .filter_map(|range| {
+                let mut parts = range.split(';');
+                let tag = parts.next()?.trim().to_lowercase();
+                let q = parts
+                    .find_map(|p| p.trim().strip_prefix("q="))
+                    .map_or(Some(1.0), |q| q.trim().parse::<f64>().ok())?;
+                (!tag.is_empty() && tag != "*" && q > 0.0).then_some((tag, q))
+            })
+            .collect();
+        ranges.sort_by(|a, b| b.1.total_cmp(&a.1));
+        for (tag, _) in ranges {
+            let primary = tag.split('-').next().unwrap_or(&tag).to_string();
+            if primary == "en" {
+                return None;
+            }
+            if self.messages.contains_key(&tag) {
+                return Some(tag);
+            }
+            if self.messages.contains_key(&primary) {
+                return Some(primary);
+            }
+        }
+        None
+    }
+
+    /// Replaces `error.message` of an error body with the localized message
+    /// for its `code` or `type`, keeping the original as
+    /// `x_original_message`. Returns whether there was one.
+    pub fn localize(&self, body: &mut Value, locale: &str) -> bool {
+        let Some(error) = body.get_mut("error").and_then(Value::as_object_mut) else {
+            return false;
+        };
+        let kind = error
+            .get("code")
+            .and_then(Value::as_str)
+            .filter(|code| self.message(locale, code).is_some())
+            .or(error.get("type").and_then(Value::as_str));
+        let Some(message) = kind.and_then(|kind| self.message(locale, kind)) else {
+            return false;
+        };
+        let message = Value::String(message.to_string());
+        if let Some(original) = error.insert("message".to_string(), message) {
+            error.insert("x_original_message".to_string(), original);
+        }
+        true
+    }
+
+    fn message(&self, locale: &str, kind: &str) -> Option<&str> {
+        self.messages.get(locale)?.get(kind).map(String::as_str)
+    }
+}
+
+/// Middleware localizing JSON error responses. Signed responses are left
+/// alone, as are streamed errors.
+pub async fn localize_errors(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let locale = state.errors.negotiate(request.headers());
+    let response = next.run(request).await;
+    let Some(locale) = locale else {
+        return response;
+    };
+    let json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    let status = response.status();
+    if !(status.is_client_error() || status.is_server_error())
+        || !json
+        || response.headers().contains_key("x-llmta-signature")
+    {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap_or_default();
+    let Ok(mut value) = serde_json::from_slice::<Value>(&bytes) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    if !state.errors.localize(&mut value, &locale) {
+        return Response::from_parts(parts, Body::from(bytes));
+    }
+    parts.headers.remove(header::CONTENT_LENGTH);
+    if let Ok(language) = HeaderValue::from_str(&locale) {
+        parts.headers.insert(header::CONTENT_LANGUAGE, language);
+    }
+    Response::from_parts(parts, Body::from(value.to_string()))
+}
@@ -0,0 +1,57 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::sync::RwLock;
+
+/// Name of the backend configured by `model_url` / `model_key`.
+pub const DEFAULT_BACKEND: &str = "default";
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MaintenanceMode {
+    #[serde(default = "default_message")]
+    pub message: String,
+    #[serde(default = "default_retry_after")]
+    pub retry_after_secs: u64,
+}
+
+fn default_message() -> String {
+    "The service is undergoing maintenance, please retry later".to_string()
+}
+
+fn default_retry_after() -> u64 {
+    60
+}
+
+/// Operator switches: global maintenance mode and per-backend draining.
+/// Draining only stops new requests; streams already running are left alone.
+#[derive(Default)]
+pub struct Maintenance {
+    mode: RwLock<Option<MaintenanceMode>>,
+    drained: RwLock<HashSet<String>>,
+}
+
+impl Maintenance {
+    pub fn mode(&self) -> Option<MaintenanceMode> {
+        self.mode.read().unwrap().clone()
+    }
+
+    pub fn set_mode(&self, mode: Option<MaintenanceMode>) {
+        *self.mode.write().unwrap() = mode;
+    }
+
+    pub fn is_drained(&self, backend: &str) -> bool {
+        self.drained.read().unwrap().contains(backend)
+    }
+
+    pub fn drained(&self) -> Vec<String> {
+        self.drained.read().unwrap().iter().cloned().collect()
+    }
+
+    pub fn set_drained(&self, backend: &str, drained: bool) {
+        let mut set = self.drained.write().unwrap();
+        if drained {
+            set.insert(backend.to_string());
+        } else {
+            set.remove(backend);
+        }
+    }
+}
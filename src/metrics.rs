@@ -0,0 +1,314 @@
+use axum::{
+    body::Body,
+    extract::State,
+    http::{header, StatusCode},
+    response::Response,
+};
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::fmt::Write;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use crate::create_error_response;
+use crate::AppState;
+
+/// Upper bounds of the upstream latency buckets, in seconds.
+const LATENCY_BUCKETS: &[f64] = &[0.05, 0.1, 0.25, 0.5, 1.0, 2.5, 5.0, 10.0, 30.0, 60.0];
+
+/// `[metrics]`: Prometheus metrics at `/metrics`.
+#[derive(Debug, Deserialize, Clone)]
+pub struct MetricsConfig {
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+impl Default for MetricsConfig {
+    fn default() -> Self {
+        MetricsConfig {
+            enabled: default_true(),
+        }
+    }
+}
+
+#[derive(Default)]
+struct Histogram {
+    /// Observations per bucket of `LATENCY_BUCKETS`, not cumulative.
+    buckets: Vec<u64>,
+    count: u64,
+    sum: f64,
+}
+
+impl Histogram {
+    fn observe(&mut self, value: f64) {
+        if self.buckets.is_empty() {
+            self.buckets = vec![0; LATENCY_BUCKETS.len()];
+        }
+        if let Some(bucket) = LATENCY_BUCKETS.iter().position(|le| value <= *le) {
+            self.buckets[bucket] += 1;
+        }
+        self.count += 1;
+        self.sum += value;
+    }
+}
+
+#[derive(Default)]
+struct Registry {
+    /// By route, model and status.
+    requests: BTreeMap<(String, String, u16), u64>,
+    /// By backend and status, `0` standing for a transport failure.
+    upstream_responses: BTreeMap<(String, u16), u64>,
+    upstream_latency: BTreeMap<(String, String), Histogram>,
+    retries: BTreeMap<String, u64>,
+    streamed_tokens: BTreeMap<String, u64>,
+    /// By model and `prompt` or `completion`.
+    tokens: BTreeMap<(String, &'static str), u64>,
+    panics: u64,
+}
+
+/// Counters and histograms for the chat path, rendered in the Prometheus
+/// text format.
+#[derive(Default)]
+pub struct Metrics {
+    registry: Mutex<Registry>,
+}
+
+/// Escapes a label value for the text format.
+fn label(value: &str) -> String {
+    value.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
+
+fn family(out: &mut String, name: &str, kind: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, help);
+    let _ = writeln!(out, "# TYPE {} {}", name, kind);
+}
+
+impl Metrics {
+    /// Counts a chat request by the status the client got.
+    pub fn record_request(&self, route: &str, model: &str, status: StatusCode) {
+        let mut registry = self.registry.lock().unwrap();
+        *registry
+            .requests
+            .entry((route.to_string(), model.to_string(), status.as_u16()))
+            .or_default() += 1;
+    }
+
+    /// Records the final upstream attempt of a request: its status, or
+    /// `None` for a transport failure, and the time to response headers
+    /// across all attempts.
+    pub fn record_upstream(
+        &self,
+        backend: &str,
+        model: &str,
+        status: Option<u16>,
+        latency: Duration,
+    ) {
+        let mut registry = self.registry.lock().unwrap();
+        *registry
+            .upstream_responses
+            .entry((backend.to_string(), status.unwrap_or(0)))
+            .or_default() += 1;
+        registry
+            .upstream_latency
+            .entry((backend.to_string(), model.to_string()))
+            .or_default()
+            .observe(latency.as_secs_f64());
+    }
+
+    pub fn record_retries(&self, backend: &str, retries: u32) {
+        if retries > 0 {
+            let mut registry = self.registry.lock().unwrap();
+            *registry.retries.entry(backend.to_string()).or_default() += u64::from(retries);
+        }
+    }
+
+    /// Counts tokens as they are streamed, estimated from the text.
+    pub fn record_streamed(&self, model: &str, text: &str) {
+        let tokens = text.chars().count().div_ceil(4) as u64;
+        if tokens > 0 {
+            let mut registry = self.registry.lock().unwrap();
+            *registry.streamed_tokens.entry(model.to_string()).or_default() += tokens;
+        }
+    }
+
+    /// Adds a completion's `usage` to the per-model token counts.
+    pub fn record_usage(&self, model: &str, usage: Option<&Value>) {
+        let Some(usage) = usage else {
+            return;
+        };
+        let mut registry = self.registry.lock().unwrap();
+        for (kind, field) in [("prompt", "prompt_tokens"), ("completion", "completion_tokens")] {
+            if let Some(tokens) = usage[field].as_u64() {
+                *registry.tokens.entry((model.to_string(), kind)).or_default() += tokens;
+            }
+        }
+    }
+
+    /// Counts a handler that panicked and was answered with a 500.
+    pub fn record_panic(&self) {
+        self.registry.lock().unwrap().panics += 1;
+    }
+
+    pub fn render(&self) -> String {
+        let registry = self.registry.lock().unwrap();
+        let mut out = String::new();
+
+        family(
+            &mut out,
+            "llmta_requests_total",
+            "counter",
+            "Chat requests by route, model and response status.",
+        );
+        for ((route, model, status), count) in &registry.requests {
+            let _ = writeln!(
+                out,
+                "llmta_requests_total{{route=\"{}\",model=\"{}\",status=\"{}\"}} {}",
+                label(route),
+                label(model),
+                status,
+                count
+            );
+        }
+
+        family(
+            &mut out,
+            "llmta_upstream_responses_total",
+            "counter",
+            "Final upstream attempts by backend and status; status 0 is a transport failure.",
+        );
+        for ((backend, status), count) in &registry.upstream_responses {
+            let _ = writeln!(
+                out,
+                "llmta_upstream_responses_total{{backend=\"{}\",status=\"{}\"}} {}",
+                label(backend),
+                status,
+                count
+            );
+        }
+
+        family(
+            &mut out,
+            "llmta_upstream_latency_seconds",
+            "histogram",
+            "Time to upstream response headers, including retries.",
+        );
+        for ((backend, model), histogram) in &registry.upstream_latency {
+            let labels = format!("backend=\"{}\",model=\"{}\"", label(backend), label(model));
+            let mut cumulative = 0;
+            for (le, count) in LATENCY_BUCKETS.iter().zip(&histogram.buckets) {
+                cumulative += count;
+                let _ = writeln!(
+                    out,
+                    "llmta_upstream_latency_seconds_bucket{{{},le=\"{}\"}} {}",
+                    labels, le, cumulative
+                );
+            }
+            let _ = writeln!(
+                out,
+                "llmta_upstream_latency_seconds_bucket{{{},le=\"+Inf\"}} {}",
+                labels, histogram.count
+            );
+            let _ = writeln!(
+                out,
+                "llmta_upstream_latency_seconds_sum{{{}}} {}",
+                labels, histogram.sum
+            );
+            let _ = writeln!(
+                out,
+                "llmta_upstream_latency_seconds_count{{{}}} {}",
+                labels, histogram.count
+            );
+        }
+
+        family(
+            &mut out,
+            "llmta_upstream_retries_total",
+            "counter",
+            "Upstream attempts retried, by backend.",
+        );
+        for (backend, count) in &registry.retries {
+            let _ = writeln!(
+                out,
+                "llmta_upstream_retries_total{{backend=\"{}\"}} {}",
+                label(backend),
+                count
+            );
+        }
+
+        family(
+            &mut out,
+            "llmta_streamed_tokens_total",
+            "counter",
+            "Tokens streamed to clients by model, estimated from the text.",
+        );
+        for (model, count) in &registry.streamed_tokens {
+            let _ = writeln!(
+                out,
+                "llmta_streamed_tokens_total{{model=\"{}\"}} {}",
+                label(model),
+                count
+            );
+        }
+
+        family(
+            &mut out,
+            "llmta_tokens_total",
+            "counter",
+            "Tokens reported by backends, by model and kind.",
+        );
+        for ((model, kind), count) in &registry.tokens {
+            let _ = writeln!(
+                out,
+                "llmta_tokens_total{{model=\"{}\",kind=\"{}\"}} {}",
+                label(model),
+                kind,
+                count
+            );
+        }
+
+        family(
+            &mut out,
+            "llmta_panics_total",
+            "counter",
+            "Requests whose handler panicked and were answered with a 500.",
+        );
+        let _ = writeln!(out, "llmta_panics_total {}", registry.panics);
+        out
+    }
+}
+
+/// Circuit breaker state of every backend, read when scraped.
+fn render_circuits(out: &mut String, state: &AppState) {
+    family(
+        out,
+        "llmta_circuit_state",
+        "gauge",
+        "Circuit breaker state by backend: 0 closed, 1 half-open, 2 open.",
+    );
+    for backend in state.backends.all() {
+        let _ = writeln!(
+            out,
+            "llmta_circuit_state{{backend=\"{}\"}} {}",
+            label(&backend.name),
+            backend.breaker.state().gauge()
+        );
+    }
+}
+
+/// `GET /metrics`
+pub async fn handle_metrics(State(state): State<Arc<AppState>>) -> Response<Body> {
+    if !state.config.metrics.enabled {
+        return create_error_response(StatusCode::NOT_FOUND, "not_found", "Metrics are disabled");
+    }
+    let mut body = state.metrics.render();
+    render_circuits(&mut body, &state);
+    Response::builder()
+        .header(header::CONTENT_TYPE, "text/plain; version=0.0.4")
+        .body(Body::from(body))
+        .unwrap()
+}
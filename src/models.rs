@@ -0,0 +1,92 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::backends::Backend;
+use crate::AppState;
+
+/// `[models]`: what `GET /v1/models` lists.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ModelsConfig {
+    /// Also list the models OpenAI-compatible backends report themselves.
+    #[serde(default)]
+    pub merge_upstream: bool,
+}
+
+fn model(id: &str, owned_by: &str) -> Value {
+    json!({ "id": id, "object": "model", "created": 0, "owned_by": owned_by })
+}
+
+/// Model entries from a backend's own `/models`; failures only cost the merge.
+async fn upstream_models(backend: &Backend) -> Vec<Value> {
+    let mut headers = reqwest::header::HeaderMap::new();
+    backend.provider.authorize(&mut headers, &backend.key);
+    let url = format!("{}/models", backend.base_url());
+    let response = backend
+        .client
+        .get(&url)
+        .headers(headers)
+        .timeout(Duration::from_secs(10))
+        .send()
+        .await;
+    let listed = match response {
+        Ok(response) if response.status().is_success() => response.json::<Value>().await.ok(),
+        Ok(response) => {
+            println!("Not merging models from {}: {}", url, response.status());
+            None
+        }
+        Err(e) => {
+            println!("Not merging models from {}: {}", url, e);
+            None
+        }
+    };
+    listed
+        .and_then(|list| list.get("data").and_then(Value::as_array).cloned())
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|m| m.get("id").is_some_and(Value::is_string))
+        .collect()
+}
+
+/// `GET /v1/models`: the configured model names in OpenAI's list format.
+/// Wildcard patterns such as `gpt-*` cannot be listed and are left out.
+pub async fn handle_models(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let peer = connect_info.map(|ConnectInfo(addr)| addr.ip());
+    if let Err(response) = state.auth.identify(&headers, peer, &Bytes::new()) {
+        return response;
+    }
+
+    let mut data: Vec<Value> = Vec::new();
+    let listed = |data: &[Value], id: &str| data.iter().any(|m| m["id"] == id);
+    for backend in state.backends.all() {
+        for id in backend.models.iter().filter(|m| !m.ends_with('*')) {
+            if !listed(&data, id) {
+                data.push(model(id, &backend.name));
+            }
+        }
+    }
+    if state.config.models.merge_upstream {
+        for backend in state.backends.all().filter(|b| b.provider.name() == "openai") {
+            for entry in upstream_models(backend).await {
+                if !listed(&data, entry["id"].as_str().unwrap_or_default()) {
+                    data.push(entry);
+                }
+            }
+        }
+    }
+
+    Json(json!({ "object": "list", "data": data })).into_response()
+}
@@ -0,0 +1,239 @@
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+use crate::translation::content_text;
+
+/// What the backend accepts in a message list. OpenAI itself is lenient;
+/// Anthropic and many local servers reject shapes OpenAI takes in stride.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Flavor {
+    #[default]
+    OpenAi,
+    /// OpenAI-compatible servers predating the `developer` role.
+    Legacy,
+    /// Strict chat templates: alternating roles starting with a user turn.
+    Strict,
+}
+
+/// What a missing or `null` message content is replaced with.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "snake_case")]
+pub enum NullContent {
+    #[default]
+    Keep,
+    EmptyString,
+    EmptyArray,
+}
+
+/// Normalization passes to run before forwarding.
+#[derive(Debug, Clone, PartialEq, Eq, Default)]
+pub struct Rules {
+    /// Role renames, e.g. `developer` -> `system`.
+    pub roles: HashMap<String, String>,
+    /// Fold all system messages into one at the start.
+    pub merge_system: bool,
+    /// Merge consecutive same-role messages and start the conversation with a user turn.
+    pub merge_roles: bool,
+    pub null_content: NullContent,
+    /// Drop messages with empty content and no tool calls.
+    pub drop_empty: bool,
+}
+
+impl Flavor {
+    pub fn rules(self) -> Rules {
+        match self {
+            Flavor::OpenAi => Rules::default(),
+            Flavor::Legacy => Rules {
+                roles: developer_as_system(),
+                ..Rules::default()
+            },
+            Flavor::Strict => Rules {
+                roles: developer_as_system(),
+                merge_system: true,
+                merge_roles: true,
+                null_content: NullContent::EmptyString,
+                drop_empty: true,
+            },
+        }
+    }
+}
+
+fn developer_as_system() -> HashMap<String, String> {
+    HashMap::from([("developer".to_string(), "system".to_string())])
+}
+
+/// `[normalize]`: per-rule overrides of the flavor defaults.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct NormalizeConfig {
+    /// Extra role renames, applied on top of the flavor's.
+    #[serde(default)]
+    pub roles: HashMap<String, String>,
+    #[serde(default)]
+    pub merge_system: Option<bool>,
+    #[serde(default)]
+    pub merge_roles: Option<bool>,
+    #[serde(default)]
+    pub null_content: Option<NullContent>,
+    #[serde(default)]
+    pub drop_empty: Option<bool>,
+}
+
+impl NormalizeConfig {
+    pub fn rules(&self, flavor: Flavor) -> Rules {
+        let defaults = flavor.rules();
+        let mut roles = defaults.roles;
+        roles.extend(self.roles.clone());
+        Rules {
+            roles,
+            merge_system: self.merge_system.unwrap_or(defaults.merge_system),
+            merge_roles: self.merge_roles.unwrap_or(defaults.merge_roles),
+            null_content: self.null_content.unwrap_or(defaults.null_content),
+            drop_empty: self.drop_empty.unwrap_or(defaults.drop_empty),
+        }
+    }
+}
+
+/// Applies `rules` to the payload's `messages`, returning a description of
+/// every change so it can be logged.
+pub fn normalize(payload: &mut Map<String, Value>, rules: &Rules) -> Vec<String> {
+    let mut changes = Vec::new();
+    let Some(Value::Array(messages)) = payload.get_mut("messages") else {
+        return changes;
+    };
+    if !rules.roles.is_empty() {
+        map_roles(messages, &rules.roles, &mut changes);
+    }
+    if rules.merge_system {
+        merge_system(messages, &mut changes);
+    }
+    // Content fixes run first: dropping empty messages can leave same-role neighbours.
+    if rules.null_content != NullContent::Keep || rules.drop_empty {
+        fix_content(messages, rules, &mut changes);
+    }
+    if rules.merge_roles {
+        merge_roles(messages, &mut changes);
+    }
+    changes
+}
+
+fn role(message: &Value) -> &str {
+    message.get("role").and_then(Value::as_str).unwrap_or("")
+}
+
+fn map_roles(messages: &mut [Value], roles: &HashMap<String, String>, changes: &mut Vec<String>) {
+    for (index, message) in messages.iter_mut().enumerate() {
+        if let Some(to) = roles.get(role(message)) {
+            changes.push(format!("mapped role {} to {} on message {}", role(message), to, index));
+            message["role"] = json!(to);
+        }
+    }
+}
+
+fn merge_system(messages: &mut Vec<Value>, changes: &mut Vec<String>) {
+    let count = messages.iter().filter(|m| role(m) == "system").count();
+    let leading = messages.first().is_some_and(|m| role(m) == "system");
+    if count == 0 || (count == 1 && leading) {
+        return;
+    }
+    let mut content: Option<Value> = None;
+    messages.retain(|m| {
+        if role(m) != "system" {
+            return true;
+        }
+        content = Some(match content.take() {
+            None => m.get("content").cloned().unwrap_or(Value::Null),
+            Some(previous) => merge_content(Some(&previous), m.get("content")),
+        });
+        false
+    });
+    changes.push(format!("merged {} system messages into one leading message", count));
+    messages.insert(0, json!({ "role": "system", "content": content }));
+}
+
+fn is_empty_content(content: Option<&Value>) -> bool {
+    match content {
+        None | Some(Value::Null) => true,
+        Some(Value::String(text)) => text.trim().is_empty(),
+        Some(Value::Array(parts)) => parts.is_empty(),
+        _ => false,
+    }
+}
+
+fn fix_content(messages: &mut Vec<Value>, rules: &Rules, changes: &mut Vec<String>) {
+    let mut index = 0;
+    messages.retain(|message| {
+        index += 1;
+        let empty = is_empty_content(message.get("content"));
+        let keep = !(rules.drop_empty && empty && mergeable(message));
+        if !keep {
+            changes.push(format!("dropped empty {} message {}", role(message), index - 1));
+        }
+        keep
+    });
+
+    for (index, message) in messages.iter_mut().enumerate() {
+        // Tool results must be plain text nearly everywhere outside OpenAI.
+        if role(message) == "tool" && message.get("content").is_some_and(Value::is_array) {
+            let text = content_text(&message["content"]);
+            message["content"] = Value::String(text);
+            changes.push(format!("flattened tool message {} content to text", index));
+        }
+        if !matches!(message.get("content"), None | Some(Value::Null)) {
+            continue;
+        }
+        let replacement = match rules.null_content {
+            NullContent::Keep => continue,
+            NullContent::EmptyString => json!(""),
+            NullContent::EmptyArray => json!([]),
+        };
+        changes.push(format!("replaced null content of {} message {}", role(message), index));
+        message["content"] = replacement;
+    }
+}
+
+/// Tool calls and results pair up by id, so those messages are never merged.
+fn mergeable(message: &Value) -> bool {
+    role(message) != "tool" && message.get("tool_calls").is_none()
+}
+
+fn merge_roles(messages: &mut Vec<Value>, changes: &mut Vec<String>) {
+    let mut merged: Vec<Value> = Vec::with_capacity(messages.len());
+    for (index, message) in messages.drain(..).enumerate() {
+        if let Some(previous) = merged.last_mut() {
+            if role(previous) == role(&message) && mergeable(previous) && mergeable(&message) {
+                changes.push(format!("merged {} message {} into the previous one", role(&message), index));
+                let content = merge_content(previous.get("content"), message.get("content"));
+                previous["content"] = content;
+                continue;
+            }
+        }
+        merged.push(message);
+    }
+
+    let first_turn = merged.iter().position(|m| role(m) != "system");
+    if let Some(position) = first_turn.filter(|&p| role(&merged[p]) != "user") {
+        changes.push(format!("inserted a user turn before the leading {} message", role(&merged[position])));
+        merged.insert(position, json!({ "role": "user", "content": "(continued)" }));
+    }
+    *messages = merged;
+}
+
+/// Joins two contents: strings with a blank line, anything else as parts.
+fn merge_content(first: Option<&Value>, second: Option<&Value>) -> Value {
+    fn parts(content: Option<&Value>) -> Vec<Value> {
+        match content {
+            Some(Value::Array(parts)) => parts.clone(),
+            Some(Value::String(text)) if !text.is_empty() => vec![json!({ "type": "text", "text": text })],
+            _ => Vec::new(),
+        }
+    }
+
+    match (first, second) {
+        (Some(Value::String(a)), Some(Value::String(b))) => Value::String(format!("{}\n\n{}", a, b)),
+        (a, Some(Value::String(b))) if parts(a).is_empty() => Value::String(b.clone()),
+        (Some(Value::String(a)), b) if parts(b).is_empty() => Value::String(a.clone()),
+        (a, b) => Value::Array(parts(a).into_iter().chain(parts(b)).collect()),
+    }
+}
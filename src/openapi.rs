@@ -0,0 +1,356 @@
+use serde_json::{json, Value};
+
+use crate::version::{API_VERSION_HEADER, SUPPORTED_VERSIONS};
+use crate::AppConfig;
+
+/// OpenAPI description of the adapter's own surface. Upstream request and
+/// response bodies are OpenAI's and only referenced, not re-described.
+pub fn spec(config: &AppConfig) -> Value {
+    let version_header = json!({
+        "name": API_VERSION_HEADER,
+        "in": "header",
+        "required": false,
+        "description": "Adapter extension API version.",
+        "schema": {
+            "type": "string",
+            "enum": SUPPORTED_VERSIONS,
+            "default": config.api.default_version(),
+        },
+    });
+    let error = json!({ "$ref": "#/components/schemas/Error" });
+
+    json!({
+        "openapi": "3.0.3",
+        "info": {
+            "title": "LLM Translator Adapter",
+            "version": config.api.default_version(),
+            "x-supported-versions": SUPPORTED_VERSIONS,
+        },
+        "paths": {
+            "/v1beta/openai/chat/completions": {
+                "post": {
+                    "summary": "OpenAI-compatible chat completion, forwarded to the configured backend",
+                    "parameters": [version_header],
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": { "$ref": "#/components/schemas/ChatRequest" } } },
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "Completion, or an SSE stream when `stream` is true",
+                            "headers": {
+                                "x-llmta-template-version": {
+                                    "description": "Template applied, as name@version",
+                                    "schema": { "type": "string" },
+                                },
+                                "x-llmta-degraded": {
+                                    "description": "Upstream failure status answered with the configured canned reply",
+                                    "schema": { "type": "integer" },
+                                },
+                            },
+                            "content": {
+                                "application/json": { "schema": { "$ref": "#/components/schemas/ChatResponse" } },
+                                "text/event-stream": { "schema": { "type": "string" } },
+                            },
+                        },
+                        "400": { "description": "Invalid request or API version", "content": { "application/json": { "schema": error } } },
+                        "429": { "description": "Rate limited, shed or over quota", "content": { "application/json": { "schema": error } } },
+                        "503": { "description": "Maintenance or draining backend", "content": { "application/json": { "schema": error } } },
+                    },
+                },
+            },
+            "/v1/estimate": {
+                "post": {
+                    "summary": "Token and cost preview for a chat request, without sending it",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": { "$ref": "#/components/schemas/EstimateRequest" } } },
+                    },
+                    "responses": {
+                        "200": { "description": "Estimate" },
+                        "400": { "description": "Malformed request", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+                    },
+                },
+            },
+            "/v1/prompts": {
+                "post": {
+                    "summary": "Store a system prompt for reference with x_prompt_id",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": {
+                            "type": "object",
+                            "required": ["content"],
+                            "properties": { "content": { "type": "string" } },
+                        } } },
+                    },
+                    "responses": {
+                        "201": { "description": "Stored; the body carries the prompt ID" },
+                        "413": { "description": "Prompt too large", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+                    },
+                },
+            },
+            "/v1/prompts/{id}": {
+                "get": {
+                    "summary": "A stored prompt",
+                    "parameters": [{ "name": "id", "in": "path", "required": true, "schema": { "type": "string" } }],
+                    "responses": { "200": { "description": "Prompt" }, "404": { "description": "Unknown or expired ID" } },
+                },
+            },
+            "/v1/models": {
+                "get": {
+                    "summary": "Configured models, in OpenAI's list format",
+                    "description": "Optionally merged with the live model lists of OpenAI-compatible backends, per [models].merge_upstream.",
+                    "responses": { "200": { "description": "Model list" } },
+                },
+            },
+            "/v1/embeddings": {
+                "post": {
+                    "summary": "Embeddings, in OpenAI's format",
+                    "description": "Passed through to OpenAI-compatible backends and translated for protocol = \"cohere\" and \"voyage\". Inputs beyond a backend's max_batch are sent in several requests and merged.",
+                    "responses": { "200": { "description": "Embedding list" } },
+                },
+            },
+            "/v1/usage": {
+                "get": {
+                    "summary": "Own hourly usage and cross-tenant totals",
+                    "description": "Totals across tenants are withheld for hours with too few active tenants and may carry Laplace noise, per the [usage.privacy] settings.",
+                    "responses": { "200": { "description": "Usage" } },
+                },
+            },
+            "/v1/feedback": {
+                "post": {
+                    "summary": "Report translation quality for an earlier request",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": {
+                            "type": "object",
+                            "required": ["request_id"],
+                            "properties": {
+                                "request_id": { "type": "string" },
+                                "score": { "type": "number", "minimum": 0, "maximum": 1 },
+                                "rating": {
+                                    "oneOf": [
+                                        { "type": "integer", "minimum": 1, "maximum": 5 },
+                                        { "type": "string", "enum": ["up", "down"] },
+                                    ],
+                                },
+                                "comment": { "type": "string", "maxLength": 2000 },
+                            },
+                        } } },
+                    },
+                    "responses": {
+                        "201": { "description": "Recorded" },
+                        "404": { "description": "Unknown request", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+                    },
+                },
+            },
+            "/v1/documents/translate": {
+                "post": {
+                    "summary": "Translate several documents, streaming progress",
+                    "description": "Documents are split into segments at paragraph breaks and translated through the chat pipeline, several documents at a time. The response is an event stream of document_started, segment_done and document_done events, ending with done.",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": {
+                            "type": "object",
+                            "required": ["target_language", "documents"],
+                            "properties": {
+                                "model": { "type": "string" },
+                                "target_language": { "type": "string" },
+                                "source_language": { "type": "string" },
+                                "documents": {
+                                    "type": "array",
+                                    "minItems": 1,
+                                    "maxItems": 100,
+                                    "items": {
+                                        "type": "object",
+                                        "required": ["text"],
+                                        "properties": { "id": { "type": "string" }, "text": { "type": "string" } },
+                                    },
+                                },
+                                "glossary": { "type": "object" },
+                                "max_segment_chars": { "type": "integer", "minimum": 20, "default": 2000 },
+                                "concurrency": { "type": "integer", "minimum": 1, "maximum": 16, "default": 4 },
+                            },
+                        } } },
+                    },
+                    "responses": {
+                        "200": { "description": "Progress events", "content": { "text/event-stream": {} } },
+                        "400": { "description": "Invalid request", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+                    },
+                },
+            },
+            "/v1/batch/translate": {
+                "post": {
+                    "summary": "Translate many texts with bounded concurrency",
+                    "description": "Texts are translated through the chat pipeline a few at a time ([batch].concurrency, up to [batch].max_concurrency), each retried on 408, 429 and 5xx answers. Results come back in order, or with stream or Accept: application/x-ndjson as one batch.item line per text as it completes, ending with a batch.summary line.",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": {
+                            "type": "object",
+                            "required": ["target_language", "texts"],
+                            "properties": {
+                                "model": { "type": "string" },
+                                "target_language": { "type": "string" },
+                                "source_language": { "type": "string" },
+                                "texts": { "type": "array", "minItems": 1, "items": { "type": "string" } },
+                                "glossary": { "type": "object" },
+                                "concurrency": { "type": "integer", "minimum": 1 },
+                                "stream": { "type": "boolean", "default": false },
+                            },
+                        } } },
+                    },
+                    "responses": {
+                        "200": {
+                            "description": "Results in order, or NDJSON lines as they complete",
+                            "content": { "application/json": {}, "application/x-ndjson": {} },
+                        },
+                        "400": { "description": "Invalid request", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+                    },
+                },
+            },
+            "/conformance": {
+                "post": {
+                    "summary": "Check which OpenAI features survive translation to a backend",
+                    "description": "Sends scripted chat requests (plain, streaming, tools, JSON mode, JSON schema, max_tokens and an invalid request) through the adapter and reports each check.",
+                    "parameters": [
+                        { "name": "backend", "in": "query", "schema": { "type": "string" } },
+                        { "name": "model", "in": "query", "schema": { "type": "string" } },
+                    ],
+                    "responses": {
+                        "200": { "description": "Conformance report" },
+                        "400": { "description": "Unknown backend, or a model it does not serve", "content": { "application/json": { "schema": { "$ref": "#/components/schemas/Error" } } } },
+                    },
+                },
+            },
+            "/v2/translate": {
+                "post": {
+                    "summary": "DeepL-compatible translation",
+                    "description": "Accepts DeepL's /v2/translate request as JSON or a form, with the key as a bearer token or DeepL-Auth-Key, and answers in DeepL's format using the chat model ([deepl].model or default_model).",
+                    "requestBody": {
+                        "required": true,
+                        "content": { "application/json": { "schema": {
+                            "type": "object",
+                            "required": ["text", "target_lang"],
+                            "properties": {
+                                "text": { "type": "array", "items": { "type": "string" }, "minItems": 1, "maxItems": 50 },
+                                "target_lang": { "type": "string" },
+                                "source_lang": { "type": "string" },
+                                "formality": { "type": "string", "enum": ["default", "more", "less", "prefer_more", "prefer_less"] },
+                                "context": { "type": "string" },
+                                "show_billed_characters": { "type": "boolean" },
+                            },
+                        } } },
+                    },
+                    "responses": {
+                        "200": { "description": "Translations" },
+                        "400": { "description": "Invalid request, as {\"message\": ...}" },
+                        "403": { "description": "Missing or invalid key" },
+                    },
+                },
+            },
+            "/.well-known/llmta-signing-key": {
+                "get": {
+                    "summary": "Public key for verifying x-llmta-signature",
+                    "responses": { "200": { "description": "Key" }, "404": { "description": "Signing disabled" } },
+                },
+            },
+            "/openapi.json": {
+                "get": { "summary": "This document", "responses": { "200": { "description": "OpenAPI document" } } },
+            },
+            "/version": {
+                "get": {
+                    "summary": "Build identity of this instance",
+                    "description": "Crate version, git commit, build date and compiled features. `/admin/version` adds a SHA-256 of the active configuration.",
+                    "responses": { "200": { "description": "Build info" } },
+                },
+            },
+            "/healthz": {
+                "get": { "summary": "Liveness of the process", "responses": { "200": { "description": "Alive" } } },
+            },
+            "/readyz": {
+                "get": {
+                    "summary": "Readiness, with the probed status of each backend",
+                    "description": "Probes backends as configured under `[health]`; results are cached for `cache_secs`.",
+                    "responses": { "200": { "description": "Ready" }, "503": { "description": "A backend failed its probe" } },
+                },
+            },
+        },
+        "components": {
+            "schemas": {
+                "Error": {
+                    "type": "object",
+                    "properties": {
+                        "error": {
+                            "type": "object",
+                            "properties": { "type": { "type": "string" }, "message": { "type": "string" } },
+                        },
+                    },
+                },
+                "ChatRequest": {
+                    "type": "object",
+                    "description": "OpenAI chat completion request plus adapter extensions",
+                    "additionalProperties": true,
+                    "properties": {
+                        "model": { "type": "string" },
+                        "messages": { "type": "array", "items": { "type": "object" } },
+                        "stream": { "type": "boolean" },
+                        "x_template": {
+                            "type": "string",
+                            "description": "Stored template, `name` for the active version or `name@version`; sent as a leading system message",
+                        },
+                        "x_template_vars": {
+                            "type": "object",
+                            "description": "Values for the template's {{placeholders}}",
+                        },
+                        "x_prompt_id": {
+                            "type": "string",
+                            "description": "ID from /v1/prompts; the stored prompt is sent as a leading system message",
+                        },
+                        "x_glossary": {
+                            "type": "object",
+                            "additionalProperties": { "type": "string" },
+                            "description": "Terms and their required translations",
+                        },
+                        "x_prompt_vars": {
+                            "type": "object",
+                            "additionalProperties": { "type": "string" },
+                            "description": "Variables for the system prompt configured for this route and model, e.g. target_language",
+                        },
+                    },
+                },
+                "EstimateRequest": {
+                    "allOf": [
+                        { "$ref": "#/components/schemas/ChatRequest" },
+                        {
+                            "type": "object",
+                            "properties": {
+                                "x_target_language": {
+                                    "type": "string",
+                                    "description": "Language the content will be translated into, e.g. DE",
+                                },
+                            },
+                        },
+                    ],
+                },
+                "ChatResponse": {
+                    "type": "object",
+                    "description": "OpenAI chat completion plus adapter extensions",
+                    "additionalProperties": true,
+                    "properties": {
+                        "x_budget_exhausted": { "type": "boolean" },
+                        "x_truncated": { "type": "boolean" },
+                        "x_translation": {
+                            "type": "object",
+                            "properties": {
+                                "detected_language": { "type": "string", "nullable": true },
+                                "character_count": { "type": "integer" },
+                                "billed_characters": { "type": "integer" },
+                                "billed_tokens": { "type": "integer", "nullable": true },
+                            },
+                        },
+                    },
+                },
+            },
+        },
+    })
+}
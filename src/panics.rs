@@ -0,0 +1,97 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderValue, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use futures::FutureExt;
+use std::any::Any;
+use std::backtrace::Backtrace;
+use std::cell::RefCell;
+use std::panic::{self, AssertUnwindSafe};
+use std::sync::{Arc, Once};
+
+use crate::AppState;
+
+thread_local! {
+    /// Where the last panic on this thread happened, taken by `catch_panics`
+    /// right after the unwind reaches it.
+    static LAST_PANIC: RefCell<Option<(String, Backtrace)>> = const { RefCell::new(None) };
+}
+
+/// Records the location and backtrace of every panic before unwinding, then
+/// reports it as the default hook would.
+fn install_hook() {
+    static INSTALL: Once = Once::new();
+    INSTALL.call_once(|| {
+        let previous = panic::take_hook();
+        panic::set_hook(Box::new(move |info| {
+            let location = info.location().map(|l| l.to_string()).unwrap_or_default();
+            LAST_PANIC.with(|last| {
+                *last.borrow_mut() = Some((location, Backtrace::force_capture()));
+            });
+            previous(info);
+        }));
+    });
+}
+
+fn message(payload: &(dyn Any + Send)) -> &str {
+    if let Some(message) = payload.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = payload.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
+
+/// Answers a request whose handler panicked with a 500 in the OpenAI error
+/// format instead of dropping the connection, so one bad payload does not
+/// go unnoticed.
+pub async fn catch_panics(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    install_hook();
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .unwrap_or_else(|| uuid::Uuid::new_v4().to_string());
+    let path = request.uri().path().to_string();
+    let payload = match AssertUnwindSafe(next.run(request)).catch_unwind().await {
+        Ok(response) => return response,
+        Err(payload) => payload,
+    };
+
+    let (location, backtrace) = LAST_PANIC
+        .with(|last| last.borrow_mut().take())
+        .map(|(location, backtrace)| (location, backtrace.to_string()))
+        .unwrap_or_default();
+    println!(
+        "Handler for {} panicked on request {} at {}: {}\n{}",
+        path,
+        request_id,
+        location,
+        message(payload.as_ref()),
+        backtrace
+    );
+    state.metrics.record_panic();
+
+    let body = serde_json::json!({
+        "error": {
+            "type": "internal_error",
+            "message": "The adapter failed unexpectedly while handling this request",
+            "request_id": request_id,
+        }
+    });
+    let mut response = (StatusCode::INTERNAL_SERVER_ERROR, Json(body)).into_response();
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert("x-request-id", value);
+    }
+    response
+}
@@ -0,0 +1,104 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Path, State},
+    http::{self, header, Method, StatusCode, Uri},
+    response::Response,
+};
+use serde_json::Value;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+use crate::breaker;
+use crate::limits;
+use crate::policy;
+use crate::proxy::{self, Admitted};
+use crate::quotas;
+use crate::AppState;
+
+/// Forwards any `/v1/*` path without a dedicated route to the backend as-is,
+/// after the usual auth, limit and quota checks and with the upstream key
+/// substituted. Bodies stream through untouched in both directions.
+pub async fn handle_passthrough(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Path(rest): Path<String>,
+    method: Method,
+    uri: Uri,
+    headers: http::HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let Admitted { identity, limit, lease } = match proxy::admit(&state, connect_info, &headers, &body) {
+        Ok(admitted) => admitted,
+        Err(response) => return response,
+    };
+
+    let payload = serde_json::from_slice::<Value>(&body).ok();
+    let model = payload
+        .as_ref()
+        .and_then(|payload| payload.get("model").and_then(Value::as_str).map(str::to_string))
+        .unwrap_or_default();
+    let streamed = payload.as_ref().is_some_and(|payload| payload["stream"] == true);
+    if !model.is_empty() {
+        if let Err(response) = identity.check_model(&model) {
+            return response;
+        }
+    }
+    let backend = state.backends.for_model(&model, Some(&identity.tenant_key()), &state.maintenance).clone();
+    let mut url = format!("{}/{}", backend.base_url(), rest);
+    if let Some(query) = uri.query() {
+        url.push('?');
+        url.push_str(query);
+    }
+
+    let mut forward_headers = reqwest::header::HeaderMap::new();
+    for (key, value) in headers.iter() {
+        if [header::HOST, header::CONTENT_LENGTH, header::AUTHORIZATION].contains(key) {
+            continue;
+        }
+        if let (Ok(name), Ok(v)) = (
+            reqwest::header::HeaderName::from_bytes(key.as_ref()),
+            reqwest::header::HeaderValue::from_bytes(value.as_bytes()),
+        ) {
+            forward_headers.insert(name, v);
+        }
+    }
+    backend.provider.authorize(&mut forward_headers, identity.upstream_key(&backend));
+
+    let mut policy = state.config.policy.resolve(&format!("/v1/{}", rest), &model);
+    // Fallback models only apply to chat; passthrough bodies are opaque.
+    policy.fallback_model = None;
+    policy.screen_limit = state.config.limits.max_response_bytes;
+    if let Err(retry_after) = backend.breaker.admit() {
+        return breaker::rejection(&backend.name, retry_after);
+    }
+    let upstream_method = reqwest::Method::from_bytes(method.as_str().as_bytes()).unwrap();
+    let sent = policy::send(&policy, backend.provider.as_ref(), None, streamed, |_| {
+        let mut headers = forward_headers.clone();
+        backend.provider.sign(upstream_method.as_str(), &url, &mut headers, &body);
+        backend
+            .client
+            .request(upstream_method.clone(), &url)
+            .headers(headers)
+            .body(body.clone())
+    })
+    .await;
+    backend.observe(&sent);
+    let response = match sent {
+        Ok(response) => response,
+        Err(error) => {
+            println!("Passthrough {} /v1/{} failed ({:?}): {}", method, rest, error.class, error.message);
+            return error.into_response();
+        }
+    };
+
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap();
+    println!("Passthrough {} /v1/{} for {} -> {}", method, rest, identity.label, status);
+
+    let builder = state
+        .config
+        .headers
+        .copy_upstream(Response::builder().status(status), response.headers());
+    let mut response = builder.body(Body::from_stream(response.bytes_stream())).unwrap();
+    limits::merge_upstream(response.headers_mut(), limit.as_ref(), None);
+    quotas::hold(response, lease)
+}
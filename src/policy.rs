@@ -0,0 +1,489 @@
+use axum::body::Bytes;
+use axum::http::StatusCode;
+use futures::StreamExt;
+use rand::Rng;
+use serde::Deserialize;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::provider::{ErrorClass, Provider, ProviderError};
+
+/// Timeout, retry and fallback settings; unset fields inherit.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PolicyOverrides {
+    /// Time allowed for the upstream to return response headers, per attempt.
+    #[serde(default)]
+    pub timeout_ms: Option<u64>,
+    /// Time from sending the request to the first bytes of the response
+    /// body, which for a stream is its first token.
+    #[serde(default)]
+    pub first_byte_timeout_ms: Option<u64>,
+    /// Longest pause between two pieces of a response body, such as the
+    /// chunks of a stream.
+    #[serde(default)]
+    pub idle_timeout_ms: Option<u64>,
+    /// Time for the whole exchange, retries and body included. Unlike
+    /// `streaming.budget_ms` it ends in an error rather than partial output.
+    #[serde(default)]
+    pub total_timeout_ms: Option<u64>,
+    /// Extra attempts after a retryable failure.
+    #[serde(default)]
+    pub retries: Option<u32>,
+    /// Delay before the first retry; doubles on each further one.
+    #[serde(default)]
+    pub backoff_ms: Option<u64>,
+    /// Upper bound for the doubling delay, and for a `Retry-After` worth
+    /// waiting out (30 seconds when unset). An upstream asking for longer
+    /// has its answer relayed without further attempts.
+    #[serde(default)]
+    pub max_backoff_ms: Option<u64>,
+    /// Fraction of each delay, between 0 and 1, that is randomly taken off
+    /// so clients failing together do not retry together.
+    #[serde(default)]
+    pub jitter: Option<f64>,
+    /// Also retry failures that may have reached the backend, such as a
+    /// connection dropped mid-request or no response headers within
+    /// `timeout_ms`. Only safe for idempotent work.
+    #[serde(default)]
+    pub retry_ambiguous: Option<bool>,
+    /// Model tried once more when every attempt failed.
+    #[serde(default)]
+    pub fallback_model: Option<String>,
+    /// Chat only: models tried in order, each on whichever backend serves
+    /// it, when the answer is an error, a timeout or a content-filter refusal.
+    #[serde(default)]
+    pub fallback_chain: Option<Vec<String>>,
+}
+
+/// `[[policy.rules]]` entry, applied to matching requests in order.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PolicyRule {
+    /// `chat`, or passthrough paths such as `/v1/embeddings`; a trailing `*`
+    /// matches by prefix (`/v1/audio/*`). Empty matches every route.
+    #[serde(default)]
+    pub routes: Vec<String>,
+    /// Model names; a trailing `*` matches by prefix. Empty matches every model.
+    #[serde(default)]
+    pub models: Vec<String>,
+    #[serde(flatten)]
+    pub policy: PolicyOverrides,
+}
+
+/// What a matched error pattern does to the attempt it was found in.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum PatternAction {
+    /// Skip any remaining retries and try the fallback model right away.
+    #[default]
+    Fallback,
+    /// Treat the attempt as a retryable failure.
+    Retry,
+}
+
+/// `[[policy.error_patterns]]` entry: text that marks a response as failed,
+/// for providers that report capacity errors with a 200 or a plain 400.
+#[derive(Debug, Deserialize, Clone, PartialEq)]
+pub struct ErrorPattern {
+    /// Matched case-insensitively against the whole response body.
+    pub pattern: String,
+    /// Statuses to look at; empty means every status. Streamed responses
+    /// are never inspected.
+    #[serde(default)]
+    pub statuses: Vec<u16>,
+    #[serde(default)]
+    pub action: PatternAction,
+}
+
+impl ErrorPattern {
+    fn applies_to(&self, status: u16) -> bool {
+        self.statuses.is_empty() || self.statuses.contains(&status)
+    }
+}
+
+/// `[policy]`: defaults plus per-route and per-model rules.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PolicyConfig {
+    #[serde(flatten)]
+    pub defaults: PolicyOverrides,
+    #[serde(default)]
+    pub rules: Vec<PolicyRule>,
+    #[serde(default)]
+    pub error_patterns: Vec<ErrorPattern>,
+}
+
+/// The effective policy for one request.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Policy {
+    pub timeout: Option<Duration>,
+    pub first_byte_timeout: Option<Duration>,
+    pub idle_timeout: Option<Duration>,
+    pub total_timeout: Option<Duration>,
+    pub retries: u32,
+    pub backoff: Duration,
+    pub max_backoff: Option<Duration>,
+    pub jitter: f64,
+    pub retry_ambiguous: bool,
+    pub fallback_model: Option<String>,
+    pub fallback_chain: Vec<String>,
+    pub error_patterns: Vec<ErrorPattern>,
+    /// Largest body buffered to look for error patterns; longer ones are
+    /// passed on unscreened. Callers set it from `limits.max_response_bytes`.
+    pub screen_limit: Option<usize>,
+}
+
+/// Longest `Retry-After` waited out when the policy sets no `max_backoff`.
+const MAX_RETRY_AFTER: Duration = Duration::from_secs(30);
+
+/// Whether `value` matches any of `patterns`, where a trailing `*` matches
+/// by prefix and an empty list matches everything.
+pub fn matches(patterns: &[String], value: &str) -> bool {
+    patterns.is_empty()
+        || patterns.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => value.starts_with(prefix),
+            None => value == pattern,
+        })
+}
+
+impl PolicyConfig {
+    pub fn resolve(&self, route: &str, model: &str) -> Policy {
+        let mut policy = Policy {
+            timeout: None,
+            first_byte_timeout: None,
+            idle_timeout: None,
+            total_timeout: None,
+            retries: 0,
+            backoff: Duration::from_millis(200),
+            max_backoff: None,
+            jitter: 0.0,
+            retry_ambiguous: false,
+            fallback_model: None,
+            fallback_chain: Vec::new(),
+            error_patterns: self.error_patterns.clone(),
+            screen_limit: None,
+        };
+        let rules = self
+            .rules
+            .iter()
+            .filter(|r| matches(&r.routes, route) && matches(&r.models, model))
+            .map(|r| &r.policy);
+        for overrides in std::iter::once(&self.defaults).chain(rules) {
+            if let Some(ms) = overrides.timeout_ms {
+                policy.timeout = Some(Duration::from_millis(ms));
+            }
+            if let Some(ms) = overrides.first_byte_timeout_ms {
+                policy.first_byte_timeout = Some(Duration::from_millis(ms));
+            }
+            if let Some(ms) = overrides.idle_timeout_ms {
+                policy.idle_timeout = Some(Duration::from_millis(ms));
+            }
+            if let Some(ms) = overrides.total_timeout_ms {
+                policy.total_timeout = Some(Duration::from_millis(ms));
+            }
+            if let Some(retries) = overrides.retries {
+                policy.retries = retries;
+            }
+            if let Some(ms) = overrides.backoff_ms {
+                policy.backoff = Duration::from_millis(ms);
+            }
+            if let Some(ms) = overrides.max_backoff_ms {
+                policy.max_backoff = Some(Duration::from_millis(ms));
+            }
+            if let Some(jitter) = overrides.jitter {
+                policy.jitter = jitter.clamp(0.0, 1.0);
+            }
+            if let Some(retry_ambiguous) = overrides.retry_ambiguous {
+                policy.retry_ambiguous = retry_ambiguous;
+            }
+            if let Some(model) = &overrides.fallback_model {
+                policy.fallback_model = Some(model.clone());
+            }
+            if let Some(chain) = &overrides.fallback_chain {
+                policy.fallback_chain = chain.clone();
+            }
+        }
+        policy
+    }
+}
+
+/// A phase of reading a response that ran out of time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TimeoutPhase {
+    FirstByte,
+    Idle,
+    Total,
+}
+
+impl TimeoutPhase {
+    pub fn message(self, timeouts: &BodyTimeouts) -> String {
+        match self {
+            TimeoutPhase::FirstByte => format!(
+                "The upstream sent no response within {:?}",
+                timeouts.first_byte_timeout.unwrap_or_default()
+            ),
+            TimeoutPhase::Idle => format!(
+                "The upstream sent nothing for {:?}",
+                timeouts.idle.unwrap_or_default()
+            ),
+            TimeoutPhase::Total => format!(
+                "The upstream did not finish within {:?}",
+                timeouts.total_timeout.unwrap_or_default()
+            ),
+        }
+    }
+}
+
+/// Deadlines for reading a response body, from a policy's timeouts.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct BodyTimeouts {
+    first_byte: Option<Instant>,
+    first_byte_timeout: Option<Duration>,
+    idle: Option<Duration>,
+    total: Option<Instant>,
+    total_timeout: Option<Duration>,
+}
+
+impl BodyTimeouts {
+    /// When the next piece of the body is due and which phase ends then;
+    /// `started` once any of the body has arrived.
+    pub fn next(&self, started: bool) -> Option<(Instant, TimeoutPhase)> {
+        let first_byte = self
+            .first_byte
+            .filter(|_| !started)
+            .map(|at| (at, TimeoutPhase::FirstByte));
+        let idle = self
+            .idle
+            .filter(|_| started)
+            .map(|idle| (Instant::now() + idle, TimeoutPhase::Idle));
+        let total = self.total.map(|at| (at, TimeoutPhase::Total));
+        [first_byte, idle, total].into_iter().flatten().min_by_key(|(at, _)| *at)
+    }
+}
+
+impl Policy {
+    /// Body deadlines for a request first sent at `sent_at`.
+    pub fn body_timeouts(&self, sent_at: Instant) -> BodyTimeouts {
+        BodyTimeouts {
+            first_byte: self.first_byte_timeout.map(|t| sent_at + t),
+            first_byte_timeout: self.first_byte_timeout,
+            idle: self.idle_timeout,
+            total: self.total_timeout.map(|t| sent_at + t),
+            total_timeout: self.total_timeout,
+        }
+    }
+
+    fn should_retry(&self, error: &ProviderError) -> bool {
+        match error.class {
+            ErrorClass::Retryable => true,
+            ErrorClass::Ambiguous => self.retry_ambiguous,
+            ErrorClass::NonRetryable => false,
+        }
+    }
+
+    /// The wait for a retry at `backoff`, capped and then jittered.
+    fn delay(&self, backoff: Duration) -> Duration {
+        let capped = self.max_backoff.map_or(backoff, |max| backoff.min(max));
+        if self.jitter <= 0.0 {
+            return capped;
+        }
+        capped.mul_f64(1.0 - rand::thread_rng().gen_range(0.0..=self.jitter))
+    }
+}
+
+/// Buffers a non-streamed response whose status some pattern applies to,
+/// returning it rebuilt together with the first pattern its body matches.
+/// Streams are never held back, and a body longer than `limit` is passed on
+/// whole once it outgrows the buffer, unscreened.
+async fn screen(
+    patterns: &[ErrorPattern],
+    streamed: bool,
+    limit: Option<usize>,
+    mut response: reqwest::Response,
+) -> Result<(reqwest::Response, Option<&ErrorPattern>), reqwest::Error> {
+    let status = response.status().as_u16();
+    let streamed = streamed
+        || response
+            .headers()
+            .get(reqwest::header::CONTENT_TYPE)
+            .and_then(|v| v.to_str().ok())
+            .is_some_and(|v| v.starts_with("text/event-stream"));
+    if streamed || !patterns.iter().any(|p| p.applies_to(status)) {
+        return Ok((response, None));
+    }
+
+    let builder = rebuilder(&response);
+    let mut body = Vec::new();
+    while let Some(chunk) = response.chunk().await? {
+        body.extend_from_slice(&chunk);
+        if limit.is_some_and(|limit| body.len() > limit) {
+            let read = futures::stream::once(async move { Ok(Bytes::from(body)) });
+            let rest = reqwest::Body::wrap_stream(read.chain(response.bytes_stream()));
+            return Ok((reqwest::Response::from(builder.body(rest).unwrap()), None));
+        }
+    }
+    let text = String::from_utf8_lossy(&body).to_lowercase();
+    let matched = patterns
+        .iter()
+        .find(|p| p.applies_to(status) && text.contains(&p.pattern.to_lowercase()));
+    Ok((reqwest::Response::from(builder.body(body).unwrap()), matched))
+}
+
+/// A response builder with `response`'s status, version and headers, to
+/// hand on a response whose body has been read.
+fn rebuilder(response: &reqwest::Response) -> http02::response::Builder {
+    let mut builder = http02::Response::builder()
+        .status(response.status())
+        .version(response.version());
+    if let Some(headers) = builder.headers_mut() {
+        headers.extend(response.headers().iter().map(|(k, v)| (k.clone(), v.clone())));
+    }
+    builder
+}
+
+/// Reads a failed response's body, returning it with the response rebuilt.
+async fn buffer(response: reqwest::Response) -> Result<(reqwest::Response, Bytes), reqwest::Error> {
+    let builder = rebuilder(&response);
+    let body = response.bytes().await?;
+    Ok((reqwest::Response::from(builder.body(body.clone()).unwrap()), body))
+}
+
+/// Sends a request under `policy`. `request` builds each attempt; it is
+/// given the fallback model for the final attempt after retries ran out.
+/// A failed last attempt's response is returned as is, so upstream error
+/// bodies still reach the client. `deadline` bounds all attempts together.
+/// A response matching one of the policy's error patterns counts as failed
+/// whatever its status; `streamed` requests are not screened.
+pub async fn send<F>(
+    policy: &Policy,
+    provider: &dyn Provider,
+    deadline: Option<Instant>,
+    streamed: bool,
+    mut request: F,
+) -> Result<reqwest::Response, ProviderError>
+where
+    F: FnMut(Option<&str>) -> reqwest::RequestBuilder,
+{
+    let attempts = policy.retries + 1;
+    let total_deadline = policy.total_timeout.map(|t| Instant::now() + t);
+    let fallback = policy.fallback_model.as_deref();
+    let total = attempts + u32::from(fallback.is_some());
+    let mut backoff = policy.backoff;
+
+    let mut attempt = 0;
+    while attempt < total {
+        attempt += 1;
+        let model = (attempt > attempts).then_some(fallback).flatten();
+        let last = attempt == total;
+        let limit = [policy.timeout.map(|t| Instant::now() + t), deadline, total_deadline]
+            .into_iter()
+            .flatten()
+            .min();
+        let sent = match limit {
+            Some(limit) => match tokio::time::timeout_at(limit, request(model).send()).await {
+                Ok(sent) => sent,
+                Err(_) if deadline.is_some_and(|d| Instant::now() >= d) => {
+                    return Err(ProviderError::new(
+                        ErrorClass::Retryable,
+                        StatusCode::GATEWAY_TIMEOUT,
+                        "Response budget exhausted",
+                        "The upstream did not respond within the configured response budget",
+                    ));
+                }
+                Err(_) if total_deadline.is_some_and(|d| Instant::now() >= d) => {
+                    return Err(ProviderError::new(
+                        ErrorClass::Retryable,
+                        StatusCode::GATEWAY_TIMEOUT,
+                        "Upstream timeout",
+                        format!(
+                            "No response within {:?}",
+                            policy.total_timeout.unwrap_or_default()
+                        ),
+                    ));
+                }
+                Err(_) => {
+                    // The request went out and may be being processed.
+                    let error = ProviderError::new(
+                        ErrorClass::Ambiguous,
+                        StatusCode::GATEWAY_TIMEOUT,
+                        "Upstream timeout",
+                        format!("No response headers within {:?}", policy.timeout.unwrap_or_default()),
+                    );
+                    if last || !policy.should_retry(&error) {
+                        return Err(error);
+                    }
+                    println!("Attempt {} of {} timed out, retrying", attempt, total);
+                    tokio::time::sleep(policy.delay(backoff)).await;
+                    backoff = backoff.saturating_mul(2);
+                    continue;
+                }
+            },
+            None => request(model).send().await,
+        };
+
+        let sent = match sent {
+            Ok(response) => screen(&policy.error_patterns, streamed, policy.screen_limit, response).await,
+            Err(e) => Err(e),
+        };
+        let error = match sent {
+            Ok((response, Some(pattern))) => {
+                let error = ProviderError::new(
+                    ErrorClass::Retryable,
+                    StatusCode::BAD_GATEWAY,
+                    "Upstream error",
+                    format!("the response matched the error pattern {:?}", pattern.pattern),
+                );
+                if last {
+                    return Ok(response);
+                }
+                if pattern.action == PatternAction::Fallback {
+                    if fallback.is_none() {
+                        return Ok(response);
+                    }
+                    println!("Attempt {} of {} failed ({}), falling back", attempt, total, error.message);
+                    attempt = attempts;
+                    continue;
+                }
+                error
+            }
+            Ok((response, None)) if response.status().is_success() || last => return Ok(response),
+            // The body is read so it can decide the class, as a content-policy
+            // refusal does, and is handed on when the answer is not retried.
+            Ok((response, None)) => match buffer(response).await {
+                Ok((response, body)) => {
+                    let error = provider.classify_response(response.status().as_u16(), response.headers(), &body);
+                    let cap = policy.max_backoff.unwrap_or(MAX_RETRY_AFTER);
+                    if !policy.should_retry(&error) || error.retry_after.is_some_and(|after| after > cap) {
+                        return Ok(response);
+                    }
+                    error
+                }
+                Err(e) => {
+                    let error = provider.classify_transport(&e);
+                    if !policy.should_retry(&error) {
+                        return Err(error);
+                    }
+                    error
+                }
+            },
+            Err(e) => {
+                let error = provider.classify_transport(&e);
+                if last || !policy.should_retry(&error) {
+                    return Err(error);
+                }
+                error
+            }
+        };
+
+        let delay = policy.delay(backoff);
+        let wait = error.retry_after.map_or(delay, |after| after.max(delay));
+        println!(
+            "Attempt {} of {} failed ({:?}: {}), retrying in {:?}",
+            attempt, total, error.class, error.message, wait
+        );
+        if [deadline, total_deadline].into_iter().flatten().any(|d| Instant::now() + wait >= d) {
+            return Err(error);
+        }
+        tokio::time::sleep(wait).await;
+        backoff = backoff.saturating_mul(2);
+    }
+    unreachable!("the last attempt always returns")
+}
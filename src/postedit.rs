@@ -0,0 +1,102 @@
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+
+use crate::policy;
+use crate::translation::content_text;
+
+const DEFAULT_PROMPT: &str = "Post-edit the draft translation of the source text below. Fix \
+mistranslations, omissions and awkward phrasing, keep the target language and formatting, and \
+reply with the final translation only.\n\nSource:\n{source}\n\nDraft:\n{draft}";
+
+/// `[[translation.post_edit]]` entry: chat requests it matches are answered
+/// in two stages. The requested model drafts the translation, then `editor`
+/// refines it from the source text and the draft.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PostEditChain {
+    /// Request paths such as `/v1/chat/completions`, as served including
+    /// any prefix or alias; a trailing `*` matches by prefix. Empty matches
+    /// every chat route.
+    #[serde(default)]
+    pub routes: Vec<String>,
+    /// Requested models; a trailing `*` matches by prefix. Empty matches
+    /// every model.
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Model that post-edits the draft.
+    pub editor: String,
+    /// Instruction sent to the editor, with `{source}` and `{draft}`
+    /// standing for the text to translate and the first stage's answer.
+    #[serde(default)]
+    pub prompt: Option<String>,
+}
+
+impl PostEditChain {
+    pub fn find<'a>(chains: &'a [PostEditChain], route: &str, model: &str) -> Option<&'a PostEditChain> {
+        chains
+            .iter()
+            .find(|chain| policy::matches(&chain.routes, route) && policy::matches(&chain.models, model))
+    }
+
+    /// The second stage's request: the client's parameters and system
+    /// messages, with the last user message replaced by the post-editing
+    /// prompt built from it and the draft.
+    pub fn edit_request(&self, payload: &Map<String, Value>, draft: &str) -> Map<String, Value> {
+        let messages = payload.get("messages").and_then(Value::as_array);
+        let source = messages
+            .into_iter()
+            .flatten()
+            .rfind(|m| m["role"] == "user")
+            .map(|m| content_text(&m["content"]))
+            .unwrap_or_default();
+        let prompt = self
+            .prompt
+            .as_deref()
+            .unwrap_or(DEFAULT_PROMPT)
+            .replace("{source}", &source)
+            .replace("{draft}", draft);
+        let mut edit: Vec<Value> = messages
+            .into_iter()
+            .flatten()
+            .filter(|m| m["role"] == "system" || m["role"] == "developer")
+            .cloned()
+            .collect();
+        edit.push(json!({ "role": "user", "content": prompt }));
+
+        let mut request = payload.clone();
+        request.insert("model".to_string(), Value::String(self.editor.clone()));
+        request.insert("messages".to_string(), Value::Array(edit));
+        request
+    }
+}
+
+/// The first stage's request: the client's, answered in one piece.
+pub fn draft_request(payload: &Map<String, Value>) -> Map<String, Value> {
+    let mut draft = payload.clone();
+    draft.insert("stream".to_string(), Value::Bool(false));
+    draft.remove("stream_options");
+    draft
+}
+
+/// The draft and usage of the first stage's completion.
+pub fn draft_of(completion: &Value) -> Option<(String, Value)> {
+    let message = &completion["choices"][0]["message"];
+    let draft = message.get("content").map(content_text)?;
+    Some((draft, completion.get("usage").cloned().unwrap_or(Value::Null)))
+}
+
+/// Adds the token counts of an earlier stage to `usage`; returns whether
+/// anything changed.
+pub fn add_usage(usage: &mut Value, prior: &Value) -> bool {
+    let Some(usage) = usage.as_object_mut() else {
+        return false;
+    };
+    let mut changed = false;
+    for field in ["prompt_tokens", "completion_tokens", "total_tokens"] {
+        if let Some(tokens) = prior[field].as_u64().filter(|tokens| *tokens > 0) {
+            let total = usage.get(field).and_then(Value::as_u64).unwrap_or(0) + tokens;
+            usage.insert(field.to_string(), Value::from(total));
+            changed = true;
+        }
+    }
+    changed
+}
@@ -0,0 +1,109 @@
+use axum::{
+    body::Body,
+    extract::Query,
+    http::StatusCode,
+    response::Response,
+};
+use serde::Deserialize;
+
+use crate::create_error_response;
+
+#[derive(Deserialize)]
+pub struct CpuProfileQuery {
+    /// Sampling window, 1 to 120 seconds.
+    #[serde(default)]
+    pub seconds: Option<u64>,
+    /// Samples per second.
+    #[serde(default)]
+    pub frequency: Option<i32>,
+    /// `pprof` (protobuf, for `go tool pprof`) or `flamegraph` (SVG).
+    #[serde(default)]
+    pub format: Option<String>,
+}
+
+fn unavailable(feature: &str) -> Response<Body> {
+    create_error_response(
+        StatusCode::NOT_IMPLEMENTED,
+        "profiling_unavailable",
+        &format!("The adapter was built without the `{}` feature", feature),
+    )
+}
+
+#[cfg(all(unix, any(feature = "profiling", feature = "heap-profiling")))]
+fn profile_response(content_type: &str, body: Vec<u8>) -> Response<Body> {
+    Response::builder()
+        .header(axum::http::header::CONTENT_TYPE, content_type)
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// `GET /admin/profile/cpu`: samples every thread for a while and returns
+/// the profile. Only one profile can run at a time.
+#[cfg(all(unix, feature = "profiling"))]
+pub async fn cpu_profile(Query(query): Query<CpuProfileQuery>) -> Response<Body> {
+    let seconds = query.seconds.unwrap_or(10).clamp(1, 120);
+    let frequency = query.frequency.unwrap_or(100).clamp(1, 1000);
+    let flamegraph = query.format.as_deref() == Some("flamegraph");
+    println!("Profiling CPU for {}s at {}Hz", seconds, frequency);
+
+    // The profiler guard is not Send, so sampling runs on a blocking thread.
+    let profile = tokio::task::spawn_blocking(move || -> Result<Vec<u8>, String> {
+        let guard = pprof::ProfilerGuardBuilder::default()
+            .frequency(frequency)
+            .blocklist(&["libc", "libgcc", "pthread", "vdso"])
+            .build()
+            .map_err(|e| e.to_string())?;
+        std::thread::sleep(std::time::Duration::from_secs(seconds));
+        let report = guard.report().build().map_err(|e| e.to_string())?;
+        let mut body = Vec::new();
+        if flamegraph {
+            report.flamegraph(&mut body).map_err(|e| e.to_string())?;
+        } else {
+            use pprof::protos::Message;
+            report
+                .pprof()
+                .map_err(|e| e.to_string())?
+                .encode(&mut body)
+                .map_err(|e| e.to_string())?;
+        }
+        Ok(body)
+    })
+    .await;
+
+    match profile {
+        Ok(Ok(body)) if flamegraph => profile_response("image/svg+xml", body),
+        Ok(Ok(body)) => profile_response("application/octet-stream", body),
+        Ok(Err(e)) => create_error_response(StatusCode::CONFLICT, "profiling_failed", &e),
+        Err(e) => create_error_response(StatusCode::INTERNAL_SERVER_ERROR, "profiling_failed", &e.to_string()),
+    }
+}
+
+#[cfg(not(all(unix, feature = "profiling")))]
+pub async fn cpu_profile(Query(_query): Query<CpuProfileQuery>) -> Response<Body> {
+    unavailable("profiling")
+}
+
+/// `GET /admin/profile/heap`: live allocations sampled by jemalloc, as pprof.
+#[cfg(all(unix, feature = "heap-profiling"))]
+pub async fn heap_profile() -> Response<Body> {
+    let Some(control) = jemalloc_pprof::PROF_CTL.as_ref() else {
+        return unavailable("heap-profiling");
+    };
+    let mut control = control.lock().await;
+    if !control.activated() {
+        return create_error_response(
+            StatusCode::CONFLICT,
+            "profiling_failed",
+            "jemalloc heap profiling is not active",
+        );
+    }
+    match control.dump_pprof() {
+        Ok(body) => profile_response("application/octet-stream", body),
+        Err(e) => create_error_response(StatusCode::INTERNAL_SERVER_ERROR, "profiling_failed", &e.to_string()),
+    }
+}
+
+#[cfg(not(all(unix, feature = "heap-profiling")))]
+pub async fn heap_profile() -> Response<Body> {
+    unavailable("heap-profiling")
+}
@@ -0,0 +1,191 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, Path, State},
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use crate::create_error_response;
+use crate::proxy::{self, Admitted};
+use crate::quotas::TenantQuotas;
+use crate::AppState;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct PromptConfig {
+    #[serde(default = "default_max_prompt_bytes")]
+    pub max_prompt_bytes: usize,
+    /// Prompts unused for this long are forgotten.
+    #[serde(default = "default_ttl_secs")]
+    pub ttl_secs: u64,
+}
+
+fn default_max_prompt_bytes() -> usize {
+    256 * 1024
+}
+
+fn default_ttl_secs() -> u64 {
+    86_400
+}
+
+impl Default for PromptConfig {
+    fn default() -> Self {
+        PromptConfig {
+            max_prompt_bytes: default_max_prompt_bytes(),
+            ttl_secs: default_ttl_secs(),
+        }
+    }
+}
+
+struct StoredPrompt {
+    content: String,
+    last_used: Instant,
+}
+
+/// Uploaded system prompts, addressed by the SHA-256 of their content.
+/// Each tenant has its own namespace so IDs cannot be probed across tenants;
+/// entries count against the tenant's cache quota.
+pub struct PromptStore {
+    config: PromptConfig,
+    prompts: Mutex<HashMap<(String, String), StoredPrompt>>,
+}
+
+pub fn prompt_id(content: &str) -> String {
+    format!("sha256:{}", hex::encode(Sha256::digest(content.as_bytes())))
+}
+
+impl PromptStore {
+    pub fn new(config: PromptConfig) -> Self {
+        PromptStore {
+            config,
+            prompts: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Stores `content`, returning its ID, or `None` when the tenant's quota is full.
+    pub fn insert(&self, quotas: &TenantQuotas, tenant: &str, content: String) -> Option<String> {
+        let id = prompt_id(&content);
+        let mut prompts = self.prompts.lock().unwrap();
+        let key = (tenant.to_string(), id.clone());
+        if let Some(existing) = prompts.get_mut(&key) {
+            existing.last_used = Instant::now();
+            return Some(id);
+        }
+        if !quotas.add_cache_entry(tenant) {
+            return None;
+        }
+        prompts.insert(key, StoredPrompt { content, last_used: Instant::now() });
+        Some(id)
+    }
+
+    pub fn get(&self, tenant: &str, id: &str) -> Option<String> {
+        let mut prompts = self.prompts.lock().unwrap();
+        let prompt = prompts.get_mut(&(tenant.to_string(), id.to_string()))?;
+        prompt.last_used = Instant::now();
+        Some(prompt.content.clone())
+    }
+
+    /// Replaces `x_prompt_id` with a leading system message holding the prompt.
+    pub fn expand(&self, tenant: &str, payload: &mut Map<String, Value>) -> Result<bool, String> {
+        let Some(reference) = payload.remove("x_prompt_id") else {
+            return Ok(false);
+        };
+        let id = reference
+            .as_str()
+            .ok_or_else(|| "x_prompt_id must be a string".to_string())?;
+        let content = self
+            .get(tenant, id)
+            .ok_or_else(|| format!("No stored prompt {}; upload it to /v1/prompts again", id))?;
+        if let Some(Value::Array(messages)) = payload.get_mut("messages") {
+            messages.insert(0, json!({ "role": "system", "content": content }));
+        }
+        Ok(true)
+    }
+
+    /// Forgets prompts unused for the configured TTL; returns how many.
+    pub fn purge_expired(&self, quotas: &TenantQuotas) -> usize {
+        let ttl = Duration::from_secs(self.config.ttl_secs);
+        let mut prompts = self.prompts.lock().unwrap();
+        let before = prompts.len();
+        prompts.retain(|(tenant, _), prompt| {
+            let keep = prompt.last_used.elapsed() < ttl;
+            if !keep {
+                quotas.remove_cache_entry(tenant);
+            }
+            keep
+        });
+        before - prompts.len()
+    }
+}
+
+#[derive(Deserialize)]
+struct Upload {
+    content: String,
+}
+
+/// `POST /v1/prompts`: stores `{"content": "..."}` and returns its ID.
+pub async fn upload_prompt(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let Admitted { identity, .. } = match proxy::admit(&state, connect_info, &headers, &body) {
+        Ok(admitted) => admitted,
+        Err(response) => return response,
+    };
+    let Ok(upload) = serde_json::from_slice::<Upload>(&body) else {
+        return create_error_response(
+            StatusCode::BAD_REQUEST,
+            "invalid_request_error",
+            "Expected a JSON object with a string `content`",
+        );
+    };
+    let bytes = upload.content.len();
+    if bytes > state.prompts.config.max_prompt_bytes {
+        return create_error_response(
+            StatusCode::PAYLOAD_TOO_LARGE,
+            "prompt_too_large",
+            &format!("Prompts are limited to {} bytes", state.prompts.config.max_prompt_bytes),
+        );
+    }
+
+    let tenant = identity.tenant_key();
+    match state.prompts.insert(&state.quotas, &tenant, upload.content) {
+        Some(id) => (StatusCode::CREATED, Json(json!({ "id": id, "bytes": bytes }))).into_response(),
+        None => create_error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            "tenant_quota_exceeded",
+            &format!("Tenant {} has stored as many prompts as its cache quota allows", tenant),
+        ),
+    }
+}
+
+/// `GET /v1/prompts/:id`: the stored content, so clients can verify an ID.
+pub async fn get_prompt(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    Path(id): Path<String>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let peer = connect_info.map(|ConnectInfo(addr)| addr.ip());
+    let identity = match state.auth.identify(&headers, peer, &Bytes::new()) {
+        Ok(identity) => identity,
+        Err(response) => return response,
+    };
+    match state.prompts.get(&identity.tenant_key(), &id) {
+        Some(content) => Json(json!({ "id": id, "content": content })).into_response(),
+        None => create_error_response(
+            StatusCode::NOT_FOUND,
+            "prompt_not_found",
+            &format!("No stored prompt {}", id),
+        ),
+    }
+}
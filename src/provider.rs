@@ -0,0 +1,269 @@
+use axum::{
+    body::Body,
+    http::StatusCode,
+    response::Response,
+};
+use serde::Deserialize;
+use serde_json::{Map, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::anthropic::Anthropic;
+use crate::azure::Azure;
+use crate::bedrock::Bedrock;
+use crate::cohere::Cohere;
+use crate::config::AppConfig;
+use crate::create_error_response;
+use crate::gemini::Gemini;
+use crate::sse::{SseEvent, StreamFormat};
+use crate::voyage::Voyage;
+
+/// Wire protocol a backend speaks.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum Protocol {
+    #[default]
+    OpenAi,
+    /// Anthropic Messages API; requests and responses are translated.
+    Anthropic,
+    /// Google Gemini `generateContent`; the backend URL is the API root,
+    /// such as `https://generativelanguage.googleapis.com/v1beta`.
+    Gemini,
+    /// Azure OpenAI; the backend URL is the resource endpoint and models
+    /// map to deployments through `[azure]`.
+    Azure,
+    /// AWS Bedrock `InvokeModel`; the backend URL is the runtime endpoint and
+    /// requests are signed with the `[bedrock]` credentials.
+    Bedrock,
+    /// Cohere `v2/embed`; the backend URL is the API root, such as
+    /// `https://api.cohere.com/v2`. Serves embeddings only.
+    Cohere,
+    /// Voyage AI embeddings; the backend URL is the API root, such as
+    /// `https://api.voyageai.com/v1`. Serves embeddings only.
+    Voyage,
+}
+
+impl Protocol {
+    pub fn provider(self, config: &AppConfig) -> Arc<dyn Provider> {
+        match self {
+            Protocol::OpenAi => Arc::new(OpenAiCompatible),
+            Protocol::Anthropic => Arc::new(Anthropic::new(config.anthropic.clone())),
+            Protocol::Gemini => Arc::new(Gemini),
+            Protocol::Azure => Arc::new(Azure::new(config.azure.clone())),
+            Protocol::Bedrock => Arc::new(Bedrock::new(&config.bedrock, config.anthropic.clone())),
+            Protocol::Cohere => Arc::new(Cohere),
+            Protocol::Voyage => Arc::new(Voyage),
+        }
+    }
+}
+
+/// Per-stream state turning a provider's events into OpenAI chunk events.
+pub trait StreamTranslator: Send {
+    fn translate(&mut self, event: SseEvent) -> Vec<SseEvent>;
+
+    /// Events to send once the upstream stream has ended.
+    fn finish(&mut self) -> Vec<SseEvent> {
+        Vec::new()
+    }
+}
+
+/// How a failed upstream call should be treated by retry and fallback logic.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ErrorClass {
+    /// Transient: timeouts, rate limits, overloaded or restarting upstreams.
+    Retryable,
+    /// Replaying the same request cannot succeed: validation, auth, content policy.
+    NonRetryable,
+    /// The request may or may not have been processed upstream.
+    Ambiguous,
+}
+
+#[derive(Debug, Clone)]
+pub struct ProviderError {
+    pub class: ErrorClass,
+    /// Status returned to the client when the error is not recovered.
+    pub status: StatusCode,
+    pub error_type: String,
+    pub message: String,
+    /// Upstream hint from `Retry-After`, if any.
+    pub retry_after: Option<Duration>,
+}
+
+impl ProviderError {
+    pub fn new(class: ErrorClass, status: StatusCode, error_type: &str, message: impl Into<String>) -> Self {
+        ProviderError {
+            class,
+            status,
+            error_type: error_type.to_string(),
+            message: message.into(),
+            retry_after: None,
+        }
+    }
+
+    pub fn is_retryable(&self) -> bool {
+        self.class == ErrorClass::Retryable
+    }
+
+    pub fn into_response(self) -> Response<Body> {
+        create_error_response(self.status, &self.error_type, &self.message)
+    }
+}
+
+/// An upstream API flavor. Implementations own everything that differs
+/// between providers, starting with how their failures are classified.
+pub trait Provider: Send + Sync {
+    fn name(&self) -> &'static str;
+
+    /// Classifies a failure that happened before a response was received.
+    fn classify_transport(&self, err: &reqwest::Error) -> ProviderError {
+        if err.is_timeout() {
+            ProviderError::new(
+                ErrorClass::Retryable,
+                StatusCode::GATEWAY_TIMEOUT,
+                "Upstream timeout",
+                err.to_string(),
+            )
+        } else if err.is_connect() {
+            ProviderError::new(
+                ErrorClass::Retryable,
+                StatusCode::BAD_GATEWAY,
+                "Failed to forward request",
+                err.to_string(),
+            )
+        } else {
+            // The connection broke after the request was (possibly) sent.
+            ProviderError::new(
+                ErrorClass::Ambiguous,
+                StatusCode::BAD_GATEWAY,
+                "Failed to forward request",
+                err.to_string(),
+            )
+        }
+    }
+
+    /// Classifies a non-success upstream response.
+    fn classify_response(
+        &self,
+        status: u16,
+        headers: &reqwest::header::HeaderMap,
+        body: &[u8],
+    ) -> ProviderError {
+        classify(status, headers, body)
+    }
+
+    /// Where a chat request for `model` is sent, given the backend's URL.
+    fn chat_url(&self, url: &str, _model: &str, _stream: bool) -> String {
+        url.to_string()
+    }
+
+    fn stream_format(&self) -> StreamFormat {
+        StreamFormat::Sse
+    }
+
+    /// Sets the credentials for a request to this provider.
+    fn authorize(&self, headers: &mut reqwest::header::HeaderMap, key: &str) {
+        headers.insert(
+            reqwest::header::AUTHORIZATION,
+            format!("Bearer {}", key).parse().unwrap(),
+        );
+    }
+
+    /// Signs a request whose URL and body are final, for providers that
+    /// authenticate with a request signature rather than a key.
+    fn sign(&self, _method: &str, _url: &str, _headers: &mut reqwest::header::HeaderMap, _body: &[u8]) {}
+
+    /// Rewrites an OpenAI chat request into the provider's format; returns
+    /// whether anything changed.
+    fn translate_request(&self, _payload: &mut Map<String, Value>) -> Result<bool, String> {
+        Ok(false)
+    }
+
+    /// Rewrites a successful non-streamed response into an OpenAI chat
+    /// completion for `model`; returns whether anything changed.
+    fn translate_completion(&self, _completion: &mut Value, _model: &str) -> bool {
+        false
+    }
+
+    /// Converter for streamed events that are not OpenAI chunks.
+    fn stream_translator(&self, _model: &str) -> Option<Box<dyn StreamTranslator>> {
+        None
+    }
+
+    /// Where an embeddings request for `model` is sent, given the backend's
+    /// API root; `None` for providers without an embeddings API.
+    fn embeddings_url(&self, _base_url: &str, _model: &str) -> Option<String> {
+        None
+    }
+
+    /// Most inputs one embeddings request may carry.
+    fn max_embedding_inputs(&self) -> usize {
+        2048
+    }
+
+    /// Rewrites an OpenAI embeddings request into the provider's format.
+    fn translate_embeddings_request(&self, _payload: &mut Map<String, Value>) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Rewrites a successful embeddings response into OpenAI's list format.
+    fn translate_embeddings(&self, _response: &mut Value, _model: &str) {}
+}
+
+/// Classification for OpenAI-style error bodies, `{"error": {"type", "message"}}`.
+pub fn classify(status: u16, headers: &reqwest::header::HeaderMap, body: &[u8]) -> ProviderError {
+    let error = serde_json::from_slice::<Value>(body)
+        .ok()
+        .and_then(|v| v.get("error").cloned());
+    let code = error
+        .as_ref()
+        .and_then(|e| e.get("code").or_else(|| e.get("type")))
+        .and_then(Value::as_str)
+        .unwrap_or("");
+    let message = error
+        .as_ref()
+        .and_then(|e| e.get("message"))
+        .and_then(Value::as_str)
+        .map(str::to_string)
+        .unwrap_or_else(|| String::from_utf8_lossy(body).chars().take(512).collect());
+
+    let class = match status {
+        _ if is_content_policy(code) => ErrorClass::NonRetryable,
+        408 | 429 | 500 | 502 | 503 | 504 => ErrorClass::Retryable,
+        400..=499 => ErrorClass::NonRetryable,
+        _ => ErrorClass::Ambiguous,
+    };
+
+    let mut error = ProviderError::new(
+        class,
+        StatusCode::from_u16(status).unwrap_or(StatusCode::BAD_GATEWAY),
+        if code.is_empty() { "upstream_error" } else { code },
+        message,
+    );
+    error.retry_after = retry_after(headers);
+    error
+}
+
+/// Any OpenAI-compatible `/chat/completions` endpoint.
+pub struct OpenAiCompatible;
+
+impl Provider for OpenAiCompatible {
+    fn name(&self) -> &'static str {
+        "openai"
+    }
+
+    fn embeddings_url(&self, base_url: &str, _model: &str) -> Option<String> {
+        Some(format!("{}/embeddings", base_url))
+    }
+}
+
+pub fn is_content_policy(code: &str) -> bool {
+    ["content_filter", "content_policy_violation", "content_policy"].contains(&code)
+}
+
+pub fn retry_after(headers: &reqwest::header::HeaderMap) -> Option<Duration> {
+    headers
+        .get(reqwest::header::RETRY_AFTER)
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.trim().parse::<u64>().ok())
+        .map(Duration::from_secs)
+}
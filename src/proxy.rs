@@ -0,0 +1,1546 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, OriginalUri, State},
+    http::{self, header, StatusCode},
+    response::Response,
+};
+use futures::{channel::mpsc, SinkExt, Stream, StreamExt};
+use serde_json::{Map, Value};
+use sha2::{Digest, Sha256};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::time::Instant;
+
+use crate::auth::Identity;
+use crate::breaker;
+use crate::cache;
+use crate::completion::{self, ChunkAccumulator, StreamUsage};
+use crate::compression;
+use crate::create_error_response;
+use crate::degrade;
+use crate::deidentify::{Placeholders, StreamRestorer};
+use crate::deprecation::{AfterSunset, ModelDeprecation};
+use crate::feedback::RequestRecord;
+use crate::glossary;
+use crate::handshake::Connection;
+use crate::limits::{self, LimitStatus, OversizePolicy, Settlement};
+use crate::normalize;
+use crate::policy::{self, BodyTimeouts, TimeoutPhase};
+use crate::postedit::{self, PostEditChain};
+use crate::provider::{self, Provider, StreamTranslator};
+use crate::queue::QueuePermit;
+use crate::quotas::{self, TenantLease};
+use crate::rules::{self, RuleRequest};
+use crate::schema::{self, ChunkRepair};
+use crate::signing::ResponseSigner;
+use crate::sse::{EventParser, SseEvent, StreamFormat};
+use crate::streams::{StreamGuard, StreamHandle};
+use crate::sysprompt::SystemPrompt;
+use crate::tools::{self, ToolDeltaNormalizer};
+use crate::translation::{self, TranslationMetadata};
+use crate::verbose::Trace;
+use crate::version;
+use crate::AppState;
+
+/// Per-request facts threaded from `handle_chat` into the response handlers.
+pub struct RequestContext {
+    pub request_id: String,
+    /// Label identifying the caller in logs and admin views; never the full key.
+    pub key: String,
+    pub model: String,
+    pub backend: String,
+    /// API of the backend; translates requests and responses when it is not OpenAI's.
+    pub provider: Arc<dyn Provider>,
+    pub deadline: Option<Instant>,
+    /// Negotiated `x-llmta-api-version`.
+    pub api_version: String,
+    /// Characters of user content, billed when translation metadata is on.
+    pub source_chars: usize,
+    /// The tenant's connection; buffered response bytes are charged to it.
+    pub lease: Arc<TenantLease>,
+    /// Prompt template applied, as `name@version`.
+    pub template: Option<String>,
+    /// Pass on only the first tool call, emulating `parallel_tool_calls: false`.
+    pub single_tool_call: bool,
+    /// Originals of what was masked for an out-of-region backend.
+    pub placeholders: Option<Arc<Placeholders>>,
+    /// Usage of an earlier stage of a post-edit chain, added to this answer's.
+    pub prior_usage: Option<Value>,
+    /// Prompt tokens of a streamed request, counted here in case the
+    /// backend reports no usage.
+    pub prompt_tokens: u64,
+    /// The client asked for `stream_options.include_usage`.
+    pub include_usage: bool,
+    /// Where a streamed answer is cached once it completes.
+    pub cache_key: Option<String>,
+    /// First-byte, idle and total deadlines for reading the response body.
+    pub timeouts: BodyTimeouts,
+    /// Set when this request is logged in detail.
+    pub trace: Option<Arc<Trace>>,
+    /// Glossary terms the answer is checked for, source to translation.
+    pub glossary: Option<Vec<(String, String)>>,
+    /// Token budgets charged up front, settled once usage is known.
+    pub settlement: Option<Arc<Settlement>>,
+}
+
+impl RequestContext {
+    /// Records the end of a phase of a traced request.
+    fn mark(&self, phase: impl Into<String>) {
+        if let Some(trace) = &self.trace {
+            trace.mark(phase);
+        }
+    }
+}
+
+/// Accounts the tokens of a completed request in the usage log, heatmap and
+/// metrics, and sends a `usage.request` webhook.
+fn record_usage(state: &AppState, ctx: &RequestContext, usage: Option<&Value>) {
+    if let (Some(settlement), Some(used)) = (&ctx.settlement, usage.and_then(|u| u["total_tokens"].as_u64())) {
+        settlement.settle(&state.budgets, used);
+    }
+    state.usage.record(ctx.lease.tenant(), usage);
+    state.heatmap.record_usage(ctx.template.as_deref(), usage);
+    state.metrics.record_usage(&ctx.model, usage);
+    let event = serde_json::json!({
+        "request_id": ctx.request_id,
+        "tenant": ctx.lease.tenant(),
+        "key": ctx.key,
+        "model": ctx.model,
+        "backend": ctx.backend,
+        "usage": usage,
+    });
+    state.webhooks.notify("usage.request", event);
+}
+
+/// Adds `x_translation` to a successful JSON completion and mirrors it in headers.
+fn enrich_translation(
+    state: &AppState,
+    ctx: &RequestContext,
+    body: Bytes,
+    headers: &mut http::HeaderMap,
+) -> Bytes {
+    if !state.config.translation.metadata {
+        return body;
+    }
+    let Ok(mut completion) = serde_json::from_slice::<Value>(&body) else {
+        return body;
+    };
+    let metadata = TranslationMetadata::from_completion(&completion, ctx.source_chars);
+    metadata.apply(headers);
+    if let Value::Object(fields) = &mut completion {
+        fields.insert("x_translation".to_string(), metadata.to_json());
+    }
+    Bytes::from(completion.to_string())
+}
+
+/// Adds `x_glossary_report` to a successful JSON completion when its
+/// glossary terms are checked, with a summary header.
+fn report_glossary(ctx: &RequestContext, body: Bytes, headers: &mut http::HeaderMap) -> Bytes {
+    let Some(terms) = ctx.glossary.as_deref().filter(|terms| !terms.is_empty()) else {
+        return body;
+    };
+    let Ok(mut completion) = serde_json::from_slice::<Value>(&body) else {
+        return body;
+    };
+    let report = glossary::check(&completion, terms);
+    if !report.missed.is_empty() {
+        println!("Answer to {} missed glossary terms {:?}", ctx.request_id, report.missed);
+    }
+    report.apply(headers);
+    if let Value::Object(fields) = &mut completion {
+        fields.insert("x_glossary_report".to_string(), serde_json::json!(report));
+    }
+    Bytes::from(completion.to_string())
+}
+
+/// Reads a whole response body, giving up as soon as it passes `max` bytes
+/// or one of `timeouts` expires.
+async fn read_capped(
+    response: reqwest::Response,
+    max: Option<usize>,
+    timeouts: &BodyTimeouts,
+) -> Result<Bytes, Response<Body>> {
+    let too_large = |max: usize| {
+        println!("Upstream response exceeded {} bytes", max);
+        create_error_response(
+            StatusCode::BAD_GATEWAY,
+            "response_too_large",
+            &format!("The upstream response exceeded {} bytes", max),
+        )
+    };
+    if let (Some(max), Some(length)) = (max, response.content_length()) {
+        if length as usize > max {
+            return Err(too_large(max));
+        }
+    }
+
+    let mut upstream = Box::pin(response.bytes_stream());
+    let mut body = Vec::new();
+    loop {
+        let next = next_before(&mut upstream, None, timeouts, !body.is_empty()).await;
+        let chunk = match next {
+            Ok(Some(chunk)) => chunk,
+            Ok(None) => break,
+            Err(Expired::Timeout(phase)) => return Err(body_timeout(phase, timeouts)),
+            Err(Expired::Budget) => unreachable!("read without a response budget"),
+        };
+        let chunk = chunk.map_err(|e| {
+            println!("Failed to read response body: {}", e);
+            create_error_response(
+                StatusCode::BAD_GATEWAY,
+                "Failed to read response",
+                &e.to_string(),
+            )
+        })?;
+        if let Some(max) = max.filter(|max| body.len() + chunk.len() > *max) {
+            return Err(too_large(max));
+        }
+        body.extend_from_slice(&chunk);
+    }
+    Ok(Bytes::from(body))
+}
+
+async fn handle_normal_response(
+    state: &AppState,
+    response: reqwest::Response,
+    ctx: &RequestContext,
+) -> Response<Body> {
+    let provider = ctx.provider.as_ref();
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap();
+    let headers = response.headers().clone();
+    let max = state.config.limits.max_response_bytes;
+    let bytes = match read_capped(response, max, &ctx.timeouts).await {
+        Ok(b) => b,
+        Err(response) => return response,
+    };
+
+    if let Err(exceeded) = ctx.lease.buffer(bytes.len()) {
+        println!("Dropping {} byte response for {}: quota exceeded", bytes.len(), ctx.request_id);
+        return exceeded.into_response();
+    }
+
+    if !status.is_success() {
+        let error = provider.classify_response(status.as_u16(), &headers, &bytes);
+        println!(
+            "Upstream {} returned {} ({:?}): {}",
+            provider.name(), status, error.class, error.message
+        );
+    }
+
+    let mut builder = Response::builder()
+        .status(status);
+
+    let mut bytes = bytes;
+    if status.is_success() {
+        let mut completion = serde_json::from_slice::<Value>(&bytes).ok();
+        if completion.as_mut().is_some_and(|c| provider.translate_completion(c, &ctx.model)) {
+            bytes = Bytes::from(completion.as_ref().unwrap().to_string());
+        }
+        if completion.as_mut().is_some_and(|c| repair(state, c, ctx)) {
+            bytes = Bytes::from(completion.as_ref().unwrap().to_string());
+        }
+        if let Some(placeholders) = &ctx.placeholders {
+            if completion.as_mut().is_some_and(|c| placeholders.restore_completion(c)) {
+                bytes = Bytes::from(completion.as_ref().unwrap().to_string());
+            }
+        }
+        record_usage(state, ctx, completion.as_ref().and_then(|c| c.get("usage")));
+        if let Some(prior) = &ctx.prior_usage {
+            let usage = completion.as_mut().and_then(|c| c.get_mut("usage"));
+            if usage.is_some_and(|usage| postedit::add_usage(usage, prior)) {
+                bytes = Bytes::from(completion.as_ref().unwrap().to_string());
+            }
+        }
+        if let Some(completion) = completion.as_mut().filter(|_| ctx.single_tool_call) {
+            if tools::keep_first_tool_call(completion) {
+                bytes = Bytes::from(completion.to_string());
+            }
+        }
+        if let Some(extra) = builder.headers_mut() {
+            bytes = enrich_translation(state, ctx, bytes, extra);
+            bytes = report_glossary(ctx, bytes, extra);
+        }
+    }
+
+    // The body may have been rewritten, so its length is recomputed.
+    builder = state.config.headers.copy_upstream(builder, &headers);
+
+    if let Some(signer) = &state.signer {
+        builder = builder.header("x-llmta-signature", signer.sign_body(&bytes));
+    }
+
+    builder.body(Body::from(bytes)).unwrap()
+}
+
+async fn handle_streaming_response(
+    state: Arc<AppState>,
+    response: reqwest::Response,
+    ctx: RequestContext,
+    permit: Option<QueuePermit>,
+    connection: Connection,
+) -> Response<Body> {
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap();
+    let headers = response.headers().clone();
+
+    let guard = state
+        .streams
+        .register(&ctx.request_id, &ctx.key, &ctx.model, &ctx.backend);
+    let (tx, rx) = mpsc::channel(16);
+    let writer = EventWriter {
+        tx,
+        next_id: 0,
+        event_types: state.config.streaming.event_types,
+        meta: Map::new(),
+        done: false,
+        finished: false,
+        errored: false,
+        stream: guard.handle(),
+        _guard: guard,
+        _permit: permit,
+        _connection: connection,
+        signer: state.signer.clone(),
+        digest: Sha256::new(),
+        tools: ToolDeltaNormalizer::new(ctx.single_tool_call),
+        translator: ctx.provider.stream_translator(&ctx.model),
+        repair: ChunkRepair::new(&ctx.request_id, &ctx.model, &state.config.validation),
+        restorer: ctx.placeholders.clone().map(StreamRestorer::new),
+        usage: StreamUsage::new(state.tokenizers.for_model(&ctx.model), ctx.prompt_tokens),
+        succeeded: status.is_success(),
+        cache: ctx.cache_key.clone().map(|key| (key, ChunkAccumulator::default())),
+        state: state.clone(),
+        ctx,
+    };
+    let request_id = writer.ctx.request_id.clone();
+    let limits = &state.config.limits;
+    let cap = limits.max_response_bytes.map(|max| (max, limits.oversize_policy));
+    let parser = EventParser::new(writer.ctx.provider.stream_format());
+    let deadline = writer.ctx.deadline;
+    tokio::spawn(pump_events(response, writer, parser, deadline, cap));
+
+    let body = Body::from_stream(rx);
+    
+    let mut builder = Response::builder()
+        .status(status)
+        .header("x-request-id", request_id.as_str());
+
+    builder = state.config.headers.copy_upstream(builder, &headers);
+
+    builder.body(body).unwrap()
+}
+
+/// Re-serializes upstream events towards the client, stamping each one with a
+/// monotonically increasing `id:` so clients can send `Last-Event-ID`.
+struct EventWriter {
+    tx: mpsc::Sender<Result<Bytes, std::io::Error>>,
+    next_id: u64,
+    event_types: bool,
+    meta: Map<String, Value>,
+    done: bool,
+    /// A chunk carried a `finish_reason`.
+    finished: bool,
+    /// An error event was sent; the stream ends there.
+    errored: bool,
+    stream: Arc<StreamHandle>,
+    _guard: StreamGuard,
+    /// Keeps the backend slot for as long as the stream runs.
+    _permit: Option<QueuePermit>,
+    /// Counted as pooled once the stream is over.
+    _connection: Connection,
+    signer: Option<Arc<ResponseSigner>>,
+    digest: Sha256,
+    tools: ToolDeltaNormalizer,
+    /// Converts events of non-OpenAI backends into chunks.
+    translator: Option<Box<dyn StreamTranslator>>,
+    /// Fills in missing identity fields, and repairs chunks in strict mode.
+    repair: ChunkRepair,
+    /// Puts masked originals back into the streamed text.
+    restorer: Option<StreamRestorer>,
+    /// Tokens of the stream, accounted when it ends.
+    usage: StreamUsage,
+    succeeded: bool,
+    /// Folds the stream into a completion, cached if the stream ends cleanly.
+    cache: Option<(String, ChunkAccumulator)>,
+    state: Arc<AppState>,
+    ctx: RequestContext,
+}
+
+impl EventWriter {
+    /// Returns `false` once the client has gone away.
+    async fn send(&mut self, mut event: SseEvent) -> bool {
+        if event.is_done() {
+            event.data = "[DONE]".to_string();
+            self.done = true;
+        } else if let Ok(mut chunk) = serde_json::from_str::<Value>(&event.data) {
+            let mut changed = completion::normalize_chunk(&mut chunk);
+            changed |= self.tools.normalize(&mut chunk);
+            changed |= self.repair.repair(&mut chunk);
+            if let Some(restorer) = &mut self.restorer {
+                changed |= restorer.restore_chunk(&mut chunk);
+            }
+            self.usage.observe(&chunk);
+            if let Some((_, accumulator)) = &mut self.cache {
+                accumulator.push(&chunk);
+            }
+            if let (Some(prior), Some(usage)) = (&self.ctx.prior_usage, chunk.get_mut("usage")) {
+                changed |= postedit::add_usage(usage, prior);
+            }
+            // JSON spread over several `data:` lines goes out on one.
+            if changed || event.data.contains('\n') {
+                event.data = chunk.to_string();
+            }
+            self.errored |= chunk.get("error").is_some();
+            self.finished |= chunk["choices"]
+                .as_array()
+                .is_some_and(|choices| choices.iter().any(|c| c["finish_reason"].is_string()));
+            self.meta.extend(completion::chunk_meta(&chunk));
+            for choice in chunk["choices"].as_array().into_iter().flatten() {
+                if let Some(text) = choice["delta"]["content"].as_str() {
+                    self.state.metrics.record_streamed(&self.ctx.model, text);
+                }
+            }
+        }
+        self.next_id += 1;
+        event.id = Some(self.next_id.to_string());
+        if self.event_types && event.event.is_none() {
+            event.event = Some(event.kind().to_string());
+        }
+        let bytes = event.to_bytes();
+        let len = bytes.len();
+        self.digest.update(&bytes);
+        let sent = self.tx.send(Ok(bytes)).await.is_ok();
+        if sent {
+            self.stream.record_sent(len);
+        }
+        sent
+    }
+
+    /// Sends an upstream event, translated into OpenAI chunks if needed.
+    async fn relay(&mut self, event: SseEvent) -> bool {
+        for event in translate(&mut self.translator, event) {
+            if event.is_done() && !(self.add_finish_reason().await && self.add_usage().await) {
+                return false;
+            }
+            if !self.send(event).await {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Sends what the translator holds back until the upstream has ended.
+    async fn finish_translation(&mut self) {
+        let Some(events) = self.translator.as_mut().map(|t| t.finish()) else {
+            return;
+        };
+        for event in events {
+            if !self.send(event).await {
+                return;
+            }
+        }
+    }
+
+    /// Sends a `stop` chunk unless a chunk already carried a `finish_reason`.
+    async fn add_finish_reason(&mut self) -> bool {
+        if self.finished || self.errored {
+            return true;
+        }
+        println!("Stream {} ended without a finish_reason, adding one", self.stream.request_id);
+        let chunk = completion::finish_chunk(&self.meta, "stop");
+        self.send(SseEvent::data(chunk.to_string())).await
+    }
+
+    /// Sends the `usage` chunk the client asked for if the backend sent none.
+    async fn add_usage(&mut self) -> bool {
+        if !self.ctx.include_usage || self.usage.reported() || self.errored {
+            return true;
+        }
+        let chunk = completion::usage_chunk(&self.meta, self.usage.usage());
+        self.send(SseEvent::data(chunk.to_string())).await
+    }
+
+    /// Ends a stream the upstream closed without a `finish_reason` or
+    /// `[DONE]`, so clients waiting for either do not hang.
+    async fn ensure_terminated(&mut self) {
+        if self.done || self.errored || !self.add_finish_reason().await || !self.add_usage().await {
+            return;
+        }
+        self.send(SseEvent::data("[DONE]")).await;
+    }
+
+    /// Appends the stream signature as an SSE comment, which clients ignore.
+    async fn sign(&mut self) {
+        let Some(signer) = &self.signer else {
+            return;
+        };
+        let digest = std::mem::take(&mut self.digest).finalize();
+        let comment = format!(": x-llmta-signature {}\n\n", signer.sign_digest(&digest));
+        let _ = self.tx.send(Ok(Bytes::from(comment))).await;
+    }
+
+    /// Ends the stream with an error event after an operator terminated it.
+    async fn terminate(&mut self) {
+        self.cache = None;
+        let error = serde_json::json!({
+            "error": {
+                "type": "stream_terminated",
+                "message": "The stream was terminated by an operator",
+            }
+        });
+        self.send(SseEvent::data(error.to_string())).await;
+    }
+
+    /// Ends the stream with an error event after a body timeout expired.
+    async fn time_out(&mut self, message: String) {
+        self.cache = None;
+        let error = serde_json::json!({
+            "error": { "type": "upstream_timeout", "message": message }
+        });
+        self.send(SseEvent::data(error.to_string())).await;
+    }
+
+    /// Closes the stream cleanly after the response budget or size cap ran
+    /// out, flagging the final chunk with `marker`.
+    async fn finish_partial(&mut self, marker: &str) {
+        if self.done {
+            return;
+        }
+        self.cache = None;
+        let mut chunk = completion::finish_chunk(&self.meta, "length");
+        chunk[marker] = Value::Bool(true);
+        if self.send(SseEvent::data(chunk.to_string())).await && self.add_usage().await {
+            self.send(SseEvent::data("[DONE]")).await;
+        }
+    }
+
+    async fn fail(&mut self, message: String) {
+        self.cache = None;
+        let _ = self
+            .tx
+            .send(Err(std::io::Error::other(message)))
+            .await;
+    }
+}
+
+impl Drop for EventWriter {
+    /// Accounts the tokens of a successful stream, however it ended, and
+    /// caches it if it ended cleanly.
+    fn drop(&mut self) {
+        if !self.succeeded {
+            return;
+        }
+        record_usage(&self.state, &self.ctx, Some(&self.usage.usage()));
+        if let Some((key, accumulator)) = self.cache.take().filter(|_| self.done && !self.errored) {
+            let mut completion = accumulator.into_completion();
+            if completion.get("usage").is_none() {
+                completion["usage"] = self.usage.usage();
+            }
+            let cache = self.state.cache.clone();
+            tokio::spawn(async move { cache.put(key, Bytes::from(completion.to_string())).await });
+        }
+    }
+}
+
+fn translate(translator: &mut Option<Box<dyn StreamTranslator>>, event: SseEvent) -> Vec<SseEvent> {
+    match translator.as_mut() {
+        Some(translator) => translator.translate(event),
+        None => vec![event],
+    }
+}
+
+/// Why no upstream item arrived in time.
+enum Expired {
+    /// The response budget ran out; what arrived so far is still served.
+    Budget,
+    Timeout(TimeoutPhase),
+}
+
+/// Waits for the next upstream item, or fails with whichever of the response
+/// budget and the body timeouts passes first. `started` once an item arrived.
+async fn next_before<S>(
+    upstream: &mut S,
+    deadline: Option<Instant>,
+    timeouts: &BodyTimeouts,
+    started: bool,
+) -> Result<Option<S::Item>, Expired>
+where
+    S: Stream + Unpin,
+{
+    let limit = match (deadline, timeouts.next(started)) {
+        (Some(deadline), Some((at, phase))) if at < deadline => Some((at, Expired::Timeout(phase))),
+        (Some(deadline), _) => Some((deadline, Expired::Budget)),
+        (None, Some((at, phase))) => Some((at, Expired::Timeout(phase))),
+        (None, None) => None,
+    };
+    match limit {
+        Some((at, expired)) => {
+            tokio::time::timeout_at(at, upstream.next()).await.map_err(|_| expired)
+        }
+        None => Ok(upstream.next().await),
+    }
+}
+
+fn body_timeout(phase: TimeoutPhase, timeouts: &BodyTimeouts) -> Response<Body> {
+    let message = phase.message(timeouts);
+    println!("{}", message);
+    create_error_response(StatusCode::GATEWAY_TIMEOUT, "Upstream timeout", &message)
+}
+
+async fn pump_events(
+    response: reqwest::Response,
+    mut writer: EventWriter,
+    mut parser: EventParser,
+    deadline: Option<Instant>,
+    cap: Option<(usize, OversizePolicy)>,
+) {
+    let mut upstream = Box::pin(response.bytes_stream());
+    let stream = writer.stream.clone();
+    let timeouts = writer.ctx.timeouts;
+    let mut received = 0usize;
+
+    loop {
+        let next = tokio::select! {
+            _ = stream.cancelled() => {
+                println!("Stream {} terminated", stream.request_id);
+                writer.terminate().await;
+                return;
+            }
+            next = next_before(&mut upstream, deadline, &timeouts, received > 0) => next,
+        };
+        let next = match next {
+            Ok(next) => next,
+            Err(Expired::Budget) => {
+                println!("Response budget exhausted, closing stream early");
+                if let Some(event) = parser.finish() {
+                    writer.relay(event).await;
+                }
+                writer.finish_partial("x_budget_exhausted").await;
+                writer.sign().await;
+                return;
+            }
+            Err(Expired::Timeout(phase)) => {
+                let message = phase.message(&timeouts);
+                println!("Stream {} timed out: {}", stream.request_id, message);
+                writer.time_out(message).await;
+                writer.sign().await;
+                return;
+            }
+        };
+        let Some(result) = next else {
+            break;
+        };
+        let chunk = match result {
+            Ok(chunk) => chunk,
+            Err(e) => {
+                println!("Upstream stream error: {}", e);
+                writer.fail(e.to_string()).await;
+                return;
+            }
+        };
+        if received == 0 {
+            writer.ctx.mark("first chunk");
+        }
+        received += chunk.len();
+        if let Some((max, policy)) = cap.filter(|(max, _)| received > *max) {
+            println!("Stream {} exceeded {} bytes, closing ({:?})", stream.request_id, max, policy);
+            match policy {
+                OversizePolicy::Error => {
+                    let error = serde_json::json!({
+                        "error": {
+                            "type": "response_too_large",
+                            "message": format!("The response exceeded {} bytes", max),
+                        }
+                    });
+                    writer.send(SseEvent::data(error.to_string())).await;
+                }
+                OversizePolicy::TruncateWithMarker => writer.finish_partial("x_truncated").await,
+            }
+            writer.sign().await;
+            return;
+        }
+        for event in parser.feed(&chunk) {
+            if !writer.relay(event).await {
+                return;
+            }
+        }
+    }
+
+    if let Some(event) = parser.finish() {
+        writer.relay(event).await;
+    }
+    writer.finish_translation().await;
+    writer.ensure_terminated().await;
+    writer.sign().await;
+}
+
+/// Fills in identity fields the backend left out and, in strict mode, fixes
+/// other deviations from the OpenAI schema.
+fn repair(state: &AppState, completion: &mut Value, ctx: &RequestContext) -> bool {
+    let validation = &state.config.validation;
+    let deviations = if validation.strict {
+        schema::repair_completion(completion, &ctx.model)
+    } else {
+        Vec::new()
+    };
+    if !deviations.is_empty() {
+        println!("Repaired response for {}: {}", ctx.request_id, deviations.join(", "));
+    }
+    let filled = schema::fill_completion(completion, validation.system_fingerprint.as_deref());
+    filled || !deviations.is_empty()
+}
+
+/// Serves a non-streaming request that was upgraded to streaming upstream so
+/// the partial output is still available if the response budget runs out.
+async fn handle_assembled_response(
+    state: &AppState,
+    response: reqwest::Response,
+    ctx: &RequestContext,
+) -> Response<Body> {
+    let deadline = ctx.deadline;
+    let status = StatusCode::from_u16(response.status().as_u16()).unwrap();
+    let mut upstream = Box::pin(response.bytes_stream());
+    let mut parser = EventParser::new(ctx.provider.stream_format());
+    let mut accumulator = ChunkAccumulator::default();
+    let mut tools = ToolDeltaNormalizer::new(ctx.single_tool_call);
+    let mut translator = ctx.provider.stream_translator(&ctx.model);
+    let max_bytes = state.config.limits.max_response_bytes;
+    let mut received = 0usize;
+
+    loop {
+        let next = match next_before(&mut upstream, deadline, &ctx.timeouts, received > 0).await {
+            Ok(next) => next,
+            Err(Expired::Budget) => {
+                println!("Response budget exhausted, returning partial completion");
+                accumulator.mark_budget_exhausted();
+                break;
+            }
+            Err(Expired::Timeout(phase)) => return body_timeout(phase, &ctx.timeouts),
+        };
+        match next {
+            Some(Ok(chunk)) => {
+                if received == 0 {
+                    ctx.mark("first chunk");
+                }
+                received += chunk.len();
+                if max_bytes.is_some_and(|max| received > max) {
+                    println!("Response exceeded {:?} bytes, returning truncated completion", max_bytes);
+                    accumulator.mark_truncated();
+                    break;
+                }
+                for event in parser.feed(&chunk) {
+                    for mut event in translate(&mut translator, event) {
+                        tools.normalize_event(&mut event);
+                        accumulator.push_event(&event);
+                    }
+                }
+            }
+            Some(Err(e)) => {
+                println!("Upstream stream error: {}", e);
+                return create_error_response(
+                    StatusCode::BAD_GATEWAY,
+                    "Failed to read response",
+                    &e.to_string(),
+                );
+            }
+            None => break,
+        }
+    }
+    let mut rest: Vec<SseEvent> = parser
+        .finish()
+        .map(|event| translate(&mut translator, event))
+        .unwrap_or_default();
+    rest.extend(translator.as_mut().map(|t| t.finish()).unwrap_or_default());
+    for mut event in rest {
+        tools.normalize_event(&mut event);
+        accumulator.push_event(&event);
+    }
+
+    let mut completion = accumulator.into_completion();
+    if status.is_success() {
+        if completion.get("usage").is_none() {
+            let tokenizer = state.tokenizers.for_model(&ctx.model);
+            let text = completion::completion_text(&completion);
+            completion["usage"] = completion::counted_usage(tokenizer.as_ref(), ctx.prompt_tokens, &text);
+        }
+        repair(state, &mut completion, ctx);
+    }
+    if let Some(placeholders) = &ctx.placeholders {
+        placeholders.restore_completion(&mut completion);
+    }
+    let mut body = Bytes::from(completion.to_string());
+    let mut builder = Response::builder()
+        .status(status)
+        .header(header::CONTENT_TYPE, "application/json");
+    if status.is_success() {
+        record_usage(state, ctx, completion.get("usage"));
+        if let (Some(prior), Some(usage)) = (&ctx.prior_usage, completion.get_mut("usage")) {
+            if postedit::add_usage(usage, prior) {
+                body = Bytes::from(completion.to_string());
+            }
+        }
+        if let Some(extra) = builder.headers_mut() {
+            body = enrich_translation(state, ctx, body, extra);
+            body = report_glossary(ctx, body, extra);
+        }
+    }
+    if let Some(signer) = &state.signer {
+        builder = builder.header("x-llmta-signature", signer.sign_body(&body));
+    }
+    builder.body(Body::from(body)).unwrap()
+}
+
+/// A request that passed the maintenance, auth, rate limit and quota checks.
+pub struct Admitted {
+    pub identity: Identity,
+    pub limit: Option<LimitStatus>,
+    pub lease: Arc<TenantLease>,
+}
+
+/// Runs the checks every proxied request goes through before it is sent
+/// anywhere, returning the error response to send when one fails.
+#[allow(clippy::result_large_err)]
+pub fn admit(
+    state: &AppState,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: &http::HeaderMap,
+    body: &Bytes,
+) -> Result<Admitted, Response<Body>> {
+    if let Some(mode) = state.maintenance.mode() {
+        let mut response = create_error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "maintenance",
+            &mode.message,
+        );
+        response.headers_mut().insert(
+            header::RETRY_AFTER,
+            http::HeaderValue::from(mode.retry_after_secs),
+        );
+        return Err(response);
+    }
+
+    let peer = connect_info.map(|ConnectInfo(addr)| addr.ip());
+    let identity = state.auth.identify(headers, peer, body)?;
+
+    let limit = match state.limiter.check(&identity.id, identity.requests_per_minute) {
+        Some(Err(status)) => {
+            let mut response = create_error_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                "rate_limit_exceeded",
+                &format!("Rate limit of {} requests per minute reached", status.limit),
+            );
+            status.apply(response.headers_mut());
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                http::HeaderValue::from(status.reset.as_secs().max(1)),
+            );
+            return Err(response);
+        }
+        Some(Ok(status)) => Some(status),
+        None => None,
+    };
+
+    let tenant = identity.tenant_key();
+    let lease = state
+        .quotas
+        .admit(&tenant, body.len())
+        .map_err(|exceeded| exceeded.into_response())?;
+
+    Ok(Admitted { identity, limit, lease })
+}
+
+pub async fn handle_chat(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    OriginalUri(uri): OriginalUri,
+    headers: http::HeaderMap,
+    body: Bytes,
+) -> Response<Body> {
+    let degraded = state.config.degraded.clone().map(|config| {
+        let message = config.message(uri.path(), &headers).to_string();
+        let payload = serde_json::from_slice::<Value>(&body).unwrap_or(Value::Null);
+        let model = payload["model"].as_str().unwrap_or(&state.config.default_model).to_string();
+        (config, message, model, payload["stream"].as_bool().unwrap_or(false))
+    });
+    let degrade = |response: Response<Body>| match &degraded {
+        Some((config, message, model, stream)) if config.applies(response.status()) => {
+            println!("Answering with the degraded response instead of {}", response.status());
+            degrade::response(message, model, *stream, response.status())
+        }
+        _ => response,
+    };
+
+    let Admitted { identity, limit, lease } = match admit(&state, connect_info, &headers, &body) {
+        Ok(admitted) => admitted,
+        // Request limits and tenant quotas; maintenance keeps its own message.
+        Err(response) if response.status() == StatusCode::TOO_MANY_REQUESTS => return degrade(response),
+        Err(response) => return response,
+    };
+
+    let mut payload = serde_json::from_slice::<Map<String, Value>>(&body).ok();
+    let model_of = |payload: Option<&Map<String, Value>>| {
+        payload
+            .and_then(|p| p.get("model"))
+            .and_then(Value::as_str)
+            .unwrap_or(&state.config.default_model)
+            .to_string()
+    };
+    let requested = model_of(payload.as_ref());
+    if let Err(response) = identity.check_model(&requested) {
+        return response;
+    }
+    let deprecation = ModelDeprecation::find(&state.config.deprecations, &requested);
+    if let Some(deprecation) = deprecation {
+        let retired = deprecation.is_past_sunset();
+        let reject = retired && deprecation.after_sunset == AfterSunset::Reject;
+        state.deprecations.record(&requested, &identity.label, deprecation, reject);
+        if reject {
+            let mut response =
+                create_error_response(StatusCode::GONE, "model_sunset", &deprecation.message(&requested));
+            response.headers_mut().extend(deprecation.headers());
+            return response;
+        }
+        if retired {
+            println!("{} still uses {}, past its sunset of {}", identity.label, requested, deprecation.sunset);
+        }
+    }
+    let deprecation_headers = deprecation.map(ModelDeprecation::headers).unwrap_or_default();
+    let mut body = body;
+    let mut tags = Vec::new();
+    if let Some(payload) = payload.as_mut() {
+        let request = RuleRequest {
+            path: uri.path(),
+            headers: &headers,
+            key_tags: identity.virtual_key.as_ref().map(|k| k.tags.as_slice()).unwrap_or_default(),
+            default_model: &state.config.default_model,
+            tokenizers: &state.tokenizers,
+        };
+        match rules::apply(&state.config.rules, &request, payload) {
+            Ok(outcome) => {
+                if outcome.changed {
+                    body = Bytes::from(serde_json::to_vec(payload).unwrap());
+                }
+                tags = outcome.tags;
+            }
+            Err(response) => return response,
+        }
+        let model = model_of(Some(payload));
+        if let Some(prompt) = SystemPrompt::find(&state.config.system_prompts, uri.path(), &model) {
+            if let Err(message) = prompt.apply(payload, uri.path(), &model) {
+                return create_error_response(StatusCode::BAD_REQUEST, "invalid_request_error", &message);
+            }
+            body = Bytes::from(serde_json::to_vec(payload).unwrap());
+        } else if payload.remove("x_prompt_vars").is_some() {
+            body = Bytes::from(serde_json::to_vec(payload).unwrap());
+        }
+    }
+    let model = model_of(payload.as_ref());
+    let scope = state.cache.scope(lease.tenant(), state.keys.overrides_for(&identity).as_ref());
+    let cache_key = payload.as_ref().and_then(|p| state.cache.key(&scope, p, &headers));
+    let stream = payload.as_ref().and_then(|p| p.get("stream")).and_then(Value::as_bool) == Some(true);
+    let mut similar = None;
+    if let (Some(key), Some(payload)) = (cache_key.as_deref(), payload.as_ref()) {
+        let reads = cache::reads(&headers);
+        let mut cached = if reads {
+            state.cache.get(key).await.map(|c| (c, "hit"))
+        } else {
+            None
+        };
+        if cached.is_none() {
+            similar = cache::semantic_query(&state, &scope, payload).await;
+            cached = similar
+                .as_ref()
+                .filter(|_| reads)
+                .and_then(|query| state.cache.similar(query))
+                .map(|c| (c, "semantic"));
+        }
+        if let Some((cached, how)) = cached {
+            let include_usage = payload
+                .get("stream_options")
+                .and_then(|o| o.get("include_usage"))
+                .and_then(Value::as_bool)
+                == Some(true);
+            let mut response = cache::replay(&state, cached, how, stream, include_usage);
+            response.headers_mut().extend(deprecation_headers);
+            rules::tag(&mut response, &tags);
+            state.metrics.record_request(uri.path(), &model, response.status());
+            limits::merge_upstream(response.headers_mut(), limit.as_ref(), None);
+            return quotas::hold(response, lease);
+        }
+    }
+    let key_tpm = identity.virtual_key.as_ref().and_then(|k| k.tokens_per_minute);
+    let charges = state.config.limits.charges(&identity.id, key_tpm, &model, || {
+        payload.as_ref().map_or(0, |payload| estimated_tokens(&state, &model, payload))
+    });
+    if let Err(exceeded) = state.budgets.take(&charges) {
+        println!("{} is out of {} budget", identity.label, exceeded.charge.what);
+        let mut response = degrade(exceeded.into_response());
+        let tokens = state.budgets.status(&charges, state.config.limits.soft_ratio);
+        limits::merge_upstream(response.headers_mut(), limit.as_ref(), tokens.as_ref());
+        return quotas::hold(response, lease);
+    }
+    let settlement = Settlement::new(&charges).map(Arc::new);
+    let chain = PostEditChain::find(&state.config.translation.post_edit, uri.path(), &model).cloned();
+    let chain = chain.zip(payload);
+    let mut response = match chain {
+        Some((chain, payload)) => {
+            forward_post_edit(state.clone(), headers, payload, &chain, identity, lease.clone(), settlement).await
+        }
+        None => {
+            forward_with_fallbacks(state.clone(), headers, body, identity, lease.clone(), None, settlement).await
+        }
+    };
+    // Streamed answers are cached by their stream as it ends cleanly.
+    if let Some(key) = cache_key.filter(|_| !stream) {
+        response = state.cache.store(key, similar, response).await;
+    }
+    let mut response = degrade(response);
+    response.headers_mut().extend(deprecation_headers);
+    rules::tag(&mut response, &tags);
+    state.metrics.record_request(uri.path(), &model, response.status());
+    let tokens = state.budgets.status(&charges, state.config.limits.soft_ratio);
+    limits::merge_upstream(response.headers_mut(), limit.as_ref(), tokens.as_ref());
+    quotas::hold(response, lease)
+}
+
+/// Tokens a chat request is charged against token budgets up front, as
+/// OpenAI does: its prompt plus the completion it may ask for.
+fn estimated_tokens(state: &AppState, model: &str, payload: &Map<String, Value>) -> u64 {
+    let messages = payload
+        .get("messages")
+        .and_then(Value::as_array)
+        .map(Vec::as_slice)
+        .unwrap_or_default();
+    let prompt = state.tokenizers.count_messages(model, messages) as u64;
+    let completion = payload
+        .get("max_completion_tokens")
+        .or(payload.get("max_tokens"))
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    prompt + completion
+}
+
+/// Answers through a `[[translation.post_edit]]` chain: a draft from the
+/// requested model, buffered, then the editor's refinement of it, streamed
+/// if the client asked for that and reporting the usage of both stages.
+async fn forward_post_edit(
+    state: Arc<AppState>,
+    headers: http::HeaderMap,
+    payload: Map<String, Value>,
+    chain: &PostEditChain,
+    identity: Identity,
+    lease: Arc<TenantLease>,
+    settlement: Option<Arc<Settlement>>,
+) -> Response<Body> {
+    let mut draft_headers = headers.clone();
+    if let Some(id) = headers.get("x-request-id").and_then(|v| v.to_str().ok()) {
+        if let Ok(value) = http::HeaderValue::from_str(&format!("{}-draft", id)) {
+            draft_headers.insert("x-request-id", value);
+        }
+    }
+    let body = Bytes::from(serde_json::to_vec(&postedit::draft_request(&payload)).unwrap());
+    let response = forward_with_fallbacks(
+        state.clone(),
+        draft_headers,
+        body,
+        identity.clone(),
+        lease.clone(),
+        None,
+        settlement.clone(),
+    )
+    .await;
+    if !response.status().is_success() {
+        return response;
+    }
+    let bytes = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap_or_default();
+    let draft = serde_json::from_slice::<Value>(&bytes).ok();
+    let Some((draft, usage)) = draft.as_ref().and_then(postedit::draft_of) else {
+        return create_error_response(
+            StatusCode::BAD_GATEWAY,
+            "Invalid upstream response",
+            "The draft stage of the post-edit chain returned no text",
+        );
+    };
+
+    println!("Post-editing a {} character draft with {}", draft.chars().count(), chain.editor);
+    let body = Bytes::from(serde_json::to_vec(&chain.edit_request(&payload, &draft)).unwrap());
+    forward_with_fallbacks(state, headers, body, identity, lease, Some(usage), settlement).await
+}
+
+/// Why a chat answer should go to the next model of a fallback chain:
+/// failures classified as retryable, which include timeouts, and
+/// content-filter refusals. Failures and JSON answers are buffered to
+/// classify them, so the response is handed back rebuilt.
+async fn fallback_reason(response: Response<Body>) -> (Response<Body>, Option<String>) {
+    let status = response.status();
+    let json = response
+        .headers()
+        .get(header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .is_some_and(|v| v.starts_with("application/json"));
+    if status.is_success() && !json {
+        return (response, None);
+    }
+    let (parts, body) = response.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap_or_default();
+    let reason = if status.is_success() {
+        let filtered = serde_json::from_slice::<Value>(&bytes).is_ok_and(|answer| {
+            answer["choices"]
+                .as_array()
+                .is_some_and(|choices| choices.iter().any(|c| c["finish_reason"] == "content_filter"))
+        });
+        filtered.then(|| "content filter".to_string())
+    } else {
+        // Errors reaching here are already in OpenAI's shape.
+        let error = provider::classify(status.as_u16(), &reqwest::header::HeaderMap::new(), &bytes);
+        if error.is_retryable() {
+            Some(format!("status {}", status.as_u16()))
+        } else if provider::is_content_policy(&error.error_type) {
+            Some("content filter".to_string())
+        } else {
+            None
+        }
+    };
+    (Response::from_parts(parts, Body::from(bytes)), reason)
+}
+
+/// `forward_chat` for the requested model and then, while the answer calls
+/// for it, for each model of the policy's `fallback_chain`. Responses name
+/// the model that answered in `x-llmta-answered-by`.
+async fn forward_with_fallbacks(
+    state: Arc<AppState>,
+    headers: http::HeaderMap,
+    body: Bytes,
+    identity: Identity,
+    lease: Arc<TenantLease>,
+    prior_usage: Option<Value>,
+    settlement: Option<Arc<Settlement>>,
+) -> Response<Body> {
+    let payload = serde_json::from_slice::<Value>(&body).ok();
+    let requested = payload
+        .as_ref()
+        .and_then(|p| p["model"].as_str())
+        .unwrap_or(state.config.default_model.as_str())
+        .to_string();
+    let chain = state.config.policy.resolve("chat", &requested).fallback_chain;
+    let Some(Value::Object(payload)) = payload.filter(|_| !chain.is_empty()) else {
+        return forward_chat(state, headers, body, identity, lease, prior_usage, settlement).await;
+    };
+
+    let mut response = forward_chat(
+        state.clone(),
+        headers.clone(),
+        body,
+        identity.clone(),
+        lease.clone(),
+        prior_usage.clone(),
+        settlement.clone(),
+    )
+    .await;
+    let mut answered_by = requested.clone();
+    for next in &chain {
+        let (checked, reason) = fallback_reason(response).await;
+        response = checked;
+        let Some(reason) = reason else {
+            break;
+        };
+        println!("{} failed ({}); falling back to {}", answered_by, reason, next);
+        let mut payload = payload.clone();
+        payload.insert("model".to_string(), Value::String(next.clone()));
+        let body = Bytes::from(serde_json::to_vec(&payload).unwrap());
+        response = forward_chat(
+            state.clone(),
+            headers.clone(),
+            body,
+            identity.clone(),
+            lease.clone(),
+            prior_usage.clone(),
+            settlement.clone(),
+        )
+        .await;
+        answered_by = next.clone();
+    }
+    if let Ok(value) = http::HeaderValue::from_str(&answered_by) {
+        response.headers_mut().insert("x-llmta-answered-by", value);
+    }
+    response
+}
+
+async fn forward_chat(
+    state: Arc<AppState>,
+    headers: http::HeaderMap,
+    body: Bytes,
+    identity: Identity,
+    lease: Arc<TenantLease>,
+    prior_usage: Option<Value>,
+    settlement: Option<Arc<Settlement>>,
+) -> Response<Body> {
+    let deadline = state.config.streaming.budget_ms
+        .map(|ms| Instant::now() + Duration::from_millis(ms));
+    let mut payload = serde_json::from_slice::<Value>(&body).ok();
+    let cache_key = match &payload {
+        Some(Value::Object(p)) if prior_usage.is_none() && p.get("stream") == Some(&Value::Bool(true)) => {
+            let scope = state.cache.scope(lease.tenant(), state.keys.overrides_for(&identity).as_ref());
+            state.cache.key(&scope, p, &headers)
+        }
+        _ => None,
+    };
+    let mut model = payload
+        .as_ref()
+        .and_then(|p| p.get("model"))
+        .and_then(Value::as_str)
+        .unwrap_or(state.config.default_model.as_str())
+        .to_string();
+    let mut rerouted = false;
+    if let Some(fallback) = state.slo.reroute(&model) {
+        println!("Error budget for {} exhausted; sending to {}", model, fallback);
+        if let Some(Value::Object(payload)) = payload.as_mut() {
+            payload.insert("model".to_string(), Value::String(fallback.clone()));
+            rerouted = true;
+        }
+        model = fallback;
+    }
+    let route = state.backends.route(&model, Some(&identity.tenant_key()), &state.maintenance);
+    if route.model != model {
+        println!("No backend serves {}; using {}", model, route.model);
+        if let Some(Value::Object(payload)) = payload.as_mut() {
+            payload.insert("model".to_string(), Value::String(route.model.clone()));
+            rerouted = true;
+        }
+        model = route.model.clone();
+    }
+    let backend = route.backend;
+    let template = match payload.as_mut() {
+        Some(Value::Object(payload)) => match state.templates.expand(payload) {
+            Ok(template) => template,
+            Err(message) => {
+                return create_error_response(StatusCode::BAD_REQUEST, "invalid_template", &message);
+            }
+        },
+        _ => None,
+    };
+    let mut ctx = RequestContext {
+        request_id: headers
+            .get("x-request-id")
+            .and_then(|v| v.to_str().ok())
+            .map(str::to_string)
+            .unwrap_or_else(|| uuid::Uuid::new_v4().to_string()),
+        key: identity.label.clone(),
+        model,
+        backend: backend.name.clone(),
+        provider: backend.provider.clone(),
+        deadline,
+        api_version: version::requested(&headers, &state.config.api)
+            .unwrap_or(state.config.api.default_version())
+            .to_string(),
+        source_chars: payload.as_ref().map(translation::source_characters).unwrap_or(0),
+        lease,
+        template: template.as_ref().map(|t| format!("{}@{}", t.name, t.version)),
+        single_tool_call: false,
+        placeholders: None,
+        prior_usage,
+        prompt_tokens: 0,
+        include_usage: false,
+        cache_key,
+        timeouts: BodyTimeouts::default(),
+        trace: None,
+        glossary: None,
+        settlement,
+    };
+    if state.verbose.applies(&identity) {
+        ctx.trace = Some(Arc::new(Trace::new(&ctx.request_id)));
+    }
+    println!(
+        "Chat request {} from {} for {} on {} (api version {}, template {})",
+        ctx.request_id,
+        ctx.key,
+        ctx.model,
+        ctx.backend,
+        ctx.api_version,
+        ctx.template.as_deref().unwrap_or("none")
+    );
+
+    if state.maintenance.is_drained(&ctx.backend) {
+        return create_error_response(
+            StatusCode::SERVICE_UNAVAILABLE,
+            "backend_unavailable",
+            &format!("Backend {} is draining and accepts no new requests", ctx.backend),
+        );
+    }
+
+    let mut body = body;
+    let mut assemble = false;
+    let mut streamed = false;
+    if let Some(Value::Object(payload)) = payload.as_mut() {
+        let mut rewritten = rerouted || ctx.template.is_some();
+
+        match state.prompts.expand(&identity.tenant_key(), payload) {
+            Ok(expanded) => rewritten |= expanded,
+            Err(message) => {
+                return create_error_response(StatusCode::BAD_REQUEST, "prompt_not_found", &message);
+            }
+        }
+
+        if let Some(overrides) = state.keys.overrides_for(&identity) {
+            overrides.apply(payload);
+            rewritten = true;
+        }
+
+        let terms = state.glossaries.relevant(&identity.tenant_key(), payload).unwrap_or_else(|e| {
+            println!("Glossary of {} unavailable: {}", identity.tenant_key(), e);
+            Vec::new()
+        });
+        let inline = payload.remove("x_glossary");
+        if !terms.is_empty() || inline.is_some() {
+            let applied = glossary::merge(&terms, inline).and_then(|glossary| {
+                translation::apply_glossary(payload, &Value::Object(glossary.clone()))?;
+                Ok(glossary)
+            });
+            match applied {
+                Ok(glossary) if state.glossaries.validates() => {
+                    let terms = glossary
+                        .iter()
+                        .filter_map(|(source, target)| Some((source.clone(), target.as_str()?.to_string())))
+                        .collect();
+                    ctx.glossary = Some(terms);
+                }
+                Ok(_) => {}
+                Err(message) => {
+                    return create_error_response(
+                        StatusCode::BAD_REQUEST,
+                        "invalid_request_error",
+                        &message,
+                    );
+                }
+            }
+            rewritten = true;
+        }
+
+        if payload.contains_key("parallel_tool_calls") {
+            let mode = state.config.tools.parallel_calls;
+            ctx.single_tool_call = tools::apply_parallel_mode(payload, mode);
+            rewritten |= mode != tools::ParallelToolCalls::Forward;
+        }
+
+        let rules = state.config.normalize.rules(state.config.flavor);
+        let changes = normalize::normalize(payload, &rules);
+        for change in &changes {
+            println!("Normalized {}: {}", ctx.request_id, change);
+        }
+        rewritten |= !changes.is_empty();
+
+        if let Some(config) = &state.config.compression {
+            let tokenizer = state.tokenizers.for_model(&ctx.model);
+            if let Some((before, after)) = compression::compress(payload, config, tokenizer.as_ref()) {
+                println!("Compressed prompt for {} from {} to {} tokens", ctx.request_id, before, after);
+                rewritten = true;
+            }
+        }
+
+        state.heatmap.sample(&ctx.model, ctx.template.as_deref(), payload);
+
+        if state.deidentifier.applies(backend.region.as_deref()) {
+            if let Some(placeholders) = state.deidentifier.mask(payload) {
+                println!("Masked {} entities in {} for {}", placeholders.len(), ctx.request_id, ctx.backend);
+                ctx.placeholders = Some(Arc::new(placeholders));
+                rewritten = true;
+            }
+        }
+
+        // With a response budget or truncating size cap, non-streaming requests
+        // are streamed upstream and reassembled here so a timeout or cut-off
+        // still yields the text generated so far.
+        let limits = &state.config.limits;
+        let truncates = limits.max_response_bytes.is_some()
+            && limits.oversize_policy == OversizePolicy::TruncateWithMarker;
+        if (deadline.is_some() || truncates) && !payload.get("stream").and_then(Value::as_bool).unwrap_or(false) {
+            payload.insert("stream".to_string(), Value::Bool(true));
+            rewritten = true;
+            assemble = true;
+        }
+
+        streamed = payload.get("stream").and_then(Value::as_bool).unwrap_or(false);
+        if streamed {
+            let messages = payload.get("messages").and_then(Value::as_array);
+            ctx.prompt_tokens = messages.map_or(0, |m| state.tokenizers.count_messages(&ctx.model, m) as u64);
+            ctx.include_usage = payload
+                .get("stream_options")
+                .and_then(|options| options.get("include_usage"))
+                .and_then(Value::as_bool)
+                .unwrap_or(false);
+        }
+        if let Some(capabilities) = state.discovery.get(&ctx.backend) {
+            let changes = capabilities.adapt(payload);
+            for change in &changes {
+                println!("Adapted {} to {}: {}", ctx.request_id, ctx.backend, change);
+            }
+            rewritten |= !changes.is_empty();
+        }
+        match ctx.provider.translate_request(payload) {
+            Ok(translated) => rewritten |= translated,
+            Err(message) => {
+                return create_error_response(StatusCode::BAD_REQUEST, "invalid_request_error", &message);
+            }
+        }
+
+        if rewritten {
+            body = Bytes::from(serde_json::to_vec(payload).unwrap());
+        }
+    }
+    ctx.mark("prepared");
+
+    // Convert axum headers to reqwest headers. The body may be rewritten and
+    // streams are parsed, so length, host and compression are left to reqwest.
+    let mut forward_headers = reqwest::header::HeaderMap::new();
+    for (key, value) in headers.iter() {
+        if [header::HOST, header::CONTENT_LENGTH, header::ACCEPT_ENCODING].contains(key) {
+            continue;
+        }
+        if let Ok(v) = reqwest::header::HeaderValue::from_bytes(value.as_bytes()) {
+            forward_headers.insert(reqwest::header::HeaderName::from_bytes(key.as_ref()).unwrap(), v);
+        }
+    }
+
+    ctx.provider.authorize(&mut forward_headers, identity.upstream_key(&backend));
+
+    if let Err(retry_after) = backend.breaker.admit() {
+        return breaker::rejection(&backend.name, retry_after);
+    }
+    let max_queue_wait = headers
+        .get("x-llmta-max-queue-wait-ms")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|v| v.parse::<u64>().ok())
+        .map(Duration::from_millis);
+    let (permit, mut queue_wait) = match state.admission.acquire(&ctx.backend, max_queue_wait).await {
+        Ok(admitted) => admitted,
+        Err(shed) => {
+            println!(
+                "Shedding {}: estimated queue wait {:?} on {}",
+                ctx.request_id, shed.estimated_wait, ctx.backend
+            );
+            let mut response = create_error_response(
+                StatusCode::TOO_MANY_REQUESTS,
+                "overloaded",
+                &format!(
+                    "Backend {} is saturated; estimated queue wait of {}ms exceeds the deadline",
+                    ctx.backend,
+                    shed.estimated_wait.as_millis()
+                ),
+            );
+            response.headers_mut().insert(
+                header::RETRY_AFTER,
+                http::HeaderValue::from(shed.estimated_wait.as_secs().max(1)),
+            );
+            return response;
+        }
+    };
+
+    let wait = state.smoother.reserve(&ctx.backend);
+    if !wait.is_zero() {
+        tokio::time::sleep(wait).await;
+        queue_wait += wait;
+    }
+    ctx.mark("queued");
+
+    let connecting = Instant::now();
+    let mut connection = backend.handshakes.connect().await;
+    queue_wait += connecting.elapsed();
+    ctx.mark("connected");
+
+    let mut policy = state.config.policy.resolve("chat", &ctx.model);
+    policy.screen_limit = state.config.limits.max_response_bytes;
+    // Providers that take the model in the URL have none in the body.
+    let fallback_body = policy.fallback_model.as_ref().and_then(|model| {
+        let mut payload = payload.clone()?;
+        if let Some(name) = payload.get_mut("model") {
+            *name = Value::String(model.clone());
+        }
+        Some(Bytes::from(serde_json::to_vec(&payload).unwrap()))
+    });
+    let url = ctx.provider.chat_url(&backend.url, &ctx.model, streamed);
+    let sent_at = Instant::now();
+    let recorded = state.feedback.record_request(RequestRecord {
+        request_id: ctx.request_id.clone(),
+        tenant: ctx.lease.tenant().to_string(),
+        key: ctx.key.clone(),
+        model: ctx.model.clone(),
+        backend: ctx.backend.clone(),
+        template: template.as_ref().map(|t| (t.name.clone(), t.version)),
+    });
+    let mut attempts: u32 = 0;
+    let sent = policy::send(&policy, ctx.provider.as_ref(), deadline, streamed, |fallback| {
+        attempts += 1;
+        let (url, body) = match (fallback, &fallback_body) {
+            (Some(model), Some(fallback_body)) => {
+                println!("Falling back to {} for {}", model, ctx.request_id);
+                (ctx.provider.chat_url(&backend.url, model, streamed), fallback_body.clone())
+            }
+            _ => (url.clone(), body.clone()),
+        };
+        let mut headers = forward_headers.clone();
+        ctx.provider.sign("POST", &url, &mut headers, &body);
+        backend.client
+            .post(url)
+            .headers(headers)
+            .body(body)
+    })
+    .await;
+    let _ = recorded.await;
+    // Time to response headers stands in for time to first token: streaming
+    // backends send headers once generation has started.
+    let succeeded = sent.as_ref().is_ok_and(|r| r.status().is_success());
+    state.slo.record(&ctx.model, sent_at.elapsed(), succeeded);
+    let status = sent.as_ref().ok().map(|r| r.status().as_u16());
+    state.metrics.record_upstream(&ctx.backend, &ctx.model, status, sent_at.elapsed());
+    state.metrics.record_retries(&ctx.backend, attempts.saturating_sub(1));
+    backend.observe(&sent);
+    ctx.timeouts = policy.body_timeouts(sent_at);
+    ctx.mark("headers");
+    if let Some(trace) = &ctx.trace {
+        let outcome = match &sent {
+            Ok(response) => response.status().to_string(),
+            Err(error) => format!("{:?}: {}", error.class, error.message),
+        };
+        trace.log(format_args!(
+            "sent {} bytes to {} in {} attempts, answered {}",
+            body.len(),
+            ctx.backend,
+            attempts,
+            outcome
+        ));
+    }
+
+    let response = match sent {
+        Ok(resp) => {
+            connection.established();
+            resp
+        }
+        Err(error) => {
+            println!("Failed to forward request ({:?}): {}", error.class, error.message);
+            return error.into_response();
+        }
+    };
+
+    let is_stream = response.headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|v| v.to_str().ok())
+        .map(|v| v.contains("text/event-stream"))
+        .unwrap_or(false)
+        || (streamed && response.status().is_success() && ctx.provider.stream_format() != StreamFormat::Sse);
+
+    let template = ctx.template.clone();
+    let mut response = if is_stream && assemble {
+        handle_assembled_response(&state, response, &ctx).await
+    } else if is_stream {
+        handle_streaming_response(state, response, ctx, permit, connection).await
+    } else {
+        handle_normal_response(&state, response, &ctx).await
+    };
+    response.headers_mut().insert(
+        "x-llmta-queue-wait-ms",
+        http::HeaderValue::from(queue_wait.as_millis() as u64),
+    );
+    if let Some(value) = template.and_then(|t| http::HeaderValue::from_str(&t).ok()) {
+        response.headers_mut().insert("x-llmta-template-version", value);
+    }
+    response
+}
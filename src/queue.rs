@@ -0,0 +1,183 @@
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{OwnedSemaphorePermit, Semaphore};
+
+/// Weight of the newest sample in the moving averages.
+const EWMA_ALPHA: f64 = 0.2;
+
+/// Concurrency-limited admission to one backend.
+struct BackendQueue {
+    semaphore: Arc<Semaphore>,
+    limit: usize,
+    waiting: AtomicUsize,
+    /// Moving averages in milliseconds; `None` until the first sample.
+    service_ms: Mutex<Option<f64>>,
+    wait_ms: Mutex<Option<f64>>,
+    admitted: AtomicU64,
+    shed: AtomicU64,
+}
+
+fn record(average: &Mutex<Option<f64>>, sample: Duration) {
+    let sample = sample.as_secs_f64() * 1000.0;
+    let mut average = average.lock().unwrap();
+    *average = Some(match *average {
+        Some(current) => current + EWMA_ALPHA * (sample - current),
+        None => sample,
+    });
+}
+
+impl BackendQueue {
+    /// Expected time until a new arrival gets a slot: everyone queued ahead
+    /// of it has to be served first, `limit` at a time.
+    fn estimate_wait(&self) -> Duration {
+        if self.semaphore.available_permits() > 0 {
+            return Duration::ZERO;
+        }
+        let service_ms = self.service_ms.lock().unwrap().unwrap_or(0.0);
+        let ahead = self.waiting.load(Ordering::Relaxed) + 1;
+        Duration::from_secs_f64(service_ms * ahead as f64 / self.limit as f64 / 1000.0)
+    }
+}
+
+/// Counts a request as queued until dropped, including when the client
+/// disconnects while waiting.
+struct Waiting<'a>(&'a AtomicUsize);
+
+impl<'a> Waiting<'a> {
+    fn enter(counter: &'a AtomicUsize) -> Self {
+        counter.fetch_add(1, Ordering::Relaxed);
+        Waiting(counter)
+    }
+}
+
+impl Drop for Waiting<'_> {
+    fn drop(&mut self) {
+        self.0.fetch_sub(1, Ordering::Relaxed);
+    }
+}
+
+/// Held while a request occupies a backend slot, including the whole
+/// stream for streaming responses.
+pub struct QueuePermit {
+    _permit: OwnedSemaphorePermit,
+    queue: Arc<BackendQueue>,
+    admitted: Instant,
+}
+
+impl Drop for QueuePermit {
+    fn drop(&mut self) {
+        record(&self.queue.service_ms, self.admitted.elapsed());
+    }
+}
+
+/// Why a request was turned away instead of queued.
+#[derive(Debug, Clone, Copy)]
+pub struct Shed {
+    pub estimated_wait: Duration,
+}
+
+/// Per-backend concurrency limits with adaptive load shedding: requests
+/// whose estimated wait exceeds their deadline are rejected up front rather
+/// than queued only to time out.
+pub struct Admission {
+    limit: Option<usize>,
+    default_max_wait: Option<Duration>,
+    queues: Mutex<HashMap<String, Arc<BackendQueue>>>,
+}
+
+impl Admission {
+    pub fn new(limit: Option<usize>, default_max_wait: Option<Duration>) -> Self {
+        Admission {
+            limit: limit.filter(|l| *l > 0),
+            default_max_wait,
+            queues: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn queue(&self, backend: &str, limit: usize) -> Arc<BackendQueue> {
+        self.queues
+            .lock()
+            .unwrap()
+            .entry(backend.to_string())
+            .or_insert_with(|| {
+                Arc::new(BackendQueue {
+                    semaphore: Arc::new(Semaphore::new(limit)),
+                    limit,
+                    waiting: AtomicUsize::new(0),
+                    service_ms: Mutex::new(None),
+                    wait_ms: Mutex::new(None),
+                    admitted: AtomicU64::new(0),
+                    shed: AtomicU64::new(0),
+                })
+            })
+            .clone()
+    }
+
+    /// Waits for a slot on `backend`. `max_wait` is the client's deadline and
+    /// falls back to the configured one. Returns the permit (if limits are
+    /// configured) and how long the request queued.
+    pub async fn acquire(
+        &self,
+        backend: &str,
+        max_wait: Option<Duration>,
+    ) -> Result<(Option<QueuePermit>, Duration), Shed> {
+        let Some(limit) = self.limit else {
+            return Ok((None, Duration::ZERO));
+        };
+        let queue = self.queue(backend, limit);
+        let max_wait = max_wait.or(self.default_max_wait);
+
+        let estimated_wait = queue.estimate_wait();
+        if max_wait.is_some_and(|max| estimated_wait > max) {
+            queue.shed.fetch_add(1, Ordering::Relaxed);
+            return Err(Shed { estimated_wait });
+        }
+
+        let started = Instant::now();
+        let waiting = Waiting::enter(&queue.waiting);
+        let acquire = queue.semaphore.clone().acquire_owned();
+        let permit = match max_wait {
+            Some(max) => tokio::time::timeout(max, acquire).await.ok(),
+            None => Some(acquire.await),
+        };
+        drop(waiting);
+
+        let Some(Ok(permit)) = permit else {
+            queue.shed.fetch_add(1, Ordering::Relaxed);
+            return Err(Shed { estimated_wait: started.elapsed() });
+        };
+        let waited = started.elapsed();
+        record(&queue.wait_ms, waited);
+        queue.admitted.fetch_add(1, Ordering::Relaxed);
+        Ok((
+            Some(QueuePermit {
+                _permit: permit,
+                queue,
+                admitted: Instant::now(),
+            }),
+            waited,
+        ))
+    }
+
+    /// Queue figures for the admin API; `null` when no limit is configured.
+    pub fn stats(&self, backend: &str) -> Value {
+        let Some(limit) = self.limit else {
+            return Value::Null;
+        };
+        let queue = self.queue(backend, limit);
+        let average = |m: &Mutex<Option<f64>>| m.lock().unwrap().map(|v| v.round());
+        json!({
+            "limit": limit,
+            "in_flight": limit - queue.semaphore.available_permits(),
+            "queued": queue.waiting.load(Ordering::Relaxed),
+            "avg_queue_wait_ms": average(&queue.wait_ms),
+            "avg_service_ms": average(&queue.service_ms),
+            "estimated_wait_ms": queue.estimate_wait().as_millis() as u64,
+            "admitted": queue.admitted.load(Ordering::Relaxed),
+            "shed": queue.shed.load(Ordering::Relaxed),
+        })
+    }
+}
@@ -0,0 +1,250 @@
+use axum::{
+    body::{Body, Bytes},
+    http::StatusCode,
+    response::Response,
+};
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::HashMap;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+
+use crate::create_error_response;
+
+/// Ceilings on what one tenant may hold at a time, so a single tenant
+/// cannot exhaust memory or sockets shared with everyone else.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct QuotaConfig {
+    #[serde(flatten)]
+    pub defaults: QuotaLimits,
+    /// Tenant-specific ceilings replacing the defaults.
+    #[serde(default)]
+    pub overrides: Vec<TenantQuota>,
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, Default)]
+pub struct QuotaLimits {
+    /// Requests in flight, streams included.
+    #[serde(default)]
+    pub max_connections: Option<usize>,
+    /// Request and response bytes held in memory.
+    #[serde(default)]
+    pub max_buffered_bytes: Option<usize>,
+    #[serde(default)]
+    pub max_cache_entries: Option<usize>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct TenantQuota {
+    pub tenant: String,
+    #[serde(flatten)]
+    pub limits: QuotaLimits,
+}
+
+#[derive(Default)]
+struct TenantUsage {
+    connections: AtomicUsize,
+    buffered_bytes: AtomicUsize,
+    cache_entries: AtomicUsize,
+}
+
+/// Adds `amount` to `counter` unless that would pass `limit`.
+fn try_add(counter: &AtomicUsize, amount: usize, limit: Option<usize>) -> bool {
+    counter
+        .fetch_update(Ordering::AcqRel, Ordering::Acquire, |current| {
+            let next = current + amount;
+            match limit {
+                Some(limit) if next > limit => None,
+                _ => Some(next),
+            }
+        })
+        .is_ok()
+}
+
+#[derive(Debug, Clone)]
+pub struct QuotaExceeded {
+    pub tenant: String,
+    pub resource: &'static str,
+    pub limit: usize,
+}
+
+impl QuotaExceeded {
+    pub fn into_response(self) -> Response<Body> {
+        create_error_response(
+            StatusCode::TOO_MANY_REQUESTS,
+            "tenant_quota_exceeded",
+            &format!("Tenant {} reached its {} quota of {}", self.tenant, self.resource, self.limit),
+        )
+    }
+}
+
+pub struct TenantQuotas {
+    config: QuotaConfig,
+    usage: Mutex<HashMap<String, Arc<TenantUsage>>>,
+}
+
+impl TenantQuotas {
+    pub fn new(config: QuotaConfig) -> Self {
+        TenantQuotas {
+            config,
+            usage: Mutex::new(HashMap::new()),
+        }
+    }
+
+    fn limits(&self, tenant: &str) -> QuotaLimits {
+        self.config
+            .overrides
+            .iter()
+            .find(|o| o.tenant == tenant)
+            .map(|o| o.limits)
+            .unwrap_or(self.config.defaults)
+    }
+
+    fn usage(&self, tenant: &str) -> Arc<TenantUsage> {
+        self.usage
+            .lock()
+            .unwrap()
+            .entry(tenant.to_string())
+            .or_default()
+            .clone()
+    }
+
+    /// Opens a connection for `tenant` holding `request_bytes` of buffered
+    /// body. Everything is released when the returned lease is dropped.
+    pub fn admit(&self, tenant: &str, request_bytes: usize) -> Result<Arc<TenantLease>, QuotaExceeded> {
+        let limits = self.limits(tenant);
+        let usage = self.usage(tenant);
+        if !try_add(&usage.connections, 1, limits.max_connections) {
+            return Err(QuotaExceeded {
+                tenant: tenant.to_string(),
+                resource: "connection",
+                limit: limits.max_connections.unwrap_or(0),
+            });
+        }
+        let lease = Arc::new(TenantLease {
+            tenant: tenant.to_string(),
+            limits,
+            usage,
+            buffered: AtomicUsize::new(0),
+        });
+        lease.buffer(request_bytes)?;
+        Ok(lease)
+    }
+
+    /// Counts a cache entry against `tenant`; `false` when over quota.
+    pub fn add_cache_entry(&self, tenant: &str) -> bool {
+        let limits = self.limits(tenant);
+        try_add(&self.usage(tenant).cache_entries, 1, limits.max_cache_entries)
+    }
+
+    pub fn remove_cache_entry(&self, tenant: &str) {
+        let usage = self.usage(tenant);
+        let _ = usage
+            .cache_entries
+            .fetch_update(Ordering::AcqRel, Ordering::Acquire, |n| n.checked_sub(1));
+    }
+
+    /// Forgets tenants holding nothing; returns how many.
+    pub fn purge_idle(&self) -> usize {
+        let mut usage = self.usage.lock().unwrap();
+        let before = usage.len();
+        usage.retain(|_, u| {
+            Arc::strong_count(u) > 1
+                || u.connections.load(Ordering::Acquire) > 0
+                || u.cache_entries.load(Ordering::Acquire) > 0
+        });
+        before - usage.len()
+    }
+
+    /// Current usage per tenant for the admin API.
+    pub fn snapshot(&self) -> Value {
+        let usage = self.usage.lock().unwrap();
+        let tenants: serde_json::Map<String, Value> = usage
+            .iter()
+            .map(|(tenant, usage)| {
+                let limits = self.limits(tenant);
+                (
+                    tenant.clone(),
+                    json!({
+                        "connections": usage.connections.load(Ordering::Relaxed),
+                        "max_connections": limits.max_connections,
+                        "buffered_bytes": usage.buffered_bytes.load(Ordering::Relaxed),
+                        "max_buffered_bytes": limits.max_buffered_bytes,
+                        "cache_entries": usage.cache_entries.load(Ordering::Relaxed),
+                        "max_cache_entries": limits.max_cache_entries,
+                    }),
+                )
+            })
+            .collect();
+        json!({ "tenants": tenants })
+    }
+}
+
+/// One open connection of a tenant and the bytes buffered on its behalf.
+pub struct TenantLease {
+    tenant: String,
+    limits: QuotaLimits,
+    usage: Arc<TenantUsage>,
+    buffered: AtomicUsize,
+}
+
+impl TenantLease {
+    pub fn tenant(&self) -> &str {
+        &self.tenant
+    }
+
+    /// Accounts `bytes` more buffered data to this connection.
+    pub fn buffer(&self, bytes: usize) -> Result<(), QuotaExceeded> {
+        if !try_add(&self.usage.buffered_bytes, bytes, self.limits.max_buffered_bytes) {
+            return Err(QuotaExceeded {
+                tenant: self.tenant.clone(),
+                resource: "buffered bytes",
+                limit: self.limits.max_buffered_bytes.unwrap_or(0),
+            });
+        }
+        self.buffered.fetch_add(bytes, Ordering::Relaxed);
+        Ok(())
+    }
+}
+
+impl Drop for TenantLease {
+    fn drop(&mut self) {
+        self.usage.connections.fetch_sub(1, Ordering::AcqRel);
+        self.usage
+            .buffered_bytes
+            .fetch_sub(*self.buffered.get_mut(), Ordering::AcqRel);
+    }
+}
+
+/// A response body that keeps its tenant lease until fully sent or dropped.
+struct LeasedBody {
+    body: Body,
+    _lease: Arc<TenantLease>,
+}
+
+impl http_body::Body for LeasedBody {
+    type Data = Bytes;
+    type Error = axum::Error;
+
+    fn poll_frame(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+    ) -> Poll<Option<Result<http_body::Frame<Bytes>, axum::Error>>> {
+        Pin::new(&mut self.body).poll_frame(cx)
+    }
+
+    fn is_end_stream(&self) -> bool {
+        self.body.is_end_stream()
+    }
+
+    fn size_hint(&self) -> http_body::SizeHint {
+        self.body.size_hint()
+    }
+}
+
+/// Ties `lease` to the lifetime of `response`'s body.
+pub fn hold(response: Response<Body>, lease: Arc<TenantLease>) -> Response<Body> {
+    response.map(|body| Body::new(LeasedBody { body, _lease: lease }))
+}
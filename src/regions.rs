@@ -0,0 +1,116 @@
+use serde::Deserialize;
+use serde_json::json;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use crate::backends::Backend;
+use crate::AppState;
+
+/// `[regions]`: routing between backends that serve the same models from
+/// several regions, as marked by their `region`.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RegionsConfig {
+    /// Region by tenant, used instead of the fastest one whenever a backend
+    /// in that region serves the model.
+    #[serde(default)]
+    pub pins: HashMap<String, String>,
+}
+
+/// Latest probe result for a regional backend. Backends count as healthy
+/// until a probe says otherwise.
+#[derive(Debug)]
+pub struct RegionProbe {
+    rtt_ms: AtomicU64,
+    healthy: AtomicBool,
+}
+
+impl Default for RegionProbe {
+    fn default() -> Self {
+        RegionProbe {
+            rtt_ms: AtomicU64::new(u64::MAX),
+            healthy: AtomicBool::new(true),
+        }
+    }
+}
+
+impl RegionProbe {
+    /// Round trip of the last successful probe, if any.
+    pub fn rtt(&self) -> Option<Duration> {
+        match self.rtt_ms.load(Ordering::Relaxed) {
+            u64::MAX => None,
+            ms => Some(Duration::from_millis(ms)),
+        }
+    }
+
+    pub fn is_healthy(&self) -> bool {
+        self.healthy.load(Ordering::Relaxed)
+    }
+
+    fn record(&self, rtt: Option<Duration>) {
+        self.rtt_ms
+            .store(rtt.map_or(u64::MAX, |rtt| rtt.as_millis() as u64), Ordering::Relaxed);
+        self.healthy.store(rtt.is_some(), Ordering::Relaxed);
+    }
+}
+
+/// Picks among the backends serving one model: the pinned region if one
+/// serves it, else the healthy region with the lowest probe round trip.
+/// Backends cooling down or with an open circuit are passed over, as by
+/// `Balancer::pick`. `None` when fewer than two candidates are regional or
+/// none is ready and healthy.
+pub fn select<'a>(candidates: &[&'a Arc<Backend>], pin: Option<&str>) -> Option<&'a Arc<Backend>> {
+    let regional: Vec<&Arc<Backend>> = candidates.iter().copied().filter(|b| b.region.is_some()).collect();
+    if regional.len() < 2 {
+        return None;
+    }
+    let ready: Vec<&Arc<Backend>> = regional
+        .into_iter()
+        .filter(|b| !b.cooldown.is_cooling() && b.breaker.allows())
+        .collect();
+    if let Some(pinned) = pin.and_then(|pin| ready.iter().copied().find(|b| b.region.as_deref() == Some(pin))) {
+        return Some(pinned);
+    }
+    ready
+        .iter()
+        .filter(|b| b.probe.is_healthy())
+        .min_by_key(|b| b.probe.rtt().unwrap_or(Duration::MAX))
+        .copied()
+}
+
+/// Times a request to the backend's `/models`. Any answer below 500 counts
+/// as reachable; errors, timeouts and 5xx mark the region unhealthy.
+async fn probe(backend: &Backend) -> Option<Duration> {
+    let mut headers = reqwest::header::HeaderMap::new();
+    backend.provider.authorize(&mut headers, &backend.key);
+    let started = Instant::now();
+    let response = backend
+        .client
+        .get(format!("{}/models", backend.base_url()))
+        .headers(headers)
+        .timeout(Duration::from_secs(5))
+        .send()
+        .await
+        .ok()?;
+    (!response.status().is_server_error()).then(|| started.elapsed())
+}
+
+/// Probes every regional backend once; run by the `region-probe` job.
+pub async fn probe_all(state: &AppState) -> String {
+    let mut probed = 0;
+    for backend in state.backends.all().filter(|b| b.region.is_some()) {
+        let rtt = probe(backend).await;
+        let alert = json!({ "backend": backend.name, "region": backend.region });
+        if rtt.is_none() && backend.probe.is_healthy() {
+            println!("Backend {} in {} failed its probe", backend.name, backend.region.as_deref().unwrap_or(""));
+            state.webhooks.notify("alert.backend_unhealthy", alert);
+        } else if rtt.is_some() && !backend.probe.is_healthy() {
+            println!("Backend {} in {} is healthy again", backend.name, backend.region.as_deref().unwrap_or(""));
+            state.webhooks.notify("alert.backend_recovered", alert);
+        }
+        backend.probe.record(rtt);
+        probed += 1;
+    }
+    format!("probed {} regional backends", probed)
+}
@@ -0,0 +1,164 @@
+use axum::{extract::Request, Router};
+use serde::Deserialize;
+use std::convert::Infallible;
+use std::path::Path;
+use std::sync::{Arc, RwLock};
+use std::time::{Duration, SystemTime};
+use tower::ServiceExt;
+
+use crate::config::AppConfig;
+use crate::glossary::GlossaryStore;
+use crate::AppState;
+
+/// `[reload]`: picking up configuration changes without a restart. SIGHUP
+/// always reloads; watching polls the files for changes.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ReloadConfig {
+    #[serde(default)]
+    pub watch: bool,
+    #[serde(default = "default_interval_secs")]
+    pub interval_secs: u64,
+}
+
+fn default_interval_secs() -> u64 {
+    5
+}
+
+impl Default for ReloadConfig {
+    fn default() -> Self {
+        ReloadConfig {
+            watch: false,
+            interval_secs: default_interval_secs(),
+        }
+    }
+}
+
+/// The state and routes built from the current configuration, replaced as a
+/// whole on reload. Requests in flight keep the state they started with.
+#[derive(Clone)]
+pub struct Reloadable {
+    live: Arc<RwLock<(Arc<AppState>, Router)>>,
+}
+
+impl Reloadable {
+    pub fn new(state: Arc<AppState>) -> Self {
+        let router = crate::router(state.clone());
+        Reloadable {
+            live: Arc::new(RwLock::new((state, router))),
+        }
+    }
+
+    pub fn state(&self) -> Arc<AppState> {
+        self.live.read().unwrap().0.clone()
+    }
+
+    /// Routes every request to the router current when it arrived.
+    pub fn router(&self) -> Router {
+        let live = self.clone();
+        Router::new().fallback(move |request: Request| {
+            let router = live.live.read().unwrap().1.clone();
+            async move {
+                let response = router.oneshot(request).await;
+                response.unwrap_or_else(|never: Infallible| match never {})
+            }
+        })
+    }
+
+    /// Switches to `config`, keeping what was learned at runtime: active
+    /// streams, budgets, metrics, operator switches and stored data.
+    /// `host`, `port`, `acme` and `database` only change on restart.
+    pub fn apply(&self, config: AppConfig) -> Result<(), String> {
+        let old = self.state();
+        if config.config_hash == old.config.config_hash {
+            return Ok(());
+        }
+        if (&config.host, config.port) != (&old.config.host, old.config.port) {
+            println!("Listen address changes only take effect after a restart");
+        }
+        let mut state = AppState::new(config).map_err(|e| e.to_string())?;
+        state.streams = old.streams.clone();
+        state.inflight = old.inflight.clone();
+        state.budgets = old.budgets.clone();
+        state.metrics = old.metrics.clone();
+        state.maintenance = old.maintenance.clone();
+        state.usage = old.usage.clone();
+        state.heatmap = old.heatmap.clone();
+        state.prompts = old.prompts.clone();
+        state.db = old.db.clone();
+        state.templates = old.templates.clone();
+        state.feedback = old.feedback.clone();
+        state.health = old.health.clone();
+        state.verbose = old.verbose.clone();
+        state.deprecations = old.deprecations.clone();
+        state.glossaries = Arc::new(GlossaryStore::new(
+            state.config.translation.glossary.clone(),
+            old.db.clone(),
+        ));
+        let state = Arc::new(state);
+        let router = crate::router(state.clone());
+        println!("Configuration reloaded ({})", &state.config.config_hash[..12]);
+        *self.live.write().unwrap() = (state, router);
+        Ok(())
+    }
+
+    /// Reloads `config/default` and `config/local`; a configuration that
+    /// fails to load or validate leaves the current one in place.
+    pub fn reload(&self) {
+        let loaded = AppConfig::load().map_err(|e| e.to_string());
+        if let Err(e) = loaded.and_then(|config| self.apply(config)) {
+            println!("Keeping the current configuration: {}", e);
+        }
+    }
+}
+
+/// Reloads on SIGHUP and, with `[reload].watch`, when a configuration file
+/// changes.
+pub fn spawn(live: Reloadable) {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let live = live.clone();
+        match signal(SignalKind::hangup()) {
+            Ok(mut hangup) => {
+                tokio::spawn(async move {
+                    while hangup.recv().await.is_some() {
+                        println!("SIGHUP received, reloading configuration");
+                        live.reload();
+                    }
+                });
+            }
+            Err(e) => println!("Cannot listen for SIGHUP: {}", e),
+        }
+    }
+    let config = live.state().config.reload.clone();
+    if !config.watch {
+        return;
+    }
+    tokio::spawn(async move {
+        let mut seen = modified();
+        loop {
+            tokio::time::sleep(Duration::from_secs(config.interval_secs.max(1))).await;
+            let current = modified();
+            if current != seen {
+                println!("Configuration files changed, reloading");
+                seen = current;
+                live.reload();
+            }
+        }
+    });
+}
+
+/// Latest modification time of the `config/default.*` and `config/local.*`
+/// files.
+fn modified() -> Option<SystemTime> {
+    std::fs::read_dir(Path::new("config"))
+        .ok()?
+        .flatten()
+        .filter(|entry| {
+            let path = entry.path();
+            let stem = path.file_stem().and_then(|s| s.to_str());
+            matches!(stem, Some("default" | "local"))
+        })
+        .filter_map(|entry| entry.metadata().and_then(|m| m.modified()).ok())
+        .max()
+}
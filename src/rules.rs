@@ -0,0 +1,248 @@
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderValue, StatusCode},
+    response::Response,
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+use crate::create_error_response;
+use crate::policy;
+use crate::tokenizer::TokenizerRegistry;
+use crate::translation;
+
+/// `[[rules]]` entry. Rules are evaluated in order against each chat
+/// request and every matching one applies; a `reject` ends evaluation.
+/// Later rules see the model, payload and tags left by earlier ones.
+#[derive(Debug, Deserialize, Clone)]
+pub struct Rule {
+    /// Shown in logs and rejection messages.
+    #[serde(default)]
+    pub name: Option<String>,
+    #[serde(default, rename = "match")]
+    pub when: RuleMatch,
+    #[serde(flatten)]
+    pub action: RuleAction,
+}
+
+/// Conditions a request must all meet; unset ones match everything. Lists
+/// match when any entry does, and a trailing `*` matches by prefix.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct RuleMatch {
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// Request paths, as routed (`/v1beta/openai/chat/completions`).
+    #[serde(default)]
+    pub paths: Vec<String>,
+    /// Header values by header name; a missing header does not match.
+    #[serde(default)]
+    pub headers: HashMap<String, String>,
+    /// Tags of the caller's virtual key or added by earlier rules.
+    #[serde(default)]
+    pub tags: Vec<String>,
+    /// Languages of the user messages as ISO 639-1 codes, e.g. `DE`.
+    #[serde(default)]
+    pub languages: Vec<String>,
+    /// Bounds on the prompt's tokens.
+    #[serde(default)]
+    pub min_tokens: Option<u64>,
+    #[serde(default)]
+    pub max_tokens: Option<u64>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+#[serde(tag = "action", rename_all = "lowercase")]
+pub enum RuleAction {
+    /// Sends the request to another model, on whichever backend serves it.
+    Route { model: String },
+    /// Answers with an error without calling a backend.
+    Reject {
+        #[serde(default = "default_reject_status")]
+        status: u16,
+        #[serde(default)]
+        message: Option<String>,
+    },
+    /// Edits the payload.
+    Transform {
+        /// Parameters forced onto the payload, e.g. `temperature`.
+        #[serde(default)]
+        set: Map<String, Value>,
+        #[serde(default)]
+        remove: Vec<String>,
+        /// System message inserted ahead of the client's messages.
+        #[serde(default)]
+        system_prompt: Option<String>,
+    },
+    /// Labels the request, for later rules and the `x-llmta-tags` header.
+    Tag { tags: Vec<String> },
+}
+
+fn default_reject_status() -> u16 {
+    403
+}
+
+/// What a chat request is matched on besides its payload.
+pub struct RuleRequest<'a> {
+    pub path: &'a str,
+    pub headers: &'a HeaderMap,
+    pub key_tags: &'a [String],
+    pub default_model: &'a str,
+    pub tokenizers: &'a TokenizerRegistry,
+}
+
+/// What evaluating the rules did to a request.
+#[derive(Debug, Default)]
+pub struct Outcome {
+    pub tags: Vec<String>,
+    /// The payload was edited and must be re-serialized.
+    pub changed: bool,
+}
+
+/// Facts about the payload, computed when a rule first needs them.
+#[derive(Default)]
+struct Derived {
+    language: Option<Option<String>>,
+    tokens: Option<u64>,
+}
+
+impl RuleMatch {
+    fn matches(
+        &self,
+        request: &RuleRequest,
+        payload: &Map<String, Value>,
+        tags: &[String],
+        derived: &mut Derived,
+    ) -> bool {
+        let model = payload
+            .get("model")
+            .and_then(Value::as_str)
+            .unwrap_or(request.default_model);
+        if !policy::matches(&self.models, model) || !policy::matches(&self.paths, request.path) {
+            return false;
+        }
+        let headers_match = self.headers.iter().all(|(name, pattern)| {
+            request
+                .headers
+                .get(name.as_str())
+                .and_then(|v| v.to_str().ok())
+                .is_some_and(|value| policy::matches(std::slice::from_ref(pattern), value))
+        });
+        if !headers_match {
+            return false;
+        }
+        if !self.tags.is_empty() && !tags.iter().any(|tag| policy::matches(&self.tags, tag)) {
+            return false;
+        }
+        if !self.languages.is_empty() {
+            let language = derived
+                .language
+                .get_or_insert_with(|| translation::detect_language(&user_text(payload)));
+            let known = language
+                .as_deref()
+                .is_some_and(|l| self.languages.iter().any(|want| want.eq_ignore_ascii_case(l)));
+            if !known {
+                return false;
+            }
+        }
+        if self.min_tokens.is_some() || self.max_tokens.is_some() {
+            let tokens = *derived.tokens.get_or_insert_with(|| {
+                let messages = payload
+                    .get("messages")
+                    .and_then(Value::as_array)
+                    .map(Vec::as_slice)
+                    .unwrap_or_default();
+                request.tokenizers.count_messages(model, messages) as u64
+            });
+            let too_few = self.min_tokens.is_some_and(|min| tokens < min);
+            let too_many = self.max_tokens.is_some_and(|max| tokens > max);
+            if too_few || too_many {
+                return false;
+            }
+        }
+        true
+    }
+}
+
+fn user_text(payload: &Map<String, Value>) -> String {
+    payload
+        .get("messages")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|m| m.get("role").and_then(Value::as_str) == Some("user"))
+        .filter_map(|m| m.get("content"))
+        .map(translation::content_text)
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Evaluates `rules` against a chat request, editing `payload` in place.
+/// Returns the response to send instead when a rule rejects the request.
+#[allow(clippy::result_large_err)]
+pub fn apply(
+    rules: &[Rule],
+    request: &RuleRequest,
+    payload: &mut Map<String, Value>,
+) -> Result<Outcome, Response<Body>> {
+    let mut outcome = Outcome {
+        tags: request.key_tags.to_vec(),
+        changed: false,
+    };
+    let mut derived = Derived::default();
+    for (index, rule) in rules.iter().enumerate() {
+        if !rule.when.matches(request, payload, &outcome.tags, &mut derived) {
+            continue;
+        }
+        let name = rule.name.clone().unwrap_or_else(|| format!("#{}", index + 1));
+        match &rule.action {
+            RuleAction::Route { model } => {
+                println!("Rule {} routes the request to {}", name, model);
+                payload.insert("model".to_string(), Value::String(model.clone()));
+                derived.tokens = None;
+                outcome.changed = true;
+            }
+            RuleAction::Reject { status, message } => {
+                println!("Rule {} rejected the request", name);
+                let status = StatusCode::from_u16(*status).unwrap_or(StatusCode::FORBIDDEN);
+                let message = message
+                    .clone()
+                    .unwrap_or_else(|| format!("The request was rejected by rule {}", name));
+                return Err(create_error_response(status, "request_rejected", &message));
+            }
+            RuleAction::Transform { set, remove, system_prompt } => {
+                for field in remove {
+                    payload.remove(field);
+                }
+                for (field, value) in set {
+                    payload.insert(field.clone(), value.clone());
+                }
+                if let Some(prompt) = system_prompt {
+                    if let Some(Value::Array(messages)) = payload.get_mut("messages") {
+                        messages.insert(0, json!({ "role": "system", "content": prompt }));
+                    }
+                }
+                derived = Derived::default();
+                outcome.changed = true;
+            }
+            RuleAction::Tag { tags } => {
+                for tag in tags {
+                    if !outcome.tags.contains(tag) {
+                        outcome.tags.push(tag.clone());
+                    }
+                }
+            }
+        }
+    }
+    Ok(outcome)
+}
+
+/// Reports a request's tags in `x-llmta-tags`.
+pub fn tag(response: &mut Response<Body>, tags: &[String]) {
+    if tags.is_empty() {
+        return;
+    }
+    if let Ok(value) = HeaderValue::from_str(&tags.join(",")) {
+        response.headers_mut().insert("x-llmta-tags", value);
+    }
+}
@@ -0,0 +1,127 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::time::Duration;
+
+use crate::AppState;
+
+/// `[pool]`: upstream connection pool sizing and idle reaping.
+#[derive(Debug, Deserialize, Clone)]
+pub struct PoolConfig {
+    /// Idle upstream connections are closed after this long.
+    #[serde(default = "default_idle_timeout_secs")]
+    pub idle_timeout_secs: u64,
+    /// Time allowed to open a connection to a backend, TLS included.
+    #[serde(default)]
+    pub connect_timeout_ms: Option<u64>,
+    /// Idle connections kept per backend host; unlimited when unset.
+    #[serde(default)]
+    pub max_idle_per_host: Option<usize>,
+    /// Streams that have not sent anything for this long are closed by the
+    /// `idle-stream-reaper` job, freeing their upstream connection.
+    #[serde(default)]
+    pub stream_idle_secs: Option<u64>,
+    /// Requests per backend that may be opening a connection at once; the
+    /// rest wait for a slot or a pooled connection. Unlimited when unset.
+    #[serde(default)]
+    pub max_handshakes: Option<usize>,
+    /// Connections opened to each backend at startup.
+    #[serde(default)]
+    pub prewarm: usize,
+}
+
+fn default_idle_timeout_secs() -> u64 {
+    90
+}
+
+impl Default for PoolConfig {
+    fn default() -> Self {
+        PoolConfig {
+            idle_timeout_secs: default_idle_timeout_secs(),
+            connect_timeout_ms: None,
+            max_idle_per_host: None,
+            stream_idle_secs: None,
+            max_handshakes: None,
+            prewarm: 0,
+        }
+    }
+}
+
+impl PoolConfig {
+    pub fn apply(&self, builder: reqwest::ClientBuilder) -> reqwest::ClientBuilder {
+        let mut builder = builder.pool_idle_timeout(Duration::from_secs(self.idle_timeout_secs));
+        if let Some(ms) = self.connect_timeout_ms {
+            builder = builder.connect_timeout(Duration::from_millis(ms));
+        }
+        match self.max_idle_per_host {
+            Some(max) => builder.pool_max_idle_per_host(max),
+            None => builder,
+        }
+    }
+}
+
+/// Reads a `kB` field of `/proc/self/status`, in bytes.
+#[cfg(target_os = "linux")]
+fn status_bytes(status: &str, field: &str) -> Option<u64> {
+    status
+        .lines()
+        .find_map(|line| line.strip_prefix(field)?.strip_prefix(':'))
+        .and_then(|value| value.trim().trim_end_matches("kB").trim().parse::<u64>().ok())
+        .map(|kb| kb * 1024)
+}
+
+#[cfg(target_os = "linux")]
+fn process() -> Value {
+    let status = std::fs::read_to_string("/proc/self/status").unwrap_or_default();
+    json!({
+        "resident_bytes": status_bytes(&status, "VmRSS"),
+        "peak_resident_bytes": status_bytes(&status, "VmHWM"),
+        "virtual_bytes": status_bytes(&status, "VmSize"),
+        "threads": status
+            .lines()
+            .find_map(|line| line.strip_prefix("Threads:"))
+            .and_then(|n| n.trim().parse::<u64>().ok()),
+        "open_fds": std::fs::read_dir("/proc/self/fd").ok().map(|fds| fds.count()),
+    })
+}
+
+/// Memory and descriptor counts are only read from procfs.
+#[cfg(not(target_os = "linux"))]
+fn process() -> Value {
+    json!({
+        "resident_bytes": null,
+        "peak_resident_bytes": null,
+        "virtual_bytes": null,
+        "threads": null,
+        "open_fds": null,
+    })
+}
+
+/// Process, tokio and connection pool figures for `/admin/runtime`.
+pub fn snapshot(state: &AppState) -> Value {
+    let metrics = tokio::runtime::Handle::current().metrics();
+    let streams = state.streams.stats();
+    let pool = &state.config.pool;
+    json!({
+        "process": process(),
+        "tokio": {
+            "workers": metrics.num_workers(),
+            "alive_tasks": metrics.num_alive_tasks(),
+            "global_queue_depth": metrics.global_queue_depth(),
+        },
+        // reqwest does not expose its pool, so the open upstream connections
+        // that matter, those held by streams, are counted from the registry.
+        "pool": {
+            "idle_timeout_secs": pool.idle_timeout_secs,
+            "connect_timeout_ms": pool.connect_timeout_ms,
+            "max_idle_per_host": pool.max_idle_per_host,
+            "stream_idle_secs": pool.stream_idle_secs,
+            "free_handshake_slots": state
+                .backends
+                .all()
+                .map(|b| (b.name.clone(), json!(b.handshakes.available())))
+                .collect::<serde_json::Map<_, _>>(),
+            "active_streams": streams.active,
+            "reaped_streams": state.streams.reaped(),
+        },
+    })
+}
@@ -0,0 +1,250 @@
+use futures::future::{BoxFuture, FutureExt};
+use std::future::Future;
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Value};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex, Weak};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tokio::sync::Notify;
+
+use crate::AppState;
+
+/// A recurring job: returns a short summary of what it did.
+pub type JobFn = Arc<dyn Fn(Arc<AppState>) -> BoxFuture<'static, Result<String, String>> + Send + Sync>;
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct SchedulerConfig {
+    /// Per-job interval overrides and switches, by job name.
+    #[serde(default)]
+    pub jobs: Vec<JobConfig>,
+}
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct JobConfig {
+    pub name: String,
+    #[serde(default)]
+    pub interval_secs: Option<u64>,
+    #[serde(default = "default_true")]
+    pub enabled: bool,
+}
+
+fn default_true() -> bool {
+    true
+}
+
+#[derive(Debug, Serialize, Clone, Default)]
+pub struct JobStatus {
+    pub runs: u64,
+    pub failures: u64,
+    /// Unix seconds.
+    pub last_run: Option<u64>,
+    pub last_duration_ms: Option<u64>,
+    pub last_result: Option<String>,
+    pub last_error: Option<String>,
+}
+
+struct Job {
+    name: String,
+    interval: Duration,
+    run: JobFn,
+    status: Mutex<JobStatus>,
+    trigger: Notify,
+}
+
+impl Job {
+    async fn execute(&self, state: Arc<AppState>) {
+        let started = Instant::now();
+        let result = (self.run)(state).await;
+        let mut status = self.status.lock().unwrap();
+        status.runs += 1;
+        status.last_run = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .ok()
+            .map(|d| d.as_secs());
+        status.last_duration_ms = Some(started.elapsed().as_millis() as u64);
+        match result {
+            Ok(summary) => status.last_result = Some(summary),
+            Err(e) => {
+                println!("Job {} failed: {}", self.name, e);
+                status.failures += 1;
+                status.last_error = Some(e);
+            }
+        }
+    }
+}
+
+/// Runs maintenance jobs in the background, in place of external cron.
+/// Jobs stop once the `AppState` they serve is dropped.
+pub struct Scheduler {
+    config: SchedulerConfig,
+    jobs: Mutex<Vec<Arc<Job>>>,
+    started: AtomicBool,
+}
+
+impl Scheduler {
+    pub fn new(config: SchedulerConfig) -> Self {
+        Scheduler {
+            config,
+            jobs: Mutex::new(Vec::new()),
+            started: AtomicBool::new(false),
+        }
+    }
+
+    /// Adds a job running every `interval` unless configured otherwise.
+    /// Jobs registered after `start` are not run.
+    pub fn register(&self, name: &str, interval: Duration, run: JobFn) {
+        let config = self.config.jobs.iter().find(|j| j.name == name);
+        if config.is_some_and(|j| !j.enabled) {
+            return;
+        }
+        let interval = config
+            .and_then(|j| j.interval_secs)
+            .map(Duration::from_secs)
+            .unwrap_or(interval);
+        self.jobs.lock().unwrap().push(Arc::new(Job {
+            name: name.to_string(),
+            interval,
+            run,
+            status: Mutex::new(JobStatus::default()),
+            trigger: Notify::new(),
+        }));
+    }
+
+    /// Spawns one loop per job; later calls are no-ops.
+    pub fn start(&self, state: Weak<AppState>) {
+        if self.started.swap(true, Ordering::SeqCst) {
+            return;
+        }
+        for job in self.jobs.lock().unwrap().iter().cloned() {
+            let state = state.clone();
+            tokio::spawn(async move {
+                loop {
+                    tokio::select! {
+                        _ = tokio::time::sleep(job.interval) => {}
+                        _ = job.trigger.notified() => {}
+                    }
+                    let Some(state) = state.upgrade() else {
+                        return;
+                    };
+                    job.execute(state).await;
+                }
+            });
+        }
+    }
+
+    /// Runs a job now instead of waiting for its interval.
+    pub fn trigger(&self, name: &str) -> bool {
+        let jobs = self.jobs.lock().unwrap();
+        let Some(job) = jobs.iter().find(|j| j.name == name) else {
+            return false;
+        };
+        job.trigger.notify_one();
+        true
+    }
+
+    pub fn snapshot(&self) -> Value {
+        let jobs: Vec<Value> = self
+            .jobs
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|job| {
+                let mut value = json!(*job.status.lock().unwrap());
+                value["name"] = json!(job.name);
+                value["interval_secs"] = json!(job.interval.as_secs());
+                value
+            })
+            .collect();
+        json!({ "jobs": jobs })
+    }
+}
+
+/// Wraps an async closure as a [`JobFn`].
+pub fn job<F, Fut>(run: F) -> JobFn
+where
+    F: Fn(Arc<AppState>) -> Fut + Send + Sync + 'static,
+    Fut: Future<Output = Result<String, String>> + Send + 'static,
+{
+    Arc::new(move |state| run(state).boxed())
+}
+
+/// Jobs every deployment gets: pruning per-key state that would otherwise
+/// only be trimmed lazily on the request path.
+pub fn register_builtin(scheduler: &Scheduler) {
+    scheduler.register(
+        "rate-limit-cleanup",
+        Duration::from_secs(60),
+        job(|state| async move {
+            let windows = state.limiter.purge_expired();
+            let buckets = state.smoother.purge_idle() + state.budgets.purge_idle();
+            Ok(format!("removed {} windows and {} buckets", windows, buckets))
+        }),
+    );
+    scheduler.register(
+        "nonce-cleanup",
+        Duration::from_secs(60),
+        job(|state| async move { Ok(format!("removed {} nonces", state.auth.purge_nonces())) }),
+    );
+    scheduler.register(
+        "quota-cleanup",
+        Duration::from_secs(300),
+        job(|state| async move { Ok(format!("removed {} idle tenants", state.quotas.purge_idle())) }),
+    );
+    scheduler.register(
+        "prompt-cleanup",
+        Duration::from_secs(600),
+        job(|state| async move {
+            Ok(format!("removed {} prompts", state.prompts.purge_expired(&state.quotas)))
+        }),
+    );
+    scheduler.register(
+        "request-log-cleanup",
+        Duration::from_secs(3600),
+        job(|state| async move {
+            let days = state.config.database.retention_days;
+            let removed = state.feedback.purge_older_than(days)?;
+            let dead = state.webhooks.purge_dead_older_than(days)?;
+            Ok(format!("removed {} requests and {} dead webhooks", removed, dead))
+        }),
+    );
+    scheduler.register(
+        "usage-cleanup",
+        Duration::from_secs(3600),
+        job(|state| async move { Ok(format!("removed {} hours of usage", state.usage.purge_expired())) }),
+    );
+    scheduler.register(
+        "webhook-delivery",
+        Duration::from_secs(5),
+        job(|state| async move { state.webhooks.deliver_due().await }),
+    );
+    scheduler.register(
+        "region-probe",
+        Duration::from_secs(30),
+        job(|state| async move { Ok(crate::regions::probe_all(&state).await) }),
+    );
+    scheduler.register(
+        "token-heatmap",
+        Duration::from_secs(60),
+        job(|state| async move { Ok(state.heatmap.aggregate(&state.tokenizers)) }),
+    );
+    scheduler.register(
+        "idle-stream-reaper",
+        Duration::from_secs(30),
+        job(|state| async move {
+            let Some(secs) = state.config.pool.stream_idle_secs else {
+                return Ok("disabled".to_string());
+            };
+            Ok(format!("closed {} idle streams", state.streams.reap_idle(Duration::from_secs(secs))))
+        }),
+    );
+    scheduler.register(
+        "request-watchdog",
+        Duration::from_secs(30),
+        job(|state| async move {
+            let config = &state.config.watchdog;
+            let max_lifetime = Duration::from_secs(config.max_lifetime_secs);
+            let overdue = state.inflight.check(max_lifetime, config.abort);
+            Ok(format!("{} requests past their maximum lifetime", overdue))
+        }),
+    );
+}
@@ -0,0 +1,209 @@
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// `[validation]`: checks upstream (or translated) responses against the
+/// OpenAI chat completion schema.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ValidationConfig {
+    /// Log and repair deviations, such as a missing `id`, a wrong `object`
+    /// or an absent `created`, before they reach the client.
+    #[serde(default)]
+    pub strict: bool,
+    /// `system_fingerprint` for responses whose backend sends none.
+    #[serde(default)]
+    pub system_fingerprint: Option<String>,
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs())
+        .unwrap_or(0)
+}
+
+fn completion_id() -> String {
+    format!("chatcmpl-{}", uuid::Uuid::new_v4().simple())
+}
+
+/// Fills in `id`, `created` and, when one is configured, `system_fingerprint`
+/// where a backend omits them. Returns whether anything was added.
+fn fill_identity(value: &mut Value, id: &str, created: u64, fingerprint: Option<&str>) -> bool {
+    let Some(value) = value.as_object_mut().filter(|v| !v.contains_key("error")) else {
+        return false;
+    };
+    let mut filled = false;
+    let fields = [
+        ("id", Some(json!(id))),
+        ("created", Some(json!(created))),
+        ("system_fingerprint", fingerprint.map(|f| json!(f))),
+    ];
+    for (key, default) in fields {
+        let Some(default) = default else {
+            continue;
+        };
+        if matches!(value.get(key), None | Some(Value::Null)) {
+            value.insert(key.to_string(), default);
+            filled = true;
+        }
+    }
+    filled
+}
+
+/// Values filled in for missing identity fields.
+struct Defaults<'a> {
+    object: &'static str,
+    id: &'a str,
+    created: u64,
+    model: &'a str,
+}
+
+fn repair_envelope(value: &mut Map<String, Value>, defaults: &Defaults, deviations: &mut Vec<String>) {
+    if value.get("id").and_then(Value::as_str).is_none_or(str::is_empty) {
+        deviations.push("missing id".to_string());
+        value.insert("id".to_string(), json!(defaults.id));
+    }
+    match value.get("object").and_then(Value::as_str) {
+        Some(object) if object == defaults.object => {}
+        found => {
+            deviations.push(format!("object {:?} instead of {:?}", found.unwrap_or_default(), defaults.object));
+            value.insert("object".to_string(), json!(defaults.object));
+        }
+    }
+    if !value.get("created").is_some_and(Value::is_u64) {
+        deviations.push("missing created".to_string());
+        value.insert("created".to_string(), json!(defaults.created));
+    }
+    if !value.get("model").is_some_and(Value::is_string) {
+        deviations.push("missing model".to_string());
+        value.insert("model".to_string(), json!(defaults.model));
+    }
+    if !value.get("choices").is_some_and(Value::is_array) {
+        deviations.push("choices is not an array".to_string());
+        value.insert("choices".to_string(), json!([]));
+    }
+    if value.get("usage").is_some_and(|u| !u.is_null() && !u.is_object()) {
+        deviations.push("usage is not an object".to_string());
+        value.remove("usage");
+    }
+}
+
+/// Repairs each choice with `body` (`message` or `delta`) in place.
+fn repair_choices(
+    value: &mut Map<String, Value>,
+    body: &str,
+    finish_reason: Value,
+    deviations: &mut Vec<String>,
+) {
+    let Some(Value::Array(choices)) = value.get_mut("choices") else {
+        return;
+    };
+    for (position, choice) in choices.iter_mut().enumerate() {
+        if !choice.is_object() {
+            deviations.push(format!("choice {} is not an object", position));
+            *choice = json!({});
+        }
+        let choice = choice.as_object_mut().unwrap();
+        if !choice.get("index").is_some_and(Value::is_u64) {
+            deviations.push(format!("choice {} has no index", position));
+            choice.insert("index".to_string(), json!(position));
+        }
+        if !choice.get(body).is_some_and(Value::is_object) {
+            deviations.push(format!("choice {} has no {}", position, body));
+            choice.insert(body.to_string(), json!({}));
+        }
+        if body == "message" {
+            let message = choice[body].as_object_mut().unwrap();
+            if !message.get("role").is_some_and(Value::is_string) {
+                deviations.push(format!("choice {} message has no role", position));
+                message.insert("role".to_string(), json!("assistant"));
+            }
+            if !message.contains_key("content") {
+                deviations.push(format!("choice {} message has no content", position));
+                message.insert("content".to_string(), Value::Null);
+            }
+        }
+        let reason_ok = match choice.get("finish_reason") {
+            Some(Value::String(_)) => true,
+            Some(Value::Null) => finish_reason.is_null(),
+            _ => false,
+        };
+        if !reason_ok {
+            deviations.push(format!("choice {} has no finish_reason", position));
+            choice.insert("finish_reason".to_string(), finish_reason.clone());
+        }
+    }
+}
+
+/// `fill_identity` for a whole `chat.completion`, with a fresh ID.
+pub fn fill_completion(completion: &mut Value, fingerprint: Option<&str>) -> bool {
+    fill_identity(completion, &completion_id(), now(), fingerprint)
+}
+
+/// Repairs a `chat.completion` in place, returning the deviations found.
+/// Error bodies and non-objects are left alone.
+pub fn repair_completion(completion: &mut Value, model: &str) -> Vec<String> {
+    let mut deviations = Vec::new();
+    let Some(value) = completion.as_object_mut().filter(|v| !v.contains_key("error")) else {
+        return deviations;
+    };
+    let id = completion_id();
+    let defaults = Defaults {
+        object: "chat.completion",
+        id: &id,
+        created: now(),
+        model,
+    };
+    repair_envelope(value, &defaults, &mut deviations);
+    repair_choices(value, "message", json!("stop"), &mut deviations);
+    deviations
+}
+
+/// Repairs the chunks of one stream, filling in the same `id` and `created`
+/// in every chunk that lacks them.
+pub struct ChunkRepair {
+    request_id: String,
+    id: String,
+    created: u64,
+    model: String,
+    strict: bool,
+    fingerprint: Option<String>,
+    reported: bool,
+}
+
+impl ChunkRepair {
+    pub fn new(request_id: &str, model: &str, config: &ValidationConfig) -> Self {
+        ChunkRepair {
+            request_id: request_id.to_string(),
+            id: completion_id(),
+            created: now(),
+            model: model.to_string(),
+            strict: config.strict,
+            fingerprint: config.system_fingerprint.clone(),
+            reported: false,
+        }
+    }
+
+    /// Returns whether the chunk changed. Deviations are logged for the
+    /// first repaired chunk only, since they usually repeat in every chunk.
+    pub fn repair(&mut self, chunk: &mut Value) -> bool {
+        let mut changed = false;
+        if let Some(value) = chunk.as_object_mut().filter(|v| self.strict && !v.contains_key("error")) {
+            let mut deviations = Vec::new();
+            let defaults = Defaults {
+                object: "chat.completion.chunk",
+                id: &self.id,
+                created: self.created,
+                model: &self.model,
+            };
+            repair_envelope(value, &defaults, &mut deviations);
+            repair_choices(value, "delta", Value::Null, &mut deviations);
+            if !deviations.is_empty() && !self.reported {
+                self.reported = true;
+                println!("Repairing stream {}: {}", self.request_id, deviations.join(", "));
+            }
+            changed = !deviations.is_empty();
+        }
+        fill_identity(chunk, &self.id, self.created, self.fingerprint.as_deref()) || changed
+    }
+}
@@ -0,0 +1,127 @@
+use std::path::PathBuf;
+
+use crate::cli::DaemonArgs;
+
+/// Command line options for running detached from the terminal on Unix:
+/// `--daemon [--pidfile <path>] [--log-file <path>]`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct DaemonOptions {
+    pub pidfile: Option<PathBuf>,
+    /// Receives stdout and stderr once detached; discarded when unset.
+    pub log_file: Option<PathBuf>,
+}
+
+impl DaemonOptions {
+    /// Returns `None` unless `--daemon` was passed.
+    pub fn from_cli(args: &DaemonArgs) -> Option<Self> {
+        args.daemon.then(|| DaemonOptions {
+            pidfile: args.pidfile.clone(),
+            log_file: args.log_file.clone(),
+        })
+    }
+}
+
+/// Forks into the background, writes the pidfile and redirects output.
+/// The working directory is kept so `config/` keeps resolving.
+#[cfg(unix)]
+pub fn daemonize(options: &DaemonOptions) -> Result<(), Box<dyn std::error::Error>> {
+    use std::fs::OpenOptions;
+
+    let mut daemon = daemonize::Daemonize::new().working_directory(std::env::current_dir()?);
+    if let Some(pidfile) = &options.pidfile {
+        daemon = daemon.pid_file(pidfile);
+    }
+    if let Some(path) = &options.log_file {
+        let log = OpenOptions::new().create(true).append(true).open(path)?;
+        daemon = daemon.stdout(log.try_clone()?).stderr(log);
+    }
+    daemon.start()?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn daemonize(_options: &DaemonOptions) -> Result<(), Box<dyn std::error::Error>> {
+    Err("--daemon is only supported on Unix; install as a Windows service instead".into())
+}
+
+/// Windows service control integration. Register the binary with
+/// `sc create llm-translator-adapter binPath= "<exe> service"`.
+#[cfg(windows)]
+pub mod windows {
+    use std::ffi::OsString;
+    use std::time::Duration;
+    use windows_service::service::{
+        ServiceControl, ServiceControlAccept, ServiceExitCode, ServiceState, ServiceStatus,
+        ServiceType,
+    };
+    use windows_service::service_control_handler::{self, ServiceControlHandlerResult};
+    use windows_service::{define_windows_service, service_dispatcher};
+
+    use crate::{serve, AppConfig};
+
+    pub const SERVICE_NAME: &str = "llm-translator-adapter";
+
+    define_windows_service!(ffi_service_main, service_main);
+
+    /// Hands the process to the service control manager; blocks until the
+    /// service stops.
+    pub fn run() -> windows_service::Result<()> {
+        service_dispatcher::start(SERVICE_NAME, ffi_service_main)
+    }
+
+    fn service_main(_arguments: Vec<OsString>) {
+        if let Err(e) = run_service() {
+            eprintln!("Service failed: {}", e);
+        }
+    }
+
+    fn status(state: ServiceState, accept: ServiceControlAccept, exit_code: u32) -> ServiceStatus {
+        ServiceStatus {
+            service_type: ServiceType::OWN_PROCESS,
+            current_state: state,
+            controls_accepted: accept,
+            exit_code: ServiceExitCode::Win32(exit_code),
+            checkpoint: 0,
+            wait_hint: Duration::default(),
+            process_id: None,
+        }
+    }
+
+    fn run_service() -> Result<(), Box<dyn std::error::Error>> {
+        // Services start in System32; resolve `config/` next to the executable.
+        if let Some(dir) = std::env::current_exe()?.parent() {
+            std::env::set_current_dir(dir)?;
+        }
+
+        let (stop_tx, stop_rx) = tokio::sync::oneshot::channel::<()>();
+        let mut stop_tx = Some(stop_tx);
+        let handle = service_control_handler::register(SERVICE_NAME, move |control| match control {
+            ServiceControl::Stop | ServiceControl::Shutdown => {
+                if let Some(tx) = stop_tx.take() {
+                    let _ = tx.send(());
+                }
+                ServiceControlHandlerResult::NoError
+            }
+            ServiceControl::Interrogate => ServiceControlHandlerResult::NoError,
+            _ => ServiceControlHandlerResult::NotImplemented,
+        })?;
+
+        handle.set_service_status(status(
+            ServiceState::Running,
+            ServiceControlAccept::STOP | ServiceControlAccept::SHUTDOWN,
+            0,
+        ))?;
+
+        let result = AppConfig::load()
+            .map_err(|e| -> Box<dyn std::error::Error> { e.into() })
+            .and_then(|config| {
+                tokio::runtime::Runtime::new()?.block_on(serve(config, async {
+                    let _ = stop_rx.await;
+                }))
+            });
+
+        let exit_code = if result.is_ok() { 0 } else { 1 };
+        handle.set_service_status(status(ServiceState::Stopped, ServiceControlAccept::empty(), exit_code))?;
+        result
+    }
+}
@@ -0,0 +1,93 @@
+use serde::Deserialize;
+use std::future::Future;
+use std::time::Duration;
+use tokio::sync::watch;
+
+use crate::AppState;
+
+/// `[shutdown]`: how a stopping server treats requests still in flight.
+#[derive(Debug, Deserialize, Clone)]
+pub struct ShutdownConfig {
+    /// Seconds active streams get to finish once the listener has stopped
+    /// accepting connections; streams still open then are terminated.
+    #[serde(default = "default_drain_timeout_secs")]
+    pub drain_timeout_secs: u64,
+}
+
+fn default_drain_timeout_secs() -> u64 {
+    30
+}
+
+impl Default for ShutdownConfig {
+    fn default() -> Self {
+        ShutdownConfig {
+            drain_timeout_secs: default_drain_timeout_secs(),
+        }
+    }
+}
+
+/// Resolves on SIGTERM or Ctrl-C (SIGINT).
+pub async fn signal() {
+    let interrupt = async {
+        if let Err(e) = tokio::signal::ctrl_c().await {
+            println!("Cannot listen for Ctrl-C: {}", e);
+            std::future::pending::<()>().await;
+        }
+    };
+    #[cfg(unix)]
+    let terminate = async {
+        use tokio::signal::unix::{signal, SignalKind};
+        match signal(SignalKind::terminate()) {
+            Ok(mut sigterm) => {
+                sigterm.recv().await;
+            }
+            Err(e) => {
+                println!("Cannot listen for SIGTERM: {}", e);
+                std::future::pending::<()>().await;
+            }
+        }
+    };
+    #[cfg(not(unix))]
+    let terminate = std::future::pending::<()>();
+    tokio::select! {
+        _ = interrupt => {}
+        _ = terminate => {}
+    }
+}
+
+/// One shutdown signal, awaited by both the listener and the drain timer.
+#[derive(Clone)]
+pub struct Stopping(watch::Receiver<bool>);
+
+impl Stopping {
+    pub fn new(signal: impl Future<Output = ()> + Send + 'static) -> Self {
+        let (stop, stopping) = watch::channel(false);
+        tokio::spawn(async move {
+            signal.await;
+            let _ = stop.send(true);
+        });
+        Stopping(stopping)
+    }
+
+    pub async fn wait(mut self) {
+        let _ = self.0.wait_for(|stopping| *stopping).await;
+    }
+}
+
+/// Resolves once the drain timeout has passed after `stopping`, having
+/// terminated the streams still open so their clients see them end.
+pub async fn drain_deadline(state: &AppState, stopping: Stopping) {
+    stopping.wait().await;
+    state.health.set_draining();
+    let timeout = Duration::from_secs(state.config.shutdown.drain_timeout_secs);
+    println!(
+        "Shutting down: {} active streams get up to {:?} to finish",
+        state.streams.active(),
+        timeout
+    );
+    tokio::time::sleep(timeout).await;
+    let terminated = state.streams.terminate_all();
+    println!("Drain timeout passed, terminated {} streams", terminated);
+    // Lets the terminated streams send their closing events.
+    tokio::time::sleep(Duration::from_secs(1)).await;
+}
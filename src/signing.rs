@@ -0,0 +1,58 @@
+use ed25519_dalek::{Signer, SigningKey};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+
+/// Response signatures are Ed25519 over the SHA-256 digest of the bytes sent
+/// to the client: the body for buffered responses, every byte of the event
+/// stream before the trailing signature comment for streams.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SigningConfig {
+    /// Hex-encoded 32-byte Ed25519 seed.
+    pub private_key: String,
+    #[serde(default = "default_key_id")]
+    pub key_id: String,
+}
+
+fn default_key_id() -> String {
+    "default".to_string()
+}
+
+pub struct ResponseSigner {
+    key: SigningKey,
+    key_id: String,
+}
+
+impl ResponseSigner {
+    pub fn from_config(config: &SigningConfig) -> Result<Self, String> {
+        let seed: [u8; 32] = hex::decode(config.private_key.trim())
+            .map_err(|e| format!("signing.private_key is not valid hex: {}", e))?
+            .try_into()
+            .map_err(|_| "signing.private_key must be 32 bytes".to_string())?;
+        Ok(ResponseSigner {
+            key: SigningKey::from_bytes(&seed),
+            key_id: config.key_id.clone(),
+        })
+    }
+
+    pub fn key_id(&self) -> &str {
+        &self.key_id
+    }
+
+    pub fn public_key_hex(&self) -> String {
+        hex::encode(self.key.verifying_key().to_bytes())
+    }
+
+    /// Signature value for the `x-llmta-signature` header.
+    pub fn sign_digest(&self, digest: &[u8]) -> String {
+        let signature = self.key.sign(digest);
+        format!(
+            "keyid={},alg=ed25519,sig={}",
+            self.key_id,
+            hex::encode(signature.to_bytes())
+        )
+    }
+
+    pub fn sign_body(&self, body: &[u8]) -> String {
+        self.sign_digest(&Sha256::digest(body))
+    }
+}
@@ -0,0 +1,143 @@
+use hmac::{Hmac, Mac};
+use reqwest::header::{HeaderMap, HeaderValue, AUTHORIZATION};
+use sha2::{Digest, Sha256};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// AWS access key pair, plus a session token for temporary credentials.
+#[derive(Debug, Clone)]
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl Credentials {
+    /// `AWS_ACCESS_KEY_ID`, `AWS_SECRET_ACCESS_KEY` and `AWS_SESSION_TOKEN`.
+    pub fn from_env() -> Option<Self> {
+        Some(Credentials {
+            access_key_id: std::env::var("AWS_ACCESS_KEY_ID").ok()?,
+            secret_access_key: std::env::var("AWS_SECRET_ACCESS_KEY").ok()?,
+            session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+        })
+    }
+}
+
+fn hmac_sha256(key: &[u8], data: &str) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data.as_bytes());
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// Percent-encodes everything but RFC 3986 unreserved characters.
+pub fn uri_encode(text: &str, keep_slash: bool) -> String {
+    let mut encoded = String::with_capacity(text.len());
+    for byte in text.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' => encoded.push(byte as char),
+            b'/' if keep_slash => encoded.push('/'),
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// `20150830T123600Z` for a time since the epoch.
+fn amz_date(time: SystemTime) -> String {
+    let secs = time.duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0);
+    let days = (secs / 86_400) as i64;
+    let rem = secs % 86_400;
+    // Civil date from days since 1970-01-01 (Howard Hinnant's algorithm).
+    let z = days + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z - era * 146_097;
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!(
+        "{:04}{:02}{:02}T{:02}{:02}{:02}Z",
+        year,
+        month,
+        day,
+        rem / 3_600,
+        rem % 3_600 / 60,
+        rem % 60
+    )
+}
+
+/// Signature Version 4 request signing for one service in one region.
+#[derive(Debug, Clone)]
+pub struct Signer {
+    pub credentials: Credentials,
+    pub region: String,
+    pub service: String,
+}
+
+impl Signer {
+    /// Adds `authorization`, `x-amz-date` and, for session credentials,
+    /// `x-amz-security-token` headers. Signs `host`, the `x-amz-*` headers and
+    /// `content-type`; other headers may change in transit.
+    pub fn sign(&self, method: &str, url: &reqwest::Url, headers: &mut HeaderMap, body: &[u8], time: SystemTime) {
+        let credentials = &self.credentials;
+        let (region, service) = (self.region.as_str(), self.service.as_str());
+        let timestamp = amz_date(time);
+        let date = &timestamp[..8];
+        headers.insert("x-amz-date", HeaderValue::from_str(&timestamp).unwrap());
+        if let Some(token) = credentials.session_token.as_deref().and_then(|t| HeaderValue::from_str(t).ok()) {
+            headers.insert("x-amz-security-token", token);
+        }
+
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or(""), port),
+            None => url.host_str().unwrap_or("").to_string(),
+        };
+        let mut signed: Vec<(String, String)> = headers
+            .iter()
+            .filter(|(name, _)| name.as_str().starts_with("x-amz-") || name.as_str() == "content-type")
+            .filter_map(|(name, value)| Some((name.as_str().to_string(), value.to_str().ok()?.trim().to_string())))
+            .collect();
+        signed.push(("host".to_string(), host));
+        signed.sort();
+        let canonical_headers: String = signed.iter().map(|(n, v)| format!("{}:{}\n", n, v)).collect();
+        let signed_headers = signed.iter().map(|(n, _)| n.as_str()).collect::<Vec<_>>().join(";");
+
+        // The URL path is already percent-encoded once; AWS expects it encoded
+        // again for every service but S3.
+        let mut query: Vec<(String, String)> = url
+            .query_pairs()
+            .map(|(k, v)| (uri_encode(&k, false), uri_encode(&v, false)))
+            .collect();
+        query.sort();
+        let canonical_query = query.iter().map(|(k, v)| format!("{}={}", k, v)).collect::<Vec<_>>().join("&");
+        let canonical_request = format!(
+            "{}\n{}\n{}\n{}\n{}\n{}",
+            method,
+            uri_encode(url.path(), true),
+            canonical_query,
+            canonical_headers,
+            signed_headers,
+            hex::encode(Sha256::digest(body))
+        );
+
+        let scope = format!("{}/{}/{}/aws4_request", date, region, service);
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            timestamp,
+            scope,
+            hex::encode(Sha256::digest(canonical_request.as_bytes()))
+        );
+        let key = hmac_sha256(format!("AWS4{}", credentials.secret_access_key).as_bytes(), date);
+        let key = hmac_sha256(&key, region);
+        let key = hmac_sha256(&key, service);
+        let key = hmac_sha256(&key, "aws4_request");
+        let signature = hex::encode(hmac_sha256(&key, &string_to_sign));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_headers, signature
+        );
+        headers.insert(AUTHORIZATION, HeaderValue::from_str(&authorization).unwrap());
+    }
+}
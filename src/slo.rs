@@ -0,0 +1,149 @@
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+/// Upper bound on samples kept per objective, whatever the window.
+const MAX_SAMPLES: usize = 100_000;
+
+/// `[[slo]]` entry: a time-to-first-byte objective for a group of models.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SloConfig {
+    /// Model names; a trailing `*` matches by prefix (`"gpt-4o*"`).
+    pub models: Vec<String>,
+    /// Upstream response headers must arrive within this many milliseconds.
+    pub ttft_ms: u64,
+    /// Fraction of requests that must meet `ttft_ms`, e.g. `0.95`.
+    #[serde(default = "default_target")]
+    pub target: f64,
+    #[serde(default = "default_window_secs")]
+    pub window_secs: u64,
+    /// Requests in the window before the budget can be declared exhausted.
+    #[serde(default = "default_min_samples")]
+    pub min_samples: usize,
+    /// Model to send requests to while the error budget is exhausted.
+    #[serde(default)]
+    pub fallback_model: Option<String>,
+}
+
+fn default_target() -> f64 {
+    0.95
+}
+
+fn default_window_secs() -> u64 {
+    3600
+}
+
+fn default_min_samples() -> usize {
+    20
+}
+
+impl SloConfig {
+    fn matches(&self, model: &str) -> bool {
+        self.models.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => model.starts_with(prefix),
+            None => model == pattern,
+        })
+    }
+}
+
+struct Objective {
+    config: SloConfig,
+    /// `(when, met)` per request, oldest first.
+    samples: Mutex<VecDeque<(Instant, bool)>>,
+}
+
+impl Objective {
+    fn window(&self) -> Duration {
+        Duration::from_secs(self.config.window_secs)
+    }
+
+    /// `(total, met)` over the rolling window.
+    fn counts(&self) -> (usize, usize) {
+        let mut samples = self.samples.lock().unwrap();
+        let window = self.window();
+        while samples.front().is_some_and(|(at, _)| at.elapsed() > window) {
+            samples.pop_front();
+        }
+        (samples.len(), samples.iter().filter(|(_, met)| *met).count())
+    }
+
+    /// Share of the allowed misses still unspent; negative once overspent.
+    fn budget_remaining(&self, total: usize, met: usize) -> f64 {
+        let allowed = (1.0 - self.config.target) * total as f64;
+        if allowed <= 0.0 {
+            return if met == total { 1.0 } else { -1.0 };
+        }
+        1.0 - (total - met) as f64 / allowed
+    }
+
+    fn exhausted(&self) -> bool {
+        let (total, met) = self.counts();
+        total >= self.config.min_samples && self.budget_remaining(total, met) <= 0.0
+    }
+}
+
+/// Rolling compliance for each configured objective.
+pub struct SloTracker {
+    objectives: Vec<Objective>,
+}
+
+impl SloTracker {
+    pub fn new(configs: Vec<SloConfig>) -> Self {
+        SloTracker {
+            objectives: configs
+                .into_iter()
+                .map(|config| Objective { config, samples: Mutex::new(VecDeque::new()) })
+                .collect(),
+        }
+    }
+
+    fn objective(&self, model: &str) -> Option<&Objective> {
+        self.objectives.iter().find(|o| o.config.matches(model))
+    }
+
+    /// Records one request; failures count as misses whatever their latency.
+    pub fn record(&self, model: &str, ttft: Duration, success: bool) {
+        let Some(objective) = self.objective(model) else {
+            return;
+        };
+        let met = success && ttft <= Duration::from_millis(objective.config.ttft_ms);
+        let mut samples = objective.samples.lock().unwrap();
+        if samples.len() >= MAX_SAMPLES {
+            samples.pop_front();
+        }
+        samples.push_back((Instant::now(), met));
+    }
+
+    /// The model to use instead of `model` while its error budget is exhausted.
+    pub fn reroute(&self, model: &str) -> Option<String> {
+        let objective = self.objective(model)?;
+        let fallback = objective.config.fallback_model.as_ref()?;
+        (fallback != model && objective.exhausted()).then(|| fallback.clone())
+    }
+
+    pub fn snapshot(&self) -> Value {
+        let objectives: Vec<Value> = self
+            .objectives
+            .iter()
+            .map(|objective| {
+                let (total, met) = objective.counts();
+                let config = &objective.config;
+                json!({
+                    "models": config.models,
+                    "ttft_ms": config.ttft_ms,
+                    "target": config.target,
+                    "window_secs": config.window_secs,
+                    "requests": total,
+                    "met": met,
+                    "compliance": (total > 0).then(|| met as f64 / total as f64),
+                    "error_budget_remaining": (total > 0).then(|| objective.budget_remaining(total, met)),
+                    "exhausted": total >= config.min_samples && objective.budget_remaining(total, met) <= 0.0,
+                    "fallback_model": config.fallback_model,
+                })
+            })
+            .collect();
+        json!({ "objectives": objectives })
+    }
+}
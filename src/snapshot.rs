@@ -0,0 +1,213 @@
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::io::AsyncWriteExt;
+
+use crate::keys::KeyOverrides;
+use crate::maintenance::MaintenanceMode;
+use crate::AppState;
+
+const SNAPSHOT_VERSION: u32 = 1;
+
+/// Where runtime state is handed over between deployments.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct StateConfig {
+    /// JSON file written on shutdown and read on startup. It holds cached
+    /// answers, so it is created readable by its owner only.
+    #[serde(default)]
+    pub path: Option<String>,
+    /// Redis alternative to `path`. Requires the `redis` feature.
+    #[serde(default)]
+    pub redis_url: Option<String>,
+    #[serde(default = "default_redis_key")]
+    pub redis_key: String,
+}
+
+fn default_redis_key() -> String {
+    "llmta:state".to_string()
+}
+
+/// A counter and how long ago its window started.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct AgedCount {
+    pub key: String,
+    pub count: u64,
+    pub age_ms: u64,
+}
+
+/// What is left of a per-minute budget and how long ago it was drawn from.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct BudgetLevel {
+    pub bucket: String,
+    pub tokens: f64,
+    pub age_ms: u64,
+}
+
+/// A cached answer and how long it has left to live.
+#[derive(Debug, Serialize, Deserialize, Clone)]
+pub struct CachedAnswer {
+    pub key: String,
+    pub body: String,
+    pub ttl_ms: u64,
+}
+
+/// In-memory state worth carrying across a blue-green switch: runtime
+/// admin changes, rate limit windows, token budgets, replay-protection
+/// nonces and cached answers. Limits are keyed by a fingerprint of the
+/// client key, never the key. Circuit breakers are left out; the new
+/// instance probes its backends afresh.
+#[derive(Debug, Serialize, Deserialize, Clone, Default)]
+pub struct Snapshot {
+    pub version: u32,
+    /// Unix seconds.
+    pub exported_at: u64,
+    #[serde(default)]
+    pub key_overrides: HashMap<String, KeyOverrides>,
+    #[serde(default)]
+    pub maintenance: Option<MaintenanceMode>,
+    #[serde(default)]
+    pub drained: Vec<String>,
+    #[serde(default)]
+    pub rate_limits: Vec<AgedCount>,
+    #[serde(default)]
+    pub nonces: Vec<AgedCount>,
+    #[serde(default)]
+    pub budgets: Vec<BudgetLevel>,
+    #[serde(default)]
+    pub cache_generation: u64,
+    #[serde(default)]
+    pub cache: Vec<CachedAnswer>,
+}
+
+pub fn export(state: &AppState) -> Snapshot {
+    let (cache_generation, cache) = state.cache.export();
+    Snapshot {
+        version: SNAPSHOT_VERSION,
+        exported_at: SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_secs())
+            .unwrap_or(0),
+        key_overrides: state.keys.all(),
+        maintenance: state.maintenance.mode(),
+        drained: state.maintenance.drained(),
+        rate_limits: state.limiter.export(),
+        nonces: state.auth.export_nonces(),
+        budgets: state.budgets.export(),
+        cache_generation,
+        cache,
+    }
+}
+
+/// Merges a snapshot into the running state; configured keys are
+/// overwritten by their exported (possibly admin-edited) overrides.
+pub fn import(state: &AppState, snapshot: Snapshot) -> Result<(), String> {
+    if snapshot.version != SNAPSHOT_VERSION {
+        return Err(format!("unsupported snapshot version {}", snapshot.version));
+    }
+    for (name, overrides) in snapshot.key_overrides {
+        state.keys.set_overrides(&name, overrides);
+    }
+    if snapshot.maintenance.is_some() {
+        state.maintenance.set_mode(snapshot.maintenance);
+    }
+    for backend in &snapshot.drained {
+        state.maintenance.set_drained(backend, true);
+    }
+    state.limiter.import(&snapshot.rate_limits);
+    state.auth.import_nonces(&snapshot.nonces);
+    state.budgets.import(&snapshot.budgets);
+    state.cache.import(snapshot.cache_generation, &snapshot.cache);
+    Ok(())
+}
+
+/// Writes the current state to the configured store, if any.
+pub async fn save(state: &AppState) -> Result<(), String> {
+    let config = &state.config.state;
+    let json = serde_json::to_vec(&export(state)).map_err(|e| e.to_string())?;
+    if let Some(path) = &config.path {
+        write_private(path, &json).await.map_err(|e| format!("{}: {}", path, e))?;
+        println!("Saved runtime state to {}", path);
+    }
+    if let Some(url) = &config.redis_url {
+        redis_store::set(url, &config.redis_key, json).await?;
+        println!("Saved runtime state to {}", config.redis_key);
+    }
+    Ok(())
+}
+
+/// Writes `json` to a fresh owner-only file next to `path` and moves it
+/// into place, so readers never see a half-written snapshot.
+async fn write_private(path: &str, json: &[u8]) -> std::io::Result<()> {
+    let temp = format!("{}.tmp", path);
+    // Permissions only apply on creation, so never reuse a leftover.
+    match tokio::fs::remove_file(&temp).await {
+        Err(e) if e.kind() != std::io::ErrorKind::NotFound => return Err(e),
+        _ => {}
+    }
+    let mut options = tokio::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    options.mode(0o600);
+    let mut file = options.open(&temp).await?;
+    file.write_all(json).await?;
+    file.sync_all().await?;
+    drop(file);
+    tokio::fs::rename(&temp, path).await
+}
+
+/// Restores state saved by a previous instance. A missing snapshot is not
+/// an error: the first deployment has nothing to restore.
+pub async fn restore(state: &AppState) -> Result<(), String> {
+    let config = &state.config.state;
+    let json = if let Some(url) = &config.redis_url {
+        redis_store::get(url, &config.redis_key).await?
+    } else if let Some(path) = &config.path {
+        match tokio::fs::read(path).await {
+            Ok(json) => Some(json),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => None,
+            Err(e) => return Err(format!("{}: {}", path, e)),
+        }
+    } else {
+        None
+    };
+    let Some(json) = json else {
+        return Ok(());
+    };
+    let snapshot: Snapshot = serde_json::from_slice(&json).map_err(|e| e.to_string())?;
+    println!("Restoring runtime state exported at {}", snapshot.exported_at);
+    import(state, snapshot)
+}
+
+#[cfg(feature = "redis")]
+mod redis_store {
+    use redis::AsyncCommands;
+
+    async fn connect(url: &str) -> Result<redis::aio::MultiplexedConnection, String> {
+        redis::Client::open(url)
+            .map_err(|e| e.to_string())?
+            .get_multiplexed_async_connection()
+            .await
+            .map_err(|e| e.to_string())
+    }
+
+    pub async fn set(url: &str, key: &str, value: Vec<u8>) -> Result<(), String> {
+        connect(url).await?.set(key, value).await.map_err(|e| e.to_string())
+    }
+
+    pub async fn get(url: &str, key: &str) -> Result<Option<Vec<u8>>, String> {
+        connect(url).await?.get(key).await.map_err(|e| e.to_string())
+    }
+}
+
+#[cfg(not(feature = "redis"))]
+mod redis_store {
+    const UNAVAILABLE: &str = "state.redis_url needs the adapter built with the `redis` feature";
+
+    pub async fn set(_url: &str, _key: &str, _value: Vec<u8>) -> Result<(), String> {
+        Err(UNAVAILABLE.to_string())
+    }
+
+    pub async fn get(_url: &str, _key: &str) -> Result<Option<Vec<u8>>, String> {
+        Err(UNAVAILABLE.to_string())
+    }
+}
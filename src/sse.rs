@@ -0,0 +1,405 @@
+use axum::body::Bytes;
+use futures::{Stream, StreamExt};
+use std::collections::VecDeque;
+
+/// A single Server-Sent Event.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct SseEvent {
+    pub event: Option<String>,
+    pub data: String,
+    pub id: Option<String>,
+    pub retry: Option<u64>,
+}
+
+impl SseEvent {
+    pub fn data(data: impl Into<String>) -> Self {
+        SseEvent {
+            data: data.into(),
+            ..Default::default()
+        }
+    }
+
+    /// Also accepts the `DONE` and lowercase variants some backends send.
+    pub fn is_done(&self) -> bool {
+        let data = self.data.trim();
+        data.eq_ignore_ascii_case("[DONE]") || data.eq_ignore_ascii_case("DONE")
+    }
+
+    pub fn is_error(&self) -> bool {
+        serde_json::from_str::<serde_json::Value>(&self.data)
+            .map(|v| v.get("error").is_some())
+            .unwrap_or(false)
+    }
+
+    /// The `event:` type clients can switch on without parsing the payload.
+    pub fn kind(&self) -> &'static str {
+        if self.is_done() {
+            "done"
+        } else if self.is_error() {
+            "error"
+        } else {
+            "delta"
+        }
+    }
+
+    pub fn to_bytes(&self) -> Bytes {
+        let mut out = String::new();
+        if let Some(id) = &self.id {
+            out.push_str("id: ");
+            out.push_str(id);
+            out.push('\n');
+        }
+        if let Some(event) = &self.event {
+            out.push_str("event: ");
+            out.push_str(event);
+            out.push('\n');
+        }
+        if let Some(retry) = self.retry {
+            out.push_str(&format!("retry: {}\n", retry));
+        }
+        for line in self.data.split('\n') {
+            out.push_str("data: ");
+            out.push_str(line);
+            out.push('\n');
+        }
+        out.push('\n');
+        Bytes::from(out)
+    }
+}
+
+/// Incremental SSE parser that reassembles events split across network chunks.
+/// Lines without a field name that look like JSON or `[DONE]` are taken as
+/// data, for backends that leave out the `data:` prefix.
+#[derive(Debug, Default)]
+pub struct SseParser {
+    buffer: Vec<u8>,
+    current: SseEvent,
+    has_data: bool,
+    /// Reading unprefixed JSON that spans several lines.
+    bare: bool,
+}
+
+impl SseParser {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+
+        let mut events = Vec::new();
+        while let Some(pos) = self.buffer.iter().position(|&b| b == b'\n') {
+            let raw: Vec<u8> = self.buffer.drain(..=pos).collect();
+            let line = String::from_utf8_lossy(&raw[..raw.len() - 1]);
+            let line: &str = &line;
+            if let Some(event) = self.process_line(line.strip_suffix('\r').unwrap_or(line)) {
+                events.push(event);
+            }
+        }
+        events
+    }
+
+    /// Flushes whatever is left once the upstream closes the stream.
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        if !self.buffer.is_empty() {
+            let raw = std::mem::take(&mut self.buffer);
+            let line = String::from_utf8_lossy(&raw);
+            let line: &str = &line;
+            self.process_line(line.strip_suffix('\r').unwrap_or(line));
+        }
+        self.dispatch()
+    }
+
+    fn process_line(&mut self, line: &str) -> Option<SseEvent> {
+        if line.is_empty() {
+            return self.dispatch();
+        }
+        if line.starts_with(':') {
+            return None;
+        }
+        if self.bare || line.starts_with('{') || line.starts_with('[') {
+            return self.process_bare(line);
+        }
+
+        let (field, value) = match line.find(':') {
+            Some(i) => {
+                let value = &line[i + 1..];
+                (&line[..i], value.strip_prefix(' ').unwrap_or(value))
+            }
+            None => (line, ""),
+        };
+
+        match field {
+            "data" => {
+                if self.has_data {
+                    self.current.data.push('\n');
+                }
+                self.current.data.push_str(value);
+                self.has_data = true;
+            }
+            "event" => self.current.event = Some(value.to_string()),
+            "id" => self.current.id = Some(value.to_string()),
+            "retry" => self.current.retry = value.parse().ok(),
+            _ => {}
+        }
+        None
+    }
+
+    /// Unprefixed data ends its event once it is complete JSON, since such
+    /// backends do not always separate events with blank lines either.
+    fn process_bare(&mut self, line: &str) -> Option<SseEvent> {
+        if self.has_data {
+            self.current.data.push('\n');
+        }
+        self.current.data.push_str(line);
+        self.has_data = true;
+        self.bare = true;
+        if self.current.is_done() || serde_json::from_str::<serde::de::IgnoredAny>(&self.current.data).is_ok() {
+            return self.dispatch();
+        }
+        None
+    }
+
+    fn dispatch(&mut self) -> Option<SseEvent> {
+        self.bare = false;
+        let event = std::mem::take(&mut self.current);
+        if std::mem::replace(&mut self.has_data, false) {
+            Some(event)
+        } else {
+            None
+        }
+    }
+}
+
+/// How a backend frames a streamed response.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StreamFormat {
+    Sse,
+    /// One JSON array whose elements arrive over time, as Gemini sends them.
+    JsonArray,
+    /// AWS binary event-stream frames, as Bedrock sends them.
+    AwsEventStream,
+}
+
+/// Splits a streamed JSON array into its elements, each as a `data` event.
+#[derive(Debug, Default)]
+pub struct JsonArrayParser {
+    buffer: Vec<u8>,
+    /// Bytes of `buffer` already scanned.
+    scanned: usize,
+    /// Offset of the element being read, once its `{` was seen.
+    start: Option<usize>,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl JsonArrayParser {
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        let mut consumed = 0;
+        while self.scanned < self.buffer.len() {
+            let byte = self.buffer[self.scanned];
+            if self.in_string {
+                match byte {
+                    _ if self.escaped => self.escaped = false,
+                    b'\\' => self.escaped = true,
+                    b'"' => self.in_string = false,
+                    _ => {}
+                }
+            } else {
+                match byte {
+                    b'"' => self.in_string = true,
+                    b'{' | b'[' if self.start.is_some() => self.depth += 1,
+                    b'{' => {
+                        self.start = Some(self.scanned);
+                        self.depth = 1;
+                    }
+                    b'}' | b']' if self.start.is_some() => {
+                        self.depth -= 1;
+                        if self.depth == 0 {
+                            let start = self.start.take().unwrap();
+                            let element = String::from_utf8_lossy(&self.buffer[start..=self.scanned]);
+                            events.push(SseEvent::data(element));
+                            consumed = self.scanned + 1;
+                        }
+                    }
+                    // `[`, `,`, `]` and whitespace between elements.
+                    _ if self.start.is_none() => consumed = self.scanned + 1,
+                    _ => {}
+                }
+            }
+            self.scanned += 1;
+        }
+        self.buffer.drain(..consumed);
+        self.scanned -= consumed;
+        if let Some(start) = self.start.as_mut() {
+            *start -= consumed;
+        }
+        events
+    }
+}
+
+/// Decodes AWS event-stream frames. An event frame becomes an event named
+/// after its `:event-type` with the payload as data; an exception frame
+/// becomes an OpenAI-style error. CRCs are not checked, TLS already covers
+/// transport integrity.
+#[derive(Debug, Default)]
+pub struct EventStreamParser {
+    buffer: Vec<u8>,
+}
+
+impl EventStreamParser {
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        self.buffer.extend_from_slice(chunk);
+        let mut events = Vec::new();
+        let mut offset = 0;
+        // Prelude: total length, headers length, prelude CRC.
+        while self.buffer.len() - offset >= 12 {
+            let word = |at: usize| u32::from_be_bytes(self.buffer[at..at + 4].try_into().unwrap()) as usize;
+            let total = word(offset);
+            let headers_len = word(offset + 4);
+            if total < 16 + headers_len {
+                events.push(SseEvent::data(
+                    serde_json::json!({ "error": { "type": "upstream_error", "message": "malformed event-stream frame" } })
+                        .to_string(),
+                ));
+                self.buffer.clear();
+                return events;
+            }
+            if self.buffer.len() - offset < total {
+                break;
+            }
+            let frame = &self.buffer[offset..offset + total];
+            let headers = frame_headers(&frame[12..12 + headers_len]);
+            events.push(frame_event(&headers, &frame[12 + headers_len..total - 4]));
+            offset += total;
+        }
+        self.buffer.drain(..offset);
+        events
+    }
+}
+
+/// String-valued frame headers; other header types are skipped.
+fn frame_headers(mut bytes: &[u8]) -> Vec<(String, String)> {
+    let mut headers = Vec::new();
+    while let Some((&name_len, rest)) = bytes.split_first() {
+        let name_len = name_len as usize;
+        if rest.len() <= name_len {
+            break;
+        }
+        let name = String::from_utf8_lossy(&rest[..name_len]).into_owned();
+        let value_type = rest[name_len];
+        let rest = &rest[name_len + 1..];
+        let size = match value_type {
+            0 | 1 => 0,
+            2 => 1,
+            3 => 2,
+            4 => 4,
+            5 | 8 => 8,
+            9 => 16,
+            6 | 7 if rest.len() >= 2 => 2 + u16::from_be_bytes([rest[0], rest[1]]) as usize,
+            _ => break,
+        };
+        if rest.len() < size {
+            break;
+        }
+        if value_type == 7 {
+            headers.push((name, String::from_utf8_lossy(&rest[2..size]).into_owned()));
+        }
+        bytes = &rest[size..];
+    }
+    headers
+}
+
+fn frame_event(headers: &[(String, String)], payload: &[u8]) -> SseEvent {
+    let header = |name: &str| headers.iter().find(|(n, _)| n == name).map(|(_, v)| v.as_str());
+    if header(":message-type") == Some("event") {
+        return SseEvent {
+            event: header(":event-type").map(str::to_string),
+            data: String::from_utf8_lossy(payload).into_owned(),
+            ..Default::default()
+        };
+    }
+    let error_type = header(":exception-type")
+        .or_else(|| header(":error-code"))
+        .unwrap_or("upstream_error");
+    let message = serde_json::from_slice::<serde_json::Value>(payload)
+        .ok()
+        .and_then(|v| v["message"].as_str().map(str::to_string))
+        .or_else(|| header(":error-message").map(str::to_string))
+        .unwrap_or_else(|| String::from_utf8_lossy(payload).into_owned());
+    SseEvent::data(serde_json::json!({ "error": { "type": error_type, "message": message } }).to_string())
+}
+
+/// The parser for a backend's stream framing.
+#[derive(Debug)]
+pub enum EventParser {
+    Sse(SseParser),
+    JsonArray(JsonArrayParser),
+    AwsEventStream(EventStreamParser),
+}
+
+impl EventParser {
+    pub fn new(format: StreamFormat) -> Self {
+        match format {
+            StreamFormat::Sse => EventParser::Sse(SseParser::new()),
+            StreamFormat::JsonArray => EventParser::JsonArray(JsonArrayParser::default()),
+            StreamFormat::AwsEventStream => EventParser::AwsEventStream(EventStreamParser::default()),
+        }
+    }
+
+    pub fn feed(&mut self, chunk: &[u8]) -> Vec<SseEvent> {
+        match self {
+            EventParser::Sse(parser) => parser.feed(chunk),
+            EventParser::JsonArray(parser) => parser.feed(chunk),
+            EventParser::AwsEventStream(parser) => parser.feed(chunk),
+        }
+    }
+
+    /// Flushes a trailing SSE event; an unfinished array element or frame
+    /// is dropped.
+    pub fn finish(&mut self) -> Option<SseEvent> {
+        match self {
+            EventParser::Sse(parser) => parser.finish(),
+            EventParser::JsonArray(_) | EventParser::AwsEventStream(_) => None,
+        }
+    }
+}
+
+/// Decodes a byte stream framed as `format` into events, for instance a
+/// `reqwest` `bytes_stream()`. A read error is passed on and ends the stream.
+pub fn decode<S, B, E>(format: StreamFormat, upstream: S) -> impl Stream<Item = Result<SseEvent, E>>
+where
+    S: Stream<Item = Result<B, E>>,
+    B: AsRef<[u8]>,
+{
+    let state = (Box::pin(upstream), EventParser::new(format), VecDeque::new(), false);
+    futures::stream::unfold(state, |(mut upstream, mut parser, mut pending, mut ended)| async move {
+        loop {
+            if let Some(event) = pending.pop_front() {
+                return Some((Ok(event), (upstream, parser, pending, ended)));
+            }
+            if ended {
+                return None;
+            }
+            match upstream.next().await {
+                Some(Ok(chunk)) => pending.extend(parser.feed(chunk.as_ref())),
+                Some(Err(e)) => return Some((Err(e), (upstream, parser, pending, true))),
+                None => {
+                    pending.extend(parser.finish());
+                    ended = true;
+                }
+            }
+        }
+    })
+}
+
+/// Serializes events back into SSE, the inverse of `decode` for `Sse`.
+pub fn encode<S>(events: S) -> impl Stream<Item = Bytes>
+where
+    S: Stream<Item = SseEvent>,
+{
+    events.map(|event| event.to_bytes())
+}
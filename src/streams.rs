@@ -0,0 +1,202 @@
+use serde::Serialize;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::Notify;
+
+/// Bookkeeping for one in-flight streaming response.
+pub struct StreamHandle {
+    pub request_id: String,
+    pub key: String,
+    pub model: String,
+    pub backend: String,
+    pub started: Instant,
+    pub bytes_sent: AtomicU64,
+    /// Milliseconds after `started` that bytes were last sent.
+    last_sent_ms: AtomicU64,
+    cancel: Notify,
+}
+
+impl StreamHandle {
+    /// Resolves once an operator asked for this stream to be terminated.
+    pub async fn cancelled(&self) {
+        self.cancel.notified().await
+    }
+
+    pub fn record_sent(&self, bytes: usize) {
+        self.bytes_sent.fetch_add(bytes as u64, Ordering::Relaxed);
+        self.last_sent_ms
+            .store(self.started.elapsed().as_millis() as u64, Ordering::Relaxed);
+    }
+
+    /// How long since the stream last sent anything.
+    pub fn idle(&self) -> Duration {
+        self.started
+            .elapsed()
+            .saturating_sub(Duration::from_millis(self.last_sent_ms.load(Ordering::Relaxed)))
+    }
+}
+
+#[derive(Debug, Serialize)]
+pub struct StreamInfo {
+    pub request_id: String,
+    pub key: String,
+    pub model: String,
+    pub backend: String,
+    pub age_ms: u128,
+    pub bytes_sent: u64,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StreamStats {
+    pub active: usize,
+    pub completed: u64,
+    pub terminated: u64,
+    pub bytes_sent: u64,
+    pub streams: Vec<StreamInfo>,
+}
+
+/// All streams currently being served, for introspection and forced shutdown.
+#[derive(Default)]
+pub struct StreamRegistry {
+    streams: Mutex<HashMap<String, Arc<StreamHandle>>>,
+    completed: AtomicU64,
+    terminated: AtomicU64,
+    reaped: AtomicU64,
+    bytes_sent: AtomicU64,
+}
+
+impl StreamRegistry {
+    pub fn register(
+        self: &Arc<Self>,
+        request_id: &str,
+        key: &str,
+        model: &str,
+        backend: &str,
+    ) -> StreamGuard {
+        let handle = Arc::new(StreamHandle {
+            request_id: request_id.to_string(),
+            key: key.to_string(),
+            model: model.to_string(),
+            backend: backend.to_string(),
+            started: Instant::now(),
+            bytes_sent: AtomicU64::new(0),
+            last_sent_ms: AtomicU64::new(0),
+            cancel: Notify::new(),
+        });
+        self.streams
+            .lock()
+            .unwrap()
+            .insert(request_id.to_string(), handle.clone());
+        StreamGuard {
+            registry: self.clone(),
+            handle,
+        }
+    }
+
+    pub fn stats(&self) -> StreamStats {
+        let mut streams: Vec<StreamInfo> = self
+            .streams
+            .lock()
+            .unwrap()
+            .values()
+            .map(|h| StreamInfo {
+                request_id: h.request_id.clone(),
+                key: h.key.clone(),
+                model: h.model.clone(),
+                backend: h.backend.clone(),
+                age_ms: h.started.elapsed().as_millis(),
+                bytes_sent: h.bytes_sent.load(Ordering::Relaxed),
+            })
+            .collect();
+        streams.sort_by_key(|s| std::cmp::Reverse(s.age_ms));
+
+        StreamStats {
+            active: streams.len(),
+            completed: self.completed.load(Ordering::Relaxed),
+            terminated: self.terminated.load(Ordering::Relaxed),
+            bytes_sent: self.bytes_sent.load(Ordering::Relaxed)
+                + streams.iter().map(|s| s.bytes_sent).sum::<u64>(),
+            streams,
+        }
+    }
+
+    pub fn active(&self) -> usize {
+        self.streams.lock().unwrap().len()
+    }
+
+    pub fn active_for_backend(&self, backend: &str) -> usize {
+        self.streams
+            .lock()
+            .unwrap()
+            .values()
+            .filter(|h| h.backend == backend)
+            .count()
+    }
+
+    /// Asks the stream to close; returns `false` if no such stream is active.
+    pub fn terminate(&self, request_id: &str) -> bool {
+        match self.streams.lock().unwrap().get(request_id) {
+            Some(handle) => {
+                handle.cancel.notify_one();
+                self.terminated.fetch_add(1, Ordering::Relaxed);
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Asks every active stream to close; returns how many there were.
+    pub fn terminate_all(&self) -> usize {
+        let streams = self.streams.lock().unwrap();
+        for handle in streams.values() {
+            handle.cancel.notify_one();
+        }
+        self.terminated.fetch_add(streams.len() as u64, Ordering::Relaxed);
+        streams.len()
+    }
+
+    /// Terminates streams idle for longer than `max_idle`; returns how many.
+    pub fn reap_idle(&self, max_idle: Duration) -> usize {
+        let streams = self.streams.lock().unwrap();
+        let mut reaped = 0;
+        for handle in streams.values().filter(|h| h.idle() > max_idle) {
+            println!("Reaping stream {} idle for {:?}", handle.request_id, handle.idle());
+            handle.cancel.notify_one();
+            reaped += 1;
+        }
+        self.reaped.fetch_add(reaped as u64, Ordering::Relaxed);
+        reaped
+    }
+
+    pub fn reaped(&self) -> u64 {
+        self.reaped.load(Ordering::Relaxed)
+    }
+}
+
+/// Keeps a stream registered until the response body is finished or dropped.
+pub struct StreamGuard {
+    registry: Arc<StreamRegistry>,
+    handle: Arc<StreamHandle>,
+}
+
+impl StreamGuard {
+    pub fn handle(&self) -> Arc<StreamHandle> {
+        self.handle.clone()
+    }
+}
+
+impl Drop for StreamGuard {
+    fn drop(&mut self) {
+        self.registry
+            .streams
+            .lock()
+            .unwrap()
+            .remove(&self.handle.request_id);
+        self.registry.completed.fetch_add(1, Ordering::Relaxed);
+        self.registry
+            .bytes_sent
+            .fetch_add(self.handle.bytes_sent.load(Ordering::Relaxed), Ordering::Relaxed);
+    }
+}
@@ -0,0 +1,116 @@
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+use crate::policy;
+
+/// How a configured system prompt meets the client's own system messages.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum InjectMode {
+    /// A system message of its own ahead of all others.
+    #[default]
+    Prepend,
+    /// Prefixed to the client's first system message, or prepended when
+    /// there is none.
+    Merge,
+    /// Replaces the client's system and developer messages.
+    Replace,
+}
+
+/// `[[system_prompts]]` entry: the system prompt chat requests it matches
+/// are sent with, so clients need not send it themselves.
+#[derive(Debug, Deserialize, Clone)]
+pub struct SystemPrompt {
+    /// Request paths; a trailing `*` matches by prefix. Empty matches every
+    /// chat route.
+    #[serde(default)]
+    pub routes: Vec<String>,
+    /// Requested models; a trailing `*` matches by prefix. Empty matches
+    /// every model.
+    #[serde(default)]
+    pub models: Vec<String>,
+    /// The prompt, where `{{name}}` stands for a variable and
+    /// `{{name | text}}` falls back to `text` when it is not set.
+    pub template: String,
+    #[serde(default)]
+    pub mode: InjectMode,
+    /// Defaults for variables, overridden by the request's `x_prompt_vars`.
+    #[serde(default)]
+    pub vars: HashMap<String, String>,
+}
+
+impl SystemPrompt {
+    pub fn find<'a>(prompts: &'a [SystemPrompt], route: &str, model: &str) -> Option<&'a SystemPrompt> {
+        prompts
+            .iter()
+            .find(|prompt| policy::matches(&prompt.routes, route) && policy::matches(&prompt.models, model))
+    }
+
+    /// Renders the prompt for this request and puts it into `messages`,
+    /// removing `x_prompt_vars`. `model` and `route` are always available
+    /// as variables.
+    pub fn apply(&self, payload: &mut Map<String, Value>, route: &str, model: &str) -> Result<(), String> {
+        let mut vars = self.vars.clone();
+        vars.insert("model".to_string(), model.to_string());
+        vars.insert("route".to_string(), route.to_string());
+        match payload.remove("x_prompt_vars") {
+            None => {}
+            Some(Value::Object(given)) => {
+                for (name, value) in given {
+                    let text = match value {
+                        Value::String(text) => text,
+                        other => other.to_string(),
+                    };
+                    vars.insert(name, text);
+                }
+            }
+            Some(_) => return Err("x_prompt_vars must be an object".to_string()),
+        }
+        let prompt = render(&self.template, &vars)?;
+
+        let Some(Value::Array(messages)) = payload.get_mut("messages") else {
+            return Ok(());
+        };
+        let is_system = |m: &Value| m["role"] == "system" || m["role"] == "developer";
+        match self.mode {
+            InjectMode::Prepend => {}
+            InjectMode::Merge => {
+                let first = messages.iter_mut().find(|m| m["role"] == "system");
+                if let Some(Value::String(content)) = first.and_then(|m| m.get_mut("content")) {
+                    *content = format!("{}\n\n{}", prompt, content);
+                    return Ok(());
+                }
+            }
+            InjectMode::Replace => messages.retain(|m| !is_system(m)),
+        }
+        messages.insert(0, json!({ "role": "system", "content": prompt }));
+        Ok(())
+    }
+}
+
+/// Substitutes `{{name}}` and `{{name | default}}` placeholders, with
+/// whitespace inside the braces ignored. A variable that is neither set nor
+/// given a default is an error, so a prompt is never sent half-filled.
+pub fn render(template: &str, vars: &HashMap<String, String>) -> Result<String, String> {
+    let mut rendered = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        let Some(end) = rest[start..].find("}}") else {
+            break;
+        };
+        rendered.push_str(&rest[..start]);
+        let placeholder = &rest[start + 2..start + end];
+        let (name, default) = match placeholder.split_once('|') {
+            Some((name, default)) => (name.trim(), Some(default.trim())),
+            None => (placeholder.trim(), None),
+        };
+        match vars.get(name).map(String::as_str).or(default) {
+            Some(value) => rendered.push_str(value),
+            None => return Err(format!("The system prompt needs the variable {}; set it in x_prompt_vars", name)),
+        }
+        rest = &rest[start + end + 2..];
+    }
+    rendered.push_str(rest);
+    Ok(rendered)
+}
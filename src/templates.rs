@@ -0,0 +1,264 @@
+use rand::Rng;
+use rusqlite::{params, OptionalExtension};
+use serde::Serialize;
+use serde_json::{json, Map, Value};
+use std::sync::Arc;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::db::Database;
+
+#[derive(Debug, Serialize, Clone, PartialEq)]
+pub struct TemplateVersion {
+    pub name: String,
+    pub version: i64,
+    pub content: String,
+    /// Unix seconds.
+    pub created_at: i64,
+}
+
+/// Versioned system prompt templates. Publishing adds a version and makes it
+/// active; rolling back re-activates an earlier one. Versions are never deleted.
+pub struct TemplateStore {
+    db: Arc<Database>,
+}
+
+fn now() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() as i64)
+        .unwrap_or(0)
+}
+
+impl TemplateStore {
+    pub fn new(db: Arc<Database>) -> Self {
+        TemplateStore { db }
+    }
+
+    pub fn publish(&self, name: &str, content: &str) -> Result<i64, String> {
+        let mut conn = self.db.conn();
+        let tx = conn.transaction().map_err(|e| e.to_string())?;
+        let version: i64 = tx
+            .query_row(
+                "SELECT COALESCE(MAX(version), 0) + 1 FROM template_versions WHERE name = ?1",
+                params![name],
+                |row| row.get(0),
+            )
+            .map_err(|e| e.to_string())?;
+        tx.execute(
+            "INSERT INTO template_versions (name, version, content, created_at) VALUES (?1, ?2, ?3, ?4)",
+            params![name, version, content, now()],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.execute(
+            "INSERT INTO templates (name, active_version) VALUES (?1, ?2)
+             ON CONFLICT (name) DO UPDATE SET active_version = excluded.active_version",
+            params![name, version],
+        )
+        .map_err(|e| e.to_string())?;
+        tx.commit().map_err(|e| e.to_string())?;
+        Ok(version)
+    }
+
+    /// Makes `version` active again; false if it does not exist.
+    pub fn rollback(&self, name: &str, version: i64) -> Result<bool, String> {
+        let conn = self.db.conn();
+        conn.execute(
+            "UPDATE templates SET active_version = ?2 WHERE name = ?1
+             AND EXISTS (SELECT 1 FROM template_versions WHERE name = ?1 AND version = ?2)",
+            params![name, version],
+        )
+        .map(|updated| updated > 0)
+        .map_err(|e| e.to_string())
+    }
+
+    /// A specific version, or the active one when `version` is `None`.
+    pub fn get(&self, name: &str, version: Option<i64>) -> Result<Option<TemplateVersion>, String> {
+        let conn = self.db.conn();
+        conn.query_row(
+            "SELECT v.name, v.version, v.content, v.created_at FROM template_versions v
+             JOIN templates t ON t.name = v.name
+             WHERE v.name = ?1 AND v.version = COALESCE(?2, t.active_version)",
+            params![name, version],
+            |row| {
+                Ok(TemplateVersion {
+                    name: row.get(0)?,
+                    version: row.get(1)?,
+                    content: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            },
+        )
+        .optional()
+        .map_err(|e| e.to_string())
+    }
+
+    /// Every template with its active version.
+    pub fn list(&self) -> Result<Vec<Value>, String> {
+        let conn = self.db.conn();
+        let mut statement = conn
+            .prepare(
+                "SELECT t.name, t.active_version, COUNT(v.version) FROM templates t
+                 JOIN template_versions v ON v.name = t.name
+                 GROUP BY t.name ORDER BY t.name",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok(json!({
+                    "name": row.get::<_, String>(0)?,
+                    "active_version": row.get::<_, i64>(1)?,
+                    "versions": row.get::<_, i64>(2)?,
+                }))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+
+    /// Versions of `name`, newest first.
+    pub fn history(&self, name: &str) -> Result<Vec<TemplateVersion>, String> {
+        let conn = self.db.conn();
+        let mut statement = conn
+            .prepare(
+                "SELECT name, version, content, created_at FROM template_versions
+                 WHERE name = ?1 ORDER BY version DESC",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params![name], |row| {
+                Ok(TemplateVersion {
+                    name: row.get(0)?,
+                    version: row.get(1)?,
+                    content: row.get(2)?,
+                    created_at: row.get(3)?,
+                })
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+
+    /// Sends `percent` of the requests for `name` that do not pin a version to
+    /// `candidate` instead of the active version.
+    pub fn set_experiment(&self, name: &str, candidate: i64, percent: f64) -> Result<bool, String> {
+        if self.get(name, Some(candidate))?.is_none() {
+            return Ok(false);
+        }
+        self.db
+            .conn()
+            .execute(
+                "INSERT INTO template_experiments (name, candidate_version, percent) VALUES (?1, ?2, ?3)
+                 ON CONFLICT (name) DO UPDATE SET
+                     candidate_version = excluded.candidate_version, percent = excluded.percent",
+                params![name, candidate, percent.clamp(0.0, 100.0)],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(true)
+    }
+
+    pub fn clear_experiment(&self, name: &str) -> Result<bool, String> {
+        self.db
+            .conn()
+            .execute("DELETE FROM template_experiments WHERE name = ?1", params![name])
+            .map(|deleted| deleted > 0)
+            .map_err(|e| e.to_string())
+    }
+
+    /// `(candidate_version, percent)` of the running experiment, if any.
+    pub fn experiment(&self, name: &str) -> Result<Option<(i64, f64)>, String> {
+        self.db
+            .conn()
+            .query_row(
+                "SELECT candidate_version, percent FROM template_experiments WHERE name = ?1",
+                params![name],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .optional()
+            .map_err(|e| e.to_string())
+    }
+
+    /// The candidate version when this request falls into the experiment.
+    fn experiment_pick(&self, name: &str) -> Result<Option<i64>, String> {
+        Ok(self
+            .experiment(name)?
+            .filter(|(_, percent)| rand::thread_rng().gen_range(0.0..100.0) < *percent)
+            .map(|(candidate, _)| candidate))
+    }
+
+    /// Requests and feedback per version of `name`, for comparing versions.
+    pub fn evaluation(&self, name: &str) -> Result<Vec<Value>, String> {
+        let conn = self.db.conn();
+        let mut statement = conn
+            .prepare(
+                "SELECT r.template_version, COUNT(DISTINCT r.id), COUNT(f.score), AVG(f.score),
+                        SUM(f.score >= 0.5), SUM(f.score < 0.5)
+                 FROM requests r LEFT JOIN feedback f ON f.request = r.id
+                 WHERE r.template = ?1
+                 GROUP BY r.template_version ORDER BY r.template_version",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map(params![name], |row| {
+                Ok(json!({
+                    "version": row.get::<_, i64>(0)?,
+                    "requests": row.get::<_, i64>(1)?,
+                    "feedback": row.get::<_, i64>(2)?,
+                    "mean_score": row.get::<_, Option<f64>>(3)?,
+                    "positive": row.get::<_, Option<i64>>(4)?.unwrap_or(0),
+                    "negative": row.get::<_, Option<i64>>(5)?.unwrap_or(0),
+                }))
+            })
+            .map_err(|e| e.to_string())?;
+        rows.collect::<Result<_, _>>().map_err(|e| e.to_string())
+    }
+
+    /// Replaces `x_template` (`"name"` or `"name@version"`) and
+    /// `x_template_vars` with a leading system message holding the rendered
+    /// template. Returns the version used.
+    pub fn expand(&self, payload: &mut Map<String, Value>) -> Result<Option<TemplateVersion>, String> {
+        let vars = payload.remove("x_template_vars");
+        let Some(reference) = payload.remove("x_template") else {
+            return Ok(None);
+        };
+        let reference = reference
+            .as_str()
+            .ok_or_else(|| "x_template must be a string".to_string())?;
+        let (name, version) = match reference.split_once('@') {
+            Some((name, version)) => {
+                let version = version
+                    .parse()
+                    .map_err(|_| format!("Invalid template version in {}", reference))?;
+                (name, Some(version))
+            }
+            None => (reference, None),
+        };
+        let version = match version {
+            Some(version) => Some(version),
+            None => self.experiment_pick(name)?,
+        };
+        let template = self
+            .get(name, version)?
+            .ok_or_else(|| format!("No template {}", reference))?;
+        let vars = match vars {
+            None => Map::new(),
+            Some(Value::Object(vars)) => vars,
+            Some(_) => return Err("x_template_vars must be an object".to_string()),
+        };
+        let content = render(&template.content, &vars);
+        if let Some(Value::Array(messages)) = payload.get_mut("messages") {
+            messages.insert(0, json!({ "role": "system", "content": content }));
+        }
+        Ok(Some(template))
+    }
+}
+
+/// Substitutes `{{name}}` placeholders; unknown placeholders are left as is.
+pub fn render(template: &str, vars: &Map<String, Value>) -> String {
+    let mut rendered = template.to_string();
+    for (name, value) in vars {
+        let text = match value {
+            Value::String(text) => text.clone(),
+            other => other.to_string(),
+        };
+        rendered = rendered.replace(&format!("{{{{{}}}}}", name), &text);
+    }
+    rendered
+}
@@ -0,0 +1,202 @@
+use reqwest::Client;
+use serde::Deserialize;
+
+use crate::runtime::PoolConfig;
+
+#[cfg(not(any(feature = "native-tls", feature = "native-tls-system", feature = "rustls-ring")))]
+compile_error!("enable a TLS backend feature: `native-tls`, `native-tls-system` or `rustls-ring`");
+
+/// Security policy for outbound connections to a backend.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct TlsConfig {
+    /// `"1.2"` or `"1.3"`; the TLS library default when unset. The native
+    /// stacks cannot require 1.3, so it needs the `rustls-ring` feature.
+    #[serde(default)]
+    pub min_version: Option<String>,
+    /// Allowed cipher suites by IANA name (`TLS13_AES_256_GCM_SHA384`).
+    /// Requires the `rustls-ring` feature, as do `pins`.
+    #[serde(default)]
+    pub ciphers: Vec<String>,
+    /// SPKI pins as `sha256/<base64>`, as printed by
+    /// `openssl x509 -pubkey | openssl pkey -pubin -outform der | openssl dgst -sha256 -binary | base64`.
+    /// A connection is accepted when any certificate in the chain matches.
+    /// Pins only hold for the backend whose own table sets them: top-level
+    /// ones for `model_url`, never for `[[backends]]` inheriting `[tls]`.
+    #[serde(default)]
+    pub pins: Vec<String>,
+}
+
+impl TlsConfig {
+    /// The policy without its pins, for hosts other than the one pinned.
+    pub fn without_pins(&self) -> TlsConfig {
+        TlsConfig {
+            pins: Vec::new(),
+            ..self.clone()
+        }
+    }
+}
+
+/// Builds the HTTP client for a backend. Pinning, cipher selection and, on
+/// a native stack, a TLS 1.3 floor need a custom rustls configuration;
+/// otherwise the default TLS stack is used.
+pub fn build_client(config: &TlsConfig, pool: &PoolConfig) -> Result<Client, String> {
+    let min_version = match config.min_version.as_deref() {
+        None => None,
+        Some("1.2") => Some(reqwest::tls::Version::TLS_1_2),
+        Some("1.3") => Some(reqwest::tls::Version::TLS_1_3),
+        Some(other) => return Err(format!("unsupported tls.min_version {:?}, use \"1.2\" or \"1.3\"", other)),
+    };
+
+    let tls13_only = min_version == Some(reqwest::tls::Version::TLS_1_3);
+    let native = cfg!(any(feature = "native-tls", feature = "native-tls-system"));
+
+    let mut builder = pool.apply(Client::builder());
+    if config.pins.is_empty() && config.ciphers.is_empty() && !(tls13_only && native) {
+        if let Some(version) = min_version {
+            builder = builder.min_tls_version(version);
+        }
+    } else {
+        builder = with_rustls(builder, config, min_version)?;
+    }
+    builder.build().map_err(|e| e.to_string())
+}
+
+#[cfg(not(feature = "rustls-ring"))]
+fn with_rustls(
+    _builder: reqwest::ClientBuilder,
+    _config: &TlsConfig,
+    _min_version: Option<reqwest::tls::Version>,
+) -> Result<reqwest::ClientBuilder, String> {
+    Err("tls.pins, tls.ciphers and tls.min_version \"1.3\" need the adapter built with the `rustls-ring` feature"
+        .to_string())
+}
+
+#[cfg(feature = "rustls-ring")]
+fn with_rustls(
+    builder: reqwest::ClientBuilder,
+    config: &TlsConfig,
+    min_version: Option<reqwest::tls::Version>,
+) -> Result<reqwest::ClientBuilder, String> {
+    let tls13_only = min_version == Some(reqwest::tls::Version::TLS_1_3);
+    Ok(builder.use_preconfigured_tls(pinning::rustls_config(config, tls13_only)?))
+}
+
+/// Whether any of the DER certificates in `chain` has its public key
+/// among `pins`.
+#[cfg(feature = "rustls-ring")]
+pub fn matches_pins(chain: &[&[u8]], pins: &[String]) -> Result<bool, String> {
+    let pins = pins.iter().map(|pin| pinning::parse_pin(pin)).collect::<Result<Vec<_>, _>>()?;
+    Ok(pinning::matches(chain.iter().copied(), &pins))
+}
+
+#[cfg(feature = "rustls-ring")]
+mod pinning {
+    use base64::Engine;
+    use sha2::{Digest, Sha256};
+    use std::sync::Arc;
+    use std::time::SystemTime;
+
+    use super::TlsConfig;
+
+    pub fn rustls_config(config: &TlsConfig, tls13_only: bool) -> Result<rustls::ClientConfig, String> {
+        let suites: Vec<rustls::SupportedCipherSuite> = if config.ciphers.is_empty() {
+            rustls::DEFAULT_CIPHER_SUITES.to_vec()
+        } else {
+            let mut suites = Vec::new();
+            for name in &config.ciphers {
+                let suite = rustls::ALL_CIPHER_SUITES
+                    .iter()
+                    .find(|s| format!("{:?}", s.suite()) == *name)
+                    .ok_or_else(|| format!("unknown cipher suite {}", name))?;
+                suites.push(*suite);
+            }
+            suites
+        };
+        let versions: &[&rustls::SupportedProtocolVersion] = if tls13_only {
+            &[&rustls::version::TLS13]
+        } else {
+            rustls::DEFAULT_VERSIONS
+        };
+
+        let mut roots = rustls::RootCertStore::empty();
+        roots.add_trust_anchors(webpki_roots::TLS_SERVER_ROOTS.iter().map(|ta| {
+            rustls::OwnedTrustAnchor::from_subject_spki_name_constraints(ta.subject, ta.spki, ta.name_constraints)
+        }));
+        let pins = config
+            .pins
+            .iter()
+            .map(|pin| parse_pin(pin))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let builder = rustls::ClientConfig::builder()
+            .with_cipher_suites(&suites)
+            .with_safe_default_kx_groups()
+            .with_protocol_versions(versions)
+            .map_err(|e| e.to_string())?;
+        let config = if pins.is_empty() {
+            builder.with_root_certificates(roots).with_no_client_auth()
+        } else {
+            builder
+                .with_custom_certificate_verifier(Arc::new(PinnedVerifier {
+                    inner: rustls::client::WebPkiVerifier::new(roots, None),
+                    pins,
+                }))
+                .with_no_client_auth()
+        };
+        Ok(config)
+    }
+
+    pub fn parse_pin(pin: &str) -> Result<[u8; 32], String> {
+        let encoded = pin
+            .strip_prefix("sha256/")
+            .ok_or_else(|| format!("pin {} must start with sha256/", pin))?;
+        base64::engine::general_purpose::STANDARD
+            .decode(encoded)
+            .ok()
+            .and_then(|digest| <[u8; 32]>::try_from(digest).ok())
+            .ok_or_else(|| format!("pin {} is not a base64 SHA-256 digest", pin))
+    }
+
+    /// SHA-256 of a certificate's DER-encoded SubjectPublicKeyInfo.
+    fn spki_sha256(der: &[u8]) -> Option<[u8; 32]> {
+        let (_, cert) = x509_parser::parse_x509_certificate(der).ok()?;
+        Some(Sha256::digest(cert.public_key().raw).into())
+    }
+
+    pub fn matches<'a>(chain: impl Iterator<Item = &'a [u8]>, pins: &[[u8; 32]]) -> bool {
+        chain.filter_map(spki_sha256).any(|digest| pins.contains(&digest))
+    }
+
+    /// Normal WebPKI validation plus a match against at least one SPKI pin.
+    struct PinnedVerifier {
+        inner: rustls::client::WebPkiVerifier,
+        pins: Vec<[u8; 32]>,
+    }
+
+    impl rustls::client::ServerCertVerifier for PinnedVerifier {
+        fn verify_server_cert(
+            &self,
+            end_entity: &rustls::Certificate,
+            intermediates: &[rustls::Certificate],
+            server_name: &rustls::ServerName,
+            scts: &mut dyn Iterator<Item = &[u8]>,
+            ocsp_response: &[u8],
+            now: SystemTime,
+        ) -> Result<rustls::client::ServerCertVerified, rustls::Error> {
+            let verified = self.inner.verify_server_cert(
+                end_entity,
+                intermediates,
+                server_name,
+                scts,
+                ocsp_response,
+                now,
+            )?;
+            let chain = std::iter::once(end_entity).chain(intermediates).map(|cert| cert.0.as_slice());
+            if matches(chain, &self.pins) {
+                Ok(verified)
+            } else {
+                Err(rustls::Error::General("no certificate matches the configured SPKI pins".to_string()))
+            }
+        }
+    }
+}
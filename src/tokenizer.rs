@@ -0,0 +1,213 @@
+use serde::Deserialize;
+use serde_json::Value;
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+
+use crate::translation::content_text;
+
+/// Counts and truncates text the way a model family does.
+pub trait Tokenizer: Send + Sync {
+    fn name(&self) -> String;
+    fn count(&self, text: &str) -> usize;
+    /// Cuts `text` down to at most `max_tokens` tokens.
+    fn truncate(&self, text: &str, max_tokens: usize) -> String;
+}
+
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "lowercase")]
+pub enum TokenizerKind {
+    /// Roughly four characters per token; always available.
+    #[default]
+    Approximate,
+    /// OpenAI BPE encodings. Requires the `tiktoken` feature.
+    Tiktoken,
+    /// `tokenizer.json` files (SentencePiece/BPE for Llama, Qwen, ...).
+    /// Requires the `hf-tokenizers` feature.
+    HuggingFace,
+}
+
+/// `[[tokenizers]]` entry mapping model aliases to a tokenizer.
+#[derive(Debug, Deserialize, Clone)]
+pub struct TokenizerConfig {
+    /// Model names; a trailing `*` matches by prefix (`"gpt-4o*"`).
+    pub models: Vec<String>,
+    #[serde(default)]
+    pub kind: TokenizerKind,
+    /// tiktoken encoding: `cl100k_base`, `o200k_base`, `p50k_base`, `r50k_base`.
+    #[serde(default)]
+    pub encoding: Option<String>,
+    /// Local `tokenizer.json`.
+    #[serde(default)]
+    pub file: Option<String>,
+    /// Hugging Face hub repository, downloaded on first use.
+    #[serde(default)]
+    pub hub: Option<String>,
+}
+
+impl TokenizerConfig {
+    fn matches(&self, model: &str) -> bool {
+        self.models.iter().any(|pattern| match pattern.strip_suffix('*') {
+            Some(prefix) => model.starts_with(prefix),
+            None => model == pattern,
+        })
+    }
+
+    fn load(&self) -> Result<Arc<dyn Tokenizer>, String> {
+        match self.kind {
+            TokenizerKind::Approximate => Ok(Arc::new(Approximate)),
+            TokenizerKind::Tiktoken => load_tiktoken(self.encoding.as_deref().unwrap_or("cl100k_base")),
+            TokenizerKind::HuggingFace => load_huggingface(self.file.as_deref(), self.hub.as_deref()),
+        }
+    }
+}
+
+/// Resolves the tokenizer for a model. Tokenizers are loaded on first use
+/// and cached; a tokenizer that fails to load falls back to [`Approximate`].
+pub struct TokenizerRegistry {
+    configs: Vec<TokenizerConfig>,
+    loaded: Mutex<HashMap<usize, Arc<dyn Tokenizer>>>,
+}
+
+impl TokenizerRegistry {
+    pub fn new(configs: Vec<TokenizerConfig>) -> Self {
+        TokenizerRegistry {
+            configs,
+            loaded: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn for_model(&self, model: &str) -> Arc<dyn Tokenizer> {
+        let Some(index) = self.configs.iter().position(|c| c.matches(model)) else {
+            return Arc::new(Approximate);
+        };
+        let mut loaded = self.loaded.lock().unwrap();
+        loaded
+            .entry(index)
+            .or_insert_with(|| {
+                self.configs[index].load().unwrap_or_else(|e| {
+                    println!("Tokenizer for {} unavailable, approximating: {}", model, e);
+                    Arc::new(Approximate)
+                })
+            })
+            .clone()
+    }
+
+    /// Loads every configured tokenizer, reporting which ones are usable.
+    pub fn check(&self) -> Vec<(String, Result<String, String>)> {
+        self.configs
+            .iter()
+            .map(|c| (c.models.join(","), c.load().map(|t| t.name())))
+            .collect()
+    }
+
+    /// Tokens in a chat `messages` array, including per-message overhead.
+    pub fn count_messages(&self, model: &str, messages: &[Value]) -> usize {
+        let tokenizer = self.for_model(model);
+        let content: usize = messages
+            .iter()
+            .filter_map(|m| m.get("content"))
+            .map(|content| tokenizer.count(&content_text(content)))
+            .sum();
+        // OpenAI's chat format adds about three tokens per message plus three to prime the reply.
+        content + messages.len() * 3 + 3
+    }
+}
+
+pub struct Approximate;
+
+impl Tokenizer for Approximate {
+    fn name(&self) -> String {
+        "approximate".to_string()
+    }
+
+    fn count(&self, text: &str) -> usize {
+        text.chars().count().div_ceil(4)
+    }
+
+    fn truncate(&self, text: &str, max_tokens: usize) -> String {
+        text.chars().take(max_tokens * 4).collect()
+    }
+}
+
+#[cfg(feature = "tiktoken")]
+fn load_tiktoken(encoding: &str) -> Result<Arc<dyn Tokenizer>, String> {
+    struct Tiktoken(String, tiktoken_rs::CoreBPE);
+
+    impl Tokenizer for Tiktoken {
+        fn name(&self) -> String {
+            format!("tiktoken/{}", self.0)
+        }
+
+        fn count(&self, text: &str) -> usize {
+            self.1.encode_with_special_tokens(text).len()
+        }
+
+        fn truncate(&self, text: &str, max_tokens: usize) -> String {
+            let tokens = self.1.encode_with_special_tokens(text);
+            if tokens.len() <= max_tokens {
+                return text.to_string();
+            }
+            self.1.decode(tokens[..max_tokens].to_vec()).unwrap_or_default()
+        }
+    }
+
+    let bpe = match encoding {
+        "cl100k_base" => tiktoken_rs::cl100k_base(),
+        "o200k_base" => tiktoken_rs::o200k_base(),
+        "p50k_base" => tiktoken_rs::p50k_base(),
+        "r50k_base" => tiktoken_rs::r50k_base(),
+        other => return Err(format!("unknown tiktoken encoding {}", other)),
+    }
+    .map_err(|e| e.to_string())?;
+    Ok(Arc::new(Tiktoken(encoding.to_string(), bpe)))
+}
+
+#[cfg(not(feature = "tiktoken"))]
+fn load_tiktoken(_encoding: &str) -> Result<Arc<dyn Tokenizer>, String> {
+    Err("built without the `tiktoken` feature".to_string())
+}
+
+#[cfg(feature = "hf-tokenizers")]
+fn load_huggingface(file: Option<&str>, hub: Option<&str>) -> Result<Arc<dyn Tokenizer>, String> {
+    struct HuggingFace(String, tokenizers::Tokenizer);
+
+    impl HuggingFace {
+        fn ids(&self, text: &str) -> Vec<u32> {
+            self.1
+                .encode(text, false)
+                .map(|e| e.get_ids().to_vec())
+                .unwrap_or_default()
+        }
+    }
+
+    impl Tokenizer for HuggingFace {
+        fn name(&self) -> String {
+            format!("huggingface/{}", self.0)
+        }
+
+        fn count(&self, text: &str) -> usize {
+            self.ids(text).len()
+        }
+
+        fn truncate(&self, text: &str, max_tokens: usize) -> String {
+            let ids = self.ids(text);
+            if ids.len() <= max_tokens {
+                return text.to_string();
+            }
+            self.1.decode(&ids[..max_tokens], true).unwrap_or_default()
+        }
+    }
+
+    let (source, tokenizer) = match (file, hub) {
+        (Some(file), _) => (file, tokenizers::Tokenizer::from_file(file)),
+        (None, Some(hub)) => (hub, tokenizers::Tokenizer::from_pretrained(hub, None)),
+        (None, None) => return Err("huggingface tokenizer needs `file` or `hub`".to_string()),
+    };
+    let tokenizer = tokenizer.map_err(|e| format!("{}: {}", source, e))?;
+    Ok(Arc::new(HuggingFace(source.to_string(), tokenizer)))
+}
+
+#[cfg(not(feature = "hf-tokenizers"))]
+fn load_huggingface(_file: Option<&str>, _hub: Option<&str>) -> Result<Arc<dyn Tokenizer>, String> {
+    Err("built without the `hf-tokenizers` feature".to_string())
+}
@@ -0,0 +1,296 @@
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::collections::HashMap;
+
+use crate::sse::SseEvent;
+
+/// How the backend is told about `parallel_tool_calls`.
+#[derive(Debug, Deserialize, Clone, Copy, PartialEq, Eq, Default)]
+#[serde(rename_all = "kebab-case")]
+pub enum ParallelToolCalls {
+    /// The backend understands the OpenAI flag.
+    #[default]
+    Forward,
+    /// The backend rejects the flag; it is removed.
+    Drop,
+    /// Anthropic style: `parallel_tool_calls: false` becomes
+    /// `tool_choice.disable_parallel_tool_use`.
+    DisableParallelToolUse,
+    /// The backend ignores the flag; when a client turns parallel calls off,
+    /// only the first tool call of each response is passed on, so the client
+    /// runs tools one at a time and the model re-issues the rest.
+    Serialize,
+}
+
+/// `[tools]`: tool calling behaviour of the backend.
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ToolsConfig {
+    #[serde(default)]
+    pub parallel_calls: ParallelToolCalls,
+}
+
+/// Applies `mode` to the payload's `parallel_tool_calls`. Returns whether the
+/// response must be cut down to one tool call.
+pub fn apply_parallel_mode(payload: &mut Map<String, Value>, mode: ParallelToolCalls) -> bool {
+    let Some(flag) = payload.get("parallel_tool_calls").and_then(Value::as_bool) else {
+        return false;
+    };
+    match mode {
+        ParallelToolCalls::Forward => false,
+        ParallelToolCalls::Drop => {
+            payload.remove("parallel_tool_calls");
+            false
+        }
+        ParallelToolCalls::DisableParallelToolUse => {
+            payload.remove("parallel_tool_calls");
+            let mut choice = anthropic_tool_choice(payload.remove("tool_choice"));
+            if !flag && choice["type"] != "none" {
+                choice["disable_parallel_tool_use"] = Value::Bool(true);
+            }
+            payload.insert("tool_choice".to_string(), choice);
+            false
+        }
+        ParallelToolCalls::Serialize => {
+            payload.remove("parallel_tool_calls");
+            !flag
+        }
+    }
+}
+
+/// Converts an OpenAI `tool_choice` into Anthropic's form; values already in
+/// that form are kept.
+pub fn anthropic_tool_choice(choice: Option<Value>) -> Value {
+    match choice {
+        Some(Value::String(kind)) if kind == "required" => json!({ "type": "any" }),
+        Some(Value::String(kind)) if kind == "none" => json!({ "type": "none" }),
+        Some(Value::Object(choice)) if choice.contains_key("function") => json!({
+            "type": "tool",
+            "name": choice.get("function").and_then(|f| f.get("name")).cloned().unwrap_or(Value::Null),
+        }),
+        Some(Value::Object(choice)) if choice.get("type").and_then(Value::as_str) != Some("function") => {
+            Value::Object(choice)
+        }
+        _ => json!({ "type": "auto" }),
+    }
+}
+
+/// Keeps only the first tool call of each choice in a non-streamed completion.
+pub fn keep_first_tool_call(completion: &mut Value) -> bool {
+    let mut changed = false;
+    for choice in completion.get_mut("choices").and_then(Value::as_array_mut).into_iter().flatten() {
+        if let Some(calls) = choice
+            .get_mut("message")
+            .and_then(|m| m.get_mut("tool_calls"))
+            .and_then(Value::as_array_mut)
+        {
+            if calls.len() > 1 {
+                calls.truncate(1);
+                changed = true;
+            }
+        }
+    }
+    changed
+}
+
+#[derive(Default)]
+struct Slot {
+    id: Option<String>,
+    announced: bool,
+    arguments: String,
+}
+
+/// Rewrites streamed tool-call deltas into the shape OpenAI clients expect:
+/// every delta carries `index`, `id`/`type`/`name` appear once on the first
+/// delta of a call, and `function.arguments` holds only the new text.
+///
+/// OpenAI-compatible servers differ: some omit `index`, some resend the
+/// whole argument string on every chunk, some send arguments as a JSON
+/// object, and some emit each call as one complete block.
+#[derive(Default)]
+pub struct ToolDeltaNormalizer {
+    /// Calls per choice index, in the order they were first seen.
+    choices: HashMap<u64, Vec<Slot>>,
+    /// Anthropic content block index to tool call index.
+    anthropic_blocks: HashMap<u64, usize>,
+    /// Drop every call after the first, for [`ParallelToolCalls::Serialize`].
+    first_only: bool,
+}
+
+impl ToolDeltaNormalizer {
+    pub fn new(first_only: bool) -> Self {
+        ToolDeltaNormalizer {
+            first_only,
+            ..Default::default()
+        }
+    }
+
+    /// Normalizes a `chat.completion.chunk` event in place, re-serializing it
+    /// only if something changed.
+    pub fn normalize_event(&mut self, event: &mut SseEvent) {
+        if event.is_done() {
+            return;
+        }
+        let Ok(mut chunk) = serde_json::from_str::<Value>(&event.data) else {
+            return;
+        };
+        if self.normalize(&mut chunk) {
+            event.data = chunk.to_string();
+        }
+    }
+
+    /// Returns whether the chunk was modified.
+    pub fn normalize(&mut self, chunk: &mut Value) -> bool {
+        let Some(choices) = chunk.get_mut("choices").and_then(Value::as_array_mut) else {
+            return false;
+        };
+        let mut changed = false;
+        for choice in choices {
+            let index = choice.get("index").and_then(Value::as_u64).unwrap_or(0);
+            let Some(calls) = choice
+                .get_mut("delta")
+                .and_then(|d| d.get_mut("tool_calls"))
+                .and_then(Value::as_array_mut)
+            else {
+                continue;
+            };
+            let slots = self.choices.entry(index).or_default();
+            for call in calls.iter_mut() {
+                if let Value::Object(call) = call {
+                    changed |= normalize_call(slots, call);
+                }
+            }
+            // A resent cumulative argument string may turn into an empty delta.
+            let first_only = self.first_only;
+            let before = calls.len();
+            calls.retain(|call| {
+                let dropped_index = first_only && call.get("index").and_then(Value::as_u64) != Some(0);
+                !is_empty_delta(call) && !dropped_index
+            });
+            changed |= calls.len() != before;
+        }
+        changed
+    }
+
+    /// Translates an Anthropic Messages stream event into an OpenAI
+    /// `delta.tool_calls` fragment, for the tool-use events that have one.
+    pub fn anthropic_delta(&mut self, event: &Value) -> Option<Value> {
+        let block = event.get("index").and_then(Value::as_u64)?;
+        match event.get("type").and_then(Value::as_str)? {
+            "content_block_start" => {
+                let content = event.get("content_block")?;
+                if content.get("type").and_then(Value::as_str) != Some("tool_use") {
+                    return None;
+                }
+                let index = self.anthropic_blocks.len();
+                self.anthropic_blocks.insert(block, index);
+                Some(json!({
+                    "tool_calls": [{
+                        "index": index,
+                        "id": content.get("id").cloned().unwrap_or(Value::Null),
+                        "type": "function",
+                        "function": {
+                            "name": content.get("name").cloned().unwrap_or(Value::Null),
+                            "arguments": "",
+                        },
+                    }]
+                }))
+            }
+            "content_block_delta" => {
+                let delta = event.get("delta")?;
+                if delta.get("type").and_then(Value::as_str) != Some("input_json_delta") {
+                    return None;
+                }
+                let index = *self.anthropic_blocks.get(&block)?;
+                Some(json!({
+                    "tool_calls": [{
+                        "index": index,
+                        "function": { "arguments": delta.get("partial_json").cloned().unwrap_or(json!("")) },
+                    }]
+                }))
+            }
+            _ => None,
+        }
+    }
+}
+
+fn normalize_call(slots: &mut Vec<Slot>, call: &mut Map<String, Value>) -> bool {
+    let mut changed = false;
+    let id = call.get("id").and_then(Value::as_str).map(str::to_string);
+
+    let position = match call.get("index").and_then(Value::as_u64) {
+        Some(index) => index as usize,
+        None => {
+            changed = true;
+            let known = id.as_ref().and_then(|id| slots.iter().position(|s| s.id.as_ref() == Some(id)));
+            match (known, &id) {
+                (Some(position), _) => position,
+                // A new id, or the very first call, starts a new slot.
+                (None, Some(_)) => slots.len(),
+                (None, None) => slots.len().saturating_sub(1),
+            }
+        }
+    };
+    while slots.len() <= position {
+        slots.push(Slot::default());
+    }
+    let slot = &mut slots[position];
+    call.insert("index".to_string(), json!(position));
+
+    if slot.announced {
+        // Only the first delta of a call names it.
+        for key in ["id", "type"] {
+            changed |= call.remove(key).is_some();
+        }
+        if let Some(Value::Object(function)) = call.get_mut("function") {
+            changed |= function.remove("name").is_some();
+        }
+    } else {
+        slot.announced = true;
+        slot.id = id;
+        if !call.contains_key("type") {
+            call.insert("type".to_string(), json!("function"));
+            changed = true;
+        }
+    }
+
+    if let Some(Value::Object(function)) = call.get_mut("function") {
+        let arguments = match function.get("arguments") {
+            None | Some(Value::Null) => None,
+            Some(Value::String(text)) => Some(text.clone()),
+            Some(other) => {
+                changed = true;
+                Some(other.to_string())
+            }
+        };
+        if let Some(arguments) = arguments {
+            let increment = match arguments.strip_prefix(slot.arguments.as_str()) {
+                // Cumulative: the full string so far was sent again.
+                Some(rest) if !slot.arguments.is_empty() => {
+                    changed = true;
+                    rest.to_string()
+                }
+                _ => arguments,
+            };
+            slot.arguments.push_str(&increment);
+            function.insert("arguments".to_string(), Value::String(increment));
+        }
+    }
+    changed
+}
+
+fn is_empty_delta(call: &Value) -> bool {
+    let Some(call) = call.as_object() else {
+        return false;
+    };
+    if !call.keys().all(|k| k == "index" || k == "function") {
+        return false;
+    }
+    match call.get("function") {
+        None => true,
+        Some(Value::Object(function)) => {
+            function.keys().all(|k| k == "arguments")
+                && matches!(function.get("arguments").and_then(Value::as_str), None | Some(""))
+        }
+        Some(_) => false,
+    }
+}
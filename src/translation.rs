@@ -0,0 +1,313 @@
+use axum::{
+    body::Bytes,
+    extract::{ConnectInfo, OriginalUri, State},
+    http::{header, HeaderMap, HeaderValue, StatusCode, Uri},
+};
+use serde::Deserialize;
+use serde_json::{json, Map, Value};
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::time::Duration;
+
+use crate::glossary::GlossaryConfig;
+use crate::postedit::PostEditChain;
+use crate::provider;
+use crate::proxy;
+use crate::AppState;
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct TranslationConfig {
+    /// Report the output language and billed characters/tokens on
+    /// non-streaming responses, like DeepL and Google Translate do.
+    #[serde(default)]
+    pub metadata: bool,
+    /// Two-stage draft and post-edit chains, the first match applying.
+    #[serde(default)]
+    pub post_edit: Vec<PostEditChain>,
+    /// Per-tenant terminology, injected like `x_glossary`.
+    #[serde(default)]
+    pub glossary: GlossaryConfig,
+}
+
+/// Text of a message `content`, either a plain string or an array of parts.
+pub fn content_text(content: &Value) -> String {
+    match content {
+        Value::String(text) => text.clone(),
+        Value::Array(parts) => parts
+            .iter()
+            .filter_map(|part| part.get("text").and_then(Value::as_str))
+            .collect::<Vec<_>>()
+            .join(""),
+        _ => String::new(),
+    }
+}
+
+/// System instruction for translating the user's text, as the built-in
+/// translation endpoints send it.
+pub fn instruction(target_language: &str, source_language: Option<&str>) -> String {
+    let languages = match source_language {
+        Some(source) => format!("from {} into {}", source, target_language),
+        None => format!("into {}", target_language),
+    };
+    format!(
+        "Translate the user's text {}. Reply with the translation only, keeping its formatting.",
+        languages
+    )
+}
+
+/// Translation endpoints' access to the chat pipeline, on behalf of the
+/// client calling them: its credentials and the instruction, model and
+/// glossary every text is translated with. Keys, limits, routing and usage
+/// accounting apply as to any chat request.
+pub struct Pipeline {
+    state: Arc<AppState>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    uri: Uri,
+    headers: HeaderMap,
+    pub model: Option<String>,
+    pub instruction: String,
+    pub glossary: Option<Value>,
+}
+
+/// Why a text came back untranslated.
+#[derive(Debug)]
+pub struct Failure {
+    pub status: StatusCode,
+    pub message: String,
+    pub retry_after: Option<Duration>,
+    /// Whether the failure is classified as transient.
+    pub retryable: bool,
+    /// The pipeline answered with the canned `[degraded]` reply.
+    pub degraded: bool,
+}
+
+impl Pipeline {
+    pub fn new(
+        state: Arc<AppState>,
+        connect_info: Option<ConnectInfo<SocketAddr>>,
+        uri: Uri,
+        mut headers: HeaderMap,
+        instruction: String,
+    ) -> Self {
+        headers.remove(header::CONTENT_LENGTH);
+        headers.remove(header::ACCEPT);
+        headers.insert(header::CONTENT_TYPE, HeaderValue::from_static("application/json"));
+        Pipeline {
+            state,
+            connect_info,
+            uri,
+            headers,
+            model: None,
+            instruction,
+            glossary: None,
+        }
+    }
+
+    /// The client's `x-request-id`, from which per-text ids are derived.
+    pub fn request_id(&self) -> Option<&str> {
+        self.headers.get("x-request-id").and_then(|v| v.to_str().ok())
+    }
+
+    /// Translates `text`, returning the translation and the answer's `usage`.
+    pub async fn translate(&self, text: &str, request_id: Option<&str>) -> Result<(String, Value), Failure> {
+        let mut payload = json!({
+            "messages": [
+                { "role": "system", "content": self.instruction },
+                { "role": "user", "content": text },
+            ],
+        });
+        if let Some(model) = &self.model {
+            payload["model"] = json!(model);
+        }
+        if let Some(glossary) = &self.glossary {
+            payload["x_glossary"] = glossary.clone();
+        }
+        let mut headers = self.headers.clone();
+        if let Some(value) = request_id.and_then(|id| HeaderValue::from_str(id).ok()) {
+            headers.insert("x-request-id", value);
+        }
+        let response = proxy::handle_chat(
+            State(self.state.clone()),
+            self.connect_info,
+            OriginalUri(self.uri.clone()),
+            headers,
+            Bytes::from(payload.to_string()),
+        )
+        .await;
+        let status = response.status();
+        let retry_after = response
+            .headers()
+            .get(header::RETRY_AFTER)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.trim().parse::<u64>().ok())
+            .map(Duration::from_secs);
+        // A canned reply stands in for a failure and is no translation.
+        let degraded = response
+            .headers()
+            .get("x-llmta-degraded")
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse::<u16>().ok())
+            .and_then(|failed| StatusCode::from_u16(failed).ok());
+        if let Some(status) = degraded {
+            return Err(Failure {
+                status,
+                message: "no backend could translate the text".to_string(),
+                retry_after,
+                retryable: provider::classify(status.as_u16(), &reqwest::header::HeaderMap::new(), &[]).is_retryable(),
+                degraded: true,
+            });
+        }
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX).await.unwrap_or_default();
+        if !status.is_success() {
+            let error = provider::classify(status.as_u16(), &reqwest::header::HeaderMap::new(), &body);
+            return Err(Failure {
+                status,
+                retry_after,
+                retryable: error.is_retryable(),
+                message: if error.message.is_empty() { "translation failed".to_string() } else { error.message },
+                degraded: false,
+            });
+        }
+        let answer: Value = serde_json::from_slice(&body).unwrap_or(Value::Null);
+        match answer["choices"][0]["message"]["content"].as_str() {
+            Some(translation) => Ok((translation.to_string(), answer["usage"].clone())),
+            None => Err(Failure {
+                status,
+                message: "the model returned no text".to_string(),
+                retry_after: None,
+                retryable: false,
+                degraded: false,
+            }),
+        }
+    }
+}
+
+/// Characters submitted for translation: the content of all user messages.
+pub fn source_characters(payload: &Value) -> usize {
+    payload
+        .get("messages")
+        .and_then(Value::as_array)
+        .into_iter()
+        .flatten()
+        .filter(|m| m.get("role").and_then(Value::as_str) == Some("user"))
+        .filter_map(|m| m.get("content"))
+        .map(|content| content_text(content).chars().count())
+        .sum()
+}
+
+/// Expands an inline `x_glossary: {"term": "translation"}` into a system
+/// instruction. The caller strips the field before forwarding.
+pub fn apply_glossary(payload: &mut Map<String, Value>, glossary: &Value) -> Result<(), String> {
+    let Value::Object(entries) = glossary else {
+        return Err("x_glossary must be an object mapping terms to translations".to_string());
+    };
+    if entries.is_empty() {
+        return Ok(());
+    }
+    let mut lines = Vec::with_capacity(entries.len());
+    for (term, translation) in entries {
+        let Some(translation) = translation.as_str() else {
+            return Err(format!("x_glossary entry {:?} must be a string", term));
+        };
+        lines.push(format!("- \"{}\" -> \"{}\"", term, translation));
+    }
+    let instruction = format!(
+        "Always translate these terms exactly as given:\n{}",
+        lines.join("\n")
+    );
+
+    let Some(Value::Array(messages)) = payload.get_mut("messages") else {
+        return Ok(());
+    };
+    let system = messages
+        .iter_mut()
+        .find(|m| m.get("role").and_then(Value::as_str) == Some("system"));
+    match system.and_then(|m| m.get_mut("content")) {
+        Some(Value::String(content)) => {
+            content.push_str("\n\n");
+            content.push_str(&instruction);
+        }
+        _ => messages.insert(0, json!({ "role": "system", "content": instruction })),
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TranslationMetadata {
+    /// ISO 639-1 code in upper case (`"DE"`), as DeepL reports it.
+    pub language: Option<String>,
+    pub character_count: usize,
+    pub billed_characters: usize,
+    pub billed_tokens: Option<u64>,
+}
+
+impl TranslationMetadata {
+    pub fn from_completion(completion: &Value, billed_characters: usize) -> Self {
+        let text = completion
+            .get("choices")
+            .and_then(|c| c.get(0))
+            .and_then(|c| c.get("message"))
+            .and_then(|m| m.get("content"))
+            .map(content_text)
+            .unwrap_or_default();
+        TranslationMetadata {
+            language: detect_language(&text),
+            character_count: text.chars().count(),
+            billed_characters,
+            billed_tokens: completion
+                .get("usage")
+                .and_then(|u| u.get("total_tokens"))
+                .and_then(Value::as_u64),
+        }
+    }
+
+    pub fn to_json(&self) -> Value {
+        json!({
+            "detected_language": self.language,
+            "character_count": self.character_count,
+            "billed_characters": self.billed_characters,
+            "billed_tokens": self.billed_tokens,
+        })
+    }
+
+    pub fn apply(&self, headers: &mut HeaderMap) {
+        if let Some(language) = self.language.as_deref().and_then(|l| HeaderValue::from_str(l).ok()) {
+            headers.insert("x-llmta-detected-language", language);
+        }
+        headers.insert("x-llmta-character-count", HeaderValue::from(self.character_count));
+        headers.insert("x-llmta-billed-characters", HeaderValue::from(self.billed_characters));
+        if let Some(tokens) = self.billed_tokens {
+            headers.insert("x-llmta-billed-tokens", HeaderValue::from(tokens));
+        }
+    }
+}
+
+/// Detects the language of `text`, preferring two-letter ISO 639-1 codes and
+/// falling back to ISO 639-3 where no two-letter code exists.
+pub fn detect_language(text: &str) -> Option<String> {
+    let info = whatlang::detect(text)?;
+    let code = info.lang().code();
+    let short = ISO_639_1
+        .iter()
+        .find(|(long, _)| *long == code)
+        .map(|(_, short)| *short)
+        .unwrap_or(code);
+    Some(short.to_uppercase())
+}
+
+const ISO_639_1: &[(&str, &str)] = &[
+    ("afr", "af"), ("aka", "ak"), ("amh", "am"), ("ara", "ar"), ("aze", "az"),
+    ("bel", "be"), ("ben", "bn"), ("bul", "bg"), ("cat", "ca"), ("ces", "cs"),
+    ("cmn", "zh"), ("dan", "da"), ("deu", "de"), ("ell", "el"), ("eng", "en"),
+    ("epo", "eo"), ("est", "et"), ("fin", "fi"), ("fra", "fr"), ("guj", "gu"),
+    ("heb", "he"), ("hin", "hi"), ("hrv", "hr"), ("hun", "hu"), ("hye", "hy"),
+    ("ind", "id"), ("ita", "it"), ("jav", "jv"), ("jpn", "ja"), ("kan", "kn"),
+    ("kat", "ka"), ("khm", "km"), ("kor", "ko"), ("lat", "la"), ("lav", "lv"),
+    ("lit", "lt"), ("mal", "ml"), ("mar", "mr"), ("mkd", "mk"), ("mya", "my"),
+    ("nep", "ne"), ("nld", "nl"), ("nob", "nb"), ("ori", "or"), ("pan", "pa"),
+    ("pes", "fa"), ("pol", "pl"), ("por", "pt"), ("ron", "ro"), ("rus", "ru"),
+    ("sin", "si"), ("slk", "sk"), ("slv", "sl"), ("sna", "sn"), ("spa", "es"),
+    ("srp", "sr"), ("swe", "sv"), ("tam", "ta"), ("tel", "te"), ("tgl", "tl"),
+    ("tha", "th"), ("tuk", "tk"), ("tur", "tr"), ("ukr", "uk"), ("urd", "ur"),
+    ("uzb", "uz"), ("vie", "vi"), ("yid", "yi"), ("zul", "zu"),
+];
@@ -0,0 +1,199 @@
+use axum::{
+    body::{Body, Bytes},
+    extract::{ConnectInfo, State},
+    http::HeaderMap,
+    response::{IntoResponse, Response},
+    Json,
+};
+use rand::Rng;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use crate::AppState;
+
+#[derive(Debug, Deserialize, Clone)]
+pub struct UsageConfig {
+    /// Hours of per-tenant usage kept for `/v1/usage`.
+    #[serde(default = "default_retention_hours")]
+    pub retention_hours: u64,
+    /// Protection applied to the cross-tenant totals tenants can see.
+    #[serde(default)]
+    pub privacy: PrivacyConfig,
+}
+
+fn default_retention_hours() -> u64 {
+    24
+}
+
+impl Default for UsageConfig {
+    fn default() -> Self {
+        UsageConfig {
+            retention_hours: default_retention_hours(),
+            privacy: PrivacyConfig::default(),
+        }
+    }
+}
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct PrivacyConfig {
+    /// Hours with fewer active tenants than this are withheld.
+    #[serde(default)]
+    pub min_tenants: Option<usize>,
+    /// Laplace noise with this privacy budget per released value; smaller is noisier.
+    #[serde(default)]
+    pub epsilon: Option<f64>,
+    /// Tokens one request may contribute to a noisy total. Bounds the
+    /// sensitivity of token sums; larger requests are clipped.
+    #[serde(default = "default_max_tokens_per_request")]
+    pub max_tokens_per_request: u64,
+}
+
+fn default_max_tokens_per_request() -> u64 {
+    4096
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Counts {
+    requests: u64,
+    prompt_tokens: u64,
+    completion_tokens: u64,
+    /// Token totals with each request clipped to `max_tokens_per_request`.
+    clipped_tokens: u64,
+}
+
+/// Hourly request and token counts per tenant.
+pub struct UsageLedger {
+    config: UsageConfig,
+    hours: Mutex<BTreeMap<u64, BTreeMap<String, Counts>>>,
+}
+
+fn current_hour() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_secs() / 3600)
+        .unwrap_or(0)
+}
+
+/// A draw from Laplace(0, scale).
+fn laplace(scale: f64) -> f64 {
+    let u: f64 = rand::thread_rng().gen_range(-0.5..0.5);
+    -scale * u.signum() * (1.0 - 2.0 * u.abs()).ln()
+}
+
+impl UsageLedger {
+    pub fn new(config: UsageConfig) -> Self {
+        UsageLedger {
+            config,
+            hours: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    /// Counts one completed request; `usage` is the completion's `usage` object, if any.
+    pub fn record(&self, tenant: &str, usage: Option<&Value>) {
+        let tokens = |name: &str| usage.and_then(|u| u.get(name)).and_then(Value::as_u64).unwrap_or(0);
+        let (prompt, completion) = (tokens("prompt_tokens"), tokens("completion_tokens"));
+        let mut hours = self.hours.lock().unwrap();
+        let counts = hours
+            .entry(current_hour())
+            .or_default()
+            .entry(tenant.to_string())
+            .or_default();
+        counts.requests += 1;
+        counts.prompt_tokens += prompt;
+        counts.completion_tokens += completion;
+        counts.clipped_tokens += (prompt + completion).min(self.config.privacy.max_tokens_per_request);
+    }
+
+    /// Drops hours past the retention period; returns how many.
+    pub fn purge_expired(&self) -> usize {
+        let oldest = current_hour().saturating_sub(self.config.retention_hours);
+        let mut hours = self.hours.lock().unwrap();
+        let before = hours.len();
+        hours.retain(|hour, _| *hour >= oldest);
+        before - hours.len()
+    }
+
+    /// The tenant's own exact hourly usage.
+    pub fn tenant(&self, tenant: &str) -> Vec<Value> {
+        self.hours
+            .lock()
+            .unwrap()
+            .iter()
+            .filter_map(|(hour, tenants)| {
+                let counts = tenants.get(tenant)?;
+                Some(json!({
+                    "hour": hour * 3600,
+                    "requests": counts.requests,
+                    "prompt_tokens": counts.prompt_tokens,
+                    "completion_tokens": counts.completion_tokens,
+                }))
+            })
+            .collect()
+    }
+
+    /// Hourly totals across all tenants, with the configured thresholds and noise.
+    pub fn aggregate(&self) -> Vec<Value> {
+        let privacy = &self.config.privacy;
+        self.hours
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(hour, tenants)| {
+                if tenants.len() < privacy.min_tenants.unwrap_or(0) {
+                    return json!({ "hour": hour * 3600, "withheld": true });
+                }
+                let requests: u64 = tenants.values().map(|c| c.requests).sum();
+                match privacy.epsilon {
+                    // Released counts are rounded and floored at zero; both are
+                    // post-processing and cost no privacy budget.
+                    Some(epsilon) => {
+                        let tokens: u64 = tenants.values().map(|c| c.clipped_tokens).sum();
+                        let noisy = |value: u64, sensitivity: f64| {
+                            (value as f64 + laplace(sensitivity / epsilon)).round().max(0.0) as u64
+                        };
+                        json!({
+                            "hour": hour * 3600,
+                            "requests": noisy(requests, 1.0),
+                            "tokens": noisy(tokens, privacy.max_tokens_per_request as f64),
+                        })
+                    }
+                    None => json!({
+                        "hour": hour * 3600,
+                        "requests": requests,
+                        "tokens": tenants.values().map(|c| c.prompt_tokens + c.completion_tokens).sum::<u64>(),
+                    }),
+                }
+            })
+            .collect()
+    }
+}
+
+/// `GET /v1/usage`: the caller's own usage plus protected totals across tenants.
+pub async fn handle_usage(
+    State(state): State<Arc<AppState>>,
+    connect_info: Option<ConnectInfo<SocketAddr>>,
+    headers: HeaderMap,
+) -> Response<Body> {
+    let peer = connect_info.map(|ConnectInfo(addr)| addr.ip());
+    let identity = match state.auth.identify(&headers, peer, &Bytes::new()) {
+        Ok(identity) => identity,
+        Err(response) => return response,
+    };
+    let tenant = identity.tenant_key();
+    let privacy = &state.config.usage.privacy;
+    Json(json!({
+        "tenant": tenant,
+        "usage": state.usage.tenant(&tenant),
+        "aggregate": state.usage.aggregate(),
+        "privacy": {
+            "min_tenants": privacy.min_tenants,
+            "epsilon": privacy.epsilon,
+            "max_tokens_per_request": privacy.max_tokens_per_request,
+        },
+    }))
+    .into_response()
+}
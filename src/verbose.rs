@@ -0,0 +1,94 @@
+use serde::{Deserialize, Serialize};
+use std::sync::{Mutex, RwLock};
+use std::time::{Duration, Instant};
+
+use crate::auth::Identity;
+
+/// Which requests are logged in detail, set at runtime through
+/// `/admin/logging` to investigate one caller without logging everything.
+#[derive(Debug, Clone, Serialize, Deserialize, Default)]
+pub struct VerboseSettings {
+    /// Key labels or ids whose every request is logged in detail.
+    #[serde(default)]
+    pub keys: Vec<String>,
+    /// Share of all other requests logged in detail, 0 to 100.
+    #[serde(default)]
+    pub sample_percent: f64,
+    /// Detailed logging turns itself off after this many seconds.
+    #[serde(default)]
+    pub ttl_secs: Option<u64>,
+}
+
+#[derive(Default)]
+pub struct VerboseLogging {
+    settings: RwLock<Option<(VerboseSettings, Instant)>>,
+}
+
+impl VerboseLogging {
+    /// The active settings and how long they have left, if any.
+    pub fn settings(&self) -> Option<(VerboseSettings, Option<Duration>)> {
+        let settings = self.settings.read().unwrap();
+        let (settings, set_at) = settings.as_ref()?;
+        let remaining = match settings.ttl_secs {
+            Some(ttl) => Some(Duration::from_secs(ttl).checked_sub(set_at.elapsed())?),
+            None => None,
+        };
+        Some((settings.clone(), remaining))
+    }
+
+    pub fn set(&self, settings: Option<VerboseSettings>) {
+        *self.settings.write().unwrap() = settings.map(|s| (s, Instant::now()));
+    }
+
+    /// Whether this caller's request should be traced.
+    pub fn applies(&self, identity: &Identity) -> bool {
+        let Some((settings, _)) = self.settings() else {
+            return false;
+        };
+        settings.keys.iter().any(|k| *k == identity.label || *k == identity.id)
+            || rand::random::<f64>() * 100.0 < settings.sample_percent
+    }
+}
+
+/// Timing breakdown of one request logged in detail, printed when the
+/// request is done: for streams, once the last byte went out.
+pub struct Trace {
+    request_id: String,
+    started: Instant,
+    marks: Mutex<Vec<(String, Duration)>>,
+}
+
+impl Trace {
+    pub fn new(request_id: &str) -> Self {
+        Trace {
+            request_id: request_id.to_string(),
+            started: Instant::now(),
+            marks: Mutex::new(Vec::new()),
+        }
+    }
+
+    /// Records that `phase` ended now.
+    pub fn mark(&self, phase: impl Into<String>) {
+        self.marks.lock().unwrap().push((phase.into(), self.started.elapsed()));
+    }
+
+    pub fn log(&self, message: impl std::fmt::Display) {
+        println!("Trace {}: {}", self.request_id, message);
+    }
+}
+
+impl Drop for Trace {
+    fn drop(&mut self) {
+        let marks = self.marks.lock().unwrap();
+        let phases: Vec<String> = marks
+            .iter()
+            .map(|(phase, at)| format!("{} +{}ms", phase, at.as_millis()))
+            .collect();
+        println!(
+            "Trace {}: {}; done after {}ms",
+            self.request_id,
+            phases.join(", "),
+            self.started.elapsed().as_millis()
+        );
+    }
+}
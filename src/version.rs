@@ -0,0 +1,76 @@
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderValue, StatusCode},
+    middleware::Next,
+    response::Response,
+};
+use serde::Deserialize;
+use std::sync::Arc;
+
+use crate::{create_error_response, AppState};
+
+pub const API_VERSION_HEADER: &str = "x-llmta-api-version";
+
+/// Versions of the adapter's extension surface (`x_*` fields, `x-llmta-*`
+/// headers), oldest first. A breaking change adds a new version and keys
+/// the old behaviour off `RequestContext::api_version`.
+pub const SUPPORTED_VERSIONS: &[&str] = &["2026-10-01"];
+
+#[derive(Debug, Deserialize, Clone, Default)]
+pub struct ApiConfig {
+    /// Version assumed when a client sends no `x-llmta-api-version`;
+    /// defaults to the latest.
+    #[serde(default)]
+    pub default_version: Option<String>,
+}
+
+impl ApiConfig {
+    pub fn default_version(&self) -> &str {
+        self.default_version
+            .as_deref()
+            .unwrap_or(SUPPORTED_VERSIONS[SUPPORTED_VERSIONS.len() - 1])
+    }
+}
+
+/// The version a request asked for, or the default. `Err` carries the
+/// unsupported version.
+pub fn requested<'a>(headers: &'a HeaderMap, config: &'a ApiConfig) -> Result<&'a str, String> {
+    let Some(value) = headers.get(API_VERSION_HEADER) else {
+        return Ok(config.default_version());
+    };
+    let version = value.to_str().unwrap_or_default().trim();
+    if SUPPORTED_VERSIONS.contains(&version) {
+        Ok(version)
+    } else {
+        Err(version.to_string())
+    }
+}
+
+/// Rejects unknown versions and echoes the negotiated one on every response.
+pub async fn negotiate(
+    State(state): State<Arc<AppState>>,
+    request: Request,
+    next: Next,
+) -> Response<Body> {
+    let version = match requested(request.headers(), &state.config.api) {
+        Ok(version) => version.to_string(),
+        Err(unsupported) => {
+            return create_error_response(
+                StatusCode::BAD_REQUEST,
+                "unsupported_api_version",
+                &format!(
+                    "API version {:?} is not supported; use one of {}",
+                    unsupported,
+                    SUPPORTED_VERSIONS.join(", ")
+                ),
+            );
+        }
+    };
+
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(&version) {
+        response.headers_mut().insert(API_VERSION_HEADER, value);
+    }
+    response
+}
@@ -0,0 +1,45 @@
+use serde_json::{json, Map, Value};
+
+use crate::provider::Provider;
+
+/// Voyage AI embeddings, which mirror OpenAI's format except for a few
+/// parameter names and the usage fields.
+pub struct Voyage;
+
+impl Provider for Voyage {
+    fn name(&self) -> &'static str {
+        "voyage"
+    }
+
+    fn translate_request(&self, _payload: &mut Map<String, Value>) -> Result<bool, String> {
+        Err("voyage backends only serve embeddings".to_string())
+    }
+
+    fn embeddings_url(&self, base_url: &str, _model: &str) -> Option<String> {
+        Some(format!("{}/embeddings", base_url))
+    }
+
+    fn max_embedding_inputs(&self) -> usize {
+        1000
+    }
+
+    fn translate_embeddings_request(&self, payload: &mut Map<String, Value>) -> Result<(), String> {
+        if let Some(dimensions) = payload.remove("dimensions") {
+            payload.insert("output_dimension".to_string(), dimensions);
+        }
+        // Voyage knows only base64; floats are its default.
+        if payload.get("encoding_format").is_some_and(|f| f != "base64") {
+            payload.remove("encoding_format");
+        }
+        payload.remove("user");
+        Ok(())
+    }
+
+    /// Voyage reports `total_tokens` only.
+    fn translate_embeddings(&self, response: &mut Value, _model: &str) {
+        let tokens = response["usage"]["total_tokens"].as_u64().unwrap_or(0);
+        if let Some(response) = response.as_object_mut() {
+            response.insert("usage".to_string(), json!({ "prompt_tokens": tokens, "total_tokens": tokens }));
+        }
+    }
+}
@@ -0,0 +1,299 @@
+use hmac::{Hmac, Mac};
+use rusqlite::params;
+use serde::Deserialize;
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use crate::db::Database;
+use crate::policy;
+
+/// `[webhooks]`: notifications such as usage events and alerts. Deliveries
+/// are queued in the database and retried with exponential backoff, so a
+/// receiver outage or a restart does not lose them.
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhooksConfig {
+    #[serde(default)]
+    pub endpoints: Vec<WebhookEndpoint>,
+    /// Attempts per delivery before it is dead-lettered.
+    #[serde(default = "default_max_attempts")]
+    pub max_attempts: u32,
+    /// Wait before the first retry, doubled for each further one.
+    #[serde(default = "default_initial_backoff_ms")]
+    pub initial_backoff_ms: u64,
+    #[serde(default = "default_max_backoff_secs")]
+    pub max_backoff_secs: u64,
+    #[serde(default = "default_timeout_secs")]
+    pub timeout_secs: u64,
+}
+
+fn default_max_attempts() -> u32 {
+    10
+}
+
+fn default_initial_backoff_ms() -> u64 {
+    5000
+}
+
+fn default_max_backoff_secs() -> u64 {
+    3600
+}
+
+fn default_timeout_secs() -> u64 {
+    10
+}
+
+impl Default for WebhooksConfig {
+    fn default() -> Self {
+        WebhooksConfig {
+            endpoints: Vec::new(),
+            max_attempts: default_max_attempts(),
+            initial_backoff_ms: default_initial_backoff_ms(),
+            max_backoff_secs: default_max_backoff_secs(),
+            timeout_secs: default_timeout_secs(),
+        }
+    }
+}
+
+/// `[[webhooks.endpoints]]` entry.
+#[derive(Debug, Deserialize, Clone)]
+pub struct WebhookEndpoint {
+    pub name: String,
+    pub url: String,
+    /// Events sent here, such as `usage.request` or `alert.*`; a trailing
+    /// `*` matches by prefix.
+    pub events: Vec<String>,
+    /// Signs bodies with HMAC-SHA256, hex encoded in
+    /// `x-llmta-webhook-signature`.
+    #[serde(default)]
+    pub secret: Option<String>,
+}
+
+struct Delivery {
+    id: i64,
+    endpoint: String,
+    event: String,
+    payload: String,
+    attempts: u32,
+}
+
+/// The webhook retry queue.
+pub struct Webhooks {
+    config: WebhooksConfig,
+    db: Arc<Database>,
+    client: reqwest::Client,
+    /// Keeps a job run and an immediate delivery from sending the same rows.
+    delivering: tokio::sync::Mutex<()>,
+}
+
+fn now_ms() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|d| d.as_millis() as i64)
+        .unwrap_or(0)
+}
+
+impl Webhooks {
+    pub fn new(config: WebhooksConfig, db: Arc<Database>, client: reqwest::Client) -> Self {
+        Webhooks {
+            config,
+            db,
+            client,
+            delivering: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Queues `event` for every endpoint subscribed to it and attempts the
+    /// deliveries right away.
+    pub fn notify(self: &Arc<Self>, event: &str, data: Value) {
+        let endpoints: Vec<&WebhookEndpoint> = self
+            .config
+            .endpoints
+            .iter()
+            .filter(|endpoint| !endpoint.events.is_empty() && policy::matches(&endpoint.events, event))
+            .collect();
+        if endpoints.is_empty() {
+            return;
+        }
+        let now = now_ms();
+        let payload = json!({
+            "id": uuid::Uuid::new_v4().to_string(),
+            "event": event,
+            "created": now / 1000,
+            "data": data,
+        })
+        .to_string();
+        {
+            let conn = self.db.conn();
+            for endpoint in endpoints {
+                let queued = conn.execute(
+                    "INSERT INTO webhook_deliveries (endpoint, event, payload, next_attempt_at, created_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5)",
+                    params![endpoint.name, event, payload, now, now / 1000],
+                );
+                if let Err(e) = queued {
+                    println!("Failed to queue webhook {} for {}: {}", event, endpoint.name, e);
+                }
+            }
+        }
+        let webhooks = self.clone();
+        tokio::spawn(async move {
+            if let Err(e) = webhooks.deliver_due().await {
+                println!("Webhook delivery failed: {}", e);
+            }
+        });
+    }
+
+    fn backoff(&self, attempts: u32) -> Duration {
+        let backoff = Duration::from_millis(self.config.initial_backoff_ms)
+            .saturating_mul(2u32.saturating_pow(attempts.saturating_sub(1)));
+        backoff.min(Duration::from_secs(self.config.max_backoff_secs))
+    }
+
+    /// Sends the deliveries that are due; run by the `webhook-delivery` job.
+    pub async fn deliver_due(&self) -> Result<String, String> {
+        let _delivering = self.delivering.lock().await;
+        let due = {
+            let conn = self.db.conn();
+            let mut statement = conn
+                .prepare(
+                    "SELECT id, endpoint, event, payload, attempts FROM webhook_deliveries
+                     WHERE dead = 0 AND next_attempt_at <= ?1 ORDER BY id LIMIT 100",
+                )
+                .map_err(|e| e.to_string())?;
+            let rows = statement
+                .query_map(params![now_ms()], |row| {
+                    Ok(Delivery {
+                        id: row.get(0)?,
+                        endpoint: row.get(1)?,
+                        event: row.get(2)?,
+                        payload: row.get(3)?,
+                        attempts: row.get(4)?,
+                    })
+                })
+                .map_err(|e| e.to_string())?;
+            rows.collect::<Result<Vec<_>, _>>().map_err(|e| e.to_string())?
+        };
+
+        let (mut delivered, mut retrying, mut dead) = (0, 0, 0);
+        for delivery in &due {
+            let result = match self.config.endpoints.iter().find(|e| e.name == delivery.endpoint) {
+                Some(endpoint) => self.send(endpoint, delivery).await,
+                None => Err("endpoint is no longer configured".to_string()),
+            };
+            let attempts = delivery.attempts + 1;
+            let conn = self.db.conn();
+            let updated = match result {
+                Ok(()) => {
+                    delivered += 1;
+                    conn.execute("DELETE FROM webhook_deliveries WHERE id = ?1", params![delivery.id])
+                }
+                Err(error) if attempts >= self.config.max_attempts => {
+                    dead += 1;
+                    println!(
+                        "Dead-lettered webhook {} #{} to {} after {} attempts: {}",
+                        delivery.event, delivery.id, delivery.endpoint, attempts, error
+                    );
+                    conn.execute(
+                        "UPDATE webhook_deliveries SET attempts = ?1, last_error = ?2, dead = 1 WHERE id = ?3",
+                        params![attempts, error, delivery.id],
+                    )
+                }
+                Err(error) => {
+                    retrying += 1;
+                    let next = now_ms() + self.backoff(attempts).as_millis() as i64;
+                    conn.execute(
+                        "UPDATE webhook_deliveries SET attempts = ?1, last_error = ?2, next_attempt_at = ?3
+                         WHERE id = ?4",
+                        params![attempts, error, next, delivery.id],
+                    )
+                }
+            };
+            updated.map_err(|e| e.to_string())?;
+        }
+        Ok(format!("delivered {}, retrying {}, dead-lettered {}", delivered, retrying, dead))
+    }
+
+    async fn send(&self, endpoint: &WebhookEndpoint, delivery: &Delivery) -> Result<(), String> {
+        let mut request = self
+            .client
+            .post(&endpoint.url)
+            .timeout(Duration::from_secs(self.config.timeout_secs))
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .header("x-llmta-event", &delivery.event)
+            .header("x-llmta-delivery", delivery.id.to_string());
+        if let Some(secret) = &endpoint.secret {
+            let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes())
+                .expect("HMAC accepts keys of any length");
+            mac.update(delivery.payload.as_bytes());
+            let signature = hex::encode(mac.finalize().into_bytes());
+            request = request.header("x-llmta-webhook-signature", signature);
+        }
+        let response = request
+            .body(delivery.payload.clone())
+            .send()
+            .await
+            .map_err(|e| e.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("receiver answered {}", response.status()))
+        }
+    }
+
+    /// Deliveries waiting for a retry, and the dead letters.
+    pub fn report(&self) -> Result<Value, String> {
+        let conn = self.db.conn();
+        let pending: i64 = conn
+            .query_row("SELECT COUNT(*) FROM webhook_deliveries WHERE dead = 0", [], |row| row.get(0))
+            .map_err(|e| e.to_string())?;
+        let mut statement = conn
+            .prepare(
+                "SELECT id, endpoint, event, payload, attempts, last_error, created_at
+                 FROM webhook_deliveries WHERE dead = 1 ORDER BY id DESC LIMIT 100",
+            )
+            .map_err(|e| e.to_string())?;
+        let rows = statement
+            .query_map([], |row| {
+                Ok(json!({
+                    "id": row.get::<_, i64>(0)?,
+                    "endpoint": row.get::<_, String>(1)?,
+                    "event": row.get::<_, String>(2)?,
+                    "payload": serde_json::from_str::<Value>(&row.get::<_, String>(3)?).unwrap_or(Value::Null),
+                    "attempts": row.get::<_, u32>(4)?,
+                    "last_error": row.get::<_, Option<String>>(5)?,
+                    "created_at": row.get::<_, i64>(6)?,
+                }))
+            })
+            .map_err(|e| e.to_string())?;
+        let dead_letters: Vec<Value> = rows.collect::<Result<_, _>>().map_err(|e| e.to_string())?;
+        Ok(json!({ "pending": pending, "dead_letters": dead_letters }))
+    }
+
+    /// Forgets dead letters older than `days`; returns how many.
+    pub fn purge_dead_older_than(&self, days: u64) -> Result<usize, String> {
+        let cutoff = now_ms() / 1000 - (days * 86_400) as i64;
+        self.db
+            .conn()
+            .execute(
+                "DELETE FROM webhook_deliveries WHERE dead = 1 AND created_at < ?1",
+                params![cutoff],
+            )
+            .map_err(|e| e.to_string())
+    }
+
+    /// Puts a dead letter back in the queue; `false` if there is none by `id`.
+    pub fn requeue(&self, id: i64) -> Result<bool, String> {
+        let updated = self
+            .db
+            .conn()
+            .execute(
+                "UPDATE webhook_deliveries SET dead = 0, attempts = 0, next_attempt_at = ?1
+                 WHERE id = ?2 AND dead = 1",
+                params![now_ms(), id],
+            )
+            .map_err(|e| e.to_string())?;
+        Ok(updated > 0)
+    }
+}
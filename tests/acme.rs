@@ -0,0 +1,76 @@
+use openai_api_proxy::acme::Challenge;
+use openai_api_proxy::{serve, AppConfig};
+
+fn config(acme: &str) -> Result<AppConfig, config::ConfigError> {
+    AppConfig::from_toml(&format!(
+        "model_url = \"http://127.0.0.1:1/v1/chat/completions\"\nmodel_key = \"k\"\n\
+         default_model = \"test-model\"\nport = 0\nhost = \"127.0.0.1\"\n\n[acme]\n{}",
+        acme
+    ))
+}
+
+#[test]
+fn reads_acme_settings_with_defaults() {
+    let acme = config("domains = [\"llm.example.com\"]\n").unwrap().acme.unwrap();
+    assert_eq!(acme.domains, ["llm.example.com"]);
+    assert_eq!(acme.challenge, Challenge::TlsAlpn01);
+    assert_eq!(acme.cache_dir, "acme-cache");
+    assert_eq!(acme.http_port, 80);
+    assert!(!acme.staging);
+
+    let acme = config("domains = [\"a.example.com\"]\nchallenge = \"http-01\"\nhttp_port = 8080\nstaging = true\n")
+        .unwrap()
+        .acme
+        .unwrap();
+    assert_eq!(acme.challenge, Challenge::Http01);
+    assert_eq!(acme.http_port, 8080);
+    assert!(acme.staging);
+
+    assert!(config("domains = [\"a.example.com\"]\nchallenge = \"dns-01\"\n").is_err());
+    assert!(config("staging = true\n").is_err());
+}
+
+#[cfg(not(feature = "acme"))]
+#[tokio::test]
+async fn refuses_acme_without_the_feature() {
+    let config = config("domains = [\"llm.example.com\"]\n").unwrap();
+    let error = serve(config, std::future::pending()).await.unwrap_err();
+    assert!(error.to_string().contains("`acme` feature"), "{}", error);
+}
+
+#[cfg(feature = "acme")]
+#[tokio::test]
+async fn answers_http_challenges_until_shut_down() {
+    let free_port = || std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let (port, http_port) = (free_port(), free_port());
+    let cache = std::env::temp_dir().join(format!("lta-acme-{}", std::process::id()));
+    let config = AppConfig::from_toml(&format!(
+        "model_url = \"http://127.0.0.1:1/v1/chat/completions\"\nmodel_key = \"k\"\n\
+         default_model = \"test-model\"\nport = {}\nhost = \"127.0.0.1\"\n\n[acme]\n\
+         domains = [\"llm.example.com\"]\nchallenge = \"http-01\"\nhttp_port = {}\nstaging = true\ncache_dir = {:?}\n",
+        port,
+        http_port,
+        cache.to_str().unwrap()
+    ))
+    .unwrap();
+
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+    let server = tokio::spawn(serve(config, async move {
+        let _ = stopped.await;
+    }));
+    let connect = |port: u16| async move {
+        for _ in 0..100 {
+            if tokio::net::TcpStream::connect(("127.0.0.1", port)).await.is_ok() {
+                return true;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        }
+        false
+    };
+    assert!(connect(http_port).await, "challenge listener");
+    assert!(connect(port).await, "TLS listener");
+
+    stop.send(()).unwrap();
+    server.await.unwrap().unwrap();
+    let _ = std::fs::remove_dir_all(&cache);
+}
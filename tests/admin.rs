@@ -0,0 +1,1164 @@
+mod common;
+
+use common::{chunk, completion, spawn_adapter, MockUpstream, Reply, CHAT_PATH};
+use openai_api_proxy::{snapshot, AppConfig, AppState};
+use serde_json::{json, Value};
+use std::time::Duration;
+
+const ADMIN: &str = "[admin]\ntoken = \"admin-secret\"\n";
+
+#[tokio::test]
+async fn admin_api_is_disabled_without_token() {
+    let upstream = MockUpstream::start().await;
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/streams", adapter))
+        .bearer_auth("anything")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 404);
+}
+
+#[tokio::test]
+async fn admin_api_rejects_wrong_token() {
+    let upstream = MockUpstream::start().await;
+    let adapter = spawn_adapter(&upstream, ADMIN).await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/streams", adapter))
+        .bearer_auth("nope")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 401);
+
+    for token in ["admin-secre", "admin-secret2", ""] {
+        let response = reqwest::Client::new()
+            .get(format!("{}/admin/streams", adapter))
+            .bearer_auth(token)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 401, "token {:?}", token);
+    }
+}
+
+#[tokio::test]
+async fn reports_config_hash_to_admins_only() {
+    let upstream = MockUpstream::start().await;
+    let adapter = spawn_adapter(&upstream, ADMIN).await;
+    let other = spawn_adapter(&upstream, &format!("{}[browser]\nenabled = false\n", ADMIN)).await;
+
+    let version = |base: String| async move {
+        reqwest::Client::new()
+            .get(format!("{}/admin/version", base))
+            .bearer_auth("admin-secret")
+            .send()
+            .await
+            .unwrap()
+            .json::<Value>()
+            .await
+            .unwrap()
+    };
+    let info = version(adapter.clone()).await;
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert_eq!(info["config_hash"].as_str().unwrap().len(), 64);
+    assert_eq!(version(adapter.clone()).await["config_hash"], info["config_hash"]);
+    assert_ne!(version(other).await["config_hash"], info["config_hash"]);
+
+    let response = reqwest::get(format!("{}/admin/version", adapter)).await.unwrap();
+    assert_eq!(response.status(), 401);
+}
+
+#[tokio::test]
+async fn lists_and_terminates_active_streams() {
+    let upstream = MockUpstream::start().await;
+    let chunks: Vec<String> = (0..100).map(|i| chunk(&format!("w{} ", i))).collect();
+    upstream.push(Reply::sse(&chunks).chunk_delay(Duration::from_millis(50)));
+    let adapter = spawn_adapter(&upstream, ADMIN).await;
+    let client = reqwest::Client::new();
+
+    let mut stream = client
+        .post(format!("{}{}", adapter, CHAT_PATH))
+        .header("x-request-id", "req-123")
+        .bearer_auth("sk-client-0000-abcd")
+        .json(&json!({ "model": "test-model", "messages": [], "stream": true }))
+        .send()
+        .await
+        .unwrap();
+    assert!(stream.chunk().await.unwrap().is_some());
+
+    let stats: Value = client
+        .get(format!("{}/admin/streams", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(stats["active"], 1);
+    assert_eq!(stats["streams"][0]["request_id"], "req-123");
+    assert_eq!(stats["streams"][0]["model"], "test-model");
+    assert_eq!(stats["streams"][0]["key"], "sk-c…abcd");
+
+    let response = client
+        .delete(format!("{}/admin/streams/req-123", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let mut rest = String::new();
+    while let Some(bytes) = stream.chunk().await.unwrap() {
+        rest.push_str(&String::from_utf8_lossy(&bytes));
+    }
+    assert!(rest.contains("stream_terminated"));
+}
+
+#[tokio::test]
+async fn applies_per_key_overrides_from_config_and_admin_api() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, common::completion("ok")));
+    let adapter = spawn_adapter(
+        &upstream,
+        &format!(
+            "{}\n[[keys]]\nname = \"translator\"\nkey = \"client-key\"\nsystem_prompt = \"Translate formally.\"\nparams = {{ temperature = 0.1 }}\n",
+            ADMIN
+        ),
+    )
+    .await;
+    let body = json!({ "model": "test-model", "messages": [{ "role": "user", "content": "hi" }] });
+
+    common::post_chat(&adapter, body.clone()).await;
+    let sent = &upstream.requests()[0].body;
+    assert_eq!(sent["messages"][0]["role"], "system");
+    assert_eq!(sent["messages"][0]["content"], "Translate formally.");
+    assert_eq!(sent["messages"][1]["content"], "hi");
+    assert_eq!(sent["temperature"], 0.1);
+
+    let response = reqwest::Client::new()
+        .put(format!("{}/admin/keys/translator/overrides", adapter))
+        .bearer_auth("admin-secret")
+        .json(&json!({ "system_prompt": "Translate casually.", "params": { "temperature": 0.9 } }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    common::post_chat(&adapter, body).await;
+    let sent = &upstream.requests()[1].body;
+    assert_eq!(sent["messages"][0]["content"], "Translate casually.");
+    assert_eq!(sent["temperature"], 0.9);
+
+    // Keys are addressed by name; the secret itself is not a route.
+    let by_secret: Value = reqwest::Client::new()
+        .get(format!("{}/admin/keys/client-key/overrides", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(by_secret["system_prompt"], Value::Null);
+}
+
+#[tokio::test]
+async fn scopes_cached_answers_to_overrides_and_stored_glossaries() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let config = r#"
+[cache]
+enabled = true
+
+[[auth.virtual_keys]]
+key = "vk-formal"
+name = "formal"
+tenant = "acme"
+
+[[auth.virtual_keys]]
+key = "vk-plain"
+name = "plain"
+tenant = "acme"
+"#;
+    let adapter = spawn_adapter(&upstream, &format!("{}{}", ADMIN, config)).await;
+    let client = reqwest::Client::new();
+    let chat = |key: &'static str| {
+        client
+            .post(format!("{}{}", adapter, CHAT_PATH))
+            .bearer_auth(key)
+            .json(&json!({ "model": "test-model", "messages": [{ "role": "user", "content": "Hi" }] }))
+            .send()
+    };
+    let cache = |response: reqwest::Response| response.headers()["x-llmta-cache"].to_str().unwrap().to_string();
+
+    client
+        .put(format!("{}/admin/keys/formal/overrides", adapter))
+        .bearer_auth("admin-secret")
+        .json(&json!({ "system_prompt": "Be formal." }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(cache(chat("vk-plain").await.unwrap()), "miss");
+    assert_eq!(cache(chat("vk-plain").await.unwrap()), "hit");
+    // Same tenant and payload, but the override changes what is sent.
+    assert_eq!(cache(chat("vk-formal").await.unwrap()), "miss");
+    assert_eq!(upstream.requests()[1].body["messages"][0]["content"], "Be formal.");
+
+    client
+        .put(format!("{}/admin/glossaries/acme", adapter))
+        .bearer_auth("admin-secret")
+        .json(&json!({ "Hi": "Hallo" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(cache(chat("vk-plain").await.unwrap()), "miss");
+    assert_eq!(upstream.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn maintenance_mode_and_drain_reject_new_requests() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, common::completion("ok")));
+    let adapter = spawn_adapter(&upstream, ADMIN).await;
+    let client = reqwest::Client::new();
+    let body = json!({ "model": "test-model", "messages": [] });
+
+    client
+        .put(format!("{}/admin/maintenance", adapter))
+        .bearer_auth("admin-secret")
+        .json(&json!({ "message": "Migrating", "retry_after_secs": 120 }))
+        .send()
+        .await
+        .unwrap();
+    let response = common::post_chat(&adapter, body.clone()).await;
+    assert_eq!(response.status(), 503);
+    assert_eq!(response.headers()["retry-after"], "120");
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["error"]["message"], "Migrating");
+
+    client
+        .delete(format!("{}/admin/maintenance", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(common::post_chat(&adapter, body.clone()).await.status(), 200);
+
+    client
+        .post(format!("{}/admin/backends/default/drain", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(common::post_chat(&adapter, body).await.status(), 503);
+}
+
+#[tokio::test]
+async fn routes_around_drained_backends() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let backend = |name: &str| {
+        format!(
+            "[[backends]]\nname = \"{}\"\nurl = \"{}/v1/chat/completions\"\nkey = \"{}-key\"\nmodels = [\"pooled\"]\n",
+            name, upstream.base_url, name
+        )
+    };
+    let config = format!("{}\n{}{}", ADMIN, backend("one"), backend("two"));
+    let adapter = spawn_adapter(&upstream, &config).await;
+    let client = reqwest::Client::new();
+    let drain = |name: &'static str| {
+        client
+            .post(format!("{}/admin/backends/{}/drain", adapter, name))
+            .bearer_auth("admin-secret")
+            .send()
+    };
+    let body = json!({ "model": "pooled", "messages": [] });
+
+    drain("one").await.unwrap();
+    for _ in 0..2 {
+        assert_eq!(common::post_chat(&adapter, body.clone()).await.status(), 200);
+    }
+    let keys: Vec<String> = upstream
+        .requests()
+        .iter()
+        .map(|r| r.headers["authorization"].to_str().unwrap().to_string())
+        .collect();
+    assert_eq!(keys, ["Bearer two-key", "Bearer two-key"]);
+
+    drain("two").await.unwrap();
+    assert_eq!(common::post_chat(&adapter, body).await.status(), 503);
+    assert_eq!(upstream.requests().len(), 2);
+}
+
+#[tokio::test]
+async fn toggles_detailed_logging_for_keys() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, ADMIN).await;
+    let client = reqwest::Client::new();
+    let url = format!("{}/admin/logging", adapter);
+
+    let rejected = client
+        .put(&url)
+        .bearer_auth("admin-secret")
+        .json(&json!({ "sample_percent": 150 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rejected.status(), 400);
+
+    client
+        .put(&url)
+        .bearer_auth("admin-secret")
+        .json(&json!({ "keys": ["client-key"], "sample_percent": 1.5, "ttl_secs": 600 }))
+        .send()
+        .await
+        .unwrap();
+    let current: Value =
+        client.get(&url).bearer_auth("admin-secret").send().await.unwrap().json().await.unwrap();
+    assert_eq!(current["enabled"], true);
+    assert_eq!(current["settings"]["keys"], json!(["client-key"]));
+    assert!(current["remaining_secs"].as_u64().is_some_and(|left| left <= 600));
+
+    let body = json!({ "model": "test-model", "messages": [] });
+    assert_eq!(common::post_chat(&adapter, body).await.status(), 200);
+
+    client.delete(&url).bearer_auth("admin-secret").send().await.unwrap();
+    let current: Value =
+        client.get(&url).bearer_auth("admin-secret").send().await.unwrap().json().await.unwrap();
+    assert_eq!(current["enabled"], false);
+}
+
+#[tokio::test]
+async fn watchdog_aborts_requests_past_their_lifetime() {
+    let upstream = MockUpstream::start().await;
+    let chunks: Vec<String> = (0..50).map(|i| chunk(&format!("w{} ", i))).collect();
+    upstream.push(Reply::sse(&chunks).chunk_delay(Duration::from_millis(100)));
+    let adapter = spawn_adapter(
+        &upstream,
+        &format!("{}[watchdog]\nmax_lifetime_secs = 1\nabort = true\n", ADMIN),
+    )
+    .await;
+    let client = reqwest::Client::new();
+
+    let stream = common::post_chat(
+        &adapter,
+        json!({ "model": "test-model", "messages": [], "stream": true }),
+    )
+    .await;
+    assert_eq!(stream.status(), 200);
+    let listed: Value = client
+        .get(format!("{}/admin/requests", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let paths: Vec<&str> =
+        listed["requests"].as_array().unwrap().iter().filter_map(|r| r["path"].as_str()).collect();
+    assert!(paths.contains(&CHAT_PATH));
+
+    tokio::time::sleep(Duration::from_millis(1200)).await;
+    client
+        .post(format!("{}/admin/jobs/request-watchdog/run", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap();
+    assert!(stream.text().await.is_err());
+
+    let listed: Value = client
+        .get(format!("{}/admin/requests", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(listed["aborted"], 1);
+    assert_eq!(listed["overdue"], 1);
+}
+
+#[tokio::test]
+async fn lists_and_triggers_scheduled_jobs() {
+    let upstream = MockUpstream::start().await;
+    let adapter = spawn_adapter(&upstream, ADMIN).await;
+    let client = reqwest::Client::new();
+
+    let triggered = client
+        .post(format!("{}/admin/jobs/rate-limit-cleanup/run", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(triggered.status(), 202);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let jobs: Value = client
+        .get(format!("{}/admin/jobs", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let job = jobs["jobs"]
+        .as_array()
+        .unwrap()
+        .iter()
+        .find(|j| j["name"] == "rate-limit-cleanup")
+        .unwrap();
+    assert_eq!(job["runs"], 1);
+    assert_eq!(job["interval_secs"], 60);
+}
+
+#[tokio::test]
+async fn exports_state_and_imports_it_into_another_instance() {
+    let upstream = MockUpstream::start().await;
+    let blue = spawn_adapter(&upstream, ADMIN).await;
+    let green = spawn_adapter(&upstream, ADMIN).await;
+    let client = reqwest::Client::new();
+
+    client
+        .put(format!("{}/admin/keys/translator/overrides", blue))
+        .bearer_auth("admin-secret")
+        .json(&json!({ "system_prompt": "Be brief." }))
+        .send()
+        .await
+        .unwrap();
+    client
+        .post(format!("{}/admin/backends/default/drain", blue))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap();
+
+    let snapshot: Value = client
+        .get(format!("{}/admin/state", blue))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(snapshot["drained"], json!(["default"]));
+    assert_eq!(snapshot["key_overrides"]["translator"]["system_prompt"], "Be brief.");
+
+    let imported = client
+        .put(format!("{}/admin/state", green))
+        .bearer_auth("admin-secret")
+        .json(&snapshot)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(imported.status(), 204);
+
+    let overrides: Value = client
+        .get(format!("{}/admin/keys/translator/overrides", green))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(overrides["system_prompt"], "Be brief.");
+    let body = json!({ "model": "test-model", "messages": [] });
+    assert_eq!(common::post_chat(&green, body).await.status(), 503);
+}
+
+#[tokio::test]
+async fn hands_over_limits_by_fingerprint_and_cached_answers() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("cached")));
+    let config = format!("{}[limits]\nrequests_per_minute = 2\n\n[cache]\nenabled = true\n", ADMIN);
+    let blue = spawn_adapter(&upstream, &config).await;
+    let green = spawn_adapter(&upstream, &config).await;
+    let client = reqwest::Client::new();
+    let body = json!({ "model": "test-model", "messages": [{ "role": "user", "content": "hi" }] });
+    assert_eq!(common::post_chat(&blue, body.clone()).await.status(), 200);
+
+    let snapshot: Value = client
+        .get(format!("{}/admin/state", blue))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(!snapshot.to_string().contains("client-key"));
+    assert_eq!(snapshot["rate_limits"][0]["count"], 1);
+    assert_eq!(snapshot["cache"].as_array().unwrap().len(), 1);
+    let imported = client
+        .put(format!("{}/admin/state", green))
+        .bearer_auth("admin-secret")
+        .json(&snapshot)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(imported.status(), 204);
+
+    // Served from the carried-over cache, and counted in the carried-over window.
+    let response = common::post_chat(&green, body.clone()).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-llmta-cache"], "hit");
+    assert_eq!(common::post_chat(&green, body).await.status(), 429);
+    assert_eq!(upstream.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn saves_state_to_a_private_file_and_restores_budgets() {
+    let path = std::env::temp_dir().join(format!("llmta-state-{}.json", std::process::id()));
+    let state = || {
+        let config = AppConfig::from_toml(&format!(
+            "model_url = \"http://127.0.0.1:1/v1/chat/completions\"\nmodel_key = \"k\"\n\
+             default_model = \"test-model\"\nport = 0\nhost = \"127.0.0.1\"\n\n[limits]\ntokens_per_minute = 100\n\n[state]\npath = {:?}\n",
+            path.to_str().unwrap()
+        ))
+        .unwrap();
+        AppState::new(config).unwrap()
+    };
+    let charges = |state: &AppState| state.config.limits.charges("client-key", None, "test-model", || 60);
+
+    let blue = state();
+    blue.budgets.take(&charges(&blue)).unwrap();
+    snapshot::save(&blue).await.unwrap();
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        let mode = std::fs::metadata(&path).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+    }
+    // Saving again replaces the file rather than writing into it.
+    snapshot::save(&blue).await.unwrap();
+    assert!(!path.with_extension("json.tmp").exists());
+
+    let green = state();
+    snapshot::restore(&green).await.unwrap();
+    assert!(green.budgets.take(&charges(&green)).is_err());
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[tokio::test]
+async fn reports_slo_compliance_and_reroutes_when_budget_is_exhausted() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, common::completion("ok")).delayed(Duration::from_millis(20)));
+    let config = format!(
+        "{}[[slo]]\nmodels = [\"test-*\"]\nttft_ms = 5\nmin_samples = 1\nfallback_model = \"fast-model\"\n",
+        ADMIN
+    );
+    let adapter = spawn_adapter(&upstream, &config).await;
+
+    let body = json!({ "model": "test-model", "messages": [] });
+    assert_eq!(common::post_chat(&adapter, body.clone()).await.status(), 200);
+
+    let slo: Value = reqwest::Client::new()
+        .get(format!("{}/admin/slo", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let objective = &slo["objectives"][0];
+    assert_eq!(objective["requests"], 1);
+    assert_eq!(objective["met"], 0);
+    assert_eq!(objective["compliance"], 0.0);
+    assert_eq!(objective["exhausted"], true);
+
+    assert_eq!(common::post_chat(&adapter, body).await.status(), 200);
+    let requests = upstream.requests();
+    assert_eq!(requests[0].body["model"], "test-model");
+    assert_eq!(requests[1].body["model"], "fast-model");
+}
+
+#[tokio::test]
+async fn publishes_and_rolls_back_prompt_templates() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, common::completion("ok")));
+    let adapter = spawn_adapter(&upstream, ADMIN).await;
+    let client = reqwest::Client::new();
+    let publish = |content: &'static str| {
+        client
+            .post(format!("{}/admin/templates/translate", adapter))
+            .bearer_auth("admin-secret")
+            .json(&json!({ "content": content }))
+            .send()
+    };
+    assert_eq!(publish("Translate into {{language}}.").await.unwrap().status(), 201);
+    let second: Value = publish("Translate faithfully into {{language}}.").await.unwrap().json().await.unwrap();
+    assert_eq!(second["version"], 2);
+
+    let body = json!({
+        "model": "test-model",
+        "x_template": "translate",
+        "x_template_vars": { "language": "German" },
+        "messages": [{ "role": "user", "content": "Hello" }]
+    });
+    let response = common::post_chat(&adapter, body.clone()).await;
+    assert_eq!(response.headers()["x-llmta-template-version"], "translate@2");
+
+    let rolled_back = client
+        .post(format!("{}/admin/templates/translate/rollback", adapter))
+        .bearer_auth("admin-secret")
+        .json(&json!({ "version": 1 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(rolled_back.status(), 200);
+    common::post_chat(&adapter, body).await;
+
+    let requests = upstream.requests();
+    assert_eq!(requests[0].body["messages"][0]["content"], "Translate faithfully into German.");
+    assert_eq!(requests[1].body["messages"][0]["content"], "Translate into German.");
+    assert!(requests[1].body.get("x_template").is_none());
+}
+
+#[tokio::test]
+async fn compares_feedback_across_template_versions() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, common::completion("ok")));
+    let adapter = spawn_adapter(&upstream, ADMIN).await;
+    let client = reqwest::Client::new();
+    for content in ["Translate.", "Translate carefully."] {
+        client
+            .post(format!("{}/admin/templates/translate", adapter))
+            .bearer_auth("admin-secret")
+            .json(&json!({ "content": content }))
+            .send()
+            .await
+            .unwrap();
+    }
+    client
+        .post(format!("{}/admin/templates/translate/rollback", adapter))
+        .bearer_auth("admin-secret")
+        .json(&json!({ "version": 1 }))
+        .send()
+        .await
+        .unwrap();
+    let experiment = client
+        .put(format!("{}/admin/templates/translate/experiment", adapter))
+        .bearer_auth("admin-secret")
+        .json(&json!({ "version": 2, "percent": 100 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(experiment.status(), 200);
+
+    let chat = client
+        .post(format!("{}{}", adapter, CHAT_PATH))
+        .bearer_auth("client-key")
+        .header("x-request-id", "req-ab-1")
+        .json(&json!({ "model": "test-model", "x_template": "translate", "messages": [] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(chat.headers()["x-llmta-template-version"], "translate@2");
+
+    let feedback = client
+        .post(format!("{}/v1/feedback", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({ "request_id": "req-ab-1", "rating": "up" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(feedback.status(), 201);
+    let unknown = client
+        .post(format!("{}/v1/feedback", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({ "request_id": "req-missing", "score": 0.2 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(unknown.status(), 404);
+
+    let evaluation: Value = client
+        .get(format!("{}/admin/templates/translate/experiment", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(evaluation["experiment"]["version"], 2);
+    assert_eq!(evaluation["versions"][0]["version"], 2);
+    assert_eq!(evaluation["versions"][0]["requests"], 1);
+    assert_eq!(evaluation["versions"][0]["mean_score"], 1.0);
+    assert_eq!(evaluation["versions"][0]["positive"], 1);
+}
+
+#[tokio::test]
+async fn links_feedback_comments_to_request_metadata() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, common::completion("ok")));
+    let adapter = spawn_adapter(&upstream, ADMIN).await;
+    let client = reqwest::Client::new();
+
+    client
+        .post(format!("{}{}", adapter, CHAT_PATH))
+        .bearer_auth("client-key")
+        .header("x-request-id", "req-fb-1")
+        .json(&json!({ "model": "test-model", "messages": [] }))
+        .send()
+        .await
+        .unwrap();
+    let feedback = client
+        .post(format!("{}/v1/feedback", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({ "request_id": "req-fb-1", "rating": 4, "comment": "Stiff wording" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(feedback.status(), 201);
+    let invalid = client
+        .post(format!("{}/v1/feedback", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({ "request_id": "req-fb-1", "rating": 9 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(invalid.status(), 400);
+
+    let report: Value = client
+        .get(format!("{}/admin/feedback", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let latest = &report["recent"][0];
+    assert_eq!(latest["score"], 0.75);
+    assert_eq!(latest["comment"], "Stiff wording");
+    assert_eq!(latest["request"]["model"], "test-model");
+    assert_eq!(latest["request"]["backend"], "default");
+    assert_eq!(report["models"][0]["mean_score"], 0.75);
+}
+
+#[tokio::test]
+async fn keeps_requests_apart_when_clients_reuse_ids() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let config = r#"
+[[auth.virtual_keys]]
+key = "vk-acme"
+name = "acme-app"
+tenant = "acme"
+
+[[auth.virtual_keys]]
+key = "vk-globex"
+name = "globex-app"
+tenant = "globex"
+"#;
+    let adapter = spawn_adapter(&upstream, &format!("{}{}", ADMIN, config)).await;
+    let client = reqwest::Client::new();
+    let chat = |key: &'static str, model: &'static str| {
+        client
+            .post(format!("{}{}", adapter, CHAT_PATH))
+            .bearer_auth(key)
+            .header("x-request-id", "req-1")
+            .json(&json!({ "model": model, "messages": [] }))
+            .send()
+    };
+    let rate = |key: &'static str| {
+        client
+            .post(format!("{}/v1/feedback", adapter))
+            .bearer_auth(key)
+            .json(&json!({ "request_id": "req-1", "rating": "up" }))
+            .send()
+    };
+
+    chat("vk-acme", "test-model").await.unwrap();
+    chat("vk-globex", "test-model").await.unwrap();
+    chat("vk-acme", "other-model").await.unwrap();
+    assert_eq!(rate("vk-globex").await.unwrap().status(), 201);
+    assert_eq!(rate("vk-acme").await.unwrap().status(), 201);
+
+    let report: Value = client
+        .get(format!("{}/admin/feedback", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["recent"][0]["request_id"], "req-1");
+    assert_eq!(report["recent"][0]["request"]["tenant"], "acme");
+    assert_eq!(report["recent"][0]["request"]["model"], "other-model");
+    assert_eq!(report["recent"][1]["request"]["tenant"], "globex");
+}
+
+#[tokio::test]
+async fn reaps_idle_streams_and_reports_runtime() {
+    let upstream = MockUpstream::start().await;
+    let chunks: Vec<String> = (0..10).map(|i| chunk(&format!("w{} ", i))).collect();
+    upstream.push(Reply::sse(&chunks).chunk_delay(Duration::from_secs(1)));
+    let adapter = spawn_adapter(&upstream, &format!("{}[pool]\nstream_idle_secs = 0\n", ADMIN)).await;
+    let client = reqwest::Client::new();
+
+    let mut stream = client
+        .post(format!("{}{}", adapter, CHAT_PATH))
+        .json(&json!({ "model": "test-model", "messages": [], "stream": true }))
+        .send()
+        .await
+        .unwrap();
+    assert!(stream.chunk().await.unwrap().is_some());
+
+    let triggered = client
+        .post(format!("{}/admin/jobs/idle-stream-reaper/run", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(triggered.status(), 202);
+    let mut rest = String::new();
+    while let Some(bytes) = stream.chunk().await.unwrap() {
+        rest.push_str(&String::from_utf8_lossy(&bytes));
+    }
+    assert!(rest.contains("stream_terminated"));
+
+    let runtime: Value = client
+        .get(format!("{}/admin/runtime", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert!(runtime["tokio"]["workers"].as_u64().unwrap() > 0);
+    assert_eq!(runtime["pool"]["idle_timeout_secs"], 90);
+    assert_eq!(runtime["pool"]["reaped_streams"], 1);
+    assert_eq!(runtime["pool"]["active_streams"], 0);
+}
+
+#[cfg(not(feature = "profiling"))]
+#[tokio::test]
+async fn profiling_endpoints_need_the_feature() {
+    let upstream = MockUpstream::start().await;
+    let adapter = spawn_adapter(&upstream, ADMIN).await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/admin/profile/cpu?seconds=1", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 501);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "profiling_unavailable");
+}
+
+#[tokio::test]
+async fn routes_to_the_fastest_region_unless_pinned() {
+    let eu = MockUpstream::start().await;
+    eu.always(Reply::json(200, completion("eu")).delayed(Duration::from_millis(300)));
+    let us = MockUpstream::start().await;
+    us.always(Reply::json(200, completion("us")));
+    let config = format!(
+        r#"{}
+[regions.pins]
+acme = "eu-west"
+
+[auth.trusted_header]
+trusted_proxies = ["127.0.0.0/8", "::1"]
+
+[[auth.trusted_header.tenants]]
+identity = "@acme.test"
+tenant = "acme"
+
+[[backends]]
+name = "eu"
+url = "{}/v1/chat/completions"
+key = "k"
+models = ["test-model"]
+region = "eu-west"
+
+[[backends]]
+name = "us"
+url = "{}/v1/chat/completions"
+key = "k"
+models = ["test-model"]
+region = "us-east"
+"#,
+        ADMIN, eu.base_url, us.base_url
+    );
+    let adapter = spawn_adapter(&us, &config).await;
+    let client = reqwest::Client::new();
+    let answer = |email: Option<&'static str>| {
+        let mut request = client
+            .post(format!("{}{}", adapter, CHAT_PATH))
+            .bearer_auth("client-key")
+            .json(&json!({ "model": "test-model", "messages": [] }));
+        if let Some(email) = email {
+            request = request.header("x-auth-request-email", email);
+        }
+        async move {
+            let completion: Value = request.send().await.unwrap().json().await.unwrap();
+            completion["choices"][0]["message"]["content"].as_str().unwrap().to_string()
+        }
+    };
+
+    let triggered = client
+        .post(format!("{}/admin/jobs/region-probe/run", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(triggered.status(), 202);
+    tokio::time::sleep(Duration::from_millis(600)).await;
+
+    assert_eq!(answer(None).await, "us");
+    assert_eq!(answer(Some("alice@acme.test")).await, "eu");
+
+    let backends: Value = client
+        .get(format!("{}/admin/backends", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let eu_backend = backends["backends"].as_array().unwrap().iter().find(|b| b["name"] == "eu").unwrap();
+    assert_eq!(eu_backend["region"], "eu-west");
+    assert_eq!(eu_backend["healthy"], true);
+    assert!(eu_backend["rtt_ms"].as_u64().unwrap() >= 300);
+
+    // A pinned region cooling down after a failure is passed over.
+    eu.push(Reply::json(503, json!({ "error": { "message": "overloaded" } })));
+    client
+        .post(format!("{}{}", adapter, CHAT_PATH))
+        .bearer_auth("client-key")
+        .header("x-auth-request-email", "alice@acme.test")
+        .json(&json!({ "model": "test-model", "messages": [] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(answer(Some("alice@acme.test")).await, "us");
+}
+
+#[tokio::test]
+async fn reports_token_heatmap_of_prompt_sections() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, &format!("{}[heatmap]\nenabled = true\n", ADMIN)).await;
+    let client = reqwest::Client::new();
+    let system = "You are a careful translator who preserves formatting, tone and terminology at all times.\n\nBe brief.";
+    for text in ["Hello", "Good morning"] {
+        let body = json!({
+            "model": "test-model",
+            "messages": [{ "role": "system", "content": system }, { "role": "user", "content": text }]
+        });
+        assert_eq!(common::post_chat(&adapter, body).await.status(), 200);
+    }
+
+    let triggered = client
+        .post(format!("{}/admin/jobs/token-heatmap/run", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(triggered.status(), 202);
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let report: Value = client
+        .get(format!("{}/admin/heatmap", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["pending"], 0);
+    let sections = report["sections"].as_array().unwrap();
+    assert_eq!(sections.len(), 2);
+    assert!(sections[0]["preview"].as_str().unwrap().starts_with("You are a careful translator"));
+    assert_eq!(sections[0]["uses"], 2);
+    assert_eq!(sections[0]["templates"]["none"], 2);
+    assert_eq!(sections[1]["preview"], "Be brief.");
+    assert!(sections[0]["total_tokens"].as_u64() > sections[1]["total_tokens"].as_u64());
+    let totals = &report["templates"]["none"];
+    assert_eq!(totals["requests"], 2);
+    assert_eq!(totals["prompt_tokens"], 10);
+    assert_eq!(totals["completion_tokens"], 4);
+}
+
+#[tokio::test]
+async fn exposes_prometheus_metrics() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("ok")));
+    upstream.push(Reply::sse(&[chunk("Hel"), chunk("lo"), "[DONE]".to_string()]));
+    let adapter = spawn_adapter(&upstream, "").await;
+    let body = json!({ "model": "test-model", "messages": [{ "role": "user", "content": "Hi" }] });
+    assert_eq!(common::post_chat(&adapter, body).await.status(), 200);
+    let body = json!({ "model": "test-model", "messages": [], "stream": true });
+    let streamed = common::post_chat(&adapter, body).await;
+    assert_eq!(streamed.status(), 200);
+    streamed.text().await.unwrap();
+
+    let response = reqwest::get(format!("{}/metrics", adapter)).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/plain"));
+    let metrics = response.text().await.unwrap();
+    assert!(metrics.contains(&format!(
+        "llmta_requests_total{{route=\"{}\",model=\"test-model\",status=\"200\"}} 2",
+        CHAT_PATH
+    )));
+    assert!(metrics.contains("# TYPE llmta_upstream_latency_seconds histogram"));
+    assert!(metrics.contains("llmta_upstream_latency_seconds_count{backend=\"default\",model=\"test-model\"} 2"));
+    assert!(metrics.contains("llmta_upstream_responses_total{backend=\"default\",status=\"200\"} 2"));
+    // 5 reported for the completion, 3 counted for the stream's empty prompt.
+    assert!(metrics.contains("llmta_tokens_total{model=\"test-model\",kind=\"prompt\"} 8"));
+    assert!(metrics.contains("llmta_streamed_tokens_total{model=\"test-model\"} 2"));
+}
+
+#[tokio::test]
+async fn retries_webhooks_and_dead_letters_undeliverable_ones() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let receiver = MockUpstream::start().await;
+    receiver.push(Reply::json(503, json!({})));
+    receiver.always(Reply::json(200, json!({})));
+    let config = format!(
+        "{}[webhooks]\nmax_attempts = 2\ninitial_backoff_ms = 0\n\
+         [[webhooks.endpoints]]\nname = \"billing\"\nurl = \"{}/hooks\"\nevents = [\"usage.*\"]\nsecret = \"hook-secret\"\n\
+         [[webhooks.endpoints]]\nname = \"gone\"\nurl = \"http://127.0.0.1:1/hooks\"\nevents = [\"usage.request\"]\n",
+        ADMIN, receiver.base_url
+    );
+    let adapter = spawn_adapter(&upstream, &config).await;
+    let client = reqwest::Client::new();
+
+    let body = json!({ "model": "test-model", "messages": [{ "role": "user", "content": "Hi" }] });
+    assert_eq!(common::post_chat(&adapter, body).await.status(), 200);
+    // The first attempts go out right away; the job retries what failed.
+    tokio::time::sleep(Duration::from_millis(200)).await;
+    let triggered = client
+        .post(format!("{}/admin/jobs/webhook-delivery/run", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(triggered.status(), 202);
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let deliveries = receiver.requests();
+    assert_eq!(deliveries.len(), 2);
+    assert!(deliveries.iter().all(|d| d.path == "/hooks"));
+    assert_eq!(deliveries[0].body, deliveries[1].body);
+    assert_eq!(deliveries[1].headers["x-llmta-event"], "usage.request");
+    assert_eq!(deliveries[1].headers["x-llmta-webhook-signature"].len(), 64);
+    let event = &deliveries[1].body;
+    assert_eq!(event["event"], "usage.request");
+    assert_eq!(event["data"]["model"], "test-model");
+    assert_eq!(event["data"]["usage"]["prompt_tokens"], 5);
+
+    let report: Value = client
+        .get(format!("{}/admin/webhooks", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["pending"], 0);
+    let dead = report["dead_letters"].as_array().unwrap();
+    assert_eq!(dead.len(), 1);
+    assert_eq!(dead[0]["endpoint"], "gone");
+    assert_eq!(dead[0]["attempts"], 2);
+    assert_eq!(dead[0]["payload"], *event);
+
+    let retry = |id: &Value| {
+        client
+            .post(format!("{}/admin/webhooks/{}/retry", adapter, id))
+            .bearer_auth("admin-secret")
+            .send()
+    };
+    assert_eq!(retry(&dead[0]["id"]).await.unwrap().status(), 202);
+    assert_eq!(retry(&json!(9999)).await.unwrap().status(), 404);
+}
+
+#[tokio::test]
+async fn flags_and_retires_deprecated_models() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("Hallo")));
+    let config = format!(
+        "{}
+[[deprecations]]
+models = [\"old-model\"]
+since = \"2024-01-01\"
+sunset = \"2999-12-31\"
+replacement = \"new-model\"
+link = \"https://example.com/migrate\"
+
+[[deprecations]]
+models = [\"ancient-*\"]
+sunset = \"2020-06-30\"
+replacement = \"new-model\"
+after_sunset = \"reject\"
+",
+        ADMIN
+    );
+    let adapter = spawn_adapter(&upstream, &config).await;
+    let client = reqwest::Client::new();
+    let chat = |model: &str| {
+        client
+            .post(format!("{}{}", adapter, CHAT_PATH))
+            .bearer_auth("client-key")
+            .json(&json!({ "model": model, "messages": [{ "role": "user", "content": "Hi" }] }))
+            .send()
+    };
+
+    let response = chat("old-model").await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["deprecation"], "@1704067200");
+    assert_eq!(response.headers()["sunset"], "Tue, 31 Dec 2999 00:00:00 GMT");
+    assert_eq!(response.headers()["link"], "<https://example.com/migrate>; rel=\"deprecation\"");
+    assert_eq!(response.headers()["x-llmta-replacement-model"], "new-model");
+    chat("old-model").await.unwrap();
+
+    let response = chat("ancient-model").await.unwrap();
+    assert_eq!(response.status(), 410);
+    assert_eq!(response.headers()["sunset"], "Tue, 30 Jun 2020 00:00:00 GMT");
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["error"]["type"], "model_sunset");
+    assert!(error["error"]["message"].as_str().unwrap().contains("use new-model instead"));
+
+    let response = chat("test-model").await.unwrap();
+    assert!(response.headers().get("deprecation").is_none());
+    assert_eq!(upstream.requests().len(), 3);
+
+    let report: Value = client
+        .get(format!("{}/admin/deprecations", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["deprecations"][0]["status"], "deprecated");
+    assert_eq!(report["deprecations"][1]["status"], "retired");
+    let uses = report["uses"].as_array().unwrap();
+    assert_eq!(uses[0]["model"], "old-model");
+    assert_eq!(uses[0]["requests"], 2);
+    assert_eq!(uses[1]["model"], "ancient-model");
+    assert_eq!(uses[1]["rejected"], 1);
+}
@@ -0,0 +1,207 @@
+mod common;
+
+use common::{completion, spawn_adapter, MockUpstream, Reply, CHAT_PATH};
+use serde_json::json;
+
+const TRUSTED: &str = r#"
+[auth.trusted_header]
+trusted_proxies = ["127.0.0.0/8", "::1"]
+required = true
+
+[[auth.trusted_header.tenants]]
+identity = "@acme.test"
+tenant = "acme"
+requests_per_minute = 1
+"#;
+
+async fn post_as(adapter: &str, email: Option<&str>) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(format!("{}{}", adapter, CHAT_PATH))
+        .json(&json!({ "model": "test-model", "messages": [] }));
+    if let Some(email) = email {
+        request = request.header("x-auth-request-email", email);
+    }
+    request.send().await.unwrap()
+}
+
+#[tokio::test]
+async fn requires_identity_header_when_configured() {
+    let upstream = MockUpstream::start().await;
+    let adapter = spawn_adapter(&upstream, TRUSTED).await;
+
+    assert_eq!(post_as(&adapter, None).await.status(), 401);
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn maps_identities_to_tenant_limits() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, TRUSTED).await;
+
+    assert_eq!(post_as(&adapter, Some("alice@acme.test")).await.status(), 200);
+    // Same tenant, so the shared one-request budget is already spent.
+    assert_eq!(post_as(&adapter, Some("bob@acme.test")).await.status(), 429);
+    // Unmapped identities are their own tenant without a limit.
+    assert_eq!(post_as(&adapter, Some("carol@other.test")).await.status(), 200);
+}
+
+#[tokio::test]
+async fn maps_identity_domains_case_insensitively() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, TRUSTED).await;
+
+    assert_eq!(post_as(&adapter, Some("alice@acme.test")).await.status(), 200);
+    assert_eq!(post_as(&adapter, Some("Bob@ACME.Test")).await.status(), 429);
+}
+
+const SIGNED: &str = r#"
+[auth.hmac]
+[[auth.hmac.clients]]
+id = "billing"
+secret = "s3cret"
+"#;
+
+fn signed_request(adapter: &str, body: &str, nonce: &str, secret: &str) -> reqwest::RequestBuilder {
+    use hmac::{Hmac, Mac};
+    let timestamp = std::time::SystemTime::now()
+        .duration_since(std::time::UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+        .to_string();
+    let mut mac = Hmac::<sha2::Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(format!("{}.{}.{}", timestamp, nonce, body).as_bytes());
+
+    reqwest::Client::new()
+        .post(format!("{}{}", adapter, CHAT_PATH))
+        .header("content-type", "application/json")
+        .header("x-llmta-client", "billing")
+        .header("x-llmta-timestamp", timestamp)
+        .header("x-llmta-nonce", nonce)
+        .header("x-llmta-signature", hex::encode(mac.finalize().into_bytes()))
+        .body(body.to_string())
+}
+
+#[tokio::test]
+async fn accepts_valid_signatures_once() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, SIGNED).await;
+    let body = r#"{"model":"test-model","messages":[]}"#;
+
+    let first = signed_request(&adapter, body, "n-1", "s3cret").send().await.unwrap();
+    assert_eq!(first.status(), 200);
+
+    let replayed = signed_request(&adapter, body, "n-1", "s3cret").send().await.unwrap();
+    assert_eq!(replayed.status(), 401);
+}
+
+#[tokio::test]
+async fn rejects_bad_signatures() {
+    let upstream = MockUpstream::start().await;
+    let adapter = spawn_adapter(&upstream, SIGNED).await;
+    let body = r#"{"model":"test-model","messages":[]}"#;
+
+    let forged = signed_request(&adapter, body, "n-2", "wrong").send().await.unwrap();
+    assert_eq!(forged.status(), 401);
+    assert!(upstream.requests().is_empty());
+}
+
+const VIRTUAL_KEYS: &str = r#"
+[[auth.virtual_keys]]
+key = "vk-team-a"
+name = "team-a"
+models = ["test-*"]
+requests_per_minute = 1
+upstream_key = "team-a-upstream"
+
+[[auth.virtual_keys]]
+key = "vk-team-b"
+name = "team-b"
+models = ["test-model"]
+"#;
+
+async fn post_with_key(adapter: &str, key: Option<&str>, model: &str) -> reqwest::Response {
+    let mut request = reqwest::Client::new()
+        .post(format!("{}{}", adapter, CHAT_PATH))
+        .json(&json!({ "model": model, "messages": [] }));
+    if let Some(key) = key {
+        request = request.bearer_auth(key);
+    }
+    request.send().await.unwrap()
+}
+
+#[tokio::test]
+async fn enforces_virtual_keys() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, VIRTUAL_KEYS).await;
+
+    assert_eq!(post_with_key(&adapter, None, "test-model").await.status(), 401);
+    assert_eq!(post_with_key(&adapter, Some("vk-unknown"), "test-model").await.status(), 401);
+    assert_eq!(post_with_key(&adapter, Some("vk-team-b"), "other-model").await.status(), 403);
+    assert!(upstream.requests().is_empty());
+
+    assert_eq!(post_with_key(&adapter, Some("vk-team-a"), "test-model").await.status(), 200);
+    assert_eq!(post_with_key(&adapter, Some("vk-team-a"), "test-model").await.status(), 429);
+    assert_eq!(post_with_key(&adapter, Some("vk-team-b"), "test-model").await.status(), 200);
+    let keys: Vec<_> = upstream
+        .requests()
+        .iter()
+        .map(|r| r.headers["authorization"].to_str().unwrap().to_string())
+        .collect();
+    assert_eq!(keys, ["Bearer team-a-upstream", "Bearer upstream-key"]);
+
+    let version = reqwest::get(format!("{}/version", adapter)).await.unwrap();
+    assert_eq!(version.status(), 200);
+}
+
+#[tokio::test]
+async fn applies_overrides_by_virtual_key_name() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, &format!("{}\n[admin]\ntoken = \"admin-secret\"\n", VIRTUAL_KEYS)).await;
+
+    let response = reqwest::Client::new()
+        .put(format!("{}/admin/keys/team-b/overrides", adapter))
+        .bearer_auth("admin-secret")
+        .json(&json!({ "system_prompt": "Answer for team B." }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    assert_eq!(post_with_key(&adapter, Some("vk-team-b"), "test-model").await.status(), 200);
+    assert_eq!(post_with_key(&adapter, Some("vk-team-a"), "test-model").await.status(), 200);
+    let requests = upstream.requests();
+    assert_eq!(requests[0].body["messages"][0]["content"], "Answer for team B.");
+    assert_eq!(requests[1].body["messages"].as_array().unwrap().len(), 0);
+}
+
+#[tokio::test]
+async fn localizes_error_messages_by_accept_language() {
+    let upstream = MockUpstream::start().await;
+    let adapter = spawn_adapter(&upstream, VIRTUAL_KEYS).await;
+    let rejected = |language: &'static str| {
+        reqwest::Client::new()
+            .post(format!("{}{}", adapter, CHAT_PATH))
+            .header("accept-language", language)
+            .json(&json!({ "model": "test-model", "messages": [] }))
+            .send()
+    };
+
+    let german = rejected("fr;q=0.5, de-CH, en;q=0.8").await.unwrap();
+    assert_eq!(german.status(), 401);
+    assert_eq!(german.headers()["content-language"], "de");
+    let error: serde_json::Value = german.json().await.unwrap();
+    assert_eq!(error["error"]["type"], "invalid_api_key");
+    assert_eq!(error["error"]["message"], "Ungültiger oder fehlender API-Schlüssel.");
+    assert!(error["error"]["x_original_message"].as_str().unwrap().starts_with("Requests must carry"));
+
+    let english = rejected("en-US, de;q=0.9").await.unwrap();
+    assert!(english.headers().get("content-language").is_none());
+    let error: serde_json::Value = english.json().await.unwrap();
+    assert!(error["error"]["message"].as_str().unwrap().starts_with("Requests must carry"));
+    assert!(error["error"].get("x_original_message").is_none());
+}
@@ -0,0 +1,105 @@
+use clap::error::ErrorKind;
+use clap::Parser;
+use openai_api_proxy::cli::{self, Cli, Command};
+use openai_api_proxy::service::DaemonOptions;
+use openai_api_proxy::AppConfig;
+use std::path::PathBuf;
+
+fn parse(args: &[&str]) -> Result<Cli, clap::Error> {
+    Cli::try_parse_from(std::iter::once("openai-api-proxy").chain(args.iter().copied()))
+}
+
+#[test]
+fn parses_flags_and_set_overrides() {
+    let cli = parse(&[
+        "--daemon",
+        "--port",
+        "9090",
+        "--model-url=http://llm.internal/v1/chat/completions",
+        "--set",
+        "limits.max_concurrency=32",
+        "--pidfile",
+        "/run/lta.pid",
+    ])
+    .unwrap();
+    assert_eq!(cli.command, None);
+    assert_eq!(
+        cli.config.overrides(),
+        [
+            ("port".to_string(), "9090".to_string()),
+            ("model_url".to_string(), "http://llm.internal/v1/chat/completions".to_string()),
+            ("limits.max_concurrency".to_string(), "32".to_string()),
+        ]
+    );
+    assert_eq!(
+        DaemonOptions::from_cli(&cli.daemon),
+        Some(DaemonOptions { pidfile: Some(PathBuf::from("/run/lta.pid")), log_file: None })
+    );
+
+    let doctor = parse(&["doctor", "--completion", "--port", "9090"]).unwrap();
+    assert_eq!(doctor.command, Some(Command::Doctor { completion: true }));
+    assert_eq!(doctor.config.overrides(), [("port".to_string(), "9090".to_string())]);
+    assert_eq!(DaemonOptions::from_cli(&doctor.daemon), None);
+}
+
+#[test]
+fn rejects_secrets_and_malformed_flags() {
+    let error = parse(&["--set", "model_key=sk-live"]).unwrap_err();
+    assert!(error.to_string().contains("model_key_file"));
+    for secret in [
+        "--set=admin.token=t",
+        "--set=signing.private_key=00",
+        "--set=backends[0].key=sk",
+        "--set=keys.1.key=sk",
+        "--set=auth.virtual_keys[0].upstream_keys.openai=sk",
+        "--set=auth.hmac.clients[2].secret=s",
+        "--set=webhooks.endpoints[0].secret=s",
+    ] {
+        assert!(parse(&[secret]).is_err(), "{}", secret);
+    }
+    assert_eq!(
+        parse(&["--set", "backends[0].key_file=/run/secrets/key"]).unwrap().config.overrides(),
+        [("backends[0].key_file".to_string(), "/run/secrets/key".to_string())]
+    );
+
+    assert!(parse(&["--set", "no-equals"]).is_err());
+    assert!(parse(&["--port"]).is_err());
+    assert!(parse(&["--port", "http"]).is_err());
+    assert!(parse(&["--pidfile", "/run/lta.pid"]).is_err());
+    assert_eq!(parse(&["--bogus"]).unwrap_err().kind(), ErrorKind::UnknownArgument);
+    assert!(parse(&["serve"]).is_err());
+    assert_eq!(parse(&["--help"]).unwrap_err().kind(), ErrorKind::DisplayHelp);
+    assert_eq!(
+        parse(&["--model-key-file", "/run/secrets/key"]).unwrap().config.overrides(),
+        [("model_key_file".to_string(), "/run/secrets/key".to_string())]
+    );
+}
+
+#[test]
+fn reads_nested_secrets_from_files() {
+    assert!(cli::is_secret("Backends[3].KEY"));
+    assert!(!cli::is_secret("backends[3].url"));
+
+    let dir = std::env::temp_dir().join(format!("lta-secrets-{}", std::process::id()));
+    std::fs::create_dir_all(&dir).unwrap();
+    let file = |name: &str, secret: &str| {
+        let path = dir.join(name);
+        std::fs::write(&path, format!("{}\n", secret)).unwrap();
+        path.to_str().unwrap().to_string()
+    };
+    let config = AppConfig::from_toml(&format!(
+        "model_url = \"http://127.0.0.1:1/v1/chat/completions\"\nmodel_key_file = {:?}\n\
+         default_model = \"test-model\"\nport = 0\nhost = \"127.0.0.1\"\n\n\
+         [[backends]]\nname = \"b\"\nurl = \"http://127.0.0.1:2/v1/chat/completions\"\nkey_file = {:?}\n\n\
+         [[auth.hmac.clients]]\nid = \"billing\"\nsecret_file = {:?}\n",
+        file("model", "sk-model"),
+        file("backend", "sk-backend"),
+        file("hmac", "hmac-secret"),
+    ))
+    .unwrap();
+    std::fs::remove_dir_all(&dir).unwrap();
+
+    assert_eq!(config.model_key, "sk-model");
+    assert_eq!(config.backends[0].key, "sk-backend");
+    assert_eq!(config.auth.hmac.unwrap().clients[0].secret, "hmac-secret");
+}
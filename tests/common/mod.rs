@@ -0,0 +1,294 @@
+//! In-process harness: a scriptable mock upstream plus the adapter itself,
+//! both served by axum on ephemeral ports.
+#![allow(dead_code)]
+
+use axum::{
+    body::{Body, Bytes},
+    extract::{Request, State},
+    http::{header, HeaderMap, StatusCode},
+    response::Response,
+    Router,
+};
+use futures::StreamExt;
+use openai_api_proxy::{router, AppConfig, AppState};
+use serde_json::{json, Value};
+use std::collections::VecDeque;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::net::TcpListener;
+
+pub const CHAT_PATH: &str = "/v1beta/openai/chat/completions";
+
+/// One scripted upstream response.
+#[derive(Clone)]
+pub struct Reply {
+    status: u16,
+    content_type: &'static str,
+    headers: Vec<(&'static str, String)>,
+    chunks: Vec<Bytes>,
+    delay: Duration,
+    chunk_delay: Duration,
+    abort: bool,
+}
+
+impl Reply {
+    pub fn json(status: u16, body: Value) -> Self {
+        Reply {
+            status,
+            content_type: "application/json",
+            headers: Vec::new(),
+            chunks: vec![Bytes::from(body.to_string())],
+            delay: Duration::ZERO,
+            chunk_delay: Duration::ZERO,
+            abort: false,
+        }
+    }
+
+    /// An SSE response emitting one `data:` event per payload.
+    pub fn sse<S: AsRef<str>>(events: &[S]) -> Self {
+        Reply {
+            status: 200,
+            content_type: "text/event-stream",
+            headers: Vec::new(),
+            chunks: events
+                .iter()
+                .map(|e| Bytes::from(format!("data: {}\n\n", e.as_ref())))
+                .collect(),
+            delay: Duration::ZERO,
+            chunk_delay: Duration::ZERO,
+            abort: false,
+        }
+    }
+
+    /// Raw body chunks, for malformed or provider-specific framing.
+    pub fn raw(status: u16, content_type: &'static str, chunks: Vec<String>) -> Self {
+        Reply {
+            status,
+            content_type,
+            headers: Vec::new(),
+            chunks: chunks.into_iter().map(Bytes::from).collect(),
+            delay: Duration::ZERO,
+            chunk_delay: Duration::ZERO,
+            abort: false,
+        }
+    }
+
+    /// Binary body chunks, such as AWS event-stream frames.
+    pub fn binary(status: u16, content_type: &'static str, chunks: Vec<Vec<u8>>) -> Self {
+        Reply {
+            status,
+            content_type,
+            headers: Vec::new(),
+            chunks: chunks.into_iter().map(Bytes::from).collect(),
+            delay: Duration::ZERO,
+            chunk_delay: Duration::ZERO,
+            abort: false,
+        }
+    }
+
+    pub fn header(mut self, name: &'static str, value: impl Into<String>) -> Self {
+        self.headers.push((name, value.into()));
+        self
+    }
+
+    /// Waits before sending the response headers.
+    pub fn delayed(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+
+    /// Waits before each body chunk.
+    pub fn chunk_delay(mut self, delay: Duration) -> Self {
+        self.chunk_delay = delay;
+        self
+    }
+
+    /// Drops the connection after the scripted chunks instead of ending cleanly.
+    pub fn aborted(mut self) -> Self {
+        self.abort = true;
+        self
+    }
+}
+
+/// A request as seen by the mock upstream.
+#[derive(Clone, Debug)]
+pub struct Recorded {
+    pub path: String,
+    pub headers: HeaderMap,
+    pub body: Value,
+}
+
+#[derive(Clone, Default)]
+struct MockState {
+    replies: Arc<Mutex<VecDeque<Reply>>>,
+    fallback: Arc<Mutex<Option<Reply>>>,
+    requests: Arc<Mutex<Vec<Recorded>>>,
+}
+
+pub struct MockUpstream {
+    pub base_url: String,
+    state: MockState,
+}
+
+impl MockUpstream {
+    pub async fn start() -> Self {
+        let state = MockState::default();
+        let app = Router::new().fallback(respond).with_state(state.clone());
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let base_url = format!("http://{}", listener.local_addr().unwrap());
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        MockUpstream { base_url, state }
+    }
+
+    /// An upstream address nothing listens on.
+    pub fn unreachable() -> Self {
+        MockUpstream {
+            base_url: "http://127.0.0.1:1".to_string(),
+            state: MockState::default(),
+        }
+    }
+
+    /// Queues a reply; replies are served in order, one per request.
+    pub fn push(&self, reply: Reply) -> &Self {
+        self.state.replies.lock().unwrap().push_back(reply);
+        self
+    }
+
+    /// Serves `reply` whenever the queue is empty.
+    pub fn always(&self, reply: Reply) -> &Self {
+        *self.state.fallback.lock().unwrap() = Some(reply);
+        self
+    }
+
+    pub fn requests(&self) -> Vec<Recorded> {
+        self.state.requests.lock().unwrap().clone()
+    }
+}
+
+async fn respond(State(state): State<MockState>, request: Request) -> Response<Body> {
+    let (parts, body) = request.into_parts();
+    let bytes = axum::body::to_bytes(body, usize::MAX).await.unwrap_or_default();
+
+    {
+        let mut requests = state.requests.lock().unwrap();
+        // Bounded so benchmarks can hammer an `always` reply indefinitely.
+        if requests.len() < 10_000 {
+            requests.push(Recorded {
+                path: parts.uri.to_string(),
+                headers: parts.headers,
+                body: serde_json::from_slice(&bytes).unwrap_or(Value::Null),
+            });
+        }
+    }
+
+    let queued = state.replies.lock().unwrap().pop_front();
+    let reply = queued.or_else(|| state.fallback.lock().unwrap().clone());
+    let Some(reply) = reply else {
+        return Response::builder()
+            .status(StatusCode::INTERNAL_SERVER_ERROR)
+            .body(Body::from("no scripted reply"))
+            .unwrap();
+    };
+
+    tokio::time::sleep(reply.delay).await;
+
+    let chunk_delay = reply.chunk_delay;
+    let chunks = futures::stream::iter(reply.chunks).then(move |chunk| async move {
+        tokio::time::sleep(chunk_delay).await;
+        Ok::<_, std::io::Error>(chunk)
+    });
+    let abort = futures::stream::iter(reply.abort.then(|| {
+        Err(std::io::Error::other("mock upstream aborted"))
+    }));
+
+    let mut builder = Response::builder()
+        .status(reply.status)
+        .header(header::CONTENT_TYPE, reply.content_type);
+    for (name, value) in &reply.headers {
+        builder = builder.header(*name, value.as_str());
+    }
+    builder.body(Body::from_stream(chunks.chain(abort))).unwrap()
+}
+
+/// Starts the adapter against `upstream`. `extra` is appended to the base
+/// config, so top-level keys must precede any tables it declares.
+pub async fn spawn_adapter(upstream: &MockUpstream, extra: &str) -> String {
+    let source = format!(
+        r#"
+model_url = "{}/v1/chat/completions"
+model_key = "upstream-key"
+default_model = "test-model"
+port = 0
+host = "127.0.0.1"
+{}
+"#,
+        upstream.base_url, extra
+    );
+    let config = AppConfig::from_toml(&source).expect("valid test config");
+
+    let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    let app = router(Arc::new(AppState::new(config).expect("valid test state")))
+        .into_make_service_with_connect_info::<SocketAddr>();
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+    base_url
+}
+
+pub async fn post_chat(base_url: &str, body: Value) -> reqwest::Response {
+    reqwest::Client::new()
+        .post(format!("{}{}", base_url, CHAT_PATH))
+        .bearer_auth("client-key")
+        .json(&body)
+        .send()
+        .await
+        .unwrap()
+}
+
+/// A `chat.completion.chunk` carrying a content delta.
+pub fn chunk(content: &str) -> String {
+    json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "created": 1700000000,
+        "model": "test-model",
+        "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }]
+    })
+    .to_string()
+}
+
+pub fn completion(content: &str) -> Value {
+    json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion",
+        "created": 1700000000,
+        "model": "test-model",
+        "choices": [{
+            "index": 0,
+            "message": { "role": "assistant", "content": content },
+            "finish_reason": "stop"
+        }],
+        "usage": { "prompt_tokens": 5, "completion_tokens": 2, "total_tokens": 7 }
+    })
+}
+
+/// Splits an SSE body into `(field, value)` lines, one `Vec` per event.
+pub fn sse_events(body: &str) -> Vec<Vec<(String, String)>> {
+    body.split("\n\n")
+        .filter(|block| !block.trim().is_empty())
+        .map(|block| {
+            block
+                .lines()
+                .filter_map(|line| line.split_once(": "))
+                .map(|(field, value)| (field.to_string(), value.to_string()))
+                .collect()
+        })
+        .collect()
+}
+
+pub fn field<'a>(event: &'a [(String, String)], name: &str) -> Option<&'a str> {
+    event
+        .iter()
+        .find(|(field, _)| field == name)
+        .map(|(_, value)| value.as_str())
+}
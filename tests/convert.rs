@@ -0,0 +1,75 @@
+use openai_api_proxy::convert::{self, Translator};
+use serde_json::json;
+
+#[test]
+fn translates_requests_to_anthropic() {
+    let request = convert::openai_to_anthropic(&json!({
+        "model": "claude-sonnet-4",
+        "messages": [
+            { "role": "system", "content": "Be brief." },
+            { "role": "user", "content": "Hi" },
+        ],
+        "max_tokens": 64,
+        "stop": "END",
+    }))
+    .unwrap();
+    assert_eq!(request["system"], "Be brief.");
+    assert_eq!(request["max_tokens"], 64);
+    assert_eq!(request["stop_sequences"], json!(["END"]));
+    assert_eq!(request["messages"][0]["role"], "user");
+
+    assert!(convert::openai_to_anthropic(&json!({ "model": "m" })).is_err());
+    assert!(Translator::gemini().request(&json!("not an object")).is_err());
+}
+
+#[test]
+fn translates_anthropic_responses_to_openai() {
+    let completion = convert::anthropic_to_openai(
+        &json!({
+            "id": "msg_1",
+            "type": "message",
+            "model": "claude-sonnet-4",
+            "content": [{ "type": "text", "text": "Hello" }],
+            "stop_reason": "max_tokens",
+            "usage": { "input_tokens": 3, "output_tokens": 1 },
+        }),
+        "claude-sonnet-4",
+    );
+    assert_eq!(completion["object"], "chat.completion");
+    assert_eq!(completion["choices"][0]["message"]["content"], "Hello");
+    assert_eq!(completion["choices"][0]["finish_reason"], "length");
+    assert_eq!(completion["usage"]["total_tokens"], 4);
+}
+
+#[test]
+fn translates_anthropic_streams_however_split() {
+    let events = [
+        json!({ "type": "message_start", "message": { "id": "msg_1", "model": "claude", "usage": { "input_tokens": 3 } } }),
+        json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": "Hel" } }),
+        json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": "lo" } }),
+        json!({ "type": "message_delta", "delta": { "stop_reason": "end_turn" }, "usage": { "output_tokens": 2 } }),
+        json!({ "type": "message_stop" }),
+    ];
+    let body: String = events
+        .iter()
+        .map(|e| format!("event: {}\ndata: {}\n\n", e["type"].as_str().unwrap(), e))
+        .collect();
+
+    let chunks = convert::anthropic_stream_to_openai_chunks(body.as_bytes(), "claude");
+    let text: String = chunks
+        .iter()
+        .filter_map(|c| c["choices"][0]["delta"]["content"].as_str())
+        .collect();
+    assert_eq!(text, "Hello");
+    assert_eq!(chunks.last().unwrap()["choices"][0]["finish_reason"], "stop");
+    assert_eq!(chunks.last().unwrap()["usage"]["prompt_tokens"], 3);
+
+    let mut converter = Translator::anthropic(Default::default()).stream("claude");
+    let mut split = Vec::new();
+    for piece in body.as_bytes().chunks(7) {
+        split.extend(converter.feed(piece));
+    }
+    split.extend(converter.finish());
+    assert_eq!(split.len(), chunks.len() + 1);
+    assert!(split.last().unwrap().is_done());
+}
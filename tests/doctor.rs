@@ -0,0 +1,61 @@
+mod common;
+
+use common::{completion, MockUpstream, Reply};
+use openai_api_proxy::{doctor, AppConfig};
+use serde_json::json;
+
+fn config(upstream: &MockUpstream, extra: &str) -> AppConfig {
+    AppConfig::from_toml(&format!(
+        "model_url = \"{}/v1/chat/completions\"\nmodel_key = \"upstream-key\"\n\
+         default_model = \"test-model\"\nport = 0\nhost = \"127.0.0.1\"\n{}",
+        upstream.base_url, extra
+    ))
+    .unwrap()
+}
+
+#[tokio::test]
+async fn passes_against_a_reachable_backend() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, json!({ "data": [] })));
+    upstream.push(Reply::json(200, completion("pong")));
+
+    assert!(doctor::check(config(&upstream, ""), true).await);
+    let requests = upstream.requests();
+    assert_eq!(requests.len(), 2);
+    assert_eq!(requests[0].path, "/v1/models");
+    assert_eq!(requests[0].headers["authorization"], "Bearer upstream-key");
+    assert_eq!(requests[1].path, "/v1/chat/completions");
+    assert_eq!(requests[1].body["model"], "test-model");
+    assert_eq!(requests[1].body["max_tokens"], 1);
+}
+
+#[tokio::test]
+async fn sends_a_completion_only_when_asked() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, json!({ "data": [] })));
+
+    assert!(doctor::check(config(&upstream, ""), false).await);
+    assert_eq!(upstream.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn fails_on_rejected_credentials_and_unreachable_backends() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(401, json!({ "error": { "message": "bad key" } })));
+    assert!(!doctor::check(config(&upstream, ""), false).await);
+
+    assert!(!doctor::check(config(&MockUpstream::unreachable(), ""), false).await);
+}
+
+#[tokio::test]
+async fn fails_on_invalid_settings_and_tokenizers() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, json!({ "data": [] })));
+
+    let bad_signing = config(&upstream, "[signing]\nprivate_key = \"not-hex\"\n");
+    assert!(!doctor::check(bad_signing, false).await);
+    assert!(upstream.requests().is_empty());
+
+    let bad_tokenizer = config(&upstream, "[[tokenizers]]\nmodels = [\"llama-*\"]\nkind = \"huggingface\"\n");
+    assert!(!doctor::check(bad_tokenizer, false).await);
+}
@@ -0,0 +1,234 @@
+mod common;
+
+use common::{completion, post_chat, spawn_adapter, MockUpstream, Reply};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn warns_before_hard_limit_and_then_rejects() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(
+        &upstream,
+        "[limits]\nrequests_per_minute = 3\nsoft_ratio = 0.6\n",
+    )
+    .await;
+    let body = json!({ "model": "test-model", "messages": [] });
+
+    let first = post_chat(&adapter, body.clone()).await;
+    assert_eq!(first.status(), 200);
+    assert_eq!(first.headers()["x-ratelimit-limit-requests"], "3");
+    assert_eq!(first.headers()["x-ratelimit-remaining-requests"], "2");
+    assert!(first.headers().get("x-ratelimit-warning").is_none());
+
+    let second = post_chat(&adapter, body.clone()).await;
+    assert_eq!(second.headers()["x-ratelimit-remaining-requests"], "1");
+    assert!(second.headers().get("x-ratelimit-warning").is_some());
+
+    post_chat(&adapter, body.clone()).await;
+    let rejected = post_chat(&adapter, body).await;
+    assert_eq!(rejected.status(), 429);
+    assert_eq!(rejected.headers()["x-ratelimit-remaining-requests"], "0");
+    assert!(rejected.headers().get("retry-after").is_some());
+}
+
+#[tokio::test]
+async fn reports_tighter_upstream_limit() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(
+        Reply::json(200, completion("ok"))
+            .header("x-ratelimit-limit-requests", "500")
+            .header("x-ratelimit-remaining-requests", "2"),
+    );
+    upstream.push(
+        Reply::json(200, completion("ok"))
+            .header("x-ratelimit-limit-requests", "500")
+            .header("x-ratelimit-remaining-requests", "400"),
+    );
+    let adapter = spawn_adapter(&upstream, "[limits]\nrequests_per_minute = 100\n").await;
+    let body = json!({ "model": "test-model", "messages": [] });
+
+    let upstream_tighter = post_chat(&adapter, body.clone()).await;
+    assert_eq!(upstream_tighter.headers()["x-ratelimit-limit-requests"], "500");
+    assert_eq!(upstream_tighter.headers()["x-ratelimit-remaining-requests"], "2");
+
+    let adapter_tighter = post_chat(&adapter, body).await;
+    assert_eq!(adapter_tighter.headers()["x-ratelimit-limit-requests"], "100");
+    assert_eq!(adapter_tighter.headers()["x-ratelimit-remaining-requests"], "98");
+}
+
+#[tokio::test]
+async fn reports_tighter_token_budget() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(
+        Reply::json(200, completion("ok"))
+            .header("x-ratelimit-limit-tokens", "90000")
+            .header("x-ratelimit-remaining-tokens", "500"),
+    );
+    upstream.push(
+        Reply::json(200, completion("ok"))
+            .header("x-ratelimit-limit-tokens", "90000")
+            .header("x-ratelimit-remaining-tokens", "80000"),
+    );
+    let adapter = spawn_adapter(&upstream, "[limits]\ntokens_per_minute = 1000\n").await;
+    let body = json!({ "model": "test-model", "messages": [] });
+
+    let upstream_tighter = post_chat(&adapter, body.clone()).await;
+    assert_eq!(upstream_tighter.headers()["x-ratelimit-limit-tokens"], "90000");
+    assert_eq!(upstream_tighter.headers()["x-ratelimit-remaining-tokens"], "500");
+
+    // Two answers of 7 tokens each have been settled.
+    let adapter_tighter = post_chat(&adapter, body).await;
+    assert_eq!(adapter_tighter.headers()["x-ratelimit-limit-tokens"], "1000");
+    let remaining: u64 = adapter_tighter.headers()["x-ratelimit-remaining-tokens"].to_str().unwrap().parse().unwrap();
+    assert!((986..990).contains(&remaining), "{} tokens left", remaining);
+    assert!(adapter_tighter.headers().contains_key("x-ratelimit-reset-tokens"));
+}
+
+#[tokio::test]
+async fn synthesizes_openai_headers_from_provider_headers() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(
+        Reply::json(200, completion("ok"))
+            .header("anthropic-ratelimit-requests-limit", "50")
+            .header("anthropic-ratelimit-requests-remaining", "49")
+            .header("anthropic-ratelimit-tokens-remaining", "39000"),
+    );
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(response.headers()["x-ratelimit-limit-requests"], "50");
+    assert_eq!(response.headers()["x-ratelimit-remaining-requests"], "49");
+    assert_eq!(response.headers()["x-ratelimit-remaining-tokens"], "39000");
+}
+
+#[tokio::test]
+async fn smooths_bursts_to_upstream_rate() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "[limits]\nupstream_rps = 10.0\nupstream_burst = 2\n").await;
+    let body = json!({ "model": "test-model", "messages": [] });
+
+    let started = std::time::Instant::now();
+    let requests = (0..5).map(|_| post_chat(&adapter, body.clone()));
+    let responses = futures::future::join_all(requests).await;
+    assert!(responses.iter().all(|r| r.status() == 200));
+    // Two go out immediately, the other three are spaced 100ms apart.
+    assert!(started.elapsed() >= std::time::Duration::from_millis(280));
+}
+
+#[tokio::test]
+async fn sheds_requests_that_would_outwait_their_deadline() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")).delayed(std::time::Duration::from_millis(400)));
+    let adapter = spawn_adapter(&upstream, "[limits]\nmax_concurrency = 1\n").await;
+    let body = json!({ "model": "test-model", "messages": [] });
+
+    let first = {
+        let (adapter, body) = (adapter.clone(), body.clone());
+        tokio::spawn(async move { post_chat(&adapter, body).await })
+    };
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    let shed = reqwest::Client::new()
+        .post(format!("{}{}", adapter, common::CHAT_PATH))
+        .bearer_auth("client-key")
+        .header("x-llmta-max-queue-wait-ms", "100")
+        .json(&body)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(shed.status(), 429);
+    assert!(shed.headers().get("retry-after").is_some());
+
+    let first = first.await.unwrap();
+    assert_eq!(first.status(), 200);
+    assert_eq!(first.headers()["x-llmta-queue-wait-ms"], "0");
+}
+
+#[tokio::test]
+async fn enforces_per_tenant_connection_quota() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")).delayed(std::time::Duration::from_millis(300)));
+    let adapter = spawn_adapter(&upstream, "[quotas]\nmax_connections = 1\n").await;
+    let body = json!({ "model": "test-model", "messages": [] });
+
+    let first = {
+        let (adapter, body) = (adapter.clone(), body.clone());
+        tokio::spawn(async move { post_chat(&adapter, body).await })
+    };
+    tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+
+    let rejected = post_chat(&adapter, body.clone()).await;
+    assert_eq!(rejected.status(), 429);
+    let error: serde_json::Value = rejected.json().await.unwrap();
+    assert_eq!(error["error"]["type"], "tenant_quota_exceeded");
+
+    let first = first.await.unwrap();
+    assert_eq!(first.status(), 200);
+    first.text().await.unwrap();
+    // The slot is released once the first response has been delivered.
+    assert_eq!(post_chat(&adapter, body).await.status(), 200);
+}
+
+#[tokio::test]
+async fn usage_report_withholds_totals_below_tenant_threshold() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "[usage.privacy]\nmin_tenants = 2\n").await;
+
+    let body = json!({ "model": "test-model", "messages": [] });
+    assert_eq!(post_chat(&adapter, body.clone()).await.status(), 200);
+    assert_eq!(post_chat(&adapter, body).await.status(), 200);
+
+    let report: Value = reqwest::Client::new()
+        .get(format!("{}/v1/usage", adapter))
+        .bearer_auth("client-key")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["usage"][0]["requests"], 2);
+    assert_eq!(report["usage"][0]["prompt_tokens"], 10);
+    assert_eq!(report["aggregate"][0]["withheld"], true);
+    assert!(report["aggregate"][0].get("requests").is_none());
+}
+
+#[tokio::test]
+async fn enforces_token_and_model_budgets() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "[limits]\ntokens_per_minute = 100\n").await;
+    let body = json!({ "model": "test-model", "messages": [], "max_tokens": 60 });
+
+    // Each is charged 63 tokens up front and settled at the 7 it used.
+    assert_eq!(post_chat(&adapter, body.clone()).await.status(), 200);
+    assert_eq!(post_chat(&adapter, body.clone()).await.status(), 200);
+    let mut heavy = completion("long");
+    heavy["usage"] = json!({ "prompt_tokens": 3, "completion_tokens": 87, "total_tokens": 90 });
+    upstream.push(Reply::json(200, heavy));
+    assert_eq!(post_chat(&adapter, body.clone()).await.status(), 200);
+    // 100 - 7 - 7 - 90 leaves 4 owing, so 67 tokens must refill first,
+    // less what refilled while the requests ran.
+    let rejected = post_chat(&adapter, body).await;
+    assert_eq!(rejected.status(), 429);
+    let retry_after: u64 = rejected.headers()["retry-after"].to_str().unwrap().parse().unwrap();
+    assert!((38..=41).contains(&retry_after), "retry after {}s", retry_after);
+    let error: Value = rejected.json().await.unwrap();
+    assert!(error["error"]["message"].as_str().unwrap().contains("100 tokens per minute"));
+    assert_eq!(upstream.requests().len(), 3);
+
+    let adapter = spawn_adapter(
+        &upstream,
+        "[[limits.models]]\nmodels = [\"test-*\"]\nrequests_per_minute = 2\n",
+    )
+    .await;
+    let body = json!({ "model": "test-model", "messages": [] });
+    assert_eq!(post_chat(&adapter, body.clone()).await.status(), 200);
+    assert_eq!(post_chat(&adapter, body.clone()).await.status(), 200);
+    let rejected = post_chat(&adapter, body).await;
+    assert_eq!(rejected.status(), 429);
+    assert!(rejected.headers().contains_key("retry-after"));
+    assert_eq!(upstream.requests().len(), 5);
+}
@@ -0,0 +1,105 @@
+mod common;
+
+use common::{completion, post_chat, spawn_adapter, MockUpstream, Reply};
+use serde_json::json;
+
+#[tokio::test]
+async fn strict_flavor_merges_same_role_messages() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "flavor = \"strict\"\n").await;
+
+    let response = post_chat(
+        &adapter,
+        json!({
+            "model": "test-model",
+            "messages": [
+                { "role": "system", "content": "Translate to German." },
+                { "role": "assistant", "content": "Ready." },
+                { "role": "user", "content": "First paragraph." },
+                { "role": "user", "content": "Second paragraph." }
+            ]
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+
+    let messages = upstream.requests()[0].body["messages"].clone();
+    let roles: Vec<&str> = messages
+        .as_array()
+        .unwrap()
+        .iter()
+        .map(|m| m["role"].as_str().unwrap())
+        .collect();
+    assert_eq!(roles, ["system", "user", "assistant", "user"]);
+    assert_eq!(messages[3]["content"], "First paragraph.\n\nSecond paragraph.");
+}
+
+#[tokio::test]
+async fn openai_flavor_forwards_messages_unchanged() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let messages = json!([
+        { "role": "user", "content": "One." },
+        { "role": "user", "content": "Two." }
+    ]);
+    post_chat(&adapter, json!({ "model": "test-model", "messages": messages })).await;
+    assert_eq!(upstream.requests()[0].body["messages"], messages);
+}
+
+#[tokio::test]
+async fn strict_flavor_fixes_null_and_empty_content() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "flavor = \"strict\"\n").await;
+
+    post_chat(
+        &adapter,
+        json!({
+            "model": "test-model",
+            "messages": [
+                { "role": "user", "content": "What's the weather?" },
+                { "role": "assistant", "content": null, "tool_calls": [{
+                    "id": "call_1", "type": "function",
+                    "function": { "name": "weather", "arguments": "{}" }
+                }] },
+                { "role": "tool", "tool_call_id": "call_1", "content": [{ "type": "text", "text": "Sunny" }] },
+                { "role": "user", "content": "" }
+            ]
+        }),
+    )
+    .await;
+
+    let messages = upstream.requests()[0].body["messages"].clone();
+    assert_eq!(messages.as_array().unwrap().len(), 3);
+    assert_eq!(messages[1]["content"], "");
+    assert_eq!(messages[2]["content"], "Sunny");
+}
+
+#[tokio::test]
+async fn strict_flavor_maps_developer_role_and_merges_system_prompts() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "flavor = \"strict\"\n").await;
+
+    post_chat(
+        &adapter,
+        json!({
+            "model": "test-model",
+            "messages": [
+                { "role": "developer", "content": "Translate to Spanish." },
+                { "role": "user", "content": "Good morning" },
+                { "role": "system", "content": "Keep it formal." }
+            ]
+        }),
+    )
+    .await;
+
+    let messages = upstream.requests()[0].body["messages"].clone();
+    assert_eq!(messages.as_array().unwrap().len(), 2);
+    assert_eq!(messages[0]["role"], "system");
+    assert_eq!(messages[0]["content"], "Translate to Spanish.\n\nKeep it formal.");
+    assert_eq!(messages[1]["role"], "user");
+}
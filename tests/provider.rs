@@ -0,0 +1,86 @@
+use openai_api_proxy::anthropic::{Anthropic, AnthropicConfig};
+use openai_api_proxy::provider::{self, ErrorClass, OpenAiCompatible, Provider};
+use reqwest::header::HeaderMap;
+use serde_json::json;
+use std::time::Duration;
+
+fn classify(status: u16, body: serde_json::Value) -> provider::ProviderError {
+    provider::classify(status, &HeaderMap::new(), body.to_string().as_bytes())
+}
+
+#[test]
+fn retries_transient_statuses() {
+    for status in [408, 429, 500, 502, 503, 504] {
+        let error = classify(status, json!({ "error": { "message": "try later" } }));
+        assert_eq!(error.class, ErrorClass::Retryable, "status {}", status);
+        assert!(error.is_retryable());
+        assert_eq!(error.status.as_u16(), status);
+    }
+}
+
+#[test]
+fn does_not_retry_client_errors() {
+    for status in [400, 401, 403, 404, 422] {
+        let error = classify(status, json!({ "error": { "type": "invalid_request_error", "message": "bad" } }));
+        assert_eq!(error.class, ErrorClass::NonRetryable, "status {}", status);
+        assert!(!error.is_retryable());
+        assert_eq!(error.error_type, "invalid_request_error");
+        assert_eq!(error.message, "bad");
+    }
+
+    let refused = classify(400, json!({ "error": { "code": "content_filter", "message": "no" } }));
+    assert_eq!(refused.class, ErrorClass::NonRetryable);
+    assert_eq!(refused.error_type, "content_filter");
+    let refused = classify(500, json!({ "error": { "code": "content_policy_violation", "message": "no" } }));
+    assert_eq!(refused.class, ErrorClass::NonRetryable);
+}
+
+#[test]
+fn treats_other_statuses_as_ambiguous() {
+    for status in [501, 505, 302] {
+        let error = classify(status, json!({}));
+        assert_eq!(error.class, ErrorClass::Ambiguous, "status {}", status);
+        assert!(!error.is_retryable());
+        assert_eq!(error.error_type, "upstream_error");
+    }
+}
+
+#[test]
+fn keeps_plain_bodies_and_retry_after() {
+    let mut headers = HeaderMap::new();
+    headers.insert("retry-after", "7".parse().unwrap());
+    let error = provider::classify(503, &headers, b"upstream overloaded");
+    assert_eq!(error.class, ErrorClass::Retryable);
+    assert_eq!(error.message, "upstream overloaded");
+    assert_eq!(error.retry_after, Some(Duration::from_secs(7)));
+
+    headers.insert("retry-after", "Wed, 21 Oct 2026 07:28:00 GMT".parse().unwrap());
+    assert_eq!(provider::retry_after(&headers), None);
+}
+
+#[test]
+fn providers_refine_the_classification() {
+    let anthropic = Anthropic::new(AnthropicConfig::default());
+    let overloaded = anthropic.classify_response(
+        529,
+        &HeaderMap::new(),
+        json!({ "error": { "type": "overloaded_error", "message": "Overloaded" } }).to_string().as_bytes(),
+    );
+    assert_eq!(overloaded.class, ErrorClass::Retryable);
+    assert_eq!(overloaded.status.as_u16(), 503);
+
+    let openai = OpenAiCompatible.classify_response(529, &HeaderMap::new(), b"");
+    assert_eq!(openai.class, ErrorClass::Ambiguous);
+}
+
+#[tokio::test]
+async fn retries_failed_connections() {
+    let err = reqwest::Client::new()
+        .post("http://127.0.0.1:1/v1/chat/completions")
+        .send()
+        .await
+        .unwrap_err();
+    let error = OpenAiCompatible.classify_transport(&err);
+    assert_eq!(error.class, ErrorClass::Retryable);
+    assert_eq!(error.status.as_u16(), 502);
+}
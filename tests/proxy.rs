@@ -0,0 +1,1805 @@
+mod common;
+
+use common::{chunk, completion, field, post_chat, spawn_adapter, sse_events, MockUpstream, Reply};
+use openai_api_proxy::embedded::Embedded;
+use openai_api_proxy::reload::Reloadable;
+use openai_api_proxy::{AppConfig, AppState};
+use serde_json::{json, Value};
+use std::sync::Arc;
+use std::time::Duration;
+
+#[tokio::test]
+async fn forwards_request_with_upstream_key() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("Bonjour")));
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["choices"][0]["message"]["content"], "Bonjour");
+
+    let requests = upstream.requests();
+    assert_eq!(requests.len(), 1);
+    assert_eq!(requests[0].path, "/v1/chat/completions");
+    assert_eq!(requests[0].headers["authorization"], "Bearer upstream-key");
+}
+
+#[tokio::test]
+async fn passes_through_upstream_errors() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(
+        400,
+        json!({ "error": { "type": "invalid_request_error", "message": "bad messages" } }),
+    ));
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(response.status(), 400);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["message"], "bad messages");
+}
+
+#[tokio::test]
+async fn unreachable_upstream_returns_bad_gateway() {
+    let adapter = spawn_adapter(&MockUpstream::unreachable(), "").await;
+
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(response.status(), 502);
+}
+
+#[tokio::test]
+async fn streams_events_with_increasing_ids() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::sse(&[chunk("Hel"), chunk("lo"), "[DONE]".to_string()]));
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let response = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "messages": [], "stream": true }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let events = sse_events(&response.text().await.unwrap());
+
+    let ids: Vec<&str> = events.iter().filter_map(|e| field(e, "id")).collect();
+    // The upstream sent no finish_reason, so a `stop` chunk precedes [DONE].
+    assert_eq!(ids, ["1", "2", "3", "4"]);
+    assert!(events.iter().all(|e| field(e, "event").is_none()));
+    assert_eq!(field(&events[3], "data"), Some("[DONE]"));
+}
+
+#[tokio::test]
+async fn tags_event_types_when_enabled() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::sse(&[
+        chunk("Hi"),
+        json!({ "error": { "message": "overloaded" } }).to_string(),
+        "[DONE]".to_string(),
+    ]));
+    let adapter = spawn_adapter(&upstream, "[streaming]\nevent_types = true\n").await;
+
+    let response = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "messages": [], "stream": true }),
+    )
+    .await;
+    let events = sse_events(&response.text().await.unwrap());
+    let kinds: Vec<&str> = events.iter().filter_map(|e| field(e, "event")).collect();
+    assert_eq!(kinds, ["delta", "error", "done"]);
+}
+
+#[tokio::test]
+async fn numbers_each_stream_from_one() {
+    let upstream = MockUpstream::start().await;
+    let finish = json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "created": 1700000000,
+        "model": "test-model",
+        "choices": [{ "index": 0, "delta": {}, "finish_reason": "stop" }]
+    });
+    for _ in 0..2 {
+        upstream.push(Reply::sse(&[chunk("Hi"), finish.to_string(), "[DONE]".to_string()]));
+    }
+    let adapter = spawn_adapter(&upstream, "[streaming]\nevent_types = true\n").await;
+
+    for _ in 0..2 {
+        let response = post_chat(
+            &adapter,
+            json!({ "model": "test-model", "messages": [], "stream": true }),
+        )
+        .await;
+        let events = sse_events(&response.text().await.unwrap());
+        let ids: Vec<&str> = events.iter().filter_map(|e| field(e, "id")).collect();
+        assert_eq!(ids, ["1", "2", "3"]);
+        let kinds: Vec<&str> = events.iter().filter_map(|e| field(e, "event")).collect();
+        assert_eq!(kinds, ["delta", "delta", "done"]);
+    }
+}
+
+#[tokio::test]
+async fn budget_closes_stream_with_partial_output() {
+    let upstream = MockUpstream::start().await;
+    let chunks: Vec<String> = (0..20).map(|i| chunk(&format!("w{} ", i))).collect();
+    upstream.push(Reply::sse(&chunks).chunk_delay(Duration::from_millis(50)));
+    let adapter = spawn_adapter(&upstream, "[streaming]\nbudget_ms = 300\n").await;
+
+    let response = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "messages": [], "stream": true }),
+    )
+    .await;
+    let events = sse_events(&response.text().await.unwrap());
+    assert!(events.len() < 20);
+    assert_eq!(field(events.last().unwrap(), "data"), Some("[DONE]"));
+
+    let last_chunk: Value =
+        serde_json::from_str(field(&events[events.len() - 2], "data").unwrap()).unwrap();
+    assert_eq!(last_chunk["choices"][0]["finish_reason"], "length");
+    assert_eq!(last_chunk["x_budget_exhausted"], true);
+}
+
+#[tokio::test]
+async fn budget_leaves_timely_answers_untouched() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::sse(&[chunk("Hi"), "[DONE]".to_string()]));
+    let adapter = spawn_adapter(&upstream, "[streaming]\nbudget_ms = 2000\n").await;
+
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["choices"][0]["message"]["content"], "Hi");
+    assert_ne!(body["choices"][0]["finish_reason"], "length");
+    assert!(body.get("x_budget_exhausted").is_none());
+}
+
+#[tokio::test]
+async fn budget_spent_before_first_byte_is_an_error() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::sse(&[chunk("late")]).delayed(Duration::from_millis(800)));
+    let adapter = spawn_adapter(&upstream, "[streaming]\nbudget_ms = 200\n").await;
+
+    let response = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "messages": [], "stream": true }),
+    )
+    .await;
+    assert_eq!(response.status(), 504);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["message"], "The upstream did not respond within the configured response budget");
+}
+
+#[tokio::test]
+async fn budget_assembles_partial_non_streaming_response() {
+    let upstream = MockUpstream::start().await;
+    let chunks: Vec<String> = (0..20).map(|i| chunk(&format!("w{} ", i))).collect();
+    upstream.push(Reply::sse(&chunks).chunk_delay(Duration::from_millis(50)));
+    let adapter = spawn_adapter(&upstream, "[streaming]\nbudget_ms = 300\n").await;
+
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["object"], "chat.completion");
+    assert_eq!(body["choices"][0]["finish_reason"], "length");
+    assert_eq!(body["x_budget_exhausted"], true);
+    assert!(body["choices"][0]["message"]["content"]
+        .as_str()
+        .unwrap()
+        .starts_with("w0 "));
+
+    assert_eq!(upstream.requests()[0].body["stream"], true);
+}
+
+#[tokio::test]
+async fn idle_timeout_ends_stream_with_error() {
+    let upstream = MockUpstream::start().await;
+    let chunks = vec![chunk("one"), chunk("two")];
+    upstream.push(Reply::sse(&chunks).chunk_delay(Duration::from_millis(300)));
+    let adapter = spawn_adapter(&upstream, "[policy]\nidle_timeout_ms = 150\n").await;
+
+    let response = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "messages": [], "stream": true }),
+    )
+    .await;
+    let events = sse_events(&response.text().await.unwrap());
+    assert_eq!(events.len(), 2);
+    let first: Value = serde_json::from_str(field(&events[0], "data").unwrap()).unwrap();
+    assert_eq!(first["choices"][0]["delta"]["content"], "one");
+    let error: Value = serde_json::from_str(field(&events[1], "data").unwrap()).unwrap();
+    assert_eq!(error["error"]["type"], "upstream_timeout");
+}
+
+#[tokio::test]
+async fn first_byte_timeout_returns_gateway_timeout() {
+    let upstream = MockUpstream::start().await;
+    let body = completion("late").to_string();
+    upstream.push(
+        Reply::raw(200, "application/json", vec![body]).chunk_delay(Duration::from_millis(300)),
+    );
+    let adapter = spawn_adapter(&upstream, "[policy]\nfirst_byte_timeout_ms = 100\n").await;
+
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(response.status(), 504);
+}
+
+#[tokio::test]
+async fn retries_header_timeouts_only_with_retry_ambiguous() {
+    let upstream = MockUpstream::start().await;
+    let slow = || Reply::json(200, completion("late")).delayed(Duration::from_millis(300));
+    upstream.push(slow()).always(Reply::json(200, completion("ok")));
+    let policy = "[policy]\ntimeout_ms = 100\nretries = 1\nbackoff_ms = 10\n";
+    let body = json!({ "model": "test-model", "messages": [] });
+
+    let adapter = spawn_adapter(&upstream, policy).await;
+    assert_eq!(post_chat(&adapter, body.clone()).await.status(), 504);
+    assert_eq!(upstream.requests().len(), 1);
+
+    upstream.push(slow());
+    let adapter = spawn_adapter(&upstream, &format!("{}retry_ambiguous = true\n", policy)).await;
+    assert_eq!(post_chat(&adapter, body).await.status(), 200);
+    assert_eq!(upstream.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn compresses_long_prompts_to_target_ratio() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "[compression]\nratio = 0.5\nmin_tokens = 50\n").await;
+
+    let filler = "This is the same filler sentence again. ".repeat(40);
+    let document = format!("Translate the report below. {}The revenue grew by 12 percent in Q3.", filler);
+    let response = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "messages": [{ "role": "user", "content": document }] }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+
+    let forwarded = upstream.requests()[0].body["messages"][0]["content"]
+        .as_str()
+        .unwrap()
+        .to_string();
+    assert!(forwarded.len() <= document.len() / 2 + 50);
+    assert!(forwarded.starts_with("Translate the report below."));
+    assert!(forwarded.ends_with("The revenue grew by 12 percent in Q3."));
+}
+
+#[tokio::test]
+async fn negotiates_api_version() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(response.headers()["x-llmta-api-version"], "2026-10-01");
+
+    let response = reqwest::Client::new()
+        .post(format!("{}{}", adapter, common::CHAT_PATH))
+        .bearer_auth("client-key")
+        .header("x-llmta-api-version", "1999-01-01")
+        .json(&json!({ "model": "test-model", "messages": [] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 400);
+    assert_eq!(upstream.requests().len(), 1);
+
+    let spec: Value = reqwest::get(format!("{}/openapi.json", adapter))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(spec["info"]["version"], "2026-10-01");
+}
+
+#[tokio::test]
+async fn serves_routes_under_prefix_and_aliases() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(
+        &upstream,
+        r#"
+[routes]
+prefix = "/llm"
+aliases = [{ path = "/openai/v1/chat/completions", target = "/v1beta/openai/chat/completions" }]
+"#,
+    )
+    .await;
+    let client = reqwest::Client::new();
+    let body = json!({ "model": "test-model", "messages": [] });
+
+    for path in ["/llm/v1beta/openai/chat/completions", "/openai/v1/chat/completions"] {
+        let response = client
+            .post(format!("{}{}", adapter, path))
+            .bearer_auth("client-key")
+            .json(&body)
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(response.status(), 200, "{}", path);
+    }
+    assert_eq!(post_chat(&adapter, body).await.status(), 404);
+}
+
+#[tokio::test]
+async fn passes_unknown_v1_paths_through_when_enabled() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, json!({ "object": "list", "data": [] })));
+    let adapter = spawn_adapter(&upstream, "[routes]\npassthrough = true\n").await;
+
+    let response = reqwest::Client::new()
+        .get(format!("{}/v1/files?purpose=batch", adapter))
+        .bearer_auth("client-key")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+
+    let requests = upstream.requests();
+    assert_eq!(requests[0].path, "/v1/files?purpose=batch");
+    assert_eq!(requests[0].headers["authorization"], "Bearer upstream-key");
+}
+
+#[tokio::test]
+async fn rejects_oversized_responses() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion(&"A".repeat(4096))));
+    let adapter = spawn_adapter(&upstream, "[limits]\nmax_response_bytes = 1024\n").await;
+
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(response.status(), 502);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "response_too_large");
+}
+
+#[tokio::test]
+async fn truncates_oversized_responses_with_marker() {
+    let upstream = MockUpstream::start().await;
+    let chunks: Vec<String> = (0..50).map(|i| chunk(&format!("word{} ", i))).collect();
+    upstream.push(Reply::sse(&chunks).chunk_delay(Duration::from_millis(5)));
+    let adapter = spawn_adapter(
+        &upstream,
+        "[limits]\nmax_response_bytes = 1024\noversize_policy = \"truncate-with-marker\"\n",
+    )
+    .await;
+
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["x_truncated"], true);
+    assert_eq!(body["choices"][0]["finish_reason"], "length");
+    assert!(body["choices"][0]["message"]["content"]
+        .as_str()
+        .unwrap()
+        .starts_with("word0 "));
+    assert_eq!(upstream.requests()[0].body["stream"], true);
+}
+
+#[tokio::test]
+async fn strips_provider_internal_headers() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(
+        Reply::json(200, completion("ok"))
+            .header("set-cookie", "session=abc")
+            .header("openai-organization", "org-secret")
+            .header("x-amzn-requestid", "internal")
+            .header("x-internal-shard", "7")
+            .header("openai-processing-ms", "42")
+            .header("x-ratelimit-remaining-requests", "99"),
+    );
+    let adapter = spawn_adapter(&upstream, "[headers]\ndeny = [\"x-internal-*\"]\n").await;
+
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    let headers = response.headers();
+    for stripped in ["set-cookie", "openai-organization", "x-amzn-requestid", "x-internal-shard"] {
+        assert!(headers.get(stripped).is_none(), "{} leaked", stripped);
+    }
+    assert_eq!(headers["openai-processing-ms"], "42");
+    assert_eq!(headers["x-ratelimit-remaining-requests"], "99");
+}
+
+#[tokio::test]
+async fn expands_stored_prompt_references() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "").await;
+    let instructions = "You are a careful legal translator. ".repeat(200);
+
+    let stored: Value = reqwest::Client::new()
+        .post(format!("{}/v1/prompts", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({ "content": instructions }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let id = stored["id"].as_str().unwrap();
+    assert!(id.starts_with("sha256:"));
+
+    let response = post_chat(
+        &adapter,
+        json!({
+            "model": "test-model",
+            "x_prompt_id": id,
+            "messages": [{ "role": "user", "content": "Hallo" }]
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let sent = &upstream.requests()[0].body;
+    assert_eq!(sent["messages"][0]["role"], "system");
+    assert_eq!(sent["messages"][0]["content"], instructions.as_str());
+    assert!(sent.get("x_prompt_id").is_none());
+
+    let unknown = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "x_prompt_id": "sha256:00", "messages": [] }),
+    )
+    .await;
+    assert_eq!(unknown.status(), 400);
+}
+
+#[tokio::test]
+async fn answers_exhausted_budgets_with_the_canned_reply() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("real answer")));
+    let adapter = spawn_adapter(
+        &upstream,
+        "[limits]\ntokens_per_minute = 20\n\n[degraded]\nmessage = \"Busy, retry soon.\"\n",
+    )
+    .await;
+    let body = json!({ "model": "test-model", "messages": [], "max_tokens": 20 });
+
+    let first: Value = post_chat(&adapter, body.clone()).await.json().await.unwrap();
+    assert_eq!(first["choices"][0]["message"]["content"], "real answer");
+
+    let response = post_chat(&adapter, body).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-llmta-degraded"], "429");
+    let completion: Value = response.json().await.unwrap();
+    assert_eq!(completion["choices"][0]["message"]["content"], "Busy, retry soon.");
+    assert_eq!(upstream.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn answers_request_limits_with_the_canned_reply() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("real answer")));
+    let adapter = spawn_adapter(&upstream, "[limits]\nrequests_per_minute = 1\n\n[degraded]\n").await;
+    let body = json!({ "model": "test-model", "messages": [] });
+
+    assert_eq!(post_chat(&adapter, body.clone()).await.status(), 200);
+    let response = post_chat(&adapter, body).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-llmta-degraded"], "429");
+    assert_eq!(upstream.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn answers_upstream_outage_with_localized_canned_reply() {
+    let upstream = MockUpstream::unreachable();
+    let adapter = spawn_adapter(
+        &upstream,
+        "[degraded]\nmessage = \"Busy, retry soon.\"\n[[degraded.messages]]\nlocale = \"de\"\nmessage = \"Bitte später erneut versuchen.\"\n",
+    )
+    .await;
+    let client = reqwest::Client::new();
+    let request = |language: &str, stream: bool| {
+        client
+            .post(format!("{}{}", adapter, common::CHAT_PATH))
+            .bearer_auth("client-key")
+            .header("accept-language", language)
+            .json(&json!({ "model": "test-model", "messages": [], "stream": stream }))
+            .send()
+    };
+
+    let response = request("de-AT, en;q=0.5", false).await.unwrap();
+    assert_eq!(response.status(), 200);
+    assert!(response.headers().contains_key("x-llmta-degraded"));
+    let completion: Value = response.json().await.unwrap();
+    assert_eq!(completion["choices"][0]["message"]["content"], "Bitte später erneut versuchen.");
+    assert_eq!(completion["model"], "test-model");
+
+    let body = request("fr", true).await.unwrap().text().await.unwrap();
+    let events = sse_events(&body);
+    let first: Value = serde_json::from_str(field(&events[0], "data").unwrap()).unwrap();
+    assert_eq!(first["choices"][0]["delta"]["content"], "Busy, retry soon.");
+    assert_eq!(field(events.last().unwrap(), "data"), Some("[DONE]"));
+}
+
+#[tokio::test]
+async fn normalizes_malformed_upstream_sse() {
+    let upstream = MockUpstream::start().await;
+    let head = r#""id":"chatcmpl-mock","object":"chat.completion.chunk","created":1700000000,"model":"test-model""#;
+    upstream.push(Reply::raw(
+        200,
+        "text/event-stream",
+        vec![
+            // No `data:` prefix, and no blank line before the next event.
+            format!("{{{},\"choices\":[{{\"index\":0,\"delta\":{{\"content\":\"He\"}}}}]}}\n", head),
+            // Pretty-printed JSON without prefixes, using completion-style `text`.
+            format!("{{\n  {},\n  \"choices\": [{{ \"index\": 0, \"text\": \"l\" }}]\n}}\n\n", head),
+            // JSON across several `data:` lines, with a whole `message`.
+            format!("data: {{{},\ndata: \"choices\":[{{\"index\":0,\"message\":{{\"content\":\"lo\"}},\"finish_reason\":\"stop\"}}]}}\n\n", head),
+            "data: DONE\n\n".to_string(),
+        ],
+    ));
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let body = post_chat(&adapter, json!({ "model": "test-model", "messages": [], "stream": true }))
+        .await
+        .text()
+        .await
+        .unwrap();
+    let data: Vec<&str> = body.lines().filter_map(|line| line.strip_prefix("data: ")).collect();
+    assert_eq!(data.len(), 4);
+    let contents: Vec<Value> = data[..3]
+        .iter()
+        .map(|d| serde_json::from_str::<Value>(d).unwrap()["choices"][0]["delta"]["content"].clone())
+        .collect();
+    assert_eq!(contents, [json!("He"), json!("l"), json!("lo")]);
+    assert_eq!(data[3], "[DONE]");
+}
+
+#[tokio::test]
+async fn normalizes_cumulative_tool_call_deltas() {
+    let upstream = MockUpstream::start().await;
+    let tool_chunk = |call: Value| {
+        json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion.chunk",
+            "created": 1700000000,
+            "model": "test-model",
+            "choices": [{ "index": 0, "delta": { "tool_calls": [call] }, "finish_reason": null }]
+        })
+        .to_string()
+    };
+    // No `index`, the name repeated and the arguments resent in full each time.
+    upstream.push(Reply::sse(&[
+        tool_chunk(json!({ "id": "call_1", "function": { "name": "lookup", "arguments": "{\"q\":" } })),
+        tool_chunk(json!({ "id": "call_1", "function": { "name": "lookup", "arguments": "{\"q\":\"rust\"}" } })),
+        tool_chunk(json!({ "id": "call_2", "function": { "name": "define", "arguments": { "term": "borrow" } } })),
+        "[DONE]".to_string(),
+    ]));
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let body = post_chat(&adapter, json!({ "model": "test-model", "messages": [], "stream": true }))
+        .await
+        .text()
+        .await
+        .unwrap();
+    let calls: Vec<Value> = sse_events(&body)
+        .iter()
+        .filter_map(|event| field(event, "data"))
+        .filter_map(|data| serde_json::from_str::<Value>(data).ok())
+        .map(|chunk| chunk["choices"][0]["delta"]["tool_calls"][0].clone())
+        .collect();
+
+    assert_eq!(calls[0]["index"], 0);
+    assert_eq!(calls[0]["type"], "function");
+    assert_eq!(calls[0]["function"]["name"], "lookup");
+    assert_eq!(calls[1]["index"], 0);
+    assert!(calls[1].get("id").is_none());
+    assert!(calls[1]["function"].get("name").is_none());
+    assert_eq!(calls[1]["function"]["arguments"], "\"rust\"}");
+    assert_eq!(calls[2]["index"], 1);
+    assert_eq!(calls[2]["function"]["arguments"], "{\"term\":\"borrow\"}");
+}
+
+#[tokio::test]
+async fn serializes_tool_calls_for_backends_ignoring_the_parallel_flag() {
+    let upstream = MockUpstream::start().await;
+    let call = |id: &str| json!({ "id": id, "type": "function", "function": { "name": "lookup", "arguments": "{}" } });
+    let mut reply = completion("");
+    reply["choices"][0]["message"]["tool_calls"] = json!([call("call_1"), call("call_2")]);
+    reply["choices"][0]["finish_reason"] = json!("tool_calls");
+    upstream.push(Reply::json(200, reply));
+    let adapter = spawn_adapter(&upstream, "[tools]\nparallel_calls = \"serialize\"\n").await;
+
+    let response: Value = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "messages": [], "tools": [], "parallel_tool_calls": false }),
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+    let calls = response["choices"][0]["message"]["tool_calls"].as_array().unwrap();
+    assert_eq!(calls.len(), 1);
+    assert_eq!(calls[0]["id"], "call_1");
+    assert!(upstream.requests()[0].body.get("parallel_tool_calls").is_none());
+}
+
+#[tokio::test]
+async fn translates_parallel_flag_to_disable_parallel_tool_use() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "[tools]\nparallel_calls = \"disable-parallel-tool-use\"\n").await;
+
+    post_chat(
+        &adapter,
+        json!({ "model": "test-model", "messages": [], "tool_choice": "required", "parallel_tool_calls": false }),
+    )
+    .await;
+    let sent = &upstream.requests()[0].body;
+    assert!(sent.get("parallel_tool_calls").is_none());
+    assert_eq!(sent["tool_choice"], json!({ "type": "any", "disable_parallel_tool_use": true }));
+}
+
+#[tokio::test]
+async fn retries_then_falls_back_per_route_and_model_policy() {
+    let upstream = MockUpstream::start().await;
+    let unavailable = || Reply::json(503, json!({ "error": { "message": "overloaded" } }));
+    upstream.push(unavailable()).push(unavailable()).push(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(
+        &upstream,
+        "[policy]\nretries = 0\n\n[[policy.rules]]\nroutes = [\"chat\"]\nmodels = [\"test-*\"]\nretries = 1\nbackoff_ms = 10\nfallback_model = \"backup-model\"\n",
+    )
+    .await;
+
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(response.status(), 200);
+    let models: Vec<Value> = upstream.requests().iter().map(|r| r.body["model"].clone()).collect();
+    assert_eq!(models, vec![json!("test-model"), json!("test-model"), json!("backup-model")]);
+}
+
+#[tokio::test]
+async fn caps_and_jitters_retry_backoff() {
+    let upstream = MockUpstream::start().await;
+    upstream
+        .push(Reply::json(429, json!({ "error": { "message": "slow down" } })))
+        .push(Reply::json(502, json!({ "error": { "message": "bad gateway" } })))
+        .push(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(
+        &upstream,
+        "[policy]\nretries = 2\nbackoff_ms = 60000\nmax_backoff_ms = 50\njitter = 1.0\n",
+    )
+    .await;
+
+    let started = std::time::Instant::now();
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [], "stream": true })).await;
+    assert_eq!(response.status(), 200);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(upstream.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn falls_back_on_error_patterns_hidden_in_responses() {
+    let upstream = MockUpstream::start().await;
+    upstream
+        .push(Reply::json(200, json!({ "error": { "message": "The model is overloaded" } })))
+        .push(Reply::json(200, completion("backup")))
+        .push(Reply::json(400, json!({ "error": { "code": "insufficient_quota" } })))
+        .push(Reply::json(200, completion("retried")));
+    let adapter = spawn_adapter(
+        &upstream,
+        "[policy]\nretries = 2\nbackoff_ms = 10\nfallback_model = \"backup-model\"\n\n[[policy.error_patterns]]\npattern = \"Model is overloaded\"\nstatuses = [200]\n\n[[policy.error_patterns]]\npattern = \"insufficient_quota\"\naction = \"retry\"\n",
+    )
+    .await;
+
+    let fallen_back: Value = post_chat(&adapter, json!({ "model": "test-model", "messages": [] }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(fallen_back["choices"][0]["message"]["content"], "backup");
+    let retried = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(retried.status(), 200);
+    let models: Vec<Value> = upstream.requests().iter().map(|r| r.body["model"].clone()).collect();
+    assert_eq!(models, vec![json!("test-model"), json!("backup-model"), json!("test-model"), json!("test-model")]);
+}
+
+#[tokio::test]
+async fn gives_up_when_retry_after_exceeds_the_backoff_cap() {
+    let upstream = MockUpstream::start().await;
+    upstream
+        .push(Reply::json(429, json!({ "error": { "message": "slow down" } })).header("retry-after", "3600"))
+        .push(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "[policy]\nretries = 2\nbackoff_ms = 10\nmax_backoff_ms = 50\n").await;
+
+    let started = std::time::Instant::now();
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(response.status(), 429);
+    assert!(started.elapsed() < Duration::from_secs(5));
+    assert_eq!(upstream.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn classifies_failed_answers_by_their_body() {
+    let upstream = MockUpstream::start().await;
+    let refused = json!({ "error": { "code": "content_policy_violation", "message": "refused" } });
+    upstream
+        .push(Reply::json(500, refused))
+        .push(Reply::json(500, json!({ "error": { "message": "internal error" } })))
+        .always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "[policy]\nretries = 2\nbackoff_ms = 10\n").await;
+    let body = json!({ "model": "test-model", "messages": [] });
+
+    let response = post_chat(&adapter, body.clone()).await;
+    assert_eq!(response.status(), 500);
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["error"]["message"], "refused");
+    assert_eq!(upstream.requests().len(), 1);
+
+    assert_eq!(post_chat(&adapter, body).await.status(), 200);
+    assert_eq!(upstream.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn leaves_streams_and_oversized_bodies_unscreened() {
+    let config = "[policy]\nfallback_model = \"backup-model\"\n\n[[policy.error_patterns]]\npattern = \"overloaded\"\nstatuses = [200]\n";
+    let overloaded = json!({ "error": { "message": "The model is overloaded" } }).to_string();
+
+    // A stream is relayed as it arrives, whatever framing it comes in.
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::raw(200, "application/x-ndjson", vec![overloaded.clone()]));
+    let adapter = spawn_adapter(&upstream, config).await;
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [], "stream": true })).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(upstream.requests().len(), 1);
+
+    // Past `max_response_bytes` the body is not held back to be searched.
+    let upstream = MockUpstream::start().await;
+    let padded = json!({ "error": { "message": "The model is overloaded" }, "padding": "A".repeat(4096) });
+    upstream.push(Reply::json(200, padded));
+    let adapter = spawn_adapter(&upstream, &format!("{}\n[limits]\nmax_response_bytes = 1024\n", config)).await;
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(response.status(), 502);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "response_too_large");
+    assert_eq!(upstream.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn walks_fallback_chain_across_backends() {
+    let upstream = MockUpstream::start().await;
+    let mut refused = completion("");
+    refused["choices"][0]["finish_reason"] = json!("content_filter");
+    upstream
+        .push(Reply::json(503, json!({ "error": { "message": "unavailable" } })))
+        .push(Reply::json(200, refused));
+    let local = MockUpstream::start().await;
+    local.push(Reply::json(200, completion("local answer")));
+    let config = format!(
+        "[policy]\nfallback_chain = [\"backup-model\", \"local-model\"]\n\n[[backends]]\nname = \"backup\"\nurl = \"{}/v1/chat/completions\"\nkey = \"k\"\nmodels = [\"backup-model\"]\n\n[[backends]]\nname = \"local\"\nurl = \"{}/v1/chat/completions\"\nkey = \"k\"\nmodels = [\"local-model\"]\n",
+        upstream.base_url, local.base_url
+    );
+    let adapter = spawn_adapter(&upstream, &config).await;
+
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-llmta-answered-by"], "local-model");
+    let answer: Value = response.json().await.unwrap();
+    assert_eq!(answer["choices"][0]["message"]["content"], "local answer");
+    let models: Vec<Value> = upstream.requests().iter().map(|r| r.body["model"].clone()).collect();
+    assert_eq!(models, vec![json!("test-model"), json!("backup-model")]);
+    assert_eq!(local.requests()[0].body["model"], "local-model");
+}
+
+#[tokio::test]
+async fn routes_requests_to_backends_by_model() {
+    let primary = MockUpstream::start().await;
+    primary.always(Reply::json(200, completion("primary")));
+    let secondary = MockUpstream::start().await;
+    secondary.always(Reply::json(200, completion("secondary")));
+    let adapter = spawn_adapter(
+        &primary,
+        &format!(
+            "[[backends]]\nname = \"secondary\"\nurl = \"{}/v1/chat/completions\"\nkey = \"secondary-key\"\nmodels = [\"other-*\"]\n",
+            secondary.base_url
+        ),
+    )
+    .await;
+
+    post_chat(&adapter, json!({ "model": "other-large", "messages": [] })).await;
+    post_chat(&adapter, json!({ "model": "unknown-model", "messages": [] })).await;
+
+    let routed = secondary.requests();
+    assert_eq!(routed.len(), 1);
+    assert_eq!(routed[0].body["model"], "other-large");
+    assert_eq!(routed[0].headers["authorization"], "Bearer secondary-key");
+    let fallback = primary.requests();
+    assert_eq!(fallback.len(), 1);
+    assert_eq!(fallback[0].body["model"], "test-model");
+}
+
+#[tokio::test]
+async fn balances_across_backends_and_cools_down_failing_ones() {
+    let upstream = MockUpstream::start().await;
+    upstream
+        .push(Reply::json(200, completion("a")))
+        .push(Reply::json(200, completion("b")))
+        .push(Reply::json(429, json!({ "error": { "message": "quota exceeded" } })))
+        .always(Reply::json(200, completion("ok")));
+    let backend = |name: &str| {
+        format!(
+            "[[backends]]\nname = \"{}\"\nurl = \"{}/v1/chat/completions\"\nkey = \"{}-key\"\nmodels = [\"pooled\"]\n",
+            name, upstream.base_url, name
+        )
+    };
+    let config = format!(
+        "[balancing]\nstrategy = \"round_robin\"\ncooldown_secs = 60\n\n{}{}",
+        backend("one"),
+        backend("two")
+    );
+    let adapter = spawn_adapter(&upstream, &config).await;
+
+    for _ in 0..5 {
+        post_chat(&adapter, json!({ "model": "pooled", "messages": [] })).await;
+    }
+
+    let keys: Vec<String> = upstream
+        .requests()
+        .iter()
+        .map(|r| r.headers["authorization"].to_str().unwrap().to_string())
+        .collect();
+    // The third request failed on `one`, which then sits out its cooldown.
+    assert_eq!(
+        keys,
+        [
+            "Bearer one-key",
+            "Bearer two-key",
+            "Bearer one-key",
+            "Bearer two-key",
+            "Bearer two-key",
+        ]
+    );
+}
+
+#[tokio::test]
+async fn circuit_breaker_fails_fast_and_recovers() {
+    let upstream = MockUpstream::start().await;
+    let failure = json!({ "error": { "message": "internal error" } });
+    upstream
+        .push(Reply::json(500, failure.clone()))
+        .push(Reply::json(500, failure))
+        .always(Reply::json(200, completion("ok")));
+    let adapter =
+        spawn_adapter(&upstream, "[breaker]\nenabled = true\nfailures = 2\nopen_secs = 1\n").await;
+    let body = json!({ "model": "test-model", "messages": [] });
+    let circuit = || async {
+        let metrics = reqwest::get(format!("{}/metrics", adapter)).await.unwrap();
+        let metrics = metrics.text().await.unwrap();
+        let gauge = |state: &str| format!("llmta_circuit_state{{backend=\"default\"}} {}", state);
+        ["0", "1", "2"].into_iter().find(|state| metrics.contains(&gauge(state))).unwrap()
+    };
+
+    assert_eq!(post_chat(&adapter, body.clone()).await.status(), 500);
+    assert_eq!(circuit().await, "0");
+    assert_eq!(post_chat(&adapter, body.clone()).await.status(), 500);
+    assert_eq!(circuit().await, "2");
+
+    let rejected = post_chat(&adapter, body.clone()).await;
+    assert_eq!(rejected.status(), 503);
+    assert_eq!(rejected.headers()["retry-after"], "1");
+    let error: Value = rejected.json().await.unwrap();
+    assert_eq!(error["error"]["type"], "circuit_open");
+    assert_eq!(upstream.requests().len(), 2);
+
+    tokio::time::sleep(Duration::from_millis(1100)).await;
+    assert_eq!(circuit().await, "1");
+    assert_eq!(post_chat(&adapter, body).await.status(), 200);
+    assert_eq!(circuit().await, "0");
+}
+
+#[tokio::test]
+async fn readiness_probes_each_backend() {
+    let upstream = MockUpstream::start().await;
+    upstream
+        .push(Reply::json(503, json!({ "error": { "message": "overloaded" } })))
+        .always(Reply::json(200, json!({ "data": [] })));
+    let adapter =
+        spawn_adapter(&upstream, "[health]\nprobe = \"models\"\ncache_secs = 0\n").await;
+
+    let live = reqwest::get(format!("{}/healthz", adapter)).await.unwrap();
+    assert_eq!(live.status(), 200);
+    assert!(upstream.requests().is_empty());
+
+    let not_ready = reqwest::get(format!("{}/readyz", adapter)).await.unwrap();
+    assert_eq!(not_ready.status(), 503);
+    let report: Value = not_ready.json().await.unwrap();
+    assert_eq!(report["status"], "not_ready");
+    assert_eq!(report["backends"][0]["name"], "default");
+    assert_eq!(report["backends"][0]["status"], "down");
+    assert_eq!(report["backends"][0]["circuit"], "closed");
+
+    let ready = reqwest::get(format!("{}/readyz", adapter)).await.unwrap();
+    assert_eq!(ready.status(), 200);
+    let report: Value = ready.json().await.unwrap();
+    assert_eq!(report["backends"][0]["status"], "up");
+    assert!(report["backends"][0]["latency_ms"].is_u64());
+    assert!(upstream.requests().iter().all(|r| r.path == "/v1/models"));
+}
+
+/// Serves a standalone adapter on `port` while `client` runs, until `stop`
+/// resolves.
+async fn serve_on(
+    port: u16,
+    upstream: &MockUpstream,
+    extra: &str,
+    stop: impl std::future::Future<Output = ()> + Send + 'static,
+    client: impl std::future::Future<Output = ()>,
+) {
+    let config = AppConfig::from_toml(&format!(
+        "model_url = \"{}/v1/chat/completions\"\nmodel_key = \"k\"\ndefault_model = \"test-model\"\n\
+         port = {}\nhost = \"127.0.0.1\"\n{}",
+        upstream.base_url, port, extra
+    ))
+    .unwrap();
+    let (served, ()) = tokio::join!(openai_api_proxy::serve(config, stop), client);
+    served.unwrap();
+}
+
+async fn wait_for_port(base: &str) {
+    for _ in 0..50 {
+        if reqwest::get(format!("{}/healthz", base)).await.is_ok() {
+            return;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    panic!("adapter at {} did not start", base);
+}
+
+#[tokio::test]
+async fn shutdown_lets_active_streams_finish() {
+    let upstream = MockUpstream::start().await;
+    let mut chunks: Vec<String> = (0..6).map(|i| chunk(&format!("w{} ", i))).collect();
+    chunks.push("[DONE]".to_string());
+    upstream.push(Reply::sse(&chunks).chunk_delay(Duration::from_millis(100)));
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let base = format!("http://127.0.0.1:{}", port);
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+
+    let client = async {
+        wait_for_port(&base).await;
+        let body = json!({ "model": "test-model", "messages": [], "stream": true });
+        let stream = post_chat(&base, body).await;
+        assert_eq!(stream.status(), 200);
+        stop.send(()).unwrap();
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        assert!(reqwest::get(format!("{}/healthz", base)).await.is_err());
+
+        let rest = stream.text().await.unwrap();
+        assert!(rest.contains("w5 "));
+        assert!(!rest.contains("stream_terminated"));
+        let events = sse_events(&rest);
+        assert_eq!(field(events.last().unwrap(), "data"), Some("[DONE]"));
+    };
+    serve_on(port, &upstream, "", async move { let _ = stopped.await; }, client).await;
+}
+
+#[tokio::test]
+async fn shutdown_terminates_streams_after_drain_timeout() {
+    let upstream = MockUpstream::start().await;
+    let chunks: Vec<String> = (0..20).map(|i| chunk(&format!("w{} ", i))).collect();
+    upstream.push(Reply::sse(&chunks).chunk_delay(Duration::from_millis(200)));
+    let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+    let base = format!("http://127.0.0.1:{}", port);
+    let (stop, stopped) = tokio::sync::oneshot::channel::<()>();
+
+    let client = async {
+        wait_for_port(&base).await;
+        let body = json!({ "model": "test-model", "messages": [], "stream": true });
+        let stream = post_chat(&base, body).await;
+        stop.send(()).unwrap();
+        let rest = stream.text().await.unwrap();
+        assert!(rest.contains("stream_terminated"));
+        assert!(sse_events(&rest).len() < 20);
+    };
+    let extra = "[shutdown]\ndrain_timeout_secs = 0\n";
+    serve_on(port, &upstream, extra, async move { let _ = stopped.await; }, client).await;
+}
+
+#[tokio::test]
+async fn reloads_configuration_without_dropping_streams() {
+    let upstream = MockUpstream::start().await;
+    let mut chunks: Vec<String> = (0..5).map(|i| chunk(&format!("w{} ", i))).collect();
+    chunks.push("[DONE]".to_string());
+    upstream
+        .push(Reply::sse(&chunks).chunk_delay(Duration::from_millis(100)))
+        .always(Reply::json(200, completion("reloaded")));
+    let config = |key: &str| {
+        AppConfig::from_toml(&format!(
+            "model_url = \"{}/v1/chat/completions\"\nmodel_key = \"{}\"\n\
+             default_model = \"test-model\"\nport = 0\nhost = \"127.0.0.1\"\n",
+            upstream.base_url, key
+        ))
+        .unwrap()
+    };
+    let live = Reloadable::new(Arc::new(AppState::new(config("old-key")).unwrap()));
+    let app = live.router().into_make_service_with_connect_info::<std::net::SocketAddr>();
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let stream = post_chat(
+        &base_url,
+        json!({ "model": "test-model", "messages": [], "stream": true }),
+    )
+    .await;
+    assert_eq!(stream.status(), 200);
+    live.apply(config("new-key")).unwrap();
+
+    let response = post_chat(&base_url, json!({ "model": "test-model", "messages": [] })).await;
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["choices"][0]["message"]["content"], "reloaded");
+    let rest = stream.text().await.unwrap();
+    assert!(rest.contains("w4 "));
+    assert_eq!(field(sse_events(&rest).last().unwrap(), "data"), Some("[DONE]"));
+
+    let requests = upstream.requests();
+    assert_eq!(requests[0].headers["authorization"], "Bearer old-key");
+    assert_eq!(requests[1].headers["authorization"], "Bearer new-key");
+}
+
+#[tokio::test]
+async fn answers_panicking_handlers_with_internal_error() {
+    let upstream = MockUpstream::start().await;
+    let config = AppConfig::from_toml(&format!(
+        "model_url = \"{}/v1/chat/completions\"\nmodel_key = \"k\"\ndefault_model = \"test-model\"\n\
+         port = 0\nhost = \"127.0.0.1\"\n",
+        upstream.base_url
+    ))
+    .unwrap();
+    let state = Arc::new(AppState::new(config).unwrap());
+    async fn boom() -> axum::http::StatusCode {
+        panic!("malformed payload")
+    }
+    let app = axum::Router::new()
+        .route("/boom", axum::routing::post(boom))
+        .layer(axum::middleware::from_fn_with_state(
+            state.clone(),
+            openai_api_proxy::panics::catch_panics,
+        ));
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/boom", base_url))
+        .header("x-request-id", "req-panic")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 500);
+    assert_eq!(response.headers()["x-request-id"], "req-panic");
+    let error: Value = response.json().await.unwrap();
+    assert_eq!(error["error"]["type"], "internal_error");
+    assert_eq!(error["error"]["request_id"], "req-panic");
+    assert!(state.metrics.render().contains("llmta_panics_total 1"));
+}
+
+#[tokio::test]
+async fn mounts_inside_another_axum_application() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("embedded")));
+    let config = AppConfig::from_toml(&format!(
+        "model_url = \"{}/v1/chat/completions\"\nmodel_key = \"k\"\ndefault_model = \"test-model\"\n\
+         port = 0\nhost = \"127.0.0.1\"\n",
+        upstream.base_url
+    ))
+    .unwrap();
+    let adapter = Embedded::new(config).await.unwrap();
+    let app = axum::Router::new()
+        .route("/status", axum::routing::get(|| async { "host app" }))
+        .nest("/llm", adapter.router());
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let base_url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+
+    let status = reqwest::get(format!("{}/status", base_url)).await.unwrap();
+    assert_eq!(status.text().await.unwrap(), "host app");
+    let response = post_chat(
+        &format!("{}/llm", base_url),
+        json!({ "model": "test-model", "messages": [] }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["choices"][0]["message"]["content"], "embedded");
+    assert_eq!(upstream.requests()[0].path, "/v1/chat/completions");
+}
+
+#[tokio::test]
+async fn shows_status_page_to_browsers() {
+    let upstream = MockUpstream::start().await;
+    let adapter = spawn_adapter(&upstream, "[browser]\nservice_name = \"Acme <LLM>\"\n").await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}{}", adapter, common::CHAT_PATH))
+        .header("accept", "text/html,application/xhtml+xml")
+        .header("accept-language", "de-CH, en;q=0.8")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 405);
+    assert!(response.headers()["content-type"].to_str().unwrap().starts_with("text/html"));
+    let page = response.text().await.unwrap();
+    assert!(page.contains("<html lang=\"de\">"));
+    assert!(page.contains("Acme &lt;LLM&gt;"));
+    assert!(page.contains(env!("CARGO_PKG_VERSION")));
+    assert!(page.contains("href=\"/openapi.json\""));
+
+    let api_client = client.get(format!("{}{}", adapter, common::CHAT_PATH)).send().await.unwrap();
+    assert_eq!(api_client.status(), 405);
+    assert!(api_client.text().await.unwrap().is_empty());
+
+    let adapter = spawn_adapter(&upstream, "[browser]\nenabled = false\n").await;
+    let response = client
+        .get(format!("{}{}", adapter, common::CHAT_PATH))
+        .header("accept", "text/html")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 405);
+    assert!(response.text().await.unwrap().is_empty());
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn reports_version_and_build_info() {
+    let upstream = MockUpstream::start().await;
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let response = reqwest::get(format!("{}/version", adapter)).await.unwrap();
+    assert_eq!(
+        response.headers()["server"],
+        format!("llm-translator-adapter/{}", env!("CARGO_PKG_VERSION"))
+    );
+    let info: Value = response.json().await.unwrap();
+    assert_eq!(info["version"], env!("CARGO_PKG_VERSION"));
+    assert!(info["git_commit"].as_str().is_some_and(|c| !c.is_empty()));
+    assert!(info["build_date"].as_str().unwrap().ends_with('Z'));
+    assert!(info["features"].is_array());
+    assert!(info.get("config_hash").is_none());
+}
+
+#[tokio::test]
+async fn repairs_non_compliant_responses_in_strict_mode() {
+    let upstream = MockUpstream::start().await;
+    let sloppy = json!({
+        "object": "text_completion",
+        "model": "test-model",
+        "choices": [{ "message": { "content": "ok" } }],
+    });
+    upstream.push(Reply::json(200, sloppy.clone()));
+    upstream.push(Reply::json(200, sloppy));
+    let bare_chunk = |content: &str| json!({ "choices": [{ "delta": { "content": content } }] }).to_string();
+    upstream.push(Reply::sse(&[bare_chunk("o"), bare_chunk("k"), "[DONE]".to_string()]));
+
+    let lenient = spawn_adapter(&upstream, "").await;
+    let untouched: Value = post_chat(&lenient, json!({ "model": "test-model", "messages": [] }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(untouched["object"], "text_completion");
+    assert!(untouched["choices"][0].get("finish_reason").is_none());
+
+    let adapter = spawn_adapter(&upstream, "[validation]\nstrict = true\n").await;
+    let repaired: Value = post_chat(&adapter, json!({ "model": "test-model", "messages": [] }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(repaired["id"].as_str().unwrap().starts_with("chatcmpl-"));
+    assert_eq!(repaired["object"], "chat.completion");
+    assert!(repaired["created"].is_u64());
+    let choice = &repaired["choices"][0];
+    assert_eq!(choice["index"], 0);
+    assert_eq!(choice["message"], json!({ "role": "assistant", "content": "ok" }));
+    assert_eq!(choice["finish_reason"], "stop");
+
+    let body = post_chat(&adapter, json!({ "model": "test-model", "messages": [], "stream": true }))
+        .await
+        .text()
+        .await
+        .unwrap();
+    let chunks: Vec<Value> = sse_events(&body)
+        .iter()
+        .filter_map(|e| field(e, "data"))
+        .filter(|d| *d != "[DONE]")
+        .map(|d| serde_json::from_str(d).unwrap())
+        .collect();
+    assert_eq!(chunks.len(), 3);
+    for chunk in &chunks {
+        assert_eq!(chunk["object"], "chat.completion.chunk");
+        assert_eq!(chunk["id"], chunks[0]["id"]);
+        assert_eq!(chunk["created"], chunks[0]["created"]);
+        assert_eq!(chunk["model"], "test-model");
+    }
+    assert!(chunks[1]["choices"][0]["finish_reason"].is_null());
+    assert_eq!(chunks[2]["choices"][0]["finish_reason"], "stop");
+}
+
+#[tokio::test]
+async fn synthesizes_missing_ids_timestamps_and_fingerprint() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(
+        200,
+        json!({
+            "object": "chat.completion",
+            "model": "test-model",
+            "choices": [{ "index": 0, "message": { "role": "assistant", "content": "ok" }, "finish_reason": "stop" }],
+        }),
+    ));
+    let bare_chunk = |content: &str| {
+        json!({ "object": "chat.completion.chunk", "model": "test-model", "choices": [{ "index": 0, "delta": { "content": content }, "finish_reason": null }] })
+            .to_string()
+    };
+    upstream.push(Reply::sse(&[bare_chunk("o"), bare_chunk("k"), "[DONE]".to_string()]));
+    upstream.push(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "[validation]\nsystem_fingerprint = \"fp_adapter\"\n").await;
+
+    let synthesized: Value = post_chat(&adapter, json!({ "model": "test-model", "messages": [] }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert!(synthesized["id"].as_str().unwrap().starts_with("chatcmpl-"));
+    assert!(synthesized["created"].as_u64().unwrap() > 1_700_000_000);
+    assert_eq!(synthesized["system_fingerprint"], "fp_adapter");
+
+    let body = post_chat(&adapter, json!({ "model": "test-model", "messages": [], "stream": true }))
+        .await
+        .text()
+        .await
+        .unwrap();
+    let chunks: Vec<Value> = sse_events(&body)
+        .iter()
+        .filter_map(|e| field(e, "data"))
+        .filter(|d| *d != "[DONE]")
+        .map(|d| serde_json::from_str(d).unwrap())
+        .collect();
+    assert_eq!(chunks.len(), 3);
+    assert!(chunks[0]["id"].as_str().unwrap().starts_with("chatcmpl-"));
+    assert_eq!(chunks[1]["id"], chunks[0]["id"]);
+    assert_eq!(chunks[1]["created"], chunks[0]["created"]);
+    assert_eq!(chunks[1]["system_fingerprint"], "fp_adapter");
+
+    // Fields the backend does send are kept.
+    let compliant: Value = post_chat(&adapter, json!({ "model": "test-model", "messages": [] }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(compliant["id"], "chatcmpl-mock");
+    assert_eq!(compliant["created"], 1700000000);
+}
+
+#[tokio::test]
+async fn lists_configured_models_merged_with_upstream() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(
+        200,
+        json!({ "object": "list", "data": [
+            { "id": "test-model", "object": "model", "created": 1, "owned_by": "upstream" },
+            { "id": "live-model", "object": "model", "created": 1, "owned_by": "upstream" },
+        ] }),
+    ));
+    let backends = format!(
+        "[[backends]]\nname = \"other\"\nurl = \"{}/v1/chat/completions\"\nkey = \"k\"\nmodels = [\"other-model\", \"other-*\"]\n",
+        upstream.base_url
+    );
+    let client = reqwest::Client::new();
+
+    let adapter = spawn_adapter(&upstream, &backends).await;
+    let list: Value = client
+        .get(format!("{}/v1/models", adapter))
+        .bearer_auth("client-key")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(list["object"], "list");
+    let ids: Vec<&str> = list["data"].as_array().unwrap().iter().map(|m| m["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, ["test-model", "other-model"]);
+    assert_eq!(list["data"][1]["owned_by"], "other");
+    assert!(upstream.requests().is_empty());
+
+    let adapter = spawn_adapter(&upstream, &format!("[models]\nmerge_upstream = true\n{}", backends)).await;
+    let list: Value = client
+        .get(format!("{}/v1/models", adapter))
+        .bearer_auth("client-key")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let ids: Vec<&str> = list["data"].as_array().unwrap().iter().map(|m| m["id"].as_str().unwrap()).collect();
+    assert_eq!(ids, ["test-model", "other-model", "live-model"]);
+    let requests = upstream.requests();
+    assert_eq!(requests[0].path, "/v1/models");
+    assert_eq!(requests[0].headers["authorization"], "Bearer upstream-key");
+}
+
+async fn stream_data(adapter: &str) -> Vec<String> {
+    let body = post_chat(adapter, json!({ "model": "test-model", "messages": [], "stream": true }))
+        .await
+        .text()
+        .await
+        .unwrap();
+    sse_events(&body)
+        .iter()
+        .filter_map(|e| field(e, "data").map(str::to_string))
+        .collect()
+}
+
+#[tokio::test]
+async fn terminates_streams_missing_finish_reason_and_done() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::sse(&[chunk("Hel"), chunk("lo")]));
+    let finished = json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "created": 1700000000,
+        "model": "test-model",
+        "choices": [{ "index": 0, "delta": {}, "finish_reason": "length" }]
+    });
+    upstream.push(Reply::sse(&[chunk("Hi"), finished.to_string()]));
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let data = stream_data(&adapter).await;
+    assert_eq!(data.len(), 4);
+    let last: Value = serde_json::from_str(&data[2]).unwrap();
+    assert_eq!(last["id"], "chatcmpl-mock");
+    assert_eq!(last["choices"][0]["finish_reason"], "stop");
+    assert_eq!(data[3], "[DONE]");
+
+    // An upstream finish_reason is kept; only [DONE] is added.
+    let data = stream_data(&adapter).await;
+    assert_eq!(data.len(), 3);
+    let last: Value = serde_json::from_str(&data[1]).unwrap();
+    assert_eq!(last["choices"][0]["finish_reason"], "length");
+    assert_eq!(data[2], "[DONE]");
+}
+
+#[tokio::test]
+async fn limits_concurrent_handshakes_per_backend() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")).delayed(Duration::from_millis(200)));
+    let adapter = spawn_adapter(&upstream, "[pool]\nmax_handshakes = 1\n").await;
+
+    // Cold requests take turns opening a connection.
+    let started = std::time::Instant::now();
+    let requests = (0..3).map(|_| post_chat(&adapter, json!({ "model": "test-model", "messages": [] })));
+    let responses = futures::future::join_all(requests).await;
+    assert!(responses.iter().all(|r| r.status() == 200));
+    assert!(started.elapsed() >= Duration::from_millis(550));
+
+    // Their connections are pooled now, so the next ones run side by side.
+    let started = std::time::Instant::now();
+    let requests = (0..3).map(|_| post_chat(&adapter, json!({ "model": "test-model", "messages": [] })));
+    let responses = futures::future::join_all(requests).await;
+    assert!(responses.iter().all(|r| r.status() == 200));
+    assert!(started.elapsed() < Duration::from_millis(550));
+}
+
+#[tokio::test]
+async fn prewarms_backend_connections_at_startup() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, json!({ "object": "list", "data": [] })));
+    let _adapter = spawn_adapter(&upstream, "[pool]\nprewarm = 2\nmax_handshakes = 1\n").await;
+    tokio::time::sleep(Duration::from_millis(200)).await;
+
+    let warmed = upstream.requests().iter().filter(|r| r.path == "/v1/models").count();
+    assert_eq!(warmed, 2);
+}
+
+#[tokio::test]
+async fn counts_usage_of_streams_without_upstream_usage() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::sse(&[chunk("Hel"), chunk("lo"), "[DONE]".to_string()]));
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let response = post_chat(
+        &adapter,
+        json!({
+            "model": "test-model",
+            "messages": [{ "role": "user", "content": "Hi" }],
+            "stream": true,
+            "stream_options": { "include_usage": true }
+        }),
+    )
+    .await;
+    let events = sse_events(&response.text().await.unwrap());
+    let data: Vec<&str> = events.iter().filter_map(|e| field(e, "data")).collect();
+    assert_eq!(data.last(), Some(&"[DONE]"));
+    let usage_chunk: Value = serde_json::from_str(data[data.len() - 2]).unwrap();
+    assert_eq!(usage_chunk["choices"], json!([]));
+    assert_eq!(usage_chunk["id"], "chatcmpl-mock");
+    // "Hi" plus the chat format overhead, and "Hello".
+    assert_eq!(usage_chunk["usage"], json!({ "prompt_tokens": 7, "completion_tokens": 2, "total_tokens": 9 }));
+
+    // Without include_usage the counts only go to the usage log.
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [], "stream": true })).await;
+    let events = sse_events(&response.text().await.unwrap());
+    assert!(events.iter().filter_map(|e| field(e, "data")).all(|d| !d.contains("usage")));
+
+    let report: Value = reqwest::Client::new()
+        .get(format!("{}/v1/usage", adapter))
+        .bearer_auth("client-key")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["usage"][0]["requests"], 2);
+    assert_eq!(report["usage"][0]["prompt_tokens"], 10);
+    assert_eq!(report["usage"][0]["completion_tokens"], 4);
+}
+
+#[tokio::test]
+async fn caches_responses_and_replays_hits_as_streams() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("Hello")));
+    let adapter = spawn_adapter(&upstream, "[cache]\nenabled = true\n").await;
+
+    let response = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "temperature": 0.2, "messages": [{ "role": "user", "content": "Hi" }] }),
+    )
+    .await;
+    assert_eq!(response.headers()["x-llmta-cache"], "miss");
+    let first: Value = response.json().await.unwrap();
+
+    // Field order does not matter.
+    let response = post_chat(
+        &adapter,
+        json!({ "messages": [{ "content": "Hi", "role": "user" }], "temperature": 0.2, "model": "test-model" }),
+    )
+    .await;
+    assert_eq!(response.headers()["x-llmta-cache"], "hit");
+    assert_eq!(response.json::<Value>().await.unwrap(), first);
+    assert_eq!(upstream.requests().len(), 1);
+
+    let response = post_chat(
+        &adapter,
+        json!({
+            "model": "test-model",
+            "temperature": 0.2,
+            "messages": [{ "role": "user", "content": "Hi" }],
+            "stream": true,
+            "stream_options": { "include_usage": true }
+        }),
+    )
+    .await;
+    assert_eq!(response.headers()["x-llmta-cache"], "hit");
+    let events = sse_events(&response.text().await.unwrap());
+    let data: Vec<&str> = events.iter().filter_map(|e| field(e, "data")).collect();
+    assert_eq!(data.last(), Some(&"[DONE]"));
+    let content: Value = serde_json::from_str(data[0]).unwrap();
+    assert_eq!(content["choices"][0]["delta"]["content"], "Hello");
+    let finish: Value = serde_json::from_str(data[1]).unwrap();
+    assert_eq!(finish["choices"][0]["finish_reason"], "stop");
+    let usage: Value = serde_json::from_str(data[2]).unwrap();
+    assert_eq!(usage["usage"]["total_tokens"], 7);
+    assert_eq!(upstream.requests().len(), 1);
+
+    // A different temperature is a different request.
+    let response = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "temperature": 0.7, "messages": [{ "role": "user", "content": "Hi" }] }),
+    )
+    .await;
+    assert_eq!(response.headers()["x-llmta-cache"], "miss");
+    assert_eq!(upstream.requests().len(), 2);
+
+    let response = reqwest::Client::new()
+        .post(format!("{}{}", adapter, common::CHAT_PATH))
+        .bearer_auth("client-key")
+        .header("cache-control", "no-cache")
+        .json(&json!({ "model": "test-model", "temperature": 0.2, "messages": [{ "role": "user", "content": "Hi" }] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.headers()["x-llmta-cache"], "miss");
+    assert_eq!(upstream.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn caches_completed_streams() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::sse(&[chunk("Hel"), chunk("lo"), "[DONE]".to_string()]));
+    let adapter = spawn_adapter(&upstream, "[cache]\nenabled = true\n").await;
+
+    let request = json!({ "model": "test-model", "messages": [{ "role": "user", "content": "Hi" }], "stream": true });
+    let response = post_chat(&adapter, request.clone()).await;
+    assert!(response.headers().get("x-llmta-cache").is_none());
+    response.text().await.unwrap();
+    tokio::time::sleep(Duration::from_millis(100)).await;
+
+    let response = post_chat(&adapter, request).await;
+    assert_eq!(response.headers()["x-llmta-cache"], "hit");
+    let events = sse_events(&response.text().await.unwrap());
+    let first: Value = serde_json::from_str(field(&events[0], "data").unwrap()).unwrap();
+    assert_eq!(first["choices"][0]["delta"]["content"], "Hello");
+    assert_eq!(upstream.requests().len(), 1);
+}
+
+#[tokio::test]
+async fn applies_routing_rules_in_order() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(
+        &upstream,
+        r#"
+[[rules]]
+name = "no-huge-prompts"
+action = "reject"
+status = 413
+message = "Prompt too long"
+match = { min_tokens = 200 }
+
+[[rules]]
+action = "tag"
+tags = ["german"]
+match = { languages = ["DE"] }
+
+[[rules]]
+action = "route"
+model = "test-model-de"
+match = { tags = ["german"] }
+
+[[rules]]
+action = "transform"
+set = { temperature = 0 }
+remove = ["user"]
+match = { models = ["test-model-de"], headers = { "x-team" = "legal*" } }
+"#,
+    )
+    .await;
+
+    let german = json!({
+        "model": "test-model",
+        "user": "someone",
+        "messages": [{ "role": "user", "content": "Bitte übersetzen Sie diesen Vertrag sorgfältig ins Englische." }]
+    });
+    let response = reqwest::Client::new()
+        .post(format!("{}{}", adapter, common::CHAT_PATH))
+        .bearer_auth("client-key")
+        .header("x-team", "legal-emea")
+        .json(&german)
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-llmta-tags"], "german");
+    let forwarded = &upstream.requests()[0].body;
+    assert_eq!(forwarded["model"], "test-model-de");
+    assert_eq!(forwarded["temperature"], 0);
+    assert!(forwarded.get("user").is_none());
+
+    // Without the header the request is routed but not transformed.
+    post_chat(&adapter, german).await;
+    let forwarded = &upstream.requests()[1].body;
+    assert_eq!(forwarded["model"], "test-model-de");
+    assert_eq!(forwarded["user"], "someone");
+
+    let response = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "messages": [{ "role": "user", "content": "Translate this, please." }] }),
+    )
+    .await;
+    assert!(response.headers().get("x-llmta-tags").is_none());
+    assert_eq!(upstream.requests()[2].body["model"], "test-model");
+
+    let response = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "messages": [{ "role": "user", "content": "word ".repeat(500) }] }),
+    )
+    .await;
+    assert_eq!(response.status(), 413);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["error"]["type"], "request_rejected");
+    assert_eq!(body["error"]["message"], "Prompt too long");
+    assert_eq!(upstream.requests().len(), 3);
+}
+
+#[tokio::test]
+async fn serves_semantically_similar_requests_from_cache() {
+    let upstream = MockUpstream::start().await;
+    let embedding = |vector: Value| {
+        Reply::json(200, json!({ "object": "list", "data": [{ "index": 0, "embedding": vector }] }))
+    };
+    upstream
+        .push(embedding(json!([1.0, 0.0, 0.0])))
+        .push(Reply::json(200, completion("Use the reset link.")))
+        .push(embedding(json!([0.99, 0.1, 0.0])))
+        .push(embedding(json!([0.0, 1.0, 0.0])))
+        .push(Reply::json(200, completion("Sunny.")));
+    let adapter = spawn_adapter(
+        &upstream,
+        "[cache]\nenabled = true\n[cache.semantic]\nmodel = \"test-embedding\"\nthreshold = 0.95\n",
+    )
+    .await;
+    let ask = |question: &str| {
+        json!({
+            "model": "test-model",
+            "messages": [
+                { "role": "system", "content": "You answer support questions." },
+                { "role": "user", "content": question }
+            ]
+        })
+    };
+
+    let response = post_chat(&adapter, ask("How do I reset my password?")).await;
+    assert_eq!(response.headers()["x-llmta-cache"], "miss");
+
+    let response = post_chat(&adapter, ask("How can I reset my password?")).await;
+    assert_eq!(response.headers()["x-llmta-cache"], "semantic");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["choices"][0]["message"]["content"], "Use the reset link.");
+
+    let response = post_chat(&adapter, ask("What is the weather like?")).await;
+    assert_eq!(response.headers()["x-llmta-cache"], "miss");
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["choices"][0]["message"]["content"], "Sunny.");
+
+    let paths: Vec<String> = upstream.requests().into_iter().map(|r| r.path).collect();
+    assert_eq!(
+        paths,
+        ["/v1/embeddings", "/v1/chat/completions", "/v1/embeddings", "/v1/embeddings", "/v1/chat/completions"]
+    );
+    assert_eq!(upstream.requests()[0].body["input"], "How do I reset my password?");
+}
+
+#[tokio::test]
+async fn discovers_backend_capabilities_and_adapts_requests() {
+    let upstream = MockUpstream::start().await;
+    let refused = || Reply::json(400, json!({ "error": { "message": "unsupported" } }));
+    upstream
+        .push(Reply::json(200, json!({ "object": "list", "data": [{ "id": "test-model" }] })))
+        .push(Reply::json(200, completion("")))
+        .push(Reply::json(200, completion("")))
+        .push(refused())
+        .push(Reply::json(200, completion("")))
+        .push(refused())
+        .push(refused());
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(&upstream, "[discovery]\nenabled = true\n").await;
+    for _ in 0..50 {
+        if upstream.requests().len() >= 7 {
+            break;
+        }
+        tokio::time::sleep(Duration::from_millis(20)).await;
+    }
+    tokio::time::sleep(Duration::from_millis(50)).await;
+    let probes = upstream.requests();
+    assert_eq!(probes[0].path, "/v1/models");
+    assert_eq!(probes[1].body["max_tokens"], 1);
+    assert_eq!(probes[6].body["messages"][0]["role"], "developer");
+
+    let response = post_chat(
+        &adapter,
+        json!({
+            "model": "test-model",
+            "messages": [
+                { "role": "developer", "content": "Translate to French." },
+                { "role": "user", "content": "Hello" }
+            ],
+            "tools": [{ "type": "function", "function": { "name": "lookup", "parameters": {} } }],
+            "parallel_tool_calls": false,
+            "response_format": { "type": "json_schema", "json_schema": { "name": "t", "schema": {} } },
+            "stream_options": { "include_usage": true }
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let forwarded = &upstream.requests()[7].body;
+    assert_eq!(forwarded["messages"][0]["role"], "system");
+    assert!(forwarded.get("parallel_tool_calls").is_none());
+    assert!(forwarded.get("stream_options").is_none());
+    // json_schema was accepted, and tools are left alone.
+    assert_eq!(forwarded["response_format"]["type"], "json_schema");
+    assert_eq!(forwarded["tools"][0]["function"]["name"], "lookup");
+}
+
+#[tokio::test]
+async fn reports_conformance_of_a_backend() {
+    let upstream = MockUpstream::start().await;
+    let stop = json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "model": "test-model",
+        "choices": [{ "index": 0, "delta": {}, "finish_reason": "stop" }]
+    });
+    let usage = json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "model": "test-model",
+        "choices": [],
+        "usage": { "prompt_tokens": 5, "completion_tokens": 5, "total_tokens": 10 }
+    });
+    let mut tool_call = completion("");
+    tool_call["choices"][0]["message"] = json!({
+        "role": "assistant",
+        "content": null,
+        "tool_calls": [{
+            "id": "call_1",
+            "type": "function",
+            "function": { "name": "get_weather", "arguments": "{\"city\":\"Paris\"}" }
+        }]
+    });
+    tool_call["choices"][0]["finish_reason"] = json!("tool_calls");
+    let tool_delta = |delta: Value| {
+        json!({
+            "id": "chatcmpl-mock",
+            "object": "chat.completion.chunk",
+            "model": "test-model",
+            "choices": [{ "index": 0, "delta": { "tool_calls": [delta] }, "finish_reason": null }]
+        })
+        .to_string()
+    };
+    let mut truncated = completion("1 2 3");
+    truncated["choices"][0]["finish_reason"] = json!("length");
+    upstream
+        .push(Reply::json(200, completion("pong")))
+        .push(Reply::sse(&[chunk("1 2 "), chunk("3 4 5"), stop.to_string(), usage.to_string(), "[DONE]".to_string()]))
+        .push(Reply::json(200, tool_call))
+        .push(Reply::sse(&[
+            tool_delta(json!({ "index": 0, "id": "call_1", "type": "function", "function": { "name": "get_weather", "arguments": "" } })),
+            tool_delta(json!({ "index": 0, "function": { "arguments": "{\"city\":" } })),
+            tool_delta(json!({ "index": 0, "function": { "arguments": "\"Paris\"}" } })),
+            "[DONE]".to_string(),
+        ]))
+        .push(Reply::json(200, completion("Sure! {\"answer\": 42}")))
+        .push(Reply::json(200, completion("{\"answer\": 42}")))
+        .push(Reply::json(200, truncated))
+        .push(Reply::json(
+            400,
+            json!({ "error": { "message": "temperature must be at most 2", "type": "invalid_request_error" } }),
+        ));
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let client = reqwest::Client::new();
+    let report: Value = client
+        .post(format!("{}/conformance?model=test-model", adapter))
+        .bearer_auth("client-key")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(report["backend"], "default");
+    assert_eq!(report["model"], "test-model");
+    let outcome = |name: &str| {
+        report["checks"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .find(|c| c["name"] == name)
+            .unwrap_or_else(|| panic!("no check {}", name))["passed"]
+            .clone()
+    };
+    for name in ["chat", "streaming", "stream_usage", "tools", "streaming_tools", "json_schema", "max_tokens", "errors"] {
+        assert_eq!(outcome(name), true, "{} in {}", name, report);
+    }
+    assert_eq!(outcome("json_mode"), false);
+    assert_eq!(report["passed"], 8);
+    assert_eq!(report["failed"], 1);
+
+    let requests = upstream.requests();
+    assert_eq!(requests.len(), 8);
+    assert_eq!(requests[2].body["tool_choice"]["function"]["name"], "get_weather");
+    assert_eq!(requests[4].body["response_format"]["type"], "json_object");
+
+    let unknown = client
+        .post(format!("{}/conformance?backend=nowhere", adapter))
+        .bearer_auth("client-key")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(unknown.status(), 400);
+}
@@ -0,0 +1,62 @@
+#![cfg(unix)]
+
+use std::path::Path;
+use std::process::{Command, Stdio};
+use std::time::{Duration, Instant};
+
+const BIN: &str = env!("CARGO_BIN_EXE_openai-api-proxy");
+
+fn wait_for(what: &str, mut ready: impl FnMut() -> bool) {
+    let deadline = Instant::now() + Duration::from_secs(10);
+    while !ready() {
+        assert!(Instant::now() < deadline, "timed out waiting for {}", what);
+        std::thread::sleep(Duration::from_millis(50));
+    }
+}
+
+fn alive(pid: &str) -> bool {
+    Command::new("kill").args(["-0", pid]).stderr(Stdio::null()).status().unwrap().success()
+}
+
+#[test]
+fn detaches_and_writes_the_pidfile_and_log() {
+    let dir = std::env::temp_dir().join(format!("lta-daemon-{}", std::process::id()));
+    std::fs::create_dir_all(dir.join("config")).unwrap();
+    std::fs::write(
+        dir.join("config/default.toml"),
+        "model_url = \"http://127.0.0.1:1/v1/chat/completions\"\nmodel_key = \"k\"\n\
+         default_model = \"test-model\"\nport = 0\nhost = \"127.0.0.1\"\n",
+    )
+    .unwrap();
+    let (pidfile, log) = (dir.join("lta.pid"), dir.join("lta.log"));
+
+    let status = Command::new(BIN)
+        .current_dir(&dir)
+        .arg("--daemon")
+        .arg("--pidfile")
+        .arg(&pidfile)
+        .arg("--log-file")
+        .arg(&log)
+        .status()
+        .unwrap();
+    // The foreground process returns as soon as the daemon is forked.
+    assert!(status.success());
+
+    let read = |path: &Path| std::fs::read_to_string(path).unwrap_or_default();
+    wait_for("the pidfile", || !read(&pidfile).trim().is_empty());
+    let pid = read(&pidfile).trim().to_string();
+    assert!(alive(&pid));
+    // Still resolves `config/` from the directory it was started in.
+    wait_for("the log", || read(&log).contains("Configuration loaded successfully"));
+
+    assert!(Command::new("kill").arg(&pid).status().unwrap().success());
+    wait_for("the daemon to stop", || !alive(&pid));
+    std::fs::remove_dir_all(&dir).unwrap();
+}
+
+#[test]
+fn refuses_daemon_options_without_daemon() {
+    let output = Command::new(BIN).args(["--pidfile", "/tmp/lta.pid"]).output().unwrap();
+    assert_eq!(output.status.code(), Some(2));
+    assert!(String::from_utf8_lossy(&output.stderr).contains("--daemon"));
+}
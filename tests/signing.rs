@@ -0,0 +1,62 @@
+mod common;
+
+use common::{chunk, completion, post_chat, spawn_adapter, MockUpstream, Reply};
+use ed25519_dalek::{Signature, Verifier, VerifyingKey};
+use serde_json::{json, Value};
+use sha2::{Digest, Sha256};
+
+const SIGNING: &str = "[signing]\nprivate_key = \"0101010101010101010101010101010101010101010101010101010101010101\"\nkey_id = \"test\"\n";
+
+async fn verifying_key(adapter: &str) -> VerifyingKey {
+    let key: Value = reqwest::get(format!("{}/.well-known/llmta-signing-key", adapter))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    let bytes: [u8; 32] = hex::decode(key["public_key"].as_str().unwrap())
+        .unwrap()
+        .try_into()
+        .unwrap();
+    VerifyingKey::from_bytes(&bytes).unwrap()
+}
+
+fn signature(value: &str) -> Signature {
+    let hex_sig = value.rsplit("sig=").next().unwrap();
+    let bytes: [u8; 64] = hex::decode(hex_sig).unwrap().try_into().unwrap();
+    Signature::from_bytes(&bytes)
+}
+
+#[tokio::test]
+async fn signs_buffered_responses() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("signed")));
+    let adapter = spawn_adapter(&upstream, SIGNING).await;
+
+    let response = post_chat(&adapter, json!({ "model": "test-model", "messages": [] })).await;
+    let header = response.headers()["x-llmta-signature"].to_str().unwrap().to_string();
+    assert!(header.starts_with("keyid=test,alg=ed25519,"));
+    let body = response.bytes().await.unwrap();
+
+    let key = verifying_key(&adapter).await;
+    key.verify(&Sha256::digest(&body), &signature(&header)).unwrap();
+}
+
+#[tokio::test]
+async fn signs_streams_with_trailing_comment() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::sse(&[chunk("a"), chunk("b"), "[DONE]".to_string()]));
+    let adapter = spawn_adapter(&upstream, SIGNING).await;
+
+    let response = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "messages": [], "stream": true }),
+    )
+    .await;
+    let text = response.text().await.unwrap();
+    let (events, trailer) = text.rsplit_once(": x-llmta-signature ").unwrap();
+
+    let key = verifying_key(&adapter).await;
+    key.verify(&Sha256::digest(events.as_bytes()), &signature(trailer.trim()))
+        .unwrap();
+}
@@ -0,0 +1,175 @@
+use futures::StreamExt;
+use openai_api_proxy::sse::{self, EventParser, JsonArrayParser, SseEvent, SseParser, StreamFormat};
+use rand::rngs::StdRng;
+use rand::{Rng, SeedableRng};
+use serde_json::json;
+
+fn parse_all(body: &[u8]) -> Vec<SseEvent> {
+    let mut parser = SseParser::new();
+    let mut events = parser.feed(body);
+    events.extend(parser.finish());
+    events
+}
+
+#[test]
+fn parses_fields_and_joins_data_lines() {
+    let events = parse_all(b"id: 7\nevent: delta\nretry: 1500\ndata: one\ndata: two\n\n");
+    assert_eq!(
+        events,
+        [SseEvent {
+            event: Some("delta".to_string()),
+            data: "one\ntwo".to_string(),
+            id: Some("7".to_string()),
+            retry: Some(1500),
+        }]
+    );
+}
+
+#[test]
+fn skips_comments_and_accepts_crlf() {
+    let events = parse_all(b": keep-alive\r\ndata: {\"a\":1}\r\n\r\n: x-signature abc\r\n\r\n");
+    assert_eq!(events, [SseEvent::data("{\"a\":1}")]);
+}
+
+#[test]
+fn reads_data_without_prefix() {
+    let events = parse_all(b"{\"a\":1}\n{\"b\":\n2}\n[DONE]\n");
+    let data: Vec<&str> = events.iter().map(|e| e.data.as_str()).collect();
+    assert_eq!(data, ["{\"a\":1}", "{\"b\":\n2}", "[DONE]"]);
+    assert!(events[2].is_done());
+}
+
+#[test]
+fn flushes_unterminated_event_on_finish() {
+    let mut parser = SseParser::new();
+    assert!(parser.feed(b"data: tail").is_empty());
+    assert_eq!(parser.finish(), Some(SseEvent::data("tail")));
+    assert_eq!(parser.finish(), None);
+}
+
+#[test]
+fn classifies_events() {
+    assert_eq!(SseEvent::data("[DONE]").kind(), "done");
+    assert_eq!(SseEvent::data("done").kind(), "done");
+    assert_eq!(SseEvent::data(r#"{"error":{"message":"x"}}"#).kind(), "error");
+    assert_eq!(SseEvent::data(r#"{"choices":[]}"#).kind(), "delta");
+}
+
+#[test]
+fn splits_json_arrays_into_events() {
+    let mut parser = EventParser::new(StreamFormat::JsonArray);
+    let mut events = parser.feed(b"[{\"text\": \"a}\\\"\"},");
+    events.extend(parser.feed(b"\n{\"nested\": [1, {\"b\": 2}]}]"));
+    let data: Vec<&str> = events.iter().map(|e| e.data.as_str()).collect();
+    assert_eq!(data, [r#"{"text": "a}\""}"#, r#"{"nested": [1, {"b": 2}]}"#]);
+}
+
+#[tokio::test]
+async fn decodes_and_encodes_streams() {
+    let chunks: Vec<Result<&[u8], std::io::Error>> = vec![
+        Ok(&b"data: {\"a\""[..]),
+        Ok(&b":1}\n\nda"[..]),
+        Ok(&b"ta: [DONE]"[..]),
+    ];
+    let events: Vec<SseEvent> = sse::decode(StreamFormat::Sse, futures::stream::iter(chunks))
+        .map(Result::unwrap)
+        .collect()
+        .await;
+    assert_eq!(events, [SseEvent::data("{\"a\":1}"), SseEvent::data("[DONE]")]);
+
+    let bytes: Vec<_> = sse::encode(futures::stream::iter(events)).collect().await;
+    assert_eq!(bytes.concat(), b"data: {\"a\":1}\n\ndata: [DONE]\n\n");
+}
+
+#[tokio::test]
+async fn decode_passes_on_read_errors() {
+    let chunks: Vec<Result<&[u8], &str>> =
+        vec![Ok(&b"data: a\n\n"[..]), Err("reset"), Ok(&b"data: b\n\n"[..])];
+    let items: Vec<_> = sse::decode(StreamFormat::Sse, futures::stream::iter(chunks))
+        .collect()
+        .await;
+    assert_eq!(items, [Ok(SseEvent::data("a")), Err("reset")]);
+}
+
+/// Text including multi-byte characters, so random splits land inside them.
+fn random_text(rng: &mut StdRng, newlines: bool) -> String {
+    const ALPHABET: &[&str] = &["a", "Z", " ", ":", "{", "}", "\"", "\\", "é", "世", "🦀", "["];
+    let len = rng.gen_range(0..12);
+    (0..len)
+        .map(|_| {
+            if newlines && rng.gen_bool(0.1) {
+                "\n"
+            } else {
+                ALPHABET[rng.gen_range(0..ALPHABET.len())]
+            }
+        })
+        .collect()
+}
+
+fn random_event(rng: &mut StdRng) -> SseEvent {
+    SseEvent {
+        event: rng.gen_bool(0.3).then(|| random_text(rng, false)),
+        data: random_text(rng, true),
+        id: rng.gen_bool(0.3).then(|| random_text(rng, false)),
+        retry: rng.gen_bool(0.2).then(|| rng.gen()),
+    }
+}
+
+/// Feeds `body` in pieces of random size, cutting anywhere.
+fn feed_split(rng: &mut StdRng, parser: &mut EventParser, body: &[u8]) -> Vec<SseEvent> {
+    let mut events = Vec::new();
+    let mut rest = body;
+    while !rest.is_empty() {
+        let (chunk, tail) = rest.split_at(rng.gen_range(1..=rest.len().min(16)));
+        events.extend(parser.feed(chunk));
+        rest = tail;
+    }
+    events.extend(parser.finish());
+    events
+}
+
+#[test]
+fn serialized_events_parse_back_however_split() {
+    let mut rng = StdRng::seed_from_u64(0x55e);
+    for _ in 0..500 {
+        let count = rng.gen_range(1..6);
+        let events: Vec<SseEvent> = (0..count).map(|_| random_event(&mut rng)).collect();
+        let body: Vec<u8> = events.iter().flat_map(|e| e.to_bytes().to_vec()).collect();
+        let mut parser = EventParser::new(StreamFormat::Sse);
+        assert_eq!(feed_split(&mut rng, &mut parser, &body), events);
+    }
+}
+
+#[test]
+fn json_array_elements_parse_back_however_split() {
+    let mut rng = StdRng::seed_from_u64(0xa77);
+    for _ in 0..500 {
+        let count = rng.gen_range(0..6);
+        let elements: Vec<String> = (0..count)
+            .map(|_| {
+                let text = random_text(&mut rng, true);
+                json!({ "text": text, "n": [rng.gen::<u8>()] }).to_string()
+            })
+            .collect();
+        let body = format!("[{}]", elements.join(",\n"));
+        let mut parser = EventParser::new(StreamFormat::JsonArray);
+        let data: Vec<String> = feed_split(&mut rng, &mut parser, body.as_bytes())
+            .into_iter()
+            .map(|e| e.data)
+            .collect();
+        assert_eq!(data, elements);
+    }
+}
+
+#[test]
+fn parsers_never_panic_on_random_bytes() {
+    let mut rng = StdRng::seed_from_u64(0xb17e);
+    for format in [StreamFormat::Sse, StreamFormat::JsonArray, StreamFormat::AwsEventStream] {
+        for _ in 0..200 {
+            let body: Vec<u8> = (0..rng.gen_range(0..256)).map(|_| rng.gen()).collect();
+            feed_split(&mut rng, &mut EventParser::new(format), &body);
+        }
+    }
+    let mut parser = JsonArrayParser::default();
+    assert!(parser.feed(b"]]]}}}").is_empty());
+}
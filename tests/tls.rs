@@ -0,0 +1,68 @@
+use base64::Engine;
+use openai_api_proxy::{buildinfo, AppConfig, AppState};
+use sha2::{Digest, Sha256};
+
+fn config(extra: &str) -> AppConfig {
+    AppConfig::from_toml(&format!(
+        "model_url = \"https://127.0.0.1:1/v1/chat/completions\"\nmodel_key = \"k\"\n\
+         default_model = \"test-model\"\nport = 0\nhost = \"127.0.0.1\"\n{}",
+        extra
+    ))
+    .unwrap()
+}
+
+/// A fresh self-signed certificate and its SPKI pin.
+fn certificate() -> (Vec<u8>, String) {
+    let cert = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    let digest = Sha256::digest(cert.get_key_pair().public_key_der());
+    let pin = format!("sha256/{}", base64::engine::general_purpose::STANDARD.encode(digest));
+    (cert.serialize_der().unwrap(), pin)
+}
+
+const PINNED_BACKEND: &str = "\n[[backends]]\nname = \"pinned\"\nurl = \"https://127.0.0.1:2/v1/chat/completions\"\nkey = \"k\"\nmodels = [\"pinned-model\"]\n\n[backends.tls]\n";
+
+#[cfg(not(feature = "rustls-ring"))]
+#[test]
+fn refuses_pins_the_tls_stack_cannot_enforce() {
+    let (_, pin) = certificate();
+    let error = AppState::new(config(&format!("\n[tls]\npins = [{:?}]\n", pin))).err().unwrap();
+    assert!(error.to_string().contains("rustls-ring"));
+
+    let error = AppState::new(config(&format!("{}pins = [{:?}]\n", PINNED_BACKEND, pin))).err().unwrap();
+    assert!(error.to_string().contains("backend pinned"));
+}
+
+#[cfg(feature = "rustls-ring")]
+#[test]
+fn accepts_only_chains_matching_a_pin() {
+    use openai_api_proxy::tls::matches_pins;
+
+    let (der, pin) = certificate();
+    let (other, other_pin) = certificate();
+    let pins = [pin];
+    assert!(matches_pins(&[&der], &pins).unwrap());
+    assert!(matches_pins(&[&other, &der], &pins).unwrap());
+    assert!(!matches_pins(&[&der], &[other_pin]).unwrap());
+    assert!(matches_pins(&[&der], &["md5/abc".to_string()]).is_err());
+
+    AppState::new(config(&format!("{}pins = {:?}\n", PINNED_BACKEND, pins))).unwrap();
+}
+
+#[test]
+fn builds_clients_on_the_selected_tls_stack() {
+    let features = buildinfo::features();
+    for (stack, enabled) in [
+        ("native-tls", cfg!(feature = "native-tls")),
+        ("native-tls-system", cfg!(feature = "native-tls-system")),
+        ("rustls-ring", cfg!(feature = "rustls-ring")),
+    ] {
+        assert_eq!(features.contains(&stack), enabled, "{}", stack);
+    }
+
+    // Any stack serves a 1.2 floor; 1.3 and cipher selection need rustls.
+    AppState::new(config("\n[tls]\nmin_version = \"1.2\"\n")).unwrap();
+    let tls13 = AppState::new(config("\n[tls]\nmin_version = \"1.3\"\n"));
+    assert_eq!(tls13.is_ok(), cfg!(feature = "rustls-ring"));
+    let ciphers = AppState::new(config("\n[tls]\nciphers = [\"TLS13_AES_256_GCM_SHA384\"]\n"));
+    assert_eq!(ciphers.is_ok(), cfg!(feature = "rustls-ring"));
+}
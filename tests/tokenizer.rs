@@ -0,0 +1,57 @@
+use openai_api_proxy::tokenizer::{TokenizerConfig, TokenizerRegistry};
+use serde_json::json;
+use std::sync::Arc;
+
+fn registry(configs: serde_json::Value) -> TokenizerRegistry {
+    TokenizerRegistry::new(serde_json::from_value::<Vec<TokenizerConfig>>(configs).unwrap())
+}
+
+#[test]
+fn resolves_models_by_name_then_prefix() {
+    let registry = registry(json!([
+        { "models": ["gpt-4o-mini"], "kind": "approximate" },
+        { "models": ["gpt-4o*"], "kind": "tiktoken", "encoding": "o200k_base" },
+        { "models": ["llama-*"], "kind": "huggingface" },
+    ]));
+
+    assert_eq!(registry.for_model("gpt-4o-mini").name(), "approximate");
+    let gpt = registry.for_model("gpt-4o-2024-08-06");
+    #[cfg(feature = "tiktoken")]
+    assert_eq!(gpt.name(), "tiktoken/o200k_base");
+    #[cfg(not(feature = "tiktoken"))]
+    assert_eq!(gpt.name(), "approximate");
+    // Loaded once per entry and shared by every model it matches.
+    assert!(Arc::ptr_eq(&gpt, &registry.for_model("gpt-4o")));
+
+    assert_eq!(registry.for_model("mistral-large").name(), "approximate");
+}
+
+#[test]
+fn falls_back_to_approximating_when_a_tokenizer_cannot_load() {
+    let registry = registry(json!([
+        { "models": ["llama-*"], "kind": "huggingface" },
+        { "models": ["gpt-*"], "kind": "tiktoken", "encoding": "nope_base" },
+    ]));
+
+    let llama = registry.for_model("llama-3-70b");
+    assert_eq!(llama.name(), "approximate");
+    assert_eq!(llama.count("twelve chars"), 3);
+    assert_eq!(llama.truncate("abcdefghij", 2), "abcdefgh");
+    assert_eq!(registry.for_model("gpt-4").name(), "approximate");
+
+    let checks = registry.check();
+    assert_eq!(checks.len(), 2);
+    assert_eq!(checks[0].0, "llama-*");
+    assert!(checks.iter().all(|(_, result)| result.is_err()));
+}
+
+#[test]
+fn counts_chat_messages_with_overhead() {
+    let registry = registry(json!([]));
+    let messages = [
+        json!({ "role": "system", "content": "abcd" }),
+        json!({ "role": "user", "content": [{ "type": "text", "text": "abcdefgh" }] }),
+    ];
+    // 1 + 2 content tokens, 3 per message and 3 to prime the reply.
+    assert_eq!(registry.count_messages("any", &messages), 12);
+}
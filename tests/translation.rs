@@ -0,0 +1,1128 @@
+mod common;
+
+use common::{chunk, completion, field, post_chat, spawn_adapter, sse_events, MockUpstream, Reply};
+use serde_json::{json, Value};
+
+#[tokio::test]
+async fn reports_language_and_billing_metadata() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(
+        200,
+        completion("Bonjour à tous, comment allez-vous aujourd'hui ? Il fait très beau."),
+    ));
+    let adapter = spawn_adapter(&upstream, "[translation]\nmetadata = true\n").await;
+
+    let response = post_chat(
+        &adapter,
+        json!({
+            "model": "test-model",
+            "messages": [
+                { "role": "system", "content": "Translate to French." },
+                { "role": "user", "content": "Hello everyone" }
+            ]
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["x-llmta-detected-language"], "FR");
+    assert_eq!(response.headers()["x-llmta-billed-characters"], "14");
+    assert_eq!(response.headers()["x-llmta-billed-tokens"], "7");
+
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["x_translation"]["detected_language"], "FR");
+    assert_eq!(body["x_translation"]["billed_characters"], 14);
+}
+
+#[tokio::test]
+async fn expands_inline_glossary_into_system_prompt() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("Der Zeitplaner")));
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let response = post_chat(
+        &adapter,
+        json!({
+            "model": "test-model",
+            "messages": [{ "role": "user", "content": "The scheduler" }],
+            "x_glossary": { "scheduler": "Zeitplaner" }
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+
+    let forwarded = &upstream.requests()[0].body;
+    assert!(forwarded.get("x_glossary").is_none());
+    assert_eq!(forwarded["messages"][0]["role"], "system");
+    assert!(forwarded["messages"][0]["content"]
+        .as_str()
+        .unwrap()
+        .contains("\"scheduler\" -> \"Zeitplaner\""));
+}
+
+#[tokio::test]
+async fn rejects_malformed_glossary() {
+    let upstream = MockUpstream::start().await;
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let response = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "messages": [], "x_glossary": ["scheduler"] }),
+    )
+    .await;
+    assert_eq!(response.status(), 400);
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn estimates_tokens_and_cost_without_calling_upstream() {
+    let upstream = MockUpstream::start().await;
+    let adapter = spawn_adapter(
+        &upstream,
+        "[[pricing]]\nmodels = [\"test-*\"]\ninput_per_million = 1.0\noutput_per_million = 4.0\n",
+    )
+    .await;
+
+    let estimate: Value = reqwest::Client::new()
+        .post(format!("{}/v1/estimate", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({
+            "model": "test-model",
+            "max_tokens": 1000,
+            "x_target_language": "en",
+            "messages": [{
+                "role": "user",
+                "content": "Der schnelle braune Fuchs springt über den faulen Hund, und alle schauen zu."
+            }]
+        }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+
+    assert_eq!(estimate["language"], "DE");
+    assert_eq!(estimate["target_language"], "EN");
+    let prompt = estimate["prompt_tokens"].as_u64().unwrap();
+    let expected = estimate["expected_completion_tokens"].as_u64().unwrap();
+    assert!(prompt > expected && expected > 0);
+    let cost = &estimate["backends"][0]["cost"];
+    assert_eq!(cost["currency"], "USD");
+    assert_eq!(cost["max_total"].as_f64().unwrap(), (prompt as f64 + 4000.0) / 1_000_000.0);
+    assert!(upstream.requests().is_empty());
+}
+
+#[tokio::test]
+async fn translates_chat_requests_to_anthropic_messages() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(
+        200,
+        json!({
+            "id": "msg_1",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-test",
+            "content": [
+                { "type": "text", "text": "Checking." },
+                { "type": "tool_use", "id": "toolu_1", "name": "lookup", "input": { "q": "x" } }
+            ],
+            "stop_reason": "tool_use",
+            "usage": { "input_tokens": 12, "output_tokens": 5 }
+        }),
+    ));
+    let adapter = spawn_adapter(&upstream, "protocol = \"anthropic\"\n").await;
+
+    let response: Value = post_chat(
+        &adapter,
+        json!({
+            "model": "claude-test",
+            "messages": [
+                { "role": "system", "content": "Be brief." },
+                { "role": "user", "content": "Look up x" }
+            ],
+            "tools": [{ "type": "function", "function": { "name": "lookup", "parameters": { "type": "object" } } }],
+            "stop": "END"
+        }),
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+
+    let sent = &upstream.requests()[0];
+    assert_eq!(sent.headers["x-api-key"], "upstream-key");
+    assert!(sent.headers.get("authorization").is_none());
+    assert_eq!(sent.body["system"], "Be brief.");
+    assert_eq!(sent.body["max_tokens"], 4096);
+    assert_eq!(sent.body["stop_sequences"], json!(["END"]));
+    assert_eq!(sent.body["messages"], json!([{ "role": "user", "content": [{ "type": "text", "text": "Look up x" }] }]));
+    assert_eq!(sent.body["tools"][0]["input_schema"], json!({ "type": "object" }));
+
+    assert_eq!(response["object"], "chat.completion");
+    let choice = &response["choices"][0];
+    assert_eq!(choice["finish_reason"], "tool_calls");
+    assert_eq!(choice["message"]["content"], "Checking.");
+    assert_eq!(choice["message"]["tool_calls"][0]["function"]["arguments"], "{\"q\":\"x\"}");
+    assert_eq!(response["usage"]["total_tokens"], 17);
+}
+
+#[tokio::test]
+async fn converts_anthropic_stream_events_to_chunks() {
+    let upstream = MockUpstream::start().await;
+    let events = [
+        json!({ "type": "message_start", "message": { "id": "msg_1", "model": "claude-test", "usage": { "input_tokens": 3 } } }),
+        json!({ "type": "content_block_start", "index": 0, "content_block": { "type": "text", "text": "" } }),
+        json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": "Hel" } }),
+        json!({ "type": "ping" }),
+        json!({ "type": "content_block_delta", "index": 0, "delta": { "type": "text_delta", "text": "lo" } }),
+        json!({ "type": "content_block_stop", "index": 0 }),
+        json!({ "type": "message_delta", "delta": { "stop_reason": "end_turn" }, "usage": { "output_tokens": 2 } }),
+        json!({ "type": "message_stop" }),
+    ];
+    let chunks: Vec<String> = events.iter().map(Value::to_string).collect();
+    upstream.push(Reply::sse(&chunks));
+    let adapter = spawn_adapter(&upstream, "protocol = \"anthropic\"\n").await;
+
+    let body = post_chat(
+        &adapter,
+        json!({ "model": "claude-test", "messages": [{ "role": "user", "content": "hi" }], "stream": true }),
+    )
+    .await
+    .text()
+    .await
+    .unwrap();
+    let data: Vec<String> = common::sse_events(&body)
+        .iter()
+        .filter_map(|e| common::field(e, "data").map(str::to_string))
+        .collect();
+    assert_eq!(data.last().unwrap(), "[DONE]");
+    let chunks: Vec<Value> = data[..data.len() - 1].iter().map(|d| serde_json::from_str(d).unwrap()).collect();
+    let text: String = chunks
+        .iter()
+        .filter_map(|c| c["choices"][0]["delta"]["content"].as_str())
+        .collect();
+    assert_eq!(text, "Hello");
+    let last = chunks.last().unwrap();
+    assert_eq!(last["object"], "chat.completion.chunk");
+    assert_eq!(last["id"], "msg_1");
+    assert_eq!(last["choices"][0]["finish_reason"], "stop");
+    assert_eq!(last["usage"]["completion_tokens"], 2);
+}
+
+fn gemini_backend(upstream: &MockUpstream) -> String {
+    format!(
+        "[[backends]]\nname = \"gemini\"\nurl = \"{}/v1beta\"\nkey = \"gemini-key\"\nprotocol = \"gemini\"\nmodels = [\"gemini-*\"]\n",
+        upstream.base_url
+    )
+}
+
+#[tokio::test]
+async fn translates_chat_requests_to_gemini_generate_content() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(
+        200,
+        json!({
+            "candidates": [{
+                "content": { "role": "model", "parts": [{ "text": "Bonjour" }] },
+                "finishReason": "STOP",
+                "index": 0
+            }],
+            "usageMetadata": { "promptTokenCount": 4, "candidatesTokenCount": 2, "totalTokenCount": 6 },
+            "modelVersion": "gemini-test"
+        }),
+    ));
+    let adapter = spawn_adapter(&upstream, &gemini_backend(&upstream)).await;
+
+    let response: Value = post_chat(
+        &adapter,
+        json!({
+            "model": "gemini-test",
+            "messages": [
+                { "role": "system", "content": "Translate to French." },
+                { "role": "user", "content": "Hello" }
+            ],
+            "temperature": 0.2,
+            "max_tokens": 50
+        }),
+    )
+    .await
+    .json()
+    .await
+    .unwrap();
+
+    let sent = &upstream.requests()[0];
+    assert_eq!(sent.path, "/v1beta/models/gemini-test:generateContent");
+    assert_eq!(sent.headers["x-goog-api-key"], "gemini-key");
+    assert_eq!(sent.body["systemInstruction"]["parts"][0]["text"], "Translate to French.");
+    assert_eq!(sent.body["contents"], json!([{ "role": "user", "parts": [{ "text": "Hello" }] }]));
+    assert_eq!(sent.body["generationConfig"], json!({ "temperature": 0.2, "maxOutputTokens": 50 }));
+
+    assert_eq!(response["choices"][0]["message"]["content"], "Bonjour");
+    assert_eq!(response["choices"][0]["finish_reason"], "stop");
+    assert_eq!(response["usage"]["total_tokens"], 6);
+}
+
+#[tokio::test]
+async fn rewrites_gemini_json_array_stream_into_sse_chunks() {
+    let upstream = MockUpstream::start().await;
+    let part = |text: &str, finish: Option<&str>| {
+        let mut candidate = json!({ "content": { "role": "model", "parts": [{ "text": text }] }, "index": 0 });
+        if let Some(finish) = finish {
+            candidate["finishReason"] = json!(finish);
+        }
+        json!({ "candidates": [candidate], "usageMetadata": { "promptTokenCount": 3, "candidatesTokenCount": 2 } })
+            .to_string()
+    };
+    // Element boundaries deliberately fall inside network chunks.
+    let body = format!("[{},\r\n{}]", part("Hel", None), part("lo", Some("STOP")));
+    let (first, second) = body.split_at(body.len() / 2 + 3);
+    upstream.push(Reply::raw(200, "application/json", vec![first.to_string(), second.to_string()]));
+    let adapter = spawn_adapter(&upstream, &gemini_backend(&upstream)).await;
+
+    let text = post_chat(
+        &adapter,
+        json!({ "model": "gemini-test", "messages": [{ "role": "user", "content": "hi" }], "stream": true }),
+    )
+    .await
+    .text()
+    .await
+    .unwrap();
+
+    assert_eq!(upstream.requests()[0].path, "/v1beta/models/gemini-test:streamGenerateContent");
+    let data: Vec<String> = common::sse_events(&text)
+        .iter()
+        .filter_map(|e| common::field(e, "data").map(str::to_string))
+        .collect();
+    assert_eq!(data.last().unwrap(), "[DONE]");
+    let chunks: Vec<Value> = data[..data.len() - 1].iter().map(|d| serde_json::from_str(d).unwrap()).collect();
+    let content: String = chunks
+        .iter()
+        .filter_map(|c| c["choices"][0]["delta"]["content"].as_str())
+        .collect();
+    assert_eq!(content, "Hello");
+    assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+    assert_eq!(chunks.last().unwrap()["choices"][0]["finish_reason"], "stop");
+}
+
+#[tokio::test]
+async fn sends_azure_requests_to_mapped_deployments() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("ok")));
+    let adapter = spawn_adapter(
+        &upstream,
+        &format!(
+            "[[backends]]\nname = \"azure\"\nurl = \"{}/\"\nkey = \"azure-key\"\nprotocol = \"azure\"\nmodels = [\"gpt-4o\", \"gpt-4o-mini\"]\n[azure]\napi_version = \"2024-10-21\"\n[azure.deployments]\n\"gpt-4o\" = \"prod-4o\"\n",
+            upstream.base_url
+        ),
+    )
+    .await;
+
+    for model in ["gpt-4o", "gpt-4o-mini"] {
+        let response = post_chat(&adapter, json!({ "model": model, "messages": [] })).await;
+        assert_eq!(response.status(), 200);
+    }
+
+    let requests = upstream.requests();
+    assert_eq!(requests[0].path, "/openai/deployments/prod-4o/chat/completions?api-version=2024-10-21");
+    assert_eq!(requests[1].path, "/openai/deployments/gpt-4o-mini/chat/completions?api-version=2024-10-21");
+    assert_eq!(requests[0].headers["api-key"], "azure-key");
+    assert!(requests[0].headers.get("authorization").is_none());
+}
+
+fn bedrock_backend(upstream: &MockUpstream) -> String {
+    format!(
+        "[[backends]]\nname = \"bedrock\"\nurl = \"{}\"\nkey = \"unused\"\nprotocol = \"bedrock\"\nmodels = [\"anthropic.*\", \"meta.*\"]\n[bedrock]\nregion = \"eu-west-1\"\naccess_key_id = \"AKIDEXAMPLE\"\nsecret_access_key = \"secret\"\n",
+        upstream.base_url
+    )
+}
+
+/// One AWS event-stream frame with string headers; CRCs are left zero.
+fn event_frame(headers: &[(&str, &str)], payload: &[u8]) -> Vec<u8> {
+    let mut encoded = Vec::new();
+    for (name, value) in headers {
+        encoded.push(name.len() as u8);
+        encoded.extend_from_slice(name.as_bytes());
+        encoded.push(7);
+        encoded.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        encoded.extend_from_slice(value.as_bytes());
+    }
+    let total = 12 + encoded.len() + payload.len() + 4;
+    let mut frame = Vec::new();
+    frame.extend_from_slice(&(total as u32).to_be_bytes());
+    frame.extend_from_slice(&(encoded.len() as u32).to_be_bytes());
+    frame.extend_from_slice(&[0; 4]);
+    frame.extend_from_slice(&encoded);
+    frame.extend_from_slice(payload);
+    frame.extend_from_slice(&[0; 4]);
+    frame
+}
+
+#[test]
+fn signs_requests_with_aws_sigv4() {
+    use openai_api_proxy::sigv4::{Credentials, Signer};
+    use std::time::{Duration, UNIX_EPOCH};
+
+    // `get-vanilla` from the AWS Signature Version 4 test suite.
+    let signer = Signer {
+        credentials: Credentials {
+            access_key_id: "AKIDEXAMPLE".to_string(),
+            secret_access_key: "wJalrXUtnFEMI/K7MDENG+bPxRfiCYEXAMPLEKEY".to_string(),
+            session_token: None,
+        },
+        region: "us-east-1".to_string(),
+        service: "service".to_string(),
+    };
+    let mut headers = reqwest::header::HeaderMap::new();
+    let url = reqwest::Url::parse("https://example.amazonaws.com/").unwrap();
+    signer.sign("GET", &url, &mut headers, b"", UNIX_EPOCH + Duration::from_secs(1_440_938_160));
+
+    assert_eq!(headers["x-amz-date"], "20150830T123600Z");
+    assert_eq!(
+        headers["authorization"],
+        "AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/20150830/us-east-1/service/aws4_request, \
+         SignedHeaders=host;x-amz-date, \
+         Signature=5fa00fa31553b73ebf1942676e86291e8372ff2a2260956d9b8aae1d763fbf31"
+    );
+}
+
+#[tokio::test]
+async fn invokes_claude_on_bedrock_with_signed_requests() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(
+        200,
+        json!({
+            "id": "msg_01",
+            "type": "message",
+            "role": "assistant",
+            "model": "claude-3-haiku-20240307",
+            "content": [{ "type": "text", "text": "Bonjour" }],
+            "stop_reason": "end_turn",
+            "usage": { "input_tokens": 9, "output_tokens": 2 },
+        }),
+    ));
+    let adapter = spawn_adapter(&upstream, &bedrock_backend(&upstream)).await;
+
+    let response = post_chat(
+        &adapter,
+        json!({
+            "model": "anthropic.claude-3-haiku-20240307-v1:0",
+            "messages": [
+                { "role": "system", "content": "Translate to French." },
+                { "role": "user", "content": "Hello" },
+            ],
+        }),
+    )
+    .await;
+    assert_eq!(response.status(), 200);
+    let completion: Value = response.json().await.unwrap();
+    assert_eq!(completion["choices"][0]["message"]["content"], "Bonjour");
+    assert_eq!(completion["usage"]["total_tokens"], 11);
+
+    let request = &upstream.requests()[0];
+    assert_eq!(request.path, "/model/anthropic.claude-3-haiku-20240307-v1%3A0/invoke");
+    assert_eq!(request.body["anthropic_version"], "bedrock-2023-05-31");
+    assert_eq!(request.body["system"], "Translate to French.");
+    assert!(request.body.get("model").is_none());
+    let authorization = request.headers["authorization"].to_str().unwrap();
+    assert!(authorization.starts_with("AWS4-HMAC-SHA256 Credential=AKIDEXAMPLE/"));
+    assert!(authorization.contains("/eu-west-1/bedrock/aws4_request"));
+    assert!(request.headers.contains_key("x-amz-date"));
+}
+
+#[tokio::test]
+async fn decodes_bedrock_event_stream_into_sse_chunks() {
+    use base64::Engine;
+
+    let upstream = MockUpstream::start().await;
+    let chunk = |body: Value| {
+        let bytes = base64::engine::general_purpose::STANDARD.encode(body.to_string());
+        event_frame(
+            &[(":event-type", "chunk"), (":content-type", "application/json"), (":message-type", "event")],
+            json!({ "bytes": bytes }).to_string().as_bytes(),
+        )
+    };
+    let mut body = chunk(json!({ "generation": "Bon", "prompt_token_count": 12, "generation_token_count": 1, "stop_reason": null }));
+    body.extend(chunk(json!({
+        "generation": "jour",
+        "prompt_token_count": null,
+        "generation_token_count": 2,
+        "stop_reason": "stop",
+        "amazon-bedrock-invocationMetrics": { "inputTokenCount": 12, "outputTokenCount": 2 },
+    })));
+    // Frame boundaries deliberately fall inside network chunks.
+    let (first, rest) = body.split_at(7);
+    let (second, third) = rest.split_at(rest.len() / 2);
+    upstream.push(Reply::binary(
+        200,
+        "application/vnd.amazon.eventstream",
+        vec![first.to_vec(), second.to_vec(), third.to_vec()],
+    ));
+    let adapter = spawn_adapter(&upstream, &bedrock_backend(&upstream)).await;
+
+    let text = post_chat(
+        &adapter,
+        json!({
+            "model": "meta.llama3-8b-instruct-v1:0",
+            "messages": [{ "role": "user", "content": "Hello" }],
+            "max_tokens": 64,
+            "stream": true,
+        }),
+    )
+    .await
+    .text()
+    .await
+    .unwrap();
+
+    let request = &upstream.requests()[0];
+    assert_eq!(request.path, "/model/meta.llama3-8b-instruct-v1%3A0/invoke-with-response-stream");
+    assert_eq!(request.body["max_gen_len"], 64);
+    assert!(request.body["prompt"]
+        .as_str()
+        .unwrap()
+        .contains("<|start_header_id|>user<|end_header_id|>\n\nHello<|eot_id|>"));
+
+    let data: Vec<String> = common::sse_events(&text)
+        .iter()
+        .filter_map(|e| common::field(e, "data").map(str::to_string))
+        .collect();
+    assert_eq!(data.last().unwrap(), "[DONE]");
+    let chunks: Vec<Value> = data[..data.len() - 1].iter().map(|d| serde_json::from_str(d).unwrap()).collect();
+    let content: String = chunks
+        .iter()
+        .filter_map(|c| c["choices"][0]["delta"]["content"].as_str())
+        .collect();
+    assert_eq!(content, "Bonjour");
+    assert_eq!(chunks[0]["choices"][0]["delta"]["role"], "assistant");
+    assert_eq!(chunks[0]["model"], "meta.llama3-8b-instruct-v1:0");
+    let last = chunks.last().unwrap();
+    assert_eq!(last["choices"][0]["finish_reason"], "stop");
+    assert_eq!(last["usage"]["total_tokens"], 14);
+}
+
+#[tokio::test]
+async fn embeds_through_openai_and_batches_cohere_requests() {
+    let upstream = MockUpstream::start().await;
+    let openai = json!({
+        "object": "list",
+        "data": [{ "object": "embedding", "index": 0, "embedding": [0.25] }],
+        "model": "text-embedding-3-small",
+        "usage": { "prompt_tokens": 1, "total_tokens": 1 }
+    });
+    upstream.push(Reply::json(200, openai.clone()));
+    upstream.push(Reply::json(
+        200,
+        json!({ "id": "e1", "embeddings": { "float": [[1.0], [0.5]] }, "meta": { "billed_units": { "input_tokens": 4 } } }),
+    ));
+    upstream.push(Reply::json(
+        200,
+        json!({ "id": "e2", "embeddings": { "float": [[2.0]] }, "meta": { "billed_units": { "input_tokens": 3 } } }),
+    ));
+    let backends = format!(
+        "[[backends]]\nname = \"cohere\"\nurl = \"{}/v2\"\nkey = \"cohere-key\"\nprotocol = \"cohere\"\nmodels = [\"embed-*\"]\nmax_batch = 2\n",
+        upstream.base_url
+    );
+    let adapter = spawn_adapter(&upstream, &backends).await;
+    let embed = |body: Value| {
+        reqwest::Client::new()
+            .post(format!("{}/v1/embeddings", adapter))
+            .bearer_auth("client-key")
+            .json(&body)
+            .send()
+    };
+
+    let passed: Value = embed(json!({ "model": "text-embedding-3-small", "input": "hello" }))
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(passed, openai);
+
+    let response = embed(json!({ "model": "embed-english-v3.0", "input": ["a", "b", "c"], "encoding_format": "base64" }))
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let translated: Value = response.json().await.unwrap();
+    let data = translated["data"].as_array().unwrap();
+    let indexes: Vec<u64> = data.iter().map(|e| e["index"].as_u64().unwrap()).collect();
+    assert_eq!(indexes, [0, 1, 2]);
+    // Little-endian f32 1.0, 0.5 and 2.0.
+    let encoded: Vec<&str> = data.iter().map(|e| e["embedding"].as_str().unwrap()).collect();
+    assert_eq!(encoded, ["AACAPw==", "AAAAPw==", "AAAAQA=="]);
+    assert_eq!(translated["model"], "embed-english-v3.0");
+    assert_eq!(translated["usage"]["prompt_tokens"], 7);
+
+    let requests = upstream.requests();
+    assert_eq!(requests[0].path, "/v1/embeddings");
+    assert_eq!(requests[0].body, json!({ "model": "text-embedding-3-small", "input": "hello" }));
+    assert_eq!(requests[1].path, "/v2/embed");
+    assert_eq!(requests[1].headers["authorization"], "Bearer cohere-key");
+    assert_eq!(requests[1].body["texts"], json!(["a", "b"]));
+    assert_eq!(requests[1].body["input_type"], "search_document");
+    assert_eq!(requests[1].body["embedding_types"], json!(["float"]));
+    assert!(requests[1].body.get("encoding_format").is_none());
+    assert_eq!(requests[2].body["texts"], json!(["c"]));
+}
+
+#[tokio::test]
+async fn masks_personal_data_for_out_of_region_backends() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("[[NAME_1]] <[[EMAIL_1]]> hat angerufen.")));
+    upstream.push(Reply::sse(&[
+        chunk("Gruß an [[NAM"),
+        chunk("E_1]]"),
+        chunk(" und ["),
+        chunk("[EMAIL_1]]"),
+        "[DONE]".to_string(),
+    ]));
+    let config = format!(
+        "[deidentify]\nhome_regions = [\"eu-west\"]\nnames = [\"Alice Martin\"]\n\n[[backends]]\nname = \"us\"\nurl = \"{}/v1/chat/completions\"\nkey = \"k\"\nmodels = [\"test-model\"]\nregion = \"us-east\"\n",
+        upstream.base_url
+    );
+    let adapter = spawn_adapter(&upstream, &config).await;
+    let text = "Alice Martin <alice@example.com> called. Alice Martin again.";
+
+    let request = json!({ "model": "test-model", "messages": [{ "role": "user", "content": text }] });
+    let answer: Value = post_chat(&adapter, request)
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(answer["choices"][0]["message"]["content"], "Alice Martin <alice@example.com> hat angerufen.");
+    let sent = &upstream.requests()[0].body["messages"][0]["content"];
+    assert_eq!(sent, "[[NAME_1]] <[[EMAIL_1]]> called. [[NAME_1]] again.");
+
+    let body = post_chat(
+        &adapter,
+        json!({ "model": "test-model", "messages": [{ "role": "user", "content": text }], "stream": true }),
+    )
+    .await
+    .text()
+    .await
+    .unwrap();
+    let streamed: String = sse_events(&body)
+        .iter()
+        .filter_map(|e| field(e, "data"))
+        .filter_map(|d| serde_json::from_str::<Value>(d).ok())
+        .filter_map(|c| c["choices"][0]["delta"]["content"].as_str().map(str::to_string))
+        .collect();
+    assert_eq!(streamed, "Gruß an Alice Martin und alice@example.com");
+}
+
+#[tokio::test]
+async fn post_edits_drafts_with_a_second_model() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("Hallo Welt")));
+    upstream.push(Reply::json(200, completion("Hallo, Welt!")));
+    upstream.push(Reply::json(200, completion("Hallo Welt")));
+    let usage = json!({
+        "id": "chatcmpl-mock",
+        "object": "chat.completion.chunk",
+        "model": "editor-model",
+        "choices": [],
+        "usage": { "prompt_tokens": 20, "completion_tokens": 4, "total_tokens": 24 }
+    });
+    upstream.push(Reply::sse(&[chunk("Hallo, Welt!"), usage.to_string(), "[DONE]".to_string()]));
+    let config = format!(
+        "[[translation.post_edit]]\nmodels = [\"test-model\"]\neditor = \"editor-model\"\n\n[[backends]]\nname = \"editor\"\nurl = \"{}/v1/chat/completions\"\nkey = \"k\"\nmodels = [\"editor-model\"]\n",
+        upstream.base_url
+    );
+    let adapter = spawn_adapter(&upstream, &config).await;
+    let messages = json!([
+        { "role": "system", "content": "Translate into German." },
+        { "role": "user", "content": "Hello world" }
+    ]);
+
+    let answer: Value = post_chat(&adapter, json!({ "model": "test-model", "messages": messages }))
+        .await
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(answer["choices"][0]["message"]["content"], "Hallo, Welt!");
+    assert_eq!(answer["usage"], json!({ "prompt_tokens": 10, "completion_tokens": 4, "total_tokens": 14 }));
+    let requests = upstream.requests();
+    assert_eq!(requests[0].body["model"], "test-model");
+    assert_eq!(requests[1].body["model"], "editor-model");
+    assert_eq!(requests[1].body["messages"][0]["content"], "Translate into German.");
+    let prompt = requests[1].body["messages"][1]["content"].as_str().unwrap();
+    assert!(prompt.contains("Source:\nHello world") && prompt.contains("Draft:\nHallo Welt"));
+
+    let body = post_chat(
+        &adapter,
+        json!({
+            "model": "test-model",
+            "messages": messages,
+            "stream": true,
+            "stream_options": { "include_usage": true }
+        }),
+    )
+    .await
+    .text()
+    .await
+    .unwrap();
+    let requests = upstream.requests();
+    assert_eq!(requests[2].body["stream"], false);
+    assert!(requests[2].body.get("stream_options").is_none());
+    assert_eq!(requests[3].body["stream"], true);
+    let reported = sse_events(&body)
+        .iter()
+        .filter_map(|e| field(e, "data"))
+        .filter_map(|d| serde_json::from_str::<Value>(d).ok())
+        .find(|c| c["usage"].is_object())
+        .unwrap();
+    assert_eq!(reported["usage"], json!({ "prompt_tokens": 25, "completion_tokens": 6, "total_tokens": 31 }));
+}
+
+#[tokio::test]
+async fn translates_documents_with_progress_events() {
+    let upstream = MockUpstream::start().await;
+    upstream.push(Reply::json(200, completion("Erster Absatz.")));
+    upstream.push(Reply::json(200, completion("Zweiter Absatz.")));
+    upstream.push(Reply::json(200, completion("Kurz.")));
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let response = reqwest::Client::new()
+        .post(format!("{}/v1/documents/translate", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({
+            "target_language": "German",
+            "max_segment_chars": 30,
+            "concurrency": 1,
+            "documents": [
+                { "id": "a", "text": "First paragraph here.\n\nSecond paragraph here." },
+                { "id": "b", "text": "Short." }
+            ]
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    assert_eq!(response.headers()["content-type"], "text/event-stream");
+    let body = response.text().await.unwrap();
+    let events = sse_events(&body);
+    let kinds: Vec<&str> = events.iter().filter_map(|e| field(e, "event")).collect();
+    assert_eq!(
+        kinds,
+        [
+            "document_started",
+            "segment_done",
+            "segment_done",
+            "document_done",
+            "document_started",
+            "segment_done",
+            "document_done",
+            "done"
+        ]
+    );
+    let data: Vec<Value> = events
+        .iter()
+        .filter_map(|e| field(e, "data"))
+        .map(|d| serde_json::from_str(d).unwrap())
+        .collect();
+    assert_eq!(data[0]["segments"], 2);
+    assert_eq!(data[3]["status"], "completed");
+    assert_eq!(data[3]["translation"], "Erster Absatz.\n\nZweiter Absatz.");
+    assert_eq!(data[6]["translation"], "Kurz.");
+    assert_eq!(data[7], json!({ "documents": 2, "completed": 2, "failed": 0 }));
+
+    let requests = upstream.requests();
+    assert_eq!(requests.len(), 3);
+    assert!(requests[0].body["messages"][0]["content"].as_str().unwrap().contains("German"));
+    assert_eq!(requests[0].body["messages"][1]["content"], "First paragraph here.");
+
+    let empty = reqwest::Client::new()
+        .post(format!("{}/v1/documents/translate", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({ "target_language": "German", "documents": [] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(empty.status(), 400);
+}
+
+#[tokio::test]
+async fn fails_documents_answered_with_the_canned_reply() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(503, json!({ "error": { "message": "overloaded" } })));
+    let adapter = spawn_adapter(&upstream, "[degraded]\nmessage = \"Busy, retry soon.\"\n").await;
+
+    let body = reqwest::Client::new()
+        .post(format!("{}/v1/documents/translate", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({ "target_language": "German", "documents": [{ "id": "a", "text": "Hello." }] }))
+        .send()
+        .await
+        .unwrap()
+        .text()
+        .await
+        .unwrap();
+    let data: Vec<Value> = sse_events(&body)
+        .iter()
+        .filter_map(|e| field(e, "data"))
+        .map(|d| serde_json::from_str(d).unwrap())
+        .collect();
+    assert_eq!(data[1]["status"], "failed");
+    assert!(data[1]["error"].as_str().unwrap().contains("503"));
+    assert!(data[1].get("translation").is_none());
+    assert_eq!(data[2], json!({ "documents": 1, "completed": 0, "failed": 1 }));
+}
+
+#[tokio::test]
+async fn injects_configured_system_prompts() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("Hallo")));
+    let config = r#"
+[[system_prompts]]
+models = ["test-model"]
+template = "You are a professional translator. Translate into {{ target_language | English }}."
+
+[[system_prompts]]
+models = ["strict-*"]
+mode = "replace"
+template = "Translate from {{source_language}} into {{target_language}} for {{model}}."
+vars = { target_language = "German" }
+
+[[system_prompts]]
+models = ["merge-model"]
+mode = "merge"
+template = "Be concise."
+"#;
+    let adapter = spawn_adapter(&upstream, config).await;
+    let system = json!({ "role": "system", "content": "Keep the tone." });
+    let user = json!({ "role": "user", "content": "Hello" });
+
+    post_chat(&adapter, json!({ "model": "test-model", "messages": [system, user], "x_prompt_vars": { "target_language": "French" } })).await;
+    post_chat(&adapter, json!({ "model": "test-model", "messages": [user] })).await;
+    post_chat(&adapter, json!({ "model": "strict-model", "messages": [system, user], "x_prompt_vars": { "source_language": "English" } })).await;
+    post_chat(&adapter, json!({ "model": "merge-model", "messages": [system, user] })).await;
+    let requests = upstream.requests();
+    assert_eq!(
+        requests[0].body["messages"],
+        json!([
+            { "role": "system", "content": "You are a professional translator. Translate into French." },
+            system,
+            user
+        ])
+    );
+    assert!(requests[0].body.get("x_prompt_vars").is_none());
+    assert_eq!(
+        requests[1].body["messages"][0]["content"],
+        "You are a professional translator. Translate into English."
+    );
+    assert_eq!(
+        requests[2].body["messages"],
+        json!([
+            { "role": "system", "content": "Translate from English into German for strict-model." },
+            user
+        ])
+    );
+    assert_eq!(
+        requests[3].body["messages"],
+        json!([{ "role": "system", "content": "Be concise.\n\nKeep the tone." }, user])
+    );
+
+    let missing = post_chat(&adapter, json!({ "model": "strict-model", "messages": [user] })).await;
+    assert_eq!(missing.status(), 400);
+    let error: Value = missing.json().await.unwrap();
+    assert!(error["error"]["message"].as_str().unwrap().contains("source_language"));
+    assert_eq!(upstream.requests().len(), 4);
+}
+
+#[tokio::test]
+async fn answers_deepl_translate_requests() {
+    let upstream = MockUpstream::start().await;
+    upstream
+        .push(Reply::json(200, completion("Guten Morgen")))
+        .push(Reply::json(200, completion("Wie geht es Ihnen?")))
+        .push(Reply::json(200, completion("Hallo")));
+    let adapter = spawn_adapter(&upstream, "[deepl]\nmodel = \"translator\"\n").await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/v2/translate", adapter))
+        .header("authorization", "DeepL-Auth-Key client-key")
+        .header("content-type", "application/x-www-form-urlencoded")
+        .body("text=Good+morning&text=How+are+you%3F&target_lang=de&formality=more")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["translations"][0]["text"], "Guten Morgen");
+    assert_eq!(body["translations"][1]["text"], "Wie geht es Ihnen?");
+    assert!(body["translations"][0]["detected_source_language"].is_string());
+
+    let mut requests = upstream.requests();
+    requests.sort_by_key(|r| r.body["messages"][1]["content"].as_str().unwrap().to_string());
+    assert_eq!(requests[0].body["model"], "translator");
+    assert_eq!(requests[0].body["messages"][1]["content"], "Good morning");
+    assert_eq!(requests[1].body["messages"][1]["content"], "How are you?");
+    let instruction = requests[0].body["messages"][0]["content"].as_str().unwrap();
+    assert!(instruction.contains("into German") && instruction.contains("formal register"));
+
+    let body: Value = client
+        .post(format!("{}/v2/translate", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({ "text": ["Hi"], "source_lang": "EN", "target_lang": "DE", "show_billed_characters": true }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(
+        body,
+        json!({ "translations": [{ "detected_source_language": "EN", "text": "Hallo", "billed_characters": 2 }] })
+    );
+    assert!(upstream.requests()[2].body["messages"][0]["content"].as_str().unwrap().contains("from English"));
+
+    let unsupported = client
+        .post(format!("{}/v2/translate", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({ "text": ["Hi"], "target_lang": "XX" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(unsupported.status(), 400);
+    let error: Value = unsupported.json().await.unwrap();
+    assert_eq!(error["message"], "Value for 'target_lang' not supported.");
+}
+
+#[tokio::test]
+async fn answers_deepl_with_its_errors_instead_of_the_canned_reply() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(503, json!({ "error": { "message": "overloaded" } })));
+    let degraded = "[degraded]\nmessage = \"Busy, retry soon.\"\n";
+    let client = reqwest::Client::new();
+    let translate = |adapter: &str| {
+        client
+            .post(format!("{}/v2/translate", adapter))
+            .bearer_auth("client-key")
+            .json(&json!({ "text": ["Hi"], "target_lang": "DE" }))
+            .send()
+    };
+
+    let adapter = spawn_adapter(&upstream, degraded).await;
+    let unavailable = translate(&adapter).await.unwrap();
+    assert_eq!(unavailable.status(), 503);
+    assert!(unavailable.text().await.unwrap().contains("Try again later"));
+
+    upstream.always(Reply::json(200, completion("Hallo")));
+    let adapter = spawn_adapter(&upstream, &format!("[limits]\nrequests_per_minute = 1\n\n{}", degraded)).await;
+    assert_eq!(translate(&adapter).await.unwrap().status(), 200);
+    let exhausted = translate(&adapter).await.unwrap();
+    assert_eq!(exhausted.status(), 456);
+    let error: Value = exhausted.json().await.unwrap();
+    assert_eq!(error["message"], "Quota exceeded.");
+}
+
+#[tokio::test]
+async fn enforces_tenant_glossaries() {
+    let upstream = MockUpstream::start().await;
+    upstream
+        .push(Reply::json(200, completion("Öffnen Sie das Acme Cloud Dashboard, um Ihre Faktura zu sehen.")))
+        .push(Reply::json(200, completion("Hallo")));
+    let config = r#"
+[admin]
+token = "admin-secret"
+
+[[auth.virtual_keys]]
+key = "vk-acme"
+name = "acme-app"
+tenant = "acme"
+
+[translation.glossary]
+validate = true
+
+[translation.glossary.tenants."*"]
+"Acme Cloud" = "Acme Cloud"
+"#;
+    let adapter = spawn_adapter(&upstream, config).await;
+    let client = reqwest::Client::new();
+
+    let stored = client
+        .put(format!("{}/admin/glossaries/acme", adapter))
+        .bearer_auth("admin-secret")
+        .json(&json!({ "invoice": "Rechnung", "dashboard": "Dashboard" }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(stored.status(), 200);
+    let glossary: Value = client
+        .get(format!("{}/admin/glossaries/acme", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(glossary["effective"]["Acme Cloud"], "Acme Cloud");
+    assert_eq!(glossary["terms"], json!({ "invoice": "Rechnung", "dashboard": "Dashboard" }));
+
+    let chat = |content: &str| {
+        client
+            .post(format!("{}/v1beta/openai/chat/completions", adapter))
+            .bearer_auth("vk-acme")
+            .json(&json!({ "model": "test-model", "messages": [{ "role": "user", "content": content }] }))
+            .send()
+    };
+    let response = chat("Open the Acme Cloud dashboard to see your invoice.").await.unwrap();
+    assert_eq!(response.headers()["x-llmta-glossary"], "honored=2; missed=1");
+    let answer: Value = response.json().await.unwrap();
+    assert_eq!(
+        answer["x_glossary_report"],
+        json!({ "honored": ["Acme Cloud", "dashboard"], "missed": ["invoice"] })
+    );
+    let requests = upstream.requests();
+    let instruction = requests[0].body["messages"][0]["content"].as_str().unwrap();
+    assert!(instruction.contains("\"invoice\" -> \"Rechnung\""));
+    assert!(instruction.contains("\"Acme Cloud\" -> \"Acme Cloud\""));
+
+    let response = chat("Hello").await.unwrap();
+    assert!(response.headers().get("x-llmta-glossary").is_none());
+    assert_eq!(upstream.requests()[1].body["messages"].as_array().unwrap().len(), 1);
+
+    let deleted = client
+        .delete(format!("{}/admin/glossaries/acme", adapter))
+        .bearer_auth("admin-secret")
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(deleted.status(), 204);
+}
+
+#[tokio::test]
+async fn translates_batches_in_order_with_retries() {
+    let upstream = MockUpstream::start().await;
+    upstream
+        .push(Reply::json(200, completion("Eins")))
+        .push(Reply::json(500, json!({ "error": { "message": "upstream hiccup" } })))
+        .push(Reply::json(200, completion("Zwei")))
+        .push(Reply::json(200, completion("Drei")));
+    let adapter = spawn_adapter(&upstream, "[batch]\nmax_items = 3\nbackoff_ms = 10\n").await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .post(format!("{}/v1/batch/translate", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({
+            "target_language": "German",
+            "texts": ["One", "Two", "Three"],
+            "concurrency": 1
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(response.status(), 200);
+    let body: Value = response.json().await.unwrap();
+    assert_eq!(body["object"], "batch.translation");
+    assert_eq!(body["completed"], 3);
+    assert_eq!(body["failed"], 0);
+    assert_eq!(body["total_tokens"], 21);
+    let results = body["results"].as_array().unwrap();
+    let translations: Vec<&str> = results.iter().map(|r| r["translation"].as_str().unwrap()).collect();
+    assert_eq!(translations, ["Eins", "Zwei", "Drei"]);
+    assert_eq!(results[0]["attempts"], 1);
+    assert_eq!(results[1]["attempts"], 2);
+    assert_eq!(results[1]["index"], 1);
+
+    let requests = upstream.requests();
+    assert_eq!(requests.len(), 4);
+    assert!(requests[0].body["messages"][0]["content"].as_str().unwrap().contains("German"));
+    assert_eq!(requests[1].body["messages"][1]["content"], "Two");
+    assert_eq!(requests[2].body["messages"][1]["content"], "Two");
+
+    upstream
+        .push(Reply::json(200, completion("Vier")))
+        .push(Reply::json(400, json!({ "error": { "message": "bad input" } })));
+    let streamed = client
+        .post(format!("{}/v1/batch/translate", adapter))
+        .bearer_auth("client-key")
+        .header("accept", "application/x-ndjson")
+        .json(&json!({ "target_language": "German", "texts": ["Four", "Five"], "concurrency": 1 }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(streamed.status(), 200);
+    assert_eq!(streamed.headers()["content-type"], "application/x-ndjson");
+    let lines: Vec<Value> = streamed
+        .text()
+        .await
+        .unwrap()
+        .lines()
+        .map(|line| serde_json::from_str(line).unwrap())
+        .collect();
+    assert_eq!(lines.len(), 3);
+    assert_eq!(lines[0]["object"], "batch.item");
+    assert_eq!(lines[0]["translation"], "Vier");
+    assert_eq!(lines[1]["index"], 1);
+    assert_eq!(lines[1]["attempts"], 1);
+    assert_eq!(lines[1]["status"], 400);
+    assert!(lines[1]["error"].is_string());
+    assert!(lines[1].get("translation").is_none());
+    assert_eq!(lines[2]["object"], "batch.summary");
+    assert_eq!(lines[2]["completed"], 1);
+    assert_eq!(lines[2]["failed"], 1);
+
+    let too_many = client
+        .post(format!("{}/v1/batch/translate", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({ "target_language": "German", "texts": ["a", "b", "c", "d"] }))
+        .send()
+        .await
+        .unwrap();
+    assert_eq!(too_many.status(), 400);
+}
+
+#[tokio::test]
+async fn fails_batch_items_answered_with_the_canned_reply() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(503, json!({ "error": { "message": "overloaded" } })));
+    let adapter = spawn_adapter(
+        &upstream,
+        "[batch]\nretries = 1\nbackoff_ms = 10\n\n[degraded]\nmessage = \"Busy, retry soon.\"\n",
+    )
+    .await;
+
+    let body: Value = reqwest::Client::new()
+        .post(format!("{}/v1/batch/translate", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({ "target_language": "German", "texts": ["One"] }))
+        .send()
+        .await
+        .unwrap()
+        .json()
+        .await
+        .unwrap();
+    assert_eq!(body["completed"], 0);
+    assert_eq!(body["failed"], 1);
+    let item = &body["results"][0];
+    assert_eq!(item["status"], 503);
+    assert_eq!(item["attempts"], 2);
+    assert!(item.get("translation").is_none());
+}
+
+#[tokio::test]
+async fn stops_a_streamed_batch_when_the_client_goes() {
+    let upstream = MockUpstream::start().await;
+    upstream.always(Reply::json(200, completion("Ja")).delayed(std::time::Duration::from_millis(100)));
+    let adapter = spawn_adapter(&upstream, "").await;
+
+    let mut response = reqwest::Client::new()
+        .post(format!("{}/v1/batch/translate", adapter))
+        .bearer_auth("client-key")
+        .json(&json!({
+            "target_language": "German",
+            "texts": ["a", "b", "c", "d", "e", "f", "g", "h"],
+            "concurrency": 1,
+            "stream": true
+        }))
+        .send()
+        .await
+        .unwrap();
+    assert!(response.chunk().await.unwrap().is_some());
+    drop(response);
+    tokio::time::sleep(std::time::Duration::from_millis(600)).await;
+    assert!(upstream.requests().len() < 5, "{} texts sent", upstream.requests().len());
+}